@@ -0,0 +1,29 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    generate_c_header();
+}
+
+/// Generate the header of the `extern "C"` functions in `interop::ffi` into
+/// `OUT_DIR`, the sources may be read-only. The checked in
+/// `include/toxicflow.h` is compared against it by the ffi tests.
+#[cfg(feature = "ffi")]
+fn generate_c_header() {
+    println!("cargo:rerun-if-changed=src/interop/ffi.rs");
+
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        include_guard: Some("TOXICFLOW_H".to_string()),
+        autogen_warning: Some(
+            "/* Generated by cbindgen from src/interop/ffi.rs, do not edit. */".to_string(),
+        ),
+        ..Default::default()
+    };
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(format!("{}/src/interop/ffi.rs", crate_dir))
+        .generate()
+        .expect("Failed to generate C header")
+        .write_to_file(format!("{}/toxicflow.h", out_dir));
+}
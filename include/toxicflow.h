@@ -0,0 +1,75 @@
+#ifndef TOXICFLOW_H
+#define TOXICFLOW_H
+
+/* Generated by cbindgen from src/interop/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Opaque handle for embedding the detector in other runtimes over C.
+ *
+ * Swaps go in as a JSON array of `SwapTransaction`s and attacks come back as
+ * a JSON array that must be released with `tf_string_free`. Failed calls
+ * return NULL (or -1) and `tf_detector_last_error` describes what went wrong.
+ * The C header is checked in as `include/toxicflow.h`, copy it from the
+ * `toxicflow.h` the build script generates into `OUT_DIR` after changing
+ * the functions.
+ */
+typedef struct TfDetector TfDetector;
+
+/**
+ * Create a detector. Release it with `tf_detector_free`.
+ */
+struct TfDetector *tf_detector_new(void);
+
+/**
+ * # Safety
+ * `detector` must come from `tf_detector_new` and not be used afterwards.
+ */
+void tf_detector_free(struct TfDetector *detector);
+
+/**
+ * Replace the pools used by `tf_detect_by_simulation` with a JSON object
+ * keyed by pool address. Returns 0 on success.
+ *
+ * # Safety
+ * `detector` must be a live handle and `pools_json` a NUL-terminated string.
+ */
+int tf_detector_set_pools(struct TfDetector *detector, const char *pools_json);
+
+/**
+ * Heuristic detection over a JSON array of swaps.
+ *
+ * # Safety
+ * `detector` must be a live handle and `swaps_json` a NUL-terminated string.
+ */
+char *tf_detect_by_heuristics(struct TfDetector *detector, const char *swaps_json);
+
+/**
+ * Simulation-based detection over a JSON array of swaps, against the pools
+ * set with `tf_detector_set_pools`.
+ *
+ * # Safety
+ * `detector` must be a live handle and `swaps_json` a NUL-terminated string.
+ */
+char *tf_detect_by_simulation(struct TfDetector *detector, const char *swaps_json);
+
+/**
+ * The error of the last failed call, or NULL. Owned by the detector and
+ * valid until its next call.
+ *
+ * # Safety
+ * `detector` must be a live handle.
+ */
+const char *tf_detector_last_error(const struct TfDetector *detector);
+
+/**
+ * # Safety
+ * `string` must have been returned by this library and not freed yet.
+ */
+void tf_string_free(char *string);
+
+#endif  /* TOXICFLOW_H */
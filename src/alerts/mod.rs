@@ -0,0 +1,251 @@
+use serde::Serialize;
+
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+use crate::sandwich::same_block_sim::SandwichAttackBySimulation;
+use crate::sandwich::severity::Severity;
+
+#[cfg(feature = "alerts")]
+pub mod webhook;
+
+/// A detected attack worth notifying someone about.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub attack_id: String,
+    pub chain_id: u64,
+    pub block_number: u64,
+    pub pool_address: String,
+    pub attacker: String,
+    pub victim: String,
+    pub victim_tx: String,
+    /// `None` for simulated attacks, which aren't scored.
+    pub confidence_score: Option<f32>,
+    /// Simulated victim loss, `None` for heuristic detections.
+    pub loss_usd: Option<f64>,
+    /// The heuristics' estimate, or the simulated round trip's.
+    pub attacker_profit_usd: f64,
+    pub severity: Severity,
+}
+
+impl From<&SandwichAttackByHeuristics> for Alert {
+    fn from(attack: &SandwichAttackByHeuristics) -> Self {
+        Self {
+            attack_id: attack.attack_id(),
+            chain_id: attack.chain_id,
+            block_number: attack.victim_tx.block_number,
+            pool_address: attack.victim_tx.pool_address.clone(),
+            attacker: attack.front_run_tx.from_address.clone(),
+            victim: attack.victim_tx.from_address.clone(),
+            victim_tx: attack.victim_tx.tx_hash.clone(),
+            confidence_score: Some(attack.confidence_score),
+            loss_usd: None,
+            attacker_profit_usd: attack.confidence_flags.total_profit_usd,
+            severity: attack.severity,
+        }
+    }
+}
+
+impl From<&SandwichAttackBySimulation> for Alert {
+    fn from(attack: &SandwichAttackBySimulation) -> Self {
+        Self {
+            attack_id: attack.attack_id(),
+            chain_id: attack.chain_id,
+            block_number: attack.victim_tx.block_number,
+            pool_address: attack.victim_tx.pool_address.clone(),
+            attacker: attack.front_run_tx.from_address.clone(),
+            victim: attack.victim_tx.from_address.clone(),
+            victim_tx: attack.victim_tx.tx_hash.clone(),
+            confidence_score: None,
+            loss_usd: Some(attack.victim_loss_usd()),
+            attacker_profit_usd: attack.attacker_profit_usd,
+            severity: attack.severity,
+        }
+    }
+}
+
+impl Alert {
+    /// One-line description used as the message text by the chat notifiers.
+    pub fn summary(&self) -> String {
+        let confidence = match self.confidence_score {
+            Some(score) => format!(", confidence {:.2}", score),
+            None => String::new(),
+        };
+        let value = match self.loss_usd {
+            Some(loss_usd) => format!("~${:.2} lost", loss_usd),
+            None => format!("~${:.2} attacker profit", self.attacker_profit_usd),
+        };
+        format!(
+            "[{}] Sandwich on pool {} in block {} (chain {}): {} attacked {}, {}{} (victim tx {})",
+            self.severity,
+            self.pool_address,
+            self.block_number,
+            self.chain_id,
+            self.attacker,
+            self.victim,
+            value,
+            confidence,
+            self.victim_tx
+        )
+    }
+}
+
+/// Delivers alerts somewhere (chat, webhook, pager, …).
+pub trait Notifier {
+    fn notify(&self, alert: &Alert) -> Result<(), String>;
+}
+
+/// Minimum confidence and USD amounts an attack needs to fire an alert.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertThresholds {
+    pub min_confidence: f32,
+    pub min_loss_usd: f64,
+    /// Applied instead of `min_loss_usd` to attacks without a known loss.
+    pub min_profit_usd: f64,
+}
+
+impl Default for AlertThresholds {
+    fn default() -> Self {
+        Self {
+            min_confidence: 0.5,
+            min_loss_usd: 100.0,
+            min_profit_usd: 100.0,
+        }
+    }
+}
+
+impl AlertThresholds {
+    /// Unscored (simulated) attacks only need to pass the loss threshold.
+    pub fn should_alert(&self, alert: &Alert) -> bool {
+        let confident = alert
+            .confidence_score
+            .is_none_or(|score| score >= self.min_confidence);
+        let large = match alert.loss_usd {
+            Some(loss_usd) => loss_usd >= self.min_loss_usd,
+            None => alert.attacker_profit_usd >= self.min_profit_usd,
+        };
+        return confident && large;
+    }
+}
+
+/// What `Alerter::process` did.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AlertOutcome {
+    /// Alerts above the thresholds.
+    pub fired: usize,
+    /// `(attack_id, error)` of every notification that failed.
+    pub failures: Vec<(String, String)>,
+}
+
+/// Sends every attack above the thresholds to all registered notifiers.
+pub struct Alerter {
+    pub thresholds: AlertThresholds,
+    notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl Alerter {
+    pub fn new(thresholds: AlertThresholds) -> Self {
+        Self {
+            thresholds,
+            notifiers: Vec::new(),
+        }
+    }
+
+    pub fn with_notifier<N: Notifier + 'static>(mut self, notifier: N) -> Self {
+        self.notifiers.push(Box::new(notifier));
+        self
+    }
+
+    /// Notify about the attacks above the thresholds. A failing notifier
+    /// doesn't stop the others, its failures are collected.
+    pub fn process<'a, A>(&self, attacks: &'a [A]) -> AlertOutcome
+    where
+        Alert: From<&'a A>,
+    {
+        let mut outcome = AlertOutcome::default();
+        for attack in attacks {
+            let alert = Alert::from(attack);
+            if !self.thresholds.should_alert(&alert) {
+                continue;
+            }
+            outcome.fired += 1;
+
+            for notifier in &self.notifiers {
+                if let Err(err) = notifier.notify(&alert) {
+                    outcome.failures.push((alert.attack_id.clone(), err));
+                }
+            }
+        }
+        return outcome;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Notifier for Recorder {
+        fn notify(&self, alert: &Alert) -> Result<(), String> {
+            self.0.borrow_mut().push(alert.attack_id.clone());
+            return Ok(());
+        }
+    }
+
+    struct Failing;
+
+    impl Notifier for Failing {
+        fn notify(&self, _alert: &Alert) -> Result<(), String> {
+            return Err("unreachable".to_string());
+        }
+    }
+
+    #[test]
+    fn test_alerter_applies_thresholds() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = find_same_block_sandwiches(&transactions);
+
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        // Heuristic detections have no known loss, only their profit counts
+        let thresholds = AlertThresholds {
+            min_confidence: 0.0,
+            min_loss_usd: f64::MAX,
+            min_profit_usd: f64::MIN,
+        };
+        let alerter = Alerter::new(thresholds).with_notifier(Recorder(sent.clone()));
+        let outcome = alerter.process(&attacks);
+        assert_eq!(outcome.fired, attacks.len());
+        assert!(outcome.failures.is_empty());
+        assert_eq!(sent.borrow().len(), attacks.len());
+        assert!(attacks
+            .iter()
+            .all(|attack| Alert::from(attack).loss_usd.is_none()));
+
+        let strict = Alerter::new(AlertThresholds {
+            min_confidence: 1.1,
+            min_loss_usd: 0.0,
+            min_profit_usd: 0.0,
+        })
+        .with_notifier(Recorder(sent.clone()));
+        assert_eq!(strict.process(&attacks), AlertOutcome::default());
+        assert_eq!(sent.borrow().len(), attacks.len());
+
+        // Failures are collected and don't stop the other notifiers
+        let failing = Alerter::new(AlertThresholds {
+            min_confidence: 0.0,
+            min_loss_usd: f64::MIN,
+            min_profit_usd: f64::MIN,
+        })
+        .with_notifier(Failing)
+        .with_notifier(Recorder(sent.clone()));
+        let outcome = failing.process(&attacks[..1]);
+        assert_eq!(outcome.fired, 1);
+        assert_eq!(
+            outcome.failures,
+            vec![(attacks[0].attack_id(), "unreachable".to_string())]
+        );
+        assert_eq!(sent.borrow().len(), attacks.len() + 1);
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use super::{Alert, Notifier};
+
+/// How long a webhook may take to answer, so a stalled one doesn't block the detector.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts to a Slack incoming webhook.
+#[derive(Debug, Clone)]
+pub struct SlackNotifier {
+    pub webhook_url: String,
+}
+
+/// Posts to a Discord channel webhook.
+#[derive(Debug, Clone)]
+pub struct DiscordNotifier {
+    pub webhook_url: String,
+}
+
+/// Posts the alert as a JSON object to any URL, with optional extra headers
+/// (e.g. an `Authorization` token for the receiving service).
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl SlackNotifier {
+    pub fn new(webhook_url: &str) -> Self {
+        Self {
+            webhook_url: webhook_url.to_string(),
+        }
+    }
+}
+
+impl DiscordNotifier {
+    pub fn new(webhook_url: &str) -> Self {
+        Self {
+            webhook_url: webhook_url.to_string(),
+        }
+    }
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+pub fn slack_payload(alert: &Alert) -> Value {
+    json!({ "text": format!(":sandwich: {}", alert.summary()) })
+}
+
+pub fn discord_payload(alert: &Alert) -> Value {
+    // Discord rejects messages over 2000 characters
+    let content: String = format!(":sandwich: {}", alert.summary())
+        .chars()
+        .take(2000)
+        .collect();
+    json!({ "content": content })
+}
+
+impl Notifier for SlackNotifier {
+    fn notify(&self, alert: &Alert) -> Result<(), String> {
+        post_json(&self.webhook_url, &[], &slack_payload(alert))
+    }
+}
+
+impl Notifier for DiscordNotifier {
+    fn notify(&self, alert: &Alert) -> Result<(), String> {
+        post_json(&self.webhook_url, &[], &discord_payload(alert))
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, alert: &Alert) -> Result<(), String> {
+        let payload = serde_json::to_value(alert)
+            .map_err(|err| format!("failed to encode alert: {}", err))?;
+        post_json(&self.url, &self.headers, &payload)
+    }
+}
+
+fn post_json(url: &str, headers: &[(String, String)], payload: &Value) -> Result<(), String> {
+    let mut request = ureq::post(url)
+        .config()
+        .timeout_global(Some(WEBHOOK_TIMEOUT))
+        .build();
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request
+        .send_json(payload)
+        .map_err(|err| format!("webhook request failed: {}", err))?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::severity::Severity;
+
+    #[test]
+    fn test_chat_payloads() {
+        let alert = Alert {
+            attack_id: "0xa-0xb-0xc".to_string(),
+            chain_id: 1,
+            block_number: 12360,
+            pool_address: "0xpool1".to_string(),
+            attacker: "0xbot".to_string(),
+            victim: "0xvictim".to_string(),
+            victim_tx: "0xb".to_string(),
+            confidence_score: Some(0.8),
+            loss_usd: Some(1234.5),
+            attacker_profit_usd: 600.0,
+            severity: Severity::High,
+        };
+
+        let slack = slack_payload(&alert);
+        let text = slack["text"].as_str().unwrap();
+        assert!(text.starts_with(":sandwich: [high] Sandwich"));
+        assert!(text.contains("block 12360"));
+        assert!(text.contains("~$1234.50 lost, confidence 0.80"));
+
+        let discord = discord_payload(&alert);
+        assert_eq!(discord["content"].as_str(), Some(text));
+    }
+}
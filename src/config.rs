@@ -0,0 +1,413 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::sandwich::activity::AddressActivity;
+use crate::sandwich::entities::EntityLinks;
+use crate::sandwich::known_actors::KnownActors;
+use crate::sandwich::pool_model::Arithmetic;
+use crate::sandwich::same_block_sim::{CounterfactualRemoval, Pool};
+use crate::sandwich::scoring::Scorer;
+use crate::sandwich::tokens::{TokenDecimals, TokenEquivalence, TokenTaxes};
+use crate::sandwich::transactions::SwapTransaction;
+
+/// Detection parameters shared by all detectors.
+///
+/// Every field has a default matching the built-in behavior, so a config
+/// file only needs the values it changes:
+///
+/// ```toml
+/// [heuristics.weights]
+/// is_profitable = 0.3
+///
+/// [simulation]
+/// reality_tolerance_pct = 2.0
+///
+/// [tokens]
+/// ETH_GROUP = ["ETH", "WETH", "stETH", "cbETH"]
+///
+/// [decimals]
+/// USDC = 6
+/// SHIB = 18
+/// ```
+///
+/// Unknown keys are rejected so typos don't silently fall back to defaults.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub heuristics: HeuristicsConfig,
+    pub simulation: SimulationConfig,
+    pub severity: SeverityConfig,
+    /// Equivalence groups, defaults to `DEFAULT_EQUIVALENCE_GROUPS`. Setting
+    /// this replaces all groups, so list every group that should still apply.
+    pub tokens: TokenEquivalence,
+    /// Decimals by token of swaps given in raw units, which detection then
+    /// converts to whole tokens, attacks included. Simulation pools must be
+    /// in whole tokens too, e.g. fetched `with_decimals`. The table replaces
+    /// `DEFAULT_TOKEN_DECIMALS`, in code use `TokenDecimals::with_token` to
+    /// add to them. Default unset, swaps are taken as whole tokens.
+    pub decimals: Option<TokenDecimals>,
+    /// Labeled MEV bots and searchers, defaults to `DEFAULT_KNOWN_ACTORS`.
+    /// Setting this replaces the list, use `KnownActors::extend` to add to it.
+    pub known_actors: KnownActors,
+    /// Funding, code and transfer links between addresses, set in code.
+    /// Default empty.
+    #[serde(skip)]
+    pub entities: EntityLinks,
+    /// Report every (front, victim, back) candidate instead of only the
+    /// best-scoring one among those overlapping (see `dedup_overlapping`).
+    /// Default `false`.
+    pub keep_raw_candidates: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeuristicsConfig {
+    /// Smallest front-run, as a share of the victim's USD volume, counted as
+    /// proportional. Default `0.05`.
+    pub min_front_ratio: f64,
+    /// Largest proportional front-run share. Default `0.5`.
+    pub max_front_ratio: f64,
+    /// How many times smaller or bigger than the front-run the back-run may
+    /// be and still be proportional. Default `2.0`.
+    pub max_back_to_front_ratio: f64,
+    pub weights: ConfidenceWeights,
+    /// Attacks scoring below this are dropped. Default `0.0`, keeping all.
+    pub min_confidence: f32,
+    /// Only report sandwiches whose back-run is in the front-run's pool.
+    /// Default `false`, so cross-DEX back-runs are reported too.
+    pub require_same_pool: bool,
+    /// Drop front/back pairs wrapping more candidate victims than this, as
+    /// they are more likely unrelated trades than a targeted attack.
+    /// Default unset, no limit.
+    pub max_victims: Option<usize>,
+    /// Highest effective priority fee of a front-run still counted as
+    /// submitted in a private bundle. Default `1`.
+    pub max_bundle_priority_fee: u64,
+    /// Hashes of transactions seen in the public mempool, set in code. A
+    /// sandwich with a leg seen there wasn't a private bundle. Default unset,
+    /// no mempool data.
+    #[serde(skip)]
+    pub mempool_sightings: Option<HashSet<String>>,
+    /// Hashes of transactions that took a flashloan (e.g. from Aave or
+    /// Balancer `FlashLoan` events), set in code. Default unset.
+    #[serde(skip)]
+    pub flashloan_txs: Option<HashSet<String>>,
+    /// Balances before the block keyed by lowercased (address, token), set in
+    /// code. A front-run spending more than its sender held must have
+    /// borrowed it. Default unset.
+    #[serde(skip)]
+    pub prior_balances: Option<HashMap<(String, String), f64>>,
+    /// USD paid directly to the block builder (coinbase transfers) keyed by
+    /// transaction hash, set in code. Default unset.
+    #[serde(skip)]
+    pub builder_payments: Option<HashMap<String, f64>>,
+    /// Rescore batch detections by how often their attacker shows up in the
+    /// whole input (see `reputation`). Streaming and per-block detection
+    /// don't see the whole input and skip it. Default `false`.
+    pub attacker_reputation: bool,
+    /// Appearances past the first after which an attacker counts as a full
+    /// repeat offender. Default `10`.
+    pub repeat_offender_appearances: u64,
+    /// Front-runs worth less than this many USD are dust, not worth
+    /// sandwiching. Only judged on pools in `pools`. Default `10.0`.
+    pub min_attack_usd: f64,
+    /// Largest share of the pool's input reserve a plausible front-run
+    /// spends. Default `0.5`.
+    pub max_pool_share: f64,
+    /// Slippage tolerance assumed for victims when sizing the optimal
+    /// front-run. Default `0.005`, the usual wallet default.
+    pub victim_slippage_tolerance: f64,
+    /// Reserves of pools before their block, keyed by pool address, set in
+    /// code. Needed to score front-runs against the optimal size. Default
+    /// unset.
+    #[serde(skip)]
+    pub pools: Option<HashMap<String, Pool>>,
+    /// Pool depth (USD on both sides) at or below which the victim's price
+    /// impact counts half, it happens organically. Default `100000.0`.
+    pub shallow_pool_usd: f64,
+    /// Pool depth at or above which price impact counts 1.5 times. Must be
+    /// above `shallow_pool_usd` for depth to count. Default `10000000.0`.
+    pub deep_pool_usd: f64,
+    /// Nonces and activity hours of addresses keyed by lowercased address
+    /// (see `activity::address_activity`), set in code. Default unset.
+    #[serde(skip)]
+    pub activity: Option<HashMap<String, AddressActivity>>,
+    /// Transactions per day from which an address is as busy as a bot.
+    /// Default `100.0`.
+    pub bot_txs_per_day: f64,
+    /// Scorer replacing the additive `weights`, set in code (e.g. with
+    /// `SandwichDetectorBuilder::scorer`) rather than in config files.
+    #[serde(skip)]
+    pub scorer: Option<Scorer>,
+}
+
+impl Default for HeuristicsConfig {
+    fn default() -> Self {
+        Self {
+            min_front_ratio: 0.05,
+            max_front_ratio: 0.5,
+            max_back_to_front_ratio: 2.0,
+            weights: ConfidenceWeights::default(),
+            min_confidence: 0.0,
+            require_same_pool: false,
+            max_victims: None,
+            max_bundle_priority_fee: 1,
+            mempool_sightings: None,
+            flashloan_txs: None,
+            prior_balances: None,
+            builder_payments: None,
+            attacker_reputation: false,
+            repeat_offender_appearances: 10,
+            min_attack_usd: 10.0,
+            max_pool_share: 0.5,
+            victim_slippage_tolerance: 0.005,
+            pools: None,
+            shallow_pool_usd: 100_000.0,
+            deep_pool_usd: 10_000_000.0,
+            activity: None,
+            bot_txs_per_day: 100.0,
+            scorer: None,
+        }
+    }
+}
+
+/// What each confidence flag adds to the score, which is capped at 1.0.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfidenceWeights {
+    /// Score before any evidence. Default `0.3`.
+    pub base: f32,
+    /// Default `0.2`.
+    pub higher_front_gas_price: f32,
+    /// Default `0.1`.
+    pub lower_back_gas_price: f32,
+    /// Default `0.1`.
+    pub front_is_contract: f32,
+    /// Default `0.1`.
+    pub back_is_contract: f32,
+    /// Default `0.25`.
+    pub is_profitable: f32,
+    /// Default `0.15`.
+    pub is_proportional: f32,
+    /// The victim's price impact is added as is, up to this much. Default `0.25`.
+    pub max_price_impact: f32,
+    /// Default `0.2`.
+    pub is_known_bot: f32,
+    /// Default `0.1`.
+    pub likely_private_bundle: f32,
+    /// Default `0.3`.
+    pub uses_flashloan: f32,
+    /// Scaled by the attacker's repeat offender factor. Default `0.2`.
+    pub attacker_repeat_offender: f32,
+    /// Negative to demote candidates whose attacker and victim look like
+    /// one entity. Default `-0.5`.
+    pub same_entity: f32,
+    /// Scaled by the swap size factor, so implausible sizes lower the
+    /// score. Default `0.15`.
+    pub swap_size: f32,
+    /// Scaled by the attacker's bot activity factor. Default `0.15`.
+    pub bot_activity: f32,
+    /// Default `0.25`.
+    pub paid_builder: f32,
+    /// Scaled by the front-run's gas z-score, from 0 at the block's mean to
+    /// the full weight at `GAS_ZSCORE_SATURATION`. Overlaps with
+    /// `higher_front_gas_price`, so it's opt-in: default `0.0`.
+    pub front_gas_zscore: f32,
+}
+
+impl Default for ConfidenceWeights {
+    fn default() -> Self {
+        Self {
+            base: 0.3,
+            higher_front_gas_price: 0.2,
+            lower_back_gas_price: 0.1,
+            front_is_contract: 0.1,
+            back_is_contract: 0.1,
+            is_profitable: 0.25,
+            is_proportional: 0.15,
+            max_price_impact: 0.25,
+            is_known_bot: 0.2,
+            likely_private_bundle: 0.1,
+            uses_flashloan: 0.3,
+            attacker_repeat_offender: 0.2,
+            same_entity: -0.5,
+            swap_size: 0.15,
+            bot_activity: 0.15,
+            paid_builder: 0.25,
+            front_gas_zscore: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    /// How far (in percent) the simulated victim output may be from the
+    /// actual one before the pool state is considered wrong and the candidate
+    /// skipped. Default `1.0`.
+    pub reality_tolerance_pct: f64,
+    /// Report candidates failing that check with `simulation_unverified`
+    /// set instead of skipping them. Default `false`.
+    pub report_unverified: bool,
+    /// `"fixed_point"` to replay V2 pools rounding like the on-chain integer
+    /// math, for swaps in raw token units (see `Arithmetic::FixedPoint`), so
+    /// not with `decimals`. Default `"float"`.
+    pub arithmetic: Arithmetic,
+    /// Swaps taken out of the block for the victim's counterfactual:
+    /// `"front_run"`, `"legs"` or every swap of the attacker, `"bundle"`
+    /// (default).
+    pub counterfactual_removal: CounterfactualRemoval,
+    /// Transfer taxes of fee-on-transfer tokens, e.g.
+    /// `[simulation.token_taxes] PEPE2 = { buy_tax_pct = 5.0, sell_tax_pct = 5.0 }`.
+    pub token_taxes: TokenTaxes,
+    /// How far (in basis points) pool fees may be from the ones simulated,
+    /// widening `victim_loss_percentage_low`/`_high`. Default `5`.
+    pub fee_uncertainty_bps: u32,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            reality_tolerance_pct: 1.0,
+            report_unverified: false,
+            arithmetic: Arithmetic::default(),
+            counterfactual_removal: CounterfactualRemoval::default(),
+            token_taxes: TokenTaxes::default(),
+            fee_uncertainty_bps: 5,
+        }
+    }
+}
+
+/// Thresholds of `Severity::assess`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeverityConfig {
+    /// Victim loss from which an attack is `Medium`. Default `100.0`.
+    pub medium_loss_usd: f64,
+    /// Default `1000.0`.
+    pub high_loss_usd: f64,
+    /// Default `10000.0`.
+    pub critical_loss_usd: f64,
+    /// Confidence below which an attack is at most `Medium`. Default `0.7`.
+    pub confident: f32,
+    /// Attacks by the same attacker from which each is raised one level.
+    /// Counted over the block when streaming. Default `5`.
+    pub recurring_attacks: u64,
+}
+
+impl Default for SeverityConfig {
+    fn default() -> Self {
+        Self {
+            medium_loss_usd: 100.0,
+            high_loss_usd: 1_000.0,
+            critical_loss_usd: 10_000.0,
+            confident: 0.7,
+            recurring_attacks: 5,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_toml_str(text: &str) -> Result<Self, String> {
+        let config: Self =
+            toml::from_str(text).map_err(|err| format!("invalid TOML config: {}", err))?;
+        config.validate()?;
+        return Ok(config);
+    }
+
+    pub fn from_yaml_str(text: &str) -> Result<Self, String> {
+        let config: Self =
+            serde_yaml::from_str(text).map_err(|err| format!("invalid YAML config: {}", err))?;
+        config.validate()?;
+        return Ok(config);
+    }
+
+    /// Reject settings that can't be combined. `decimals` turns amounts into
+    /// whole tokens, which `Arithmetic::FixedPoint` would round down to
+    /// integers (1.5 WETH to 1), so it needs the raw units left alone.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.decimals.is_some() && self.simulation.arithmetic == Arithmetic::FixedPoint {
+            return Err(
+                "simulation.arithmetic = \"fixed_point\" needs raw amounts, unset decimals"
+                    .to_string(),
+            );
+        }
+        return Ok(());
+    }
+
+    /// Read a `.toml`, `.yaml` or `.yml` config file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        let config = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml_str(&text),
+            Some("yaml") | Some("yml") => Self::from_yaml_str(&text),
+            _ => Err("expected a .toml, .yaml or .yml file".to_string()),
+        };
+        return config.map_err(|err| format!("{}: {}", path.display(), err));
+    }
+
+    /// `transactions` converted with `decimals` when set.
+    pub(crate) fn whole_token_amounts<'a>(
+        &self,
+        transactions: &'a [SwapTransaction],
+    ) -> Cow<'a, [SwapTransaction]> {
+        return match &self.decimals {
+            Some(decimals) => {
+                let mut normalized = transactions.to_vec();
+                decimals.normalize_amounts(&mut normalized);
+                Cow::Owned(normalized)
+            }
+            None => Cow::Borrowed(transactions),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::{
+        find_same_block_sandwiches, find_same_block_sandwiches_with_config,
+    };
+
+    #[test]
+    fn test_partial_toml_and_yaml_keep_defaults() {
+        let from_toml = Config::from_toml_str(
+            "[heuristics.weights]\nis_profitable = 0.5\n\n[simulation]\nreality_tolerance_pct = 2.5\n",
+        )
+        .unwrap();
+        let from_yaml = Config::from_yaml_str(
+            "heuristics:\n  weights:\n    is_profitable: 0.5\nsimulation:\n  reality_tolerance_pct: 2.5\n",
+        )
+        .unwrap();
+        assert_eq!(from_toml, from_yaml);
+        assert_eq!(from_toml.heuristics.weights.is_profitable, 0.5);
+        assert_eq!(from_toml.heuristics.weights.base, 0.3);
+        assert_eq!(from_toml.tokens, TokenEquivalence::default());
+
+        let err = Config::from_toml_str("[heuristics]\nmin_front_ration = 0.1\n").unwrap_err();
+        assert!(err.contains("unknown field `min_front_ration`"), "{}", err);
+    }
+
+    #[test]
+    fn test_default_config_matches_builtin_behavior() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let mut builtin = find_same_block_sandwiches(&transactions);
+        let mut configured =
+            find_same_block_sandwiches_with_config(&transactions, &Config::default()).attacks;
+        builtin.sort_by_key(|attack| attack.attack_id());
+        configured.sort_by_key(|attack| attack.attack_id());
+        assert_eq!(builtin, configured);
+
+        // Without the ETH group the WETH/ETH sandwich is no longer a sandwich
+        let mut config = Config::default();
+        config.tokens.groups.remove("ETH_GROUP");
+        let attacks = find_same_block_sandwiches_with_config(&transactions, &config).attacks;
+        assert!(attacks.len() < builtin.len());
+        assert!(attacks
+            .iter()
+            .all(|attack| attack.victim_tx.tx_hash != "0xweth_victim"));
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::storage::write_atomically;
+
+/// Allows bursts of up to `capacity` requests, refilled at
+/// `requests_per_second`, so short spikes don't wait but the sustained rate
+/// stays within a provider's limit. A rate of 0 or less doesn't limit.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    requests_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(requests_per_second: f64, capacity: u32) -> Self {
+        Self {
+            capacity: f64::from(capacity),
+            tokens: f64::from(capacity),
+            requests_per_second,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token, sleeping until one is available.
+    pub fn acquire(&mut self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+
+    /// Take a token at `now`, returning how long to wait before it may be used.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        if self.requests_per_second.is_nan() || self.requests_per_second <= 0.0 {
+            return Duration::ZERO;
+        }
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.requests_per_second).min(self.capacity);
+        self.last_refill = now;
+
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            return Duration::ZERO;
+        }
+        return Duration::from_secs_f64(-self.tokens / self.requests_per_second);
+    }
+}
+
+/// Load a JSON object cache, or an empty one if the file doesn't exist yet.
+pub fn load_json_cache<T: DeserializeOwned>(path: &Path) -> Result<HashMap<String, T>, String> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    serde_json::from_str(&contents)
+        .map_err(|err| format!("invalid cache {}: {}", path.display(), err))
+}
+
+pub fn save_json_cache<T: Serialize>(
+    path: &Path,
+    cache: &HashMap<String, T>,
+) -> Result<(), String> {
+    let contents = serde_json::to_string_pretty(cache)
+        .map_err(|err| format!("failed to encode cache: {}", err))?;
+    write_atomically(path, &contents)
+        .map_err(|err| format!("failed to write {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_allows_bursts_then_paces() {
+        let mut bucket = TokenBucket::new(10.0, 3);
+        let start = bucket.last_refill;
+        for _ in 0..3 {
+            assert_eq!(bucket.reserve(start), Duration::ZERO);
+        }
+        let wait = bucket.reserve(start);
+        assert!((wait.as_secs_f64() - 0.1).abs() < 1e-9);
+
+        // A second later the bucket is full again
+        let later = start + Duration::from_secs(1);
+        for _ in 0..3 {
+            assert_eq!(bucket.reserve(later), Duration::ZERO);
+        }
+        assert!(!bucket.reserve(later).is_zero());
+
+        let mut unlimited = TokenBucket::new(0.0, 1);
+        for _ in 0..3 {
+            assert_eq!(unlimited.reserve(start), Duration::ZERO);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::cache::{load_json_cache, save_json_cache};
+use super::rpc::RpcClient;
+use crate::sandwich::transactions::SwapTransaction;
+
+/// Where contract code is looked up.
+#[derive(Debug, Clone)]
+pub enum ContractLookup {
+    /// A JSON-RPC node, `eth_getCode` at the swap's block (archive nodes
+    /// answer for old blocks). Blockscout instances expose the same call on
+    /// their `/api/eth-rpc` endpoint.
+    Rpc { client: Arc<RpcClient> },
+    /// An Etherscan-compatible explorer API (`module=proxy&action=eth_getCode`)
+    /// at the client's URL. Free tiers are typically limited to 5 requests
+    /// per second, see `RpcClient::with_rate_limit`.
+    Explorer {
+        client: Arc<RpcClient>,
+        api_key: String,
+    },
+}
+
+/// Resolves whether addresses were contracts at a block, caching results in
+/// memory and optionally in a JSON file so repeated runs don't hit the API
+/// again.
+///
+/// Code can be deployed after the swap (CREATE2 at a known address) or
+/// removed since (`SELFDESTRUCT`), so answers are per block, keyed
+/// `address:block_number` in the cache.
+#[derive(Debug)]
+pub struct ContractEnricher {
+    lookup: ContractLookup,
+    cache: HashMap<String, bool>,
+    cache_path: Option<PathBuf>,
+    pub lookups: usize,
+}
+
+impl ContractEnricher {
+    pub fn new(lookup: ContractLookup) -> Self {
+        Self {
+            lookup,
+            cache: HashMap::new(),
+            cache_path: None,
+            lookups: 0,
+        }
+    }
+
+    /// Load the cache from `path` (if it exists) and write it back on `save_cache`.
+    pub fn with_cache_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        self.cache = load_json_cache(&path)?;
+        self.cache_path = Some(path);
+        return Ok(self);
+    }
+
+    pub fn save_cache(&self) -> Result<(), String> {
+        match &self.cache_path {
+            Some(path) => save_json_cache(path, &self.cache),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether `address` had code at the end of `block_number`.
+    pub fn is_contract(&mut self, address: &str, block_number: u64) -> Result<bool, String> {
+        let address = address.to_lowercase();
+        let key = format!("{}:{}", address, block_number);
+        if let Some(is_contract) = self.cache.get(&key) {
+            return Ok(*is_contract);
+        }
+
+        let code = fetch_code(&self.lookup, &address, block_number)?;
+        self.lookups += 1;
+
+        let is_contract = has_code(&code);
+        self.cache.insert(key, is_contract);
+        return Ok(is_contract);
+    }
+
+    /// Set `is_contract_caller` on every swap whose `from_address` is a contract.
+    ///
+    /// The flag is only ever set, never cleared, so swaps already known to go
+    /// through a contract (e.g. decoded from traces) keep it. The cache is saved
+    /// once at the end, also when a lookup fails part way.
+    pub fn enrich(&mut self, transactions: &mut [SwapTransaction]) -> Result<(), String> {
+        let mut result = Ok(());
+        for tx in transactions.iter_mut() {
+            match self.is_contract(&tx.from_address, tx.block_number) {
+                Ok(is_contract) => tx.is_contract_caller |= is_contract,
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+
+        self.save_cache()?;
+        return result;
+    }
+}
+
+/// `0x` (or empty) means an externally owned account. EIP-7702 delegated
+/// accounts (`0xef0100…`) execute code too, so they count as contracts.
+fn has_code(code: &str) -> bool {
+    let code = code.trim_start_matches("0x");
+    !code.is_empty() && code.chars().any(|c| c != '0')
+}
+
+fn fetch_code(lookup: &ContractLookup, address: &str, block_number: u64) -> Result<String, String> {
+    let block_tag = format!("0x{:x}", block_number);
+    let response: serde_json::Value = match lookup {
+        ContractLookup::Rpc { client } => {
+            let params = serde_json::json!([address, block_tag]);
+            let result = client
+                .call("eth_getCode", params, Some(block_number))
+                .map_err(|err| format!("eth_getCode failed for {}: {}", address, err))?;
+            serde_json::json!({ "result": result })
+        }
+        ContractLookup::Explorer { client, api_key } => client
+            .get(
+                "",
+                &[
+                    ("module", "proxy"),
+                    ("action", "eth_getCode"),
+                    ("address", address),
+                    ("tag", &block_tag),
+                    ("apikey", api_key),
+                ],
+            )
+            .map_err(|err| format!("explorer getCode failed for {}: {}", address, err))?,
+    };
+
+    match response.get("result").and_then(|result| result.as_str()) {
+        Some(code) if code.starts_with("0x") => Ok(code.to_string()),
+        // Explorers report errors (e.g. rate limits) as a plain string result
+        _ => Err(format!("no code returned for {}: {}", address, response)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enrich_uses_cache_without_lookups() {
+        let path = std::env::temp_dir().join(format!("contract-cache-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"0xbot:1": true, "0xvictim:1": false, "0xvictim:2": true}"#,
+        )
+        .unwrap();
+
+        // The URL is unreachable, any lookup would fail the test
+        let lookup = ContractLookup::Rpc {
+            client: Arc::new(RpcClient::new("http://127.0.0.1:1")),
+        };
+        let mut enricher = ContractEnricher::new(lookup)
+            .with_cache_file(&path)
+            .expect("Failed to load cache");
+
+        let csv = "tx_hash,block_number,timestamp,tx_position_in_block,from_address,token_in,token_out,amount_in,amount_out,gas_price,pool_address,token_launch_block,is_contract_caller,usd_value_in,usd_value_out,gas_cost_usd\n\
+                   0xa,1,0,0,0xBOT,ETH,USDC,1,3000,10,0xpool,0,false,3000,3000,1\n\
+                   0xb,1,0,1,0xvictim,ETH,USDC,1,3000,10,0xpool,0,true,3000,3000,1\n";
+        let mut swaps: Vec<SwapTransaction> = crate::ingest::csv::read_transactions(csv.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        enricher.enrich(&mut swaps).expect("Failed to enrich");
+        assert_eq!(enricher.lookups, 0);
+        assert!(
+            swaps[0].is_contract_caller,
+            "Cached contract, case insensitive"
+        );
+        assert!(swaps[1].is_contract_caller, "Existing flag is kept");
+        // Deployed at block 2, after the swap
+        assert_eq!(enricher.is_contract("0xvictim", 1), Ok(false));
+        assert_eq!(enricher.is_contract("0xvictim", 2), Ok(true));
+        assert_eq!(enricher.lookups, 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_has_code() {
+        assert!(!has_code("0x"));
+        assert!(!has_code(""));
+        assert!(has_code("0x6080604052"));
+        assert!(has_code("0xef0100aabbccddeeff00112233445566778899aabbcc"));
+    }
+}
@@ -0,0 +1,660 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use revm::db::CacheDB;
+use revm::primitives::{
+    AccountInfo, Address, BlockEnv, Bytecode, Bytes, ExecutionResult, SpecId, TxEnv, TxKind, B256,
+    U256,
+};
+use revm::{DatabaseRef, Evm};
+use serde_json::{json, Value};
+
+use super::rpc::RpcClient;
+use crate::config::Config;
+use crate::sandwich::cancel::CancellationToken;
+use crate::sandwich::error::{DetectionOutcome, DetectorError};
+use crate::sandwich::pool_model::PoolModel;
+use crate::sandwich::same_block_sim::{
+    find_sandwiches_in_block_by_simulation, SandwichAttackBySimulation,
+};
+use crate::sandwich::transactions::{group_transactions_by_block, SwapTransaction};
+
+/// `balanceOf(address)` of an ERC-20.
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+
+/// Account state read from a JSON-RPC node at the end of `block_number`,
+/// the fork revm executes on. Reads are pinned to the block so the client's
+/// cache keeps them across runs.
+#[derive(Debug, Clone)]
+pub struct RpcStateDb {
+    client: Arc<RpcClient>,
+    block_number: u64,
+}
+
+impl RpcStateDb {
+    pub fn new(client: Arc<RpcClient>, block_number: u64) -> Self {
+        return Self {
+            client,
+            block_number,
+        };
+    }
+
+    fn call_at_block(&self, method: &str, params: Vec<Value>) -> Result<Value, String> {
+        let mut params = params;
+        params.push(json!(format!("0x{:x}", self.block_number)));
+        return self
+            .client
+            .call(method, Value::Array(params), Some(self.block_number));
+    }
+}
+
+impl DatabaseRef for RpcStateDb {
+    type Error = String;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, String> {
+        let address = json!(hex_address(&address));
+        let balance = parse_u256(&self.call_at_block("eth_getBalance", vec![address.clone()])?)?;
+        let nonce =
+            parse_u256(&self.call_at_block("eth_getTransactionCount", vec![address.clone()])?)?;
+        let code = parse_bytes(&self.call_at_block("eth_getCode", vec![address])?)?;
+        let code = Bytecode::new_raw(code);
+        return Ok(Some(AccountInfo::new(
+            balance,
+            nonce.to::<u64>(),
+            code.hash_slow(),
+            code,
+        )));
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, String> {
+        // Code always comes with its account from `basic_ref`
+        return Err(format!("code {} was not loaded with an account", code_hash));
+    }
+
+    fn storage_ref(&self, address: Address, index: U256) -> Result<U256, String> {
+        let params = vec![
+            json!(hex_address(&address)),
+            json!(format!("0x{:x}", index)),
+        ];
+        return parse_u256(&self.call_at_block("eth_getStorageAt", params)?);
+    }
+
+    fn block_hash_ref(&self, number: U256) -> Result<B256, String> {
+        let params = json!([format!("0x{:x}", number), false]);
+        let block = self
+            .client
+            .call("eth_getBlockByNumber", params, Some(number.to::<u64>()))?;
+        return B256::from_str(block["hash"].as_str().unwrap_or_default())
+            .map_err(|err| format!("invalid hash of block {}: {}", number, err));
+    }
+}
+
+/// A transaction of the block as revm runs it, with the
+/// `(token_in, amount_in)` and `token_out` of each of its swaps.
+#[derive(Debug, Clone)]
+struct PreparedTx {
+    tx_env: TxEnv,
+    inputs: Vec<(String, f64)>,
+    token_outs: Vec<String>,
+}
+
+impl PreparedTx {
+    /// Whether `swap` is one of the transaction's hops. Its calldata fixes
+    /// what goes in, so only the route's entry must keep its recorded
+    /// input; later hops get whatever the simulation passes along.
+    fn replays(&self, swap: &SwapTransaction) -> bool {
+        if !self.token_outs.contains(&swap.token_out) {
+            return false;
+        }
+        let entry = self
+            .inputs
+            .iter()
+            .find(|(token_in, _)| !self.token_outs.contains(token_in));
+        return match entry {
+            Some((token_in, amount_in)) if *token_in == swap.token_in => {
+                *amount_in == swap.amount_in
+            }
+            _ => true,
+        };
+    }
+}
+
+/// Executes the block's actual transactions on a fork of the chain instead
+/// of pricing swaps on a curve, so complex pools, fee-on-transfer tokens and
+/// routes through several protocols come out as they would on chain.
+///
+/// A swap runs its whole transaction and yields what the sender's balance
+/// of `token_out` changed by; each transaction runs at most once per state,
+/// later hops of a route read the same result. Swaps must be in raw units
+/// with token addresses, as decoded from traces. Swaps the block didn't
+/// contain, such as the resized front-runs of the extraction search, can't
+/// be replayed: `try_swap` fails on them and `swap` returns NaN.
+///
+/// Only the transactions of the prepared swaps are replayed. Anything else
+/// the block ran before them (transfers, liquidity changes, swaps missing
+/// from the input) is skipped, so the fork drifts from the chain's state as
+/// the block goes on; the simulation's reality check
+/// (`SimulationConfig::reality_tolerance_pct`) catches victims it affects.
+///
+/// The pools `evm_pool_map` returns share one fork, so a transaction run
+/// through one pool moves the others too. Cloning a pool copies the fork's
+/// state, which is how the simulation branches into the with and without
+/// attacker timelines, and every clone is a timeline of its own: the pools
+/// of a cloned map no longer see each other's transactions. Gas is free and
+/// the base fee zero, so balances only move with the swaps themselves.
+pub struct EvmPool {
+    fork: Arc<Mutex<Fork>>,
+    pool_address: String,
+    client: Arc<RpcClient>,
+    block_env: BlockEnv,
+    spec_id: SpecId,
+    native_token: String,
+    transactions: HashMap<String, PreparedTx>,
+}
+
+/// The chain state the pools of one timeline execute on.
+struct Fork {
+    db: CacheDB<RpcStateDb>,
+    /// Balance changes by token of the transactions already executed.
+    executed: HashMap<String, HashMap<String, f64>>,
+}
+
+impl Clone for EvmPool {
+    fn clone(&self) -> Self {
+        let source = self.fork.lock().unwrap();
+        let fork = Fork {
+            db: source.db.clone(),
+            executed: source.executed.clone(),
+        };
+        return Self {
+            fork: Arc::new(Mutex::new(fork)),
+            pool_address: self.pool_address.clone(),
+            client: self.client.clone(),
+            block_env: self.block_env.clone(),
+            spec_id: self.spec_id,
+            native_token: self.native_token.clone(),
+            transactions: self.transactions.clone(),
+        };
+    }
+}
+
+impl EvmPool {
+    /// Fork the state at the start of `block_number`, executing with the
+    /// rules of `SpecId::LATEST` unless set otherwise with `with_spec_id`.
+    pub fn new(client: Arc<RpcClient>, block_number: u64) -> Result<Self, String> {
+        let params = json!([format!("0x{:x}", block_number), false]);
+        let header = client.call("eth_getBlockByNumber", params, Some(block_number))?;
+        if header.is_null() {
+            return Err(format!("block {} not found", block_number));
+        }
+        let block_env = BlockEnv {
+            number: U256::from(block_number),
+            coinbase: parse_address(&header["miner"])?,
+            timestamp: parse_u256(&header["timestamp"])?,
+            gas_limit: parse_u256(&header["gasLimit"])?,
+            basefee: U256::ZERO,
+            difficulty: parse_u256(&header["difficulty"]).unwrap_or_default(),
+            prevrandao: B256::from_str(header["mixHash"].as_str().unwrap_or_default()).ok(),
+            ..BlockEnv::default()
+        };
+        let db = RpcStateDb::new(client.clone(), block_number.saturating_sub(1));
+        let fork = Fork {
+            db: CacheDB::new(db),
+            executed: HashMap::new(),
+        };
+        return Ok(Self {
+            fork: Arc::new(Mutex::new(fork)),
+            pool_address: String::new(),
+            client,
+            block_env,
+            spec_id: SpecId::LATEST,
+            native_token: "ETH".to_string(),
+            transactions: HashMap::new(),
+        });
+    }
+
+    /// Hard fork whose rules the block is executed with.
+    pub fn with_spec_id(mut self, spec_id: SpecId) -> Self {
+        self.spec_id = spec_id;
+        self
+    }
+
+    /// The token swaps name the chain's native currency with, measured as
+    /// the sender's balance. Defaults to `ETH`.
+    pub fn with_native_token(mut self, token: &str) -> Self {
+        self.native_token = token.to_string();
+        self
+    }
+
+    /// Fetch the transactions of the swaps in `transactions` that belong to
+    /// this block so they can be replayed.
+    pub fn prepare(mut self, transactions: &[SwapTransaction]) -> Result<Self, String> {
+        let block_number = self.block_env.number.to::<u64>();
+        for tx in transactions {
+            if tx.block_number != block_number {
+                continue;
+            }
+            if let Some(prepared) = self.transactions.get_mut(&tx.tx_hash) {
+                prepared.inputs.push((tx.token_in.clone(), tx.amount_in));
+                if !prepared.token_outs.contains(&tx.token_out) {
+                    prepared.token_outs.push(tx.token_out.clone());
+                }
+                continue;
+            }
+            let params = json!([tx.tx_hash]);
+            let fetched =
+                self.client
+                    .call("eth_getTransactionByHash", params, Some(block_number))?;
+            if fetched.is_null() {
+                return Err(format!("transaction {} not found", tx.tx_hash));
+            }
+            let tx_env = TxEnv {
+                caller: parse_address(&fetched["from"])?,
+                gas_limit: parse_u256(&fetched["gas"])?.to::<u64>(),
+                gas_price: U256::ZERO,
+                transact_to: match fetched["to"].is_null() {
+                    true => TxKind::Create,
+                    false => TxKind::Call(parse_address(&fetched["to"])?),
+                },
+                value: parse_u256(&fetched["value"])?,
+                data: parse_bytes(&fetched["input"])?,
+                nonce: None,
+                chain_id: None,
+                ..TxEnv::default()
+            };
+            let prepared = PreparedTx {
+                tx_env,
+                inputs: vec![(tx.token_in.clone(), tx.amount_in)],
+                token_outs: vec![tx.token_out.clone()],
+            };
+            self.transactions.insert(tx.tx_hash.clone(), prepared);
+        }
+        return Ok(self);
+    }
+
+    /// The same fork as this pool, standing for `pool_address`.
+    fn at_pool(&self, pool_address: &str) -> Self {
+        return Self {
+            fork: self.fork.clone(),
+            pool_address: pool_address.to_string(),
+            client: self.client.clone(),
+            block_env: self.block_env.clone(),
+            spec_id: self.spec_id,
+            native_token: self.native_token.clone(),
+            transactions: self.transactions.clone(),
+        };
+    }
+
+    /// Run the swap's transaction if it hasn't been on this state yet and
+    /// return its balance changes.
+    fn execute(&self, tx_hash: &str) -> Result<HashMap<String, f64>, String> {
+        let mut fork = self.fork.lock().unwrap();
+        if let Some(deltas) = fork.executed.get(tx_hash) {
+            return Ok(deltas.clone());
+        }
+        let prepared = match self.transactions.get(tx_hash) {
+            Some(prepared) => prepared.clone(),
+            None => return Err(format!("transaction {} was not prepared", tx_hash)),
+        };
+        let caller = prepared.tx_env.caller;
+        let mut before = Vec::new();
+        for token in &prepared.token_outs {
+            before.push(self.balance_of(&mut fork.db, token, caller)?);
+        }
+
+        let result = self.transact(&mut fork.db, prepared.tx_env, true)?;
+        if !result.is_success() {
+            return Err(format!("transaction {} failed: {:?}", tx_hash, result));
+        }
+
+        let mut deltas = HashMap::new();
+        for (token, before) in prepared.token_outs.iter().zip(before) {
+            let after = self.balance_of(&mut fork.db, token, caller)?;
+            deltas.insert(token.clone(), f64::from(after) - f64::from(before));
+        }
+        fork.executed.insert(tx_hash.to_string(), deltas.clone());
+        return Ok(deltas);
+    }
+
+    fn balance_of(
+        &self,
+        db: &mut CacheDB<RpcStateDb>,
+        token: &str,
+        owner: Address,
+    ) -> Result<U256, String> {
+        if token.eq_ignore_ascii_case(&self.native_token) {
+            let account = revm::Database::basic(db, owner)?;
+            return Ok(account.map(|account| account.balance).unwrap_or_default());
+        }
+        let token_address = Address::from_str(token)
+            .map_err(|err| format!("token {} is not an address: {}", token, err))?;
+        let mut data = BALANCE_OF_SELECTOR.to_vec();
+        data.extend_from_slice(B256::left_padding_from(owner.as_slice()).as_slice());
+        let call = TxEnv {
+            caller: owner,
+            gas_limit: 1_000_000,
+            gas_price: U256::ZERO,
+            transact_to: TxKind::Call(token_address),
+            data: Bytes::from(data),
+            nonce: None,
+            chain_id: None,
+            ..TxEnv::default()
+        };
+        return match self.transact(db, call, false)? {
+            ExecutionResult::Success { output, .. } if output.data().len() >= 32 => {
+                Ok(U256::from_be_slice(&output.data()[..32]))
+            }
+            result => Err(format!("balanceOf on {} failed: {:?}", token, result)),
+        };
+    }
+
+    fn transact(
+        &self,
+        db: &mut CacheDB<RpcStateDb>,
+        tx_env: TxEnv,
+        commit: bool,
+    ) -> Result<ExecutionResult, String> {
+        let mut evm = Evm::builder()
+            .with_db(db)
+            .with_spec_id(self.spec_id)
+            .with_block_env(self.block_env.clone())
+            .with_tx_env(tx_env)
+            .build();
+        let result = match commit {
+            true => evm.transact_commit(),
+            false => evm.transact().map(|result| result.result),
+        };
+        return result.map_err(|err| format!("{:?}", err));
+    }
+}
+
+impl fmt::Debug for EvmPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EvmPool")
+            .field("block_number", &self.block_env.number)
+            .field("spec_id", &self.spec_id)
+            .field("pool_address", &self.pool_address)
+            .field("transactions", &self.transactions.len())
+            .field("executed", &self.fork.lock().unwrap().executed.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl PoolModel for EvmPool {
+    /// NaN where `try_swap` fails.
+    fn swap(&mut self, swap: &SwapTransaction) -> f64 {
+        return self.try_swap(swap).unwrap_or(f64::NAN);
+    }
+
+    fn try_swap(&mut self, swap: &SwapTransaction) -> Result<f64, DetectorError> {
+        let failed = |reason: String| DetectorError::SwapFailed {
+            tx_hash: swap.tx_hash.clone(),
+            reason,
+        };
+        let replayable = self
+            .transactions
+            .get(&swap.tx_hash)
+            .is_some_and(|prepared| prepared.replays(swap));
+        if !replayable {
+            return Err(failed("not a swap of the block's transactions".to_string()));
+        }
+        let deltas = self.execute(&swap.tx_hash).map_err(failed)?;
+        return match deltas.get(&swap.token_out) {
+            Some(delta) => Ok(*delta),
+            None => Err(failed(format!("no balance change of {}", swap.token_out))),
+        };
+    }
+
+    /// Fees are whatever the contracts charge.
+    fn fee_bps(&self) -> u32 {
+        return 0;
+    }
+
+    fn set_fee_bps(&mut self, _fee_bps: u32) {}
+
+    fn boxed_clone(&self) -> Box<dyn PoolModel> {
+        return Box::new(self.clone());
+    }
+}
+
+/// The pools of the block's swaps, keyed by their addresses, all on one
+/// fork of the state before the block.
+pub fn evm_pool_map(
+    client: &Arc<RpcClient>,
+    spec_id: SpecId,
+    block_number: u64,
+    transactions: &[SwapTransaction],
+) -> Result<HashMap<String, EvmPool>, String> {
+    let pool = EvmPool::new(client.clone(), block_number)?
+        .with_spec_id(spec_id)
+        .prepare(transactions)?;
+    let mut pool_map = HashMap::new();
+    for tx in transactions {
+        if tx.block_number == block_number && !pool_map.contains_key(&tx.pool_address) {
+            pool_map.insert(tx.pool_address.clone(), pool.at_pool(&tx.pool_address));
+        }
+    }
+    return Ok(pool_map);
+}
+
+/// `find_sandwich_attacks_by_simulation` with every block replayed on a
+/// fork of the chain rather than on constant-product pools.
+pub fn find_sandwich_attacks_with_evm(
+    client: &Arc<RpcClient>,
+    spec_id: SpecId,
+    transactions: &[SwapTransaction],
+    config: &Config,
+) -> Result<DetectionOutcome<SandwichAttackBySimulation>, String> {
+    let mut blocks: Vec<_> = group_transactions_by_block(transactions)
+        .into_iter()
+        .collect();
+    blocks.sort_by_key(|(block_id, _)| *block_id);
+
+    let mut outcome = DetectionOutcome::default();
+    for (block_id, block_transactions) in blocks {
+        let pool_map = evm_pool_map(client, spec_id, block_id.block_number, &block_transactions)?;
+        outcome.extend(find_sandwiches_in_block_by_simulation(
+            &pool_map,
+            &block_transactions,
+            config,
+            &CancellationToken::new(),
+        ));
+    }
+    return Ok(outcome);
+}
+
+/// Lowercase `0x` hex, as nodes and the RPC cache keys expect.
+fn hex_address(address: &Address) -> String {
+    return format!("{:?}", address);
+}
+
+fn parse_address(value: &Value) -> Result<Address, String> {
+    let text = value.as_str().unwrap_or_default();
+    return Address::from_str(text).map_err(|err| format!("invalid address {}: {}", value, err));
+}
+
+fn parse_u256(value: &Value) -> Result<U256, String> {
+    let text = value.as_str().unwrap_or_default();
+    return U256::from_str(text).map_err(|err| format!("invalid quantity {}: {}", value, err));
+}
+
+fn parse_bytes(value: &Value) -> Result<Bytes, String> {
+    let text = value.as_str().unwrap_or_default();
+    return Bytes::from_str(text).map_err(|err| format!("invalid data {}: {}", value, err));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enrich::cache::save_json_cache;
+    use crate::enrich::rpc::cache_key;
+
+    const CONTRACT: &str = "0x000000000000000000000000000000000000c0de";
+    const BOT: &str = "0x00000000000000000000000000000000000000b0";
+    const VICTIM: &str = "0x0000000000000000000000000000000000000071";
+    const MINER: &str = "0x00000000000000000000000000000000000000c0";
+
+    fn account(cached: &mut HashMap<String, Value>, address: &str, balance: u128, code: &str) {
+        for (method, result) in [
+            ("eth_getBalance", format!("0x{:x}", balance)),
+            ("eth_getTransactionCount", "0x0".to_string()),
+            ("eth_getCode", code.to_string()),
+        ] {
+            let params = json!([address, "0x63"]);
+            cached.insert(cache_key(method, &params, 99), json!(result));
+        }
+    }
+
+    fn swap(tx_hash: &str, position: u32, from: &str) -> SwapTransaction {
+        return SwapTransaction {
+            tx_hash: tx_hash.to_string(),
+            chain_id: 1,
+            block_number: 100,
+            timestamp: 1_700_000_000,
+            tx_position_in_block: position,
+            from_address: from.to_string(),
+            token_in: "USDC".to_string(),
+            token_out: "ETH".to_string(),
+            amount_in: 0.0,
+            amount_out: 0.0,
+            gas_price: 0,
+            pool_address: CONTRACT.to_string(),
+            token_launch_block: 0,
+            is_contract_caller: false,
+            usd_value_in: 0.0,
+            usd_value_out: 0.0,
+            gas_cost_usd: 0.0,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
+            raw_amount_in: None,
+        };
+    }
+
+    /// A client whose whole fork of block 100 comes from a cache file:
+    /// `0xfront` and `0xvictim` both call the contract.
+    fn cached_client(name: &str) -> (Arc<RpcClient>, std::path::PathBuf) {
+        let mut cached = HashMap::new();
+        // Pays a tenth of its balance to whoever calls it, so every call
+        // gets less than the one before
+        account(
+            &mut cached,
+            CONTRACT,
+            100_000_000_000_000_000_000,
+            "0x6000600060006000600a4704335af15000",
+        );
+        account(&mut cached, BOT, 0, "0x");
+        account(&mut cached, VICTIM, 0, "0x");
+        account(&mut cached, MINER, 0, "0x");
+        let header = json!({
+            "miner": MINER,
+            "timestamp": "0x6553f100",
+            "gasLimit": "0x1c9c380",
+            "difficulty": "0x0",
+            "mixHash": format!("0x{:064x}", 1),
+        });
+        cached.insert(
+            cache_key("eth_getBlockByNumber", &json!(["0x64", false]), 100),
+            header,
+        );
+        for (tx_hash, from) in [("0xfront", BOT), ("0xvictim", VICTIM)] {
+            let tx = json!({
+                "from": from,
+                "to": CONTRACT,
+                "gas": "0x30d40",
+                "value": "0x0",
+                "input": "0x",
+            });
+            cached.insert(
+                cache_key("eth_getTransactionByHash", &json!([tx_hash]), 100),
+                tx,
+            );
+        }
+        let path = std::env::temp_dir().join(format!("evm-{}-{}.json", name, std::process::id()));
+        save_json_cache(&path, &cached).unwrap();
+
+        // The URL is unreachable, the whole fork must come from the cache
+        let client = RpcClient::new("http://127.0.0.1:1")
+            .with_retries(0, std::time::Duration::from_millis(1))
+            .with_cache_file(&path)
+            .expect("Failed to load cache");
+        return (Arc::new(client), path);
+    }
+
+    #[test]
+    fn test_block_transactions_replay_on_a_cached_fork() {
+        let (client, path) = cached_client("replay");
+        let front = swap("0xfront", 0, BOT);
+        let victim = swap("0xvictim", 1, VICTIM);
+        let pool_map = evm_pool_map(
+            &client,
+            SpecId::CANCUN,
+            100,
+            &[front.clone(), victim.clone()],
+        )
+        .expect("Failed to prepare the fork");
+        let mut pool = pool_map[CONTRACT].clone();
+
+        let unsandwiched = pool.clone();
+        assert_eq!(pool.swap(&front), 1e19);
+        assert_eq!(pool.swap(&victim), 9e18);
+        // A route's later hops read the result of the same execution
+        assert_eq!(pool.swap(&victim), 9e18);
+        // Without the front-run the victim is paid from the untouched balance
+        assert_eq!(unsandwiched.clone().swap(&victim), 1e19);
+
+        let resized = SwapTransaction {
+            amount_in: 5.0,
+            ..front.clone()
+        };
+        assert!(unsandwiched.clone().swap(&resized).is_nan());
+        assert!(matches!(
+            unsandwiched.clone().try_swap(&resized),
+            Err(DetectorError::SwapFailed { .. })
+        ));
+        assert_eq!(client.requests(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_pools_of_a_block_share_their_fork() {
+        let (client, path) = cached_client("shared");
+        // Both transactions go through the contract, recorded on two pools
+        let front = SwapTransaction {
+            pool_address: "0xpool_a".to_string(),
+            ..swap("0xfront", 0, BOT)
+        };
+        let victim = SwapTransaction {
+            pool_address: "0xpool_b".to_string(),
+            ..swap("0xvictim", 1, VICTIM)
+        };
+        let pool_map = evm_pool_map(
+            &client,
+            SpecId::CANCUN,
+            100,
+            &[front.clone(), victim.clone()],
+        )
+        .expect("Failed to prepare the fork");
+        assert_eq!(pool_map.len(), 2);
+
+        let unsandwiched = pool_map.clone();
+        let mut pools = pool_map;
+        assert_eq!(pools.get_mut("0xpool_a").unwrap().swap(&front), 1e19);
+        // The front-run on the other pool paid out of the same balance
+        let branch = pools.clone();
+        assert_eq!(pools.get_mut("0xpool_b").unwrap().swap(&victim), 9e18);
+        assert_eq!(branch["0xpool_b"].clone().swap(&victim), 9e18);
+
+        // Every clone is a timeline of its own, even of the same map
+        let mut front_pool = unsandwiched["0xpool_a"].clone();
+        let mut victim_pool = unsandwiched["0xpool_b"].clone();
+        assert_eq!(front_pool.swap(&front), 1e19);
+        assert_eq!(victim_pool.swap(&victim), 1e19);
+        assert_eq!(unsandwiched["0xpool_b"].clone().swap(&victim), 1e19);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,11 @@
+pub mod cache;
+#[cfg(feature = "rpc")]
+pub mod contracts;
+#[cfg(feature = "evm")]
+pub mod evm;
+#[cfg(feature = "rpc")]
+pub mod pools;
+#[cfg(feature = "rpc")]
+pub mod prices;
+#[cfg(feature = "rpc")]
+pub mod rpc;
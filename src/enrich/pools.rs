@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::prices::eth_call;
+use super::rpc::RpcClient;
+use crate::config::Config;
+use crate::ingest::traces::{signed_word_to_f64, word_to_f64, word_to_u256, PoolTokens};
+use crate::sandwich::cancel::CancellationToken;
+use crate::sandwich::error::DetectionOutcome;
+use crate::sandwich::pool_model::{ConcentratedLiquidityPool, PoolModel};
+use crate::sandwich::same_block_sim::{
+    find_sandwiches_in_block_by_simulation, Pool, SandwichAttackBySimulation,
+};
+use crate::sandwich::tokens::TokenDecimals;
+use crate::sandwich::transactions::{group_transactions_by_block, SwapTransaction};
+
+/// `getReserves()` of a Uniswap V2 pair.
+const GET_RESERVES_SELECTOR: &str = "0x0902f1ac";
+/// `slot0()`, `liquidity()` and `fee()` of a Uniswap V3 pool.
+const SLOT0_SELECTOR: &str = "0x3850c7bd";
+const LIQUIDITY_SELECTOR: &str = "0x1a686502";
+const FEE_SELECTOR: &str = "0xddca3f43";
+/// `token0()` and `token1()`, the same on V2 and V3.
+const TOKEN0_SELECTOR: &str = "0x0dfe1681";
+const TOKEN1_SELECTOR: &str = "0xd21220a7";
+
+/// Which state calls a pool answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolKind {
+    UniswapV2,
+    /// Read without its initialized ticks, so swaps leaving the current
+    /// range are priced with the in-range liquidity.
+    UniswapV3,
+}
+
+/// Builds simulation pool maps from an archive node: the state of every
+/// pool a block's swaps go through, read at the end of the previous block.
+///
+/// Amounts are in raw token units, as the swaps decoded from traces. States
+/// are kept in memory, enable the client's cache to keep them across runs.
+#[derive(Debug)]
+pub struct PoolStateFetcher {
+    client: Arc<RpcClient>,
+    kinds: HashMap<String, PoolKind>,
+    tokens: HashMap<String, PoolTokens>,
+    decimals: Option<TokenDecimals>,
+    states: HashMap<(String, u64), Box<dyn PoolModel>>,
+}
+
+impl PoolStateFetcher {
+    /// Pools are V2 unless set otherwise with `with_pool_kind`.
+    pub fn new(client: Arc<RpcClient>) -> Self {
+        Self {
+            client,
+            kinds: HashMap::new(),
+            tokens: HashMap::new(),
+            decimals: None,
+            states: HashMap::new(),
+        }
+    }
+
+    pub fn with_pool_kind(mut self, pool_address: &str, kind: PoolKind) -> Self {
+        self.kinds.insert(pool_address.to_lowercase(), kind);
+        self
+    }
+
+    /// Tokens of a pool as the swaps name them, e.g. symbols. Otherwise
+    /// `token0()`/`token1()` are asked for and swaps must use addresses.
+    pub fn with_pool_tokens(mut self, pool_address: &str, tokens: PoolTokens) -> Self {
+        self.tokens.insert(pool_address.to_lowercase(), tokens);
+        self
+    }
+
+    /// Convert V2 reserves to whole tokens, for swaps normalized with the
+    /// same decimals. V3 states stay in raw units.
+    pub fn with_decimals(mut self, decimals: TokenDecimals) -> Self {
+        self.decimals = Some(decimals);
+        self
+    }
+
+    /// State of `pool_address` at the end of `block_number`.
+    pub fn fetch_pool(
+        &mut self,
+        pool_address: &str,
+        block_number: u64,
+    ) -> Result<Box<dyn PoolModel>, String> {
+        let key = (pool_address.to_lowercase(), block_number);
+        if let Some(state) = self.states.get(&key) {
+            return Ok(state.clone());
+        }
+
+        let tokens = self.pool_tokens(pool_address, block_number)?;
+        let kind = self
+            .kinds
+            .get(&key.0)
+            .copied()
+            .unwrap_or(PoolKind::UniswapV2);
+        let state: Box<dyn PoolModel> = match kind {
+            PoolKind::UniswapV2 => {
+                let reserves = self.call(pool_address, GET_RESERVES_SELECTOR, block_number)?;
+                let reserve0 = reserves.get(..64).and_then(word_to_u256);
+                let reserve1 = reserves.get(64..128).and_then(word_to_u256);
+                let (reserve0, reserve1) = match (reserve0, reserve1) {
+                    (Some(reserve0), Some(reserve1)) => (reserve0, reserve1),
+                    _ => return Err(format!("invalid getReserves result from {}", pool_address)),
+                };
+                let pool =
+                    Pool::from_raw_reserves(reserve0, reserve1, tokens.token0, tokens.token1);
+                match &self.decimals {
+                    Some(decimals) => Box::new(pool.normalized(decimals)),
+                    None => Box::new(pool),
+                }
+            }
+            PoolKind::UniswapV3 => {
+                let slot0 = self.call(pool_address, SLOT0_SELECTOR, block_number)?;
+                let (sqrt_price_x96, tick) = match (slot0.get(..64), slot0.get(64..128)) {
+                    (Some(sqrt_price), Some(tick)) => {
+                        (word_to_f64(sqrt_price), signed_word_to_f64(tick))
+                    }
+                    _ => return Err(format!("invalid slot0 result from {}", pool_address)),
+                };
+                let liquidity =
+                    word_to_f64(&self.call(pool_address, LIQUIDITY_SELECTOR, block_number)?);
+                // In hundredths of a basis point
+                let fee = word_to_f64(&self.call(pool_address, FEE_SELECTOR, block_number)?);
+                let mut pool = ConcentratedLiquidityPool::new(
+                    sqrt_price_x96 / 2f64.powi(96),
+                    liquidity,
+                    tokens.token0,
+                    tokens.token1,
+                )
+                .with_fee_bps((fee / 100.0).round() as u32);
+                pool.tick = tick as i32;
+                Box::new(pool)
+            }
+        };
+
+        self.states.insert(key, state.clone());
+        return Ok(state);
+    }
+
+    /// Pools of the swaps of `block_number`, as they were before it.
+    pub fn pool_map(
+        &mut self,
+        block_number: u64,
+        transactions: &[SwapTransaction],
+    ) -> Result<HashMap<String, Box<dyn PoolModel>>, String> {
+        let mut pool_map = HashMap::new();
+        for tx in transactions {
+            if tx.block_number != block_number || pool_map.contains_key(&tx.pool_address) {
+                continue;
+            }
+            let state = self.fetch_pool(&tx.pool_address, block_number.saturating_sub(1))?;
+            pool_map.insert(tx.pool_address.clone(), state);
+        }
+        return Ok(pool_map);
+    }
+
+    fn pool_tokens(&mut self, pool_address: &str, block_number: u64) -> Result<PoolTokens, String> {
+        let key = pool_address.to_lowercase();
+        if let Some(tokens) = self.tokens.get(&key) {
+            return Ok(tokens.clone());
+        }
+        let address = |word: String| match word.get(24..64) {
+            Some(address) => Ok(format!("0x{}", address)),
+            None => Err(format!("invalid token address from {}", pool_address)),
+        };
+        let tokens = PoolTokens {
+            token0: address(self.call(pool_address, TOKEN0_SELECTOR, block_number)?)?,
+            token1: address(self.call(pool_address, TOKEN1_SELECTOR, block_number)?)?,
+        };
+        self.tokens.insert(key, tokens.clone());
+        return Ok(tokens);
+    }
+
+    fn call(
+        &self,
+        pool_address: &str,
+        selector: &str,
+        block_number: u64,
+    ) -> Result<String, String> {
+        return eth_call(&self.client, pool_address, selector, block_number);
+    }
+}
+
+/// `find_sandwich_attacks_by_simulation` with each block simulated on pool
+/// states fetched from the node rather than a hand-built `pool_map`. The
+/// transactions should be of the client's chain.
+pub fn find_sandwich_attacks_with_fetched_pools(
+    fetcher: &mut PoolStateFetcher,
+    transactions: &[SwapTransaction],
+    config: &Config,
+) -> Result<DetectionOutcome<SandwichAttackBySimulation>, String> {
+    let mut blocks: Vec<_> = group_transactions_by_block(transactions)
+        .into_iter()
+        .collect();
+    blocks.sort_by_key(|(block_id, _)| *block_id);
+
+    let mut outcome = DetectionOutcome::default();
+    for (block_id, block_transactions) in blocks {
+        let pool_map = fetcher.pool_map(block_id.block_number, &block_transactions)?;
+        outcome.extend(find_sandwiches_in_block_by_simulation(
+            &pool_map,
+            &block_transactions,
+            config,
+            &CancellationToken::new(),
+        ));
+    }
+    return Ok(outcome);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enrich::cache::save_json_cache;
+    use crate::enrich::rpc::cache_key;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+    use serde_json::{json, Value};
+
+    fn word(value: u128) -> String {
+        format!("{:064x}", value)
+    }
+
+    fn eth_call_key(to: &str, data: &str, block_number: u64) -> String {
+        let params = json!([{ "to": to, "data": data }, format!("0x{:x}", block_number)]);
+        cache_key("eth_call", &params, block_number)
+    }
+
+    #[test]
+    fn test_pool_maps_from_cached_state_calls() {
+        let transactions = crate::test_support::sample_block();
+        let path = std::env::temp_dir().join(format!("pool-cache-{}.json", std::process::id()));
+        let usdc = "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+        let weth = "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+        let cached: HashMap<String, Value> = HashMap::from([
+            (
+                eth_call_key("0xpool1", GET_RESERVES_SELECTOR, 12359),
+                json!(format!(
+                    "0x{}{}{}",
+                    word(1000000),
+                    word(50000000000),
+                    word(1640995388)
+                )),
+            ),
+            (
+                eth_call_key("0xpool_v3", SLOT0_SELECTOR, 100),
+                // sqrtPriceX96 of 2^96 (price 1) at tick 0
+                json!(format!("0x{}{}", word(1 << 96), word(0))),
+            ),
+            (
+                eth_call_key("0xpool_v3", LIQUIDITY_SELECTOR, 100),
+                json!(format!("0x{}", word(5000))),
+            ),
+            (
+                eth_call_key("0xpool_v3", FEE_SELECTOR, 100),
+                json!(format!("0x{}", word(500))),
+            ),
+            (
+                eth_call_key("0xpool_v3", TOKEN0_SELECTOR, 100),
+                json!(format!("0x{:0>64}", usdc)),
+            ),
+            (
+                eth_call_key("0xpool_v3", TOKEN1_SELECTOR, 100),
+                json!(format!("0x{:0>64}", weth)),
+            ),
+        ]);
+        save_json_cache(&path, &cached).unwrap();
+
+        // The URL is unreachable, every state must come from the cache
+        let client = RpcClient::new("http://127.0.0.1:1")
+            .with_retries(0, std::time::Duration::from_millis(1))
+            .with_cache_file(&path)
+            .expect("Failed to load cache");
+        let client = Arc::new(client);
+        let tokens = PoolTokens {
+            token0: "USDC".to_string(),
+            token1: "SHIB".to_string(),
+        };
+        let mut fetcher = PoolStateFetcher::new(client.clone())
+            .with_pool_tokens("0xpool1", tokens)
+            .with_pool_kind("0xPOOL_V3", PoolKind::UniswapV3);
+
+        let fetched = find_sandwich_attacks_with_fetched_pools(
+            &mut fetcher,
+            &transactions,
+            &Config::default(),
+        )
+        .expect("Failed to fetch pools");
+        let pool_map = crate::test_support::sample_pool_map();
+        assert!(!fetched.attacks.is_empty());
+        assert_eq!(
+            fetched.attacks,
+            find_sandwich_attacks_by_simulation(&pool_map, &transactions)
+        );
+
+        let v3 = format!("{:?}", fetcher.fetch_pool("0xpool_v3", 100).unwrap());
+        let expected = ConcentratedLiquidityPool::new(
+            1.0,
+            5000.0,
+            format!("0x{}", usdc),
+            format!("0x{}", weth),
+        )
+        .with_fee_bps(5);
+        assert_eq!(v3, format!("{:?}", expected));
+        assert_eq!(client.requests(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
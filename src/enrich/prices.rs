@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::cache::{load_json_cache, save_json_cache};
+use super::rpc::RpcClient;
+use crate::ingest::traces::{signed_word_to_f64, word_to_f64};
+use crate::sandwich::tokens::TokenDecimals;
+use crate::sandwich::transactions::SwapTransaction;
+
+pub const DEFILLAMA_URL: &str = "https://coins.llama.fi";
+pub const COINGECKO_URL: &str = "https://api.coingecko.com/api/v3";
+
+/// Historical prices from aggregators are cached per hour.
+const PRICE_BUCKET_SECONDS: u64 = 3600;
+
+/// `latestRoundData()` and `decimals()` of a Chainlink aggregator.
+const LATEST_ROUND_DATA_SELECTOR: &str = "0xfeaf968c";
+const DECIMALS_SELECTOR: &str = "0x313ce567";
+
+/// Where historical USD prices come from. Tokens are mapped to the
+/// source's identifiers with `PriceOracle::with_token_id`. Requests go
+/// through the source's client, rate limit it to the API's limits.
+#[derive(Debug, Clone)]
+pub enum PriceSource {
+    /// DefiLlama coins API, with a client for `DEFILLAMA_URL`. Ids look like
+    /// `coingecko:ethereum` or `ethereum:0xa0b8…`; unmapped `0x` tokens are
+    /// looked up on `chain`.
+    DefiLlama {
+        client: Arc<RpcClient>,
+        chain: String,
+    },
+    /// CoinGecko `market_chart/range`, with a client for `COINGECKO_URL`.
+    /// Ids are CoinGecko coin ids (e.g. `ethereum`).
+    CoinGecko {
+        client: Arc<RpcClient>,
+        api_key: Option<String>,
+    },
+    /// Chainlink aggregators read at the swap's block, ids are the
+    /// USD feed proxy addresses (e.g. ETH/USD `0x5f4e…8419`).
+    Chainlink { client: Arc<RpcClient> },
+}
+
+/// Looks up USD prices at a transaction's time and fills in missing USD values.
+#[derive(Debug)]
+pub struct PriceOracle {
+    source: PriceSource,
+    token_ids: HashMap<String, String>,
+    native_token: String,
+    cache: HashMap<String, f64>,
+    cache_path: Option<PathBuf>,
+    pub lookups: usize,
+}
+
+impl PriceOracle {
+    pub fn new(source: PriceSource) -> Self {
+        Self {
+            source,
+            token_ids: HashMap::new(),
+            native_token: "ETH".to_string(),
+            cache: HashMap::new(),
+            cache_path: None,
+            lookups: 0,
+        }
+    }
+
+    /// Map a token (as it appears in `token_in`/`token_out`) to the source's identifier.
+    pub fn with_token_id(mut self, token: &str, id: &str) -> Self {
+        self.token_ids.insert(token.to_string(), id.to_string());
+        self
+    }
+
+    /// The token gas is paid in, used for `gas_cost_usd`. Defaults to `ETH`.
+    pub fn with_native_token(mut self, token: &str) -> Self {
+        self.native_token = token.to_string();
+        self
+    }
+
+    /// Load the cache from `path` (if it exists) and write it back on `save_cache`.
+    pub fn with_cache_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        self.cache = load_json_cache(&path)?;
+        self.cache_path = Some(path);
+        return Ok(self);
+    }
+
+    pub fn save_cache(&self) -> Result<(), String> {
+        match &self.cache_path {
+            Some(path) => save_json_cache(path, &self.cache),
+            None => Ok(()),
+        }
+    }
+
+    /// USD price of `token` at the given time, or `None` when the token
+    /// can't be mapped to an identifier of the source.
+    pub fn price_usd(
+        &mut self,
+        token: &str,
+        timestamp: u64,
+        block_number: u64,
+    ) -> Result<Option<f64>, String> {
+        let Some(id) = self.token_id(token) else {
+            return Ok(None);
+        };
+
+        let cache_key = match self.source {
+            PriceSource::Chainlink { .. } => format!("{}@block{}", id, block_number),
+            _ => format!(
+                "{}@{}",
+                id,
+                timestamp / PRICE_BUCKET_SECONDS * PRICE_BUCKET_SECONDS
+            ),
+        };
+        if let Some(price) = self.cache.get(&cache_key) {
+            return Ok(Some(*price));
+        }
+
+        let price = fetch_price(&self.source, &id, timestamp, block_number)?;
+        self.lookups += 1;
+
+        if let Some(price) = price {
+            self.cache.insert(cache_key, price);
+        }
+        return Ok(price);
+    }
+
+    /// Fill `usd_value_in`, `usd_value_out` and `gas_cost_usd` where they are zero.
+    ///
+    /// Prices are per whole token. Swaps in raw units (decoded logs, traces,
+    /// BigQuery rows) need their tokens' `decimals` to be priced; `None` when
+    /// the amounts are already whole tokens, as in most CSV files. Raw
+    /// amounts of tokens without known decimals are priced as they are.
+    /// `gas_price` is in wei, see `SwapTransaction::gas_price`. Gas used isn't part of a swap record,
+    /// so `gas_cost_usd` is only filled for transactions found in `gas_used`.
+    /// Values without a known price are left at zero. Returns the number of
+    /// fields filled; the cache is saved once at the end.
+    pub fn backfill(
+        &mut self,
+        transactions: &mut [SwapTransaction],
+        gas_used: &HashMap<String, u64>,
+        decimals: Option<&TokenDecimals>,
+    ) -> Result<usize, String> {
+        let mut filled = 0;
+        let to_units = |token: &str, amount: f64| match decimals {
+            Some(decimals) => decimals.to_units(token, amount),
+            None => amount,
+        };
+
+        for tx in transactions.iter_mut() {
+            if tx.usd_value_in == 0.0 {
+                if let Some(price) = self.price_usd(&tx.token_in, tx.timestamp, tx.block_number)? {
+                    tx.usd_value_in = to_units(&tx.token_in, tx.amount_in) * price;
+                    filled += 1;
+                }
+            }
+
+            if tx.usd_value_out == 0.0 {
+                if let Some(price) = self.price_usd(&tx.token_out, tx.timestamp, tx.block_number)? {
+                    tx.usd_value_out = to_units(&tx.token_out, tx.amount_out) * price;
+                    filled += 1;
+                }
+            }
+
+            if tx.gas_cost_usd == 0.0 {
+                if let Some(gas) = gas_used.get(&tx.tx_hash) {
+                    let native_token = self.native_token.clone();
+                    if let Some(price) =
+                        self.price_usd(&native_token, tx.timestamp, tx.block_number)?
+                    {
+                        tx.gas_cost_usd = tx.gas_price as f64 * 1e-18 * *gas as f64 * price;
+                        filled += 1;
+                    }
+                }
+            }
+        }
+
+        self.save_cache()?;
+        return Ok(filled);
+    }
+
+    fn token_id(&self, token: &str) -> Option<String> {
+        if let Some(id) = self.token_ids.get(token) {
+            return Some(id.clone());
+        }
+
+        match &self.source {
+            PriceSource::DefiLlama { chain, .. } if token.starts_with("0x") => {
+                Some(format!("{}:{}", chain, token.to_lowercase()))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn fetch_price(
+    source: &PriceSource,
+    id: &str,
+    timestamp: u64,
+    block_number: u64,
+) -> Result<Option<f64>, String> {
+    match source {
+        PriceSource::DefiLlama { client, .. } => {
+            let path = format!("/prices/historical/{}/{}", timestamp, id);
+            let response = client
+                .get(&path, &[("searchWidth", "4h")])
+                .map_err(|err| format!("defillama price request failed: {}", err))?;
+            Ok(parse_defillama_price(&response, id))
+        }
+        PriceSource::CoinGecko { client, api_key } => {
+            let path = format!("/coins/{}/market_chart/range", id);
+            let from = timestamp.saturating_sub(PRICE_BUCKET_SECONDS).to_string();
+            let to = (timestamp + PRICE_BUCKET_SECONDS).to_string();
+            let mut query = vec![("vs_currency", "usd"), ("from", &from), ("to", &to)];
+            if let Some(api_key) = api_key {
+                query.push(("x_cg_demo_api_key", api_key));
+            }
+            let response = client
+                .get(&path, &query)
+                .map_err(|err| format!("coingecko price request failed: {}", err))?;
+            parse_coingecko_price(&response, timestamp)
+        }
+        PriceSource::Chainlink { client } => {
+            let decimals = word_to_f64(&eth_call(client, id, DECIMALS_SELECTOR, block_number)?);
+            let round_data = eth_call(client, id, LATEST_ROUND_DATA_SELECTOR, block_number)?;
+            // (roundId, answer, startedAt, updatedAt, answeredInRound)
+            let Some(answer) = round_data.get(64..128) else {
+                return Ok(None);
+            };
+            Ok(Some(signed_word_to_f64(answer) / 10f64.powf(decimals)))
+        }
+    }
+}
+
+/// Return data of an `eth_call`, without the `0x` prefix.
+pub(crate) fn eth_call(
+    client: &RpcClient,
+    to: &str,
+    data: &str,
+    block_number: u64,
+) -> Result<String, String> {
+    let params = serde_json::json!([{ "to": to, "data": data }, format!("0x{:x}", block_number)]);
+    let result = client
+        .call("eth_call", params, Some(block_number))
+        .map_err(|err| format!("eth_call to {} failed: {}", to, err))?;
+
+    match result.as_str() {
+        Some(result) => Ok(result.trim_start_matches("0x").to_string()),
+        None => Err(format!("invalid eth_call result from {}: {}", to, result)),
+    }
+}
+
+/// `{"coins": {"<id>": {"price": 3200.5, ...}}}`, empty when the coin is unknown.
+pub fn parse_defillama_price(response: &serde_json::Value, id: &str) -> Option<f64> {
+    response["coins"][id]["price"].as_f64()
+}
+
+/// `{"prices": [[millis, price], ...]}`, the point closest to `timestamp` is used.
+pub fn parse_coingecko_price(
+    response: &serde_json::Value,
+    timestamp: u64,
+) -> Result<Option<f64>, String> {
+    let Some(prices) = response["prices"].as_array() else {
+        return Err(format!("invalid coingecko response: {}", response));
+    };
+
+    let target_millis = timestamp as f64 * 1000.0;
+    let closest = prices
+        .iter()
+        .filter_map(|point| Some((point[0].as_f64()?, point[1].as_f64()?)))
+        .min_by(|a, b| {
+            (a.0 - target_millis)
+                .abs()
+                .total_cmp(&(b.0 - target_millis).abs())
+        });
+
+    Ok(closest.map(|(_millis, price)| price))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backfill_from_cache() {
+        let path = std::env::temp_dir().join(format!("price-cache-{}.json", std::process::id()));
+        // 1640995200 is on an hour boundary
+        std::fs::write(
+            &path,
+            r#"{"coingecko:ethereum@1640995200": 3700.0, "coingecko:usd-coin@1640995200": 1.0}"#,
+        )
+        .unwrap();
+
+        // The URL is unreachable, any lookup would fail the test
+        let mut oracle = PriceOracle::new(PriceSource::DefiLlama {
+            client: Arc::new(RpcClient::new("http://127.0.0.1:1")),
+            chain: "ethereum".to_string(),
+        })
+        .with_token_id("ETH", "coingecko:ethereum")
+        .with_token_id("USDC", "coingecko:usd-coin")
+        .with_cache_file(&path)
+        .expect("Failed to load cache");
+
+        let csv = "tx_hash,block_number,timestamp,tx_position_in_block,from_address,token_in,token_out,amount_in,amount_out,gas_price,pool_address,token_launch_block,is_contract_caller,usd_value_in,usd_value_out,gas_cost_usd\n\
+                   0xa,1,1640995260,0,0xbot,ETH,USDC,2,7400,100000000000,0xpool,0,false,0,0,0\n\
+                   0xb,1,1640995260,1,0xuser,USDC,SHIB,7400,1,100000000000,0xpool,0,false,7000,0,5\n";
+        let mut swaps: Vec<SwapTransaction> = crate::ingest::csv::read_transactions(csv.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let gas_used = HashMap::from([("0xa".to_string(), 150_000)]);
+
+        let filled = oracle
+            .backfill(&mut swaps, &gas_used, None)
+            .expect("Failed to backfill");
+        assert_eq!(oracle.lookups, 0);
+        assert_eq!(filled, 3);
+        assert_eq!(swaps[0].usd_value_in, 7400.0);
+        assert_eq!(swaps[0].usd_value_out, 7400.0);
+        // 100 gwei * 150k gas = 0.015 ETH
+        assert!((swaps[0].gas_cost_usd - 55.5).abs() < 1e-9);
+
+        assert_eq!(swaps[1].usd_value_in, 7000.0, "Existing values are kept");
+        assert_eq!(swaps[1].usd_value_out, 0.0, "SHIB has no mapping");
+        assert_eq!(swaps[1].gas_cost_usd, 5.0);
+
+        // The same swap decoded from logs, in raw units
+        let mut raw_swaps = swaps.clone();
+        raw_swaps[0].amount_in = 2e18;
+        raw_swaps[0].amount_out = 7400e6;
+        raw_swaps[0].usd_value_in = 0.0;
+        raw_swaps[0].usd_value_out = 0.0;
+        oracle
+            .backfill(
+                &mut raw_swaps[..1],
+                &gas_used,
+                Some(&TokenDecimals::default()),
+            )
+            .expect("Failed to backfill");
+        assert!((raw_swaps[0].usd_value_in - 7400.0).abs() < 1e-6);
+        assert!((raw_swaps[0].usd_value_out - 7400.0).abs() < 1e-6);
+        assert_eq!(oracle.lookups, 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_price_responses() {
+        let llama = serde_json::json!({"coins":{"coingecko:ethereum":{"symbol":"ETH","price":3712.4,"timestamp":1640995190,"confidence":0.99}}});
+        assert_eq!(
+            parse_defillama_price(&llama, "coingecko:ethereum"),
+            Some(3712.4)
+        );
+        assert_eq!(
+            parse_defillama_price(&serde_json::json!({"coins":{}}), "x"),
+            None
+        );
+
+        let gecko = serde_json::json!({"prices":[[1640992000000u64,3650.0],[1640995300000u64,3700.0],[1640998000000u64,3750.0]]});
+        assert_eq!(
+            parse_coingecko_price(&gecko, 1640995200).unwrap(),
+            Some(3700.0)
+        );
+        assert!(parse_coingecko_price(&serde_json::json!({"error":"rate limited"}), 0).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::cache::{load_json_cache, save_json_cache, TokenBucket};
+
+/// Longest wait between two attempts of a request.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How long a request may take before it fails (and is retried).
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Client shared by the RPC-backed adapters (trace and Solana ingestion,
+/// contract lookups, prices): JSON-RPC calls with `call`, and explorer and
+/// price APIs with `get`.
+///
+/// Requests are rate limited with a token bucket, time out, transport
+/// errors, HTTP 429 and 5xx responses are retried with exponential backoff,
+/// and responses of calls pinned to a block can be cached (optionally in a
+/// JSON file) so re-runs don't query the provider again. Share it with an `Arc`.
+#[derive(Debug)]
+pub struct RpcClient {
+    url: String,
+    agent: ureq::Agent,
+    rate_limiter: Option<Mutex<TokenBucket>>,
+    max_retries: u32,
+    initial_backoff: Duration,
+    cache: Option<Mutex<HashMap<String, Value>>>,
+    cache_path: Option<PathBuf>,
+    requests: AtomicUsize,
+}
+
+impl RpcClient {
+    /// No rate limit or cache, 3 retries starting at 500ms and a 30s timeout.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            agent: new_agent(DEFAULT_TIMEOUT),
+            rate_limiter: None,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            cache: None,
+            cache_path: None,
+            requests: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// At most `requests_per_second` on average, with bursts of up to `burst` requests.
+    pub fn with_rate_limit(mut self, requests_per_second: f64, burst: u32) -> Self {
+        self.rate_limiter = Some(Mutex::new(TokenBucket::new(requests_per_second, burst)));
+        self
+    }
+
+    /// Retry a failed request up to `max_retries` times, doubling the wait
+    /// from `initial_backoff` after each attempt.
+    pub fn with_retries(mut self, max_retries: u32, initial_backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Fail requests taking longer than `timeout`, connecting included.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = new_agent(timeout);
+        self
+    }
+
+    /// Cache responses of block-pinned calls in memory.
+    pub fn with_cache(mut self) -> Self {
+        if self.cache.is_none() {
+            self.cache = Some(Mutex::new(HashMap::new()));
+        }
+        self
+    }
+
+    /// Load the cache from `path` (if it exists) and write it back on `save_cache`.
+    pub fn with_cache_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        self.cache = Some(Mutex::new(load_json_cache(&path)?));
+        self.cache_path = Some(path);
+        return Ok(self);
+    }
+
+    pub fn save_cache(&self) -> Result<(), String> {
+        match (&self.cache, &self.cache_path) {
+            (Some(cache), Some(path)) => save_json_cache(path, &cache.lock().unwrap()),
+            _ => Ok(()),
+        }
+    }
+
+    /// HTTP requests sent so far, retries included.
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// The `result` of a JSON-RPC call.
+    ///
+    /// `block` is the block (or slot) the call is pinned to; only pinned calls
+    /// are cached, since the answer of e.g. `latest` changes.
+    pub fn call(&self, method: &str, params: Value, block: Option<u64>) -> Result<Value, String> {
+        let key = block.map(|block| cache_key(method, &params, block));
+        if let (Some(cache), Some(key)) = (&self.cache, &key) {
+            if let Some(result) = cache.lock().unwrap().get(key) {
+                return Ok(result.clone());
+            }
+        }
+
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let mut response =
+            self.send_with_retries(method, || self.agent.post(&self.url).send_json(&request))?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("{} failed: {}", method, error));
+        }
+        let result = response["result"].take();
+
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            cache.lock().unwrap().insert(key, result.clone());
+        }
+        return Ok(result);
+    }
+
+    /// GET `path` (appended to the client's URL) with `query`, e.g. of an
+    /// explorer or price API, as JSON. Not cached, the callers cache the
+    /// values they extract.
+    pub fn get(&self, path: &str, query: &[(&str, &str)]) -> Result<Value, String> {
+        let url = format!("{}{}", self.url.trim_end_matches('/'), path);
+        return self.send_with_retries(&url, || {
+            self.agent
+                .get(&url)
+                .query_pairs(query.iter().copied())
+                .call()
+        });
+    }
+
+    /// Send a request made by `send` as a JSON response, `what` names it in errors.
+    fn send_with_retries<F>(&self, what: &str, send: F) -> Result<Value, String>
+    where
+        F: Fn() -> Result<ureq::http::Response<ureq::Body>, ureq::Error>,
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.lock().unwrap().acquire();
+            }
+            self.requests.fetch_add(1, Ordering::Relaxed);
+
+            let err = match send() {
+                Ok(mut response) => {
+                    return response
+                        .body_mut()
+                        .read_json()
+                        .map_err(|err| format!("invalid {} response: {}", what, err));
+                }
+                Err(err) => err,
+            };
+            if attempt >= self.max_retries || !is_retryable(&err) {
+                return Err(format!("{} failed: {}", what, err));
+            }
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            attempt += 1;
+        }
+    }
+}
+
+fn new_agent(timeout: Duration) -> ureq::Agent {
+    return ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .build()
+        .new_agent();
+}
+
+/// Cache key of a call, `method(params)@block`.
+pub fn cache_key(method: &str, params: &Value, block: u64) -> String {
+    format!("{}({})@{}", method, params, block)
+}
+
+/// Rate limits, server errors and connection problems are worth another try.
+fn is_retryable(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::StatusCode(status) => *status == 429 || *status >= 500,
+        ureq::Error::Io(_)
+        | ureq::Error::Timeout(_)
+        | ureq::Error::ConnectionFailed
+        | ureq::Error::HostNotFound => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_calls_skip_the_provider() {
+        let path = std::env::temp_dir().join(format!("rpc-cache-{}.json", std::process::id()));
+        let params = serde_json::json!(["0x3039"]);
+        let cached = HashMap::from([(
+            cache_key("debug_traceBlockByNumber", &params, 12345),
+            serde_json::json!([]),
+        )]);
+        save_json_cache(&path, &cached).unwrap();
+
+        // The URL is unreachable, any request fails
+        let client = RpcClient::new("http://127.0.0.1:1")
+            .with_retries(2, Duration::from_millis(1))
+            .with_cache_file(&path)
+            .expect("Failed to load cache");
+
+        let result = client.call("debug_traceBlockByNumber", params.clone(), Some(12345));
+        assert_eq!(result, Ok(serde_json::json!([])));
+        assert_eq!(client.requests(), 0);
+
+        // Not pinned to a block, so not served from the cache, and retried
+        assert!(client
+            .call("debug_traceBlockByNumber", params, None)
+            .is_err());
+        assert_eq!(client.requests(), 3);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Read};
+
+use chrono::{DateTime, NaiveDateTime};
+use serde::{Deserialize, Deserializer};
+
+use crate::sandwich::transactions::{raw_amount, SwapTransaction, ETHEREUM_CHAIN_ID};
+
+/// A row of `bigquery-public-data.crypto_ethereum.token_transfers`.
+/// Extra columns of the export (e.g. `block_hash`) are ignored.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TokenTransferRow {
+    pub token_address: String,
+    pub from_address: String,
+    pub to_address: String,
+    /// Raw token amount, a string since it routinely exceeds 64 bits.
+    pub value: String,
+    pub transaction_hash: String,
+    pub log_index: u64,
+    #[serde(deserialize_with = "unix_seconds")]
+    pub block_timestamp: u64,
+    pub block_number: u64,
+}
+
+/// A row of `bigquery-public-data.crypto_ethereum.traces`.
+/// Only the columns needed to find the transaction index and origin are kept.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TraceRow {
+    #[serde(default)]
+    pub transaction_hash: Option<String>,
+    #[serde(default)]
+    pub transaction_index: Option<u32>,
+    #[serde(default)]
+    pub from_address: Option<String>,
+    #[serde(default)]
+    pub to_address: Option<String>,
+    /// Comma separated call path, empty (or missing) for the top-level call.
+    #[serde(default)]
+    pub trace_address: Option<String>,
+    pub block_number: u64,
+}
+
+/// A row of `bigquery-public-data.crypto_ethereum.transactions`, the source of
+/// transaction indexes and gas prices.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TransactionRow {
+    pub hash: String,
+    /// 0-based, like `traces.transaction_index`.
+    pub transaction_index: u32,
+    pub gas_price: u64,
+    /// What the transaction actually paid per gas, missing in older exports.
+    #[serde(default)]
+    pub receipt_effective_gas_price: Option<u64>,
+    #[serde(default)]
+    pub max_fee_per_gas: Option<u64>,
+    #[serde(default)]
+    pub max_priority_fee_per_gas: Option<u64>,
+    pub block_number: u64,
+}
+
+/// Accept the timestamp encodings of the different export formats:
+/// `2021-01-01 00:00:00 UTC` (CSV/JSON), RFC 3339, or an integer
+/// (seconds, or microseconds as used by Avro's `timestamp-micros`).
+fn unix_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp {
+        Number(i64),
+        Text(String),
+    }
+
+    match Timestamp::deserialize(deserializer)? {
+        Timestamp::Number(value) if value > 100_000_000_000_000 => Ok((value / 1_000_000) as u64),
+        Timestamp::Number(value) => Ok(value as u64),
+        Timestamp::Text(text) => parse_timestamp(&text).map_err(serde::de::Error::custom),
+    }
+}
+
+fn parse_timestamp(text: &str) -> Result<u64, String> {
+    if let Ok(seconds) = text.parse::<u64>() {
+        return Ok(seconds);
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Ok(datetime.timestamp() as u64);
+    }
+
+    let trimmed = text.trim_end_matches(" UTC");
+    NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%d %H:%M:%S%.f")
+        .map(|datetime| datetime.and_utc().timestamp() as u64)
+        .map_err(|_| format!("unsupported timestamp: {}", text))
+}
+
+pub fn read_token_transfers_csv<R: Read>(reader: R) -> Result<Vec<TokenTransferRow>, String> {
+    read_csv(reader, "token_transfers")
+}
+
+pub fn read_traces_csv<R: Read>(reader: R) -> Result<Vec<TraceRow>, String> {
+    read_csv(reader, "traces")
+}
+
+pub fn read_transactions_csv<R: Read>(reader: R) -> Result<Vec<TransactionRow>, String> {
+    read_csv(reader, "transactions")
+}
+
+/// Read a newline delimited JSON export of `token_transfers`.
+pub fn read_token_transfers_json<R: BufRead>(reader: R) -> Result<Vec<TokenTransferRow>, String> {
+    read_json_lines(reader, "token_transfers")
+}
+
+/// Read a newline delimited JSON export of `traces`.
+pub fn read_traces_json<R: BufRead>(reader: R) -> Result<Vec<TraceRow>, String> {
+    read_json_lines(reader, "traces")
+}
+
+/// Read a newline delimited JSON export of `transactions`.
+pub fn read_transactions_json<R: BufRead>(reader: R) -> Result<Vec<TransactionRow>, String> {
+    read_json_lines(reader, "transactions")
+}
+
+/// Read an Avro export of `token_transfers`.
+#[cfg(feature = "avro")]
+pub fn read_token_transfers_avro<R: Read>(reader: R) -> Result<Vec<TokenTransferRow>, String> {
+    read_avro(reader, "token_transfers")
+}
+
+/// Read an Avro export of `traces`.
+#[cfg(feature = "avro")]
+pub fn read_traces_avro<R: Read>(reader: R) -> Result<Vec<TraceRow>, String> {
+    read_avro(reader, "traces")
+}
+
+/// Read an Avro export of `transactions`.
+#[cfg(feature = "avro")]
+pub fn read_transactions_avro<R: Read>(reader: R) -> Result<Vec<TransactionRow>, String> {
+    read_avro(reader, "transactions")
+}
+
+fn read_csv<R: Read, T: serde::de::DeserializeOwned>(
+    reader: R,
+    table: &str,
+) -> Result<Vec<T>, String> {
+    let mut reader = csv::Reader::from_reader(reader);
+    reader
+        .deserialize()
+        .enumerate()
+        .map(|(index, row)| {
+            row.map_err(|err| format!("invalid {} row {}: {}", table, index + 1, err))
+        })
+        .collect()
+}
+
+fn read_json_lines<R: BufRead, T: serde::de::DeserializeOwned>(
+    reader: R,
+    table: &str,
+) -> Result<Vec<T>, String> {
+    let mut rows = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| format!("failed to read {} export: {}", table, err))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let row = serde_json::from_str(&line)
+            .map_err(|err| format!("invalid {} row {}: {}", table, index + 1, err))?;
+        rows.push(row);
+    }
+
+    return Ok(rows);
+}
+
+#[cfg(feature = "avro")]
+fn read_avro<R: Read, T: serde::de::DeserializeOwned>(
+    reader: R,
+    table: &str,
+) -> Result<Vec<T>, String> {
+    let reader = apache_avro::Reader::new(reader)
+        .map_err(|err| format!("invalid {} avro export: {}", table, err))?;
+
+    reader
+        .enumerate()
+        .map(|(index, value)| {
+            let value =
+                value.map_err(|err| format!("invalid {} row {}: {}", table, index + 1, err))?;
+            apache_avro::from_value(&value)
+                .map_err(|err| format!("invalid {} row {}: {}", table, index + 1, err))
+        })
+        .collect()
+}
+
+/// Reconstruct swaps from the token transfers of each transaction.
+///
+/// A swap shows up as a pair of transfers in the same transaction: the trader
+/// sends token X to a pool, and that pool sends token Y back out. The pool is
+/// the address that both receives and sends. Routed trades produce one swap
+/// per hop.
+///
+/// When traces are given, they supply the transaction index and the EOA that
+/// initiated the transaction. The EOA is reported as the swapper, and the swap
+/// is marked as contract-called if the tokens were sent by another address
+/// (a router or bot contract). Without traces, the token sender is used as the
+/// swapper.
+///
+/// `transactions` supply the transaction index and the gas price (the
+/// receipt's effective one when exported). Swaps of transactions missing from
+/// it have a zero `gas_price`, so the gas heuristics can't fire on them. See
+/// `transaction_positions` for blocks with unknown indexes.
+///
+/// `token_symbols` maps token addresses to symbols so the token equivalence
+/// groups apply, unmapped tokens keep their address.
+///
+/// Fields the export doesn't carry are left zeroed: the USD values and
+/// `token_launch_block`. Amounts are raw token units, not adjusted for
+/// decimals. Fails on a transfer value that isn't a number.
+pub fn swaps_from_transfers(
+    transfers: &[TokenTransferRow],
+    traces: &[TraceRow],
+    transactions: &[TransactionRow],
+    token_symbols: &HashMap<String, String>,
+) -> Result<Vec<SwapTransaction>, String> {
+    let origins = transaction_origins(traces);
+    let transactions: HashMap<&str, &TransactionRow> = transactions
+        .iter()
+        .map(|transaction| (transaction.hash.as_str(), transaction))
+        .collect();
+    let positions = transaction_positions(transfers, &origins, &transactions);
+
+    let mut transfers_by_tx: HashMap<&str, Vec<&TokenTransferRow>> = HashMap::new();
+    for transfer in transfers {
+        transfers_by_tx
+            .entry(transfer.transaction_hash.as_str())
+            .or_default()
+            .push(transfer);
+    }
+
+    let mut swaps = Vec::new();
+    for (tx_hash, mut tx_transfers) in transfers_by_tx {
+        tx_transfers.sort_by_key(|transfer| transfer.log_index);
+        let mut used = vec![false; tx_transfers.len()];
+
+        for in_pos in 0..tx_transfers.len() {
+            if used[in_pos] {
+                continue;
+            }
+            let transfer_in = tx_transfers[in_pos];
+            let pool = transfer_in.to_address.to_lowercase();
+
+            let transfer_out_pos = (0..tx_transfers.len()).find(|&out_pos| {
+                let transfer_out = tx_transfers[out_pos];
+                !used[out_pos]
+                    && out_pos != in_pos
+                    && transfer_out.from_address.to_lowercase() == pool
+                    && transfer_out.token_address != transfer_in.token_address
+            });
+
+            let Some(out_pos) = transfer_out_pos else {
+                continue;
+            };
+            used[in_pos] = true;
+            used[out_pos] = true;
+            let transfer_out = tx_transfers[out_pos];
+
+            let sender = transfer_in.from_address.to_lowercase();
+            let (from_address, is_contract_caller) = match origins.get(tx_hash) {
+                Some((_, Some(origin))) => (origin.clone(), *origin != sender),
+                _ => (sender, false),
+            };
+
+            let transaction = transactions.get(tx_hash);
+            swaps.push(SwapTransaction {
+                tx_hash: tx_hash.to_string(),
+                chain_id: ETHEREUM_CHAIN_ID,
+                block_number: transfer_in.block_number,
+                timestamp: transfer_in.block_timestamp,
+                tx_position_in_block: positions.get(tx_hash).copied().unwrap_or(0),
+                from_address,
+                token_in: token_symbol(&transfer_in.token_address, token_symbols),
+                token_out: token_symbol(&transfer_out.token_address, token_symbols),
+                amount_in: transfer_value(transfer_in)?,
+                amount_out: transfer_value(transfer_out)?,
+                gas_price: transaction.map_or(0, |transaction| {
+                    transaction
+                        .receipt_effective_gas_price
+                        .unwrap_or(transaction.gas_price)
+                }),
+                pool_address: pool,
+                token_launch_block: 0,
+                is_contract_caller,
+                usd_value_in: 0.0,
+                usd_value_out: 0.0,
+                gas_cost_usd: 0.0,
+                max_fee_per_gas: transaction.and_then(|transaction| transaction.max_fee_per_gas),
+                max_priority_fee_per_gas: transaction
+                    .and_then(|transaction| transaction.max_priority_fee_per_gas),
+                base_fee_per_gas: None,
+                raw_amount_in: raw_amount::parse(&transfer_in.value).ok(),
+            });
+        }
+    }
+
+    swaps.sort_by_key(|swap| (swap.block_number, swap.tx_position_in_block));
+    return Ok(swaps);
+}
+
+fn transfer_value(transfer: &TokenTransferRow) -> Result<f64, String> {
+    return transfer.value.parse().map_err(|_| {
+        format!(
+            "invalid token_transfers value {:?} in {} log {}",
+            transfer.value, transfer.transaction_hash, transfer.log_index
+        )
+    });
+}
+
+fn token_symbol(token_address: &str, token_symbols: &HashMap<String, String>) -> String {
+    let address = token_address.to_lowercase();
+    match token_symbols.get(&address) {
+        Some(symbol) => symbol.clone(),
+        None => address,
+    }
+}
+
+/// Transaction index and initiating EOA of each transaction, from its top-level trace.
+fn transaction_origins(traces: &[TraceRow]) -> HashMap<String, (Option<u32>, Option<String>)> {
+    let mut origins = HashMap::new();
+
+    for trace in traces {
+        let is_top_level = trace
+            .trace_address
+            .as_deref()
+            .is_none_or(|address| address.is_empty());
+        if !is_top_level {
+            continue;
+        }
+
+        if let Some(tx_hash) = &trace.transaction_hash {
+            origins.insert(
+                tx_hash.clone(),
+                (
+                    trace.transaction_index,
+                    trace.from_address.as_ref().map(|from| from.to_lowercase()),
+                ),
+            );
+        }
+    }
+
+    return origins;
+}
+
+/// Position of each transaction within its block, 0-based: the transaction
+/// index from `transactions` or the traces when every transaction of the
+/// block has one, otherwise (so known and guessed positions don't collide)
+/// the rank of each transaction's first log within the block.
+fn transaction_positions(
+    transfers: &[TokenTransferRow],
+    origins: &HashMap<String, (Option<u32>, Option<String>)>,
+    transactions: &HashMap<&str, &TransactionRow>,
+) -> HashMap<String, u32> {
+    let mut first_log_by_tx: HashMap<&str, (u64, u64)> = HashMap::new();
+    for transfer in transfers {
+        let entry = first_log_by_tx
+            .entry(transfer.transaction_hash.as_str())
+            .or_insert((transfer.block_number, transfer.log_index));
+        entry.1 = entry.1.min(transfer.log_index);
+    }
+
+    let mut txs_by_block: HashMap<u64, Vec<(u64, &str)>> = HashMap::new();
+    for (tx_hash, (block_number, first_log)) in first_log_by_tx {
+        txs_by_block
+            .entry(block_number)
+            .or_default()
+            .push((first_log, tx_hash));
+    }
+
+    let mut positions = HashMap::new();
+    let transaction_index = |tx_hash: &str| match transactions.get(tx_hash) {
+        Some(transaction) => Some(transaction.transaction_index),
+        None => origins.get(tx_hash).and_then(|(index, _)| *index),
+    };
+    for (_block_number, mut txs) in txs_by_block {
+        txs.sort();
+        let indexed = txs
+            .iter()
+            .all(|(_first_log, tx_hash)| transaction_index(tx_hash).is_some());
+        for (rank, (_first_log, tx_hash)) in txs.into_iter().enumerate() {
+            let position = match transaction_index(tx_hash) {
+                Some(index) if indexed => index,
+                _ => rank as u32,
+            };
+            positions.insert(tx_hash.to_string(), position);
+        }
+    }
+
+    return positions;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSFERS_CSV: &str = "\
+token_address,from_address,to_address,value,transaction_hash,log_index,block_timestamp,block_number,block_hash
+0xusdc,0xBot,0xpool1,1000000000,0xfront,10,2021-01-01 00:00:00 UTC,12360,0xblock
+0xshib,0xpool1,0xbot,49950050,0xfront,11,2021-01-01 00:00:00 UTC,12360,0xblock
+0xusdc,0xrouter,0xpool1,5000000000,0xvictim,20,2021-01-01 00:00:00 UTC,12360,0xblock
+0xshib,0xpool1,0xvictim,248260656,0xvictim,21,2021-01-01 00:00:00 UTC,12360,0xblock
+0xusdc,0xalice,0xbob,42,0xplain_transfer,25,2021-01-01 00:00:00 UTC,12360,0xblock
+0xshib,0xbot,0xpool1,49950050,0xback,30,2021-01-01 00:00:00 UTC,12360,0xblock
+0xusdc,0xpool1,0xbot,950000000,0xback,31,2021-01-01 00:00:00 UTC,12360,0xblock
+";
+
+    fn symbols() -> HashMap<String, String> {
+        HashMap::from([
+            ("0xusdc".to_string(), "USDC".to_string()),
+            ("0xshib".to_string(), "SHIB".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_swaps_from_token_transfers_csv() {
+        let transfers = read_token_transfers_csv(TRANSFERS_CSV.as_bytes()).unwrap();
+        assert_eq!(transfers.len(), 7);
+        assert_eq!(transfers[0].block_timestamp, 1609459200);
+
+        let swaps = swaps_from_transfers(&transfers, &[], &[], &symbols()).unwrap();
+        assert_eq!(swaps.len(), 3, "The plain transfer is not a swap");
+
+        let hashes: Vec<&str> = swaps.iter().map(|swap| swap.tx_hash.as_str()).collect();
+        assert_eq!(hashes, vec!["0xfront", "0xvictim", "0xback"]);
+
+        assert_eq!(swaps[0].from_address, "0xbot");
+        assert_eq!(swaps[0].token_in, "USDC");
+        assert_eq!(swaps[0].token_out, "SHIB");
+        assert_eq!(swaps[0].pool_address, "0xpool1");
+        assert_eq!(swaps[0].amount_out, 49950050.0);
+        assert_eq!(swaps[2].token_out, "USDC");
+        assert_eq!(swaps[0].tx_position_in_block, 0);
+        assert_eq!(swaps[2].tx_position_in_block, 3);
+        assert_eq!(swaps[0].gas_price, 0);
+
+        let mut garbled = transfers.clone();
+        garbled[0].value = "1e".to_string();
+        assert!(swaps_from_transfers(&garbled, &[], &[], &symbols()).is_err());
+    }
+
+    #[test]
+    fn test_traces_supply_origin_and_position() {
+        let transfers = read_token_transfers_csv(TRANSFERS_CSV.as_bytes()).unwrap();
+        let traces = read_traces_json(
+            r#"{"transaction_hash":"0xvictim","transaction_index":7,"from_address":"0xVictimEOA","to_address":"0xrouter","trace_address":null,"block_number":12360}
+{"transaction_hash":"0xvictim","transaction_index":7,"from_address":"0xrouter","to_address":"0xpool1","trace_address":"0","block_number":12360}
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let swaps = swaps_from_transfers(&transfers, &traces, &[], &symbols()).unwrap();
+        let victim = swaps
+            .iter()
+            .find(|swap| swap.tx_hash == "0xvictim")
+            .unwrap();
+
+        assert_eq!(victim.from_address, "0xvictimeoa");
+        assert!(victim.is_contract_caller, "Tokens came from the router");
+        // The rest of the block has no index, so all are ranked by log
+        assert_eq!(victim.tx_position_in_block, 1);
+
+        let transactions = read_transactions_csv(
+            "\
+hash,transaction_index,gas_price,receipt_effective_gas_price,max_fee_per_gas,max_priority_fee_per_gas,block_number
+0xfront,3,200000000000,150000000000,200000000000,100000000000,12360
+0xvictim,7,50000000000,,,,12360
+0xplain_transfer,8,40000000000,40000000000,,,12360
+0xback,9,10000000000,10000000000,,,12360
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        let swaps = swaps_from_transfers(&transfers, &traces, &transactions, &symbols()).unwrap();
+        let positions: Vec<u32> = swaps.iter().map(|swap| swap.tx_position_in_block).collect();
+        assert_eq!(positions, [3, 7, 9]);
+        assert_eq!(swaps[0].gas_price, 150000000000);
+        assert_eq!(swaps[0].max_priority_fee_per_gas, Some(100000000000));
+        assert_eq!(swaps[1].gas_price, 50000000000);
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use ethnum::U256;
+
+use crate::sandwich::transactions::{SwapTransaction, ETHEREUM_CHAIN_ID};
+use crate::storage::{FEE_COLUMNS, SWAP_COLUMNS};
+
+/// Layout version of swap exports, declared by an optional `# schema_version: N` first line.
+///
+/// 1. The original columns, without `chain_id` (mainnet is assumed). Files
+///    without the header line are read as version 1.
+/// 2. Adds a required `chain_id` column.
+pub const CSV_SCHEMA_VERSION: u32 = 2;
+
+/// Streams swaps out of a CSV export, validating it against the schema.
+///
+/// The header must contain the columns of the declared schema version,
+/// in any order; the `FEE_COLUMNS` and `raw_amount_in` are optional and
+/// extra columns are ignored. Rows that don't match report the row, line,
+/// column and expected type so broken exports can be fixed by hand.
+pub struct SwapCsvReader<R: Read> {
+    records: csv::StringRecordsIntoIter<BufReader<R>>,
+    columns: HashMap<&'static str, usize>,
+    /// Lines before the CSV header (the schema version line).
+    line_offset: u64,
+    row: usize,
+}
+
+impl<R: Read> SwapCsvReader<R> {
+    /// Reads the schema version and header, failing if required columns are missing.
+    pub fn new(reader: R) -> Result<Self, String> {
+        let mut reader = BufReader::new(reader);
+        let (version, line_offset) = match read_schema_version(&mut reader)? {
+            Some(version) => (version, 1),
+            None => (1, 0),
+        };
+
+        let mut reader = csv::Reader::from_reader(reader);
+        let headers = reader
+            .headers()
+            .map_err(|err| format!("invalid CSV header: {}", err))?;
+        let columns = validate_headers(headers, version)?;
+
+        return Ok(Self {
+            records: reader.into_records(),
+            columns,
+            line_offset,
+            row: 0,
+        });
+    }
+
+    fn parse_record(&self, record: &csv::StringRecord) -> Result<SwapTransaction, String> {
+        let line = record
+            .position()
+            .map(|position| position.line())
+            .unwrap_or(0)
+            + self.line_offset;
+        let text = |column: &str| self.field(record, column).to_string();
+        let unsigned =
+            |column: &str| self.parse::<u64>(record, line, column, "an unsigned integer");
+        let number = |column: &str| self.parse::<f64>(record, line, column, "a number");
+        // Missing columns and empty cells are unset fees
+        let fee = |column: &str| -> Result<Option<u64>, String> {
+            if self.field(record, column).is_empty() {
+                return Ok(None);
+            }
+            return self
+                .parse::<u64>(record, line, column, "an unsigned integer")
+                .map(Some);
+        };
+        let raw = |column: &str| -> Result<Option<U256>, String> {
+            if self.field(record, column).is_empty() {
+                return Ok(None);
+            }
+            return self
+                .parse::<U256>(record, line, column, "an unsigned integer")
+                .map(Some);
+        };
+
+        let chain_id = match self.columns.contains_key("chain_id") {
+            true => unsigned("chain_id")?,
+            false => ETHEREUM_CHAIN_ID,
+        };
+
+        return Ok(SwapTransaction {
+            tx_hash: text("tx_hash"),
+            chain_id,
+            block_number: unsigned("block_number")?,
+            timestamp: unsigned("timestamp")?,
+            tx_position_in_block: self.parse(
+                record,
+                line,
+                "tx_position_in_block",
+                "a 32-bit unsigned integer",
+            )?,
+            from_address: text("from_address"),
+            token_in: text("token_in"),
+            token_out: text("token_out"),
+            amount_in: number("amount_in")?,
+            amount_out: number("amount_out")?,
+            gas_price: unsigned("gas_price")?,
+            pool_address: text("pool_address"),
+            token_launch_block: unsigned("token_launch_block")?,
+            is_contract_caller: self.parse(record, line, "is_contract_caller", "true or false")?,
+            usd_value_in: number("usd_value_in")?,
+            usd_value_out: number("usd_value_out")?,
+            gas_cost_usd: number("gas_cost_usd")?,
+            max_fee_per_gas: fee("max_fee_per_gas")?,
+            max_priority_fee_per_gas: fee("max_priority_fee_per_gas")?,
+            base_fee_per_gas: fee("base_fee_per_gas")?,
+            raw_amount_in: raw("raw_amount_in")?,
+        });
+    }
+
+    fn field<'r>(&self, record: &'r csv::StringRecord, column: &str) -> &'r str {
+        self.columns
+            .get(column)
+            .and_then(|index| record.get(*index))
+            .unwrap_or("")
+    }
+
+    fn parse<T: FromStr>(
+        &self,
+        record: &csv::StringRecord,
+        line: u64,
+        column: &str,
+        expected: &str,
+    ) -> Result<T, String> {
+        let value = self.field(record, column);
+        value.parse().map_err(|_| {
+            format!(
+                "invalid CSV row {} (line {}), column {}: expected {}, got {:?}",
+                self.row, line, column, expected, value
+            )
+        })
+    }
+}
+
+impl<R: Read> Iterator for SwapCsvReader<R> {
+    type Item = Result<SwapTransaction, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.next()?;
+        self.row += 1;
+
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => return Some(Err(format!("invalid CSV row {}: {}", self.row, err))),
+        };
+        return Some(self.parse_record(&record));
+    }
+}
+
+/// Consumes a leading `# schema_version: N` line, if there is one.
+fn read_schema_version<R: BufRead>(reader: &mut R) -> Result<Option<u32>, String> {
+    let starts_with_comment = reader
+        .fill_buf()
+        .map_err(|err| format!("failed to read CSV: {}", err))?
+        .starts_with(b"#");
+    if !starts_with_comment {
+        return Ok(None);
+    }
+
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .map_err(|err| format!("failed to read CSV: {}", err))?;
+
+    let version = line
+        .trim_start_matches('#')
+        .trim()
+        .strip_prefix("schema_version:")
+        .and_then(|version| version.trim().parse::<u32>().ok())
+        .ok_or_else(|| {
+            format!(
+                "invalid CSV schema line {:?}, expected `# schema_version: N`",
+                line.trim()
+            )
+        })?;
+
+    if version == 0 || version > CSV_SCHEMA_VERSION {
+        return Err(format!(
+            "unsupported CSV schema version {}, this build reads versions 1 to {}",
+            version, CSV_SCHEMA_VERSION
+        ));
+    }
+    return Ok(Some(version));
+}
+
+/// Maps the known columns to their index, checking the version's required ones are there.
+fn validate_headers(
+    headers: &csv::StringRecord,
+    version: u32,
+) -> Result<HashMap<&'static str, usize>, String> {
+    let mut columns = HashMap::new();
+    for (index, header) in headers.iter().enumerate() {
+        let mut known = SWAP_COLUMNS
+            .iter()
+            .chain(FEE_COLUMNS)
+            .chain(&["raw_amount_in"]);
+        if let Some(column) = known.find(|column| **column == header.trim()) {
+            columns.entry(*column).or_insert(index);
+        }
+    }
+
+    let missing: Vec<&str> = SWAP_COLUMNS
+        .iter()
+        .filter(|column| version >= 2 || **column != "chain_id")
+        .filter(|column| !columns.contains_key(*column))
+        .copied()
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "CSV is missing columns for schema version {}: {}",
+            version,
+            missing.join(", ")
+        ));
+    }
+
+    return Ok(columns);
+}
+
+/// Lazily decode swaps from CSV, one row at a time.
+///
+/// Unlike reading the file into a string first, memory use doesn't grow with
+/// the input size, so multi-GB exports can be piped straight into `detect_stream`.
+/// An invalid header is reported as the first (and only) item.
+pub fn read_transactions<R: Read>(
+    reader: R,
+) -> impl Iterator<Item = Result<SwapTransaction, String>> {
+    let (reader, header_error) = match SwapCsvReader::new(reader) {
+        Ok(reader) => (Some(reader), None),
+        Err(err) => (None, Some(Err(err))),
+    };
+    header_error.into_iter().chain(reader.into_iter().flatten())
+}
+
+pub fn open_transactions<P: AsRef<Path>>(
+    path: P,
+) -> Result<impl Iterator<Item = Result<SwapTransaction, String>>, String> {
+    let file = File::open(path.as_ref())
+        .map_err(|err| format!("failed to open {}: {}", path.as_ref().display(), err))?;
+    return SwapCsvReader::new(file).map_err(|err| format!("{}: {}", path.as_ref().display(), err));
+}
+
+/// The swaps of the sample export tests run against.
+#[cfg(test)]
+pub(crate) fn sample_transactions() -> Vec<SwapTransaction> {
+    return open_transactions("data/sandwiches.csv")
+        .expect("Failed to open sample CSV file")
+        .map(|tx| tx.expect("Failed to parse CSV row"))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "tx_hash,block_number,timestamp,tx_position_in_block,from_address,token_in,token_out,amount_in,amount_out,gas_price,pool_address,token_launch_block,is_contract_caller,usd_value_in,usd_value_out,gas_cost_usd";
+
+    #[test]
+    fn test_reports_row_column_and_expected_type() {
+        let csv = format!(
+            "# schema_version: 2\n{},chain_id,note\n\
+             0xa,1,0,0,0xbot,ETH,USDC,1,3000,10,0xpool,0,false,3000,3000,1,10,extra\n\
+             0xb,1,0,1,0xvictim,ETH,USDC,1,3000,ten,0xpool,0,false,3000,3000,1,10,extra\n",
+            HEADER
+        );
+        let rows: Vec<_> = read_transactions(csv.as_bytes()).collect();
+
+        let first = rows[0].as_ref().expect("Extra columns are ignored");
+        assert_eq!(first.chain_id, 10);
+        assert_eq!(
+            rows[1].as_ref().unwrap_err(),
+            "invalid CSV row 2 (line 4), column gas_price: expected an unsigned integer, got \"ten\""
+        );
+    }
+
+    #[test]
+    fn test_validates_header_against_schema_version() {
+        // Version 1 exports (no header line) don't have chain_id
+        let legacy = format!(
+            "{}\n0xa,1,0,0,0xbot,ETH,USDC,1,3000,10,0xpool,0,false,3000,3000,1\n",
+            HEADER
+        );
+        let swaps: Vec<_> = read_transactions(legacy.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(swaps[0].chain_id, ETHEREUM_CHAIN_ID);
+
+        let versioned = format!("# schema_version: 2\n{}\n", HEADER);
+        let err = SwapCsvReader::new(versioned.as_bytes()).err().unwrap();
+        assert!(
+            err.contains("missing columns for schema version 2: chain_id"),
+            "{}",
+            err
+        );
+
+        let newer = format!("# schema_version: 9\n{}\n", HEADER);
+        assert!(SwapCsvReader::new(newer.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use std::path::Path;
+
+use duckdb::types::{TimeUnit, Value};
+use duckdb::Connection;
+
+use crate::sandwich::transactions::{SwapTransaction, ETHEREUM_CHAIN_ID};
+
+/// Runs analyst-provided SQL against DuckDB and materializes the result as swaps.
+///
+/// The query can read tables of a DuckDB database as well as files directly,
+/// e.g. `SELECT * FROM 'swaps/*.parquet' WHERE pool_address = '0x…'`.
+/// Its result must expose the `SWAP_COLUMNS` (extra columns are ignored,
+/// `chain_id` may be left out for mainnet data). Column types are converted
+/// leniently: integers, decimals and doubles are all accepted for numbers and
+/// `TIMESTAMP` columns are turned into unix seconds.
+pub struct DuckDbSource {
+    connection: Connection,
+}
+
+impl DuckDbSource {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let connection =
+            Connection::open(path).map_err(|err| format!("failed to open duckdb: {}", err))?;
+        Ok(Self { connection })
+    }
+
+    /// An in-memory database, for querying Parquet/CSV files directly.
+    pub fn open_in_memory() -> Result<Self, String> {
+        let connection = Connection::open_in_memory()
+            .map_err(|err| format!("failed to open duckdb: {}", err))?;
+        Ok(Self { connection })
+    }
+
+    pub fn query_swaps(&self, sql: &str) -> Result<Vec<SwapTransaction>, String> {
+        let mut statement = self
+            .connection
+            .prepare(sql)
+            .map_err(|err| format!("invalid swap query: {}", err))?;
+        let mut rows = statement
+            .query([])
+            .map_err(|err| format!("failed to query swaps: {}", err))?;
+
+        let columns = match rows.as_ref() {
+            Some(statement) => statement.column_names(),
+            None => Vec::new(),
+        };
+        let has_chain_id = columns.iter().any(|column| column == "chain_id");
+
+        let mut swaps = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .map_err(|err| format!("failed to read swaps: {}", err))?
+        {
+            let value = |column: &str| -> Result<Value, String> {
+                row.get::<_, Value>(column)
+                    .map_err(|err| format!("invalid column {}: {}", column, err))
+            };
+            let text = |column: &str| -> Result<String, String> {
+                match value(column)? {
+                    Value::Text(text) => Ok(text),
+                    other => Err(format!("column {} is not text: {:?}", column, other)),
+                }
+            };
+            let unsigned = |column: &str| value_to_u64(column, value(column)?);
+            let float = |column: &str| value_to_f64(column, value(column)?);
+            let fee = |column: &str| -> Result<Option<u64>, String> {
+                if !columns.iter().any(|name| name == column) {
+                    return Ok(None);
+                }
+                match value(column)? {
+                    Value::Null => Ok(None),
+                    other => value_to_u64(column, other).map(Some),
+                }
+            };
+
+            let tx_position_in_block = unsigned("tx_position_in_block")?;
+            swaps.push(SwapTransaction {
+                tx_hash: text("tx_hash")?,
+                chain_id: match has_chain_id {
+                    true => unsigned("chain_id")?,
+                    false => ETHEREUM_CHAIN_ID,
+                },
+                block_number: unsigned("block_number")?,
+                timestamp: unsigned("timestamp")?,
+                tx_position_in_block: u32::try_from(tx_position_in_block).map_err(|_| {
+                    format!(
+                        "tx_position_in_block out of range: {}",
+                        tx_position_in_block
+                    )
+                })?,
+                from_address: text("from_address")?,
+                token_in: text("token_in")?,
+                token_out: text("token_out")?,
+                amount_in: float("amount_in")?,
+                amount_out: float("amount_out")?,
+                gas_price: unsigned("gas_price")?,
+                pool_address: text("pool_address")?,
+                token_launch_block: unsigned("token_launch_block")?,
+                is_contract_caller: match value("is_contract_caller")? {
+                    Value::Boolean(flag) => flag,
+                    other => value_to_u64("is_contract_caller", other)? != 0,
+                },
+                usd_value_in: float("usd_value_in")?,
+                usd_value_out: float("usd_value_out")?,
+                gas_cost_usd: float("gas_cost_usd")?,
+                max_fee_per_gas: fee("max_fee_per_gas")?,
+                max_priority_fee_per_gas: fee("max_priority_fee_per_gas")?,
+                base_fee_per_gas: fee("base_fee_per_gas")?,
+                raw_amount_in: None,
+            });
+        }
+
+        return Ok(swaps);
+    }
+
+    /// Load swaps from Parquet files matching `glob`, optionally filtered by a SQL condition.
+    pub fn load_parquet(
+        &self,
+        glob: &str,
+        condition: Option<&str>,
+    ) -> Result<Vec<SwapTransaction>, String> {
+        let sql = format!(
+            "SELECT * FROM read_parquet('{}') WHERE {} ORDER BY block_number, tx_position_in_block",
+            glob.replace('\'', "''"),
+            condition.unwrap_or("true")
+        );
+        return self.query_swaps(&sql);
+    }
+}
+
+fn value_to_u64(column: &str, value: Value) -> Result<u64, String> {
+    let number = match value {
+        Value::TinyInt(n) => i128::from(n),
+        Value::SmallInt(n) => i128::from(n),
+        Value::Int(n) => i128::from(n),
+        Value::BigInt(n) => i128::from(n),
+        Value::HugeInt(n) => n,
+        Value::UTinyInt(n) => i128::from(n),
+        Value::USmallInt(n) => i128::from(n),
+        Value::UInt(n) => i128::from(n),
+        Value::UBigInt(n) => i128::from(n),
+        Value::Timestamp(unit, n) => {
+            let seconds = match unit {
+                TimeUnit::Second => n,
+                TimeUnit::Millisecond => n / 1_000,
+                TimeUnit::Microsecond => n / 1_000_000,
+                TimeUnit::Nanosecond => n / 1_000_000_000,
+            };
+            i128::from(seconds)
+        }
+        Value::Decimal(_) | Value::Double(_) | Value::Float(_) => {
+            value_to_f64(column, value)? as i128
+        }
+        other => return Err(format!("column {} is not a number: {:?}", column, other)),
+    };
+
+    u64::try_from(number).map_err(|_| format!("column {} out of range: {}", column, number))
+}
+
+fn value_to_f64(column: &str, value: Value) -> Result<f64, String> {
+    match value {
+        Value::Float(n) => Ok(f64::from(n)),
+        Value::Double(n) => Ok(n),
+        Value::Decimal(n) => n
+            .to_string()
+            .parse()
+            .map_err(|_| format!("column {} is not a number: {}", column, n)),
+        other => value_to_u64(column, other).map(|n| n as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_swaps_over_csv_and_parquet() {
+        let source = DuckDbSource::open_in_memory().expect("Failed to open duckdb");
+
+        let swaps = source
+            .query_swaps(
+                "SELECT * FROM read_csv('data/sandwiches.csv')
+                WHERE block_number BETWEEN 12360 AND 12361
+                ORDER BY block_number, tx_position_in_block",
+            )
+            .expect("Failed to query swaps");
+        let expected: Vec<SwapTransaction> = crate::ingest::csv::sample_transactions()
+            .into_iter()
+            .filter(|tx| (12360..=12361).contains(&tx.block_number))
+            .collect();
+        assert_eq!(swaps, expected);
+
+        let path = std::env::temp_dir().join(format!("swaps-{}.parquet", std::process::id()));
+        source
+            .connection
+            .execute_batch(&format!(
+                "COPY (SELECT * FROM read_csv('data/sandwiches.csv')) TO '{}' (FORMAT PARQUET)",
+                path.display()
+            ))
+            .expect("Failed to write parquet");
+
+        let from_parquet = source
+            .load_parquet(
+                &path.display().to_string(),
+                Some("pool_address = '0xpool1'"),
+            )
+            .expect("Failed to load parquet");
+        assert!(!from_parquet.is_empty());
+        assert!(from_parquet.iter().all(|tx| tx.pool_address == "0xpool1"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,8 @@
+pub mod bigquery;
+pub mod csv;
+#[cfg(feature = "duckdb")]
+pub mod duckdb;
+pub mod reserves;
+#[cfg(feature = "solana")]
+pub mod solana;
+pub mod traces;
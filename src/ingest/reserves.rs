@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ethnum::U256;
+
+use super::traces::{word_to_u256, PoolTokens, ReceiptLog};
+use crate::config::Config;
+use crate::sandwich::cancel::CancellationToken;
+use crate::sandwich::error::DetectionOutcome;
+use crate::sandwich::same_block_sim::{
+    find_sandwiches_in_block_by_simulation, Pool, SandwichAttackBySimulation,
+};
+use crate::sandwich::tokens::TokenDecimals;
+use crate::sandwich::transactions::{group_transactions_by_block, SwapTransaction};
+
+/// `Sync(uint112,uint112)` emitted by Uniswap V2 style pools after every
+/// change of their reserves.
+pub const UNISWAP_V2_SYNC_TOPIC: &str =
+    "0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1";
+
+/// The reserves of a pool right after one of its transactions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncEvent {
+    pub pool_address: String,
+    pub block_number: u64,
+    pub log_index: u64,
+    pub reserve0: U256,
+    pub reserve1: U256,
+}
+
+/// `None` for logs other than `Sync`.
+pub fn decode_sync_event(block_number: u64, log: &ReceiptLog) -> Option<SyncEvent> {
+    let topic = log.topics.first()?.to_lowercase();
+    if topic != UNISWAP_V2_SYNC_TOPIC {
+        return None;
+    }
+    let data = log.data.trim_start_matches("0x");
+    return Some(SyncEvent {
+        pool_address: log.address.to_lowercase(),
+        block_number,
+        log_index: log.log_index,
+        reserve0: word_to_u256(data.get(..64)?)?,
+        reserve1: word_to_u256(data.get(64..128)?)?,
+    });
+}
+
+/// Derives start-of-block reserves of V2 pools by replaying their `Sync`
+/// events, an alternative to reading them from an archive node.
+///
+/// Only pools listed in `tokens` are kept since the simulation needs their
+/// token order. A pool's reserves are only known once one of its `Sync`
+/// events has been seen, so replay from before the first block to simulate.
+#[derive(Debug, Clone, Default)]
+pub struct ReserveReconstructor {
+    tokens: HashMap<String, PoolTokens>,
+    decimals: Option<TokenDecimals>,
+    /// Last `(log_index, reserve0, reserve1)` of every block, by pool.
+    reserves: HashMap<String, BTreeMap<u64, (u64, U256, U256)>>,
+}
+
+impl ReserveReconstructor {
+    pub fn new(tokens: HashMap<String, PoolTokens>) -> Self {
+        return Self {
+            tokens: tokens
+                .into_iter()
+                .map(|(pool, tokens)| (pool.to_lowercase(), tokens))
+                .collect(),
+            decimals: None,
+            reserves: HashMap::new(),
+        };
+    }
+
+    /// Give pools in whole tokens, for swaps normalized with the same
+    /// decimals. `Sync` events carry raw reserves.
+    pub fn with_decimals(mut self, decimals: TokenDecimals) -> Self {
+        self.decimals = Some(decimals);
+        return self;
+    }
+
+    /// Events may come in any order, the last one of each block wins.
+    pub fn observe(&mut self, event: &SyncEvent) {
+        let pool = event.pool_address.to_lowercase();
+        if !self.tokens.contains_key(&pool) {
+            return;
+        }
+        let blocks = self.reserves.entry(pool).or_default();
+        let state = (event.log_index, event.reserve0, event.reserve1);
+        match blocks.get(&event.block_number) {
+            Some((log_index, _, _)) if *log_index > event.log_index => {}
+            _ => {
+                blocks.insert(event.block_number, state);
+            }
+        }
+    }
+
+    /// Replay the `Sync` events among the receipt logs of a block.
+    pub fn observe_logs(&mut self, block_number: u64, logs: &[ReceiptLog]) {
+        for log in logs {
+            if let Some(event) = decode_sync_event(block_number, log) {
+                self.observe(&event);
+            }
+        }
+    }
+
+    /// Reserves `(reserve0, reserve1)` of `pool_address` at the start of
+    /// `block_number`.
+    pub fn reserves_before(&self, pool_address: &str, block_number: u64) -> Option<(f64, f64)> {
+        let (reserve0, reserve1) = self.raw_reserves_before(pool_address, block_number)?;
+        return Some((reserve0.as_f64(), reserve1.as_f64()));
+    }
+
+    /// `reserves_before`, exact.
+    pub fn raw_reserves_before(
+        &self,
+        pool_address: &str,
+        block_number: u64,
+    ) -> Option<(U256, U256)> {
+        let blocks = self.reserves.get(&pool_address.to_lowercase())?;
+        let (_block, (_log_index, reserve0, reserve1)) =
+            blocks.range(..block_number).next_back()?;
+        return Some((*reserve0, *reserve1));
+    }
+
+    /// Every pool with known reserves, as they were at the start of
+    /// `block_number`, keyed by pool address as in the swaps.
+    pub fn pool_map(&self, block_number: u64) -> HashMap<String, Pool> {
+        let mut pool_map = HashMap::new();
+        for (pool_address, tokens) in &self.tokens {
+            if let Some((reserve0, reserve1)) = self.raw_reserves_before(pool_address, block_number)
+            {
+                let mut pool = Pool::from_raw_reserves(
+                    reserve0,
+                    reserve1,
+                    tokens.token0.clone(),
+                    tokens.token1.clone(),
+                );
+                if let Some(decimals) = &self.decimals {
+                    pool = pool.normalized(decimals);
+                }
+                pool_map.insert(pool_address.clone(), pool);
+            }
+        }
+        return pool_map;
+    }
+}
+
+/// `find_sandwich_attacks_by_simulation` with each block simulated on the
+/// reserves `reconstructor` derived for it. Pool addresses of the swaps are
+/// expected lowercase.
+pub fn find_sandwich_attacks_with_reconstructed_pools(
+    reconstructor: &ReserveReconstructor,
+    transactions: &[SwapTransaction],
+    config: &Config,
+) -> DetectionOutcome<SandwichAttackBySimulation> {
+    let mut blocks: Vec<_> = group_transactions_by_block(transactions)
+        .into_iter()
+        .collect();
+    blocks.sort_by_key(|(block_id, _)| *block_id);
+
+    let mut outcome = DetectionOutcome::default();
+    for (block_id, block_transactions) in blocks {
+        let pool_map = reconstructor.pool_map(block_id.block_number);
+        outcome.extend(find_sandwiches_in_block_by_simulation(
+            &pool_map,
+            &block_transactions,
+            config,
+            &CancellationToken::new(),
+        ));
+    }
+    return outcome;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+
+    fn sync_log(pool: &str, log_index: u64, reserve0: u128, reserve1: u128) -> ReceiptLog {
+        return ReceiptLog {
+            address: pool.to_string(),
+            topics: vec![UNISWAP_V2_SYNC_TOPIC.to_string()],
+            data: format!("0x{:064x}{:064x}", reserve0, reserve1),
+            transaction_hash: format!("0x{}", log_index),
+            log_index,
+        };
+    }
+
+    #[test]
+    fn test_replayed_sync_events_give_start_of_block_reserves() {
+        let transactions = crate::test_support::sample_block();
+        let tokens = PoolTokens {
+            token0: "USDC".to_string(),
+            token1: "SHIB".to_string(),
+        };
+        let mut reconstructor =
+            ReserveReconstructor::new(HashMap::from([("0xPOOL1".to_string(), tokens)]));
+
+        // Out of order within the block, the later log is the end state
+        reconstructor.observe_logs(
+            12359,
+            &[
+                sync_log("0xpool1", 7, 1000000, 50000000000),
+                sync_log("0xpool1", 3, 990000, 50500000000),
+                sync_log("0xunknown", 8, 1, 1),
+            ],
+        );
+        reconstructor.observe_logs(12360, &[sync_log("0xpool1", 2, 1006000, 49700000000)]);
+
+        assert_eq!(reconstructor.reserves_before("0xpool1", 12359), None);
+        assert_eq!(
+            reconstructor.reserves_before("0xpool1", 12360),
+            Some((1000000.0, 50000000000.0))
+        );
+        assert_eq!(
+            reconstructor.reserves_before("0xpool1", 12400),
+            Some((1006000.0, 49700000000.0))
+        );
+        assert_eq!(reconstructor.pool_map(12360).len(), 1);
+
+        let pool_map = crate::test_support::sample_pool_map();
+        let reconstructed = find_sandwich_attacks_with_reconstructed_pools(
+            &reconstructor,
+            &transactions,
+            &Config::default(),
+        );
+        assert!(!reconstructed.attacks.is_empty());
+        assert_eq!(
+            reconstructed.attacks,
+            find_sandwich_attacks_by_simulation(&pool_map, &transactions)
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+#[cfg(feature = "rpc")]
+use crate::enrich::rpc::RpcClient;
+use crate::sandwich::transactions::SwapTransaction;
+
+/// Solana has no EIP-155 chain ID, this is the one cross-chain aggregators use for mainnet-beta.
+pub const SOLANA_CHAIN_ID: u64 = 1151111081099710;
+
+/// Mint of wrapped SOL, used as the token for native SOL legs.
+pub const WSOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+/// Supported DEX programs as `(program_id, index of the pool account in the swap instruction)`.
+pub const DEX_PROGRAMS: &[(&str, usize)] = &[
+    // Raydium AMM v4
+    ("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8", 1),
+    // Raydium CLMM
+    ("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK", 2),
+    // Raydium CPMM
+    ("CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C", 3),
+    // Orca Whirlpools
+    ("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc", 2),
+    // Orca legacy token swap
+    ("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP", 0),
+];
+
+/// A `getBlock` result requested with `encoding: "jsonParsed"` and
+/// `transactionDetails: "full"`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SolanaBlock {
+    #[serde(default)]
+    pub block_time: Option<u64>,
+    #[serde(default)]
+    pub transactions: Vec<SolanaTransactionWithMeta>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SolanaTransactionWithMeta {
+    pub transaction: SolanaTransaction,
+    #[serde(default)]
+    pub meta: Option<SolanaMeta>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SolanaTransaction {
+    pub signatures: Vec<String>,
+    pub message: SolanaMessage,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SolanaMessage {
+    pub account_keys: Vec<AccountKey>,
+    #[serde(default)]
+    pub instructions: Vec<ParsedInstruction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountKey {
+    pub pubkey: String,
+    #[serde(default)]
+    pub signer: bool,
+}
+
+/// Instructions of programs the node can't parse come back as raw
+/// `programId` + `accounts`, which is all we need to find the pool.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedInstruction {
+    pub program_id: String,
+    #[serde(default)]
+    pub accounts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SolanaMeta {
+    #[serde(default)]
+    pub err: Option<serde_json::Value>,
+    pub fee: u64,
+    #[serde(default)]
+    pub pre_balances: Vec<u64>,
+    #[serde(default)]
+    pub post_balances: Vec<u64>,
+    #[serde(default)]
+    pub pre_token_balances: Vec<TokenBalance>,
+    #[serde(default)]
+    pub post_token_balances: Vec<TokenBalance>,
+    #[serde(default)]
+    pub inner_instructions: Vec<InnerInstructions>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenBalance {
+    pub account_index: usize,
+    pub mint: String,
+    #[serde(default)]
+    pub owner: Option<String>,
+    pub ui_token_amount: UiTokenAmount,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiTokenAmount {
+    #[serde(default)]
+    pub ui_amount: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InnerInstructions {
+    pub index: usize,
+    #[serde(default)]
+    pub instructions: Vec<ParsedInstruction>,
+}
+
+pub fn parse_block(json: &str) -> Result<SolanaBlock, String> {
+    serde_json::from_str(json).map_err(|err| format!("invalid Solana block: {}", err))
+}
+
+/// Map the Raydium/Orca swaps of a block into the common `SwapTransaction` model.
+///
+/// - `slot` becomes the `block_number`.
+/// - `tx_position_in_block` is the transaction's index within the slot, so
+///   skipped transactions leave gaps.
+/// - The swapper is the fee payer, and the amounts are its token balance changes
+///   (in UI units, i.e. decimals applied). Native SOL is reported as `WSOL_MINT`.
+/// - The transaction fee in lamports stands in for the gas price, it is what
+///   orders transactions within a leader's block.
+/// - A swap is marked as contract-called when the DEX was reached through
+///   another program (e.g. an aggregator) rather than invoked at the top level.
+///
+/// Failed transactions are skipped. `mint_symbols` maps mints to symbols so the
+/// token equivalence groups apply, unmapped mints keep their address.
+///
+/// TODO: Multi-hop transactions are reported as a single swap on the first
+/// pool, from the first token spent to the first token received.
+pub fn swaps_from_block(
+    slot: u64,
+    block: &SolanaBlock,
+    mint_symbols: &HashMap<String, String>,
+) -> Vec<SwapTransaction> {
+    let mut swaps = Vec::new();
+    let timestamp = block.block_time.unwrap_or(0);
+
+    for (position, tx) in block.transactions.iter().enumerate() {
+        let Some(meta) = &tx.meta else {
+            continue;
+        };
+        if meta.err.is_some() {
+            continue;
+        }
+
+        let Some((pool, is_routed)) = find_dex_pool(&tx.transaction.message, meta) else {
+            continue;
+        };
+
+        let Some(payer) = tx.transaction.message.account_keys.first() else {
+            continue;
+        };
+        let Some((token_in, amount_in, token_out, amount_out)) =
+            payer_balance_changes(&payer.pubkey, meta)
+        else {
+            continue;
+        };
+
+        swaps.push(SwapTransaction {
+            tx_hash: tx
+                .transaction
+                .signatures
+                .first()
+                .cloned()
+                .unwrap_or_default(),
+            chain_id: SOLANA_CHAIN_ID,
+            block_number: slot,
+            timestamp,
+            tx_position_in_block: position as u32,
+            from_address: payer.pubkey.clone(),
+            token_in: mint_symbol(&token_in, mint_symbols),
+            token_out: mint_symbol(&token_out, mint_symbols),
+            amount_in,
+            amount_out,
+            gas_price: meta.fee,
+            pool_address: pool,
+            token_launch_block: 0,
+            is_contract_caller: is_routed,
+            usd_value_in: 0.0,
+            usd_value_out: 0.0,
+            gas_cost_usd: 0.0,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
+            raw_amount_in: None,
+        });
+    }
+
+    return swaps;
+}
+
+fn mint_symbol(mint: &str, mint_symbols: &HashMap<String, String>) -> String {
+    mint_symbols
+        .get(mint)
+        .cloned()
+        .unwrap_or_else(|| mint.to_string())
+}
+
+fn pool_account(instruction: &ParsedInstruction) -> Option<String> {
+    let (_program_id, pool_index) = DEX_PROGRAMS
+        .iter()
+        .find(|(program_id, _)| *program_id == instruction.program_id)?;
+    instruction.accounts.get(*pool_index).cloned()
+}
+
+/// Find the first DEX instruction, either at the top level or invoked by another
+/// program, and return its pool and whether it was reached through another program.
+fn find_dex_pool(message: &SolanaMessage, meta: &SolanaMeta) -> Option<(String, bool)> {
+    for (index, instruction) in message.instructions.iter().enumerate() {
+        if let Some(pool) = pool_account(instruction) {
+            return Some((pool, false));
+        }
+
+        let inner = meta
+            .inner_instructions
+            .iter()
+            .filter(|inner| inner.index == index)
+            .flat_map(|inner| inner.instructions.iter());
+        for instruction in inner {
+            if let Some(pool) = pool_account(instruction) {
+                return Some((pool, true));
+            }
+        }
+    }
+
+    return None;
+}
+
+/// Net token changes of the payer as `(token_in, amount_in, token_out, amount_out)`.
+fn payer_balance_changes(payer: &str, meta: &SolanaMeta) -> Option<(String, f64, String, f64)> {
+    let mut deltas: Vec<(String, f64)> = Vec::new();
+    let mut add_delta = |mint: &str, delta: f64| match deltas.iter_mut().find(|(m, _)| m == mint) {
+        Some((_, total)) => *total += delta,
+        None => deltas.push((mint.to_string(), delta)),
+    };
+
+    for balance in &meta.pre_token_balances {
+        if balance.owner.as_deref() == Some(payer) {
+            add_delta(
+                &balance.mint,
+                -balance.ui_token_amount.ui_amount.unwrap_or(0.0),
+            );
+        }
+    }
+    for balance in &meta.post_token_balances {
+        if balance.owner.as_deref() == Some(payer) {
+            add_delta(
+                &balance.mint,
+                balance.ui_token_amount.ui_amount.unwrap_or(0.0),
+            );
+        }
+    }
+
+    // Native SOL spent or received, excluding the fee and the rent the payer
+    // always pays, which would otherwise outweigh small token amounts
+    if let (Some(pre), Some(post)) = (meta.pre_balances.first(), meta.post_balances.first()) {
+        let lamports = *post as f64 - *pre as f64 + meta.fee as f64 + rent_lamports(meta);
+        add_delta(WSOL_MINT, lamports / 1_000_000_000.0);
+    }
+
+    // Ignore rounding dust
+    let (token_in, amount_in) = deltas
+        .iter()
+        .filter(|(_, delta)| *delta < -1e-6)
+        .min_by(|a, b| a.1.total_cmp(&b.1))?;
+    let (token_out, amount_out) = deltas
+        .iter()
+        .filter(|(mint, delta)| *delta > 1e-6 && mint != token_in)
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    return Some((token_in.clone(), -amount_in, token_out.clone(), *amount_out));
+}
+
+/// Rent deposited into the accounts the transaction created (e.g. token
+/// accounts for the output) less the rent refunded by those it closed, in
+/// lamports. Wrapped SOL held by these accounts is already a token delta.
+fn rent_lamports(meta: &SolanaMeta) -> f64 {
+    let wrapped = |balances: &[TokenBalance], index: usize| -> f64 {
+        let balance = balances
+            .iter()
+            .find(|balance| balance.account_index == index && balance.mint == WSOL_MINT);
+        return balance
+            .and_then(|balance| balance.ui_token_amount.ui_amount)
+            .unwrap_or(0.0)
+            * 1_000_000_000.0;
+    };
+
+    let mut rent = 0.0;
+    let balances = meta.pre_balances.iter().zip(&meta.post_balances);
+    // The payer is the first account
+    for (index, (pre, post)) in balances.enumerate().skip(1) {
+        if *pre == 0 && *post > 0 {
+            rent += *post as f64 - wrapped(&meta.post_token_balances, index);
+        } else if *pre > 0 && *post == 0 {
+            rent -= *pre as f64 - wrapped(&meta.pre_token_balances, index);
+        }
+    }
+    return rent;
+}
+
+/// Fetch a confirmed block in the `jsonParsed` encoding this adapter expects.
+#[cfg(feature = "rpc")]
+pub fn fetch_block(client: &RpcClient, slot: u64) -> Result<SolanaBlock, String> {
+    let params = serde_json::json!([
+        slot,
+        {
+            "encoding": "jsonParsed",
+            "transactionDetails": "full",
+            "maxSupportedTransactionVersion": 0,
+            "rewards": false
+        }
+    ]);
+    let result = client.call("getBlock", params, Some(slot))?;
+
+    serde_json::from_value(result).map_err(|err| format!("invalid Solana block: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const BONK_MINT: &str = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263";
+
+    fn swap_tx(signature: &str, payer: &str, dex: usize, routed: bool, fee: u64) -> String {
+        let (program, pool_index) = DEX_PROGRAMS[dex];
+        let mut accounts = vec!["\"Other\"".to_string(); 4];
+        accounts[pool_index] = format!("\"POOL_{}\"", signature);
+        let dex_instruction = format!(
+            r#"{{"programId":"{}","accounts":[{}],"data":"3x"}}"#,
+            program,
+            accounts.join(",")
+        );
+        let (instructions, inner) = if routed {
+            (
+                r#"[{"programId":"JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4","accounts":[],"data":"1"}]"#
+                    .to_string(),
+                format!(r#"[{{"index":0,"instructions":[{}]}}]"#, dex_instruction),
+            )
+        } else {
+            (format!("[{}]", dex_instruction), "[]".to_string())
+        };
+
+        format!(
+            r#"{{"transaction":{{"signatures":["{sig}"],"message":{{"accountKeys":[{{"pubkey":"{payer}","signer":true}}],"instructions":{instructions}}}}},
+            "meta":{{"err":null,"fee":{fee},"preBalances":[2000000000,2039280,0],"postBalances":[{post},2039280,2039280],
+            "preTokenBalances":[{{"accountIndex":1,"mint":"{usdc}","owner":"{payer}","uiTokenAmount":{{"uiAmount":1000.0}}}}],
+            "postTokenBalances":[{{"accountIndex":1,"mint":"{usdc}","owner":"{payer}","uiTokenAmount":{{"uiAmount":0.0}}}},
+                                 {{"accountIndex":2,"mint":"{bonk}","owner":"{payer}","uiTokenAmount":{{"uiAmount":5000000.0}}}}],
+            "innerInstructions":{inner}}}}}"#,
+            sig = signature,
+            payer = payer,
+            instructions = instructions,
+            fee = fee,
+            // Only the fee and the rent of the new BONK account leave the payer's SOL balance
+            post = 2000000000 - fee - 2039280,
+            usdc = USDC_MINT,
+            bonk = BONK_MINT,
+            inner = inner,
+        )
+    }
+
+    #[test]
+    fn test_swaps_from_raydium_and_orca_block() {
+        let json = format!(
+            r#"{{"blockTime":1700000000,"transactions":[{},{},{}]}}"#,
+            swap_tx("sig1", "Bot", 0, false, 500000),
+            r#"{"transaction":{"signatures":["vote"],"message":{"accountKeys":[{"pubkey":"Validator","signer":true}],"instructions":[{"programId":"Vote111111111111111111111111111111111111111","accounts":[]}]}},"meta":{"err":null,"fee":5000}}"#,
+            swap_tx("sig2", "Victim", 3, true, 5000),
+        );
+        let block = parse_block(&json).expect("Failed to parse block");
+        let symbols = HashMap::from([
+            (USDC_MINT.to_string(), "USDC".to_string()),
+            (BONK_MINT.to_string(), "BONK".to_string()),
+        ]);
+
+        let swaps = swaps_from_block(250000000, &block, &symbols);
+        assert_eq!(swaps.len(), 2, "Vote transactions are not swaps");
+
+        assert_eq!(swaps[0].chain_id, SOLANA_CHAIN_ID);
+        assert_eq!(swaps[0].block_number, 250000000);
+        assert_eq!(swaps[0].timestamp, 1700000000);
+        assert_eq!(swaps[0].tx_hash, "sig1");
+        assert_eq!(swaps[0].from_address, "Bot");
+        assert_eq!(swaps[0].pool_address, "POOL_sig1");
+        assert_eq!(swaps[0].token_in, "USDC");
+        assert_eq!(swaps[0].token_out, "BONK");
+        assert_eq!(swaps[0].amount_in, 1000.0);
+        assert_eq!(swaps[0].amount_out, 5000000.0);
+        assert_eq!(swaps[0].gas_price, 500000);
+        assert!(!swaps[0].is_contract_caller);
+
+        assert_eq!(swaps[0].tx_position_in_block, 0);
+        assert_eq!(
+            swaps[1].tx_position_in_block, 2,
+            "Positions count the vote transaction"
+        );
+        assert_eq!(swaps[1].pool_address, "POOL_sig2");
+        assert!(swaps[1].is_contract_caller, "Routed through an aggregator");
+    }
+
+    #[test]
+    fn test_rent_is_not_a_sol_leg() {
+        let meta = |pre_balances: &str, post_balances: &str, wsol: &str| -> SolanaMeta {
+            let json = format!(
+                r#"{{"fee":5000,"preBalances":{},"postBalances":{},
+                "preTokenBalances":[{{"accountIndex":1,"mint":"{usdc}","owner":"Payer","uiTokenAmount":{{"uiAmount":0.001}}}}{wsol}],
+                "postTokenBalances":[{{"accountIndex":1,"mint":"{usdc}","owner":"Payer","uiTokenAmount":{{"uiAmount":0.0}}}},
+                                     {{"accountIndex":2,"mint":"{bonk}","owner":"Payer","uiTokenAmount":{{"uiAmount":5.0}}}}]}}"#,
+                pre_balances,
+                post_balances,
+                usdc = USDC_MINT,
+                bonk = BONK_MINT,
+                wsol = wsol,
+            );
+            return serde_json::from_str(&json).unwrap();
+        };
+
+        // 0.001 USDC in, the new BONK account's rent (0.002 SOL) isn't spent SOL
+        let created = meta("[1000000000,2039280,0]", "[997955720,2039280,2039280]", "");
+        assert_eq!(
+            payer_balance_changes("Payer", &created),
+            Some((USDC_MINT.to_string(), 0.001, BONK_MINT.to_string(), 5.0))
+        );
+
+        // Closing a wrapped SOL account refunds its rent next to the 0.5 SOL it held
+        let wsol = format!(
+            r#",{{"accountIndex":3,"mint":"{}","owner":"Payer","uiTokenAmount":{{"uiAmount":0.5}}}}"#,
+            WSOL_MINT
+        );
+        let closed = meta(
+            "[1000000000,2039280,0,502039280]",
+            "[1499995000,2039280,2039280,0]",
+            &wsol,
+        );
+        let (token_in, _, token_out, _) = payer_balance_changes("Payer", &closed).unwrap();
+        assert_eq!(
+            (token_in.as_str(), token_out.as_str()),
+            (USDC_MINT, BONK_MINT)
+        );
+    }
+}
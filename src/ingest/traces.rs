@@ -0,0 +1,642 @@
+use std::collections::HashMap;
+
+use ethnum::U256;
+use serde::Deserialize;
+
+#[cfg(feature = "rpc")]
+use crate::enrich::rpc::RpcClient;
+use crate::sandwich::transactions::{SwapTransaction, ETHEREUM_CHAIN_ID};
+
+/// `Swap(address,uint256,uint256,uint256,uint256,address)` emitted by Uniswap V2 style pools.
+pub const UNISWAP_V2_SWAP_TOPIC: &str =
+    "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822";
+
+/// `Swap(address,address,int256,int256,uint160,uint128,int24)` emitted by Uniswap V3 style pools.
+pub const UNISWAP_V3_SWAP_TOPIC: &str =
+    "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67";
+
+/// The two tokens of a pool, in the pool's own `token0`/`token1` order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolTokens {
+    pub token0: String,
+    pub token1: String,
+}
+
+/// One entry of `debug_traceBlockByNumber` with the `callTracer`
+/// (and `withLog: true` so call frames carry their logs).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TracedTransaction {
+    pub tx_hash: String,
+    pub result: CallFrame,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CallFrame {
+    pub from: String,
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub calls: Vec<CallFrame>,
+    #[serde(default)]
+    pub logs: Vec<TraceLog>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TraceLog {
+    pub address: String,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub data: String,
+    /// How many of the frame's `calls` ran before the log was emitted.
+    /// Unset by tracers that predate it.
+    #[serde(default, deserialize_with = "optional_quantity")]
+    pub position: Option<u64>,
+}
+
+/// One entry of Parity/OpenEthereum style `trace_block` output.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParityTrace {
+    pub action: ParityAction,
+    #[serde(default)]
+    pub trace_address: Vec<u64>,
+    #[serde(default)]
+    pub transaction_hash: Option<String>,
+    #[serde(default)]
+    pub transaction_position: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ParityAction {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+}
+
+/// A receipt log, as returned by `eth_getBlockReceipts`/`eth_getLogs`.
+/// `trace_block` carries no logs, so they're joined in from receipts.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptLog {
+    pub address: String,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub data: String,
+    pub transaction_hash: String,
+    #[serde(deserialize_with = "quantity")]
+    pub log_index: u64,
+}
+
+/// The parts of an `eth_getBlockReceipts` receipt the trace adapters need,
+/// traces carry neither the transaction's index nor its gas price.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionReceipt {
+    pub transaction_hash: String,
+    #[serde(deserialize_with = "quantity")]
+    pub transaction_index: u64,
+    #[serde(deserialize_with = "quantity")]
+    pub effective_gas_price: u64,
+}
+
+fn quantity<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    u64::from_str_radix(value.trim_start_matches("0x"), 16).map_err(serde::de::Error::custom)
+}
+
+fn optional_quantity<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    return quantity(deserializer).map(Some);
+}
+
+/// A swap event together with who actually triggered it.
+struct DecodedSwap {
+    pool: String,
+    /// Address that called into the pool: the EOA itself for direct swaps,
+    /// a router, aggregator or bot contract otherwise.
+    pool_caller: Option<String>,
+    log: TraceLog,
+}
+
+pub fn parse_call_traces(json: &str) -> Result<Vec<TracedTransaction>, String> {
+    serde_json::from_str(json).map_err(|err| format!("invalid callTracer output: {}", err))
+}
+
+pub fn parse_parity_traces(json: &str) -> Result<Vec<ParityTrace>, String> {
+    serde_json::from_str(json).map_err(|err| format!("invalid trace_block output: {}", err))
+}
+
+pub fn parse_receipt_logs(json: &str) -> Result<Vec<ReceiptLog>, String> {
+    serde_json::from_str(json).map_err(|err| format!("invalid receipt logs: {}", err))
+}
+
+pub fn parse_receipts(json: &str) -> Result<Vec<TransactionReceipt>, String> {
+    serde_json::from_str(json).map_err(|err| format!("invalid receipts: {}", err))
+}
+
+/// Receipts by lowercase transaction hash.
+fn receipts_by_hash(receipts: &[TransactionReceipt]) -> HashMap<String, &TransactionReceipt> {
+    return receipts
+        .iter()
+        .map(|receipt| (receipt.transaction_hash.to_lowercase(), receipt))
+        .collect();
+}
+
+/// Extract every swap of a block from `callTracer` output, including swaps
+/// executed deep inside router and aggregator calls.
+///
+/// The swapper is the EOA that sent the transaction, not the router that
+/// happened to call the pool. The swap is flagged as contract-called when
+/// the pool was called by anything other than that EOA.
+///
+/// Only pools listed in `pools` are decoded since their token order is
+/// needed to make sense of the event amounts.
+///
+/// Position and gas price come from the block's `receipts`: a transaction
+/// without one falls back to its index in `traced` (the transaction index
+/// when `traced` is the whole block, as `debug_traceBlockByNumber` returns
+/// it) and a `gas_price` of 0, which the gas heuristics can't tell apart.
+///
+/// Swaps are tagged with `ETHEREUM_CHAIN_ID`, set `chain_id` on the
+/// results when tracing another EVM chain.
+pub fn swaps_from_call_traces(
+    block_number: u64,
+    timestamp: u64,
+    traced: &[TracedTransaction],
+    receipts: &[TransactionReceipt],
+    pools: &HashMap<String, PoolTokens>,
+) -> Vec<SwapTransaction> {
+    let receipts = receipts_by_hash(receipts);
+    let mut swaps = Vec::new();
+
+    for (index, tx) in traced.iter().enumerate() {
+        let receipt = receipts.get(&tx.tx_hash.to_lowercase());
+        let position = receipt.map_or(index as u32, |receipt| receipt.transaction_index as u32);
+        let origin = tx.result.from.to_lowercase();
+        let mut decoded = Vec::new();
+        collect_frame_swaps(&tx.result, &mut decoded);
+
+        for swap in decoded {
+            if let Some(mut swap) = to_swap_transaction(
+                &tx.tx_hash,
+                block_number,
+                timestamp,
+                position,
+                &origin,
+                &swap,
+                pools,
+            ) {
+                swap.gas_price = receipt.map_or(0, |receipt| receipt.effective_gas_price);
+                swaps.push(swap);
+            }
+        }
+    }
+
+    return swaps;
+}
+
+/// Walk the call tree depth first, in execution order: each log goes after
+/// the calls its `position` says ran before it. Logs without a position go
+/// after all of the frame's calls, as a pool emits `Swap` once its transfers
+/// and callbacks are done. Logs are emitted by the frame's callee, so the
+/// frame's `from` is whoever called into the pool.
+fn collect_frame_swaps(frame: &CallFrame, decoded: &mut Vec<DecodedSwap>) {
+    let emitted_after = |log: &TraceLog| log.position.unwrap_or(frame.calls.len() as u64);
+    let mut logs = frame.logs.iter().peekable();
+
+    for (index, call) in frame.calls.iter().enumerate() {
+        while let Some(log) = logs.next_if(|log| emitted_after(log) <= index as u64) {
+            push_swap_log(frame, log, decoded);
+        }
+        collect_frame_swaps(call, decoded);
+    }
+    for log in logs {
+        push_swap_log(frame, log, decoded);
+    }
+}
+
+fn push_swap_log(frame: &CallFrame, log: &TraceLog, decoded: &mut Vec<DecodedSwap>) {
+    if is_swap_log(log) {
+        decoded.push(DecodedSwap {
+            pool: log.address.to_lowercase(),
+            pool_caller: Some(frame.from.to_lowercase()),
+            log: log.clone(),
+        });
+    }
+}
+
+/// Extract every swap of a block from `trace_block` output joined with the
+/// block's receipt logs, and gas prices from its `receipts` (0 for
+/// transactions without one, as in `swaps_from_call_traces`).
+pub fn swaps_from_parity_traces(
+    block_number: u64,
+    timestamp: u64,
+    traces: &[ParityTrace],
+    logs: &[ReceiptLog],
+    receipts: &[TransactionReceipt],
+    pools: &HashMap<String, PoolTokens>,
+) -> Vec<SwapTransaction> {
+    let receipts = receipts_by_hash(receipts);
+    // Origin and position come from the top-level trace of each transaction
+    let mut origins: HashMap<&str, (String, u32)> = HashMap::new();
+    // Who called each pool within each transaction
+    let mut pool_callers: HashMap<(&str, String), String> = HashMap::new();
+
+    for trace in traces {
+        let (Some(tx_hash), Some(from)) = (&trace.transaction_hash, &trace.action.from) else {
+            continue;
+        };
+
+        if trace.trace_address.is_empty() {
+            origins.insert(
+                tx_hash,
+                (from.to_lowercase(), trace.transaction_position.unwrap_or(0)),
+            );
+        }
+
+        if let Some(to) = &trace.action.to {
+            pool_callers
+                .entry((tx_hash, to.to_lowercase()))
+                .or_insert_with(|| from.to_lowercase());
+        }
+    }
+
+    let mut sorted_logs: Vec<&ReceiptLog> = logs.iter().collect();
+    sorted_logs.sort_by_key(|log| log.log_index);
+
+    let mut swaps = Vec::new();
+    for log in sorted_logs {
+        let trace_log = TraceLog {
+            address: log.address.clone(),
+            topics: log.topics.clone(),
+            data: log.data.clone(),
+            position: None,
+        };
+        if !is_swap_log(&trace_log) {
+            continue;
+        }
+
+        let Some((origin, position)) = origins.get(log.transaction_hash.as_str()) else {
+            continue;
+        };
+        let pool = log.address.to_lowercase();
+        let decoded = DecodedSwap {
+            pool_caller: pool_callers
+                .get(&(log.transaction_hash.as_str(), pool.clone()))
+                .cloned(),
+            pool,
+            log: trace_log,
+        };
+
+        if let Some(mut swap) = to_swap_transaction(
+            &log.transaction_hash,
+            block_number,
+            timestamp,
+            *position,
+            origin,
+            &decoded,
+            pools,
+        ) {
+            swap.gas_price = receipts
+                .get(&log.transaction_hash.to_lowercase())
+                .map_or(0, |receipt| receipt.effective_gas_price);
+            swaps.push(swap);
+        }
+    }
+
+    return swaps;
+}
+
+fn is_swap_log(log: &TraceLog) -> bool {
+    match log.topics.first() {
+        Some(topic) => {
+            let topic = topic.to_lowercase();
+            topic == UNISWAP_V2_SWAP_TOPIC || topic == UNISWAP_V3_SWAP_TOPIC
+        }
+        None => false,
+    }
+}
+
+fn to_swap_transaction(
+    tx_hash: &str,
+    block_number: u64,
+    timestamp: u64,
+    position: u32,
+    origin: &str,
+    decoded: &DecodedSwap,
+    pools: &HashMap<String, PoolTokens>,
+) -> Option<SwapTransaction> {
+    let tokens = pools.get(&decoded.pool)?;
+    let (token0_in, raw_amount_in, amount_out) = decode_swap_amounts(&decoded.log)?;
+
+    let (token_in, token_out) = if token0_in {
+        (tokens.token0.clone(), tokens.token1.clone())
+    } else {
+        (tokens.token1.clone(), tokens.token0.clone())
+    };
+
+    let is_contract_caller = match &decoded.pool_caller {
+        Some(caller) => caller != origin,
+        None => false,
+    };
+
+    Some(SwapTransaction {
+        tx_hash: tx_hash.to_string(),
+        chain_id: ETHEREUM_CHAIN_ID,
+        block_number,
+        timestamp,
+        tx_position_in_block: position,
+        from_address: origin.to_string(),
+        token_in,
+        token_out,
+        amount_in: raw_amount_in.as_f64(),
+        amount_out,
+        // Traces don't carry it, the callers take it from the receipts
+        gas_price: 0,
+        pool_address: decoded.pool.clone(),
+        token_launch_block: 0,
+        is_contract_caller,
+        usd_value_in: 0.0,
+        usd_value_out: 0.0,
+        gas_cost_usd: 0.0,
+        max_fee_per_gas: None,
+        max_priority_fee_per_gas: None,
+        base_fee_per_gas: None,
+        raw_amount_in: Some(raw_amount_in),
+    })
+}
+
+/// Decode a swap event into `(token0_is_input, amount_in, amount_out)` in raw token units,
+/// `amount_in` exact.
+fn decode_swap_amounts(log: &TraceLog) -> Option<(bool, U256, f64)> {
+    let words = data_words(&log.data);
+    let topic = log.topics.first()?.to_lowercase();
+
+    if topic == UNISWAP_V2_SWAP_TOPIC {
+        if words.len() < 4 {
+            return None;
+        }
+        let amount0_in = word_to_f64(words[0]);
+        let amount0_out = word_to_f64(words[2]);
+        let amount1_out = word_to_f64(words[3]);
+
+        if amount0_in > 0.0 {
+            return Some((true, word_to_u256(words[0])?, amount1_out));
+        }
+        return Some((false, word_to_u256(words[1])?, amount0_out));
+    }
+
+    if topic == UNISWAP_V3_SWAP_TOPIC {
+        if words.len() < 2 {
+            return None;
+        }
+        // Deltas are from the pool's point of view, positive means the pool received tokens
+        let amount0 = signed_word_to_f64(words[0]);
+        let amount1 = signed_word_to_f64(words[1]);
+
+        // The input is the positive delta, its word is also its unsigned value
+        if amount0 > 0.0 {
+            return Some((true, word_to_u256(words[0])?, -amount1));
+        }
+        return Some((false, word_to_u256(words[1])?, -amount0));
+    }
+
+    return None;
+}
+
+fn data_words(data: &str) -> Vec<&str> {
+    let hex = data.trim_start_matches("0x");
+    (0..hex.len() / 64)
+        .map(|index| &hex[index * 64..(index + 1) * 64])
+        .collect()
+}
+
+/// A 256-bit unsigned word, `None` if it isn't hex.
+pub(crate) fn word_to_u256(word: &str) -> Option<U256> {
+    return U256::from_str_radix(word, 16).ok();
+}
+
+/// Approximate a 256-bit unsigned word as `f64`.
+pub(crate) fn word_to_f64(word: &str) -> f64 {
+    word.chars()
+        .filter_map(|digit| digit.to_digit(16))
+        .fold(0.0, |value, digit| value * 16.0 + digit as f64)
+}
+
+/// Approximate a two's complement 256-bit signed word as `f64`.
+pub(crate) fn signed_word_to_f64(word: &str) -> f64 {
+    let is_negative = word.chars().next().and_then(|digit| digit.to_digit(16)) >= Some(8);
+    if !is_negative {
+        return word_to_f64(word);
+    }
+
+    // -(!word + 1)
+    let inverted = word
+        .chars()
+        .filter_map(|digit| digit.to_digit(16))
+        .fold(0.0, |value, digit| value * 16.0 + (15 - digit) as f64);
+    return -(inverted + 1.0);
+}
+
+/// Fetch the receipts of a block, for the positions and gas prices traces lack.
+#[cfg(feature = "rpc")]
+pub fn fetch_receipts(
+    client: &RpcClient,
+    block_number: u64,
+) -> Result<Vec<TransactionReceipt>, String> {
+    let params = serde_json::json!([format!("0x{:x}", block_number)]);
+    let result = client.call("eth_getBlockReceipts", params, Some(block_number))?;
+
+    serde_json::from_value(result).map_err(|err| format!("invalid receipts: {}", err))
+}
+
+/// Fetch `callTracer` traces (with logs) for a block from an archive node.
+#[cfg(feature = "rpc")]
+pub fn fetch_call_traces(
+    client: &RpcClient,
+    block_number: u64,
+) -> Result<Vec<TracedTransaction>, String> {
+    let params = serde_json::json!([
+        format!("0x{:x}", block_number),
+        { "tracer": "callTracer", "tracerConfig": { "withLog": true } }
+    ]);
+    let result = client.call("debug_traceBlockByNumber", params, Some(block_number))?;
+
+    serde_json::from_value(result).map_err(|err| format!("invalid callTracer output: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(value: u128) -> String {
+        format!("{:064x}", value)
+    }
+
+    fn pools() -> HashMap<String, PoolTokens> {
+        HashMap::from([
+            (
+                "0xpool_v2".to_string(),
+                PoolTokens {
+                    token0: "USDC".to_string(),
+                    token1: "SHIB".to_string(),
+                },
+            ),
+            (
+                "0xpool_v3".to_string(),
+                PoolTokens {
+                    token0: "USDC".to_string(),
+                    token1: "WETH".to_string(),
+                },
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_swaps_inside_router_calls_are_attributed_to_eoa() {
+        let v2_data = format!("0x{}{}{}{}", word(5000), word(0), word(0), word(248260656));
+        // V3: pool receives 1 WETH (token1) and pays out 3198 USDC (token0)
+        let v3_data = format!("0x{}{}", "f".repeat(52) + "fffffffff382", word(1));
+
+        let json = format!(
+            r#"[
+            {{"txHash":"0xrouted","result":{{"from":"0xVictimEOA","to":"0xrouter","calls":[
+                {{"from":"0xrouter","to":"0xpool_v2","logs":[{{"address":"0xPOOL_V2","topics":["{}"],"data":"{}"}}]}}
+            ]}}}},
+            {{"txHash":"0xdirect","result":{{"from":"0xtrader","to":"0xpool_v3","logs":[
+                {{"address":"0xpool_v3","topics":["{}"],"data":"{}"}}
+            ]}}}},
+            {{"txHash":"0xunknown_pool","result":{{"from":"0xtrader","to":"0xother","logs":[
+                {{"address":"0xother","topics":["{}"],"data":"{}"}}
+            ]}}}}
+        ]"#,
+            UNISWAP_V2_SWAP_TOPIC,
+            v2_data,
+            UNISWAP_V3_SWAP_TOPIC,
+            v3_data,
+            UNISWAP_V2_SWAP_TOPIC,
+            v2_data
+        );
+
+        let traced = parse_call_traces(&json).expect("Failed to parse traces");
+        let receipts = parse_receipts(
+            r#"[
+            {"transactionHash":"0xROUTED","transactionIndex":"0x4","effectiveGasPrice":"0x3b9aca00"}
+        ]"#,
+        )
+        .expect("Failed to parse receipts");
+        let swaps = swaps_from_call_traces(12360, 1640995400, &traced, &receipts, &pools());
+        assert_eq!(swaps.len(), 2, "Swaps on unknown pools are skipped");
+
+        let routed = &swaps[0];
+        assert_eq!(routed.from_address, "0xvictimeoa");
+        assert_eq!(routed.pool_address, "0xpool_v2");
+        assert!(routed.is_contract_caller, "The router called the pool");
+        assert_eq!(routed.token_in, "USDC");
+        assert_eq!(routed.token_out, "SHIB");
+        assert_eq!(routed.amount_in, 5000.0);
+        assert_eq!(routed.amount_out, 248260656.0);
+        assert_eq!(routed.tx_position_in_block, 4);
+        assert_eq!(routed.gas_price, 1_000_000_000);
+
+        let direct = &swaps[1];
+        assert!(!direct.is_contract_caller);
+        assert_eq!(direct.token_in, "WETH");
+        assert_eq!(direct.token_out, "USDC");
+        assert_eq!(direct.amount_in, 1.0);
+        assert_eq!(direct.amount_out, 3198.0);
+        assert_eq!(
+            direct.tx_position_in_block, 1,
+            "Without a receipt, the index in the block's traces"
+        );
+        assert_eq!(direct.gas_price, 0);
+    }
+
+    #[test]
+    fn test_frame_logs_interleave_with_calls() {
+        let v2_data = format!("0x{}{}{}{}", word(5000), word(0), word(0), word(248260656));
+        let v3_data = format!("0x{}{}", "f".repeat(52) + "fffffffff382", word(1));
+        let log = |pool: &str, topic: &str, data: &str, position: &str| {
+            format!(
+                r#"{{"address":"{}","topics":["{}"],"data":"{}"{}}}"#,
+                pool, topic, data, position
+            )
+        };
+        // The V3 pool pays out, calls back into the router (which swaps on
+        // the V2 pool) and only then emits its own Swap
+        let callback = format!(
+            r#"{{"from":"0xpool_v3","to":"0xrouter","calls":[{{"from":"0xrouter","to":"0xpool_v2","logs":[{}]}}]}}"#,
+            log("0xpool_v2", UNISWAP_V2_SWAP_TOPIC, &v2_data, "")
+        );
+        let trace = |position: &str| {
+            format!(
+                r#"[{{"txHash":"0xroute","result":{{"from":"0xtrader","to":"0xrouter","calls":[
+                    {{"from":"0xrouter","to":"0xpool_v3","calls":[{}],"logs":[{}]}}
+                ]}}}}]"#,
+                callback,
+                log("0xpool_v3", UNISWAP_V3_SWAP_TOPIC, &v3_data, position)
+            )
+        };
+        let pools_in_order = |json: &str| -> Vec<String> {
+            let traced = parse_call_traces(json).expect("Failed to parse traces");
+            swaps_from_call_traces(12360, 1640995400, &traced, &[], &pools())
+                .into_iter()
+                .map(|swap| swap.pool_address)
+                .collect()
+        };
+
+        assert_eq!(
+            pools_in_order(&trace(r#","position":"0x1""#)),
+            vec!["0xpool_v2", "0xpool_v3"]
+        );
+        assert_eq!(
+            pools_in_order(&trace(r#","position":"0x0""#)),
+            vec!["0xpool_v3", "0xpool_v2"]
+        );
+        assert_eq!(
+            pools_in_order(&trace("")),
+            vec!["0xpool_v2", "0xpool_v3"],
+            "Without a position the frame's logs follow its calls"
+        );
+    }
+
+    #[test]
+    fn test_parity_traces_joined_with_receipt_logs() {
+        let traces = parse_parity_traces(
+            r#"[
+            {"action":{"from":"0xbot_eoa","to":"0xbot_contract"},"traceAddress":[],"transactionHash":"0xfront","transactionPosition":3,"type":"call"},
+            {"action":{"from":"0xbot_contract","to":"0xpool_v2"},"traceAddress":[0],"transactionHash":"0xfront","transactionPosition":3,"type":"call"}
+        ]"#,
+        )
+        .expect("Failed to parse traces");
+        let logs = parse_receipt_logs(&format!(
+            r#"[{{"address":"0xpool_v2","topics":["{}"],"data":"0x{}{}{}{}","transactionHash":"0xfront","logIndex":"0x1a"}}]"#,
+            UNISWAP_V2_SWAP_TOPIC,
+            word(0),
+            word(49950050),
+            word(1000),
+            word(0)
+        ))
+        .expect("Failed to parse logs");
+
+        let receipts = parse_receipts(
+            r#"[{"transactionHash":"0xfront","transactionIndex":"0x3","effectiveGasPrice":"0x77359400"}]"#,
+        )
+        .expect("Failed to parse receipts");
+
+        let swaps =
+            swaps_from_parity_traces(12360, 1640995400, &traces, &logs, &receipts, &pools());
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].gas_price, 2_000_000_000);
+        assert_eq!(swaps[0].from_address, "0xbot_eoa");
+        assert!(swaps[0].is_contract_caller);
+        assert_eq!(swaps[0].tx_position_in_block, 3);
+        assert_eq!(swaps[0].token_in, "SHIB");
+        assert_eq!(swaps[0].token_out, "USDC");
+    }
+}
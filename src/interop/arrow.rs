@@ -0,0 +1,257 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Float32Array, Float64Array, RecordBatch, StringArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use super::{Attacks, SimulatedAttacks};
+use crate::sandwich::same_block_heuristics::ConfidenceFlags;
+use crate::sandwich::transactions::SwapTransaction;
+
+/// Columns shared by both detectors, in the same order as the Polars frames.
+const LEG_FIELDS: [(&str, DataType); 9] = [
+    ("attack_id", DataType::Utf8),
+    ("chain_id", DataType::UInt64),
+    ("block_number", DataType::UInt64),
+    ("pool_address", DataType::Utf8),
+    ("attacker", DataType::Utf8),
+    ("victim", DataType::Utf8),
+    ("front_run_tx", DataType::Utf8),
+    ("victim_tx", DataType::Utf8),
+    ("back_run_tx", DataType::Utf8),
+];
+
+/// Schema of `RecordBatch::try_from(Attacks(..))`, e.g. to open a Parquet writer
+/// before the first batch is ready.
+pub fn attacks_schema() -> SchemaRef {
+    let mut fields = leg_fields();
+    fields.push(Field::new("confidence_score", DataType::Float32, false));
+    for flag in [
+        "higher_front_gas_price",
+        "lower_back_gas_price",
+        "front_is_contract",
+        "back_is_contract",
+        "is_profitable",
+        "is_proportional",
+    ] {
+        fields.push(Field::new(flag, DataType::Boolean, false));
+    }
+    fields.push(Field::new("price_impact_rate", DataType::Float32, false));
+    fields.push(Field::new("total_profit_usd", DataType::Float64, false));
+    for (name, data_type) in [
+        ("is_known_bot", DataType::Boolean),
+        ("likely_private_bundle", DataType::Boolean),
+        ("uses_flashloan", DataType::Boolean),
+        ("attacker_repeat_offender", DataType::Float32),
+        ("same_entity", DataType::Boolean),
+        ("swap_size_factor", DataType::Float32),
+        ("bot_activity_factor", DataType::Float32),
+        ("paid_builder", DataType::Boolean),
+        ("profitable_only_before_bribe", DataType::Boolean),
+        ("front_gas_zscore", DataType::Float32),
+        ("price_impact_depth_scale", DataType::Float32),
+        // Comma separated, as in the CSV report
+        ("custom_flags", DataType::Utf8),
+    ] {
+        fields.push(Field::new(name, data_type, false));
+    }
+    return Arc::new(Schema::new(fields));
+}
+
+/// Schema of `RecordBatch::try_from(SimulatedAttacks(..))`.
+pub fn simulated_attacks_schema() -> SchemaRef {
+    let mut fields = leg_fields();
+    fields.push(Field::new(
+        "victim_loss_percentage",
+        DataType::Float64,
+        false,
+    ));
+    return Arc::new(Schema::new(fields));
+}
+
+impl TryFrom<Attacks<'_>> for RecordBatch {
+    type Error = String;
+
+    fn try_from(attacks: Attacks<'_>) -> Result<Self, Self::Error> {
+        let attacks = attacks.0;
+        let flag = |value: fn(&ConfidenceFlags) -> bool| -> ArrayRef {
+            Arc::new(BooleanArray::from(
+                attacks
+                    .iter()
+                    .map(|attack| value(&attack.confidence_flags))
+                    .collect::<Vec<_>>(),
+            ))
+        };
+        let factor = |value: fn(&ConfidenceFlags) -> f32| -> ArrayRef {
+            Arc::new(Float32Array::from_iter_values(
+                attacks.iter().map(|attack| value(&attack.confidence_flags)),
+            ))
+        };
+
+        let mut columns = leg_columns(attacks.iter().map(|attack| {
+            (
+                attack.attack_id(),
+                attack.chain_id,
+                &attack.front_run_tx,
+                &attack.victim_tx,
+                &attack.back_run_tx,
+            )
+        }));
+        columns.extend([
+            Arc::new(Float32Array::from_iter_values(
+                attacks.iter().map(|attack| attack.confidence_score),
+            )) as ArrayRef,
+            flag(|flags| flags.higher_front_gas_price),
+            flag(|flags| flags.lower_back_gas_price),
+            flag(|flags| flags.front_is_contract),
+            flag(|flags| flags.back_is_contract),
+            flag(|flags| flags.is_profitable),
+            flag(|flags| flags.is_proportional),
+            factor(|flags| flags.price_impact_rate),
+            Arc::new(Float64Array::from_iter_values(
+                attacks
+                    .iter()
+                    .map(|attack| attack.confidence_flags.total_profit_usd),
+            )),
+            flag(|flags| flags.is_known_bot),
+            flag(|flags| flags.likely_private_bundle),
+            flag(|flags| flags.uses_flashloan),
+            factor(|flags| flags.attacker_repeat_offender),
+            flag(|flags| flags.same_entity),
+            factor(|flags| flags.swap_size_factor),
+            factor(|flags| flags.bot_activity_factor),
+            flag(|flags| flags.paid_builder),
+            flag(|flags| flags.profitable_only_before_bribe),
+            factor(|flags| flags.front_gas_zscore),
+            factor(|flags| flags.price_impact_depth_scale),
+            Arc::new(StringArray::from(
+                attacks
+                    .iter()
+                    .map(|attack| attack.confidence_flags.custom_flags.join(","))
+                    .collect::<Vec<_>>(),
+            )),
+        ]);
+
+        RecordBatch::try_new(attacks_schema(), columns)
+            .map_err(|err| format!("failed to build attacks batch: {}", err))
+    }
+}
+
+impl TryFrom<SimulatedAttacks<'_>> for RecordBatch {
+    type Error = String;
+
+    fn try_from(attacks: SimulatedAttacks<'_>) -> Result<Self, Self::Error> {
+        let attacks = attacks.0;
+
+        let mut columns = leg_columns(attacks.iter().map(|attack| {
+            (
+                attack.attack_id(),
+                attack.chain_id,
+                &attack.front_run_tx,
+                &attack.victim_tx,
+                &attack.back_run_tx,
+            )
+        }));
+        columns.push(Arc::new(Float64Array::from_iter_values(
+            attacks.iter().map(|attack| attack.victim_loss_percentage),
+        )));
+
+        RecordBatch::try_new(simulated_attacks_schema(), columns)
+            .map_err(|err| format!("failed to build attacks batch: {}", err))
+    }
+}
+
+fn leg_fields() -> Vec<Field> {
+    return LEG_FIELDS
+        .iter()
+        .map(|(name, data_type)| Field::new(*name, data_type.clone(), false))
+        .collect();
+}
+
+fn leg_columns<'a, I>(attacks: I) -> Vec<ArrayRef>
+where
+    I: Iterator<
+        Item = (
+            String,
+            u64,
+            &'a SwapTransaction,
+            &'a SwapTransaction,
+            &'a SwapTransaction,
+        ),
+    >,
+{
+    let mut attack_ids = Vec::new();
+    let mut chain_ids = Vec::new();
+    let mut block_numbers = Vec::new();
+    let mut pool_addresses = Vec::new();
+    let mut attackers = Vec::new();
+    let mut victims = Vec::new();
+    let mut front_run_txs = Vec::new();
+    let mut victim_txs = Vec::new();
+    let mut back_run_txs = Vec::new();
+
+    for (attack_id, chain_id, front, victim, back) in attacks {
+        attack_ids.push(attack_id);
+        chain_ids.push(chain_id);
+        block_numbers.push(victim.block_number);
+        pool_addresses.push(victim.pool_address.as_str());
+        attackers.push(front.from_address.as_str());
+        victims.push(victim.from_address.as_str());
+        front_run_txs.push(front.tx_hash.as_str());
+        victim_txs.push(victim.tx_hash.as_str());
+        back_run_txs.push(back.tx_hash.as_str());
+    }
+
+    return vec![
+        Arc::new(StringArray::from(attack_ids)),
+        Arc::new(UInt64Array::from(chain_ids)),
+        Arc::new(UInt64Array::from(block_numbers)),
+        Arc::new(StringArray::from(pool_addresses)),
+        Arc::new(StringArray::from(attackers)),
+        Arc::new(StringArray::from(victims)),
+        Arc::new(StringArray::from(front_run_txs)),
+        Arc::new(StringArray::from(victim_txs)),
+        Arc::new(StringArray::from(back_run_txs)),
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+    use arrow_array::Array;
+
+    #[test]
+    fn test_attacks_to_record_batch() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = find_same_block_sandwiches(&transactions);
+
+        let batch = RecordBatch::try_from(Attacks(&attacks)).expect("Failed to build batch");
+        assert_eq!(batch.num_rows(), attacks.len());
+        assert_eq!(batch.schema(), attacks_schema());
+
+        let attack_ids = batch
+            .column_by_name("attack_id")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(attack_ids.value(0), attacks[0].attack_id());
+        let profits = batch
+            .column_by_name("total_profit_usd")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(
+            profits.value(0),
+            attacks[0].confidence_flags.total_profit_usd
+        );
+        assert_eq!(profits.null_count(), 0);
+
+        let empty = RecordBatch::try_from(SimulatedAttacks(&[])).unwrap();
+        assert_eq!(empty.num_rows(), 0);
+        assert_eq!(empty.num_columns(), LEG_FIELDS.len() + 1);
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use serde::Serialize;
+
+use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+use crate::sandwich::same_block_sim::{find_sandwich_attacks_by_simulation, Pool};
+use crate::sandwich::transactions::SwapTransaction;
+
+/// Opaque handle for embedding the detector in other runtimes over C.
+///
+/// Swaps go in as a JSON array of `SwapTransaction`s and attacks come back as
+/// a JSON array that must be released with `tf_string_free`. Failed calls
+/// return NULL (or -1) and `tf_detector_last_error` describes what went wrong.
+/// The C header is checked in as `include/toxicflow.h`, copy it from the
+/// `toxicflow.h` the build script generates into `OUT_DIR` after changing
+/// the functions.
+pub struct TfDetector {
+    pools: HashMap<String, Pool>,
+    last_error: Option<CString>,
+}
+
+impl TfDetector {
+    fn run<T>(&mut self, call: impl FnOnce(&mut Self) -> Result<T, String>) -> Option<T> {
+        let result = match catch_unwind(AssertUnwindSafe(|| call(self))) {
+            Ok(result) => result,
+            Err(_) => Err("detector panicked".to_string()),
+        };
+        match result {
+            Ok(value) => {
+                self.last_error = None;
+                return Some(value);
+            }
+            Err(err) => {
+                self.last_error = CString::new(err.replace('\0', " ")).ok();
+                return None;
+            }
+        }
+    }
+}
+
+/// Create a detector. Release it with `tf_detector_free`.
+#[no_mangle]
+pub extern "C" fn tf_detector_new() -> *mut TfDetector {
+    let detector = TfDetector {
+        pools: HashMap::new(),
+        last_error: None,
+    };
+    return Box::into_raw(Box::new(detector));
+}
+
+/// # Safety
+/// `detector` must come from `tf_detector_new` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tf_detector_free(detector: *mut TfDetector) {
+    if !detector.is_null() {
+        drop(Box::from_raw(detector));
+    }
+}
+
+/// Replace the pools used by `tf_detect_by_simulation` with a JSON object
+/// keyed by pool address. Returns 0 on success.
+///
+/// # Safety
+/// `detector` must be a live handle and `pools_json` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tf_detector_set_pools(
+    detector: *mut TfDetector,
+    pools_json: *const c_char,
+) -> c_int {
+    let detector = match detector.as_mut() {
+        Some(detector) => detector,
+        None => return -1,
+    };
+    let set = detector.run(|detector| {
+        detector.pools = serde_json::from_str(read_str(pools_json)?)
+            .map_err(|err| format!("invalid pools JSON: {}", err))?;
+        return Ok(());
+    });
+    return match set {
+        Some(()) => 0,
+        None => -1,
+    };
+}
+
+/// Heuristic detection over a JSON array of swaps.
+///
+/// # Safety
+/// `detector` must be a live handle and `swaps_json` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tf_detect_by_heuristics(
+    detector: *mut TfDetector,
+    swaps_json: *const c_char,
+) -> *mut c_char {
+    let detector = match detector.as_mut() {
+        Some(detector) => detector,
+        None => return ptr::null_mut(),
+    };
+    let attacks = detector.run(|_| {
+        let swaps = read_swaps(swaps_json)?;
+        return to_json(&find_same_block_sandwiches(&swaps));
+    });
+    return attacks.map_or(ptr::null_mut(), CString::into_raw);
+}
+
+/// Simulation-based detection over a JSON array of swaps, against the pools
+/// set with `tf_detector_set_pools`.
+///
+/// # Safety
+/// `detector` must be a live handle and `swaps_json` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tf_detect_by_simulation(
+    detector: *mut TfDetector,
+    swaps_json: *const c_char,
+) -> *mut c_char {
+    let detector = match detector.as_mut() {
+        Some(detector) => detector,
+        None => return ptr::null_mut(),
+    };
+    let attacks = detector.run(|detector| {
+        let swaps = read_swaps(swaps_json)?;
+        return to_json(&find_sandwich_attacks_by_simulation(
+            &detector.pools,
+            &swaps,
+        ));
+    });
+    return attacks.map_or(ptr::null_mut(), CString::into_raw);
+}
+
+/// The error of the last failed call, or NULL. Owned by the detector and
+/// valid until its next call.
+///
+/// # Safety
+/// `detector` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn tf_detector_last_error(detector: *const TfDetector) -> *const c_char {
+    return match detector.as_ref().and_then(|d| d.last_error.as_ref()) {
+        Some(err) => err.as_ptr(),
+        None => ptr::null(),
+    };
+}
+
+/// # Safety
+/// `string` must have been returned by this library and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn tf_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+unsafe fn read_str<'a>(string: *const c_char) -> Result<&'a str, String> {
+    if string.is_null() {
+        return Err("unexpected NULL string".to_string());
+    }
+    return CStr::from_ptr(string)
+        .to_str()
+        .map_err(|err| format!("string is not UTF-8: {}", err));
+}
+
+unsafe fn read_swaps(swaps_json: *const c_char) -> Result<Vec<SwapTransaction>, String> {
+    return serde_json::from_str(read_str(swaps_json)?)
+        .map_err(|err| format!("invalid swaps JSON: {}", err));
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<CString, String> {
+    let json =
+        serde_json::to_string(value).map_err(|err| format!("failed to encode JSON: {}", err))?;
+    return CString::new(json).map_err(|err| format!("failed to encode JSON: {}", err));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_in_header_is_up_to_date() {
+        assert_eq!(
+            include_str!("../../include/toxicflow.h"),
+            include_str!(concat!(env!("OUT_DIR"), "/toxicflow.h")),
+            "include/toxicflow.h is stale, copy it from OUT_DIR"
+        );
+    }
+
+    #[test]
+    fn test_detect_over_json_handles() {
+        let swaps = crate::ingest::csv::sample_transactions();
+        let swaps_json = CString::new(serde_json::to_string(&swaps).unwrap()).unwrap();
+
+        unsafe {
+            let detector = tf_detector_new();
+
+            let attacks = tf_detect_by_heuristics(detector, swaps_json.as_ptr());
+            assert!(!attacks.is_null());
+            let json = CStr::from_ptr(attacks).to_str().unwrap();
+            let parsed: Vec<serde_json::Value> = serde_json::from_str(json).unwrap();
+            assert_eq!(parsed.len(), find_same_block_sandwiches(&swaps).len());
+            tf_string_free(attacks);
+            assert!(tf_detector_last_error(detector).is_null());
+
+            let invalid = CString::new("{\"0xpool1\": 3}").unwrap();
+            assert_eq!(tf_detector_set_pools(detector, invalid.as_ptr()), -1);
+            let err = CStr::from_ptr(tf_detector_last_error(detector));
+            assert!(err.to_str().unwrap().starts_with("invalid pools JSON"));
+
+            tf_detector_free(detector);
+        }
+    }
+}
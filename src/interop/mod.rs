@@ -0,0 +1,33 @@
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+use crate::sandwich::same_block_sim::SandwichAttackBySimulation;
+use crate::sandwich::transactions::SwapTransaction;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+/// C bindings. The manifest only builds an rlib, build the shared library with
+/// `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "polars")]
+pub mod polars;
+/// JavaScript bindings. Build the module with `cargo rustc --lib --release
+/// --target wasm32-unknown-unknown --features wasm --crate-type cdylib` and
+/// pass the `.wasm` to `wasm-bindgen`.
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// Swaps going in and out of a `DataFrame` with the `SWAP_COLUMNS`.
+///
+/// Reading casts columns to the expected types (so e.g. `i64` block numbers
+/// are accepted), ignores extra columns and assumes mainnet when there is no
+/// `chain_id` column.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Swaps(pub Vec<SwapTransaction>);
+
+/// Heuristic detections as a columnar table, one row per attack with its confidence flags.
+#[derive(Debug, Clone, Copy)]
+pub struct Attacks<'a>(pub &'a [SandwichAttackByHeuristics]);
+
+/// Simulation detections as a columnar table, one row per attack with the victim loss.
+#[derive(Debug, Clone, Copy)]
+pub struct SimulatedAttacks<'a>(pub &'a [SandwichAttackBySimulation]);
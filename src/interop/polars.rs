@@ -0,0 +1,490 @@
+use polars::prelude::{Column, DataFrame, DataType};
+
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+use crate::sandwich::transactions::{SwapTransaction, ETHEREUM_CHAIN_ID};
+
+pub use super::{Attacks, SimulatedAttacks, Swaps};
+
+impl TryFrom<&DataFrame> for Swaps {
+    type Error = String;
+
+    fn try_from(df: &DataFrame) -> Result<Self, Self::Error> {
+        let chain_ids = match df.column("chain_id") {
+            Ok(_) => u64_values(df, "chain_id")?,
+            Err(_) => vec![ETHEREUM_CHAIN_ID; df.height()],
+        };
+        let tx_hashes = string_values(df, "tx_hash")?;
+        let block_numbers = u64_values(df, "block_number")?;
+        let timestamps = u64_values(df, "timestamp")?;
+        let positions = u64_values(df, "tx_position_in_block")?;
+        let from_addresses = string_values(df, "from_address")?;
+        let tokens_in = string_values(df, "token_in")?;
+        let tokens_out = string_values(df, "token_out")?;
+        let amounts_in = f64_values(df, "amount_in")?;
+        let amounts_out = f64_values(df, "amount_out")?;
+        let gas_prices = u64_values(df, "gas_price")?;
+        let pool_addresses = string_values(df, "pool_address")?;
+        let launch_blocks = u64_values(df, "token_launch_block")?;
+        let contract_callers = bool_values(df, "is_contract_caller")?;
+        let usd_values_in = f64_values(df, "usd_value_in")?;
+        let usd_values_out = f64_values(df, "usd_value_out")?;
+        let gas_costs = f64_values(df, "gas_cost_usd")?;
+        let max_fees = optional_u64_values(df, "max_fee_per_gas")?;
+        let max_priority_fees = optional_u64_values(df, "max_priority_fee_per_gas")?;
+        let base_fees = optional_u64_values(df, "base_fee_per_gas")?;
+
+        let mut swaps = Vec::with_capacity(df.height());
+        for row in 0..df.height() {
+            swaps.push(SwapTransaction {
+                tx_hash: tx_hashes[row].clone(),
+                chain_id: chain_ids[row],
+                block_number: block_numbers[row],
+                timestamp: timestamps[row],
+                tx_position_in_block: u32::try_from(positions[row]).map_err(|_| {
+                    format!(
+                        "tx_position_in_block out of range in row {}: {}",
+                        row, positions[row]
+                    )
+                })?,
+                from_address: from_addresses[row].clone(),
+                token_in: tokens_in[row].clone(),
+                token_out: tokens_out[row].clone(),
+                amount_in: amounts_in[row],
+                amount_out: amounts_out[row],
+                gas_price: gas_prices[row],
+                pool_address: pool_addresses[row].clone(),
+                token_launch_block: launch_blocks[row],
+                is_contract_caller: contract_callers[row],
+                usd_value_in: usd_values_in[row],
+                usd_value_out: usd_values_out[row],
+                gas_cost_usd: gas_costs[row],
+                max_fee_per_gas: max_fees[row],
+                max_priority_fee_per_gas: max_priority_fees[row],
+                base_fee_per_gas: base_fees[row],
+                raw_amount_in: None,
+            });
+        }
+
+        return Ok(Swaps(swaps));
+    }
+}
+
+impl TryFrom<DataFrame> for Swaps {
+    type Error = String;
+
+    fn try_from(df: DataFrame) -> Result<Self, Self::Error> {
+        Swaps::try_from(&df)
+    }
+}
+
+impl TryFrom<&Swaps> for DataFrame {
+    type Error = String;
+
+    fn try_from(swaps: &Swaps) -> Result<Self, Self::Error> {
+        let swaps = &swaps.0;
+        let columns = vec![
+            string_column("tx_hash", swaps, |tx| &tx.tx_hash),
+            Column::new(
+                "chain_id".into(),
+                swaps.iter().map(|tx| tx.chain_id).collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "block_number".into(),
+                swaps.iter().map(|tx| tx.block_number).collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "timestamp".into(),
+                swaps.iter().map(|tx| tx.timestamp).collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "tx_position_in_block".into(),
+                swaps
+                    .iter()
+                    .map(|tx| tx.tx_position_in_block)
+                    .collect::<Vec<_>>(),
+            ),
+            string_column("from_address", swaps, |tx| &tx.from_address),
+            string_column("token_in", swaps, |tx| &tx.token_in),
+            string_column("token_out", swaps, |tx| &tx.token_out),
+            Column::new(
+                "amount_in".into(),
+                swaps.iter().map(|tx| tx.amount_in).collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "amount_out".into(),
+                swaps.iter().map(|tx| tx.amount_out).collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "gas_price".into(),
+                swaps.iter().map(|tx| tx.gas_price).collect::<Vec<_>>(),
+            ),
+            string_column("pool_address", swaps, |tx| &tx.pool_address),
+            Column::new(
+                "token_launch_block".into(),
+                swaps
+                    .iter()
+                    .map(|tx| tx.token_launch_block)
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "is_contract_caller".into(),
+                swaps
+                    .iter()
+                    .map(|tx| tx.is_contract_caller)
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "usd_value_in".into(),
+                swaps.iter().map(|tx| tx.usd_value_in).collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "usd_value_out".into(),
+                swaps.iter().map(|tx| tx.usd_value_out).collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "gas_cost_usd".into(),
+                swaps.iter().map(|tx| tx.gas_cost_usd).collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "max_fee_per_gas".into(),
+                swaps
+                    .iter()
+                    .map(|tx| tx.max_fee_per_gas)
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "max_priority_fee_per_gas".into(),
+                swaps
+                    .iter()
+                    .map(|tx| tx.max_priority_fee_per_gas)
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "base_fee_per_gas".into(),
+                swaps
+                    .iter()
+                    .map(|tx| tx.base_fee_per_gas)
+                    .collect::<Vec<_>>(),
+            ),
+        ];
+
+        DataFrame::new(columns).map_err(|err| format!("failed to build swaps frame: {}", err))
+    }
+}
+
+impl TryFrom<Swaps> for DataFrame {
+    type Error = String;
+
+    fn try_from(swaps: Swaps) -> Result<Self, Self::Error> {
+        DataFrame::try_from(&swaps)
+    }
+}
+
+impl TryFrom<Attacks<'_>> for DataFrame {
+    type Error = String;
+
+    fn try_from(attacks: Attacks<'_>) -> Result<Self, Self::Error> {
+        let attacks = attacks.0;
+        let flag = |name: &str, value: fn(&SandwichAttackByHeuristics) -> bool| {
+            Column::new(name.into(), attacks.iter().map(value).collect::<Vec<_>>())
+        };
+        let factor = |name: &str, value: fn(&SandwichAttackByHeuristics) -> f32| {
+            Column::new(name.into(), attacks.iter().map(value).collect::<Vec<_>>())
+        };
+
+        let mut columns = attack_leg_columns(attacks.iter().map(|attack| {
+            (
+                attack.attack_id(),
+                attack.chain_id,
+                &attack.front_run_tx,
+                &attack.victim_tx,
+                &attack.back_run_tx,
+            )
+        }));
+        columns.extend([
+            Column::new(
+                "confidence_score".into(),
+                attacks
+                    .iter()
+                    .map(|attack| attack.confidence_score)
+                    .collect::<Vec<_>>(),
+            ),
+            flag("higher_front_gas_price", |attack| {
+                attack.confidence_flags.higher_front_gas_price
+            }),
+            flag("lower_back_gas_price", |attack| {
+                attack.confidence_flags.lower_back_gas_price
+            }),
+            flag("front_is_contract", |attack| {
+                attack.confidence_flags.front_is_contract
+            }),
+            flag("back_is_contract", |attack| {
+                attack.confidence_flags.back_is_contract
+            }),
+            flag("is_profitable", |attack| {
+                attack.confidence_flags.is_profitable
+            }),
+            flag("is_proportional", |attack| {
+                attack.confidence_flags.is_proportional
+            }),
+            factor("price_impact_rate", |attack| {
+                attack.confidence_flags.price_impact_rate
+            }),
+            Column::new(
+                "total_profit_usd".into(),
+                attacks
+                    .iter()
+                    .map(|attack| attack.confidence_flags.total_profit_usd)
+                    .collect::<Vec<_>>(),
+            ),
+            flag("is_known_bot", |attack| {
+                attack.confidence_flags.is_known_bot
+            }),
+            flag("likely_private_bundle", |attack| {
+                attack.confidence_flags.likely_private_bundle
+            }),
+            flag("uses_flashloan", |attack| {
+                attack.confidence_flags.uses_flashloan
+            }),
+            factor("attacker_repeat_offender", |attack| {
+                attack.confidence_flags.attacker_repeat_offender
+            }),
+            flag("same_entity", |attack| attack.confidence_flags.same_entity),
+            factor("swap_size_factor", |attack| {
+                attack.confidence_flags.swap_size_factor
+            }),
+            factor("bot_activity_factor", |attack| {
+                attack.confidence_flags.bot_activity_factor
+            }),
+            flag("paid_builder", |attack| {
+                attack.confidence_flags.paid_builder
+            }),
+            flag("profitable_only_before_bribe", |attack| {
+                attack.confidence_flags.profitable_only_before_bribe
+            }),
+            factor("front_gas_zscore", |attack| {
+                attack.confidence_flags.front_gas_zscore
+            }),
+            factor("price_impact_depth_scale", |attack| {
+                attack.confidence_flags.price_impact_depth_scale
+            }),
+            // Comma separated, as in the CSV report
+            Column::new(
+                "custom_flags".into(),
+                attacks
+                    .iter()
+                    .map(|attack| attack.confidence_flags.custom_flags.join(","))
+                    .collect::<Vec<_>>(),
+            ),
+        ]);
+
+        DataFrame::new(columns).map_err(|err| format!("failed to build attacks frame: {}", err))
+    }
+}
+
+impl TryFrom<SimulatedAttacks<'_>> for DataFrame {
+    type Error = String;
+
+    fn try_from(attacks: SimulatedAttacks<'_>) -> Result<Self, Self::Error> {
+        let attacks = attacks.0;
+
+        let mut columns = attack_leg_columns(attacks.iter().map(|attack| {
+            (
+                attack.attack_id(),
+                attack.chain_id,
+                &attack.front_run_tx,
+                &attack.victim_tx,
+                &attack.back_run_tx,
+            )
+        }));
+        columns.push(Column::new(
+            "victim_loss_percentage".into(),
+            attacks
+                .iter()
+                .map(|attack| attack.victim_loss_percentage)
+                .collect::<Vec<_>>(),
+        ));
+
+        DataFrame::new(columns).map_err(|err| format!("failed to build attacks frame: {}", err))
+    }
+}
+
+/// Columns shared by both detectors: the attack ID, where it happened and its legs.
+fn attack_leg_columns<'a, I>(attacks: I) -> Vec<Column>
+where
+    I: Iterator<
+        Item = (
+            String,
+            u64,
+            &'a SwapTransaction,
+            &'a SwapTransaction,
+            &'a SwapTransaction,
+        ),
+    >,
+{
+    let mut attack_ids = Vec::new();
+    let mut chain_ids = Vec::new();
+    let mut block_numbers = Vec::new();
+    let mut pool_addresses = Vec::new();
+    let mut attackers = Vec::new();
+    let mut victims = Vec::new();
+    let mut front_run_txs = Vec::new();
+    let mut victim_txs = Vec::new();
+    let mut back_run_txs = Vec::new();
+
+    for (attack_id, chain_id, front, victim, back) in attacks {
+        attack_ids.push(attack_id);
+        chain_ids.push(chain_id);
+        block_numbers.push(victim.block_number);
+        pool_addresses.push(victim.pool_address.clone());
+        attackers.push(front.from_address.clone());
+        victims.push(victim.from_address.clone());
+        front_run_txs.push(front.tx_hash.clone());
+        victim_txs.push(victim.tx_hash.clone());
+        back_run_txs.push(back.tx_hash.clone());
+    }
+
+    vec![
+        Column::new("attack_id".into(), attack_ids),
+        Column::new("chain_id".into(), chain_ids),
+        Column::new("block_number".into(), block_numbers),
+        Column::new("pool_address".into(), pool_addresses),
+        Column::new("attacker".into(), attackers),
+        Column::new("victim".into(), victims),
+        Column::new("front_run_tx".into(), front_run_txs),
+        Column::new("victim_tx".into(), victim_txs),
+        Column::new("back_run_tx".into(), back_run_txs),
+    ]
+}
+
+fn string_column<F>(name: &str, swaps: &[SwapTransaction], value: F) -> Column
+where
+    F: Fn(&SwapTransaction) -> &String,
+{
+    Column::new(
+        name.into(),
+        swaps
+            .iter()
+            .map(|tx| value(tx).as_str())
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn cast_column(df: &DataFrame, name: &str, dtype: &DataType) -> Result<Column, String> {
+    df.column(name)
+        .map_err(|_| format!("missing column {}", name))?
+        .cast(dtype)
+        .map_err(|err| format!("column {} is not {}: {}", name, dtype, err))
+}
+
+fn null_error(name: &str, row: usize) -> String {
+    format!("null {} in row {}", name, row)
+}
+
+fn u64_values(df: &DataFrame, name: &str) -> Result<Vec<u64>, String> {
+    let column = cast_column(df, name, &DataType::UInt64)?;
+    let values = column.u64().map_err(|err| err.to_string())?;
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(row, value)| value.ok_or_else(|| null_error(name, row)))
+        .collect()
+}
+
+/// All `None` when the frame has no such column.
+fn optional_u64_values(df: &DataFrame, name: &str) -> Result<Vec<Option<u64>>, String> {
+    if df.column(name).is_err() {
+        return Ok(vec![None; df.height()]);
+    }
+    let column = cast_column(df, name, &DataType::UInt64)?;
+    let values = column.u64().map_err(|err| err.to_string())?;
+    return Ok(values.into_iter().collect());
+}
+
+fn f64_values(df: &DataFrame, name: &str) -> Result<Vec<f64>, String> {
+    let column = cast_column(df, name, &DataType::Float64)?;
+    let values = column.f64().map_err(|err| err.to_string())?;
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(row, value)| value.ok_or_else(|| null_error(name, row)))
+        .collect()
+}
+
+fn bool_values(df: &DataFrame, name: &str) -> Result<Vec<bool>, String> {
+    let column = cast_column(df, name, &DataType::Boolean)?;
+    let values = column.bool().map_err(|err| err.to_string())?;
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(row, value)| value.ok_or_else(|| null_error(name, row)))
+        .collect()
+}
+
+fn string_values(df: &DataFrame, name: &str) -> Result<Vec<String>, String> {
+    let column = cast_column(df, name, &DataType::String)?;
+    let values = column.str().map_err(|err| err.to_string())?;
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(row, value)| {
+            value
+                .map(|value| value.to_string())
+                .ok_or_else(|| null_error(name, row))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::csv::sample_transactions;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+
+    #[test]
+    fn test_swaps_round_trip_through_dataframe() {
+        let swaps = Swaps(sample_transactions());
+        let df = DataFrame::try_from(&swaps).expect("Failed to build frame");
+        assert_eq!(df.height(), swaps.0.len());
+
+        // Extra columns are ignored, other integer types are cast
+        let mut df = df;
+        df.with_column(Column::new("note".into(), vec!["x"; swaps.0.len()]))
+            .unwrap();
+        let block_numbers = df
+            .column("block_number")
+            .unwrap()
+            .cast(&DataType::Int64)
+            .unwrap();
+        df.with_column(block_numbers).unwrap();
+
+        let round_tripped = Swaps::try_from(df).expect("Failed to read frame");
+        assert_eq!(round_tripped, swaps);
+
+        let missing = DataFrame::new(vec![Column::new("tx_hash".into(), vec!["0xa"])]).unwrap();
+        assert!(Swaps::try_from(missing)
+            .unwrap_err()
+            .contains("block_number"));
+    }
+
+    #[test]
+    fn test_attacks_to_dataframe() {
+        let attacks = find_same_block_sandwiches(&sample_transactions());
+        let df = DataFrame::try_from(Attacks(&attacks)).expect("Failed to build frame");
+
+        assert_eq!(df.height(), attacks.len());
+        let ids = df.column("attack_id").unwrap().str().unwrap();
+        assert_eq!(ids.get(0), Some(attacks[0].attack_id().as_str()));
+        assert!(df.column("confidence_score").is_ok());
+        let scales = df
+            .column("price_impact_depth_scale")
+            .unwrap()
+            .f32()
+            .unwrap();
+        assert_eq!(
+            scales.get(0),
+            Some(attacks[0].confidence_flags.price_impact_depth_scale)
+        );
+        assert!(df.column("custom_flags").is_ok());
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::sandwich::same_block_heuristics::{
+    find_same_block_sandwiches, SandwichAttackByHeuristics,
+};
+use crate::sandwich::same_block_sim::{
+    find_sandwich_attacks_by_simulation, Pool, SandwichAttackBySimulation,
+};
+use crate::sandwich::transactions::SwapTransaction;
+
+/// Classify sandwiches in already-fetched swaps using the heuristics.
+///
+/// `swaps` is an array of plain objects with the `SwapTransaction` fields
+/// (snake_case, `chain_id` optional); attacks come back as plain objects.
+#[wasm_bindgen(js_name = detectSandwiches)]
+pub fn detect_sandwiches(swaps: JsValue) -> Result<JsValue, JsError> {
+    let swaps: Vec<SwapTransaction> = serde_wasm_bindgen::from_value(swaps)?;
+    return to_js(&detect(&swaps));
+}
+
+/// Classify sandwiches by simulating swaps against pool reserves.
+///
+/// `pools` is an object keyed by pool address holding the `Pool` fields.
+#[wasm_bindgen(js_name = simulateSandwiches)]
+pub fn simulate_sandwiches(pools: JsValue, swaps: JsValue) -> Result<JsValue, JsError> {
+    let pools: HashMap<String, Pool> = serde_wasm_bindgen::from_value(pools)?;
+    let swaps: Vec<SwapTransaction> = serde_wasm_bindgen::from_value(swaps)?;
+    let attacks = simulate(&pools, &swaps).map_err(|err| JsError::new(&err))?;
+    return to_js(&attacks);
+}
+
+fn detect(swaps: &[SwapTransaction]) -> Vec<SandwichAttackByHeuristics> {
+    return find_same_block_sandwiches(swaps);
+}
+
+/// Pools are keyed by address only, so a single call can't mix chains.
+fn simulate(
+    pools: &HashMap<String, Pool>,
+    swaps: &[SwapTransaction],
+) -> Result<Vec<SandwichAttackBySimulation>, String> {
+    if let Some(first) = swaps.first() {
+        if let Some(other) = swaps.iter().find(|tx| tx.chain_id != first.chain_id) {
+            return Err(format!(
+                "swaps span chains {} and {}, simulate one chain at a time",
+                first.chain_id, other.chain_id
+            ));
+        }
+    }
+    return Ok(find_sandwich_attacks_by_simulation(pools, swaps));
+}
+
+/// Plain objects rather than `Map`s, and numbers rather than `BigInt`s.
+fn to_js<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsError> {
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    return Ok(value.serialize(&serializer)?);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulate_rejects_mixed_chains() {
+        let swaps = crate::ingest::csv::sample_transactions();
+        let pools = HashMap::from([(
+            "0xpool1".to_string(),
+            Pool::new(1_000.0, 3_000_000.0, "ETH".to_string(), "USDC".to_string()),
+        )]);
+        assert!(simulate(&pools, &swaps).is_ok());
+        assert!(!detect(&swaps).is_empty());
+
+        let mut mixed = swaps.clone();
+        mixed[0].chain_id = 137;
+        let err = simulate(&pools, &mixed).unwrap_err();
+        assert!(err.contains("span chains 137 and 1"), "{}", err);
+    }
+}
@@ -0,0 +1,32 @@
+// The codebase deliberately uses explicit `return` statements.
+#![allow(clippy::needless_return)]
+
+pub mod alerts;
+pub mod config;
+pub mod enrich;
+pub mod ingest;
+pub mod interop;
+pub mod metrics;
+pub mod mev;
+/// The types most detection code needs, independent of the module layout:
+///
+/// ```
+/// use toxicflow_detector::prelude::*;
+///
+/// fn confident(transactions: &[SwapTransaction]) -> AttackSet<SandwichAttackByHeuristics> {
+///     let detector = SandwichDetector::builder().min_confidence(0.7).build();
+///     return AttackSet::from(detector.find_sandwiches(transactions).attacks);
+/// }
+///
+/// assert!(confident(&[]).is_empty());
+/// ```
+pub mod prelude;
+pub mod report;
+pub mod sandwich;
+pub mod service;
+pub mod storage;
+pub mod stream;
+pub mod telemetry;
+/// Fixtures shared between the tests of different modules.
+#[cfg(test)]
+mod test_support;
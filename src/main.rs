@@ -1,5 +1,3 @@
-mod sandwich;
-
 fn main() {
     println!("Hello from Toxic Flow Detector!");
 }
@@ -7,6 +5,7 @@ fn main() {
 #[cfg(test)]
 mod tests {
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn test_app_starts() {
         assert!(true);
     }
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::sandwich::same_block_sim::SandwichAttackBySimulation;
+use crate::sandwich::transactions::BlockId;
+
+#[cfg(feature = "metrics")]
+pub mod server;
+
+#[cfg(feature = "metrics")]
+pub use server::serve;
+
+/// Upper bounds (in seconds) of the detection latency histogram buckets.
+const LATENCY_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// Counters and gauges of a running detector, rendered in the Prometheus
+/// text format. Share it as an `Arc` between the detector and `serve`.
+#[derive(Debug, Default)]
+pub struct DetectorMetrics {
+    attacks_detected: AtomicU64,
+    /// `f64` bits, see `add_f64`.
+    attacker_profit_usd: AtomicU64,
+    /// `f64` bits, see `add_f64`.
+    victim_loss_usd: AtomicU64,
+    blocks_processed: AtomicU64,
+    /// Highest block number processed, per chain id.
+    last_block_numbers: Mutex<BTreeMap<u64, u64>>,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_count: AtomicU64,
+    /// `f64` bits, see `add_f64`.
+    latency_sum_seconds: AtomicU64,
+}
+
+impl DetectorMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one block run through detection.
+    ///
+    /// `attacker_profit_usd` is the attackers' estimated net profit over the
+    /// block's attacks, negative when they lost money.
+    pub fn observe_block(
+        &self,
+        block: BlockId,
+        latency: Duration,
+        attacks: usize,
+        attacker_profit_usd: f64,
+    ) {
+        self.blocks_processed.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut last_block_numbers) = self.last_block_numbers.lock() {
+            let last = last_block_numbers.entry(block.chain_id).or_insert(0);
+            *last = (*last).max(block.block_number);
+        }
+        self.attacks_detected
+            .fetch_add(attacks as u64, Ordering::Relaxed);
+        add_f64(&self.attacker_profit_usd, attacker_profit_usd);
+
+        let seconds = latency.as_secs_f64();
+        for (bucket, upper_bound) in self.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+            if seconds <= *upper_bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+        add_f64(&self.latency_sum_seconds, seconds);
+    }
+
+    /// Add the simulated victim losses of a block's attacks. Heuristic
+    /// detection doesn't measure the loss, so only simulated attacks count.
+    pub fn observe_victim_losses(&self, attacks: &[SandwichAttackBySimulation]) {
+        add_f64(
+            &self.victim_loss_usd,
+            attacks.iter().map(|attack| attack.victim_loss_usd()).sum(),
+        );
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        output.push_str("# HELP toxicflow_attacks_detected_total Sandwich attacks detected.\n");
+        output.push_str("# TYPE toxicflow_attacks_detected_total counter\n");
+        output.push_str(&format!(
+            "toxicflow_attacks_detected_total {}\n",
+            self.attacks_detected.load(Ordering::Relaxed)
+        ));
+
+        // A gauge rather than a counter: unprofitable attacks lower it
+        output.push_str(
+            "# HELP toxicflow_attacker_profit_usd Estimated net USD profit of detected attackers.\n",
+        );
+        output.push_str("# TYPE toxicflow_attacker_profit_usd gauge\n");
+        output.push_str(&format!(
+            "toxicflow_attacker_profit_usd {}\n",
+            load_f64(&self.attacker_profit_usd)
+        ));
+
+        output.push_str(
+            "# HELP toxicflow_victim_loss_usd_total Simulated USD loss of sandwiched victims.\n",
+        );
+        output.push_str("# TYPE toxicflow_victim_loss_usd_total counter\n");
+        output.push_str(&format!(
+            "toxicflow_victim_loss_usd_total {}\n",
+            load_f64(&self.victim_loss_usd)
+        ));
+
+        output.push_str("# HELP toxicflow_blocks_processed_total Blocks run through detection.\n");
+        output.push_str("# TYPE toxicflow_blocks_processed_total counter\n");
+        output.push_str(&format!(
+            "toxicflow_blocks_processed_total {}\n",
+            self.blocks_processed.load(Ordering::Relaxed)
+        ));
+
+        output.push_str(
+            "# HELP toxicflow_last_block_number Highest block number processed, per chain.\n",
+        );
+        output.push_str("# TYPE toxicflow_last_block_number gauge\n");
+        if let Ok(last_block_numbers) = self.last_block_numbers.lock() {
+            for (chain_id, block_number) in last_block_numbers.iter() {
+                output.push_str(&format!(
+                    "toxicflow_last_block_number{{chain_id=\"{}\"}} {}\n",
+                    chain_id, block_number
+                ));
+            }
+        }
+
+        output.push_str(
+            "# HELP toxicflow_detection_latency_seconds Time spent detecting attacks in a block.\n",
+        );
+        output.push_str("# TYPE toxicflow_detection_latency_seconds histogram\n");
+        for (bucket, upper_bound) in self.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+            output.push_str(&format!(
+                "toxicflow_detection_latency_seconds_bucket{{le=\"{}\"}} {}\n",
+                upper_bound,
+                bucket.load(Ordering::Relaxed)
+            ));
+        }
+        let count = self.latency_count.load(Ordering::Relaxed);
+        output.push_str(&format!(
+            "toxicflow_detection_latency_seconds_bucket{{le=\"+Inf\"}} {}\n",
+            count
+        ));
+        output.push_str(&format!(
+            "toxicflow_detection_latency_seconds_sum {}\n",
+            load_f64(&self.latency_sum_seconds)
+        ));
+        output.push_str(&format!(
+            "toxicflow_detection_latency_seconds_count {}\n",
+            count
+        ));
+
+        return output;
+    }
+}
+
+/// There is no atomic float, so sums are kept as `f64` bits and updated with a CAS loop.
+fn add_f64(value: &AtomicU64, amount: f64) {
+    let _ = value.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        Some((f64::from_bits(bits) + amount).to_bits())
+    });
+}
+
+fn load_f64(value: &AtomicU64) -> f64 {
+    f64::from_bits(value.load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+    use crate::test_support::{sample_block, sample_pool_map};
+
+    fn block(chain_id: u64, block_number: u64) -> BlockId {
+        BlockId {
+            chain_id,
+            block_number,
+        }
+    }
+
+    #[test]
+    fn test_render_prometheus_text() {
+        let metrics = DetectorMetrics::new();
+        metrics.observe_block(block(1, 12360), Duration::from_millis(3), 2, 150.5);
+        metrics.observe_block(block(1, 12361), Duration::from_millis(200), 1, -20.5);
+        metrics.observe_block(block(137, 900), Duration::from_millis(1), 0, 0.0);
+
+        let attacks = find_sandwich_attacks_by_simulation(&sample_pool_map(), &sample_block());
+        metrics.observe_victim_losses(&attacks);
+        let loss: f64 = attacks.iter().map(|a| a.victim_loss_usd()).sum();
+        assert!(loss > 0.0);
+
+        let output = metrics.render();
+        assert!(output.contains("toxicflow_attacks_detected_total 3\n"));
+        assert!(output.contains("toxicflow_attacker_profit_usd 130\n"));
+        assert!(output.contains(&format!("toxicflow_victim_loss_usd_total {}\n", loss)));
+        assert!(output.contains("toxicflow_blocks_processed_total 3\n"));
+        assert!(output.contains("toxicflow_last_block_number{chain_id=\"1\"} 12361\n"));
+        assert!(output.contains("toxicflow_last_block_number{chain_id=\"137\"} 900\n"));
+        assert!(output.contains("toxicflow_detection_latency_seconds_bucket{le=\"0.005\"} 2\n"));
+        assert!(output.contains("toxicflow_detection_latency_seconds_bucket{le=\"0.25\"} 3\n"));
+        assert!(output.contains("toxicflow_detection_latency_seconds_count 3\n"));
+    }
+}
@@ -0,0 +1,100 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::DetectorMetrics;
+
+/// How long a scrape may take to send its request or read the response,
+/// so a stalled client can't hold up the (single) server thread.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serve `GET /metrics` on `addr` from a background thread, for Prometheus to scrape.
+///
+/// Returns the bound address (useful with port 0) and the server thread.
+pub fn serve<A: ToSocketAddrs>(
+    metrics: Arc<DetectorMetrics>,
+    addr: A,
+) -> Result<(std::net::SocketAddr, JoinHandle<()>), String> {
+    let listener =
+        TcpListener::bind(addr).map_err(|err| format!("failed to bind metrics server: {}", err))?;
+    let local_addr = listener
+        .local_addr()
+        .map_err(|err| format!("failed to bind metrics server: {}", err))?;
+
+    let handle = std::thread::spawn(move || {
+        // A failed request shows up as a failed scrape on Prometheus' side
+        for stream in listener.incoming().flatten() {
+            let _ = handle_connection(stream, &metrics);
+        }
+    });
+
+    return Ok((local_addr, handle));
+}
+
+fn handle_connection(mut stream: TcpStream, metrics: &DetectorMetrics) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(IO_TIMEOUT))
+        .map_err(|err| err.to_string())?;
+    stream
+        .set_write_timeout(Some(IO_TIMEOUT))
+        .map_err(|err| err.to_string())?;
+
+    let mut request_line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut request_line)
+        .map_err(|err| err.to_string())?;
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let response = match path {
+        "/metrics" => {
+            let body = metrics.render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+
+    stream
+        .write_all(response.as_bytes())
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::transactions::BlockId;
+    use std::io::Read;
+
+    #[test]
+    fn test_serve_metrics_endpoint() {
+        let metrics = Arc::new(DetectorMetrics::new());
+        let block = BlockId {
+            chain_id: 1,
+            block_number: 12360,
+        };
+        metrics.observe_block(block, Duration::from_millis(1), 1, 10.0);
+        let (addr, _handle) = serve(metrics, "127.0.0.1:0").expect("Failed to start server");
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).expect("Failed to connect");
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("toxicflow_attacks_detected_total 1\n"));
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+
+        // A client that never sends its request doesn't block the next scrape
+        let _stalled = TcpStream::connect(addr).expect("Failed to connect");
+        assert!(get("/metrics").starts_with("HTTP/1.1 200 OK"));
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+use crate::sandwich::transactions::SwapTransaction;
+
+/// Public MEV-Share SSE endpoint broadcasting hints for pending transactions and bundles.
+pub const MEV_SHARE_STREAM_URL: &str = "https://mev-share.flashbots.net";
+
+/// Historical hints endpoint of the MEV-Share node.
+pub const MEV_SHARE_HISTORY_URL: &str = "https://mev-share.flashbots.net/api/v1/history";
+
+/// Hints requested per page of the historical API, the most it serves at once.
+pub const HISTORY_PAGE_LIMIT: usize = 500;
+
+/// How long connecting to the event stream and getting its response headers
+/// may take. The stream itself is open-ended, reading it doesn't time out.
+#[cfg(feature = "rpc")]
+const STREAM_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// A single MEV-Share hint as broadcast on the event stream.
+///
+/// Users choose which fields they share, so everything apart from the
+/// hash may be missing (or explicitly `null` on the wire).
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MevShareEvent {
+    pub hash: String,
+    #[serde(default)]
+    pub logs: Option<Vec<HintLog>>,
+    #[serde(default)]
+    pub txs: Option<Vec<HintTx>>,
+    #[serde(default)]
+    pub mev_gas_price: Option<String>,
+    #[serde(default)]
+    pub gas_used: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct HintLog {
+    pub address: String,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub data: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HintTx {
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub function_selector: Option<String>,
+    #[serde(default)]
+    pub call_data: Option<String>,
+}
+
+/// An entry of the historical hints API, i.e. a hint together with
+/// the block in which the node saw it.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct MevShareHistoryEntry {
+    pub block: u64,
+    pub timestamp: u64,
+    pub hint: MevShareEvent,
+}
+
+/// Where the victim's transaction was visible to the attacker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum VictimExposure {
+    /// The victim sent its transaction through MEV-Share and a hint was broadcast for it.
+    SharedHint,
+    /// No hint was found, so the victim was most likely picked up from the public mempool.
+    PublicMempool,
+}
+
+/// What a searcher could learn about a hinted transaction.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HintedFlow {
+    pub hash: String,
+    pub block: Option<u64>,
+    pub shared_logs: bool,
+    pub shared_calldata: bool,
+}
+
+/// Lookup of MEV-Share hints by transaction hash, used to join them
+/// against the victims of on-chain sandwiches.
+#[derive(Debug, Default)]
+pub struct HintIndex {
+    hints: HashMap<String, HintedFlow>,
+}
+
+impl HintIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_events(events: &[MevShareEvent]) -> Self {
+        let mut index = Self::new();
+        for event in events {
+            index.insert(event, None);
+        }
+        return index;
+    }
+
+    pub fn from_history(entries: &[MevShareHistoryEntry]) -> Self {
+        let mut index = Self::new();
+        for entry in entries {
+            index.insert(&entry.hint, Some(entry.block));
+        }
+        return index;
+    }
+
+    pub fn insert(&mut self, event: &MevShareEvent, block: Option<u64>) {
+        let shared_logs = event.logs.as_ref().is_some_and(|logs| !logs.is_empty());
+        let shared_calldata = event.txs.as_ref().is_some_and(|txs| {
+            txs.iter()
+                .any(|tx| tx.call_data.is_some() || tx.function_selector.is_some())
+        });
+
+        self.hints.insert(
+            event.hash.to_lowercase(),
+            HintedFlow {
+                hash: event.hash.clone(),
+                block,
+                shared_logs,
+                shared_calldata,
+            },
+        );
+    }
+
+    pub fn get(&self, tx_hash: &str) -> Option<&HintedFlow> {
+        self.hints.get(&tx_hash.to_lowercase())
+    }
+
+    pub fn len(&self) -> usize {
+        self.hints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hints.is_empty()
+    }
+
+    /// Classify how the victim's transaction was exposed to the attacker.
+    ///
+    /// TODO: Bundle hints are keyed by bundle hash, not by the hashes of the
+    /// transactions inside the bundle, so victims that were part of a shared
+    /// bundle are currently reported as public mempool victims.
+    pub fn victim_exposure(&self, victim: &SwapTransaction) -> VictimExposure {
+        match self.get(&victim.tx_hash) {
+            Some(_) => VictimExposure::SharedHint,
+            None => VictimExposure::PublicMempool,
+        }
+    }
+}
+
+/// Join detected attacks against the hint index, pairing each attack
+/// with how its victim was exposed.
+pub fn classify_attacks<'a>(
+    attacks: &'a [SandwichAttackByHeuristics],
+    index: &HintIndex,
+) -> Vec<(&'a SandwichAttackByHeuristics, VictimExposure)> {
+    attacks
+        .iter()
+        .map(|attack| (attack, index.victim_exposure(&attack.victim_tx)))
+        .collect()
+}
+
+/// Parse a server-sent events stream as produced by the MEV-Share node.
+///
+/// Each event's `data:` lines are joined with newlines and parsed as JSON,
+/// comments (`:`) and other SSE fields are ignored. Fails on the first
+/// malformed event.
+pub fn parse_event_stream<R: BufRead>(reader: R) -> Result<Vec<MevShareEvent>, String> {
+    let mut events = Vec::new();
+    let mut failure = None;
+    read_event_stream(reader, |event| match event {
+        Ok(event) => {
+            events.push(event);
+            return true;
+        }
+        Err(err) => {
+            failure = Some(err);
+            return false;
+        }
+    })?;
+
+    return match failure {
+        Some(err) => Err(err),
+        None => Ok(events),
+    };
+}
+
+/// Feed each event of a server-sent event stream to `on_event` until it
+/// returns false, malformed events as errors. Only failing to read the
+/// stream ends it early.
+fn read_event_stream<R, F>(reader: R, mut on_event: F) -> Result<(), String>
+where
+    R: BufRead,
+    F: FnMut(Result<MevShareEvent, String>) -> bool,
+{
+    let mut data = String::new();
+
+    for line in reader.lines() {
+        let line = line.map_err(|err| format!("failed to read event stream: {}", err))?;
+
+        if line.is_empty() {
+            let event = parse_event_data(&data).transpose();
+            data.clear();
+            if let Some(event) = event {
+                if !on_event(event) {
+                    return Ok(());
+                }
+            }
+            continue;
+        }
+
+        if let Some(payload) = line.strip_prefix("data:") {
+            if !data.is_empty() {
+                data.push('\n');
+            }
+            // Only the single space after the colon is part of the field syntax
+            data.push_str(payload.strip_prefix(' ').unwrap_or(payload));
+        }
+    }
+
+    // The stream may end without a trailing blank line
+    if let Some(event) = parse_event_data(&data).transpose() {
+        on_event(event);
+    }
+
+    return Ok(());
+}
+
+fn parse_event_data(data: &str) -> Result<Option<MevShareEvent>, String> {
+    if data.trim().is_empty() {
+        return Ok(None);
+    }
+
+    serde_json::from_str(data)
+        .map(Some)
+        .map_err(|err| format!("invalid MEV-Share event: {}", err))
+}
+
+/// Parse a response body of the historical hints API.
+pub fn parse_history(json: &str) -> Result<Vec<MevShareHistoryEntry>, String> {
+    serde_json::from_str(json).map_err(|err| format!("invalid MEV-Share history: {}", err))
+}
+
+/// Fetch the hints the MEV-Share node saw for the given (inclusive) block
+/// range, `HISTORY_PAGE_LIMIT` at a time, with a client for
+/// `MEV_SHARE_HISTORY_URL`.
+///
+/// Fails rather than looping when a page repeats the previous one's last
+/// entry, i.e. the server ignores `offset`.
+#[cfg(feature = "rpc")]
+pub fn fetch_history(
+    client: &crate::enrich::rpc::RpcClient,
+    block_start: u64,
+    block_end: u64,
+) -> Result<Vec<MevShareHistoryEntry>, String> {
+    let block_start = block_start.to_string();
+    let block_end = block_end.to_string();
+    let limit = HISTORY_PAGE_LIMIT.to_string();
+    let mut entries: Vec<MevShareHistoryEntry> = Vec::new();
+    loop {
+        let offset = entries.len().to_string();
+        let response = client
+            .get(
+                "",
+                &[
+                    ("blockStart", &block_start),
+                    ("blockEnd", &block_end),
+                    ("limit", &limit),
+                    ("offset", &offset),
+                ],
+            )
+            .map_err(|err| format!("MEV-Share history request failed: {}", err))?;
+
+        let page: Vec<MevShareHistoryEntry> = serde_json::from_value(response)
+            .map_err(|err| format!("invalid MEV-Share history: {}", err))?;
+        if !entries.is_empty() && page.last() == entries.last() {
+            return Err(format!(
+                "MEV-Share history repeated a page at offset {}",
+                offset
+            ));
+        }
+        let is_last_page = page.len() < HISTORY_PAGE_LIMIT;
+        entries.extend(page);
+        if is_last_page {
+            return Ok(entries);
+        }
+    }
+}
+
+/// Subscribe to the live event stream, calling `on_event` for every hint
+/// until it returns `false` or the connection is closed. Malformed events
+/// are passed on as errors, the stream goes on after them.
+#[cfg(feature = "rpc")]
+pub fn subscribe<F>(on_event: F) -> Result<(), String>
+where
+    F: FnMut(Result<MevShareEvent, String>) -> bool,
+{
+    let response = ureq::get(MEV_SHARE_STREAM_URL)
+        .header("Accept", "text/event-stream")
+        .config()
+        .timeout_connect(Some(STREAM_CONNECT_TIMEOUT))
+        .timeout_send_request(Some(STREAM_CONNECT_TIMEOUT))
+        .timeout_recv_response(Some(STREAM_CONNECT_TIMEOUT))
+        .build()
+        .call()
+        .map_err(|err| format!("MEV-Share stream request failed: {}", err))?;
+    let reader = std::io::BufReader::new(response.into_body().into_reader());
+
+    return read_event_stream(reader, on_event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::csv::sample_transactions;
+    use crate::sandwich::find_same_block_sandwiches;
+
+    #[test]
+    fn test_join_hints_against_detected_attacks() {
+        let stream = r#": keep-alive
+
+data: {"hash":"0xVICTIM001","logs":[{"address":"0xpool1","topics":["0xd78ad95f"],"data":null}],"txs":null,"mevGasPrice":"0x2faf080","gasUsed":"0x30d40"}
+
+data: {"hash":"0xdeadbeef","logs":null,
+data: "txs":[{"to":"0xrouter","functionSelector":"0x38ed1739","callData":null}]}
+
+"#;
+        let events = parse_event_stream(stream.as_bytes()).expect("Failed to parse stream");
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1].txs.as_ref().unwrap()[0].to.as_deref(),
+            Some("0xrouter")
+        );
+
+        let index = HintIndex::from_events(&events);
+        let hinted = index
+            .get("0xvictim001")
+            .expect("Hash lookup is case-insensitive");
+        assert!(hinted.shared_logs);
+        assert!(!hinted.shared_calldata);
+        assert!(index.get("0xdeadbeef").unwrap().shared_calldata);
+
+        let attacks = find_same_block_sandwiches(&sample_transactions());
+        let classified = classify_attacks(&attacks, &index);
+        assert_eq!(classified.len(), attacks.len());
+
+        for (attack, exposure) in classified {
+            if attack.victim_tx.tx_hash == "0xvictim001" {
+                assert_eq!(exposure, VictimExposure::SharedHint);
+            } else {
+                assert_eq!(exposure, VictimExposure::PublicMempool);
+            }
+        }
+    }
+
+    #[test]
+    fn test_data_lines_are_joined_with_newlines() {
+        // A number split across lines must not be read as 12345
+        let stream = "data: {\"hash\":\"0xvictim001\",\"gasUsed\":12\ndata: 345}\n\n";
+        assert!(parse_event_stream(stream.as_bytes()).is_err());
+
+        let stream = "data: {\"hash\":\"0xvictim001\",\ndata:\"gasUsed\":\"0x30d40\"}\n\n";
+        let events = parse_event_stream(stream.as_bytes()).expect("Failed to parse stream");
+        assert_eq!(events[0].gas_used.as_deref(), Some("0x30d40"));
+    }
+
+    #[test]
+    fn test_malformed_events_do_not_end_the_stream() {
+        let stream = "data: {\"hash\":\n\ndata: {\"hash\":\"0xvictim001\"}\n\n";
+        let mut events = Vec::new();
+        read_event_stream(stream.as_bytes(), |event| {
+            events.push(event);
+            return true;
+        })
+        .expect("Failed to read stream");
+
+        assert_eq!(events.len(), 2);
+        assert!(events[0].is_err());
+        assert_eq!(events[1].as_ref().unwrap().hash, "0xvictim001");
+    }
+
+    #[test]
+    fn test_parse_history() {
+        let body = r#"[{"block":12360,"timestamp":1640995400,"hint":{"hash":"0xvictim001","logs":[],"txs":[]}}]"#;
+        let entries = parse_history(body).expect("Failed to parse history");
+        let index = HintIndex::from_history(&entries);
+
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.get("0xvictim001").unwrap().block, Some(12360));
+        assert!(!index.get("0xvictim001").unwrap().shared_logs);
+    }
+
+    #[cfg(feature = "rpc")]
+    #[test]
+    fn test_fetch_history_stops_when_offset_is_ignored() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        // Serves the same full page whatever the offset
+        std::thread::spawn(move || {
+            let entry = r#"{"block":12360,"timestamp":1640995400,"hint":{"hash":"0xvictim001"}}"#;
+            let page = format!("[{}]", vec![entry; HISTORY_PAGE_LIMIT].join(","));
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    page.len(),
+                    page
+                );
+            }
+        });
+
+        let client = crate::enrich::rpc::RpcClient::new(&url);
+        let result = fetch_history(&client, 12360, 12361);
+        assert!(result.is_err());
+        assert_eq!(client.requests(), 2);
+    }
+}
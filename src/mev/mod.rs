@@ -0,0 +1,2 @@
+pub mod mev_share;
+pub mod relays;
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+use crate::sandwich::transactions::ETHEREUM_CHAIN_ID;
+
+/// A few well known mainnet MEV-Boost relays, as `(name, base_url)`.
+///
+/// TODO: Relays come and go, this list should be user configurable.
+pub const MAINNET_RELAYS: &[(&str, &str)] = &[
+    ("flashbots", "https://boost-relay.flashbots.net"),
+    ("ultrasound", "https://relay.ultrasound.money"),
+    ("bloxroute", "https://bloxroute.max-profit.blxrbdn.com"),
+    ("agnostic", "https://agnostic-relay.net"),
+    ("aestus", "https://aestus.live"),
+    ("titan", "https://titanrelay.xyz"),
+];
+
+/// Path of the relay data API listing payloads delivered to proposers.
+pub const PAYLOADS_DELIVERED_PATH: &str = "/relay/v1/data/bidtraces/proposer_payload_delivered";
+
+/// Payloads requested per page, the most relays serve at once.
+pub const PAGE_LIMIT: usize = 200;
+
+/// Start of the mainnet beacon chain, whose slots last `SECONDS_PER_SLOT`.
+pub const MAINNET_GENESIS_TIME: u64 = 1_606_824_023;
+pub const SECONDS_PER_SLOT: u64 = 12;
+
+/// Slot of a post-merge mainnet block with the given timestamp.
+pub fn slot_at(timestamp: u64) -> u64 {
+    return timestamp.saturating_sub(MAINNET_GENESIS_TIME) / SECONDS_PER_SLOT;
+}
+
+/// A bid trace as returned by a relay's `proposer_payload_delivered` endpoint.
+/// Numbers are encoded as decimal strings on the wire.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DeliveredPayload {
+    #[serde(deserialize_with = "u64_from_str")]
+    pub slot: u64,
+    #[serde(deserialize_with = "u64_from_str")]
+    pub block_number: u64,
+    pub block_hash: String,
+    pub builder_pubkey: String,
+    pub proposer_pubkey: String,
+    pub proposer_fee_recipient: String,
+    /// Payment to the proposer in wei, kept as a string since it can exceed `u64`.
+    pub value: String,
+    #[serde(default, deserialize_with = "option_u64_from_str")]
+    pub num_tx: Option<u64>,
+}
+
+/// The builder that won a block and the relays that delivered its payload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelayAttribution {
+    pub block_number: u64,
+    pub block_hash: String,
+    pub builder_pubkey: String,
+    pub proposer_fee_recipient: String,
+    pub value_wei: String,
+    pub relays: Vec<String>,
+    /// Relays that delivered another payload (block hash or builder) for the
+    /// same number, e.g. one reorged out. Which of them landed is unknown,
+    /// so `count_attacks_by_builder` doesn't credit the block to anyone.
+    pub conflicting_relays: Vec<String>,
+}
+
+impl RelayAttribution {
+    pub fn is_ambiguous(&self) -> bool {
+        return !self.conflicting_relays.is_empty();
+    }
+}
+
+/// A detected attack together with the builder/relay that included it,
+/// `None` if no relay reported the block (e.g. a locally built block).
+#[derive(Debug, Serialize)]
+pub struct AttributedAttack<'a> {
+    pub attack: &'a SandwichAttackByHeuristics,
+    pub attribution: Option<&'a RelayAttribution>,
+}
+
+fn u64_from_str<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
+
+fn option_u64_from_str<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    let value = Option::<String>::deserialize(deserializer)?;
+    match value {
+        Some(value) => value.parse().map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Parse a response body of the `proposer_payload_delivered` endpoint.
+pub fn parse_payloads_delivered(json: &str) -> Result<Vec<DeliveredPayload>, String> {
+    serde_json::from_str(json).map_err(|err| format!("invalid relay payloads: {}", err))
+}
+
+/// Merge the payloads reported by each relay into one attribution per block.
+///
+/// The same payload is commonly delivered by several relays at once,
+/// in which case all of them are listed on the attribution. Relays reporting
+/// a different payload for a block already attributed are only listed in
+/// its `conflicting_relays`.
+pub fn attribute_blocks(
+    payloads_by_relay: &[(String, Vec<DeliveredPayload>)],
+) -> HashMap<u64, RelayAttribution> {
+    let mut attributions: HashMap<u64, RelayAttribution> = HashMap::new();
+
+    for (relay, payloads) in payloads_by_relay {
+        for payload in payloads {
+            let attribution =
+                attributions
+                    .entry(payload.block_number)
+                    .or_insert_with(|| RelayAttribution {
+                        block_number: payload.block_number,
+                        block_hash: payload.block_hash.clone(),
+                        builder_pubkey: payload.builder_pubkey.clone(),
+                        proposer_fee_recipient: payload.proposer_fee_recipient.clone(),
+                        value_wei: payload.value.clone(),
+                        relays: Vec::new(),
+                        conflicting_relays: Vec::new(),
+                    });
+
+            let relays = if attribution.block_hash == payload.block_hash
+                && attribution.builder_pubkey == payload.builder_pubkey
+            {
+                &mut attribution.relays
+            } else {
+                &mut attribution.conflicting_relays
+            };
+            if !relays.contains(relay) {
+                relays.push(relay.clone());
+            }
+        }
+    }
+
+    return attributions;
+}
+
+/// Annotate every attack with the builder and relays of the block it landed in.
+/// Relays only serve Ethereum mainnet, attacks on other chains stay unattributed.
+pub fn annotate_attacks<'a>(
+    attacks: &'a [SandwichAttackByHeuristics],
+    attributions: &'a HashMap<u64, RelayAttribution>,
+) -> Vec<AttributedAttack<'a>> {
+    attacks
+        .iter()
+        .map(|attack| AttributedAttack {
+            attack,
+            attribution: match attack.chain_id {
+                ETHEREUM_CHAIN_ID => attributions.get(&attack.victim_tx.block_number),
+                _ => None,
+            },
+        })
+        .collect()
+}
+
+/// Count attacks per builder pubkey, attacks in unattributed or ambiguous
+/// blocks are skipped.
+pub fn count_attacks_by_builder(attacks: &[AttributedAttack]) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+
+    for attack in attacks {
+        if let Some(attribution) = attack.attribution.filter(|a| !a.is_ambiguous()) {
+            *counts
+                .entry(attribution.builder_pubkey.clone())
+                .or_insert(0) += 1;
+        }
+    }
+
+    return counts;
+}
+
+/// One page of at most `limit` payloads a relay delivered at or before slot
+/// `cursor`, newest first.
+#[cfg(feature = "rpc")]
+pub fn fetch_payloads_page(
+    relay_url: &str,
+    cursor: u64,
+    limit: usize,
+) -> Result<Vec<DeliveredPayload>, String> {
+    let url = format!(
+        "{}{}",
+        relay_url.trim_end_matches('/'),
+        PAYLOADS_DELIVERED_PATH
+    );
+    let body = ureq::get(&url)
+        .query("cursor", cursor.to_string())
+        .query("limit", limit.to_string())
+        .call()
+        .map_err(|err| format!("relay request to {} failed: {}", relay_url, err))?
+        .body_mut()
+        .read_to_string()
+        .map_err(|err| format!("failed to read relay response: {}", err))?;
+
+    return parse_payloads_delivered(&body);
+}
+
+/// Page through every relay's delivered payloads of the (inclusive) block
+/// range and attribute the blocks. `end_timestamp`, the timestamp of
+/// `block_end`, gives the slot paging starts from.
+///
+/// A relay failing is not fatal, its error is returned alongside the attributions
+/// gathered from the other relays and its pages fetched so far.
+#[cfg(feature = "rpc")]
+pub fn fetch_attributions(
+    relays: &[(&str, &str)],
+    block_start: u64,
+    block_end: u64,
+    end_timestamp: u64,
+) -> (HashMap<u64, RelayAttribution>, Vec<String>) {
+    let mut payloads_by_relay = Vec::new();
+    let mut errors = Vec::new();
+
+    for (name, url) in relays {
+        let mut payloads = Vec::new();
+        let mut cursor = Some(slot_at(end_timestamp));
+        while let Some(slot) = cursor {
+            let page = match fetch_payloads_page(url, slot, PAGE_LIMIT) {
+                Ok(page) => page,
+                Err(err) => {
+                    errors.push(err);
+                    break;
+                }
+            };
+            cursor = match page.last() {
+                Some(last) if page.len() >= PAGE_LIMIT && last.block_number > block_start => {
+                    last.slot.checked_sub(1)
+                }
+                _ => None,
+            };
+            payloads.extend(
+                page.into_iter()
+                    .filter(|payload| (block_start..=block_end).contains(&payload.block_number)),
+            );
+        }
+        payloads_by_relay.push((name.to_string(), payloads));
+    }
+
+    return (attribute_blocks(&payloads_by_relay), errors);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::csv::sample_transactions;
+    use crate::sandwich::find_same_block_sandwiches;
+
+    fn payload(block_number: u64, builder: &str) -> String {
+        format!(
+            r#"{{"slot":"{}","parent_hash":"0xparent","block_hash":"0xblock{}{}","builder_pubkey":"{}","proposer_pubkey":"0xproposer","proposer_fee_recipient":"0xfeerecipient","gas_limit":"30000000","gas_used":"12000000","value":"45000000000000000","block_number":"{}","num_tx":"150"}}"#,
+            block_number + 1000,
+            block_number,
+            builder,
+            builder,
+            block_number
+        )
+    }
+
+    #[test]
+    fn test_annotate_attacks_with_relay_attribution() {
+        let flashbots = parse_payloads_delivered(&format!(
+            "[{},{}]",
+            payload(12360, "0xbuilder_a"),
+            payload(12361, "0xbuilder_b")
+        ))
+        .expect("Failed to parse payloads");
+        let ultrasound = parse_payloads_delivered(&format!("[{}]", payload(12360, "0xbuilder_a")))
+            .expect("Failed to parse payloads");
+        // A competing payload for a block, the first relay's may have been reorged out
+        let aestus = parse_payloads_delivered(&format!("[{}]", payload(12362, "0xbuilder_c")))
+            .expect("Failed to parse payloads");
+        let titan = parse_payloads_delivered(&format!("[{}]", payload(12362, "0xbuilder_d")))
+            .expect("Failed to parse payloads");
+        assert_eq!(flashbots[0].slot, 13360);
+        assert_eq!(flashbots[0].num_tx, Some(150));
+
+        let attributions = attribute_blocks(&[
+            ("flashbots".to_string(), flashbots),
+            ("ultrasound".to_string(), ultrasound),
+            ("aestus".to_string(), aestus),
+            ("titan".to_string(), titan),
+        ]);
+        assert_eq!(attributions.len(), 3);
+        assert_eq!(
+            attributions[&12360].relays,
+            vec!["flashbots".to_string(), "ultrasound".to_string()]
+        );
+        assert!(!attributions[&12360].is_ambiguous());
+        assert_eq!(attributions[&12362].relays, vec!["aestus".to_string()]);
+        assert_eq!(
+            attributions[&12362].conflicting_relays,
+            vec!["titan".to_string()]
+        );
+
+        let attacks = find_same_block_sandwiches(&sample_transactions());
+        let annotated = annotate_attacks(&attacks, &attributions);
+        assert_eq!(annotated.len(), attacks.len());
+
+        for attack in &annotated {
+            match attack.attack.victim_tx.block_number {
+                12360..=12362 => assert!(attack.attribution.is_some()),
+                _ => assert!(attack.attribution.is_none()),
+            }
+        }
+
+        let counts = count_attacks_by_builder(&annotated);
+        assert_eq!(counts["0xbuilder_a"], 1);
+        assert_eq!(counts["0xbuilder_b"], 1);
+        assert!(
+            !counts.contains_key("0xbuilder_c"),
+            "Ambiguous blocks aren't credited"
+        );
+        assert_eq!(slot_at(1_681_338_455), 6_209_536);
+    }
+}
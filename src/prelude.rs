@@ -0,0 +1,49 @@
+pub use crate::config::{
+    ConfidenceWeights, Config, HeuristicsConfig, SeverityConfig, SimulationConfig,
+};
+pub use crate::sandwich::activity::AddressActivity;
+pub use crate::sandwich::analytics::{
+    aggregate_by_attacker, aggregate_by_pool, aggregate_by_victim, AttackerSummary, PoolSummary,
+    VictimSummary,
+};
+pub use crate::sandwich::attack_set::AttackSet;
+pub use crate::sandwich::builder::{SandwichDetector, SandwichDetectorBuilder};
+pub use crate::sandwich::calibration::{CalibratedScorer, PlattScaling};
+pub use crate::sandwich::cancel::CancellationToken;
+pub use crate::sandwich::classification::{Classification, MevCategory, SandwichVariant, SubLabel};
+#[cfg(feature = "classifier")]
+pub use crate::sandwich::classifier::LogisticModel;
+pub use crate::sandwich::cross_validation::{cross_validate, CrossValidationReport};
+pub use crate::sandwich::detector::{
+    Block, CustomDetection, Detection, DetectionContext, Detector, HeuristicsDetector, Pipeline,
+    SimulationDetector,
+};
+pub use crate::sandwich::entities::EntityLinks;
+pub use crate::sandwich::error::{DetectionOutcome, DetectorError};
+pub use crate::sandwich::known_actors::KnownActors;
+pub use crate::sandwich::pool_hooks::{HookedPool, SwapHook};
+pub use crate::sandwich::pool_model::{
+    Arithmetic, ConcentratedLiquidityPool, PoolModel, StableSwapPool, WeightedPool,
+};
+pub use crate::sandwich::pool_registry::PoolRegistry;
+pub use crate::sandwich::progress::Progress;
+pub use crate::sandwich::registry::DetectorRegistry;
+pub use crate::sandwich::reputation::AttackerReputation;
+#[cfg(feature = "rules")]
+pub use crate::sandwich::rules::{Rule, RuleSet};
+pub use crate::sandwich::same_block_heuristics::{
+    find_same_block_sandwiches, ConfidenceFlags, SandwichAttackByHeuristics,
+};
+pub use crate::sandwich::same_block_sim::{
+    find_sandwich_attacks_by_simulation, simulate_without_attacker, Counterfactual,
+    CounterfactualRemoval, Pool, SandwichAttackBySimulation,
+};
+pub use crate::sandwich::sandwich_report::{merge_reports, SandwichReport};
+pub use crate::sandwich::scoring::{
+    rescore, rescore_with, AdditiveScorer, BayesianScorer, ConfidenceFactor, ConfidenceScorer,
+    LikelihoodRatios,
+};
+pub use crate::sandwich::severity::Severity;
+pub use crate::sandwich::streaming::StreamingDetector;
+pub use crate::sandwich::tokens::{TokenDecimals, TokenEquivalence, TokenTaxes};
+pub use crate::sandwich::transactions::{BlockId, SwapTransaction, ETHEREUM_CHAIN_ID};
@@ -0,0 +1,181 @@
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+use crate::sandwich::same_block_sim::SandwichAttackBySimulation;
+use crate::telemetry;
+
+/// One attack flattened into a spreadsheet row.
+///
+/// Heuristic detections leave the victim loss columns empty, simulated
+/// ones leave the confidence columns empty.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttackRow {
+    pub attack_id: String,
+    pub chain_id: u64,
+    pub block_number: u64,
+    pub front_run_tx: String,
+    pub victim_tx: String,
+    pub back_run_tx: String,
+    pub attacker: String,
+    pub victim: String,
+    pub front_run_pool: String,
+    pub victim_pool: String,
+    pub back_run_pool: String,
+    pub confidence_score: Option<f32>,
+    pub higher_front_gas_price: Option<bool>,
+    pub lower_back_gas_price: Option<bool>,
+    pub front_is_contract: Option<bool>,
+    pub back_is_contract: Option<bool>,
+    pub is_profitable: Option<bool>,
+    pub is_proportional: Option<bool>,
+    pub price_impact_rate: Option<f32>,
+    pub total_profit_usd: Option<f64>,
+    pub is_known_bot: Option<bool>,
+    pub likely_private_bundle: Option<bool>,
+    pub uses_flashloan: Option<bool>,
+    pub attacker_repeat_offender: Option<f32>,
+    pub same_entity: Option<bool>,
+    pub swap_size_factor: Option<f32>,
+    pub bot_activity_factor: Option<f32>,
+    pub paid_builder: Option<bool>,
+    pub profitable_only_before_bribe: Option<bool>,
+    pub front_gas_zscore: Option<f32>,
+    pub price_impact_depth_scale: Option<f32>,
+    /// Names of the rules that held, comma separated.
+    pub custom_flags: Option<String>,
+    pub victim_loss_percentage: Option<f64>,
+    pub victim_loss_percentage_low: Option<f64>,
+    pub victim_loss_percentage_high: Option<f64>,
+}
+
+impl From<&SandwichAttackByHeuristics> for AttackRow {
+    fn from(attack: &SandwichAttackByHeuristics) -> Self {
+        let flags = &attack.confidence_flags;
+        Self {
+            attack_id: attack.attack_id(),
+            chain_id: attack.chain_id,
+            block_number: attack.victim_tx.block_number,
+            front_run_tx: attack.front_run_tx.tx_hash.clone(),
+            victim_tx: attack.victim_tx.tx_hash.clone(),
+            back_run_tx: attack.back_run_tx.tx_hash.clone(),
+            attacker: attack.front_run_tx.from_address.clone(),
+            victim: attack.victim_tx.from_address.clone(),
+            front_run_pool: attack.front_run_tx.pool_address.clone(),
+            victim_pool: attack.victim_tx.pool_address.clone(),
+            back_run_pool: attack.back_run_tx.pool_address.clone(),
+            confidence_score: Some(attack.confidence_score),
+            higher_front_gas_price: Some(flags.higher_front_gas_price),
+            lower_back_gas_price: Some(flags.lower_back_gas_price),
+            front_is_contract: Some(flags.front_is_contract),
+            back_is_contract: Some(flags.back_is_contract),
+            is_profitable: Some(flags.is_profitable),
+            is_proportional: Some(flags.is_proportional),
+            price_impact_rate: Some(flags.price_impact_rate),
+            total_profit_usd: Some(flags.total_profit_usd),
+            is_known_bot: Some(flags.is_known_bot),
+            likely_private_bundle: Some(flags.likely_private_bundle),
+            uses_flashloan: Some(flags.uses_flashloan),
+            attacker_repeat_offender: Some(flags.attacker_repeat_offender),
+            same_entity: Some(flags.same_entity),
+            swap_size_factor: Some(flags.swap_size_factor),
+            bot_activity_factor: Some(flags.bot_activity_factor),
+            paid_builder: Some(flags.paid_builder),
+            profitable_only_before_bribe: Some(flags.profitable_only_before_bribe),
+            front_gas_zscore: Some(flags.front_gas_zscore),
+            price_impact_depth_scale: Some(flags.price_impact_depth_scale),
+            custom_flags: Some(flags.custom_flags.join(",")),
+            victim_loss_percentage: None,
+            victim_loss_percentage_low: None,
+            victim_loss_percentage_high: None,
+        }
+    }
+}
+
+impl From<&SandwichAttackBySimulation> for AttackRow {
+    fn from(attack: &SandwichAttackBySimulation) -> Self {
+        Self {
+            attack_id: attack.attack_id(),
+            chain_id: attack.chain_id,
+            block_number: attack.victim_tx.block_number,
+            front_run_tx: attack.front_run_tx.tx_hash.clone(),
+            victim_tx: attack.victim_tx.tx_hash.clone(),
+            back_run_tx: attack.back_run_tx.tx_hash.clone(),
+            attacker: attack.front_run_tx.from_address.clone(),
+            victim: attack.victim_tx.from_address.clone(),
+            front_run_pool: attack.front_run_tx.pool_address.clone(),
+            victim_pool: attack.victim_tx.pool_address.clone(),
+            back_run_pool: attack.back_run_tx.pool_address.clone(),
+            confidence_score: None,
+            higher_front_gas_price: None,
+            lower_back_gas_price: None,
+            front_is_contract: None,
+            back_is_contract: None,
+            is_profitable: None,
+            is_proportional: None,
+            price_impact_rate: None,
+            total_profit_usd: None,
+            is_known_bot: None,
+            likely_private_bundle: None,
+            uses_flashloan: None,
+            attacker_repeat_offender: None,
+            same_entity: None,
+            swap_size_factor: None,
+            bot_activity_factor: None,
+            paid_builder: None,
+            profitable_only_before_bribe: None,
+            front_gas_zscore: None,
+            price_impact_depth_scale: None,
+            custom_flags: None,
+            victim_loss_percentage: Some(attack.victim_loss_percentage),
+            victim_loss_percentage_low: Some(attack.victim_loss_percentage_low),
+            victim_loss_percentage_high: Some(attack.victim_loss_percentage_high),
+        }
+    }
+}
+
+/// Write one CSV row per attack, with a header, for spreadsheets and BI tools.
+pub fn write_csv<A, W: Write>(attacks: &[A], writer: W) -> Result<(), String>
+where
+    for<'a> AttackRow: From<&'a A>,
+{
+    let attributes = [("attacks", attacks.len() as i64)];
+    return telemetry::traced_result(telemetry::WRITE_REPORT, &attributes, || {
+        let mut writer = csv::Writer::from_writer(writer);
+        for attack in attacks {
+            writer
+                .serialize(AttackRow::from(attack))
+                .map_err(|err| format!("failed to write CSV report: {}", err))?;
+        }
+        writer
+            .flush()
+            .map_err(|err| format!("failed to write CSV report: {}", err))?;
+        return Ok(());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+
+    #[test]
+    fn test_write_csv_one_row_per_attack() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = find_same_block_sandwiches(&transactions);
+
+        let mut output = Vec::new();
+        write_csv(&attacks, &mut output).expect("Failed to write attacks");
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), attacks.len() + 1);
+        assert!(lines[0].starts_with("attack_id,chain_id,block_number,front_run_tx"));
+        assert!(lines[1].starts_with(&format!("{},1,", attacks[0].attack_id())));
+        assert!(
+            lines[1].ends_with(','),
+            "Heuristic rows have no victim loss"
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::csv::AttackRow;
+use crate::alerts::Alert;
+use crate::sandwich::classification::Classification;
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+use crate::sandwich::same_block_sim::SandwichAttackBySimulation;
+
+/// Documents sent per `_bulk` request.
+const BULK_SIZE: usize = 500;
+
+/// One attack as indexed, the CSV row plus what dashboards filter on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttackDocument {
+    /// Unix seconds of the victim's block, mapped as `epoch_second`.
+    #[serde(rename = "@timestamp")]
+    pub timestamp: u64,
+    pub detector: &'static str,
+    #[serde(flatten)]
+    pub row: AttackRow,
+    /// Simulated victim loss, left out when it isn't known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loss_usd: Option<f64>,
+    /// See `Alert::attacker_profit_usd`.
+    pub attacker_profit_usd: f64,
+    pub classification: Classification,
+}
+
+impl From<&SandwichAttackByHeuristics> for AttackDocument {
+    fn from(attack: &SandwichAttackByHeuristics) -> Self {
+        let alert = Alert::from(attack);
+        Self {
+            timestamp: attack.victim_tx.timestamp,
+            detector: "heuristics",
+            row: AttackRow::from(attack),
+            loss_usd: alert.loss_usd,
+            attacker_profit_usd: alert.attacker_profit_usd,
+            classification: attack.classification.clone(),
+        }
+    }
+}
+
+impl From<&SandwichAttackBySimulation> for AttackDocument {
+    fn from(attack: &SandwichAttackBySimulation) -> Self {
+        let alert = Alert::from(attack);
+        Self {
+            timestamp: attack.victim_tx.timestamp,
+            detector: "simulation",
+            row: AttackRow::from(attack),
+            loss_usd: alert.loss_usd,
+            attacker_profit_usd: alert.attacker_profit_usd,
+            classification: attack.classification.clone(),
+        }
+    }
+}
+
+/// Bulk-indexes attacks into Elasticsearch or OpenSearch (both speak the same
+/// `_bulk` and index APIs).
+///
+/// Documents are keyed by detector and attack ID, so re-running detection
+/// over the same blocks updates them instead of adding duplicates.
+#[derive(Debug, Clone)]
+pub struct ElasticsearchExporter {
+    pub url: String,
+    pub index: String,
+    /// Value of the `Authorization` header, if the cluster needs one.
+    authorization: Option<String>,
+}
+
+impl ElasticsearchExporter {
+    /// `url` is the cluster endpoint, e.g. `http://localhost:9200`.
+    pub fn new(url: &str, index: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            index: index.to_string(),
+            authorization: None,
+        }
+    }
+
+    /// Elasticsearch API key, as shown (base64 encoded) when it was created.
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.authorization = Some(format!("ApiKey {}", api_key));
+        self
+    }
+
+    /// Pre-encoded `user:password` for HTTP basic auth, e.g. on OpenSearch.
+    pub fn with_basic_auth(mut self, encoded_credentials: &str) -> Self {
+        self.authorization = Some(format!("Basic {}", encoded_credentials));
+        self
+    }
+
+    /// Explicit mapping: addresses and hashes as `keyword` for exact filters
+    /// and terms aggregations, numbers typed so dashboards can sum them.
+    pub fn mapping() -> Value {
+        let keyword = json!({ "type": "keyword" });
+        let boolean = json!({ "type": "boolean" });
+        let float = json!({ "type": "float" });
+        let double = json!({ "type": "double" });
+        let mut properties = json!({
+            "@timestamp": { "type": "date", "format": "epoch_second" },
+            "detector": keyword,
+            "attack_id": keyword,
+            "chain_id": { "type": "long" },
+            "block_number": { "type": "long" },
+            "front_run_tx": keyword,
+            "victim_tx": keyword,
+            "back_run_tx": keyword,
+            "attacker": keyword,
+            "victim": keyword,
+            "front_run_pool": keyword,
+            "victim_pool": keyword,
+            "back_run_pool": keyword,
+            "confidence_score": float,
+            "victim_loss_percentage": double,
+            "victim_loss_percentage_low": double,
+            "victim_loss_percentage_high": double,
+            "loss_usd": double,
+            "attacker_profit_usd": double,
+            "classification": {
+                "properties": {
+                    "category": keyword,
+                    "labels": keyword
+                }
+            }
+        });
+        // The flags of `AttackRow`, empty for simulated attacks
+        for (flag, mapping) in [
+            ("higher_front_gas_price", &boolean),
+            ("lower_back_gas_price", &boolean),
+            ("front_is_contract", &boolean),
+            ("back_is_contract", &boolean),
+            ("is_profitable", &boolean),
+            ("is_proportional", &boolean),
+            ("price_impact_rate", &float),
+            ("total_profit_usd", &double),
+            ("is_known_bot", &boolean),
+            ("likely_private_bundle", &boolean),
+            ("uses_flashloan", &boolean),
+            ("attacker_repeat_offender", &float),
+            ("same_entity", &boolean),
+            ("swap_size_factor", &float),
+            ("bot_activity_factor", &float),
+            ("paid_builder", &boolean),
+            ("profitable_only_before_bribe", &boolean),
+            ("front_gas_zscore", &float),
+            ("price_impact_depth_scale", &float),
+            ("custom_flags", &keyword),
+        ] {
+            properties[flag] = mapping.clone();
+        }
+        return json!({
+            "mappings": { "dynamic": "strict", "properties": properties }
+        });
+    }
+
+    /// Create the index with `mapping()`, doing nothing if it already exists.
+    pub fn create_index(&self) -> Result<(), String> {
+        let url = format!("{}/{}", self.url, self.index);
+        let mut request = ureq::put(&url).config().http_status_as_error(false).build();
+        if let Some(authorization) = &self.authorization {
+            request = request.header("Authorization", authorization);
+        }
+        let mut response = request
+            .send_json(Self::mapping())
+            .map_err(|err| format!("failed to create index {}: {}", self.index, err))?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+
+        let body: Value = response.body_mut().read_json().unwrap_or(Value::Null);
+        if body["error"]["type"] == "resource_already_exists_exception" {
+            return Ok(());
+        }
+        return Err(format!(
+            "failed to create index {}: {} {}",
+            self.index,
+            response.status(),
+            body
+        ));
+    }
+
+    /// Index the attacks in `_bulk` requests, returning how many were indexed.
+    pub fn index_attacks<A>(&self, attacks: &[A]) -> Result<usize, String>
+    where
+        for<'a> AttackDocument: From<&'a A>,
+    {
+        let url = format!("{}/_bulk", self.url);
+        for chunk in attacks.chunks(BULK_SIZE) {
+            let documents: Vec<AttackDocument> = chunk.iter().map(AttackDocument::from).collect();
+            let mut request = ureq::post(&url).header("Content-Type", "application/x-ndjson");
+            if let Some(authorization) = &self.authorization {
+                request = request.header("Authorization", authorization);
+            }
+            let response: Value = request
+                .send(self.bulk_body(&documents)?)
+                .map_err(|err| format!("bulk request failed: {}", err))?
+                .body_mut()
+                .read_json()
+                .map_err(|err| format!("invalid bulk response: {}", err))?;
+            check_bulk_response(&response)?;
+        }
+        return Ok(attacks.len());
+    }
+
+    /// The NDJSON body of a `_bulk` request indexing `documents`.
+    pub fn bulk_body(&self, documents: &[AttackDocument]) -> Result<String, String> {
+        let mut body = String::new();
+        for document in documents {
+            let action = json!({
+                "index": {
+                    "_index": self.index,
+                    "_id": format!("{}:{}", document.detector, document.row.attack_id)
+                }
+            });
+            let source = serde_json::to_string(document)
+                .map_err(|err| format!("failed to encode attack: {}", err))?;
+            body.push_str(&action.to_string());
+            body.push('\n');
+            body.push_str(&source);
+            body.push('\n');
+        }
+        return Ok(body);
+    }
+}
+
+/// `_bulk` answers 200 even when items fail, report the first failure.
+fn check_bulk_response(response: &Value) -> Result<(), String> {
+    if response["errors"] != true {
+        return Ok(());
+    }
+    let errors: Vec<&Value> = response["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item["index"].get("error"))
+        .collect();
+    let first = errors
+        .first()
+        .and_then(|error| error["reason"].as_str())
+        .unwrap_or("unknown error");
+    return Err(format!(
+        "failed to index {} attacks, first error: {}",
+        errors.len(),
+        first
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+
+    #[test]
+    fn test_bulk_body_and_response_errors() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = find_same_block_sandwiches(&transactions);
+        let exporter = ElasticsearchExporter::new("http://localhost:9200/", "sandwiches");
+        let documents: Vec<AttackDocument> = attacks.iter().map(AttackDocument::from).collect();
+
+        let body = exporter.bulk_body(&documents).unwrap();
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), attacks.len() * 2);
+        assert_eq!(
+            lines[0]["index"]["_id"],
+            format!("heuristics:{}", attacks[0].attack_id())
+        );
+        assert_eq!(lines[1]["@timestamp"], attacks[0].victim_tx.timestamp);
+        assert_eq!(lines[1]["classification"]["category"], "sandwich");
+        // The heuristics don't know the victim's loss, only the profit
+        assert!(lines[1].get("loss_usd").is_none());
+        assert_eq!(
+            lines[1]["attacker_profit_usd"],
+            attacks[0].confidence_flags.total_profit_usd
+        );
+
+        // Every indexed field is mapped, as the mapping is strict
+        let mapped = ElasticsearchExporter::mapping();
+        for field in lines[1].as_object().unwrap().keys() {
+            assert!(
+                mapped["mappings"]["properties"].get(field).is_some(),
+                "{} is not mapped",
+                field
+            );
+        }
+
+        assert!(check_bulk_response(&json!({ "errors": false, "items": [] })).is_ok());
+        let failed = json!({
+            "errors": true,
+            "items": [
+                { "index": { "status": 201 } },
+                { "index": { "status": 400, "error": { "reason": "mapper_parsing_exception" } } }
+            ]
+        });
+        assert_eq!(
+            check_bulk_response(&failed).unwrap_err(),
+            "failed to index 1 attacks, first error: mapper_parsing_exception"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::alerts::Alert;
+
+/// What an address did across the attacks in the graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressRole {
+    Attacker,
+    Victim,
+    /// Attacked some swaps and was sandwiched in others.
+    Both,
+}
+
+impl AddressRole {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AddressRole::Attacker => "attacker",
+            AddressRole::Victim => "victim",
+            AddressRole::Both => "both",
+        }
+    }
+}
+
+/// All attacks of one attacker on one victim.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AttackEdge {
+    pub count: usize,
+    /// Summed simulated victim loss, `None` when no attack's loss is known.
+    pub loss_usd: Option<f64>,
+}
+
+/// Directed attacker → victim graph, for spotting serial attackers and
+/// clusters in Graphviz or Gephi.
+#[derive(Debug, Clone, Default)]
+pub struct AttackGraph {
+    nodes: BTreeMap<String, AddressRole>,
+    edges: BTreeMap<(String, String), AttackEdge>,
+}
+
+impl AttackGraph {
+    pub fn from_attacks<A>(attacks: &[A]) -> Self
+    where
+        for<'a> Alert: From<&'a A>,
+    {
+        let mut graph = Self::default();
+        for attack in attacks {
+            let alert = Alert::from(attack);
+            graph.add_attack(&alert.attacker, &alert.victim, alert.loss_usd);
+        }
+        return graph;
+    }
+
+    /// `loss_usd` is the victim's loss, `None` when it isn't known.
+    pub fn add_attack(&mut self, attacker: &str, victim: &str, loss_usd: Option<f64>) {
+        self.add_node(attacker, AddressRole::Attacker);
+        self.add_node(victim, AddressRole::Victim);
+
+        let edge = self
+            .edges
+            .entry((attacker.to_string(), victim.to_string()))
+            .or_default();
+        edge.count += 1;
+        if let Some(loss_usd) = loss_usd {
+            edge.loss_usd = Some(edge.loss_usd.unwrap_or(0.0) + loss_usd);
+        }
+    }
+
+    fn add_node(&mut self, address: &str, role: AddressRole) {
+        self.nodes
+            .entry(address.to_string())
+            .and_modify(|existing| {
+                if *existing != role {
+                    *existing = AddressRole::Both;
+                }
+            })
+            .or_insert(role);
+    }
+
+    pub fn role(&self, address: &str) -> Option<AddressRole> {
+        return self.nodes.get(address).copied();
+    }
+
+    pub fn edge(&self, attacker: &str, victim: &str) -> Option<&AttackEdge> {
+        return self.edges.get(&(attacker.to_string(), victim.to_string()));
+    }
+
+    pub fn edge_count(&self) -> usize {
+        return self.edges.len();
+    }
+
+    /// Graphviz DOT, with attackers in red, victims in blue and edge width
+    /// growing with the number of attacks. Edges are labeled with their
+    /// count and, when known, the victim's loss.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph attacks {\n  rankdir=LR;\n  node [style=filled];\n");
+        for (address, role) in &self.nodes {
+            let color = match role {
+                AddressRole::Attacker => "#f4a6a6",
+                AddressRole::Victim => "#a6c8f4",
+                AddressRole::Both => "#d9b3f4",
+            };
+            dot.push_str(&format!(
+                "  \"{}\" [fillcolor=\"{}\", role=\"{}\"];\n",
+                escape_dot(address),
+                color,
+                role.as_str()
+            ));
+        }
+        for ((attacker, victim), edge) in &self.edges {
+            let label = match edge.loss_usd {
+                Some(loss_usd) => format!("{} (${:.2})", edge.count, loss_usd),
+                None => edge.count.to_string(),
+            };
+            dot.push_str(&format!(
+                "  \"{}\" -> \"{}\" [label=\"{}\", weight={}, penwidth={:.1}];\n",
+                escape_dot(attacker),
+                escape_dot(victim),
+                label,
+                edge.count,
+                1.0 + (edge.count as f64).ln()
+            ));
+        }
+        dot.push_str("}\n");
+        return dot;
+    }
+
+    /// GEXF 1.2 for Gephi, with the role as a node attribute and the attack
+    /// count (as weight) and USD loss, when known, on edges.
+    pub fn to_gexf(&self) -> String {
+        let mut gexf = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <gexf xmlns=\"http://gexf.net/1.2\" version=\"1.2\">\n\
+             <graph defaultedgetype=\"directed\">\n\
+             <attributes class=\"node\">\n\
+             <attribute id=\"role\" title=\"role\" type=\"string\"/>\n\
+             </attributes>\n\
+             <attributes class=\"edge\">\n\
+             <attribute id=\"loss_usd\" title=\"loss_usd\" type=\"double\"/>\n\
+             </attributes>\n\
+             <nodes>\n",
+        );
+        for (address, role) in &self.nodes {
+            gexf.push_str(&format!(
+                "<node id=\"{0}\" label=\"{0}\"><attvalues><attvalue for=\"role\" value=\"{1}\"/></attvalues></node>\n",
+                escape_xml(address),
+                role.as_str()
+            ));
+        }
+        gexf.push_str("</nodes>\n<edges>\n");
+        for (id, ((attacker, victim), edge)) in self.edges.iter().enumerate() {
+            let attributes = match edge.loss_usd {
+                Some(loss_usd) => format!(
+                    "<attvalues><attvalue for=\"loss_usd\" value=\"{}\"/></attvalues>",
+                    loss_usd
+                ),
+                None => String::new(),
+            };
+            gexf.push_str(&format!(
+                "<edge id=\"{}\" source=\"{}\" target=\"{}\" weight=\"{}\">{}</edge>\n",
+                id,
+                escape_xml(attacker),
+                escape_xml(victim),
+                edge.count,
+                attributes
+            ));
+        }
+        gexf.push_str("</edges>\n</graph>\n</gexf>\n");
+        return gexf;
+    }
+
+    pub fn write_dot<W: Write>(&self, mut writer: W) -> Result<(), String> {
+        writer
+            .write_all(self.to_dot().as_bytes())
+            .map_err(|err| format!("failed to write DOT graph: {}", err))
+    }
+
+    pub fn write_gexf<W: Write>(&self, mut writer: W) -> Result<(), String> {
+        writer
+            .write_all(self.to_gexf().as_bytes())
+            .map_err(|err| format!("failed to write GEXF graph: {}", err))
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    return text.replace('\\', "\\\\").replace('"', "\\\"");
+}
+
+fn escape_xml(text: &str) -> String {
+    return text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+
+    #[test]
+    fn test_graph_aggregates_attacker_victim_edges() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = find_same_block_sandwiches(&transactions);
+        let mut graph = AttackGraph::from_attacks(&attacks);
+        assert_eq!(graph.edge_count(), attacks.len());
+
+        // Heuristic detections don't know the victim's loss
+        assert!(attacks.iter().all(|attack| {
+            let victim = &attack.victim_tx.from_address;
+            graph
+                .edge(&attack.front_run_tx.from_address, victim)
+                .unwrap()
+                .loss_usd
+                .is_none()
+        }));
+
+        graph.add_attack("0xbot", "0xalice", Some(10.0));
+        graph.add_attack("0xbot", "0xalice", None);
+        graph.add_attack("0xbot", "0xalice", Some(5.5));
+        graph.add_attack("0xalice", "0xcarol", None);
+        assert_eq!(
+            graph.edge("0xbot", "0xalice"),
+            Some(&AttackEdge {
+                count: 3,
+                loss_usd: Some(15.5)
+            })
+        );
+        assert_eq!(graph.role("0xalice"), Some(AddressRole::Both));
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"0xbot\" -> \"0xalice\" [label=\"3 ($15.50)\", weight=3"));
+        assert!(dot.contains("\"0xalice\" -> \"0xcarol\" [label=\"1\", weight=1"));
+        let gexf = graph.to_gexf();
+        assert!(gexf.contains("source=\"0xbot\" target=\"0xalice\" weight=\"3\"><attvalues>"));
+        assert!(gexf.contains("source=\"0xalice\" target=\"0xcarol\" weight=\"1\"></edge>"));
+        assert!(gexf.contains("<attvalue for=\"role\" value=\"both\"/>"));
+    }
+}
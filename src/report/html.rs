@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+
+use crate::sandwich::same_block_heuristics::{ConfidenceFlags, SandwichAttackByHeuristics};
+use crate::sandwich::transactions::BlockId;
+use crate::telemetry;
+
+/// Rows shown in the top attackers/victims tables.
+const TOP_ADDRESSES: usize = 10;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left}\
+th{background:#f0f0f0}td.num{text-align:right}\
+code{font-size:0.9em}";
+
+/// Render a self-contained HTML report (no external assets) of one detection run:
+/// summary stats, top attackers and victims, confidence flag breakdown and
+/// the attacks found in each block.
+pub fn render_html(title: &str, attacks: &[SandwichAttackByHeuristics]) -> String {
+    let mut html = String::new();
+    html.push_str(&format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        escape(title),
+        STYLE,
+        escape(title)
+    ));
+
+    render_summary(&mut html, attacks);
+    render_top_attackers(&mut html, attacks);
+    render_top_victims(&mut html, attacks);
+    render_flag_breakdown(&mut html, attacks);
+    render_blocks(&mut html, attacks);
+
+    html.push_str("</body>\n</html>\n");
+    return html;
+}
+
+pub fn write_html<W: Write>(
+    title: &str,
+    attacks: &[SandwichAttackByHeuristics],
+    mut writer: W,
+) -> Result<(), String> {
+    let attributes = [("attacks", attacks.len() as i64)];
+    return telemetry::traced_result(telemetry::WRITE_REPORT, &attributes, || {
+        writer
+            .write_all(render_html(title, attacks).as_bytes())
+            .map_err(|err| format!("failed to write HTML report: {}", err))
+    });
+}
+
+fn render_summary(html: &mut String, attacks: &[SandwichAttackByHeuristics]) {
+    let blocks: HashSet<BlockId> = attacks.iter().map(|a| a.victim_tx.block_id()).collect();
+    let attackers: HashSet<&str> = attacks
+        .iter()
+        .map(|a| a.front_run_tx.from_address.as_str())
+        .collect();
+    let victims: HashSet<&str> = attacks
+        .iter()
+        .map(|a| a.victim_tx.from_address.as_str())
+        .collect();
+    let total_profit: f64 = attacks
+        .iter()
+        .map(|a| a.confidence_flags.total_profit_usd)
+        .sum();
+    let average_confidence = match attacks.len() {
+        0 => 0.0,
+        count => attacks.iter().map(|a| a.confidence_score).sum::<f32>() / count as f32,
+    };
+
+    html.push_str("<h2>Summary</h2>\n<table>\n");
+    for (label, value) in [
+        ("Attacks", attacks.len().to_string()),
+        ("Blocks with attacks", blocks.len().to_string()),
+        ("Attackers", attackers.len().to_string()),
+        ("Victims", victims.len().to_string()),
+        (
+            "Total attacker profit (USD)",
+            format!("{:.2}", total_profit),
+        ),
+        ("Average confidence", format!("{:.2}", average_confidence)),
+    ] {
+        html.push_str(&format!(
+            "<tr><th>{}</th><td class=\"num\">{}</td></tr>\n",
+            label, value
+        ));
+    }
+    html.push_str("</table>\n");
+}
+
+fn render_top_attackers(html: &mut String, attacks: &[SandwichAttackByHeuristics]) {
+    // attacker -> (attacks, profit)
+    let mut attackers: HashMap<&str, (usize, f64)> = HashMap::new();
+    for attack in attacks {
+        let entry = attackers
+            .entry(attack.front_run_tx.from_address.as_str())
+            .or_default();
+        entry.0 += 1;
+        entry.1 += attack.confidence_flags.total_profit_usd;
+    }
+
+    let mut ranked: Vec<_> = attackers.into_iter().collect();
+    ranked.sort_by(|a, b| b.1 .1.total_cmp(&a.1 .1).then(a.0.cmp(b.0)));
+
+    html.push_str("<h2>Top attackers</h2>\n<table>\n<tr><th>Attacker</th><th>Attacks</th><th>Profit (USD)</th></tr>\n");
+    for (attacker, (count, profit)) in ranked.into_iter().take(TOP_ADDRESSES) {
+        html.push_str(&format!(
+            "<tr><td><code>{}</code></td><td class=\"num\">{}</td><td class=\"num\">{:.2}</td></tr>\n",
+            escape(attacker),
+            count,
+            profit
+        ));
+    }
+    html.push_str("</table>\n");
+}
+
+fn render_top_victims(html: &mut String, attacks: &[SandwichAttackByHeuristics]) {
+    // victim -> (times sandwiched, swapped volume)
+    let mut victims: HashMap<&str, (usize, f64)> = HashMap::new();
+    for attack in attacks {
+        let entry = victims
+            .entry(attack.victim_tx.from_address.as_str())
+            .or_default();
+        entry.0 += 1;
+        entry.1 += attack.victim_tx.usd_value_in;
+    }
+
+    let mut ranked: Vec<_> = victims.into_iter().collect();
+    ranked.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(b.0)));
+
+    html.push_str("<h2>Top victims</h2>\n<table>\n<tr><th>Victim</th><th>Times sandwiched</th><th>Sandwiched volume (USD)</th></tr>\n");
+    for (victim, (count, volume)) in ranked.into_iter().take(TOP_ADDRESSES) {
+        html.push_str(&format!(
+            "<tr><td><code>{}</code></td><td class=\"num\">{}</td><td class=\"num\">{:.2}</td></tr>\n",
+            escape(victim),
+            count,
+            volume
+        ));
+    }
+    html.push_str("</table>\n");
+}
+
+fn render_flag_breakdown(html: &mut String, attacks: &[SandwichAttackByHeuristics]) {
+    let count = |is_set: fn(&ConfidenceFlags) -> bool| {
+        attacks
+            .iter()
+            .filter(|a| is_set(&a.confidence_flags))
+            .count()
+    };
+    let flags = [
+        (
+            "Higher front-run gas price",
+            count(|f| f.higher_front_gas_price),
+        ),
+        (
+            "Lower back-run gas price",
+            count(|f| f.lower_back_gas_price),
+        ),
+        ("Front-run from a contract", count(|f| f.front_is_contract)),
+        ("Back-run from a contract", count(|f| f.back_is_contract)),
+        ("Profitable", count(|f| f.is_profitable)),
+        ("Proportional legs", count(|f| f.is_proportional)),
+    ];
+
+    html.push_str("<h2>Confidence flags</h2>\n<table>\n<tr><th>Flag</th><th>Attacks</th><th>Share</th></tr>\n");
+    for (label, count) in flags {
+        let share = match attacks.len() {
+            0 => 0.0,
+            total => count as f64 * 100.0 / total as f64,
+        };
+        html.push_str(&format!(
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{:.1}%</td></tr>\n",
+            label, count, share
+        ));
+    }
+    html.push_str("</table>\n");
+}
+
+fn render_blocks(html: &mut String, attacks: &[SandwichAttackByHeuristics]) {
+    let mut blocks: BTreeMap<BlockId, Vec<&SandwichAttackByHeuristics>> = BTreeMap::new();
+    for attack in attacks {
+        blocks
+            .entry(attack.victim_tx.block_id())
+            .or_default()
+            .push(attack);
+    }
+
+    html.push_str("<h2>Attacks by block</h2>\n");
+    for (block, block_attacks) in blocks {
+        html.push_str(&format!(
+            "<h3>Block {} (chain {})</h3>\n<table>\n<tr><th>Attack</th><th>Pool</th><th>Attacker</th><th>Victim</th><th>Confidence</th><th>Profit (USD)</th></tr>\n",
+            block.block_number, block.chain_id
+        ));
+        for attack in block_attacks {
+            html.push_str(&format!(
+                "<tr><td><code>{}</code></td><td><code>{}</code></td><td><code>{}</code></td><td><code>{}</code></td><td class=\"num\">{:.2}</td><td class=\"num\">{:.2}</td></tr>\n",
+                escape(&attack.attack_id()),
+                escape(&attack.victim_tx.pool_address),
+                escape(&attack.front_run_tx.from_address),
+                escape(&attack.victim_tx.from_address),
+                attack.confidence_score,
+                attack.confidence_flags.total_profit_usd
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+}
+
+/// Input comes from user-supplied data, so everything is escaped before it's embedded.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+
+    #[test]
+    fn test_render_html_report() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = find_same_block_sandwiches(&transactions);
+
+        let html = render_html("Run <1>", &attacks);
+        assert!(html.contains("<title>Run &lt;1&gt;</title>"));
+        assert!(html.contains(&format!(
+            "<tr><th>Attacks</th><td class=\"num\">{}</td></tr>",
+            attacks.len()
+        )));
+        assert!(html.contains("<h3>Block 12360 (chain 1)</h3>"));
+        assert!(html.contains(&attacks[0].front_run_tx.from_address));
+        assert!(!html.contains("<script"), "The report is static");
+    }
+}
@@ -0,0 +1,43 @@
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::telemetry;
+
+/// Write detection results as a pretty-printed JSON array.
+///
+/// Works for any serializable result, e.g. `SandwichAttackByHeuristics`,
+/// `SandwichAttackBySimulation` or relay-attributed attacks.
+pub fn export_json<T: Serialize, W: Write>(attacks: &[T], mut writer: W) -> Result<(), String> {
+    let attributes = [("attacks", attacks.len() as i64)];
+    return telemetry::traced_result(telemetry::WRITE_REPORT, &attributes, || {
+        serde_json::to_writer_pretty(&mut writer, attacks)
+            .map_err(|err| format!("failed to write JSON report: {}", err))?;
+        writer
+            .write_all(b"\n")
+            .map_err(|err| format!("failed to write JSON report: {}", err))?;
+        return Ok(());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::{
+        find_same_block_sandwiches, SandwichAttackByHeuristics,
+    };
+
+    #[test]
+    fn test_export_json_round_trip() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = find_same_block_sandwiches(&transactions);
+        assert!(!attacks.is_empty());
+
+        let mut output = Vec::new();
+        export_json(&attacks, &mut output).expect("Failed to export attacks");
+
+        let parsed: Vec<SandwichAttackByHeuristics> =
+            serde_json::from_slice(&output).expect("Failed to parse exported attacks");
+        assert_eq!(parsed, attacks);
+    }
+}
@@ -0,0 +1,19 @@
+pub mod csv;
+#[cfg(feature = "elasticsearch")]
+pub mod elasticsearch;
+pub mod graph;
+pub mod html;
+pub mod json;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod zeromev;
+
+pub use csv::write_csv;
+#[cfg(feature = "elasticsearch")]
+pub use elasticsearch::ElasticsearchExporter;
+pub use graph::AttackGraph;
+pub use html::{render_html, write_html};
+pub use json::export_json;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteReport;
+pub use zeromev::ZeromevExporter;
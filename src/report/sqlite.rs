@@ -0,0 +1,505 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection, Transaction};
+
+use super::csv::AttackRow;
+use crate::alerts::Alert;
+use crate::config::ConfidenceWeights;
+use crate::sandwich::classification::Classification;
+use crate::sandwich::same_block_heuristics::{ConfidenceFlags, SandwichAttackByHeuristics};
+use crate::sandwich::same_block_sim::SandwichAttackBySimulation;
+use crate::sandwich::scoring::additive_confidence;
+
+/// Denormalized layout for analysts: one row per attack and detector in each
+/// run, with its flags unpivoted into `attack_flags` so they can be filtered
+/// and grouped without knowing the column list.
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS detection_runs (
+    run_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source TEXT NOT NULL,
+    detector_version TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    finished_at TEXT,
+    attack_count INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS attacks (
+    run_id INTEGER NOT NULL REFERENCES detection_runs (run_id),
+    attack_id TEXT NOT NULL,
+    detector TEXT NOT NULL,
+    chain_id INTEGER NOT NULL,
+    block_number INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    attacker TEXT NOT NULL,
+    victim TEXT NOT NULL,
+    pool_address TEXT NOT NULL,
+    front_run_tx TEXT NOT NULL,
+    victim_tx TEXT NOT NULL,
+    back_run_tx TEXT NOT NULL,
+    confidence_score REAL,
+    victim_loss_percentage REAL,
+    -- Simulated victim loss, NULL when only the heuristics found the attack
+    loss_usd REAL,
+    category TEXT NOT NULL,
+    labels TEXT NOT NULL,
+    confidence_flags TEXT,
+    PRIMARY KEY (run_id, attack_id, detector)
+);
+
+CREATE INDEX IF NOT EXISTS attacks_attacker_idx ON attacks (attacker);
+
+CREATE TABLE IF NOT EXISTS attack_flags (
+    run_id INTEGER NOT NULL,
+    attack_id TEXT NOT NULL,
+    detector TEXT NOT NULL,
+    flag TEXT NOT NULL,
+    value REAL NOT NULL,
+    PRIMARY KEY (run_id, attack_id, detector, flag)
+);
+";
+
+/// Attackers ranked by summed loss, one row per address.
+///
+/// Every attack counts once, as the latest run that found it recorded it
+/// (with the simulated loss when one of its detectors knows it), however
+/// many runs and detectors found it. Attacks without a known loss count
+/// but add nothing to it.
+pub const TOP_ATTACKERS_SQL: &str = "
+WITH latest_runs AS (
+    SELECT attack_id, MAX(run_id) AS run_id FROM attacks GROUP BY attack_id
+),
+unique_attacks AS (
+    SELECT attack_id, attacker, victim, MAX(loss_usd) AS loss_usd
+    FROM attacks JOIN latest_runs USING (attack_id, run_id)
+    GROUP BY attack_id
+)
+SELECT attacker, COUNT(*), COUNT(DISTINCT victim), SUM(loss_usd)
+FROM unique_attacks
+GROUP BY attacker
+ORDER BY SUM(loss_usd) DESC NULLS LAST, attacker
+LIMIT ?1";
+
+/// Attacks and loss per UTC day, oldest first, each attack counted once as
+/// in `TOP_ATTACKERS_SQL`.
+pub const DAILY_TOTALS_SQL: &str = "
+WITH latest_runs AS (
+    SELECT attack_id, MAX(run_id) AS run_id FROM attacks GROUP BY attack_id
+),
+unique_attacks AS (
+    SELECT attack_id, timestamp, MAX(loss_usd) AS loss_usd
+    FROM attacks JOIN latest_runs USING (attack_id, run_id)
+    GROUP BY attack_id
+)
+SELECT date(timestamp, 'unixepoch') AS day, COUNT(*), SUM(loss_usd)
+FROM unique_attacks
+GROUP BY day
+ORDER BY day";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttackerTotal {
+    pub attacker: String,
+    pub attacks: u64,
+    pub victims: u64,
+    /// `None` when none of the attacks' losses is known.
+    pub loss_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyTotal {
+    /// `YYYY-MM-DD`, in UTC.
+    pub day: String,
+    pub attacks: u64,
+    /// `None` when none of the attacks' losses is known.
+    pub loss_usd: Option<f64>,
+}
+
+/// Result sink writing detection runs into a standalone SQLite file that can
+/// be opened with any SQLite client for ad-hoc queries.
+///
+/// Unlike `storage::sqlite::SqliteStore` it doesn't keep the swaps, only what
+/// each run found, so results of several runs can be compared side by side.
+pub struct SqliteReport {
+    connection: Connection,
+}
+
+impl SqliteReport {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let connection = Connection::open(path)
+            .map_err(|err| format!("failed to open sqlite report: {}", err))?;
+        return Self::from_connection(connection);
+    }
+
+    pub fn open_in_memory() -> Result<Self, String> {
+        let connection = Connection::open_in_memory()
+            .map_err(|err| format!("failed to open sqlite report: {}", err))?;
+        return Self::from_connection(connection);
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self, String> {
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|err| format!("failed to create report schema: {}", err))?;
+        Ok(Self { connection })
+    }
+
+    /// Record the start of a run over `source` (a file, a query, ...) and return its ID.
+    pub fn begin_run(&mut self, source: &str) -> Result<i64, String> {
+        self.connection
+            .execute(
+                "INSERT INTO detection_runs (source, detector_version, started_at)
+                VALUES (?1, ?2, ?3)",
+                params![
+                    source,
+                    env!("CARGO_PKG_VERSION"),
+                    chrono::Utc::now().to_rfc3339()
+                ],
+            )
+            .map_err(|err| format!("failed to start run: {}", err))?;
+        return Ok(self.connection.last_insert_rowid());
+    }
+
+    /// Stamp the run as finished, with the number of distinct attacks it found.
+    pub fn finish_run(&mut self, run_id: i64) -> Result<(), String> {
+        self.connection
+            .execute(
+                "UPDATE detection_runs SET
+                    finished_at = ?2,
+                    attack_count = (SELECT COUNT(DISTINCT attack_id) FROM attacks WHERE run_id = ?1)
+                WHERE run_id = ?1",
+                params![run_id, chrono::Utc::now().to_rfc3339()],
+            )
+            .map_err(|err| format!("failed to finish run {}: {}", run_id, err))?;
+        return Ok(());
+    }
+
+    pub fn write_attacks(
+        &mut self,
+        run_id: i64,
+        attacks: &[SandwichAttackByHeuristics],
+    ) -> Result<(), String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+        for attack in attacks {
+            insert_attack(
+                &transaction,
+                run_id,
+                "heuristics",
+                &AttackRow::from(attack),
+                attack.victim_tx.timestamp,
+                Alert::from(attack).loss_usd,
+                &attack.classification,
+            )?;
+            let flags = serde_json::to_string(&attack.confidence_flags)
+                .map_err(|err| format!("failed to encode flags: {}", err))?;
+            transaction
+                .execute(
+                    "UPDATE attacks SET confidence_flags = ?3
+                    WHERE run_id = ?1 AND attack_id = ?2 AND detector = 'heuristics'",
+                    params![run_id, attack.attack_id(), flags],
+                )
+                .map_err(|err| format!("failed to store flags: {}", err))?;
+        }
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit attacks: {}", err))
+    }
+
+    /// Recompute the confidence of a run's heuristic attacks from their
+    /// stored flags under new `weights`, without detecting them again.
+    /// Returns how many were rescored, attacks written before the flags were
+    /// stored are left as is.
+    pub fn rescore_run(
+        &mut self,
+        run_id: i64,
+        weights: &ConfidenceWeights,
+    ) -> Result<usize, String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+        let stored: Vec<(String, String)> = {
+            let mut statement = transaction
+                .prepare(
+                    "SELECT attack_id, confidence_flags FROM attacks
+                    WHERE run_id = ?1 AND detector = 'heuristics' AND confidence_flags IS NOT NULL",
+                )
+                .map_err(|err| format!("failed to read flags: {}", err))?;
+            let rows = statement
+                .query_map([run_id], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(|err| format!("failed to read flags: {}", err))?;
+            rows.collect::<Result<_, _>>()
+                .map_err(|err| format!("failed to read flags: {}", err))?
+        };
+
+        for (attack_id, flags) in &stored {
+            let flags: ConfidenceFlags = serde_json::from_str(flags)
+                .map_err(|err| format!("invalid flags of {}: {}", attack_id, err))?;
+            transaction
+                .execute(
+                    "UPDATE attacks SET confidence_score = ?3
+                    WHERE run_id = ?1 AND attack_id = ?2 AND detector = 'heuristics'",
+                    params![run_id, attack_id, additive_confidence(&flags, weights)],
+                )
+                .map_err(|err| format!("failed to rescore {}: {}", attack_id, err))?;
+        }
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit scores: {}", err))?;
+        return Ok(stored.len());
+    }
+
+    pub fn write_simulated_attacks(
+        &mut self,
+        run_id: i64,
+        attacks: &[SandwichAttackBySimulation],
+    ) -> Result<(), String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+        for attack in attacks {
+            insert_attack(
+                &transaction,
+                run_id,
+                "simulation",
+                &AttackRow::from(attack),
+                attack.victim_tx.timestamp,
+                Alert::from(attack).loss_usd,
+                &attack.classification,
+            )?;
+        }
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit attacks: {}", err))
+    }
+
+    /// See `TOP_ATTACKERS_SQL`.
+    pub fn top_attackers(&self, limit: usize) -> Result<Vec<AttackerTotal>, String> {
+        let mut statement = self
+            .connection
+            .prepare(TOP_ATTACKERS_SQL)
+            .map_err(|err| format!("failed to query top attackers: {}", err))?;
+        let rows = statement
+            .query_map(params![limit as i64], |row| {
+                Ok(AttackerTotal {
+                    attacker: row.get(0)?,
+                    attacks: row.get(1)?,
+                    victims: row.get(2)?,
+                    loss_usd: row.get(3)?,
+                })
+            })
+            .map_err(|err| format!("failed to query top attackers: {}", err))?;
+        rows.collect::<Result<_, _>>()
+            .map_err(|err| format!("failed to read top attackers: {}", err))
+    }
+
+    /// See `DAILY_TOTALS_SQL`.
+    pub fn daily_totals(&self) -> Result<Vec<DailyTotal>, String> {
+        let mut statement = self
+            .connection
+            .prepare(DAILY_TOTALS_SQL)
+            .map_err(|err| format!("failed to query daily totals: {}", err))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(DailyTotal {
+                    day: row.get(0)?,
+                    attacks: row.get(1)?,
+                    loss_usd: row.get(2)?,
+                })
+            })
+            .map_err(|err| format!("failed to query daily totals: {}", err))?;
+        rows.collect::<Result<_, _>>()
+            .map_err(|err| format!("failed to read daily totals: {}", err))
+    }
+}
+
+fn insert_attack(
+    transaction: &Transaction,
+    run_id: i64,
+    detector: &str,
+    row: &AttackRow,
+    timestamp: u64,
+    loss_usd: Option<f64>,
+    classification: &Classification,
+) -> Result<(), String> {
+    let category = serde_json::to_value(classification.category)
+        .map_err(|err| format!("failed to encode category: {}", err))?;
+    let labels = serde_json::to_value(&classification.labels)
+        .map_err(|err| format!("failed to encode labels: {}", err))?;
+    let labels: Vec<&str> = labels
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|label| label.as_str())
+        .collect();
+
+    transaction
+        .execute(
+            "INSERT OR REPLACE INTO attacks (
+                run_id, attack_id, detector, chain_id, block_number, timestamp, attacker,
+                victim, pool_address, front_run_tx, victim_tx, back_run_tx, confidence_score,
+                victim_loss_percentage, loss_usd, category, labels
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+            params![
+                run_id,
+                row.attack_id,
+                detector,
+                row.chain_id as i64,
+                row.block_number as i64,
+                timestamp as i64,
+                row.attacker,
+                row.victim,
+                row.victim_pool,
+                row.front_run_tx,
+                row.victim_tx,
+                row.back_run_tx,
+                row.confidence_score,
+                row.victim_loss_percentage,
+                loss_usd,
+                category.as_str().unwrap_or_default(),
+                labels.join(","),
+            ],
+        )
+        .map_err(|err| format!("failed to save attack {}: {}", row.attack_id, err))?;
+
+    let flag = |value: Option<bool>| value.map(|value| if value { 1.0 } else { 0.0 });
+    let flags = [
+        ("higher_front_gas_price", flag(row.higher_front_gas_price)),
+        ("lower_back_gas_price", flag(row.lower_back_gas_price)),
+        ("front_is_contract", flag(row.front_is_contract)),
+        ("back_is_contract", flag(row.back_is_contract)),
+        ("is_profitable", flag(row.is_profitable)),
+        ("is_proportional", flag(row.is_proportional)),
+        ("price_impact_rate", row.price_impact_rate.map(f64::from)),
+        ("total_profit_usd", row.total_profit_usd),
+        ("is_known_bot", flag(row.is_known_bot)),
+        ("likely_private_bundle", flag(row.likely_private_bundle)),
+        ("uses_flashloan", flag(row.uses_flashloan)),
+        (
+            "attacker_repeat_offender",
+            row.attacker_repeat_offender.map(f64::from),
+        ),
+        ("same_entity", flag(row.same_entity)),
+        ("swap_size_factor", row.swap_size_factor.map(f64::from)),
+        (
+            "bot_activity_factor",
+            row.bot_activity_factor.map(f64::from),
+        ),
+        ("paid_builder", flag(row.paid_builder)),
+        (
+            "profitable_only_before_bribe",
+            flag(row.profitable_only_before_bribe),
+        ),
+        ("front_gas_zscore", row.front_gas_zscore.map(f64::from)),
+        (
+            "price_impact_depth_scale",
+            row.price_impact_depth_scale.map(f64::from),
+        ),
+    ];
+    for (name, value) in flags {
+        let value = match value {
+            Some(value) => value,
+            None => continue,
+        };
+        transaction
+            .execute(
+                "INSERT OR REPLACE INTO attack_flags (run_id, attack_id, detector, flag, value)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![run_id, row.attack_id, detector, name, value],
+            )
+            .map_err(|err| format!("failed to save flags of {}: {}", row.attack_id, err))?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+
+    #[test]
+    fn test_runs_and_canned_queries() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = find_same_block_sandwiches(&transactions);
+
+        let mut report = SqliteReport::open_in_memory().expect("Failed to open report");
+        let run_id = report.begin_run("data/sandwiches.csv").unwrap();
+        report.write_attacks(run_id, &attacks).unwrap();
+        // Writing the same results again doesn't duplicate them
+        report.write_attacks(run_id, &attacks).unwrap();
+        report.finish_run(run_id).unwrap();
+
+        let attack_count: u64 = report
+            .connection
+            .query_row(
+                "SELECT attack_count FROM detection_runs WHERE run_id = ?1",
+                [run_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(attack_count, attacks.len() as u64);
+
+        // The heuristics don't know the victims' losses
+        let top = report.top_attackers(1).unwrap();
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].loss_usd, None);
+
+        let daily = report.daily_totals().unwrap();
+        assert_eq!(
+            daily.iter().map(|day| day.attacks).sum::<u64>(),
+            attacks.len() as u64
+        );
+        assert_eq!(daily[0].day, "2022-01-01");
+
+        let weights = ConfidenceWeights {
+            base: 0.0,
+            ..ConfidenceWeights::default()
+        };
+        assert_eq!(report.rescore_run(run_id, &weights).unwrap(), attacks.len());
+        let mut rescored = attacks.clone();
+        crate::sandwich::scoring::rescore(&mut rescored, &weights);
+        let score: f64 = report
+            .connection
+            .query_row(
+                "SELECT confidence_score FROM attacks WHERE run_id = ?1 AND attack_id = ?2",
+                params![run_id, rescored[0].attack_id()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!((score - f64::from(rescored[0].confidence_score)).abs() < 1e-6);
+
+        // The simulation does, and ranks its attackers first
+        let pool_map = crate::test_support::sample_pool_map();
+        let simulated = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
+        assert!(!simulated.is_empty());
+        let run_id = report.begin_run("data/sandwiches.csv").unwrap();
+        report.write_simulated_attacks(run_id, &simulated).unwrap();
+        let top = report.top_attackers(1).unwrap();
+        let loss = simulated
+            .iter()
+            .filter(|attack| attack.front_run_tx.from_address == top[0].attacker)
+            .map(|attack| Alert::from(attack).loss_usd.unwrap())
+            .sum::<f64>();
+        assert!((top[0].loss_usd.unwrap() - loss).abs() < 1e-6);
+
+        // Another run finding the same attacks, with both detectors, doesn't add to them
+        let run_id = report.begin_run("data/sandwiches.csv").unwrap();
+        report.write_attacks(run_id, &attacks).unwrap();
+        report.write_simulated_attacks(run_id, &simulated).unwrap();
+        assert_eq!(report.top_attackers(1).unwrap(), top);
+        let total_loss: f64 = simulated
+            .iter()
+            .map(|attack| Alert::from(attack).loss_usd.unwrap())
+            .sum();
+        let daily = report.daily_totals().unwrap();
+        assert_eq!(
+            daily.iter().map(|day| day.attacks).sum::<u64>(),
+            attacks.len() as u64
+        );
+        let daily_loss: f64 = daily.iter().filter_map(|day| day.loss_usd).sum();
+        assert!((daily_loss - total_loss).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+use crate::sandwich::same_block_sim::SandwichAttackBySimulation;
+use crate::sandwich::transactions::{SwapTransaction, ETHEREUM_CHAIN_ID};
+
+/// Protocol label for pools missing from the exporter's protocol map.
+pub const UNKNOWN_PROTOCOL: &str = "unknown";
+
+/// One transaction in zeromev's MEV classification schema.
+///
+/// zeromev reports a sandwich as three rows keyed by block and transaction
+/// index: the `frontrun`, the victim's `sandwich` and the `backrun`. User
+/// columns are only set on the victim row, extractor columns on the attacker
+/// rows (profit on the backrun). `address_to` is the pool, since swaps don't
+/// record the router that was called.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ZeromevRow {
+    pub block_number: u64,
+    pub tx_index: u32,
+    pub mev_type: &'static str,
+    pub protocol: String,
+    pub user_loss_usd: Option<f64>,
+    pub user_swap_volume_usd: Option<f64>,
+    pub user_swap_count: Option<u32>,
+    pub extractor_profit_usd: Option<f64>,
+    pub extractor_swap_volume_usd: Option<f64>,
+    pub extractor_swap_count: Option<u32>,
+    pub address_from: String,
+    pub address_to: String,
+}
+
+/// The three transactions of an attack and what is known about its USD impact.
+pub struct ZeromevSandwich<'a> {
+    pub chain_id: u64,
+    pub front_run_tx: &'a SwapTransaction,
+    pub victim_tx: &'a SwapTransaction,
+    pub back_run_tx: &'a SwapTransaction,
+    /// Only simulation measures what the victim lost.
+    pub user_loss_usd: Option<f64>,
+    /// The detector's attacker profit, net of gas and builder payments.
+    pub extractor_profit_usd: f64,
+}
+
+impl<'a> From<&'a SandwichAttackByHeuristics> for ZeromevSandwich<'a> {
+    fn from(attack: &'a SandwichAttackByHeuristics) -> Self {
+        Self {
+            chain_id: attack.chain_id,
+            front_run_tx: &attack.front_run_tx,
+            victim_tx: &attack.victim_tx,
+            back_run_tx: &attack.back_run_tx,
+            user_loss_usd: None,
+            extractor_profit_usd: attack.confidence_flags.total_profit_usd,
+        }
+    }
+}
+
+impl<'a> From<&'a SandwichAttackBySimulation> for ZeromevSandwich<'a> {
+    fn from(attack: &'a SandwichAttackBySimulation) -> Self {
+        Self {
+            chain_id: attack.chain_id,
+            front_run_tx: &attack.front_run_tx,
+            victim_tx: &attack.victim_tx,
+            back_run_tx: &attack.back_run_tx,
+            user_loss_usd: Some(
+                attack.victim_tx.usd_value_in * attack.victim_loss_percentage / 100.0,
+            ),
+            extractor_profit_usd: attack.attacker_profit_usd,
+        }
+    }
+}
+
+/// Maps attacks to zeromev rows so results can be compared against or merged
+/// with zeromev's public dataset.
+///
+/// zeromev only covers Ethereum mainnet, so attacks on other chains are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct ZeromevExporter {
+    protocols: HashMap<String, String>,
+}
+
+impl ZeromevExporter {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Label swaps through `pool_address` with a zeromev protocol name, e.g. `uniswap2`.
+    pub fn with_protocol(mut self, pool_address: &str, protocol: &str) -> Self {
+        self.protocols
+            .insert(pool_address.to_lowercase(), protocol.to_string());
+        return self;
+    }
+
+    pub fn rows<'a, A: 'a>(&self, attacks: &'a [A]) -> Vec<ZeromevRow>
+    where
+        ZeromevSandwich<'a>: From<&'a A>,
+    {
+        let mut rows = Vec::new();
+        for attack in attacks {
+            let sandwich = ZeromevSandwich::from(attack);
+            if sandwich.chain_id != ETHEREUM_CHAIN_ID {
+                continue;
+            }
+            rows.extend(self.sandwich_rows(&sandwich));
+        }
+        return rows;
+    }
+
+    /// Write the rows as CSV, with zeromev's column names as the header.
+    pub fn write_csv<'a, A: 'a, W: Write>(&self, attacks: &'a [A], writer: W) -> Result<(), String>
+    where
+        ZeromevSandwich<'a>: From<&'a A>,
+    {
+        let mut writer = csv::Writer::from_writer(writer);
+        for row in self.rows(attacks) {
+            writer
+                .serialize(row)
+                .map_err(|err| format!("failed to write zeromev export: {}", err))?;
+        }
+        writer
+            .flush()
+            .map_err(|err| format!("failed to write zeromev export: {}", err))?;
+        return Ok(());
+    }
+
+    fn sandwich_rows(&self, sandwich: &ZeromevSandwich) -> [ZeromevRow; 3] {
+        let front = sandwich.front_run_tx;
+        let victim = sandwich.victim_tx;
+        let back = sandwich.back_run_tx;
+
+        let extractor = |tx: &SwapTransaction, mev_type, profit| ZeromevRow {
+            extractor_profit_usd: profit,
+            extractor_swap_volume_usd: Some(tx.usd_value_in),
+            extractor_swap_count: Some(1),
+            ..self.row(tx, mev_type)
+        };
+        let user = ZeromevRow {
+            user_loss_usd: sandwich.user_loss_usd,
+            user_swap_volume_usd: Some(victim.usd_value_in),
+            user_swap_count: Some(1),
+            ..self.row(victim, "sandwich")
+        };
+
+        return [
+            extractor(front, "frontrun", None),
+            user,
+            extractor(back, "backrun", Some(sandwich.extractor_profit_usd)),
+        ];
+    }
+
+    fn row(&self, tx: &SwapTransaction, mev_type: &'static str) -> ZeromevRow {
+        let protocol = self
+            .protocols
+            .get(&tx.pool_address.to_lowercase())
+            .map(String::as_str)
+            .unwrap_or(UNKNOWN_PROTOCOL);
+        ZeromevRow {
+            block_number: tx.block_number,
+            tx_index: tx.tx_position_in_block,
+            mev_type,
+            protocol: protocol.to_string(),
+            user_loss_usd: None,
+            user_swap_volume_usd: None,
+            user_swap_count: None,
+            extractor_profit_usd: None,
+            extractor_swap_volume_usd: None,
+            extractor_swap_count: None,
+            address_from: tx.from_address.clone(),
+            address_to: tx.pool_address.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::same_block_heuristics::{
+        find_same_block_sandwiches, find_same_block_sandwiches_with_config,
+    };
+
+    #[test]
+    fn test_sandwich_maps_to_frontrun_sandwich_backrun_rows() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let mut attacks = find_same_block_sandwiches(&transactions);
+        attacks.sort_by_key(|attack| attack.victim_tx.block_number);
+        let attack = &attacks[0];
+
+        let exporter =
+            ZeromevExporter::new().with_protocol(&attack.victim_tx.pool_address, "uniswap2");
+        let rows = exporter.rows(std::slice::from_ref(attack));
+
+        let types: Vec<_> = rows.iter().map(|row| row.mev_type).collect();
+        assert_eq!(types, ["frontrun", "sandwich", "backrun"]);
+        assert_eq!(rows[1].tx_index, attack.victim_tx.tx_position_in_block);
+        assert_eq!(rows[1].protocol, "uniswap2");
+        assert_eq!(rows[1].user_loss_usd, None);
+        assert_eq!(
+            rows[2].extractor_profit_usd,
+            Some(attack.confidence_flags.total_profit_usd)
+        );
+
+        // Bids to the builder are part of the attack's cost
+        let mut config = Config::default();
+        config.heuristics.builder_payments =
+            Some(HashMap::from([(attack.back_run_tx.tx_hash.clone(), 12.5)]));
+        let bribed = find_same_block_sandwiches_with_config(&transactions, &config)
+            .attacks
+            .into_iter()
+            .find(|bribed| bribed.attack_id() == attack.attack_id())
+            .unwrap();
+        let rows = exporter.rows(std::slice::from_ref(&bribed));
+        assert_eq!(
+            rows[2].extractor_profit_usd,
+            Some(attack.confidence_flags.total_profit_usd - 12.5)
+        );
+
+        let mut other_chain = attack.clone();
+        other_chain.chain_id = 137;
+        assert!(exporter.rows(&[other_chain]).is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// What is known about an address's transactions beyond the swaps, e.g.
+/// its nonce and the timestamps of its recent transactions.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AddressActivity {
+    /// Transactions sent, the nonce for externally owned accounts.
+    pub tx_count: u64,
+    pub first_timestamp: u64,
+    pub last_timestamp: u64,
+    /// Bit `h` set when the address sent a transaction in UTC hour `h`.
+    pub active_hours: u32,
+}
+
+impl AddressActivity {
+    pub fn new(tx_count: u64) -> Self {
+        return Self {
+            tx_count,
+            ..Self::default()
+        };
+    }
+
+    /// Record a transaction seen at `timestamp` (seconds), extending the
+    /// observed span and active hours. Doesn't count it, `tx_count` comes
+    /// from the nonce.
+    pub fn observe(&mut self, timestamp: u64) {
+        if self.active_hours == 0 || timestamp < self.first_timestamp {
+            self.first_timestamp = timestamp;
+        }
+        self.last_timestamp = self.last_timestamp.max(timestamp);
+        self.active_hours |= 1 << (timestamp % SECONDS_PER_DAY / 3600);
+    }
+
+    /// Transactions per day over the observed span, at least one day.
+    pub fn txs_per_day(&self) -> f64 {
+        let span = self.last_timestamp.saturating_sub(self.first_timestamp);
+        let days = (span as f64 / SECONDS_PER_DAY as f64).max(1.0);
+        return self.tx_count as f64 / days;
+    }
+
+    /// From 0 to 1, how bot-like the address transacts: its frequency
+    /// relative to `bot_txs_per_day` (capped at 1) times the share of the
+    /// day's hours it is active in. A busy human still sleeps.
+    pub fn bot_factor(&self, bot_txs_per_day: f64) -> f32 {
+        let frequency = (self.txs_per_day() / bot_txs_per_day.max(f64::EPSILON)).min(1.0);
+        let coverage = f64::from(self.active_hours.count_ones()) / 24.0;
+        return (frequency * coverage) as f32;
+    }
+}
+
+/// Activity of the sender of every transaction of `timestamps`, as
+/// (address, timestamp) pairs, with the nonces of `tx_counts`. Keyed by
+/// lowercased address, as `HeuristicsConfig::activity` expects.
+pub fn address_activity<'a, I>(
+    tx_counts: &HashMap<String, u64>,
+    timestamps: I,
+) -> HashMap<String, AddressActivity>
+where
+    I: IntoIterator<Item = (&'a str, u64)>,
+{
+    let mut activity: HashMap<String, AddressActivity> = tx_counts
+        .iter()
+        .map(|(address, count)| (address.to_lowercase(), AddressActivity::new(*count)))
+        .collect();
+    for (address, timestamp) in timestamps {
+        activity
+            .entry(address.to_lowercase())
+            .or_default()
+            .observe(timestamp);
+    }
+    return activity;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches_with_config;
+
+    #[test]
+    fn test_round_the_clock_high_volume_senders_look_like_bots() {
+        let start = 1640995200;
+        let hourly: Vec<u64> = (0..48).map(|hour| start + hour * 3600).collect();
+        let evenings: Vec<u64> = (0..7)
+            .map(|day| start + day * SECONDS_PER_DAY + 20 * 3600)
+            .collect();
+        let tx_counts = HashMap::from([
+            ("0xATTACKER1".to_string(), 5000),
+            ("0xhuman".to_string(), 5000),
+        ]);
+        let timestamps = hourly
+            .iter()
+            .map(|timestamp| ("0xattacker1", *timestamp))
+            .chain(evenings.iter().map(|timestamp| ("0xhuman", *timestamp)));
+        let activity = address_activity(&tx_counts, timestamps);
+
+        let bot = &activity["0xattacker1"];
+        assert_eq!(bot.active_hours.count_ones(), 24);
+        assert_eq!(bot.bot_factor(100.0), 1.0);
+        let human = &activity["0xhuman"];
+        assert!(human.bot_factor(100.0) < 0.05);
+        assert!(AddressActivity::new(10).bot_factor(100.0) == 0.0);
+
+        let transactions = crate::ingest::csv::sample_transactions();
+        let mut config = Config::default();
+        config.heuristics.weights.base = 0.0;
+        let before = find_same_block_sandwiches_with_config(&transactions, &config).attacks;
+        config.heuristics.activity = Some(activity);
+        let after = find_same_block_sandwiches_with_config(&transactions, &config).attacks;
+        for (before, after) in before.iter().zip(&after) {
+            if after.front_run_tx.from_address == "0xattacker1" {
+                assert_eq!(after.confidence_flags.bot_activity_factor, 1.0);
+                assert!(after.confidence_score > before.confidence_score);
+            } else {
+                assert_eq!(after.confidence_flags.bot_activity_factor, 0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::sandwich_report::SandwichReport;
+use super::transactions::SwapTransaction;
+
+/// Everything one victim (victim swap sender) lost over a set of attacks.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct VictimSummary {
+    pub times_sandwiched: u64,
+    /// Of the attacks the simulation measured.
+    pub total_loss_usd: f64,
+    pub worst_loss_usd: f64,
+    /// Attacks only the heuristics found, whose loss isn't known.
+    pub unknown_loss_count: u64,
+    /// Front-run senders, lowercased.
+    pub attackers: BTreeSet<String>,
+}
+
+/// Roll `attacks` up by victim, keyed by lowercased address. Only simulated
+/// losses are summed, the attacker's profit is a different amount.
+pub fn aggregate_by_victim(attacks: &[SandwichReport]) -> HashMap<String, VictimSummary> {
+    let mut summaries: HashMap<String, VictimSummary> = HashMap::new();
+    for attack in attacks {
+        let summary = summaries
+            .entry(attack.victim_tx.from_address.to_lowercase())
+            .or_default();
+        summary.times_sandwiched += 1;
+        match attack.victim_loss_usd() {
+            Some(loss_usd) => {
+                summary.total_loss_usd += loss_usd;
+                summary.worst_loss_usd = summary.worst_loss_usd.max(loss_usd);
+            }
+            None => summary.unknown_loss_count += 1,
+        }
+        summary
+            .attackers
+            .insert(attack.front_run_tx.from_address.to_lowercase());
+    }
+    return summaries;
+}
+
+/// One row of the attacker leaderboard.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AttackerSummary {
+    /// Front-run sender, lowercased.
+    pub attacker: String,
+    pub attack_count: u64,
+    /// Share of the attacks that made a profit.
+    pub success_rate: f64,
+    pub total_profit_usd: f64,
+    pub median_profit_usd: f64,
+    /// Victim pools, lowercased.
+    pub pools: BTreeSet<String>,
+    pub first_seen_block: u64,
+    pub last_seen_block: u64,
+}
+
+/// Roll `attacks` up by attacker, ranked by total profit, most first. An
+/// attack's profit is the heuristics' estimate, or the simulated round
+/// trip's when only the simulation found it.
+pub fn aggregate_by_attacker(attacks: &[SandwichReport]) -> Vec<AttackerSummary> {
+    let mut by_attacker: BTreeMap<String, Vec<&SandwichReport>> = BTreeMap::new();
+    for attack in attacks {
+        by_attacker
+            .entry(attack.front_run_tx.from_address.to_lowercase())
+            .or_default()
+            .push(attack);
+    }
+
+    let mut leaderboard = Vec::new();
+    for (attacker, attacks) in by_attacker {
+        let mut profits: Vec<f64> = attacks
+            .iter()
+            .map(|attack| {
+                attack
+                    .profit_usd()
+                    .or(attack.simulated_profit_usd)
+                    .unwrap_or(0.0)
+            })
+            .collect();
+        profits.sort_by(f64::total_cmp);
+        let middle = profits.len() / 2;
+        let median_profit_usd = match profits.len() % 2 {
+            0 => (profits[middle - 1] + profits[middle]) / 2.0,
+            _ => profits[middle],
+        };
+        let blocks = attacks.iter().map(|attack| attack.victim_tx.block_number);
+        leaderboard.push(AttackerSummary {
+            attacker,
+            attack_count: attacks.len() as u64,
+            success_rate: profits.iter().filter(|profit| **profit > 0.0).count() as f64
+                / profits.len() as f64,
+            total_profit_usd: profits.iter().sum(),
+            median_profit_usd,
+            pools: attacks
+                .iter()
+                .map(|attack| attack.victim_tx.pool_address.to_lowercase())
+                .collect(),
+            first_seen_block: blocks.clone().min().unwrap_or(0),
+            last_seen_block: blocks.max().unwrap_or(0),
+        });
+    }
+    // Stable, ties stay ordered by address
+    leaderboard.sort_by(|a, b| b.total_profit_usd.total_cmp(&a.total_profit_usd));
+    return leaderboard;
+}
+
+/// How toxic one pool is over a set of attacks.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PoolSummary {
+    pub sandwich_count: u64,
+    /// Of the attacks the simulation measured.
+    pub total_victim_loss_usd: f64,
+    /// Attacks only the heuristics found, whose loss isn't known.
+    pub unknown_loss_count: u64,
+    /// Share of the pool's swaps that were a sandwich's victim.
+    pub sandwiched_swap_share: f64,
+    /// Mean of the victims' price impact rate, over the attacks the
+    /// heuristics found. 0 without any.
+    pub average_price_impact: f64,
+}
+
+/// Roll `attacks` up by victim pool, keyed by lowercased address, with
+/// `transactions` the swaps they were detected in. Only simulated losses
+/// are summed, as in `aggregate_by_victim`.
+pub fn aggregate_by_pool(
+    attacks: &[SandwichReport],
+    transactions: &[SwapTransaction],
+) -> HashMap<String, PoolSummary> {
+    let mut swaps: HashMap<String, u64> = HashMap::new();
+    for tx in transactions {
+        *swaps.entry(tx.pool_address.to_lowercase()).or_default() += 1;
+    }
+
+    let mut summaries: HashMap<String, PoolSummary> = HashMap::new();
+    let mut victims: HashMap<String, BTreeSet<&str>> = HashMap::new();
+    let mut impacts: HashMap<String, Vec<f64>> = HashMap::new();
+    for attack in attacks {
+        let pool = attack.victim_tx.pool_address.to_lowercase();
+        let summary = summaries.entry(pool.clone()).or_default();
+        summary.sandwich_count += 1;
+        match attack.victim_loss_usd() {
+            Some(loss_usd) => summary.total_victim_loss_usd += loss_usd,
+            None => summary.unknown_loss_count += 1,
+        }
+        if let Some(flags) = &attack.confidence_flags {
+            impacts
+                .entry(pool.clone())
+                .or_default()
+                .push(flags.price_impact_rate as f64);
+        }
+        victims
+            .entry(pool)
+            .or_default()
+            .insert(attack.victim_tx.tx_hash.as_str());
+    }
+
+    for (pool, summary) in summaries.iter_mut() {
+        // A victim with several candidate sandwiches counts once
+        let sandwiched = victims[pool].len() as u64;
+        let pool_swaps = swaps.get(pool).copied().unwrap_or(0).max(sandwiched);
+        summary.sandwiched_swap_share = sandwiched as f64 / pool_swaps as f64;
+        if let Some(impacts) = impacts.get(pool) {
+            summary.average_price_impact = impacts.iter().sum::<f64>() / impacts.len() as f64;
+        }
+    }
+    return summaries;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+    use crate::sandwich::sandwich_report::merge_reports;
+
+    #[test]
+    fn test_aggregate_by_victim_totals_losses_and_attackers() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let pool_map = crate::test_support::sample_pool_map();
+        let reports = merge_reports(
+            find_same_block_sandwiches(&transactions),
+            find_sandwich_attacks_by_simulation(&pool_map, &transactions),
+        );
+        assert!(!reports.is_empty());
+
+        // The same victim sandwiched twice, once by another attacker
+        let mut repeated = reports[0].clone();
+        repeated.attack_id.push_str("-again");
+        repeated.front_run_tx.from_address = "0xOTHER".to_string();
+        let mut attacks = reports.clone();
+        attacks.push(repeated.clone());
+
+        let summaries = aggregate_by_victim(&attacks);
+        let times: u64 = summaries
+            .values()
+            .map(|summary| summary.times_sandwiched)
+            .sum();
+        assert_eq!(times, attacks.len() as u64);
+
+        let victim = &summaries[&repeated.victim_tx.from_address.to_lowercase()];
+        assert!(victim.times_sandwiched >= 2);
+        assert!(victim.attackers.contains("0xother"));
+        assert!(victim.attackers.len() >= 2);
+        assert!(victim.worst_loss_usd <= victim.total_loss_usd);
+
+        // Heuristic-only attacks add nothing to the losses
+        let unknown: u64 = summaries
+            .values()
+            .map(|summary| summary.unknown_loss_count)
+            .sum();
+        let heuristic_only = attacks
+            .iter()
+            .filter(|attack| !attack.found_by_simulation())
+            .count();
+        assert!(heuristic_only > 0);
+        assert_eq!(unknown, heuristic_only as u64);
+        let total: f64 = summaries
+            .values()
+            .map(|summary| summary.total_loss_usd)
+            .sum();
+        let simulated: f64 = attacks
+            .iter()
+            .filter_map(|attack| attack.victim_loss_usd())
+            .sum();
+        assert!((total - simulated).abs() < 1e-9);
+        assert!(summaries
+            .values()
+            .any(|summary| summary.worst_loss_usd > 0.0));
+    }
+
+    #[test]
+    fn test_aggregate_by_attacker_ranks_by_total_profit() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let reports = merge_reports(find_same_block_sandwiches(&transactions), Vec::new());
+        let mut attacks = reports.clone();
+        for (block_number, profit) in [(20000, 10.0), (20001, -5.0), (20002, 40.0)] {
+            let mut attack = reports[0].clone();
+            attack.attack_id = format!("0xbot-{}", block_number);
+            attack.front_run_tx.from_address = "0xBOT".to_string();
+            attack.victim_tx.block_number = block_number;
+            attack.confidence_flags.as_mut().unwrap().total_profit_usd = profit;
+            attacks.push(attack);
+        }
+        // Only simulated, a victim loss doesn't make the attack a success
+        let mut simulated = attacks[attacks.len() - 1].clone();
+        simulated.attack_id = "0xbot-20003".to_string();
+        simulated.victim_tx.block_number = 20003;
+        simulated.confidence_score = None;
+        simulated.confidence_flags = None;
+        simulated.victim_loss_percentage = Some(50.0);
+        simulated.simulated_profit_usd = Some(-5.0);
+        attacks.push(simulated);
+
+        let leaderboard = aggregate_by_attacker(&attacks);
+        for pair in leaderboard.windows(2) {
+            assert!(pair[0].total_profit_usd >= pair[1].total_profit_usd);
+        }
+        let total: u64 = leaderboard.iter().map(|row| row.attack_count).sum();
+        assert_eq!(total, attacks.len() as u64);
+
+        let bot = leaderboard
+            .iter()
+            .find(|row| row.attacker == "0xbot")
+            .expect("No row for the bot");
+        assert_eq!(bot.attack_count, 4);
+        assert_eq!(bot.total_profit_usd, 40.0);
+        assert_eq!(bot.median_profit_usd, 2.5);
+        assert_eq!(bot.success_rate, 0.5);
+        assert_eq!((bot.first_seen_block, bot.last_seen_block), (20000, 20003));
+        assert_eq!(bot.pools.len(), 1);
+    }
+
+    #[test]
+    fn test_aggregate_by_pool_counts_sandwiched_share() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let pool_map = crate::test_support::sample_pool_map();
+        let attacks = merge_reports(
+            find_same_block_sandwiches(&transactions),
+            find_sandwich_attacks_by_simulation(&pool_map, &transactions),
+        );
+
+        let summaries = aggregate_by_pool(&attacks, &transactions);
+        let count: u64 = summaries
+            .values()
+            .map(|summary| summary.sandwich_count)
+            .sum();
+        assert_eq!(count, attacks.len() as u64);
+
+        let pool1 = &summaries["0xpool1"];
+        let pool1_swaps = transactions
+            .iter()
+            .filter(|tx| tx.pool_address == "0xpool1")
+            .count();
+        let victims: BTreeSet<_> = attacks
+            .iter()
+            .filter(|attack| attack.victim_tx.pool_address == "0xpool1")
+            .map(|attack| attack.victim_tx.tx_hash.clone())
+            .collect();
+        assert_eq!(
+            pool1.sandwiched_swap_share,
+            victims.len() as f64 / pool1_swaps as f64
+        );
+        assert!(pool1.sandwiched_swap_share > 0.0 && pool1.sandwiched_swap_share < 1.0);
+        assert!(pool1.total_victim_loss_usd > 0.0);
+        assert!(pool1.average_price_impact > 0.0);
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use super::detector::{Block, Detection, Pipeline};
+use super::error::DetectorError;
+use super::transactions::{stream_transactions_by_block, SwapTransaction};
+
+/// Blocks fetched ahead of detection before ingestion waits.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 16;
+
+/// Run `pipeline` over `transactions` without blocking the async runtime.
+///
+/// The ingestion iterator (CSV, RPC, database reads are all blocking) runs on
+/// tokio's blocking pool and hands whole blocks to detection through a channel
+/// of `capacity` blocks, so fetching the next blocks overlaps with detecting
+/// the current one while memory stays bounded. Detection is CPU-bound and runs
+/// on the blocking pool too, only the channel plumbing runs on the runtime. As
+/// with `detect_stream`, the input must be ordered by block. Detections arrive
+/// as soon as their block is done, ingestion stops at the first error, which
+/// is the last message.
+pub fn spawn_detection<I>(
+    pipeline: Arc<Pipeline>,
+    transactions: I,
+    capacity: usize,
+) -> mpsc::Receiver<Result<Detection, DetectorError>>
+where
+    I: IntoIterator<Item = Result<SwapTransaction, String>> + Send + 'static,
+{
+    let (block_sender, mut block_receiver) =
+        mpsc::channel::<Result<Block, DetectorError>>(capacity);
+    let (detection_sender, detection_receiver) = mpsc::channel(capacity);
+
+    tokio::task::spawn_blocking(move || {
+        let mut failure = None;
+        let swaps = transactions.into_iter().map_while(|tx| match tx {
+            Ok(tx) => Some(tx),
+            Err(err) => {
+                failure = Some(DetectorError::Ingestion(err));
+                None
+            }
+        });
+        for block in stream_transactions_by_block(swaps) {
+            if block_sender.blocking_send(Ok(Block::from(block))).is_err() {
+                return;
+            }
+        }
+        if let Some(err) = failure {
+            let _ = block_sender.blocking_send(Err(err));
+        }
+    });
+
+    tokio::spawn(async move {
+        while let Some(block) = block_receiver.recv().await {
+            let detections = match block {
+                Ok(block) => {
+                    let pipeline = Arc::clone(&pipeline);
+                    let detected =
+                        tokio::task::spawn_blocking(move || pipeline.detect_block(&block)).await;
+                    match detected {
+                        Ok(detections) => detections.into_iter().map(Ok).collect(),
+                        // A panicking detector fails the task as it would inline
+                        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+                        // The runtime is shutting down
+                        Err(_) => return,
+                    }
+                }
+                Err(err) => vec![Err(err)],
+            };
+            for detection in detections {
+                if detection_sender.send(detection).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    return detection_receiver;
+}
+
+/// Async counterpart of `Pipeline::run_stream`, collecting every detection
+/// (see `spawn_detection`).
+pub async fn detect_async<I>(
+    pipeline: Arc<Pipeline>,
+    transactions: I,
+) -> Result<Vec<Detection>, DetectorError>
+where
+    I: IntoIterator<Item = Result<SwapTransaction, String>> + Send + 'static,
+{
+    let mut receiver = spawn_detection(pipeline, transactions, DEFAULT_CHANNEL_CAPACITY);
+    let mut detections = Vec::new();
+    while let Some(detection) = receiver.recv().await {
+        detections.push(detection?);
+    }
+    return Ok(detections);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::detector::HeuristicsDetector;
+
+    #[tokio::test]
+    async fn test_detect_async_matches_sync_pipeline() {
+        let pipeline = Arc::new(Pipeline::new().with_detector(HeuristicsDetector::default()));
+        let mut transactions = crate::ingest::csv::sample_transactions();
+        transactions.sort_by_key(|tx: &SwapTransaction| tx.block_id());
+        let expected = pipeline.run(&transactions);
+
+        let detections = detect_async(pipeline.clone(), transactions.clone().into_iter().map(Ok))
+            .await
+            .unwrap();
+        assert_eq!(detections.len(), expected.len());
+
+        let mut failing: Vec<Result<SwapTransaction, String>> =
+            transactions.into_iter().map(Ok).collect();
+        failing.push(Err("connection reset".to_string()));
+        let err = detect_async(pipeline, failing).await.unwrap_err();
+        assert_eq!(
+            err,
+            DetectorError::Ingestion("connection reset".to_string())
+        );
+    }
+}
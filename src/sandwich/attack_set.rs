@@ -0,0 +1,192 @@
+use std::ops::{Deref, RangeBounds};
+
+use super::dedup::Candidate;
+use super::same_block_heuristics::SandwichAttackByHeuristics;
+use super::same_block_sim::SandwichAttackBySimulation;
+use super::scoring::rescore;
+use crate::config::ConfidenceWeights;
+
+/// Detected attacks with chainable filters and rankings, e.g.
+/// `AttackSet::from(attacks).pool("0xpool1").min_confidence(0.7).sort_by_profit().top(10)`.
+///
+/// Sorts are stable, so an earlier sort breaks ties of a later one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttackSet<A> {
+    attacks: Vec<A>,
+}
+
+impl<A> From<Vec<A>> for AttackSet<A> {
+    fn from(attacks: Vec<A>) -> Self {
+        Self { attacks }
+    }
+}
+
+impl<A> FromIterator<A> for AttackSet<A> {
+    fn from_iter<I: IntoIterator<Item = A>>(attacks: I) -> Self {
+        Self {
+            attacks: attacks.into_iter().collect(),
+        }
+    }
+}
+
+impl<A> Deref for AttackSet<A> {
+    type Target = [A];
+
+    fn deref(&self) -> &[A] {
+        &self.attacks
+    }
+}
+
+impl<A> IntoIterator for AttackSet<A> {
+    type Item = A;
+    type IntoIter = std::vec::IntoIter<A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.attacks.into_iter()
+    }
+}
+
+impl<A> AttackSet<A> {
+    pub fn into_vec(self) -> Vec<A> {
+        return self.attacks;
+    }
+
+    pub fn filter<F: FnMut(&A) -> bool>(mut self, predicate: F) -> Self {
+        self.attacks.retain(predicate);
+        return self;
+    }
+
+    /// Keep the first `n`, e.g. after sorting.
+    pub fn top(mut self, n: usize) -> Self {
+        self.attacks.truncate(n);
+        return self;
+    }
+}
+
+impl<A: Candidate> AttackSet<A> {
+    /// Attacks on the victim's pool.
+    pub fn pool(self, pool_address: &str) -> Self {
+        return self.filter(|attack| attack.victim().pool_address == pool_address);
+    }
+
+    /// Attacks whose front-run was sent by `address`.
+    pub fn attacker(self, address: &str) -> Self {
+        return self.filter(|attack| attack.front_run().from_address == address);
+    }
+
+    pub fn blocks<R: RangeBounds<u64>>(self, range: R) -> Self {
+        return self.filter(|attack| range.contains(&attack.victim().block_number));
+    }
+
+    /// Oldest block first, then by position in the block.
+    pub fn sort_by_block(mut self) -> Self {
+        self.attacks.sort_by_key(|attack| {
+            let victim = attack.victim();
+            (victim.block_number, victim.tx_position_in_block)
+        });
+        return self;
+    }
+
+    pub fn sort_by_pool(mut self) -> Self {
+        self.attacks
+            .sort_by(|a, b| a.victim().pool_address.cmp(&b.victim().pool_address));
+        return self;
+    }
+
+    pub fn sort_by_attacker(mut self) -> Self {
+        self.attacks
+            .sort_by(|a, b| a.front_run().from_address.cmp(&b.front_run().from_address));
+        return self;
+    }
+}
+
+impl AttackSet<SandwichAttackByHeuristics> {
+    /// See `scoring::rescore`.
+    pub fn rescore(mut self, weights: &ConfidenceWeights) -> Self {
+        rescore(&mut self.attacks, weights);
+        return self;
+    }
+
+    pub fn min_confidence(self, min_confidence: f32) -> Self {
+        return self.filter(|attack| attack.confidence_score >= min_confidence);
+    }
+
+    pub fn min_profit(self, min_profit_usd: f64) -> Self {
+        return self.filter(|attack| attack.confidence_flags.total_profit_usd >= min_profit_usd);
+    }
+
+    /// Most confident first.
+    pub fn sort_by_confidence(mut self) -> Self {
+        self.attacks
+            .sort_by(|a, b| b.confidence_score.total_cmp(&a.confidence_score));
+        return self;
+    }
+
+    /// Most profitable first.
+    pub fn sort_by_profit(mut self) -> Self {
+        self.attacks.sort_by(|a, b| {
+            b.confidence_flags
+                .total_profit_usd
+                .total_cmp(&a.confidence_flags.total_profit_usd)
+        });
+        return self;
+    }
+}
+
+impl AttackSet<SandwichAttackBySimulation> {
+    /// `min_loss_percentage` as in `victim_loss_percentage`, e.g. `1.0` for 1%.
+    pub fn min_victim_loss(self, min_loss_percentage: f64) -> Self {
+        return self.filter(|attack| attack.victim_loss_percentage >= min_loss_percentage);
+    }
+
+    /// Biggest victim loss first.
+    pub fn sort_by_victim_loss(mut self) -> Self {
+        self.attacks.sort_by(|a, b| {
+            b.victim_loss_percentage
+                .total_cmp(&a.victim_loss_percentage)
+        });
+        return self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+
+    #[test]
+    fn test_filter_sort_and_take_top() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = AttackSet::from(find_same_block_sandwiches(&transactions));
+        let total = attacks.len();
+
+        let top = attacks.clone().sort_by_profit().top(2);
+        assert_eq!(top.len(), 2);
+        assert!(
+            top[0].confidence_flags.total_profit_usd >= top[1].confidence_flags.total_profit_usd
+        );
+        assert!(attacks.iter().all(|attack| {
+            attack.confidence_flags.total_profit_usd <= top[0].confidence_flags.total_profit_usd
+        }));
+
+        let pool1 = attacks
+            .clone()
+            .pool("0xpool1")
+            .min_confidence(0.5)
+            .sort_by_block();
+        assert!(!pool1.is_empty() && pool1.len() < total);
+        assert!(pool1
+            .iter()
+            .all(|attack| attack.victim_tx.pool_address == "0xpool1"));
+        assert!(pool1
+            .windows(2)
+            .all(|pair| pair[0].victim_tx.block_number <= pair[1].victim_tx.block_number));
+
+        let first_block = pool1[0].victim_tx.block_number;
+        let attacker = pool1[0].front_run_tx.from_address.clone();
+        let single = attacks
+            .blocks(first_block..=first_block)
+            .attacker(&attacker);
+        assert_eq!(single.len(), 1);
+    }
+}
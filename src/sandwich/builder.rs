@@ -0,0 +1,292 @@
+#[cfg(feature = "rules")]
+use std::sync::Arc;
+
+use super::detector::{Block, Detection, Detector};
+use super::error::{DetectionOutcome, DetectorError};
+#[cfg(feature = "rules")]
+use super::rules::RuleSet;
+use super::same_block_heuristics::{
+    find_same_block_sandwiches_with_config, find_sandwiches_in_block, SandwichAttackByHeuristics,
+};
+use super::scoring::{ConfidenceScorer, Scorer};
+#[cfg(feature = "rules")]
+use super::severity::assign_severity;
+use super::tokens::TokenEquivalence;
+use super::transactions::{stream_transactions_by_block, BlockId, SwapTransaction};
+use crate::config::{ConfidenceWeights, Config};
+
+/// Heuristics sandwich detection with its parameters, for library users who
+/// tune detection in code rather than through a config file:
+///
+/// ```
+/// use toxicflow_detector::sandwich::SandwichDetector;
+///
+/// let detector = SandwichDetector::builder()
+///     .min_confidence(0.7)
+///     .require_same_pool(true)
+///     .max_victims(5)
+///     .build();
+/// assert_eq!(detector.config().heuristics.max_victims, Some(5));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SandwichDetector {
+    config: Config,
+    #[cfg(feature = "rules")]
+    rules: Option<Arc<RuleSet>>,
+}
+
+impl SandwichDetector {
+    pub fn new(config: Config) -> Self {
+        return Self {
+            config,
+            #[cfg(feature = "rules")]
+            rules: None,
+        };
+    }
+
+    pub fn builder() -> SandwichDetectorBuilder {
+        return SandwichDetectorBuilder::default();
+    }
+
+    pub fn config(&self) -> &Config {
+        return &self.config;
+    }
+
+    /// See `find_same_block_sandwiches_with_config`.
+    pub fn find_sandwiches(
+        &self,
+        transactions: &[SwapTransaction],
+    ) -> DetectionOutcome<SandwichAttackByHeuristics> {
+        let mut outcome = find_same_block_sandwiches_with_config(transactions, &self.config);
+        outcome.attacks = self.apply_rules(outcome.attacks, &mut outcome.skipped);
+        return outcome;
+    }
+
+    /// See `same_block_heuristics::detect_stream`. A failed user rule is
+    /// yielded as an error, ahead of the attacks of its block.
+    pub fn detect_stream<I>(
+        &self,
+        transactions: I,
+    ) -> impl Iterator<Item = Result<SandwichAttackByHeuristics, (BlockId, DetectorError)>>
+    where
+        I: IntoIterator<Item = SwapTransaction>,
+    {
+        let detector = self.clone();
+        stream_transactions_by_block(transactions)
+            .filter(|(_block_id, block_transactions)| block_transactions.len() >= 3)
+            .flat_map(move |(_block_id, block_transactions)| {
+                let attacks = find_sandwiches_in_block(&block_transactions, &detector.config)
+                    .unwrap_or_default();
+                let mut errors = Vec::new();
+                let kept = detector.apply_rules(attacks, &mut errors);
+                errors
+                    .into_iter()
+                    .map(Err)
+                    .chain(kept.into_iter().map(Ok))
+                    .collect::<Vec<_>>()
+            })
+    }
+
+    /// Run the user rules (if any) on `attacks`, in block order, dropping
+    /// vetoed ones. An attack whose rule fails is kept as is and the error
+    /// recorded. Severity is assigned again afterwards, per block, so the
+    /// weight rules add counts towards `SeverityConfig::confident`.
+    #[cfg(feature = "rules")]
+    fn apply_rules(
+        &self,
+        attacks: Vec<SandwichAttackByHeuristics>,
+        errors: &mut Vec<(BlockId, DetectorError)>,
+    ) -> Vec<SandwichAttackByHeuristics> {
+        let rules = match &self.rules {
+            Some(rules) => rules,
+            None => return attacks,
+        };
+        let mut kept = Vec::new();
+        for mut attack in attacks {
+            match rules.apply(&mut attack) {
+                Ok(true) => kept.push(attack),
+                Ok(false) => {}
+                Err(err) => {
+                    errors.push((attack.victim_tx.block_id(), err));
+                    kept.push(attack);
+                }
+            }
+        }
+        for block_attacks in
+            kept.chunk_by_mut(|a, b| a.victim_tx.block_id() == b.victim_tx.block_id())
+        {
+            assign_severity(block_attacks, &self.config.severity);
+        }
+        return kept;
+    }
+
+    #[cfg(not(feature = "rules"))]
+    fn apply_rules(
+        &self,
+        attacks: Vec<SandwichAttackByHeuristics>,
+        _errors: &mut Vec<(BlockId, DetectorError)>,
+    ) -> Vec<SandwichAttackByHeuristics> {
+        return attacks;
+    }
+}
+
+impl Detector for SandwichDetector {
+    fn name(&self) -> &str {
+        "heuristics"
+    }
+
+    fn detect(&self, block: &Block) -> Vec<Detection> {
+        return self.detect_outcome(block).attacks;
+    }
+
+    fn detect_outcome(&self, block: &Block) -> DetectionOutcome<Detection> {
+        let mut outcome = DetectionOutcome::default();
+        let attacks = match find_sandwiches_in_block(&block.transactions, &self.config) {
+            Ok(attacks) => attacks,
+            Err(err) => {
+                outcome.skipped.push((block.id, err));
+                Vec::new()
+            }
+        };
+        outcome.attacks = self
+            .apply_rules(attacks, &mut outcome.skipped)
+            .into_iter()
+            .map(Detection::Heuristics)
+            .collect();
+        return outcome;
+    }
+}
+
+/// Fluent builder for `SandwichDetector`, starting from the default `Config`.
+#[derive(Debug, Clone, Default)]
+pub struct SandwichDetectorBuilder {
+    config: Config,
+    #[cfg(feature = "rules")]
+    rules: Option<Arc<RuleSet>>,
+}
+
+impl SandwichDetectorBuilder {
+    /// Start from `config` (e.g. loaded from a file) instead of the defaults.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        return self;
+    }
+
+    pub fn min_confidence(mut self, min_confidence: f32) -> Self {
+        self.config.heuristics.min_confidence = min_confidence;
+        return self;
+    }
+
+    pub fn require_same_pool(mut self, require_same_pool: bool) -> Self {
+        self.config.heuristics.require_same_pool = require_same_pool;
+        return self;
+    }
+
+    pub fn max_victims(mut self, max_victims: usize) -> Self {
+        self.config.heuristics.max_victims = Some(max_victims);
+        return self;
+    }
+
+    /// Front-run share of the victim's volume counted as proportional.
+    pub fn front_ratio_range(mut self, min: f64, max: f64) -> Self {
+        self.config.heuristics.min_front_ratio = min;
+        self.config.heuristics.max_front_ratio = max;
+        return self;
+    }
+
+    pub fn max_back_to_front_ratio(mut self, ratio: f64) -> Self {
+        self.config.heuristics.max_back_to_front_ratio = ratio;
+        return self;
+    }
+
+    pub fn weights(mut self, weights: ConfidenceWeights) -> Self {
+        self.config.heuristics.weights = weights;
+        return self;
+    }
+
+    /// Score candidates with `scorer` instead of the additive weights.
+    pub fn scorer<S: ConfidenceScorer + 'static>(mut self, scorer: S) -> Self {
+        self.config.heuristics.scorer = Some(Scorer::new(scorer));
+        return self;
+    }
+
+    /// Add an equivalence group, or replace the group of that name.
+    pub fn token_group(mut self, name: &str, tokens: &[&str]) -> Self {
+        let tokens = tokens.iter().map(|token| token.to_string()).collect();
+        self.config.tokens.groups.insert(name.to_string(), tokens);
+        return self;
+    }
+
+    pub fn tokens(mut self, tokens: TokenEquivalence) -> Self {
+        self.config.tokens = tokens;
+        return self;
+    }
+
+    pub fn keep_raw_candidates(mut self, keep_raw_candidates: bool) -> Self {
+        self.config.keep_raw_candidates = keep_raw_candidates;
+        return self;
+    }
+
+    /// User rules run on every candidate, see `RuleSet`.
+    #[cfg(feature = "rules")]
+    pub fn rules(mut self, rules: RuleSet) -> Self {
+        self.rules = Some(Arc::new(rules));
+        return self;
+    }
+
+    pub fn build(self) -> SandwichDetector {
+        return SandwichDetector {
+            config: self.config,
+            #[cfg(feature = "rules")]
+            rules: self.rules,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+
+    #[test]
+    fn test_builder_options_filter_attacks() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        // Blocks are grouped in a HashMap, so compare sorted
+        let sorted = |mut attacks: Vec<SandwichAttackByHeuristics>| {
+            attacks.sort_by_key(|attack| attack.attack_id());
+            attacks
+        };
+        let all = sorted(find_same_block_sandwiches(&transactions));
+        let built = SandwichDetector::builder().build();
+        assert_eq!(sorted(built.find_sandwiches(&transactions).attacks), all);
+
+        let confident = SandwichDetector::builder()
+            .min_confidence(0.9)
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+        assert!(confident.len() < all.len());
+        assert!(confident
+            .iter()
+            .all(|attack| attack.confidence_score >= 0.9));
+
+        let same_pool = SandwichDetector::builder()
+            .require_same_pool(true)
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+        assert!(all
+            .iter()
+            .any(|attack| attack.victim_tx.tx_hash == "0xcrossdex_victim"));
+        assert!(same_pool
+            .iter()
+            .all(|attack| attack.front_run_tx.pool_address == attack.back_run_tx.pool_address));
+
+        let no_victims = SandwichDetector::builder()
+            .max_victims(0)
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+        assert!(no_victims.is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use super::same_block_heuristics::{ConfidenceFlags, SandwichAttackByHeuristics};
+use super::scoring::ConfidenceScorer;
+use super::transactions::SwapTransaction;
+
+/// Newton iterations of `PlattScaling::fit`.
+const MAX_ITERATIONS: usize = 100;
+
+/// Maps a raw confidence score to the probability that the candidate is an
+/// attack, `1 / (1 + exp(a * score + b))`, fitted on labeled candidates.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PlattScaling {
+    pub a: f64,
+    pub b: f64,
+}
+
+impl PlattScaling {
+    /// Fit on `(score, is_attack)` pairs, following Platt (1999) with the
+    /// Newton method of Lin, Lin and Weng (2007). Needs both attacks and
+    /// non-attacks.
+    pub fn fit(samples: &[(f32, bool)]) -> Result<Self, String> {
+        let positives = samples.iter().filter(|(_, is_attack)| *is_attack).count() as f64;
+        let negatives = samples.len() as f64 - positives;
+        if positives == 0.0 || negatives == 0.0 {
+            return Err("calibration needs both attacks and non-attacks".to_string());
+        }
+
+        // Smoothed targets, so perfectly separable samples don't diverge
+        let high_target = (positives + 1.0) / (positives + 2.0);
+        let low_target = 1.0 / (negatives + 2.0);
+        let samples: Vec<(f64, f64)> = samples
+            .iter()
+            .map(|(score, is_attack)| {
+                let target = if *is_attack { high_target } else { low_target };
+                (f64::from(*score), target)
+            })
+            .collect();
+
+        let mut scaling = Self {
+            a: 0.0,
+            b: ((negatives + 1.0) / (positives + 1.0)).ln(),
+        };
+        let mut loss = scaling.loss(&samples);
+        for _ in 0..MAX_ITERATIONS {
+            // Gradient and Hessian of the log loss, slightly regularized
+            let (mut g1, mut g2) = (0.0, 0.0);
+            let (mut h11, mut h22, mut h21) = (1e-12, 1e-12, 0.0);
+            for (score, target) in &samples {
+                let p = scaling.probability_f64(*score);
+                let d2 = p * (1.0 - p);
+                h11 += score * score * d2;
+                h22 += d2;
+                h21 += score * d2;
+                let d1 = target - p;
+                g1 += score * d1;
+                g2 += d1;
+            }
+            if g1.abs() < 1e-5 && g2.abs() < 1e-5 {
+                break;
+            }
+
+            let det = h11 * h22 - h21 * h21;
+            let da = -(h22 * g1 - h21 * g2) / det;
+            let db = -(-h21 * g1 + h11 * g2) / det;
+            let descent = g1 * da + g2 * db;
+
+            let mut step = 1.0;
+            while step >= 1e-10 {
+                let candidate = Self {
+                    a: scaling.a + step * da,
+                    b: scaling.b + step * db,
+                };
+                let candidate_loss = candidate.loss(&samples);
+                if candidate_loss < loss + 1e-4 * step * descent {
+                    scaling = candidate;
+                    loss = candidate_loss;
+                    break;
+                }
+                step /= 2.0;
+            }
+            if step < 1e-10 {
+                break;
+            }
+        }
+        return Ok(scaling);
+    }
+
+    /// Probability that a candidate scoring `score` is an attack.
+    pub fn probability(&self, score: f32) -> f32 {
+        return self.probability_f64(f64::from(score)) as f32;
+    }
+
+    fn probability_f64(&self, score: f64) -> f64 {
+        let exponent = self.a * score + self.b;
+        // Two forms so exp never overflows
+        if exponent >= 0.0 {
+            let e = (-exponent).exp();
+            return e / (1.0 + e);
+        }
+        return 1.0 / (1.0 + exponent.exp());
+    }
+
+    /// Cross-entropy of the scaled scores against `(score, target)` pairs.
+    fn loss(&self, samples: &[(f64, f64)]) -> f64 {
+        return samples
+            .iter()
+            .map(|(score, target)| {
+                let exponent = self.a * score + self.b;
+                if exponent >= 0.0 {
+                    target * exponent + (-exponent).exp().ln_1p()
+                } else {
+                    (target - 1.0) * exponent + exponent.exp().ln_1p()
+                }
+            })
+            .sum();
+    }
+}
+
+/// Mean squared error of scores read as probabilities; lower is better
+/// calibrated.
+pub fn brier_score(samples: &[(f32, bool)]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let total: f64 = samples
+        .iter()
+        .map(|(score, is_attack)| {
+            let outcome = if *is_attack { 1.0 } else { 0.0 };
+            (f64::from(*score) - outcome).powi(2)
+        })
+        .sum();
+    return total / samples.len() as f64;
+}
+
+/// Ground truth as CSV with `attack_id` and `is_attack` columns.
+pub fn read_labels<R: Read>(reader: R) -> Result<HashMap<String, bool>, String> {
+    #[derive(serde::Deserialize)]
+    struct Label {
+        attack_id: String,
+        is_attack: bool,
+    }
+
+    let mut labels = HashMap::new();
+    for row in csv::Reader::from_reader(reader).deserialize() {
+        let label: Label = row.map_err(|err| format!("invalid label row: {}", err))?;
+        labels.insert(label.attack_id, label.is_attack);
+    }
+    return Ok(labels);
+}
+
+/// `(confidence_score, is_attack)` of the candidates that have a label.
+/// Run detection with `keep_raw_candidates` and no `min_confidence` so the
+/// samples include the candidates the config would drop.
+pub fn labeled_samples(
+    candidates: &[SandwichAttackByHeuristics],
+    labels: &HashMap<String, bool>,
+) -> Vec<(f32, bool)> {
+    return candidates
+        .iter()
+        .filter_map(|candidate| {
+            labels
+                .get(&candidate.attack_id())
+                .map(|is_attack| (candidate.confidence_score, *is_attack))
+        })
+        .collect();
+}
+
+/// Another scorer's score mapped through a `PlattScaling`, so the
+/// confidence reads as a probability.
+#[derive(Debug)]
+pub struct CalibratedScorer<S> {
+    pub scorer: S,
+    pub scaling: PlattScaling,
+}
+
+impl<S: ConfidenceScorer> ConfidenceScorer for CalibratedScorer<S> {
+    fn score(
+        &self,
+        front: &SwapTransaction,
+        victim: &SwapTransaction,
+        back: &SwapTransaction,
+        flags: &ConfidenceFlags,
+    ) -> f32 {
+        let raw = self.scorer.score(front, victim, back, flags);
+        return self.scaling.probability(raw);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_platt_scaling_improves_calibration() {
+        // Raw scores that overstate confidence: only a third of the
+        // candidates scoring 0.9 are attacks, and none scoring 0.3
+        let mut samples = Vec::new();
+        for i in 0..30 {
+            samples.push((0.9, i % 3 == 0));
+            samples.push((0.6, i % 10 == 0));
+            samples.push((0.3, false));
+        }
+        samples.push((0.3, true));
+
+        let scaling = PlattScaling::fit(&samples).expect("Failed to fit");
+        assert!(scaling.probability(0.9) > scaling.probability(0.6));
+        assert!((scaling.probability(0.9) - 1.0 / 3.0).abs() < 0.1);
+
+        let calibrated: Vec<(f32, bool)> = samples
+            .iter()
+            .map(|(score, is_attack)| (scaling.probability(*score), *is_attack))
+            .collect();
+        assert!(brier_score(&calibrated) < brier_score(&samples));
+
+        assert!(PlattScaling::fit(&[(0.9, true)]).is_err());
+
+        let labels = read_labels("attack_id,is_attack\n0xa_0xb_0xc,true\n".as_bytes()).unwrap();
+        assert_eq!(labels.get("0xa_0xb_0xc"), Some(&true));
+    }
+}
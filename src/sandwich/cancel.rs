@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Stops a long scan from another thread or after a deadline.
+///
+/// Detection checks it between blocks (and between candidates when
+/// simulating), returning what it found so far with `truncated` set.
+/// Clones share the same cancellation flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        return self;
+    }
+
+    /// Deadline `budget` from now.
+    pub fn with_time_budget(self, budget: Duration) -> Self {
+        return self.with_deadline(Instant::now() + budget);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return true;
+        }
+        return self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches_until;
+
+    #[test]
+    fn test_cancelled_scan_returns_partial_results() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let config = Config::default();
+
+        let token = CancellationToken::new();
+        let complete = find_same_block_sandwiches_until(&transactions, &config, &token);
+        assert!(!complete.truncated);
+        assert!(!complete.attacks.is_empty());
+
+        let clone = token.clone();
+        clone.cancel();
+        assert!(token.is_cancelled());
+        let cancelled = find_same_block_sandwiches_until(&transactions, &config, &token);
+        assert!(cancelled.truncated);
+        assert!(cancelled.attacks.is_empty());
+
+        let expired = CancellationToken::new().with_time_budget(Duration::ZERO);
+        assert!(expired.is_cancelled());
+        assert!(!CancellationToken::new()
+            .with_time_budget(Duration::from_secs(60))
+            .is_cancelled());
+    }
+}
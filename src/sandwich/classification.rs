@@ -0,0 +1,163 @@
+use super::transactions::SwapTransaction;
+
+/// Top-level MEV category of a result, following EigenPhi's taxonomy.
+///
+/// The detectors in this crate only produce `Sandwich`; the other categories
+/// are part of the taxonomy so results from other sources share the same labels.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum MevCategory {
+    /// Front-run and back-run by the same attacker around a victim swap.
+    #[default]
+    Sandwich,
+    /// Price differences closed across pools within a transaction.
+    Arbitrage,
+    /// Just-in-time liquidity added and removed around a swap.
+    Jit,
+    /// Undercollateralised positions closed for a bonus.
+    Liquidation,
+    /// Several of the above in one attack, e.g. a sandwich whose back-run arbitrages.
+    Hybrid,
+}
+
+/// Finer-grained properties of an attack within its category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SubLabel {
+    /// The back-run goes through a different pool than the front-run.
+    CrossDex,
+    /// The legs trade economically equivalent tokens (e.g. WETH/ETH) rather than the same ones.
+    EquivalentToken,
+    /// The same front- and back-run wrap more than one victim.
+    MultiVictim,
+    /// The back-run lands in a later block than the front-run.
+    MultiBlock,
+}
+
+/// The single most specific shape of a sandwich, for consumers that want
+/// one value to group or filter by rather than the full set of `SubLabel`s.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SandwichVariant {
+    /// All three legs in one pool, trading the same tokens.
+    #[default]
+    ClassicSamePool,
+    CrossDex,
+    EquivalentToken,
+    MultiVictim,
+    MultiBlock,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct Classification {
+    pub category: MevCategory,
+    pub labels: Vec<SubLabel>,
+    /// Picked from `labels`, see `classify_sandwich`.
+    #[serde(default)]
+    pub variant: SandwichVariant,
+}
+
+impl Classification {
+    pub fn has_label(&self, label: SubLabel) -> bool {
+        return self.labels.contains(&label);
+    }
+}
+
+/// Classify a sandwich from its three legs.
+///
+/// `victims` is how many victims the same front- and back-run wrap. When
+/// several labels apply, the variant is the first of multi-block,
+/// multi-victim, cross-DEX and equivalent-token.
+pub fn classify_sandwich(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+    victims: usize,
+) -> Classification {
+    let mut labels = Vec::new();
+
+    if front.pool_address != back.pool_address {
+        labels.push(SubLabel::CrossDex);
+    }
+
+    let same_tokens = front.token_in == back.token_out
+        && front.token_out == back.token_in
+        && front.token_in == victim.token_in
+        && front.token_out == victim.token_out;
+    if !same_tokens {
+        labels.push(SubLabel::EquivalentToken);
+    }
+
+    if victims > 1 {
+        labels.push(SubLabel::MultiVictim);
+    }
+
+    if front.block_id() != back.block_id() {
+        labels.push(SubLabel::MultiBlock);
+    }
+
+    let variant = if labels.contains(&SubLabel::MultiBlock) {
+        SandwichVariant::MultiBlock
+    } else if labels.contains(&SubLabel::MultiVictim) {
+        SandwichVariant::MultiVictim
+    } else if labels.contains(&SubLabel::CrossDex) {
+        SandwichVariant::CrossDex
+    } else if labels.contains(&SubLabel::EquivalentToken) {
+        SandwichVariant::EquivalentToken
+    } else {
+        SandwichVariant::ClassicSamePool
+    };
+
+    return Classification {
+        category: MevCategory::Sandwich,
+        labels,
+        variant,
+    };
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::find_same_block_sandwiches;
+
+    #[test]
+    fn test_sub_labels_on_sample_sandwiches() {
+        let mut transactions = crate::ingest::csv::sample_transactions();
+
+        // A second victim between the same front- and back-run of block 12360
+        let mut second_victim = transactions
+            .iter()
+            .find(|tx| tx.tx_hash == "0xvictim001")
+            .unwrap()
+            .clone();
+        second_victim.tx_hash = "0xvictim001b".to_string();
+        second_victim.from_address = "0xvictim1b".to_string();
+        transactions.push(second_victim);
+
+        let attacks = find_same_block_sandwiches(&transactions);
+        let classification = |victim: &str| {
+            let attack = attacks
+                .iter()
+                .find(|attack| attack.victim_tx.tx_hash == victim)
+                .unwrap();
+            assert_eq!(attack.classification.category, MevCategory::Sandwich);
+            attack.classification.clone()
+        };
+        let labels = |victim: &str| classification(victim).labels;
+        let variant = |victim: &str| classification(victim).variant;
+
+        assert_eq!(labels("0xvictim002"), []);
+        assert_eq!(labels("0xcrossdex_victim"), [SubLabel::CrossDex]);
+        assert_eq!(labels("0xweth_victim"), [SubLabel::EquivalentToken]);
+        assert_eq!(labels("0xvictim001"), [SubLabel::MultiVictim]);
+        assert_eq!(labels("0xvictim001b"), [SubLabel::MultiVictim]);
+
+        assert_eq!(variant("0xvictim002"), SandwichVariant::ClassicSamePool);
+        assert_eq!(variant("0xcrossdex_victim"), SandwichVariant::CrossDex);
+        assert_eq!(variant("0xweth_victim"), SandwichVariant::EquivalentToken);
+        assert_eq!(variant("0xvictim001"), SandwichVariant::MultiVictim);
+    }
+}
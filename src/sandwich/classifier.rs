@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use smartcore::linalg::basic::arrays::Array;
+use smartcore::linalg::basic::matrix::DenseMatrix;
+use smartcore::linear::logistic_regression::{LogisticRegression, LogisticRegressionParameters};
+
+use super::features::{extract, FeatureContext, FeatureVector, FEATURE_NAMES, FEATURE_VERSION};
+use super::same_block_heuristics::{ConfidenceFlags, SandwichAttackByHeuristics};
+use super::scoring::ConfidenceScorer;
+use super::transactions::SwapTransaction;
+
+/// Logistic regression over the feature vectors, usable as a
+/// `ConfidenceScorer` in place of the additive weights.
+///
+/// Features are standardized with the means and scales of the training
+/// set. A scorer only sees the three legs, so the model is trained on the
+/// features `extract` gets from them alone; the block and history counts
+/// are 0 on both sides.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LogisticModel {
+    pub feature_version: u32,
+    pub means: Vec<f64>,
+    pub scales: Vec<f64>,
+    pub coefficients: Vec<f64>,
+    pub intercept: f64,
+}
+
+impl LogisticModel {
+    /// Train on candidates labeled as attacks or not, e.g. with
+    /// `calibration::read_labels`. Unlabeled candidates are left out.
+    pub fn fit(
+        candidates: &[SandwichAttackByHeuristics],
+        labels: &HashMap<String, bool>,
+    ) -> Result<Self, String> {
+        let mut features = Vec::new();
+        let mut targets = Vec::new();
+        for candidate in candidates {
+            if let Some(is_attack) = labels.get(&candidate.attack_id()) {
+                features.push(legs_features(
+                    &candidate.front_run_tx,
+                    &candidate.victim_tx,
+                    &candidate.back_run_tx,
+                ));
+                targets.push(*is_attack);
+            }
+        }
+        return Self::fit_features(&features, &targets);
+    }
+
+    /// Train on feature vectors, `is_attack[i]` labeling `features[i]`.
+    pub fn fit_features(features: &[FeatureVector], is_attack: &[bool]) -> Result<Self, String> {
+        if features.len() != is_attack.len() {
+            return Err("expected one label per feature vector".to_string());
+        }
+        if features
+            .iter()
+            .any(|feature| feature.version != FEATURE_VERSION)
+        {
+            return Err(format!("expected features of version {}", FEATURE_VERSION));
+        }
+        if !is_attack.contains(&true) || !is_attack.contains(&false) {
+            return Err("training needs both attacks and non-attacks".to_string());
+        }
+
+        let rows: Vec<[f64; FEATURE_NAMES.len()]> =
+            features.iter().map(|feature| feature.values()).collect();
+        let count = rows.len() as f64;
+        let mut means = vec![0.0; FEATURE_NAMES.len()];
+        let mut scales = vec![0.0; FEATURE_NAMES.len()];
+        for column in 0..FEATURE_NAMES.len() {
+            let mean = rows.iter().map(|row| row[column]).sum::<f64>() / count;
+            let variance = rows
+                .iter()
+                .map(|row| (row[column] - mean).powi(2))
+                .sum::<f64>()
+                / count;
+            means[column] = mean;
+            // Constant features (e.g. counts without context) stay at 0
+            scales[column] = if variance > 0.0 { variance.sqrt() } else { 1.0 };
+        }
+
+        let standardized: Vec<Vec<f64>> = rows
+            .iter()
+            .map(|row| standardize(row, &means, &scales))
+            .collect();
+        let x = DenseMatrix::from_2d_vec(&standardized)
+            .map_err(|err| format!("invalid training data: {}", err))?;
+        let y: Vec<i32> = is_attack
+            .iter()
+            .map(|is_attack| *is_attack as i32)
+            .collect();
+        let regression = LogisticRegression::fit(&x, &y, LogisticRegressionParameters::default())
+            .map_err(|err| format!("failed to train classifier: {}", err))?;
+
+        let coefficients = (0..FEATURE_NAMES.len())
+            .map(|column| *regression.coefficients().get((0, column)))
+            .collect();
+        return Ok(Self {
+            feature_version: FEATURE_VERSION,
+            means,
+            scales,
+            coefficients,
+            intercept: *regression.intercept().get((0, 0)),
+        });
+    }
+
+    /// Read a model saved with `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        let model: Self = serde_json::from_str(&text)
+            .map_err(|err| format!("invalid model {}: {}", path.display(), err))?;
+        if model.feature_version != FEATURE_VERSION {
+            return Err(format!(
+                "{} was trained on features of version {}, expected {}",
+                path.display(),
+                model.feature_version,
+                FEATURE_VERSION
+            ));
+        }
+        if model.means.len() != FEATURE_NAMES.len()
+            || model.scales.len() != FEATURE_NAMES.len()
+            || model.coefficients.len() != FEATURE_NAMES.len()
+        {
+            return Err(format!(
+                "{}: expected {} features",
+                path.display(),
+                FEATURE_NAMES.len()
+            ));
+        }
+        return Ok(model);
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|err| format!("failed to encode model: {}", err))?;
+        return std::fs::write(path, text)
+            .map_err(|err| format!("failed to write {}: {}", path.display(), err));
+    }
+
+    /// Probability that the candidate described by `features` is an attack.
+    pub fn probability(&self, features: &FeatureVector) -> f64 {
+        let standardized = standardize(&features.values(), &self.means, &self.scales);
+        let logit: f64 = self.intercept
+            + standardized
+                .iter()
+                .zip(&self.coefficients)
+                .map(|(value, coefficient)| value * coefficient)
+                .sum::<f64>();
+        return 1.0 / (1.0 + (-logit).exp());
+    }
+}
+
+impl ConfidenceScorer for LogisticModel {
+    fn score(
+        &self,
+        front: &SwapTransaction,
+        victim: &SwapTransaction,
+        back: &SwapTransaction,
+        _flags: &ConfidenceFlags,
+    ) -> f32 {
+        return self.probability(&legs_features(front, victim, back)) as f32;
+    }
+}
+
+fn legs_features(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+) -> FeatureVector {
+    return extract(front, victim, back, &FeatureContext::default());
+}
+
+fn standardize(values: &[f64], means: &[f64], scales: &[f64]) -> Vec<f64> {
+    return values
+        .iter()
+        .zip(means.iter().zip(scales))
+        .map(|(value, (mean, scale))| (value - mean) / scale)
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::builder::SandwichDetector;
+
+    #[test]
+    fn test_trained_model_ranks_profitable_candidates_higher() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let candidates = SandwichDetector::builder()
+            .keep_raw_candidates(true)
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+
+        // Pretend only the profitable candidates were confirmed
+        let labels: HashMap<String, bool> = candidates
+            .iter()
+            .map(|candidate| {
+                (
+                    candidate.attack_id(),
+                    candidate.confidence_flags.is_profitable,
+                )
+            })
+            .collect();
+        let model = LogisticModel::fit(&candidates, &labels).expect("Failed to train");
+
+        let path = std::env::temp_dir().join(format!("model-{}.json", std::process::id()));
+        model.save(&path).unwrap();
+        let loaded = LogisticModel::load(&path).expect("Failed to load model");
+        let features = legs_features(
+            &candidates[0].front_run_tx,
+            &candidates[0].victim_tx,
+            &candidates[0].back_run_tx,
+        );
+        assert!((loaded.probability(&features) - model.probability(&features)).abs() < 1e-9);
+        std::fs::remove_file(&path).unwrap();
+
+        let scored = SandwichDetector::builder()
+            .keep_raw_candidates(true)
+            .scorer(model)
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+        let mean = |profitable: bool| {
+            let scores: Vec<f32> = scored
+                .iter()
+                .filter(|attack| attack.confidence_flags.is_profitable == profitable)
+                .map(|attack| attack.confidence_score)
+                .collect();
+            scores.iter().sum::<f32>() / scores.len() as f32
+        };
+        assert!(mean(true) > mean(false));
+    }
+}
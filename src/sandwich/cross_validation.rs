@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::error::DetectorError;
+use super::same_block_heuristics::{
+    find_same_block_sandwiches_with_config, SandwichAttackByHeuristics,
+};
+use super::same_block_sim::{
+    find_sandwich_attacks_by_simulation_with_config, Pool, SandwichAttackBySimulation,
+};
+use super::transactions::{BlockId, SwapTransaction};
+use crate::config::{Config, HeuristicsConfig};
+
+/// Added to the heuristic confidence of attacks the simulation confirms.
+pub const AGREEMENT_BOOST: f32 = 0.2;
+
+/// A sandwich both detectors found, with the same three legs.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Agreement {
+    pub heuristics: SandwichAttackByHeuristics,
+    pub simulation: SandwichAttackBySimulation,
+    /// `confidence_score` plus `AGREEMENT_BOOST`, capped at 1.0.
+    pub boosted_confidence: f32,
+}
+
+/// Why only one detector reported an attack.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DivergenceReason {
+    /// The other detector attributed the victim to different front/back legs.
+    DifferentLegs {
+        attack_id: String,
+    },
+    /// No reserves in the pool map for the attacked pool.
+    NoPoolState,
+    NoPoolTransactions,
+    /// The pool reserves did not reproduce the victim's actual output.
+    SimulationDiverged {
+        difference_pct: f64,
+    },
+    /// Heuristics found the candidate but the config filtered it out,
+    /// e.g. by `min_confidence` or `require_same_pool`.
+    FilteredByConfig {
+        confidence_score: f32,
+    },
+    Unexplained,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Divergence<A> {
+    pub attack: A,
+    pub reason: DivergenceReason,
+}
+
+/// Where the heuristic and simulation detectors agree and where they don't.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize)]
+pub struct CrossValidationReport {
+    pub agreed: Vec<Agreement>,
+    pub heuristics_only: Vec<Divergence<SandwichAttackByHeuristics>>,
+    pub simulation_only: Vec<Divergence<SandwichAttackBySimulation>>,
+}
+
+impl CrossValidationReport {
+    /// Share of all reported attacks found by both detectors.
+    pub fn agreement_rate(&self) -> f64 {
+        let total = self.agreed.len() + self.heuristics_only.len() + self.simulation_only.len();
+        if total == 0 {
+            return 0.0;
+        }
+        return self.agreed.len() as f64 / total as f64;
+    }
+}
+
+/// Run both detectors with the default config and compare their results.
+///
+/// As for `find_sandwich_attacks_by_simulation`, `pool_map` should hold the
+/// pools of a single chain.
+pub fn cross_validate(
+    transactions: &[SwapTransaction],
+    pool_map: &HashMap<String, Pool>,
+) -> CrossValidationReport {
+    return cross_validate_with_config(transactions, pool_map, &Config::default());
+}
+
+/// `cross_validate` with custom detection parameters, used by both detectors.
+pub fn cross_validate_with_config(
+    transactions: &[SwapTransaction],
+    pool_map: &HashMap<String, Pool>,
+    config: &Config,
+) -> CrossValidationReport {
+    let heuristics = find_same_block_sandwiches_with_config(transactions, config);
+    let simulation =
+        find_sandwich_attacks_by_simulation_with_config(pool_map, transactions, config);
+
+    let mut simulated: BTreeMap<String, SandwichAttackBySimulation> = simulation
+        .attacks
+        .into_iter()
+        .map(|attack| (attack.attack_id(), attack))
+        .collect();
+    let simulated_victims: HashMap<String, String> = simulated
+        .iter()
+        .map(|(attack_id, attack)| (attack.victim_tx.tx_hash.clone(), attack_id.clone()))
+        .collect();
+    let heuristic_victims: HashMap<String, String> = heuristics
+        .attacks
+        .iter()
+        .map(|attack| (attack.victim_tx.tx_hash.clone(), attack.attack_id()))
+        .collect();
+
+    let mut report = CrossValidationReport::default();
+    let mut heuristic_attacks = heuristics.attacks;
+    heuristic_attacks.sort_by_key(|attack| attack.attack_id());
+    for attack in heuristic_attacks {
+        match simulated.remove(&attack.attack_id()) {
+            Some(simulation) => report.agreed.push(Agreement {
+                boosted_confidence: (attack.confidence_score + AGREEMENT_BOOST).min(1.0),
+                heuristics: attack,
+                simulation,
+            }),
+            None => {
+                let reason = match simulated_victims.get(&attack.victim_tx.tx_hash) {
+                    Some(attack_id) => DivergenceReason::DifferentLegs {
+                        attack_id: attack_id.clone(),
+                    },
+                    None => simulation_skip_reason(&attack, &simulation.skipped),
+                };
+                report.heuristics_only.push(Divergence { attack, reason });
+            }
+        }
+    }
+
+    if simulated.is_empty() {
+        return report;
+    }
+    // Look for the remaining candidates without the heuristics filters
+    let relaxed = Config {
+        heuristics: HeuristicsConfig {
+            min_confidence: 0.0,
+            require_same_pool: false,
+            max_victims: None,
+            ..config.heuristics.clone()
+        },
+        keep_raw_candidates: true,
+        ..config.clone()
+    };
+    let unfiltered: HashMap<String, f32> =
+        find_same_block_sandwiches_with_config(transactions, &relaxed)
+            .attacks
+            .into_iter()
+            .map(|attack| (attack.attack_id(), attack.confidence_score))
+            .collect();
+    for (attack_id, attack) in simulated {
+        let reason = match heuristic_victims.get(&attack.victim_tx.tx_hash) {
+            Some(other) => DivergenceReason::DifferentLegs {
+                attack_id: other.clone(),
+            },
+            None => match unfiltered.get(&attack_id) {
+                Some(confidence_score) => DivergenceReason::FilteredByConfig {
+                    confidence_score: *confidence_score,
+                },
+                None => DivergenceReason::Unexplained,
+            },
+        };
+        report.simulation_only.push(Divergence { attack, reason });
+    }
+    return report;
+}
+
+/// Match a candidate the simulation skipped to the error it recorded.
+fn simulation_skip_reason(
+    attack: &SandwichAttackByHeuristics,
+    skipped: &[(BlockId, DetectorError)],
+) -> DivergenceReason {
+    let victim = &attack.victim_tx;
+    for (block_id, err) in skipped {
+        if *block_id != victim.block_id() {
+            continue;
+        }
+        match err {
+            DetectorError::UnknownPool(pool) if *pool == attack.front_run_tx.pool_address => {
+                return DivergenceReason::NoPoolState;
+            }
+            DetectorError::NoPoolTransactions(pool) if *pool == victim.pool_address => {
+                return DivergenceReason::NoPoolTransactions;
+            }
+            DetectorError::SimulationDiverged {
+                tx_hash,
+                difference_pct,
+                ..
+            } if *tx_hash == victim.tx_hash => {
+                return DivergenceReason::SimulationDiverged {
+                    difference_pct: *difference_pct,
+                };
+            }
+            _ => {}
+        }
+    }
+    return DivergenceReason::Unexplained;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cross_validation_explains_divergences() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let pool_map = crate::test_support::sample_pool_map();
+
+        let report = cross_validate(&transactions, &pool_map);
+        assert!(!report.agreed.is_empty());
+        for agreement in &report.agreed {
+            assert_eq!(
+                agreement.heuristics.attack_id(),
+                agreement.simulation.attack_id()
+            );
+            assert!(agreement.boosted_confidence > agreement.heuristics.confidence_score);
+        }
+        // Pools other than 0xpool1 have no reserves to simulate with
+        assert!(report.heuristics_only.iter().any(|divergence| {
+            divergence.attack.victim_tx.pool_address == "0xpool4"
+                && divergence.reason == DivergenceReason::NoPoolState
+        }));
+        assert!(report.agreement_rate() > 0.0 && report.agreement_rate() < 1.0);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json["heuristics_only"][0]["reason"]["reason"].is_string());
+    }
+}
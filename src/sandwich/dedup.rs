@@ -0,0 +1,127 @@
+use super::same_block_heuristics::SandwichAttackByHeuristics;
+use super::same_block_sim::SandwichAttackBySimulation;
+use super::transactions::SwapTransaction;
+
+/// A candidate sandwich that can be compared against overlapping ones.
+pub trait Candidate {
+    fn front_run(&self) -> &SwapTransaction;
+    fn victim(&self) -> &SwapTransaction;
+    fn back_run(&self) -> &SwapTransaction;
+    /// Higher is a better explanation of the victim's trade.
+    fn score(&self) -> f64;
+
+    /// Both explain the same victim trade with a shared front- or back-run,
+    /// e.g. a bot with two back-runs yields one candidate per back-run.
+    fn overlaps(&self, other: &Self) -> bool {
+        return self.victim().tx_hash == other.victim().tx_hash
+            && (self.front_run().tx_hash == other.front_run().tx_hash
+                || self.back_run().tx_hash == other.back_run().tx_hash);
+    }
+}
+
+impl Candidate for SandwichAttackByHeuristics {
+    fn front_run(&self) -> &SwapTransaction {
+        &self.front_run_tx
+    }
+
+    fn victim(&self) -> &SwapTransaction {
+        &self.victim_tx
+    }
+
+    fn back_run(&self) -> &SwapTransaction {
+        &self.back_run_tx
+    }
+
+    fn score(&self) -> f64 {
+        self.confidence_score as f64
+    }
+}
+
+impl Candidate for SandwichAttackBySimulation {
+    fn front_run(&self) -> &SwapTransaction {
+        &self.front_run_tx
+    }
+
+    fn victim(&self) -> &SwapTransaction {
+        &self.victim_tx
+    }
+
+    fn back_run(&self) -> &SwapTransaction {
+        &self.back_run_tx
+    }
+
+    fn score(&self) -> f64 {
+        self.victim_loss_percentage
+    }
+}
+
+/// Keep the best-scoring set of non-overlapping candidates, in their original
+/// order. Ties go to the earlier candidate, i.e. the earliest front-run and
+/// the nearest back-run.
+pub fn dedup_overlapping<A: Candidate>(candidates: Vec<A>) -> Vec<A> {
+    let mut by_score: Vec<usize> = (0..candidates.len()).collect();
+    by_score.sort_by(|a, b| candidates[*b].score().total_cmp(&candidates[*a].score()));
+
+    let mut kept: Vec<usize> = Vec::new();
+    for index in by_score {
+        if !kept
+            .iter()
+            .any(|other| candidates[index].overlaps(&candidates[*other]))
+        {
+            kept.push(index);
+        }
+    }
+    kept.sort();
+
+    let mut kept = kept.into_iter().peekable();
+    return candidates
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| match kept.peek() {
+            Some(next) if *next == index => {
+                kept.next();
+                Some(candidate)
+            }
+            _ => None,
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches_with_config;
+
+    #[test]
+    fn test_second_back_run_is_merged_into_one_attack() {
+        let mut transactions = crate::ingest::csv::sample_transactions();
+        // The bot back-runs 0xweth_victim twice, the second time for less
+        let mut second_back = transactions
+            .iter()
+            .find(|tx| tx.tx_hash == "0xweth_back")
+            .unwrap()
+            .clone();
+        second_back.tx_hash = "0xweth_back2".to_string();
+        second_back.tx_position_in_block += 1;
+        second_back.usd_value_out = 1.0;
+        transactions.push(second_back);
+        let for_victim = |attacks: &[SandwichAttackByHeuristics]| {
+            attacks
+                .iter()
+                .filter(|attack| attack.victim_tx.tx_hash == "0xweth_victim")
+                .map(|attack| attack.back_run_tx.tx_hash.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let deduped = find_same_block_sandwiches_with_config(&transactions, &Config::default());
+        assert_eq!(for_victim(&deduped.attacks), ["0xweth_back"]);
+
+        let raw_config = Config {
+            keep_raw_candidates: true,
+            ..Config::default()
+        };
+        let raw = find_same_block_sandwiches_with_config(&transactions, &raw_config);
+        assert_eq!(for_victim(&raw.attacks), ["0xweth_back", "0xweth_back2"]);
+    }
+}
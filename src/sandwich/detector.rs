@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+
+use super::cancel::CancellationToken;
+use super::classification::Classification;
+use super::error::DetectionOutcome;
+use super::pool_model::PoolModel;
+use super::progress::{Progress, ProgressTracker};
+use super::same_block_heuristics::{
+    find_sandwiches_in_block, find_sandwiches_in_block_with_history, SandwichAttackByHeuristics,
+};
+use super::same_block_sim::{
+    find_sandwiches_in_block_by_simulation, Pool, SandwichAttackBySimulation,
+};
+use super::streaming::AttackerHistory;
+use super::transactions::{
+    group_transactions_by_block, stream_transactions_by_block, BlockId, SwapTransaction,
+};
+use super::utils::sandwich_attack_id;
+use crate::config::Config;
+use crate::storage::checkpoint::{CheckpointStore, CHECKPOINT_INTERVAL_BLOCKS};
+use crate::telemetry;
+
+/// The swaps of one block, sorted by their position in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub id: BlockId,
+    pub transactions: Vec<SwapTransaction>,
+}
+
+impl Block {
+    pub fn new(id: BlockId, mut transactions: Vec<SwapTransaction>) -> Self {
+        transactions.sort_by_key(|tx| tx.tx_position_in_block);
+        return Self { id, transactions };
+    }
+}
+
+impl From<(BlockId, Vec<SwapTransaction>)> for Block {
+    fn from((id, transactions): (BlockId, Vec<SwapTransaction>)) -> Self {
+        return Self::new(id, transactions);
+    }
+}
+
+/// A result of any detector, tagged with the detector that produced it.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "detector", rename_all = "snake_case")]
+pub enum Detection {
+    Heuristics(SandwichAttackByHeuristics),
+    Simulation(SandwichAttackBySimulation),
+    /// Found by a detector outside this crate, see `DetectorRegistry`.
+    Custom(CustomDetection),
+}
+
+/// A sandwich reported by an external detector, with whatever evidence it
+/// wants to keep in `details`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CustomDetection {
+    /// The reporting detector's `Detector::name`.
+    pub name: String,
+    pub chain_id: u64,
+    pub front_run_tx: SwapTransaction,
+    pub victim_tx: SwapTransaction,
+    pub back_run_tx: SwapTransaction,
+    pub confidence_score: f32,
+    #[serde(default)]
+    pub classification: Classification,
+    #[serde(default)]
+    pub details: serde_json::Value,
+}
+
+impl Detection {
+    pub fn attack_id(&self) -> String {
+        match self {
+            Detection::Heuristics(attack) => attack.attack_id(),
+            Detection::Simulation(attack) => attack.attack_id(),
+            Detection::Custom(attack) => {
+                sandwich_attack_id(&attack.front_run_tx, &attack.victim_tx, &attack.back_run_tx)
+            }
+        }
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            Detection::Heuristics(attack) => attack.chain_id,
+            Detection::Simulation(attack) => attack.chain_id,
+            Detection::Custom(attack) => attack.chain_id,
+        }
+    }
+
+    pub fn front_run_tx(&self) -> &SwapTransaction {
+        match self {
+            Detection::Heuristics(attack) => &attack.front_run_tx,
+            Detection::Simulation(attack) => &attack.front_run_tx,
+            Detection::Custom(attack) => &attack.front_run_tx,
+        }
+    }
+
+    pub fn victim_tx(&self) -> &SwapTransaction {
+        match self {
+            Detection::Heuristics(attack) => &attack.victim_tx,
+            Detection::Simulation(attack) => &attack.victim_tx,
+            Detection::Custom(attack) => &attack.victim_tx,
+        }
+    }
+
+    pub fn classification(&self) -> &Classification {
+        match self {
+            Detection::Heuristics(attack) => &attack.classification,
+            Detection::Simulation(attack) => &attack.classification,
+            Detection::Custom(attack) => &attack.classification,
+        }
+    }
+}
+
+/// Finds attacks in one block at a time, so several detectors can share a
+/// single pass over the grouped swaps (see `Pipeline`).
+/// Detectors are shared across threads by the async and service APIs.
+pub trait Detector: Send + Sync {
+    /// Short identifier, e.g. for logs and metrics.
+    fn name(&self) -> &str;
+
+    fn detect(&self, block: &Block) -> Vec<Detection>;
+
+    /// `detect` reporting what was skipped and why. Skips nothing by default.
+    fn detect_outcome(&self, block: &Block) -> DetectionOutcome<Detection> {
+        return DetectionOutcome {
+            attacks: self.detect(block),
+            ..DetectionOutcome::default()
+        };
+    }
+
+    /// `detect` with the rolling state of a `StreamingDetector`, for
+    /// detectors of multi-block patterns or repeat attackers. Ignores the
+    /// context by default.
+    fn detect_with_context(&self, block: &Block, _context: &DetectionContext) -> Vec<Detection> {
+        return self.detect(block);
+    }
+}
+
+/// What a detector may know beyond the block at hand.
+#[derive(Debug, Clone)]
+pub struct DetectionContext<'a> {
+    /// Earlier blocks of the same chain still within the reorg window,
+    /// oldest first. They may yet be replaced by a reorg.
+    pub recent_blocks: Vec<&'a Block>,
+    /// Finalized attacks per attacker on the same chain, keyed by lowercased
+    /// address.
+    pub attackers: &'a HashMap<String, AttackerHistory>,
+}
+
+/// The evidence-based heuristics of `same_block_heuristics`.
+#[derive(Debug, Clone, Default)]
+pub struct HeuristicsDetector {
+    config: Config,
+}
+
+impl HeuristicsDetector {
+    pub fn new(config: Config) -> Self {
+        return Self { config };
+    }
+}
+
+impl Detector for HeuristicsDetector {
+    fn name(&self) -> &str {
+        "heuristics"
+    }
+
+    fn detect(&self, block: &Block) -> Vec<Detection> {
+        return self.detect_outcome(block).attacks;
+    }
+
+    fn detect_outcome(&self, block: &Block) -> DetectionOutcome<Detection> {
+        let mut outcome = DetectionOutcome::default();
+        match find_sandwiches_in_block(&block.transactions, &self.config) {
+            Ok(attacks) => {
+                outcome.attacks = attacks.into_iter().map(Detection::Heuristics).collect()
+            }
+            Err(err) => outcome.skipped.push((block.id, err)),
+        }
+        return outcome;
+    }
+
+    /// Recurrence (severity and, with `attacker_reputation`, confidence)
+    /// counts the attackers' finalized attacks too.
+    fn detect_with_context(&self, block: &Block, context: &DetectionContext) -> Vec<Detection> {
+        return find_sandwiches_in_block_with_history(
+            &block.transactions,
+            context.attackers,
+            &self.config,
+        )
+        .unwrap_or_default()
+        .into_iter()
+        .map(Detection::Heuristics)
+        .collect();
+    }
+}
+
+/// Simulation of `same_block_sim` against known pool state, constant product
+/// by default or any other `PoolModel`.
+#[derive(Debug, Clone, Default)]
+pub struct SimulationDetector<P = Pool> {
+    pool_map: HashMap<String, P>,
+    config: Config,
+}
+
+impl<P: PoolModel + Clone> SimulationDetector<P> {
+    /// Pools are keyed by address, so they should all be on one chain.
+    pub fn new(pool_map: HashMap<String, P>) -> Self {
+        return Self {
+            pool_map,
+            config: Config::default(),
+        };
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        return self;
+    }
+}
+
+impl<P: PoolModel + Clone> Detector for SimulationDetector<P> {
+    fn name(&self) -> &str {
+        "simulation"
+    }
+
+    fn detect(&self, block: &Block) -> Vec<Detection> {
+        return self.detect_outcome(block).attacks;
+    }
+
+    fn detect_outcome(&self, block: &Block) -> DetectionOutcome<Detection> {
+        let outcome = find_sandwiches_in_block_by_simulation(
+            &self.pool_map,
+            &block.transactions,
+            &self.config,
+            &CancellationToken::new(),
+        );
+        return DetectionOutcome {
+            attacks: outcome
+                .attacks
+                .into_iter()
+                .map(Detection::Simulation)
+                .collect(),
+            skipped: outcome.skipped,
+            truncated: outcome.truncated,
+        };
+    }
+}
+
+/// Runs every registered detector over each block, grouping the swaps once.
+#[derive(Default)]
+pub struct Pipeline {
+    detectors: Vec<Box<dyn Detector>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn with_detector<D: Detector + 'static>(mut self, detector: D) -> Self {
+        self.detectors.push(Box::new(detector));
+        return self;
+    }
+
+    pub fn with_boxed_detector(mut self, detector: Box<dyn Detector>) -> Self {
+        self.detectors.push(detector);
+        return self;
+    }
+
+    pub fn detector_names(&self) -> Vec<&str> {
+        return self
+            .detectors
+            .iter()
+            .map(|detector| detector.name())
+            .collect();
+    }
+
+    /// Detections of all detectors for one block, in registration order.
+    pub fn detect_block(&self, block: &Block) -> Vec<Detection> {
+        return self.detect_block_outcome(block).attacks;
+    }
+
+    /// `detect_block` with what each detector skipped and why.
+    pub fn detect_block_outcome(&self, block: &Block) -> DetectionOutcome<Detection> {
+        return self.traced_detect(block, |detector| detector.detect_outcome(block));
+    }
+
+    /// `detect_block` passing `context` to each detector, see
+    /// `Detector::detect_with_context`.
+    pub fn detect_block_with_context(
+        &self,
+        block: &Block,
+        context: &DetectionContext,
+    ) -> Vec<Detection> {
+        return self
+            .traced_detect(block, |detector| DetectionOutcome {
+                attacks: detector.detect_with_context(block, context),
+                ..DetectionOutcome::default()
+            })
+            .attacks;
+    }
+
+    fn traced_detect<F>(&self, block: &Block, detect: F) -> DetectionOutcome<Detection>
+    where
+        F: Fn(&dyn Detector) -> DetectionOutcome<Detection>,
+    {
+        let attributes = [
+            ("chain_id", block.id.chain_id as i64),
+            ("block_number", block.id.block_number as i64),
+        ];
+        return telemetry::traced(telemetry::DETECT_BLOCK, &attributes, || {
+            let mut outcome = DetectionOutcome::default();
+            for detector in &self.detectors {
+                outcome.extend(detect(detector.as_ref()));
+            }
+            outcome
+        });
+    }
+
+    /// Group `transactions` by block and run the detectors over each, oldest block first.
+    pub fn run(&self, transactions: &[SwapTransaction]) -> Vec<Detection> {
+        let mut blocks: Vec<Block> = group_transactions_by_block(transactions)
+            .into_iter()
+            .map(Block::from)
+            .collect();
+        blocks.sort_by_key(|block| block.id);
+        return blocks
+            .iter()
+            .flat_map(|block| self.detect_block(block))
+            .collect();
+    }
+
+    /// `run` stopping between blocks once `cancel` fires.
+    pub fn run_until(
+        &self,
+        transactions: &[SwapTransaction],
+        cancel: &CancellationToken,
+    ) -> DetectionOutcome<Detection> {
+        return self.run_with_progress(transactions, cancel, |_| {});
+    }
+
+    /// `run_until` calling `progress` after each block.
+    pub fn run_with_progress<F>(
+        &self,
+        transactions: &[SwapTransaction],
+        cancel: &CancellationToken,
+        mut progress: F,
+    ) -> DetectionOutcome<Detection>
+    where
+        F: FnMut(Progress),
+    {
+        let mut blocks: Vec<Block> = group_transactions_by_block(transactions)
+            .into_iter()
+            .map(Block::from)
+            .collect();
+        blocks.sort_by_key(|block| block.id);
+
+        let mut outcome = DetectionOutcome::default();
+        let mut tracker = ProgressTracker::new(blocks.len());
+        for block in &blocks {
+            if cancel.is_cancelled() {
+                outcome.truncated = true;
+                break;
+            }
+            outcome.extend(self.detect_block_outcome(block));
+            progress(tracker.advance(block.id, outcome.attacks.len()));
+        }
+        return outcome;
+    }
+
+    /// `run_until` resuming from the checkpoint in `store`: blocks it covers
+    /// are skipped, and the checkpoint is saved every
+    /// `CHECKPOINT_INTERVAL_BLOCKS` blocks and when the scan stops.
+    ///
+    /// `on_block` receives each block's detections (e.g. to persist them). A
+    /// crash can make it see up to `CHECKPOINT_INTERVAL_BLOCKS` blocks again
+    /// on resume, so it should be idempotent. Returns `false` if cancelled.
+    pub fn run_resumable<S, F>(
+        &self,
+        transactions: &[SwapTransaction],
+        store: &mut S,
+        cancel: &CancellationToken,
+        mut on_block: F,
+    ) -> Result<bool, String>
+    where
+        S: CheckpointStore + ?Sized,
+        F: FnMut(BlockId, Vec<Detection>) -> Result<(), String>,
+    {
+        let mut checkpoint = store.load_checkpoint()?;
+        let mut blocks: Vec<Block> = group_transactions_by_block(&checkpoint.pending(transactions))
+            .into_iter()
+            .map(Block::from)
+            .collect();
+        blocks.sort_by_key(|block| block.id);
+
+        let mut finished = true;
+        for (index, block) in blocks.iter().enumerate() {
+            if cancel.is_cancelled() {
+                finished = false;
+                break;
+            }
+            on_block(block.id, self.detect_block(block))?;
+            checkpoint.record(block.id);
+            if (index + 1) % CHECKPOINT_INTERVAL_BLOCKS == 0 {
+                store.save_checkpoint(&checkpoint)?;
+            }
+        }
+        store.save_checkpoint(&checkpoint)?;
+        return Ok(finished);
+    }
+
+    /// Streaming counterpart of `run`, the input must be ordered by block.
+    pub fn run_stream<'a, I>(&'a self, transactions: I) -> impl Iterator<Item = Detection> + 'a
+    where
+        I: IntoIterator<Item = SwapTransaction>,
+        I::IntoIter: 'a,
+    {
+        stream_transactions_by_block(transactions)
+            .flat_map(move |block| self.detect_block(&Block::from(block)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::error::DetectorError;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+
+    #[test]
+    fn test_pipeline_runs_all_detectors_in_one_pass() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let pool_map = crate::test_support::sample_pool_map();
+
+        let pipeline = Pipeline::new()
+            .with_detector(HeuristicsDetector::default())
+            .with_detector(SimulationDetector::new(pool_map.clone()));
+        assert_eq!(pipeline.detector_names(), ["heuristics", "simulation"]);
+
+        let detections = pipeline.run(&transactions);
+        let heuristics = detections
+            .iter()
+            .filter(|detection| matches!(detection, Detection::Heuristics(_)))
+            .count();
+        let simulated = detections.len() - heuristics;
+        assert_eq!(heuristics, find_same_block_sandwiches(&transactions).len());
+        assert_eq!(
+            simulated,
+            find_sandwich_attacks_by_simulation(&pool_map, &transactions).len()
+        );
+        assert!(detections
+            .windows(2)
+            .all(|pair| pair[0].victim_tx().block_number <= pair[1].victim_tx().block_number));
+
+        let streamed: Vec<Detection> = pipeline.run_stream(transactions.clone()).collect();
+        assert_eq!(streamed.len(), detections.len());
+
+        let json = serde_json::to_value(&detections[0]).unwrap();
+        assert_eq!(json["detector"], "heuristics");
+
+        // Candidates the simulation can't check are reported, not dropped
+        let blind =
+            Pipeline::new().with_detector(SimulationDetector::new(HashMap::<String, Pool>::new()));
+        let outcome = blind.run_until(&transactions, &CancellationToken::new());
+        assert!(outcome.attacks.is_empty());
+        assert!(!outcome.skipped.is_empty());
+        assert!(outcome
+            .skipped
+            .iter()
+            .all(|(_, err)| matches!(err, DetectorError::UnknownPool(_))));
+    }
+}
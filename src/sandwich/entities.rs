@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+/// Why two addresses look like one entity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntityLink {
+    /// Both were first funded by this address, or one funded the other.
+    SharedFunder(String),
+    /// Both are contracts deploying the same code.
+    SameCode,
+    /// They often sent each other funds, both ways.
+    FrequentTransfers,
+}
+
+/// Off-swap data tying addresses together, set in code from funding traces,
+/// contract code and transfer history. Addresses match case-insensitively.
+/// A sandwich whose attacker and victim are linked is more likely one entity
+/// rebalancing across its own wallets than an attack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityLinks {
+    /// Address that first funded each address.
+    pub funders: HashMap<String, String>,
+    /// Hash of the deployed code of contract addresses.
+    pub code_hashes: HashMap<String, String>,
+    /// Transfer counts keyed by (from, to).
+    pub transfers: HashMap<(String, String), u64>,
+    /// Transfers needed in each direction to link two addresses. Default `3`.
+    pub min_transfers_each_way: u64,
+}
+
+impl Default for EntityLinks {
+    fn default() -> Self {
+        return Self {
+            funders: HashMap::new(),
+            code_hashes: HashMap::new(),
+            transfers: HashMap::new(),
+            min_transfers_each_way: 3,
+        };
+    }
+}
+
+impl EntityLinks {
+    pub fn add_funder(&mut self, address: &str, funder: &str) {
+        self.funders
+            .insert(address.to_lowercase(), funder.to_lowercase());
+    }
+
+    pub fn add_code_hash(&mut self, address: &str, code_hash: &str) {
+        self.code_hashes
+            .insert(address.to_lowercase(), code_hash.to_lowercase());
+    }
+
+    pub fn add_transfer(&mut self, from: &str, to: &str) {
+        *self
+            .transfers
+            .entry((from.to_lowercase(), to.to_lowercase()))
+            .or_insert(0) += 1;
+    }
+
+    /// The first link found between `a` and `b`, if any.
+    pub fn link(&self, a: &str, b: &str) -> Option<EntityLink> {
+        let a = a.to_lowercase();
+        let b = b.to_lowercase();
+
+        match (self.funders.get(&a), self.funders.get(&b)) {
+            (Some(funder_a), Some(funder_b)) if funder_a == funder_b => {
+                return Some(EntityLink::SharedFunder(funder_a.clone()));
+            }
+            (Some(funder_a), _) if *funder_a == b => return Some(EntityLink::SharedFunder(b)),
+            (_, Some(funder_b)) if *funder_b == a => return Some(EntityLink::SharedFunder(a)),
+            _ => {}
+        }
+
+        if let (Some(code_a), Some(code_b)) = (self.code_hashes.get(&a), self.code_hashes.get(&b)) {
+            if code_a == code_b {
+                return Some(EntityLink::SameCode);
+            }
+        }
+
+        let count = |from: &String, to: &String| {
+            let key = (from.clone(), to.clone());
+            return self.transfers.get(&key).copied().unwrap_or(0);
+        };
+        if count(&a, &b) >= self.min_transfers_each_way
+            && count(&b, &a) >= self.min_transfers_each_way
+        {
+            return Some(EntityLink::FrequentTransfers);
+        }
+
+        return None;
+    }
+
+    pub fn same_entity(&self, a: &str, b: &str) -> bool {
+        return self.link(a, b).is_some();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches_with_config;
+
+    #[test]
+    fn test_linked_attacker_and_victim_are_demoted() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let mut config = Config::default();
+        let before = find_same_block_sandwiches_with_config(&transactions, &config).attacks;
+        let attack = before
+            .iter()
+            .find(|attack| attack.victim_tx.block_number == 12360)
+            .unwrap();
+        let attacker = &attack.front_run_tx.from_address;
+        let victim = &attack.victim_tx.from_address;
+        assert!(before
+            .iter()
+            .all(|attack| !attack.confidence_flags.same_entity));
+
+        let mut links = EntityLinks::default();
+        links.add_transfer(attacker, victim);
+        links.add_transfer(victim, attacker);
+        assert_eq!(links.link(attacker, victim), None);
+        for _ in 0..2 {
+            links.add_transfer(attacker, victim);
+            links.add_transfer(victim, attacker);
+        }
+        assert_eq!(
+            links.link(attacker, victim),
+            Some(EntityLink::FrequentTransfers)
+        );
+
+        let mut funded = EntityLinks::default();
+        funded.add_funder(attacker, "0xFUNDER");
+        funded.add_funder(&victim.to_uppercase(), "0xfunder");
+        assert_eq!(
+            funded.link(attacker, victim),
+            Some(EntityLink::SharedFunder("0xfunder".to_string()))
+        );
+
+        config.entities = funded;
+        let after = find_same_block_sandwiches_with_config(&transactions, &config).attacks;
+        let demoted = after
+            .iter()
+            .find(|other| other.attack_id() == attack.attack_id())
+            .unwrap();
+        assert!(demoted.confidence_flags.same_entity);
+        assert!(demoted.confidence_score < attack.confidence_score);
+    }
+}
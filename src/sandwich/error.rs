@@ -0,0 +1,112 @@
+use super::same_block_sim::SimulationDiagnostic;
+use super::transactions::BlockId;
+
+/// Why a block, or a candidate sandwich in it, could not be checked.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum DetectorError {
+    #[error("not enough transactions to have a sandwich ({0})")]
+    NotEnoughTransactions(usize),
+    #[error("no pool state for {0}")]
+    UnknownPool(String),
+    #[error("no transactions found in the victim pool {0}")]
+    NoPoolTransactions(String),
+    /// The simulated victim output is too far from the actual one, so the
+    /// pool state is likely wrong (see `SimulationConfig::reality_tolerance_pct`).
+    #[error("simulated output of {tx_hash} is {difference_pct:.3}% off the actual one")]
+    SimulationDiverged {
+        tx_hash: String,
+        difference_pct: f64,
+        diagnostic: Box<SimulationDiagnostic>,
+    },
+    /// A pool model couldn't execute a swap, e.g. an `EvmPool` asked for a
+    /// transaction the block doesn't contain.
+    #[error("cannot simulate {tx_hash}: {reason}")]
+    SwapFailed { tx_hash: String, reason: String },
+    /// A block at or behind the finalized head was pushed to a `StreamingDetector`.
+    #[error("block {} on chain {} is already final", .0.block_number, .0.chain_id)]
+    ReorgBeyondWindow(BlockId),
+    /// The swap source failed, e.g. a CSV row or an RPC request.
+    #[error("ingestion failed: {0}")]
+    Ingestion(String),
+    #[error("a detector named {0} is already registered")]
+    DuplicateDetector(String),
+    #[error("no detector named {0} is registered")]
+    UnknownDetector(String),
+    /// The `Config` combines settings that can't work together, see `Config::validate`.
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    /// A user rule failed to compile or evaluate.
+    #[error("rule {name}: {message}")]
+    Rule { name: String, message: String },
+}
+
+/// Attacks found, plus what was skipped and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectionOutcome<A> {
+    pub attacks: Vec<A>,
+    /// One entry per skipped block, per skipped candidate for simulation and
+    /// per failed user rule, with the block it was in.
+    pub skipped: Vec<(BlockId, DetectorError)>,
+    /// The scan was cancelled (see `CancellationToken`) before covering all blocks.
+    pub truncated: bool,
+}
+
+impl<A> Default for DetectionOutcome<A> {
+    fn default() -> Self {
+        Self {
+            attacks: Vec::new(),
+            skipped: Vec::new(),
+            truncated: false,
+        }
+    }
+}
+
+impl<A> DetectionOutcome<A> {
+    pub fn extend(&mut self, other: DetectionOutcome<A>) {
+        self.attacks.extend(other.attacks);
+        self.skipped.extend(other.skipped);
+        self.truncated |= other.truncated;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches_with_config;
+    use crate::sandwich::same_block_sim::{find_sandwich_attacks_by_simulation_with_config, Pool};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_skipped_say_why() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let config = Config::default();
+
+        let outcome = find_same_block_sandwiches_with_config(&transactions[..2], &config);
+        assert!(outcome.attacks.is_empty());
+        assert_eq!(
+            outcome.skipped,
+            [(
+                transactions[0].block_id(),
+                DetectorError::NotEnoughTransactions(2)
+            )]
+        );
+
+        // Without pool state every candidate is skipped rather than dropped silently
+        let outcome = find_sandwich_attacks_by_simulation_with_config(
+            &HashMap::<String, Pool>::new(),
+            &transactions,
+            &config,
+        );
+        assert!(outcome.attacks.is_empty());
+        assert!(!outcome.skipped.is_empty());
+        assert!(outcome
+            .skipped
+            .iter()
+            .all(|(_, err)| matches!(err, DetectorError::UnknownPool(_))));
+        assert_eq!(
+            DetectorError::UnknownPool("0xpool1".to_string()).to_string(),
+            "no pool state for 0xpool1"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use super::pool_model::PoolModel;
+use super::transactions::SwapTransaction;
+
+/// The most profitable front-run against a victim that still lets the
+/// victim's swap through.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct OptimalSandwich {
+    /// In the front-run's input token.
+    pub front_amount_in: f64,
+    /// What the round trip makes at that size, in the same token.
+    pub profit_token: f64,
+}
+
+/// Profit of front-running `victim` with `front_amount_in` on `pool` (the
+/// state right before the front-run) and selling everything back after it.
+pub fn sandwich_profit<P: PoolModel + Clone>(
+    pool: &P,
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    front_amount_in: f64,
+) -> f64 {
+    let mut pool = pool.clone();
+    let bought = pool.swap(&SwapTransaction {
+        amount_in: front_amount_in,
+        ..front.clone()
+    });
+    pool.swap(victim);
+    let sold = pool.swap(&SwapTransaction {
+        token_in: front.token_out.clone(),
+        token_out: front.token_in.clone(),
+        amount_in: bought,
+        ..front.clone()
+    });
+    return sold - front_amount_in;
+}
+
+/// The largest front-run on `pool` that still leaves `victim`'s output
+/// within `slippage_tolerance` of what it would get unsandwiched, a bigger
+/// one would make the victim's swap revert.
+pub fn largest_tolerated_front_run<P: PoolModel + Clone>(
+    pool: &P,
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    slippage_tolerance: f64,
+) -> f64 {
+    let min_out = pool.clone().swap(victim) * (1.0 - slippage_tolerance);
+    let victim_out = |size: f64| {
+        let mut pool = pool.clone();
+        pool.swap(&SwapTransaction {
+            amount_in: size,
+            ..front.clone()
+        });
+        return pool.swap(victim);
+    };
+
+    // Largest size the victim tolerates, bracketed by doubling first
+    let mut low = 0.0;
+    let mut high = front.amount_in.max(victim.amount_in).max(1.0);
+    for _ in 0..64 {
+        if victim_out(high) < min_out {
+            break;
+        }
+        low = high;
+        high *= 2.0;
+    }
+    for _ in 0..100 {
+        let middle = (low + high) / 2.0;
+        if victim_out(middle) >= min_out {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    return low;
+}
+
+/// Search the front-run sizes keeping the victim's output within
+/// `slippage_tolerance` of what it would get unsandwiched. The actual
+/// front-run is always allowed, the victim's real tolerance was at least
+/// that loose.
+pub fn optimal_sandwich<P: PoolModel + Clone>(
+    pool: &P,
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    slippage_tolerance: f64,
+) -> OptimalSandwich {
+    let max_size =
+        largest_tolerated_front_run(pool, front, victim, slippage_tolerance).max(front.amount_in);
+
+    // The profit rises with the size until fees and the victim's limit
+    // take over, golden-section search for its peak
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let profit = |size: f64| sandwich_profit(pool, front, victim, size);
+    let (mut a, mut b) = (0.0, max_size);
+    for _ in 0..100 {
+        let c = b - ratio * (b - a);
+        let d = a + ratio * (b - a);
+        if profit(c) < profit(d) {
+            a = c;
+        } else {
+            b = d;
+        }
+    }
+    let front_amount_in = (a + b) / 2.0;
+    return OptimalSandwich {
+        front_amount_in,
+        profit_token: profit(front_amount_in),
+    };
+}
+
+/// Share of the optimal sandwich's profit the actual front-run size got,
+/// from 0 (nothing or a loss) to 1. Bots size near 1, humans rarely do.
+/// Pools that can't price other sizes (NaN) give 0.
+pub fn extraction_efficiency<P: PoolModel + Clone>(
+    pool: &P,
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    slippage_tolerance: f64,
+) -> f64 {
+    let optimal = optimal_sandwich(pool, front, victim, slippage_tolerance);
+    if optimal.profit_token.is_nan() || optimal.profit_token <= 0.0 {
+        return 0.0;
+    }
+    let actual = sandwich_profit(pool, front, victim, front.amount_in);
+    return (actual / optimal.profit_token).clamp(0.0, 1.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_optimal_front_run_and_extraction_efficiency() {
+        let transactions = crate::test_support::sample_block();
+        let (front, victim) = (&transactions[0], &transactions[1]);
+        let pool = crate::test_support::sample_pool();
+
+        let optimal = optimal_sandwich(&pool, front, victim, 0.05);
+        for size in [10.0, front.amount_in, optimal.front_amount_in * 0.9] {
+            assert!(sandwich_profit(&pool, front, victim, size) <= optimal.profit_token);
+        }
+        // A looser victim leaves more to take
+        let tight = optimal_sandwich(&pool, front, victim, 0.001);
+        assert!(tight.profit_token < optimal.profit_token);
+
+        let efficiency = extraction_efficiency(&pool, front, victim, 0.05);
+        assert!(efficiency > 0.0 && efficiency < 1.0);
+        let sized = SwapTransaction {
+            amount_in: optimal.front_amount_in,
+            ..front.clone()
+        };
+        assert!(extraction_efficiency(&pool, &sized, victim, 0.05) > 0.999);
+
+        let pool_map = HashMap::from([("0xpool1".to_string(), pool.clone())]);
+        let attacks = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
+        assert!(attacks[0].extraction_efficiency > efficiency);
+        assert_eq!(
+            attacks[0].extraction_efficiency,
+            extraction_efficiency(&pool, front, victim, 0.005)
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use std::io::Write;
+
+use super::same_block_heuristics::{calculate_victim_price_impact, SandwichAttackByHeuristics};
+use super::streaming::AttackerHistory;
+use super::tokens::{TokenEquivalence, DEFAULT_EQUIVALENCE};
+use super::transactions::{group_transactions_by_block, SwapTransaction};
+use super::utils::sandwich_attack_id;
+
+/// Version of the feature set. Bumped whenever a feature is added, removed
+/// or computed differently, so models trained on older exports can be told
+/// apart.
+pub const FEATURE_VERSION: u32 = 1;
+
+/// Names of `FeatureVector::values`, in order.
+pub const FEATURE_NAMES: [&str; 15] = [
+    "front_gas_delta",
+    "back_gas_delta",
+    "front_victim_size_ratio",
+    "back_front_size_ratio",
+    "victim_price_impact",
+    "profit_usd",
+    "front_victim_gap",
+    "victim_back_gap",
+    "front_is_contract",
+    "back_is_contract",
+    "token_age_blocks",
+    "swaps_between",
+    "attacker_swaps_in_block",
+    "pool_swaps_in_block",
+    "attacker_prior_attacks",
+];
+
+/// What `extract` knows beyond the three legs. Counts that need a missing
+/// piece of context are 0.
+#[derive(Debug, Clone, Copy)]
+pub struct FeatureContext<'a> {
+    /// All swaps of the candidate's block.
+    pub block: &'a [SwapTransaction],
+    /// Past attacks of the front-runner's address, e.g. from a `StreamingDetector`.
+    pub attacker_history: Option<&'a AttackerHistory>,
+    pub tokens: &'a TokenEquivalence,
+}
+
+impl Default for FeatureContext<'_> {
+    fn default() -> Self {
+        return Self {
+            block: &[],
+            attacker_history: None,
+            tokens: &DEFAULT_EQUIVALENCE,
+        };
+    }
+}
+
+/// Numeric description of a sandwich candidate, for training classifiers.
+/// Booleans are 0/1, ratios with a zero denominator are 0.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FeatureVector {
+    pub attack_id: String,
+    pub version: u32,
+    /// Front-run gas price minus the victim's.
+    pub front_gas_delta: f64,
+    /// Victim gas price minus the back-run's.
+    pub back_gas_delta: f64,
+    /// Front-run USD size over the victim's.
+    pub front_victim_size_ratio: f64,
+    /// Back-run USD size over the front-run's.
+    pub back_front_size_ratio: f64,
+    /// How much worse the victim's rate was than the front-run's.
+    pub victim_price_impact: f64,
+    /// Back-run proceeds minus the front-run cost and both gas costs.
+    pub profit_usd: f64,
+    /// Positions between the front-run and the victim.
+    pub front_victim_gap: f64,
+    /// Positions between the victim and the back-run.
+    pub victim_back_gap: f64,
+    pub front_is_contract: f64,
+    pub back_is_contract: f64,
+    /// Blocks since the bought token launched.
+    pub token_age_blocks: f64,
+    /// Swaps of the block strictly between the front-run and the back-run.
+    pub swaps_between: f64,
+    /// Swaps of the block sent by the attacker.
+    pub attacker_swaps_in_block: f64,
+    /// Swaps of the block in the victim's pool.
+    pub pool_swaps_in_block: f64,
+    /// Attacks of the attacker in earlier blocks.
+    pub attacker_prior_attacks: f64,
+}
+
+impl FeatureVector {
+    /// The features in `FEATURE_NAMES` order.
+    pub fn values(&self) -> [f64; FEATURE_NAMES.len()] {
+        return [
+            self.front_gas_delta,
+            self.back_gas_delta,
+            self.front_victim_size_ratio,
+            self.back_front_size_ratio,
+            self.victim_price_impact,
+            self.profit_usd,
+            self.front_victim_gap,
+            self.victim_back_gap,
+            self.front_is_contract,
+            self.back_is_contract,
+            self.token_age_blocks,
+            self.swaps_between,
+            self.attacker_swaps_in_block,
+            self.pool_swaps_in_block,
+            self.attacker_prior_attacks,
+        ];
+    }
+}
+
+/// The features of the candidate `front`, `victim`, `back`.
+pub fn extract(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+    context: &FeatureContext,
+) -> FeatureVector {
+    let count = |matches: &dyn Fn(&SwapTransaction) -> bool| {
+        return context.block.iter().filter(|tx| matches(tx)).count() as f64;
+    };
+    let front_position = front.tx_position_in_block;
+    let back_position = back.tx_position_in_block;
+
+    return FeatureVector {
+        attack_id: sandwich_attack_id(front, victim, back),
+        version: FEATURE_VERSION,
+        front_gas_delta: front.gas_price as f64 - victim.gas_price as f64,
+        back_gas_delta: victim.gas_price as f64 - back.gas_price as f64,
+        front_victim_size_ratio: ratio(front.usd_value_in, victim.usd_value_in),
+        back_front_size_ratio: ratio(back.usd_value_in, front.usd_value_in),
+        victim_price_impact: f64::from(calculate_victim_price_impact(
+            front,
+            victim,
+            context.tokens,
+        )),
+        profit_usd: back.usd_value_out
+            - front.usd_value_in
+            - front.gas_cost_usd
+            - back.gas_cost_usd,
+        front_victim_gap: victim.tx_position_in_block as f64 - front_position as f64,
+        victim_back_gap: back_position as f64 - victim.tx_position_in_block as f64,
+        front_is_contract: flag(front.is_contract_caller),
+        back_is_contract: flag(back.is_contract_caller),
+        token_age_blocks: victim
+            .block_number
+            .saturating_sub(victim.token_launch_block) as f64,
+        swaps_between: count(&|tx| {
+            tx.tx_position_in_block > front_position && tx.tx_position_in_block < back_position
+        }),
+        attacker_swaps_in_block: count(&|tx| tx.from_address == front.from_address),
+        pool_swaps_in_block: count(&|tx| tx.pool_address == victim.pool_address),
+        attacker_prior_attacks: match context.attacker_history {
+            Some(history) => history.attacks as f64,
+            None => 0.0,
+        },
+    };
+}
+
+/// Features of heuristic candidates, with the blocks taken from
+/// `transactions`. Run detection with `keep_raw_candidates` and no
+/// `min_confidence` to export every candidate.
+pub fn extract_candidates(
+    candidates: &[SandwichAttackByHeuristics],
+    transactions: &[SwapTransaction],
+) -> Vec<FeatureVector> {
+    let blocks = group_transactions_by_block(transactions);
+    return candidates
+        .iter()
+        .map(|candidate| {
+            let context = FeatureContext {
+                block: match blocks.get(&candidate.victim_tx.block_id()) {
+                    Some(block) => block,
+                    None => &[],
+                },
+                ..FeatureContext::default()
+            };
+            extract(
+                &candidate.front_run_tx,
+                &candidate.victim_tx,
+                &candidate.back_run_tx,
+                &context,
+            )
+        })
+        .collect();
+}
+
+/// Write feature vectors as CSV, one column per field.
+pub fn write_csv<W: Write>(writer: W, features: &[FeatureVector]) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for feature in features {
+        writer
+            .serialize(feature)
+            .map_err(|err| format!("failed to write features: {}", err))?;
+    }
+    return writer
+        .flush()
+        .map_err(|err| format!("failed to write features: {}", err));
+}
+
+/// Write feature vectors to a Parquet file through an in-memory DuckDB.
+#[cfg(feature = "duckdb")]
+pub fn write_parquet<P: AsRef<std::path::Path>>(
+    path: P,
+    features: &[FeatureVector],
+) -> Result<(), String> {
+    use duckdb::types::Value;
+
+    let connection = duckdb::Connection::open_in_memory()
+        .map_err(|err| format!("failed to open duckdb: {}", err))?;
+    let columns: Vec<String> = FEATURE_NAMES
+        .iter()
+        .map(|name| format!("{} DOUBLE", name))
+        .collect();
+    connection
+        .execute_batch(&format!(
+            "CREATE TABLE features (attack_id VARCHAR, version UINTEGER, {})",
+            columns.join(", ")
+        ))
+        .map_err(|err| format!("failed to create features table: {}", err))?;
+
+    {
+        let mut appender = connection
+            .appender("features")
+            .map_err(|err| format!("failed to append features: {}", err))?;
+        for feature in features {
+            let mut row = vec![
+                Value::Text(feature.attack_id.clone()),
+                Value::UInt(feature.version),
+            ];
+            row.extend(feature.values().into_iter().map(Value::Double));
+            appender
+                .append_row(duckdb::appender_params_from_iter(row))
+                .map_err(|err| format!("failed to append features: {}", err))?;
+        }
+    }
+
+    let path = path.as_ref().display().to_string().replace('\'', "''");
+    return connection
+        .execute_batch(&format!("COPY features TO '{}' (FORMAT PARQUET)", path))
+        .map_err(|err| format!("failed to write parquet: {}", err));
+}
+
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0.0 {
+        return 0.0;
+    }
+    return numerator / denominator;
+}
+
+fn flag(value: bool) -> f64 {
+    return if value { 1.0 } else { 0.0 };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::builder::SandwichDetector;
+
+    #[test]
+    fn test_extract_candidate_features() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = SandwichDetector::builder()
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+
+        let features = extract_candidates(&attacks, &transactions);
+        assert_eq!(features.len(), attacks.len());
+        for (feature, attack) in features.iter().zip(&attacks) {
+            assert_eq!(feature.attack_id, attack.attack_id());
+            assert_eq!(feature.version, FEATURE_VERSION);
+            assert!(feature.victim_back_gap >= 1.0);
+            assert!(feature.attacker_swaps_in_block >= 2.0);
+            assert!(feature.swaps_between >= 1.0);
+            assert!((feature.profit_usd - attack.confidence_flags.total_profit_usd).abs() < 1e-9);
+        }
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &features).expect("Failed to write CSV");
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("attack_id,version,front_gas_delta,"));
+        assert_eq!(csv.lines().count(), features.len() + 1);
+
+        #[cfg(feature = "duckdb")]
+        {
+            let path =
+                std::env::temp_dir().join(format!("features-{}.parquet", std::process::id()));
+            write_parquet(&path, &features).expect("Failed to write parquet");
+            let rows: i64 = duckdb::Connection::open_in_memory()
+                .unwrap()
+                .query_row(
+                    &format!("SELECT count(*) FROM '{}'", path.display()),
+                    [],
+                    |row| row.get(0),
+                )
+                .expect("Failed to read parquet");
+            assert_eq!(rows, features.len() as i64);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
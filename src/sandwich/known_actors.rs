@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+/// Labeled MEV bot and searcher addresses bundled with the crate.
+///
+/// TODO: Only a handful of well-known actors so far, refresh from a labeled
+/// source (e.g. Etherscan or libMEV labels) and load it with `KnownActors::load`.
+pub const DEFAULT_KNOWN_ACTORS: &[(&str, &str, ActorKind)] = &[
+    (
+        "0xae2fc483527b8ef99eb5d9b44875f005ba1fae13",
+        "jaredfromsubway.eth",
+        ActorKind::Searcher,
+    ),
+    (
+        "0x6b75d8af000000e20b7a7ddf000ba900b4009a80",
+        "jaredfromsubway.eth bot",
+        ActorKind::MevBot,
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActorKind {
+    /// A contract executing MEV strategies.
+    MevBot,
+    /// An address operating MEV bots.
+    Searcher,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KnownActor {
+    pub label: String,
+    pub kind: ActorKind,
+}
+
+/// Known MEV actors keyed by address. Addresses match case-insensitively.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct KnownActors {
+    pub actors: BTreeMap<String, KnownActor>,
+}
+
+impl Default for KnownActors {
+    /// The `DEFAULT_KNOWN_ACTORS`.
+    fn default() -> Self {
+        let actors = DEFAULT_KNOWN_ACTORS
+            .iter()
+            .map(|(address, label, kind)| {
+                let actor = KnownActor {
+                    label: label.to_string(),
+                    kind: *kind,
+                };
+                (address.to_string(), actor)
+            })
+            .collect();
+        return Self { actors };
+    }
+}
+
+impl KnownActors {
+    /// No known actors, e.g. to build a list from a file only.
+    pub fn empty() -> Self {
+        return Self {
+            actors: BTreeMap::new(),
+        };
+    }
+
+    /// Read actors from CSV with `address`, `label` and `kind` columns.
+    pub fn from_csv_reader<R: Read>(reader: R) -> Result<Self, String> {
+        #[derive(serde::Deserialize)]
+        struct Row {
+            address: String,
+            label: String,
+            kind: ActorKind,
+        }
+
+        let mut actors = Self::empty();
+        for row in csv::Reader::from_reader(reader).deserialize() {
+            let row: Row = row.map_err(|err| format!("invalid known actor row: {}", err))?;
+            actors.insert(
+                &row.address,
+                KnownActor {
+                    label: row.label,
+                    kind: row.kind,
+                },
+            );
+        }
+        return Ok(actors);
+    }
+
+    /// Read a CSV file of actors (see `from_csv_reader`).
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        return Self::from_csv_reader(file).map_err(|err| format!("{}: {}", path.display(), err));
+    }
+
+    /// Add or replace an actor.
+    pub fn insert(&mut self, address: &str, actor: KnownActor) {
+        self.actors.remove(&self.key(address));
+        self.actors.insert(address.to_lowercase(), actor);
+    }
+
+    /// Add the actors of `other`, its labels winning over ours.
+    pub fn extend(&mut self, other: KnownActors) {
+        for (address, actor) in other.actors {
+            self.insert(&address, actor);
+        }
+    }
+
+    pub fn get(&self, address: &str) -> Option<&KnownActor> {
+        return self.actors.get(&self.key(address));
+    }
+
+    pub fn is_known_bot(&self, address: &str) -> bool {
+        return self.get(address).is_some();
+    }
+
+    /// The key `address` is stored under, entries read from config files
+    /// aren't lowercased.
+    fn key(&self, address: &str) -> String {
+        return self
+            .actors
+            .keys()
+            .find(|key| key.eq_ignore_ascii_case(address))
+            .cloned()
+            .unwrap_or_else(|| address.to_lowercase());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches_with_config;
+
+    #[test]
+    fn test_known_attacker_is_flagged_and_boosted() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let before = find_same_block_sandwiches_with_config(&transactions, &Config::default());
+        assert!(before
+            .attacks
+            .iter()
+            .all(|attack| !attack.confidence_flags.is_known_bot));
+
+        let mut config = Config::default();
+        config.heuristics.weights.base = 0.0;
+        let file = "address,label,kind\n0xATTACKER1,test bot,mev_bot\n";
+        config
+            .known_actors
+            .extend(KnownActors::from_csv_reader(file.as_bytes()).unwrap());
+        assert!(config
+            .known_actors
+            .is_known_bot("0xae2fc483527b8ef99eb5d9b44875f005ba1fae13"));
+        let after = find_same_block_sandwiches_with_config(&transactions, &config);
+
+        let known: Vec<_> = after
+            .attacks
+            .iter()
+            .filter(|attack| attack.confidence_flags.is_known_bot)
+            .collect();
+        assert!(!known.is_empty());
+        assert!(known
+            .iter()
+            .all(|attack| attack.front_run_tx.from_address == "0xattacker1"));
+
+        let unboosted = Config {
+            known_actors: KnownActors::empty(),
+            ..config.clone()
+        };
+        let unboosted = find_same_block_sandwiches_with_config(&transactions, &unboosted);
+        let unboosted_attack = unboosted
+            .attacks
+            .iter()
+            .find(|attack| attack.attack_id() == known[0].attack_id())
+            .unwrap();
+        assert!(known[0].confidence_score > unboosted_attack.confidence_score);
+    }
+}
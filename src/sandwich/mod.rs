@@ -1,7 +1,69 @@
+pub mod activity;
+pub mod analytics;
+#[cfg(feature = "async")]
+pub mod async_detection;
+pub mod attack_set;
+pub mod builder;
+pub mod calibration;
+pub mod cancel;
+pub mod classification;
+#[cfg(feature = "classifier")]
+pub mod classifier;
+pub mod cross_validation;
+pub mod dedup;
+pub mod detector;
+pub mod entities;
+pub mod error;
+pub mod extraction;
+pub mod features;
+pub mod known_actors;
+pub mod pool_hooks;
+pub mod pool_model;
+pub mod pool_registry;
+pub mod progress;
+pub mod registry;
+pub mod reputation;
+#[cfg(feature = "rules")]
+pub mod rules;
 pub mod same_block_heuristics;
 pub mod same_block_sim;
+pub mod sandwich_report;
+pub mod scoring;
+pub mod severity;
+pub mod streaming;
 pub mod tokens;
 pub mod transactions;
 pub mod utils;
 
+pub use activity::AddressActivity;
+pub use analytics::{
+    aggregate_by_attacker, aggregate_by_pool, aggregate_by_victim, AttackerSummary, PoolSummary,
+    VictimSummary,
+};
+pub use attack_set::AttackSet;
+pub use builder::{SandwichDetector, SandwichDetectorBuilder};
+pub use calibration::{CalibratedScorer, PlattScaling};
+pub use cancel::CancellationToken;
+pub use cross_validation::{cross_validate, CrossValidationReport};
+pub use detector::{Detection, DetectionContext, Detector, Pipeline};
+pub use entities::EntityLinks;
+pub use error::{DetectionOutcome, DetectorError};
+pub use extraction::{extraction_efficiency, OptimalSandwich};
+pub use features::{FeatureContext, FeatureVector};
+pub use pool_hooks::{HookedPool, SwapHook};
+pub use pool_model::{
+    Arithmetic, ConcentratedLiquidityPool, PoolModel, StableSwapPool, WeightedPool,
+};
+pub use pool_registry::PoolRegistry;
+pub use progress::Progress;
+pub use registry::DetectorRegistry;
+pub use reputation::AttackerReputation;
 pub use same_block_heuristics::{find_same_block_sandwiches, SandwichAttackByHeuristics};
+pub use sandwich_report::{merge_reports, SandwichReport};
+pub use scoring::{
+    rescore, rescore_with, AdditiveScorer, BayesianScorer, ConfidenceFactor, ConfidenceScorer,
+    LikelihoodRatios,
+};
+pub use severity::Severity;
+pub use streaming::StreamingDetector;
+pub use transactions::{BlockId, SwapTransaction, ETHEREUM_CHAIN_ID};
@@ -0,0 +1,146 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::error::DetectorError;
+use super::pool_model::{Arithmetic, PoolModel};
+use super::transactions::SwapTransaction;
+
+/// What a Uniswap V4 hook does around each swap of its pool. The defaults
+/// leave the swap untouched, implement those the hook overrides.
+pub trait SwapHook: Send + Sync {
+    /// Dynamic fee for this swap, `None` keeps the pool's.
+    fn fee_bps(&self, _swap: &SwapTransaction) -> Option<u32> {
+        return None;
+    }
+
+    /// Input the pool actually trades, after the hook's `beforeSwap` delta.
+    fn before_swap(&self, swap: &SwapTransaction) -> f64 {
+        return swap.amount_in;
+    }
+
+    /// Output the swapper receives, after the hook's `afterSwap` delta.
+    fn after_swap(&self, _swap: &SwapTransaction, amount_out: f64) -> f64 {
+        return amount_out;
+    }
+}
+
+/// Any pool model with a V4 hook around it, so simulating a hooked pool
+/// applies its fees and deltas instead of silently pricing it as the bare
+/// curve.
+#[derive(Clone)]
+pub struct HookedPool<P> {
+    pub pool: P,
+    hook: Arc<dyn SwapHook>,
+}
+
+impl<P: PoolModel + Clone> HookedPool<P> {
+    pub fn new<H: SwapHook + 'static>(pool: P, hook: H) -> Self {
+        return Self {
+            pool,
+            hook: Arc::new(hook),
+        };
+    }
+}
+
+impl<P: fmt::Debug> fmt::Debug for HookedPool<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HookedPool")
+            .field("pool", &self.pool)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P: PoolModel + Clone + 'static> PoolModel for HookedPool<P> {
+    fn swap(&mut self, swap: &SwapTransaction) -> f64 {
+        return self.try_swap(swap).unwrap_or(f64::NAN);
+    }
+
+    fn try_swap(&mut self, swap: &SwapTransaction) -> Result<f64, DetectorError> {
+        let pool_fee = self.pool.fee_bps();
+        if let Some(fee_bps) = self.hook.fee_bps(swap) {
+            self.pool.set_fee_bps(fee_bps);
+        }
+        let hooked_swap = SwapTransaction {
+            amount_in: self.hook.before_swap(swap),
+            ..swap.clone()
+        };
+        let amount_out = self.pool.try_swap(&hooked_swap);
+        self.pool.set_fee_bps(pool_fee);
+        return Ok(self.hook.after_swap(swap, amount_out?));
+    }
+
+    fn fee_bps(&self) -> u32 {
+        return self.pool.fee_bps();
+    }
+
+    fn set_fee_bps(&mut self, fee_bps: u32) {
+        self.pool.set_fee_bps(fee_bps);
+    }
+
+    fn boxed_clone(&self) -> Box<dyn PoolModel> {
+        return Box::new(self.clone());
+    }
+
+    fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.pool.set_arithmetic(arithmetic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+    use std::collections::HashMap;
+
+    /// Charges 1% to swaps above 1000 and rebates 10 tokens of output.
+    struct SizeFeeRebateHook;
+
+    impl SwapHook for SizeFeeRebateHook {
+        fn fee_bps(&self, swap: &SwapTransaction) -> Option<u32> {
+            if swap.amount_in > 1000.0 {
+                return Some(100);
+            }
+            return None;
+        }
+
+        fn after_swap(&self, _swap: &SwapTransaction, amount_out: f64) -> f64 {
+            return amount_out + 10.0;
+        }
+    }
+
+    #[test]
+    fn test_hooks_adjust_fee_and_output() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let pool = crate::test_support::sample_pool();
+        let small = transactions
+            .iter()
+            .find(|tx| tx.token_in == "USDC" && tx.amount_in <= 1000.0)
+            .unwrap();
+        let large = transactions
+            .iter()
+            .find(|tx| tx.token_in == "USDC" && tx.amount_in > 1000.0)
+            .unwrap();
+
+        let mut hooked = HookedPool::new(pool.clone(), SizeFeeRebateHook);
+        let small_out = hooked.swap(small);
+        assert_eq!(small_out, pool.clone().swap(small) + 10.0);
+        assert_eq!(hooked.fee_bps(), 30);
+
+        let mut expected = pool.clone();
+        expected.swap(small);
+        let large_out = hooked.swap(large);
+        assert_eq!(large_out, expected.with_fee_bps(100).swap(large) + 10.0);
+        assert_eq!(hooked.fee_bps(), 30);
+
+        // Without the hook the simulation would price the pool as plain V2
+        let plain = HashMap::from([("0xpool1".to_string(), pool.clone())]);
+        let hooked = HashMap::from([(
+            "0xpool1".to_string(),
+            HookedPool::new(pool, SizeFeeRebateHook),
+        )]);
+        assert_ne!(
+            find_sandwich_attacks_by_simulation(&plain, &transactions),
+            find_sandwich_attacks_by_simulation(&hooked, &transactions)
+        );
+    }
+}
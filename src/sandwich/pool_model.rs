@@ -0,0 +1,704 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::error::DetectorError;
+use super::same_block_sim::Pool;
+use super::transactions::SwapTransaction;
+
+/// How a pool prices swaps, so the simulation can replay a block against it.
+///
+/// `Pool` is the constant-product (Uniswap V2) model,
+/// `ConcentratedLiquidityPool` the Uniswap V3 one, `StableSwapPool`
+/// Curve's and `WeightedPool` Balancer's. Maps mixing them can hold
+/// `Box<dyn PoolModel>`.
+pub trait PoolModel: fmt::Debug + Send + Sync {
+    /// Execute `swap` against the current state, moving the pool past it,
+    /// and return the tokens the swapper receives.
+    fn swap(&mut self, swap: &SwapTransaction) -> f64;
+
+    /// `swap`, failing rather than returning a meaningless amount when the
+    /// model can't execute it. The simulation swaps through this.
+    fn try_swap(&mut self, swap: &SwapTransaction) -> Result<f64, DetectorError> {
+        return Ok(self.swap(swap));
+    }
+
+    /// LP fee of the next swaps, in basis points.
+    fn fee_bps(&self) -> u32;
+
+    fn set_fee_bps(&mut self, fee_bps: u32);
+
+    fn boxed_clone(&self) -> Box<dyn PoolModel>;
+
+    /// Switch the swap math, ignored by models only priced in `f64`.
+    fn set_arithmetic(&mut self, _arithmetic: Arithmetic) {}
+}
+
+/// Number type of the simulation's swap math.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arithmetic {
+    #[default]
+    Float,
+    /// Integer U256 math rounding down like the contracts, for amounts in
+    /// raw token units. Only `Pool` (V2's `getAmountOut`) supports it.
+    /// Exact with the raw amounts and reserves (`SwapTransaction::raw_amount_in`,
+    /// `Pool::from_raw_reserves`), which are carried from swap to swap.
+    /// Without them the `f64` ones are rounded down, off past 2^53 units
+    /// (0.009 of an 18 decimals token).
+    FixedPoint,
+}
+
+impl PoolModel for Pool {
+    fn swap(&mut self, swap: &SwapTransaction) -> f64 {
+        let simulation = self.simulate_swap(swap);
+        *self = simulation.new_pool_state;
+        return simulation.tokens_received;
+    }
+
+    fn fee_bps(&self) -> u32 {
+        return self.fee_bps;
+    }
+
+    fn set_fee_bps(&mut self, fee_bps: u32) {
+        self.fee_bps = fee_bps;
+    }
+
+    fn boxed_clone(&self) -> Box<dyn PoolModel> {
+        return Box::new(self.clone());
+    }
+
+    fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+}
+
+impl PoolModel for Box<dyn PoolModel> {
+    fn swap(&mut self, swap: &SwapTransaction) -> f64 {
+        return self.as_mut().swap(swap);
+    }
+
+    fn try_swap(&mut self, swap: &SwapTransaction) -> Result<f64, DetectorError> {
+        return self.as_mut().try_swap(swap);
+    }
+
+    fn fee_bps(&self) -> u32 {
+        return self.as_ref().fee_bps();
+    }
+
+    fn set_fee_bps(&mut self, fee_bps: u32) {
+        self.as_mut().set_fee_bps(fee_bps);
+    }
+
+    fn boxed_clone(&self) -> Box<dyn PoolModel> {
+        return self.as_ref().boxed_clone();
+    }
+
+    fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.as_mut().set_arithmetic(arithmetic);
+    }
+}
+
+impl Clone for Box<dyn PoolModel> {
+    fn clone(&self) -> Self {
+        return self.boxed_clone();
+    }
+}
+
+/// Base of the price of each tick, price = `TICK_BASE ^ tick`.
+const TICK_BASE: f64 = 1.0001;
+
+/// A Uniswap V3 pool: liquidity concentrated in tick ranges, so the price
+/// moves faster (and victims lose more) once a swap leaves the dense range.
+///
+/// Prices are of token A in token B, as in `Pool::get_token_a_price`, and
+/// amounts are floats rather than the pool's fixed-point integers.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ConcentratedLiquidityPool {
+    pub token_a_address: String,
+    pub token_b_address: String,
+    /// Square root of the current price.
+    pub sqrt_price: f64,
+    /// Liquidity of the positions in range at the current price.
+    pub liquidity: f64,
+    /// Tick of the current price, see `tick_at_sqrt_price`.
+    pub tick: i32,
+    /// Net liquidity added when the price crosses each initialized tick
+    /// upwards (and removed crossing it downwards).
+    pub ticks: BTreeMap<i32, f64>,
+    /// Default `30`, V3 pools also come in `1`, `5` and `100`.
+    pub fee_bps: u32,
+}
+
+impl ConcentratedLiquidityPool {
+    pub fn new(
+        sqrt_price: f64,
+        liquidity: f64,
+        token_a_address: String,
+        token_b_address: String,
+    ) -> Self {
+        return Self {
+            token_a_address,
+            token_b_address,
+            sqrt_price,
+            liquidity,
+            tick: tick_at_sqrt_price(sqrt_price),
+            ticks: BTreeMap::new(),
+            fee_bps: 30,
+        };
+    }
+
+    /// Add a position of `liquidity` between `lower_tick` and `upper_tick`.
+    /// Doesn't change the in-range liquidity, `new` takes that as of now.
+    pub fn with_position(mut self, lower_tick: i32, upper_tick: i32, liquidity: f64) -> Self {
+        *self.ticks.entry(lower_tick).or_insert(0.0) += liquidity;
+        *self.ticks.entry(upper_tick).or_insert(0.0) -= liquidity;
+        return self;
+    }
+
+    pub fn with_fee_bps(mut self, fee_bps: u32) -> Self {
+        self.fee_bps = fee_bps;
+        return self;
+    }
+
+    pub fn price(&self) -> f64 {
+        return self.sqrt_price * self.sqrt_price;
+    }
+
+    /// Tokens `amount_in` of token A buys, moving the price down.
+    fn swap_a_for_b(&mut self, mut amount_in: f64) -> f64 {
+        let mut amount_out = 0.0;
+        while amount_in > 0.0 {
+            let next_tick = self.ticks.range(..=self.tick).next_back().map(|(t, _)| *t);
+            let target = next_tick.map(sqrt_price_at_tick);
+
+            if let Some(target) = target {
+                let to_target = self.liquidity * (1.0 / target - 1.0 / self.sqrt_price);
+                if amount_in >= to_target {
+                    amount_in -= to_target;
+                    amount_out += self.liquidity * (self.sqrt_price - target);
+                    let tick = next_tick.unwrap();
+                    self.liquidity -= self.ticks[&tick];
+                    self.sqrt_price = target;
+                    self.tick = tick - 1;
+                    continue;
+                }
+            }
+
+            if self.liquidity <= 0.0 {
+                break;
+            }
+            let sqrt_price =
+                self.liquidity * self.sqrt_price / (self.liquidity + amount_in * self.sqrt_price);
+            amount_out += self.liquidity * (self.sqrt_price - sqrt_price);
+            self.sqrt_price = sqrt_price;
+            self.tick = tick_at_sqrt_price(sqrt_price);
+            break;
+        }
+        return amount_out;
+    }
+
+    /// Tokens `amount_in` of token B buys, moving the price up.
+    fn swap_b_for_a(&mut self, mut amount_in: f64) -> f64 {
+        let mut amount_out = 0.0;
+        while amount_in > 0.0 {
+            let next_tick = self.ticks.range(self.tick + 1..).next().map(|(t, _)| *t);
+            let target = next_tick.map(sqrt_price_at_tick);
+
+            if let Some(target) = target {
+                let to_target = self.liquidity * (target - self.sqrt_price);
+                if amount_in >= to_target {
+                    amount_in -= to_target;
+                    amount_out += self.liquidity * (1.0 / self.sqrt_price - 1.0 / target);
+                    let tick = next_tick.unwrap();
+                    self.liquidity += self.ticks[&tick];
+                    self.sqrt_price = target;
+                    self.tick = tick;
+                    continue;
+                }
+            }
+
+            if self.liquidity <= 0.0 {
+                break;
+            }
+            let sqrt_price = self.sqrt_price + amount_in / self.liquidity;
+            amount_out += self.liquidity * (1.0 / self.sqrt_price - 1.0 / sqrt_price);
+            self.sqrt_price = sqrt_price;
+            self.tick = tick_at_sqrt_price(sqrt_price);
+            break;
+        }
+        return amount_out;
+    }
+}
+
+impl PoolModel for ConcentratedLiquidityPool {
+    /// Swaps of tokens the pool doesn't hold receive nothing.
+    fn swap(&mut self, swap: &SwapTransaction) -> f64 {
+        return self.try_swap(swap).unwrap_or(0.0);
+    }
+
+    fn try_swap(&mut self, swap: &SwapTransaction) -> Result<f64, DetectorError> {
+        let tokens = [self.token_a_address.as_str(), self.token_b_address.as_str()];
+        let (i, _) = token_pair(&tokens, swap)?;
+        let amount_in = swap.amount_in * (1.0 - f64::from(self.fee_bps) / 10_000.0);
+        if i == 1 {
+            return Ok(self.swap_b_for_a(amount_in));
+        }
+        return Ok(self.swap_a_for_b(amount_in));
+    }
+
+    fn fee_bps(&self) -> u32 {
+        return self.fee_bps;
+    }
+
+    fn set_fee_bps(&mut self, fee_bps: u32) {
+        self.fee_bps = fee_bps;
+    }
+
+    fn boxed_clone(&self) -> Box<dyn PoolModel> {
+        return Box::new(self.clone());
+    }
+}
+
+/// A Curve stableswap pool of two or more pegged tokens. Near the peg it
+/// trades almost 1:1, the `amplification` sets how far that flat part goes
+/// before slippage picks up like constant product.
+///
+/// Balances are in token units, as swap amounts, so tokens with different
+/// decimals compare directly.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StableSwapPool {
+    /// In pool order, matching `balances`.
+    pub token_addresses: Vec<String>,
+    pub balances: Vec<f64>,
+    /// Curve's `A`, e.g. `2000` for the 3pool.
+    pub amplification: f64,
+    /// Taken from the output and left in the pool. Default `4`.
+    pub fee_bps: u32,
+}
+
+/// Newton iterations before giving up on converging.
+const STABLESWAP_ITERATIONS: usize = 255;
+
+impl StableSwapPool {
+    pub fn new(token_addresses: Vec<String>, balances: Vec<f64>, amplification: f64) -> Self {
+        return Self {
+            token_addresses,
+            balances,
+            amplification,
+            fee_bps: 4,
+        };
+    }
+
+    pub fn with_fee_bps(mut self, fee_bps: u32) -> Self {
+        self.fee_bps = fee_bps;
+        return self;
+    }
+
+    /// `Ann` of the contracts' `get_D` and `get_y`, `A * n`.
+    fn amplification_coefficient(&self) -> f64 {
+        return self.amplification * self.balances.len() as f64;
+    }
+
+    /// The invariant `D`, the total balance when all tokens are at the peg.
+    pub fn invariant(&self) -> f64 {
+        let n = self.balances.len() as f64;
+        let sum: f64 = self.balances.iter().sum();
+        if sum == 0.0 {
+            return 0.0;
+        }
+        let ann = self.amplification_coefficient();
+        let mut d = sum;
+        for _ in 0..STABLESWAP_ITERATIONS {
+            let d_product = self
+                .balances
+                .iter()
+                .fold(d, |product, balance| product * d / (balance * n));
+            let previous = d;
+            d = (ann * sum + d_product * n) * d / ((ann - 1.0) * d + (n + 1.0) * d_product);
+            if (d - previous).abs() <= d * 1e-15 {
+                break;
+            }
+        }
+        return d;
+    }
+
+    /// Balance of token `j` keeping the invariant `d` once token `i` has
+    /// `new_balance`.
+    fn balance_after(&self, i: usize, j: usize, new_balance: f64, d: f64) -> f64 {
+        let n = self.balances.len() as f64;
+        let ann = self.amplification_coefficient();
+        let mut c = d;
+        let mut sum = 0.0;
+        for (k, balance) in self.balances.iter().enumerate() {
+            let balance = match k {
+                k if k == i => new_balance,
+                k if k == j => continue,
+                _ => *balance,
+            };
+            sum += balance;
+            c = c * d / (balance * n);
+        }
+        c = c * d / (ann * n);
+        let b = sum + d / ann;
+
+        let mut y = d;
+        for _ in 0..STABLESWAP_ITERATIONS {
+            let previous = y;
+            y = (y * y + c) / (2.0 * y + b - d);
+            if (y - previous).abs() <= y * 1e-15 {
+                break;
+            }
+        }
+        return y;
+    }
+}
+
+impl PoolModel for StableSwapPool {
+    /// Swaps of tokens the pool doesn't hold receive nothing.
+    fn swap(&mut self, swap: &SwapTransaction) -> f64 {
+        return self.try_swap(swap).unwrap_or(0.0);
+    }
+
+    fn try_swap(&mut self, swap: &SwapTransaction) -> Result<f64, DetectorError> {
+        let (i, j) = token_pair(&self.token_addresses, swap)?;
+        let d = self.invariant();
+        let new_balance = self.balances[i] + swap.amount_in;
+        let output = self.balances[j] - self.balance_after(i, j, new_balance, d);
+        let received = output * (1.0 - f64::from(self.fee_bps) / 10_000.0);
+
+        self.balances[i] = new_balance;
+        self.balances[j] -= received;
+        return Ok(received);
+    }
+
+    fn fee_bps(&self) -> u32 {
+        return self.fee_bps;
+    }
+
+    fn set_fee_bps(&mut self, fee_bps: u32) {
+        self.fee_bps = fee_bps;
+    }
+
+    fn boxed_clone(&self) -> Box<dyn PoolModel> {
+        return Box::new(self.clone());
+    }
+}
+
+/// A Balancer weighted pool, e.g. 80/20 BAL/WETH. The invariant is the
+/// product of balances each raised to its weight, so a 50/50 pool prices
+/// like constant product and the lighter side of an 80/20 one slips faster.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WeightedPool {
+    /// In pool order, matching `balances` and `weights`.
+    pub token_addresses: Vec<String>,
+    pub balances: Vec<f64>,
+    /// Normalized weights, summing to 1.
+    pub weights: Vec<f64>,
+    /// Taken from the input and left in the pool. Default `30`.
+    pub fee_bps: u32,
+}
+
+impl WeightedPool {
+    /// `weights` need not sum to 1, e.g. `[80.0, 20.0]` works.
+    pub fn new(token_addresses: Vec<String>, balances: Vec<f64>, weights: Vec<f64>) -> Self {
+        let total: f64 = weights.iter().sum();
+        return Self {
+            token_addresses,
+            balances,
+            weights: weights.iter().map(|weight| weight / total).collect(),
+            fee_bps: 30,
+        };
+    }
+
+    pub fn with_fee_bps(mut self, fee_bps: u32) -> Self {
+        self.fee_bps = fee_bps;
+        return self;
+    }
+
+    /// Price of token `i` in token `j` at the current balances.
+    pub fn spot_price(&self, i: usize, j: usize) -> f64 {
+        return (self.balances[j] / self.weights[j]) / (self.balances[i] / self.weights[i]);
+    }
+}
+
+impl PoolModel for WeightedPool {
+    /// Swaps of tokens the pool doesn't hold receive nothing.
+    fn swap(&mut self, swap: &SwapTransaction) -> f64 {
+        return self.try_swap(swap).unwrap_or(0.0);
+    }
+
+    fn try_swap(&mut self, swap: &SwapTransaction) -> Result<f64, DetectorError> {
+        let (i, j) = token_pair(&self.token_addresses, swap)?;
+        let amount_in = swap.amount_in * (1.0 - f64::from(self.fee_bps) / 10_000.0);
+        let base = self.balances[i] / (self.balances[i] + amount_in);
+        let received = self.balances[j] * (1.0 - base.powf(self.weights[i] / self.weights[j]));
+
+        self.balances[i] += swap.amount_in;
+        self.balances[j] -= received;
+        return Ok(received);
+    }
+
+    fn fee_bps(&self) -> u32 {
+        return self.fee_bps;
+    }
+
+    fn set_fee_bps(&mut self, fee_bps: u32) {
+        self.fee_bps = fee_bps;
+    }
+
+    fn boxed_clone(&self) -> Box<dyn PoolModel> {
+        return Box::new(self.clone());
+    }
+}
+
+/// Indices of the swap's input and output tokens in a multi-token pool.
+fn token_pair<S: AsRef<str>>(
+    token_addresses: &[S],
+    swap: &SwapTransaction,
+) -> Result<(usize, usize), DetectorError> {
+    let index_of = |token: &str| {
+        token_addresses
+            .iter()
+            .position(|address| address.as_ref() == token)
+    };
+    return match (index_of(&swap.token_in), index_of(&swap.token_out)) {
+        (Some(i), Some(j)) if i != j => Ok((i, j)),
+        _ => Err(DetectorError::SwapFailed {
+            tx_hash: swap.tx_hash.clone(),
+            reason: format!(
+                "pool doesn't trade {} for {}",
+                swap.token_in, swap.token_out
+            ),
+        }),
+    };
+}
+
+pub fn sqrt_price_at_tick(tick: i32) -> f64 {
+    return TICK_BASE.powf(f64::from(tick) / 2.0);
+}
+
+/// The tick whose range holds `sqrt_price`, the greatest one at or below it.
+pub fn tick_at_sqrt_price(sqrt_price: f64) -> i32 {
+    return (2.0 * sqrt_price.ln() / TICK_BASE.ln()).floor() as i32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::error::DetectorError;
+    use crate::sandwich::same_block_sim::{
+        find_sandwich_attacks_by_simulation, find_sandwich_attacks_by_simulation_with_config,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_concentrated_liquidity_matches_constant_product_in_range() {
+        // Pool treats any token but A as B, the V3 pool knows its tokens
+        let transactions: Vec<_> = crate::ingest::csv::sample_transactions()
+            .into_iter()
+            .filter(|tx| tx.token_in != "USDT" && tx.token_out != "USDT")
+            .collect();
+        // Infinite range liquidity behaves like a V2 pool with the same
+        // reserves, as long as V2 fees (which stay in the reserves) are off
+        let v2 = crate::test_support::sample_pool().with_fee_bps(0);
+        let v3 = ConcentratedLiquidityPool::new(
+            (v2.token_b_reserve / v2.token_a_reserve).sqrt(),
+            (v2.token_a_reserve * v2.token_b_reserve).sqrt(),
+            "USDC".into(),
+            "SHIB".into(),
+        )
+        .with_fee_bps(0);
+        let swaps: Vec<_> = transactions
+            .iter()
+            .filter(|tx| tx.pool_address == "0xpool1")
+            .collect();
+        let (mut v2_state, mut v3_state) = (v2.clone(), v3.clone());
+        for swap in &swaps {
+            let expected = PoolModel::swap(&mut v2_state, swap);
+            let received = v3_state.swap(swap);
+            assert!((received - expected).abs() / expected < 1e-9);
+        }
+
+        // Most liquidity sits right around the price, the victim's swap leaves it
+        let concentrated = v3
+            .clone()
+            .with_position(v3.tick - 10, v3.tick + 10, v3.liquidity * 0.9);
+        let mut crossing = concentrated.clone();
+        let crossing_out = crossing.swap(swaps[0]);
+        assert!(crossing_out < v3.clone().swap(swaps[0]));
+        assert!(crossing.tick < v3.tick - 10);
+        assert!((crossing.liquidity - v3.liquidity * 0.1).abs() < 1e-6 * v3.liquidity);
+
+        // Tokens the pool doesn't hold fail the swap and leave the pool as is
+        let mut unheld = swaps[0].clone();
+        unheld.token_out = "USDT".to_string();
+        let mut untouched = v3.clone();
+        assert!(matches!(
+            untouched.try_swap(&unheld),
+            Err(DetectorError::SwapFailed { .. })
+        ));
+        assert_eq!(untouched, v3);
+
+        let v2_pools = HashMap::from([("0xpool1".to_string(), v2)]);
+        let v3_pools: HashMap<String, Box<dyn PoolModel>> =
+            HashMap::from([("0xpool1".to_string(), Box::new(v3) as Box<dyn PoolModel>)]);
+        let from_v2 = find_sandwich_attacks_by_simulation(&v2_pools, &transactions);
+        let from_v3 = find_sandwich_attacks_by_simulation(&v3_pools, &transactions);
+        assert!(!from_v2.is_empty());
+        assert_eq!(from_v2.len(), from_v3.len());
+        for (v2_attack, v3_attack) in from_v2.iter().zip(&from_v3) {
+            assert_eq!(v2_attack.attack_id(), v3_attack.attack_id());
+            assert!(
+                (v2_attack.victim_loss_percentage - v3_attack.victim_loss_percentage).abs() < 1e-6
+            );
+        }
+    }
+
+    #[test]
+    fn test_stableswap_keeps_stable_pairs_near_the_peg() {
+        let template = crate::ingest::csv::sample_transactions()[0].clone();
+        let pool = StableSwapPool::new(
+            vec!["USDC".into(), "USDT".into(), "DAI".into()],
+            vec![1_000_000.0; 3],
+            2000.0,
+        );
+        let d = pool.invariant();
+        assert!((d - 3_000_000.0).abs() < 1e-6);
+
+        let swap = |hash: &str, position: u32, from: &str, token_in: &str, amount_in: f64| {
+            let token_out = if token_in == "USDC" { "USDT" } else { "USDC" };
+            return SwapTransaction {
+                tx_hash: hash.to_string(),
+                block_number: 1,
+                tx_position_in_block: position,
+                from_address: from.to_string(),
+                token_in: token_in.to_string(),
+                token_out: token_out.to_string(),
+                amount_in,
+                pool_address: "0xcurve".to_string(),
+                ..template.clone()
+            };
+        };
+        let victim = swap("0xvictim", 2, "0xuser", "USDC", 50_000.0);
+        let stable_out = pool.clone().swap(&victim);
+        let constant_product_out =
+            Pool::new(1_000_000.0, 1_000_000.0, "USDC".into(), "USDT".into())
+                .simulate_swap(&victim)
+                .tokens_received;
+        assert!(stable_out > 49_970.0 && stable_out < 50_000.0);
+        assert!(constant_product_out < 47_700.0);
+        // The invariant holds, it only grows by the fee
+        let mut after = pool.clone();
+        after.swap(&victim);
+        assert!(after.invariant() >= d);
+
+        // A sandwich replayed with the pool's real outputs
+        let mut state = pool.clone();
+        let mut block = vec![
+            swap("0xfront", 1, "0xbot", "USDC", 400_000.0),
+            victim,
+            swap("0xback", 3, "0xbot", "USDT", 0.0),
+        ];
+        block[0].amount_out = state.swap(&block[0]);
+        block[1].amount_out = state.swap(&block[1]);
+        block[2].amount_in = block[0].amount_out;
+        block[2].amount_out = state.swap(&block[2]);
+
+        // Stablecoins being equivalent, both directions would look the same
+        let mut config = Config::default();
+        config.tokens.groups.remove("STABLECOINS");
+        let stable_pools = HashMap::from([("0xcurve".to_string(), pool)]);
+        let attacks =
+            find_sandwich_attacks_by_simulation_with_config(&stable_pools, &block, &config).attacks;
+        assert_eq!(attacks.len(), 1);
+        assert!(attacks[0].victim_loss_percentage > 0.0);
+        assert!(attacks[0].victim_loss_percentage < 1.0);
+        // Constant product doesn't even reproduce the victim's output
+        let product_pools = HashMap::from([(
+            "0xcurve".to_string(),
+            Pool::new(1_000_000.0, 1_000_000.0, "USDC".into(), "USDT".into()),
+        )]);
+        let outcome =
+            find_sandwich_attacks_by_simulation_with_config(&product_pools, &block, &config);
+        assert!(outcome.attacks.is_empty());
+        assert!(matches!(
+            outcome.skipped[0].1,
+            DetectorError::SimulationDiverged { .. }
+        ));
+    }
+
+    #[test]
+    fn test_stableswap_matches_the_3pool_get_dy() {
+        let template = crate::ingest::csv::sample_transactions()[0].clone();
+        // The 3pool's A and 1 bp fee, USDT short of the peg
+        let pool = StableSwapPool::new(
+            vec!["DAI".into(), "USDC".into(), "USDT".into()],
+            vec![100_000_000.0, 100_000_000.0, 50_000_000.0],
+            2000.0,
+        )
+        .with_fee_bps(1);
+        let swap = SwapTransaction {
+            token_in: "USDT".to_string(),
+            token_out: "DAI".to_string(),
+            amount_in: 10_000_000.0,
+            ..template
+        };
+        // get_dy(2, 0, 10_000_000 * 10**6) with the contract's integer math
+        let expected = 10_002_569.742_278_84;
+        let received = pool.clone().swap(&swap);
+        assert!((received - expected).abs() / expected < 1e-9);
+    }
+
+    #[test]
+    fn test_weighted_pool_generalizes_constant_product() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let tokens = vec!["USDC".to_string(), "SHIB".to_string()];
+        let balances = vec![1000000.0, 50000000000.0];
+        let v2 = Pool::new(balances[0], balances[1], "USDC".into(), "SHIB".into());
+        let even = WeightedPool::new(tokens.clone(), balances.clone(), vec![50.0, 50.0]);
+        let swap = transactions
+            .iter()
+            .find(|tx| tx.pool_address == "0xpool1")
+            .unwrap();
+        let expected = v2.simulate_swap(swap).tokens_received;
+        assert!((even.clone().swap(swap) - expected).abs() / expected < 1e-9);
+
+        // Same spot price, but the USDC side holds only 20% of the value
+        let lopsided = WeightedPool::new(tokens, vec![250000.0, 50000000000.0], vec![20.0, 80.0]);
+        assert!((lopsided.spot_price(0, 1) - even.spot_price(0, 1)).abs() < 1e-6);
+        let mut after = lopsided.clone();
+        assert!(after.swap(swap) < expected);
+        assert_eq!(after.balances[0], 250000.0 + swap.amount_in);
+
+        // Tokens the pool doesn't hold fail the swap and leave the pool as is
+        let mut unheld = swap.clone();
+        unheld.token_out = "USDT".to_string();
+        assert!(matches!(
+            after.clone().try_swap(&unheld),
+            Err(DetectorError::SwapFailed { .. })
+        ));
+        let mut stable =
+            StableSwapPool::new(vec!["USDC".into(), "DAI".into()], vec![1e6; 2], 100.0);
+        assert!(stable.try_swap(&unheld).is_err());
+        assert_eq!(stable.balances, vec![1e6; 2]);
+
+        // Pool treats any token but A as B, the weighted pool knows its tokens
+        let pair_swaps: Vec<_> = transactions
+            .into_iter()
+            .filter(|tx| tx.token_in != "USDT" && tx.token_out != "USDT")
+            .collect();
+        let v2_pools = HashMap::from([("0xpool1".to_string(), v2)]);
+        let weighted_pools = HashMap::from([("0xpool1".to_string(), even)]);
+        let from_v2 = find_sandwich_attacks_by_simulation(&v2_pools, &pair_swaps);
+        let from_weighted = find_sandwich_attacks_by_simulation(&weighted_pools, &pair_swaps);
+        assert!(!from_v2.is_empty());
+        assert_eq!(from_v2.len(), from_weighted.len());
+        for (v2_attack, weighted_attack) in from_v2.iter().zip(&from_weighted) {
+            assert!(
+                (v2_attack.victim_loss_percentage - weighted_attack.victim_loss_percentage).abs()
+                    < 1e-6
+            );
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::same_block_sim::Pool;
+use super::transactions::SwapTransaction;
+
+/// Token pairs a pool traded with their number of swaps, in first-seen order.
+type PairCounts<'a> = Vec<((&'a str, &'a str), usize)>;
+
+/// The pools a set of swaps goes through, with the token pair each trades,
+/// so a simulation `pool_map` only needs their reserves.
+///
+/// Reserves come from snapshots (e.g. `SqliteStore::load_pool_map`), from
+/// `with_reserves` or from any provider through `seed_with`. Pools without
+/// reserves are left out of `pool_map`, their candidates are then skipped
+/// as `DetectorError::UnknownPool`.
+#[derive(Debug, Clone, Default)]
+pub struct PoolRegistry {
+    pairs: BTreeMap<String, (String, String)>,
+    states: HashMap<String, Pool>,
+}
+
+impl PoolRegistry {
+    /// Each pool's pair is the one most of its swaps trade, tokens in
+    /// lexicographic order like Uniswap's `token0`/`token1`.
+    pub fn from_transactions(transactions: &[SwapTransaction]) -> Self {
+        let mut counts: BTreeMap<&str, PairCounts> = BTreeMap::new();
+        for tx in transactions {
+            let pair = match tx.token_in <= tx.token_out {
+                true => (tx.token_in.as_str(), tx.token_out.as_str()),
+                false => (tx.token_out.as_str(), tx.token_in.as_str()),
+            };
+            let pairs = counts.entry(tx.pool_address.as_str()).or_default();
+            match pairs.iter_mut().find(|(seen, _)| *seen == pair) {
+                Some((_, count)) => *count += 1,
+                None => pairs.push((pair, 1)),
+            }
+        }
+
+        let mut pairs = BTreeMap::new();
+        for (pool_address, seen) in counts {
+            // The first seen wins ties
+            let mut best = seen[0];
+            for candidate in &seen[1..] {
+                if candidate.1 > best.1 {
+                    best = *candidate;
+                }
+            }
+            let (token_a, token_b) = best.0;
+            pairs.insert(
+                pool_address.to_string(),
+                (token_a.to_string(), token_b.to_string()),
+            );
+        }
+        return Self {
+            pairs,
+            states: HashMap::new(),
+        };
+    }
+
+    /// Pool addresses, sorted.
+    pub fn pools(&self) -> impl Iterator<Item = &str> {
+        return self.pairs.keys().map(|pool_address| pool_address.as_str());
+    }
+
+    /// The inferred `(token_a, token_b)` of a pool.
+    pub fn tokens(&self, pool_address: &str) -> Option<(&str, &str)> {
+        return self
+            .pairs
+            .get(pool_address)
+            .map(|(token_a, token_b)| (token_a.as_str(), token_b.as_str()));
+    }
+
+    /// Reserves in the order of `tokens`. Ignored for pools none of the
+    /// swaps went through.
+    pub fn with_reserves(mut self, pool_address: &str, reserve_a: f64, reserve_b: f64) -> Self {
+        if let Some((token_a, token_b)) = self.pairs.get(pool_address) {
+            let pool = Pool::new(reserve_a, reserve_b, token_a.clone(), token_b.clone());
+            self.states.insert(pool_address.to_string(), pool);
+        }
+        return self;
+    }
+
+    /// Take the states of `snapshots` as they are, their tokens win over
+    /// the inferred ones.
+    pub fn with_snapshots(mut self, snapshots: HashMap<String, Pool>) -> Self {
+        for (pool_address, pool) in snapshots {
+            if self.pairs.contains_key(&pool_address) {
+                self.states.insert(pool_address, pool);
+            }
+        }
+        return self;
+    }
+
+    /// Ask `provider` for the `(reserve_a, reserve_b)` of every pool still
+    /// without reserves, given its address and tokens. `None` leaves the
+    /// pool out. Returns how many pools were seeded.
+    pub fn seed_with<F>(&mut self, mut provider: F) -> Result<usize, String>
+    where
+        F: FnMut(&str, (&str, &str)) -> Result<Option<(f64, f64)>, String>,
+    {
+        let mut seeded = 0;
+        for (pool_address, (token_a, token_b)) in &self.pairs {
+            if self.states.contains_key(pool_address) {
+                continue;
+            }
+            if let Some((reserve_a, reserve_b)) =
+                provider(pool_address, (token_a.as_str(), token_b.as_str()))?
+            {
+                let pool = Pool::new(reserve_a, reserve_b, token_a.clone(), token_b.clone());
+                self.states.insert(pool_address.clone(), pool);
+                seeded += 1;
+            }
+        }
+        return Ok(seeded);
+    }
+
+    /// Pools that have no reserves yet.
+    pub fn unseeded(&self) -> Vec<&str> {
+        return self
+            .pools()
+            .filter(|pool_address| !self.states.contains_key(*pool_address))
+            .collect();
+    }
+
+    /// Every pool with reserves, ready for `find_sandwich_attacks_by_simulation`.
+    pub fn pool_map(&self) -> HashMap<String, Pool> {
+        return self.states.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+
+    #[test]
+    fn test_registry_infers_pairs_and_seeds_reserves() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let registry = PoolRegistry::from_transactions(&transactions);
+        // USDT/SHIB swaps through 0xpool1 are outnumbered by USDC/SHIB ones,
+        // NEWTOKEN trades more against USDC than against ETH in 0xpool4
+        assert_eq!(registry.tokens("0xpool1"), Some(("SHIB", "USDC")));
+        assert_eq!(registry.tokens("0xpool4"), Some(("NEWTOKEN", "USDC")));
+        assert_eq!(registry.tokens("0xunknown"), None);
+        assert_eq!(registry.unseeded().len(), registry.pools().count());
+
+        let mut registry = registry
+            .with_reserves("0xpool1", 50000000000.0, 1000000.0)
+            .with_reserves("0xunknown", 1.0, 1.0)
+            .with_snapshots(HashMap::from([(
+                "0xpool2".to_string(),
+                Pool::new(1000.0, 3_200_000.0, "ETH".into(), "USDC".into()),
+            )]));
+        let seeded = registry
+            .seed_with(|pool_address, (token_a, token_b)| {
+                if pool_address != "0xpool4" {
+                    return Ok(None);
+                }
+                assert_eq!((token_a, token_b), ("NEWTOKEN", "USDC"));
+                return Ok(Some((1_000_000.0, 10_000.0)));
+            })
+            .unwrap();
+        assert_eq!(seeded, 1);
+        assert!(!registry.unseeded().contains(&"0xpool4"));
+        assert!(registry
+            .seed_with(|_, _| Err("offline".to_string()))
+            .is_err());
+
+        let pool_map = registry.pool_map();
+        assert_eq!(pool_map.len(), 3);
+        assert_eq!(pool_map["0xpool2"].token_b_address, "USDC");
+        let block: Vec<_> = transactions
+            .iter()
+            .filter(|tx| tx.block_number == 12360)
+            .cloned()
+            .collect();
+        let hand_written = crate::test_support::sample_pool_map();
+        assert_eq!(
+            find_sandwich_attacks_by_simulation(&pool_map, &block),
+            find_sandwich_attacks_by_simulation(&hand_written, &block)
+        );
+    }
+}
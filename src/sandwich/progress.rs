@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+use super::transactions::BlockId;
+
+/// Where a scan is, passed to progress hooks after each block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub blocks_processed: usize,
+    pub total_blocks: usize,
+    pub attacks_found: usize,
+    pub last_block: BlockId,
+    pub elapsed: Duration,
+    /// Time left at the average pace so far.
+    pub eta: Duration,
+}
+
+impl Progress {
+    /// Share of the blocks processed, between 0 and 1.
+    pub fn fraction(&self) -> f64 {
+        if self.total_blocks == 0 {
+            return 1.0;
+        }
+        return self.blocks_processed as f64 / self.total_blocks as f64;
+    }
+}
+
+/// Counts blocks and attacks of a scan to build `Progress` snapshots.
+pub(crate) struct ProgressTracker {
+    started: Instant,
+    total_blocks: usize,
+    blocks_processed: usize,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(total_blocks: usize) -> Self {
+        return Self {
+            started: Instant::now(),
+            total_blocks,
+            blocks_processed: 0,
+        };
+    }
+
+    /// Record `block` as processed, with `attacks_found` attacks so far.
+    pub(crate) fn advance(&mut self, block: BlockId, attacks_found: usize) -> Progress {
+        self.blocks_processed += 1;
+        let elapsed = self.started.elapsed();
+        let remaining = self.total_blocks.saturating_sub(self.blocks_processed);
+        let eta = elapsed.mul_f64(remaining as f64 / self.blocks_processed as f64);
+        return Progress {
+            blocks_processed: self.blocks_processed,
+            total_blocks: self.total_blocks,
+            attacks_found,
+            last_block: block,
+            elapsed,
+            eta,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::cancel::CancellationToken;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches_with_progress;
+
+    #[test]
+    fn test_progress_reported_after_each_block() {
+        let transactions = crate::ingest::csv::sample_transactions();
+
+        let mut reports = Vec::new();
+        let outcome = find_same_block_sandwiches_with_progress(
+            &transactions,
+            &Config::default(),
+            &CancellationToken::new(),
+            |progress| reports.push(progress),
+        );
+
+        let last = reports.last().expect("No progress reported");
+        assert_eq!(reports.len(), last.total_blocks);
+        assert_eq!(last.fraction(), 1.0);
+        assert_eq!(last.eta, Duration::ZERO);
+        assert_eq!(last.attacks_found, outcome.attacks.len());
+        assert!(reports
+            .windows(2)
+            .all(|pair| pair[0].last_block < pair[1].last_block
+                && pair[0].attacks_found <= pair[1].attacks_found));
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+
+use super::detector::{Detector, HeuristicsDetector, Pipeline};
+use super::error::DetectorError;
+use crate::config::Config;
+
+/// Builds a detector from the shared config, so plugins see the same token
+/// equivalence groups and thresholds as the built-in detectors.
+pub type DetectorFactory = Box<dyn Fn(&Config) -> Box<dyn Detector> + Send + Sync>;
+
+/// Named detectors a `Pipeline` can be assembled from, including ones
+/// registered by downstream crates:
+///
+/// ```
+/// use toxicflow_detector::config::Config;
+/// use toxicflow_detector::sandwich::detector::{Block, Detection, Detector};
+/// use toxicflow_detector::sandwich::registry::DetectorRegistry;
+///
+/// struct KnownBots;
+///
+/// impl Detector for KnownBots {
+///     fn name(&self) -> &str {
+///         "known_bots"
+///     }
+///
+///     fn detect(&self, _block: &Block) -> Vec<Detection> {
+///         Vec::new()
+///     }
+/// }
+///
+/// let mut registry = DetectorRegistry::new();
+/// registry.register("known_bots", |_config| Box::new(KnownBots)).unwrap();
+/// let pipeline = registry.pipeline(&["heuristics", "known_bots"], &Config::default()).unwrap();
+/// assert_eq!(pipeline.detector_names(), ["heuristics", "known_bots"]);
+/// ```
+///
+/// The simulation detector needs pool state, so it is not registered by
+/// default. Register it with the pools in the factory closure.
+pub struct DetectorRegistry {
+    factories: BTreeMap<String, DetectorFactory>,
+}
+
+impl Default for DetectorRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.factories.insert(
+            "heuristics".to_string(),
+            Box::new(|config: &Config| -> Box<dyn Detector> {
+                Box::new(HeuristicsDetector::new(config.clone()))
+            }),
+        );
+        return registry;
+    }
+}
+
+impl DetectorRegistry {
+    /// A registry with the built-in `heuristics` detector.
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn empty() -> Self {
+        return Self {
+            factories: BTreeMap::new(),
+        };
+    }
+
+    pub fn register<F>(&mut self, name: &str, factory: F) -> Result<(), DetectorError>
+    where
+        F: Fn(&Config) -> Box<dyn Detector> + Send + Sync + 'static,
+    {
+        if self.factories.contains_key(name) {
+            return Err(DetectorError::DuplicateDetector(name.to_string()));
+        }
+        self.factories.insert(name.to_string(), Box::new(factory));
+        return Ok(());
+    }
+
+    /// Registered names, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        return self.factories.keys().map(|name| name.as_str()).collect();
+    }
+
+    pub fn create(&self, name: &str, config: &Config) -> Result<Box<dyn Detector>, DetectorError> {
+        match self.factories.get(name) {
+            Some(factory) => Ok(factory(config)),
+            None => Err(DetectorError::UnknownDetector(name.to_string())),
+        }
+    }
+
+    /// A pipeline running the named detectors, in the given order.
+    pub fn pipeline(&self, names: &[&str], config: &Config) -> Result<Pipeline, DetectorError> {
+        let mut pipeline = Pipeline::new();
+        for name in names {
+            pipeline = pipeline.with_boxed_detector(self.create(name, config)?);
+        }
+        return Ok(pipeline);
+    }
+
+    /// A pipeline running every registered detector, in name order.
+    pub fn pipeline_all(&self, config: &Config) -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        for factory in self.factories.values() {
+            pipeline = pipeline.with_boxed_detector(factory(config));
+        }
+        return pipeline;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::detector::{Block, CustomDetection, Detection};
+    use crate::sandwich::tokens::TokenEquivalence;
+
+    /// Flags swaps between tokens its config treats as equivalent.
+    struct SameGroupSwaps {
+        tokens: TokenEquivalence,
+    }
+
+    impl Detector for SameGroupSwaps {
+        fn name(&self) -> &str {
+            "same_group_swaps"
+        }
+
+        fn detect(&self, block: &Block) -> Vec<Detection> {
+            return block
+                .transactions
+                .iter()
+                .filter(|tx| self.tokens.are_equivalent(&tx.token_in, &tx.token_out))
+                .map(|tx| {
+                    Detection::Custom(CustomDetection {
+                        name: self.name().to_string(),
+                        chain_id: tx.chain_id,
+                        front_run_tx: tx.clone(),
+                        victim_tx: tx.clone(),
+                        back_run_tx: tx.clone(),
+                        confidence_score: 1.0,
+                        classification: Default::default(),
+                        details: serde_json::json!({ "pool": tx.pool_address }),
+                    })
+                })
+                .collect();
+        }
+    }
+
+    #[test]
+    fn test_registered_detector_runs_in_pipeline() {
+        let mut transactions = crate::ingest::csv::sample_transactions();
+        transactions[0].token_out = "USDT".to_string();
+
+        let mut registry = DetectorRegistry::new();
+        registry
+            .register("same_group_swaps", |config| {
+                Box::new(SameGroupSwaps {
+                    tokens: config.tokens.clone(),
+                })
+            })
+            .unwrap();
+        assert_eq!(
+            registry.register("heuristics", |config| Box::new(HeuristicsDetector::new(
+                config.clone()
+            ))),
+            Err(DetectorError::DuplicateDetector("heuristics".to_string()))
+        );
+        assert_eq!(registry.names(), ["heuristics", "same_group_swaps"]);
+        assert!(registry.pipeline(&["acme"], &Config::default()).is_err());
+
+        let detections = registry.pipeline_all(&Config::default()).run(&transactions);
+        let custom: Vec<&Detection> = detections
+            .iter()
+            .filter(|detection| matches!(detection, Detection::Custom(_)))
+            .collect();
+        assert_eq!(custom.len(), 1);
+        assert_eq!(custom[0].victim_tx().tx_hash, transactions[0].tx_hash);
+        let json = serde_json::to_value(custom[0]).unwrap();
+        assert_eq!(json["detector"], "custom");
+        assert_eq!(json["name"], "same_group_swaps");
+
+        // The plugin sees the shared config
+        let mut config = Config::default();
+        config.tokens.groups.clear();
+        let detections = registry
+            .pipeline(&["same_group_swaps"], &config)
+            .unwrap()
+            .run(&transactions);
+        assert!(detections.is_empty());
+    }
+}
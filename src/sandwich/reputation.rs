@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use super::same_block_heuristics::{score_candidate, SandwichAttackByHeuristics};
+use super::severity::Severity;
+use super::streaming::AttackerHistory;
+use crate::config::Config;
+
+/// What the whole input says about one attacker (front-run sender).
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AttackerReputation {
+    /// Sandwich candidates with this attacker.
+    pub appearances: u64,
+    /// Candidates that were profitable.
+    pub wins: u64,
+    pub total_profit_usd: f64,
+}
+
+impl AttackerReputation {
+    pub fn win_rate(&self) -> f64 {
+        if self.appearances == 0 {
+            return 0.0;
+        }
+        return self.wins as f64 / self.appearances as f64;
+    }
+
+    /// 0 for a one-off match, rising linearly to 1 once the attacker has
+    /// `full_after` more appearances.
+    pub fn repeat_offender_factor(&self, full_after: u64) -> f32 {
+        let repeats = self.appearances.saturating_sub(1);
+        return (repeats as f32 / full_after.max(1) as f32).min(1.0);
+    }
+}
+
+/// Reputation of every attacker of `attacks`, keyed by lowercased address.
+pub fn attacker_reputations(
+    attacks: &[SandwichAttackByHeuristics],
+) -> HashMap<String, AttackerReputation> {
+    let mut reputations: HashMap<String, AttackerReputation> = HashMap::new();
+    for attack in attacks {
+        let reputation = reputations
+            .entry(attack.front_run_tx.from_address.to_lowercase())
+            .or_default();
+        reputation.appearances += 1;
+        if attack.confidence_flags.is_profitable {
+            reputation.wins += 1;
+        }
+        reputation.total_profit_usd += attack.confidence_flags.total_profit_usd;
+    }
+    return reputations;
+}
+
+/// Second pass over a batch of detections: set each attack's
+/// `attacker_repeat_offender` factor from its attacker's reputation, rescore
+/// it and drop those now below `min_confidence`. A one-off match is
+/// ambiguous, the 50th by the same address isn't.
+pub fn apply_reputation(
+    attacks: Vec<SandwichAttackByHeuristics>,
+    config: &Config,
+) -> Vec<SandwichAttackByHeuristics> {
+    let reputations = attacker_reputations(&attacks);
+    let mut kept = Vec::new();
+    for mut attack in attacks {
+        let reputation = &reputations[&attack.front_run_tx.from_address.to_lowercase()];
+        rescore_repeat_offender(&mut attack, reputation, config);
+        if attack.confidence_score >= config.heuristics.min_confidence {
+            kept.push(attack);
+        }
+    }
+    return kept;
+}
+
+/// `apply_reputation` for one block of a stream: an attacker's appearances
+/// also count its earlier attacks from `history` (keyed by lowercased
+/// address, see `StreamingDetector`). Severities are reassessed with the same
+/// counts. The repeat offender factor and the confidence filter are only
+/// applied with `attacker_reputation`.
+pub fn apply_attacker_history(
+    attacks: Vec<SandwichAttackByHeuristics>,
+    history: &HashMap<String, AttackerHistory>,
+    config: &Config,
+) -> Vec<SandwichAttackByHeuristics> {
+    let reputations = attacker_reputations(&attacks);
+    let mut kept = Vec::new();
+    for mut attack in attacks {
+        let attacker = attack.front_run_tx.from_address.to_lowercase();
+        let reputation = AttackerReputation {
+            appearances: reputations[&attacker].appearances
+                + history.get(&attacker).map_or(0, |history| history.attacks),
+            ..AttackerReputation::default()
+        };
+        if config.heuristics.attacker_reputation {
+            rescore_repeat_offender(&mut attack, &reputation, config);
+            if attack.confidence_score < config.heuristics.min_confidence {
+                continue;
+            }
+        }
+        attack.severity = Severity::assess(
+            attack.estimated_victim_loss_usd(),
+            attack.confidence_score,
+            reputation.appearances,
+            &config.severity,
+        );
+        kept.push(attack);
+    }
+    return kept;
+}
+
+fn rescore_repeat_offender(
+    attack: &mut SandwichAttackByHeuristics,
+    reputation: &AttackerReputation,
+    config: &Config,
+) {
+    attack.confidence_flags.attacker_repeat_offender =
+        reputation.repeat_offender_factor(config.heuristics.repeat_offender_appearances);
+    attack.confidence_score = score_candidate(
+        &attack.front_run_tx,
+        &attack.victim_tx,
+        &attack.back_run_tx,
+        &attack.confidence_flags,
+        config,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches_with_config;
+    use crate::sandwich::transactions::SwapTransaction;
+
+    #[test]
+    fn test_repeat_attackers_are_boosted() {
+        let mut transactions = crate::ingest::csv::sample_transactions();
+        // The attacker of block 12360 strikes again in three later blocks
+        let repeated: Vec<_> = transactions
+            .iter()
+            .filter(|tx| tx.block_number == 12360)
+            .cloned()
+            .collect();
+        for offset in 1..=3 {
+            transactions.extend(repeated.iter().map(|tx| SwapTransaction {
+                tx_hash: format!("{}-{}", tx.tx_hash, offset),
+                block_number: tx.block_number + 1000 * offset,
+                ..tx.clone()
+            }));
+        }
+        let mut config = Config::default();
+        config.heuristics.weights.base = 0.0;
+        let before = find_same_block_sandwiches_with_config(&transactions, &config).attacks;
+
+        let reputations = attacker_reputations(&before);
+        let repeat_attack = before
+            .iter()
+            .find(|attack| attack.victim_tx.block_number == 12360)
+            .unwrap();
+        let repeat_attacker = repeat_attack.front_run_tx.from_address.to_lowercase();
+        let reputation = &reputations[&repeat_attacker];
+        assert_eq!(reputation.appearances, 4);
+        assert!((0.0..=1.0).contains(&reputation.win_rate()));
+
+        config.heuristics.attacker_reputation = true;
+        let after = find_same_block_sandwiches_with_config(&transactions, &config).attacks;
+        assert_eq!(after.len(), before.len());
+        for (before, after) in before.iter().zip(&after) {
+            let factor = after.confidence_flags.attacker_repeat_offender;
+            if after.front_run_tx.from_address.to_lowercase() == repeat_attacker {
+                assert_eq!(factor, 0.3);
+                assert!(after.confidence_score > before.confidence_score);
+            } else {
+                assert_eq!(factor, 0.0);
+                assert_eq!(after.confidence_score, before.confidence_score);
+            }
+        }
+
+        // Filtering waits for the rescored confidence
+        config.heuristics.min_confidence = repeat_attack.confidence_score + 0.01;
+        let boosted = find_same_block_sandwiches_with_config(&transactions, &config).attacks;
+        let kept = boosted
+            .iter()
+            .filter(|attack| attack.front_run_tx.from_address.to_lowercase() == repeat_attacker)
+            .count();
+        assert_eq!(kept, 4);
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt;
+
+use rhai::{Dynamic, Engine, Scope, AST};
+
+use super::error::DetectorError;
+use super::same_block_heuristics::SandwichAttackByHeuristics;
+
+/// Operations one rule may run per candidate, so a runaway script can't stall detection.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// A Rhai expression evaluated per candidate, with `front`, `victim` and
+/// `back` (the swaps, field names as in `SwapTransaction`) and `flags` (the
+/// `ConfidenceFlags`) in scope, e.g.
+/// `front.gas_price > victim.gas_price * 2.0 && back.is_contract_caller`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Added to `ConfidenceFlags::custom_flags` when the condition holds.
+    pub name: String,
+    pub condition: String,
+    /// Added to the confidence score (capped at 1.0) when the condition holds.
+    #[serde(default)]
+    pub weight: f32,
+    /// Drop the candidate when the condition holds.
+    #[serde(default)]
+    pub veto: bool,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    rules: Vec<Rule>,
+}
+
+/// Compiled rules, applied to heuristic candidates by `SandwichDetector`.
+pub struct RuleSet {
+    engine: Engine,
+    rules: Vec<(Rule, AST)>,
+}
+
+impl fmt::Debug for RuleSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.rules.iter().map(|(rule, _)| rule))
+            .finish()
+    }
+}
+
+impl RuleSet {
+    pub fn new(rules: Vec<Rule>) -> Result<Self, DetectorError> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let mut compiled = Vec::new();
+        for rule in rules {
+            let ast =
+                engine
+                    .compile_expression(&rule.condition)
+                    .map_err(|err| DetectorError::Rule {
+                        name: rule.name.clone(),
+                        message: err.to_string(),
+                    })?;
+            compiled.push((rule, ast));
+        }
+        return Ok(Self {
+            engine,
+            rules: compiled,
+        });
+    }
+
+    /// Rules as `[[rules]]` tables with `name`, `condition`, `weight` and `veto`.
+    pub fn from_toml_str(text: &str) -> Result<Self, DetectorError> {
+        let file: RulesFile = toml::from_str(text).map_err(|err| DetectorError::Rule {
+            name: "rules file".to_string(),
+            message: err.to_string(),
+        })?;
+        return Self::new(file.rules);
+    }
+
+    /// Evaluate every rule on `attack`, adding the flags and weights of the
+    /// rules that hold. Returns `false` if a veto rule holds.
+    pub fn apply(&self, attack: &mut SandwichAttackByHeuristics) -> Result<bool, DetectorError> {
+        let mut scope = Scope::new();
+        scope.push_constant("front", to_dynamic(&attack.front_run_tx)?);
+        scope.push_constant("victim", to_dynamic(&attack.victim_tx)?);
+        scope.push_constant("back", to_dynamic(&attack.back_run_tx)?);
+        scope.push_constant("flags", to_dynamic(&attack.confidence_flags)?);
+
+        for (rule, ast) in &self.rules {
+            let holds = self
+                .engine
+                .eval_ast_with_scope::<bool>(&mut scope, ast)
+                .map_err(|err| DetectorError::Rule {
+                    name: rule.name.clone(),
+                    message: err.to_string(),
+                })?;
+            if !holds {
+                continue;
+            }
+            if rule.veto {
+                return Ok(false);
+            }
+            attack.confidence_flags.custom_flags.push(rule.name.clone());
+            attack.confidence_score = (attack.confidence_score + rule.weight).min(1.0);
+        }
+        return Ok(true);
+    }
+}
+
+fn to_dynamic<T: serde::Serialize>(value: &T) -> Result<Dynamic, DetectorError> {
+    return rhai::serde::to_dynamic(value).map_err(|err| DetectorError::Rule {
+        name: "scope".to_string(),
+        message: err.to_string(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::builder::SandwichDetector;
+    use crate::sandwich::cancel::CancellationToken;
+    use crate::sandwich::detector::Pipeline;
+    use crate::sandwich::severity::Severity;
+
+    #[test]
+    fn test_rules_flag_and_veto_candidates() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let rules = RuleSet::from_toml_str(
+            r#"
+            [[rules]]
+            name = "big_victim"
+            condition = "victim.usd_value_in >= 5000.0"
+            weight = 0.05
+
+            [[rules]]
+            name = "ignore_pool4"
+            condition = "victim.pool_address == \"0xpool4\" && !flags.is_profitable"
+            veto = true
+            "#,
+        )
+        .unwrap();
+
+        let plain = SandwichDetector::builder()
+            .build()
+            .find_sandwiches(&transactions);
+        let scripted = SandwichDetector::builder()
+            .rules(rules)
+            .build()
+            .find_sandwiches(&transactions);
+        assert!(!scripted
+            .skipped
+            .iter()
+            .any(|(_, err)| matches!(err, DetectorError::Rule { .. })));
+
+        let vetoed = plain
+            .attacks
+            .iter()
+            .filter(|attack| {
+                attack.victim_tx.pool_address == "0xpool4" && !attack.confidence_flags.is_profitable
+            })
+            .count();
+        assert_eq!(scripted.attacks.len(), plain.attacks.len() - vetoed);
+        for attack in &scripted.attacks {
+            let flagged = attack
+                .confidence_flags
+                .custom_flags
+                .contains(&"big_victim".to_string());
+            assert_eq!(flagged, attack.victim_tx.usd_value_in >= 5000.0);
+        }
+
+        // A rule failing at runtime keeps the attack and reports why
+        let failing = RuleSet::from_toml_str(
+            r#"
+            [[rules]]
+            name = "typo"
+            condition = "no_such_function(victim)"
+            "#,
+        )
+        .unwrap();
+        let detector = SandwichDetector::builder().rules(failing).build();
+        let streamed: Vec<_> = detector.detect_stream(transactions.clone()).collect();
+        let failed = streamed.iter().filter(|item| item.is_err()).count();
+        assert_eq!(failed, plain.attacks.len());
+        assert_eq!(streamed.len(), 2 * plain.attacks.len());
+        let outcome = Pipeline::new()
+            .with_detector(detector)
+            .run_until(&transactions, &CancellationToken::new());
+        assert_eq!(outcome.attacks.len(), plain.attacks.len());
+        let rule_errors = outcome
+            .skipped
+            .iter()
+            .filter(|(_, err)| matches!(err, DetectorError::Rule { name, .. } if name == "typo"))
+            .count();
+        assert_eq!(rule_errors, plain.attacks.len());
+
+        // Weight added by rules counts towards the severity's confidence cap
+        let mut config = crate::config::Config::default();
+        config.severity.critical_loss_usd = f64::MIN;
+        config.severity.confident = 1.0;
+        let vouching = RuleSet::from_toml_str(
+            r#"
+            [[rules]]
+            name = "vouched"
+            condition = "true"
+            weight = 1.0
+            "#,
+        )
+        .unwrap();
+        let capped = SandwichDetector::builder()
+            .config(config.clone())
+            .build()
+            .find_sandwiches(&transactions);
+        assert!(capped
+            .attacks
+            .iter()
+            .any(|attack| attack.severity != Severity::Critical));
+        let vouched = SandwichDetector::builder()
+            .config(config)
+            .rules(vouching)
+            .build();
+        let found = vouched.find_sandwiches(&transactions);
+        assert_eq!(found.attacks.len(), capped.attacks.len());
+        assert!(found
+            .attacks
+            .iter()
+            .all(|attack| attack.severity == Severity::Critical));
+        assert!(vouched
+            .detect_stream(transactions.clone())
+            .all(|item| item.is_ok_and(|attack| attack.severity == Severity::Critical)));
+
+        let err = RuleSet::new(vec![Rule {
+            name: "broken".to_string(),
+            condition: "victim.gas_price >".to_string(),
+            weight: 0.0,
+            veto: false,
+        }])
+        .unwrap_err();
+        assert!(matches!(err, DetectorError::Rule { name, .. } if name == "broken"));
+    }
+}
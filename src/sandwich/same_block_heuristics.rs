@@ -1,9 +1,29 @@
-use super::tokens::{are_tokens_equivalent, are_tokens_reversed};
-use super::transactions::{group_transactions_by_block, SwapTransaction};
-use super::utils::is_sandwich_pattern;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, PartialEq, Clone)]
+use super::cancel::CancellationToken;
+use super::classification::{classify_sandwich, Classification};
+use super::dedup::dedup_overlapping;
+use super::error::{DetectionOutcome, DetectorError};
+use super::extraction::largest_tolerated_front_run;
+use super::progress::{Progress, ProgressTracker};
+use super::reputation::{apply_attacker_history, apply_reputation};
+use super::scoring::{additive_confidence, explain_confidence, ConfidenceFactor};
+use super::severity::{assign_severity, Severity};
+use super::streaming::AttackerHistory;
+use super::tokens::TokenEquivalence;
+use super::transactions::{
+    group_transactions_by_block, stream_transactions_by_block, SwapTransaction,
+};
+use super::utils::{format_legs, is_sandwich_pattern_with, sandwich_attack_id};
+use crate::config::{ConfidenceWeights, Config, HeuristicsConfig};
+use crate::telemetry;
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ConfidenceFlags {
+    /// The gas flags compare effective priority fees when all three legs
+    /// carry the EIP-1559 fields, see `SwapTransaction::effective_priority_fee`.
     pub higher_front_gas_price: bool,
     pub lower_back_gas_price: bool,
     pub front_is_contract: bool,
@@ -11,50 +31,295 @@ pub struct ConfidenceFlags {
     pub is_profitable: bool,
     pub is_proportional: bool,
     pub price_impact_rate: f32,
+    /// Multiplies the price impact's weight by the victim pool's depth, see
+    /// `pool_depth_scale`. 1 without reserves.
+    #[serde(default = "default_depth_scale")]
+    pub price_impact_depth_scale: f32,
     pub total_profit_usd: f64,
+    /// The front-runner is in `Config::known_actors`.
+    #[serde(default)]
+    pub is_known_bot: bool,
+    /// See `is_likely_private_bundle`.
+    #[serde(default)]
+    pub likely_private_bundle: bool,
+    /// See `uses_flashloan`.
+    #[serde(default)]
+    pub uses_flashloan: bool,
+    /// From 0 for a one-off attacker to 1 for a repeat offender, set by the
+    /// reputation pass (see `reputation::apply_reputation`).
+    #[serde(default)]
+    pub attacker_repeat_offender: f32,
+    /// The attacker and victim are linked in `Config::entities`, likely one
+    /// entity rebalancing.
+    #[serde(default)]
+    pub same_entity: bool,
+    /// See `swap_size_factor`.
+    #[serde(default)]
+    pub swap_size_factor: f32,
+    /// From 0 to 1, how bot-like the front-runner transacts overall, see
+    /// `AddressActivity::bot_factor`. 0 without activity data.
+    #[serde(default)]
+    pub bot_activity_factor: f32,
+    /// The front or back leg paid the builder directly (see
+    /// `HeuristicsConfig::builder_payments`), a bundle bid. The payments are
+    /// subtracted from `total_profit_usd`.
+    #[serde(default)]
+    pub paid_builder: bool,
+    /// `total_profit_usd` is only positive before the builder payments, the
+    /// attacker bid away the whole sandwich.
+    #[serde(default)]
+    pub profitable_only_before_bribe: bool,
+    /// Standard deviations the front-run's fee sits above the mean of its
+    /// block (priority fees when every swap of the block has them), robust to
+    /// blocks where everyone paid a lot. Set when scanning blocks.
+    #[serde(default)]
+    pub front_gas_zscore: f32,
+    /// Names of the user rules that held (see the `rules` feature).
+    #[serde(default)]
+    pub custom_flags: Vec<String>,
+}
+
+fn default_depth_scale() -> f32 {
+    1.0
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct SandwichAttackByHeuristics {
+    pub chain_id: u64,
     pub front_run_tx: SwapTransaction,
     pub victim_tx: SwapTransaction,
     pub back_run_tx: SwapTransaction,
     pub confidence_score: f32,
     pub confidence_flags: ConfidenceFlags,
+    #[serde(default)]
+    pub classification: Classification,
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+impl SandwichAttackByHeuristics {
+    pub fn attack_id(&self) -> String {
+        sandwich_attack_id(&self.front_run_tx, &self.victim_tx, &self.back_run_tx)
+    }
+
+    /// What the victim's worse rate than the front-run's cost it in USD
+    /// (`price_impact_rate` of its input), the heuristics' stand-in for the
+    /// loss the simulation measures.
+    pub fn estimated_victim_loss_usd(&self) -> f64 {
+        return self.victim_tx.usd_value_in * f64::from(self.confidence_flags.price_impact_rate);
+    }
+
+    /// The `Display` line followed by the three legs and the confidence flags.
+    pub fn summary(&self) -> String {
+        let flags = &self.confidence_flags;
+        return format!(
+            "{}\n{}  flags:  profitable={}, proportional={}, contracts={}/{}, gas_priority={}/{}, price_impact={:.3}",
+            self,
+            format_legs(&self.front_run_tx, &self.victim_tx, &self.back_run_tx),
+            flags.is_profitable,
+            flags.is_proportional,
+            flags.front_is_contract,
+            flags.back_is_contract,
+            flags.higher_front_gas_price,
+            flags.lower_back_gas_price,
+            flags.price_impact_rate
+        );
+    }
+
+    /// Why the attack got its confidence under the default weights, as the
+    /// contributing factors largest first.
+    pub fn explain(&self) -> Vec<ConfidenceFactor> {
+        return self.explain_with(&ConfidenceWeights::default());
+    }
+
+    /// `explain` for detections made with custom `weights`. Attacks scored
+    /// by a custom `HeuristicsConfig::scorer` didn't use the weights.
+    pub fn explain_with(&self, weights: &ConfidenceWeights) -> Vec<ConfidenceFactor> {
+        return explain_confidence(
+            &self.front_run_tx,
+            &self.victim_tx,
+            &self.back_run_tx,
+            &self.confidence_flags,
+            weights,
+        );
+    }
+}
+
+/// One compact line: block, attacker, victim, pool, profit and confidence.
+impl fmt::Display for SandwichAttackByHeuristics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block {} | attacker {} | victim {} | pool {} | profit ${:.2} | confidence {:.3}",
+            self.victim_tx.block_number,
+            self.front_run_tx.from_address,
+            self.victim_tx.from_address,
+            self.victim_tx.pool_address,
+            self.confidence_flags.total_profit_usd,
+            self.confidence_score
+        )
+    }
 }
 
 /// Find same block sandwich attacks in a list of swap transactions.
 ///
-/// First we group transactions by their block number, sorting them by position within the block.
+/// First we group transactions by their chain and block number, sorting them by position within the block.
 /// Then we find sandwiches within each block.
 pub fn find_same_block_sandwiches(
     transactions: &[SwapTransaction],
 ) -> Vec<SandwichAttackByHeuristics> {
-    let mut attacks = Vec::new();
-    let transactions_by_block = group_transactions_by_block(transactions);
+    return find_same_block_sandwiches_with_config(transactions, &Config::default()).attacks;
+}
+
+/// `find_same_block_sandwiches` with custom detection parameters, also
+/// returning the blocks that were skipped.
+pub fn find_same_block_sandwiches_with_config(
+    transactions: &[SwapTransaction],
+    config: &Config,
+) -> DetectionOutcome<SandwichAttackByHeuristics> {
+    return find_same_block_sandwiches_until(transactions, config, &CancellationToken::new());
+}
 
-    for (_block_number, block_transactions) in transactions_by_block {
-        let block_attacks = find_sandwiches_in_block(&block_transactions);
+/// `find_same_block_sandwiches_with_config` stopping once `cancel` fires.
+/// Blocks are scanned oldest first, so a truncated outcome covers a prefix
+/// of the chain.
+pub fn find_same_block_sandwiches_until(
+    transactions: &[SwapTransaction],
+    config: &Config,
+    cancel: &CancellationToken,
+) -> DetectionOutcome<SandwichAttackByHeuristics> {
+    return find_same_block_sandwiches_with_progress(transactions, config, cancel, |_| {});
+}
+
+/// `find_same_block_sandwiches_until` calling `progress` after each block.
+pub fn find_same_block_sandwiches_with_progress<F>(
+    transactions: &[SwapTransaction],
+    config: &Config,
+    cancel: &CancellationToken,
+    mut progress: F,
+) -> DetectionOutcome<SandwichAttackByHeuristics>
+where
+    F: FnMut(Progress),
+{
+    let scan_config = reputation_scan_config(config);
+    let mut outcome = DetectionOutcome::default();
+    let mut transactions_by_block: Vec<_> = group_transactions_by_block(transactions)
+        .into_iter()
+        .collect();
+    transactions_by_block.sort_by_key(|(block_id, _)| *block_id);
+
+    let mut tracker = ProgressTracker::new(transactions_by_block.len());
+    for (block_id, block_transactions) in transactions_by_block {
+        if cancel.is_cancelled() {
+            outcome.truncated = true;
+            break;
+        }
+        let block_attacks = scan_block_traced(&block_transactions, &scan_config);
         match block_attacks {
-            Ok(block_attacks) => attacks.extend(block_attacks),
-            Err(err) => println!("Error finding sandwiches: {}", err),
+            Ok(block_attacks) => outcome.attacks.extend(block_attacks),
+            Err(err) => outcome.skipped.push((block_id, err)),
         }
+        progress(tracker.advance(block_id, outcome.attacks.len()));
     }
 
-    return attacks;
+    if config.heuristics.attacker_reputation {
+        outcome.attacks = apply_reputation(outcome.attacks, config);
+    }
+    // Recurrence over the whole input rather than each block
+    assign_severity(&mut outcome.attacks, &config.severity);
+
+    return outcome;
+}
+
+/// Streaming counterpart of `find_same_block_sandwiches`.
+///
+/// Transactions are grouped into blocks as they arrive (the input must be
+/// ordered by block), so only one block is held in memory at a time and
+/// attacks are yielded as soon as their block is complete.
+pub fn detect_stream<I>(transactions: I) -> impl Iterator<Item = SandwichAttackByHeuristics>
+where
+    I: IntoIterator<Item = SwapTransaction>,
+{
+    detect_stream_with_config(transactions, Config::default())
+}
+
+/// `detect_stream` with custom detection parameters.
+pub fn detect_stream_with_config<I>(
+    transactions: I,
+    config: Config,
+) -> impl Iterator<Item = SandwichAttackByHeuristics>
+where
+    I: IntoIterator<Item = SwapTransaction>,
+{
+    stream_transactions_by_block(transactions)
+        .filter(|(_block_id, block_transactions)| block_transactions.len() >= 3)
+        .flat_map(move |(_block_id, block_transactions)| {
+            find_sandwiches_in_block(&block_transactions, &config).unwrap_or_default()
+        })
 }
 
 /// Go through the given swap transactions (assumed to be in the same block)
-/// and find any sandwich attacks.
-fn find_sandwiches_in_block(
+/// and find any sandwich attacks, counting recurrence for their severity
+/// within the block.
+pub(crate) fn find_sandwiches_in_block(
     transactions: &[SwapTransaction],
-) -> Result<Vec<SandwichAttackByHeuristics>, String> {
+    config: &Config,
+) -> Result<Vec<SandwichAttackByHeuristics>, DetectorError> {
+    let mut attacks = scan_block_traced(transactions, config)?;
+    assign_severity(&mut attacks, &config.severity);
+    return Ok(attacks);
+}
+
+/// `find_sandwiches_in_block` for a stream, counting the attackers' earlier
+/// attacks in `history` (see `apply_attacker_history`).
+pub(crate) fn find_sandwiches_in_block_with_history(
+    transactions: &[SwapTransaction],
+    history: &HashMap<String, AttackerHistory>,
+    config: &Config,
+) -> Result<Vec<SandwichAttackByHeuristics>, DetectorError> {
+    let attacks = scan_block_traced(transactions, &reputation_scan_config(config))?;
+    return Ok(apply_attacker_history(attacks, history, config));
+}
+
+/// The reputation pass filters by confidence once attacks are rescored, so
+/// the scan itself must keep everything.
+fn reputation_scan_config(config: &Config) -> Cow<'_, Config> {
+    if !config.heuristics.attacker_reputation {
+        return Cow::Borrowed(config);
+    }
+    return Cow::Owned(Config {
+        heuristics: HeuristicsConfig {
+            min_confidence: 0.0,
+            ..config.heuristics.clone()
+        },
+        ..config.clone()
+    });
+}
+
+/// `find_sandwiches_in_block` without severities, for callers that count
+/// recurrence over more than one block.
+fn scan_block_traced(
+    transactions: &[SwapTransaction],
+    config: &Config,
+) -> Result<Vec<SandwichAttackByHeuristics>, DetectorError> {
+    return telemetry::traced_result(
+        telemetry::HEURISTICS_BLOCK,
+        &[("transactions", transactions.len() as i64)],
+        || scan_block(&config.whole_token_amounts(transactions), config),
+    );
+}
+
+fn scan_block(
+    transactions: &[SwapTransaction],
+    config: &Config,
+) -> Result<Vec<SandwichAttackByHeuristics>, DetectorError> {
     let mut attacks = Vec::new();
 
     if transactions.len() < 3 {
-        return Err("not enough transactions to have a sandwich".to_string());
+        return Err(DetectorError::NotEnoughTransactions(transactions.len()));
     }
 
+    let block_fees = BlockFees::new(transactions);
     for front_pos in 0..transactions.len() - 2 {
         let front_tx = &transactions[front_pos];
 
@@ -65,46 +330,93 @@ fn find_sandwiches_in_block(
                 continue;
             }
 
-            if !are_tokens_reversed(front_tx, back_tx) {
+            if !config.tokens.are_reversed(front_tx, back_tx) {
                 continue;
             }
 
-            for victim_pos in front_pos + 1..back_pos {
-                let victim_tx = &transactions[victim_pos];
-
-                if is_sandwich_pattern(front_tx, victim_tx, back_tx) {
-                    let confidence_flags = extract_sandwich_evidence(front_tx, victim_tx, back_tx);
-                    let confidence_score = calculate_sandwich_confidence(&confidence_flags);
-                    attacks.push(SandwichAttackByHeuristics {
-                        front_run_tx: front_tx.clone(),
-                        victim_tx: victim_tx.clone(),
-                        back_run_tx: back_tx.clone(),
-                        confidence_score,
-                        confidence_flags,
-                    });
+            if config.heuristics.require_same_pool && front_tx.pool_address != back_tx.pool_address
+            {
+                continue;
+            }
+
+            let victims: Vec<&SwapTransaction> = transactions[front_pos + 1..back_pos]
+                .iter()
+                .filter(|victim_tx| {
+                    is_sandwich_pattern_with(&config.tokens, front_tx, victim_tx, back_tx)
+                })
+                .collect();
+            if let Some(max_victims) = config.heuristics.max_victims {
+                if victims.len() > max_victims {
+                    continue;
+                }
+            }
+
+            for victim_tx in &victims {
+                let mut confidence_flags =
+                    extract_sandwich_evidence(front_tx, victim_tx, back_tx, config);
+                confidence_flags.front_gas_zscore = block_fees.zscore(front_tx);
+                let confidence_score =
+                    score_candidate(front_tx, victim_tx, back_tx, &confidence_flags, config);
+                if confidence_score < config.heuristics.min_confidence {
+                    continue;
                 }
+                attacks.push(SandwichAttackByHeuristics {
+                    chain_id: victim_tx.chain_id,
+                    front_run_tx: front_tx.clone(),
+                    victim_tx: (*victim_tx).clone(),
+                    back_run_tx: back_tx.clone(),
+                    confidence_score,
+                    confidence_flags,
+                    classification: classify_sandwich(front_tx, victim_tx, back_tx, victims.len()),
+                    severity: Severity::default(),
+                });
             }
         }
     }
 
+    if !config.keep_raw_candidates {
+        attacks = dedup_overlapping(attacks);
+    }
+
     Ok(attacks)
 }
 
+/// Score a candidate with the configured scorer, or the additive weights.
+pub(crate) fn score_candidate(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+    flags: &ConfidenceFlags,
+    config: &Config,
+) -> f32 {
+    return match &config.heuristics.scorer {
+        Some(scorer) => scorer.score(front, victim, back, flags),
+        None => additive_confidence(flags, &config.heuristics.weights),
+    };
+}
+
 /// Extract all evidence/signals from a potential sandwich attack.
 fn extract_sandwich_evidence(
     front: &SwapTransaction,
     victim: &SwapTransaction,
     back: &SwapTransaction,
+    config: &Config,
 ) -> ConfidenceFlags {
-    let higher_front_gas_price = front.gas_price > victim.gas_price;
-    let lower_back_gas_price = back.gas_price < victim.gas_price;
+    let [front_fee, victim_fee, back_fee] = ordering_fees(front, victim, back);
+    let higher_front_gas_price = front_fee > victim_fee;
+    let lower_back_gas_price = back_fee < victim_fee;
     let front_is_contract = front.is_contract_caller;
     let back_is_contract = back.is_contract_caller;
-    let total_profit_usd =
-        back.usd_value_out - front.usd_value_in - front.gas_cost_usd - back.gas_cost_usd;
+    let builder_payments_usd = builder_payment_usd(front, &config.heuristics)
+        + builder_payment_usd(back, &config.heuristics);
+    let total_profit_usd = back.usd_value_out
+        - front.usd_value_in
+        - front.gas_cost_usd
+        - back.gas_cost_usd
+        - builder_payments_usd;
     let is_profitable = total_profit_usd > 0.0;
-    let is_proportional = is_proportional_sandwich(front, victim, back);
-    let price_impact_rate = calculate_victim_price_impact(front, victim);
+    let is_proportional = is_proportional_sandwich(front, victim, back, &config.heuristics);
+    let price_impact_rate = calculate_victim_price_impact(front, victim, &config.tokens);
 
     ConfidenceFlags {
         higher_front_gas_price,
@@ -114,63 +426,244 @@ fn extract_sandwich_evidence(
         is_profitable,
         is_proportional,
         price_impact_rate,
+        price_impact_depth_scale: pool_depth_scale(victim, &config.heuristics),
         total_profit_usd,
+        is_known_bot: config.known_actors.is_known_bot(&front.from_address),
+        likely_private_bundle: is_likely_private_bundle(front, victim, back, &config.heuristics),
+        uses_flashloan: uses_flashloan(front, &config.heuristics),
+        attacker_repeat_offender: 0.0,
+        same_entity: config
+            .entities
+            .same_entity(&front.from_address, &victim.from_address),
+        swap_size_factor: swap_size_factor(front, victim, &config.heuristics),
+        paid_builder: builder_payments_usd > 0.0,
+        profitable_only_before_bribe: profitable_only_before_bribe(
+            total_profit_usd,
+            builder_payments_usd,
+        ),
+        front_gas_zscore: 0.0,
+        bot_activity_factor: match &config.heuristics.activity {
+            Some(activity) => match activity.get(&front.from_address.to_lowercase()) {
+                Some(activity) => activity.bot_factor(config.heuristics.bot_txs_per_day),
+                None => 0.0,
+            },
+            None => 0.0,
+        },
+        custom_flags: Vec::new(),
     }
 }
 
-/// Score the sandwich evidence given the confidence flags.
-///
-/// TODO: This detection "algorithm" is very rudimentary to say the least.
-/// We can add things like a flashloan detection, known MEV bot addresses,
-/// priority fee analysis, figure out private mempools,
-/// and more sophisticated confidence scoring weights (maybe accounting
-/// for probability of false positives of each flag?).
-fn calculate_sandwich_confidence(evidence: &ConfidenceFlags) -> f32 {
-    let mut confidence = 0.3;
+/// How much the victim's price impact says about an attack given the depth
+/// of its pool: 5% in a deep pool is damning, in a micro-cap pool it happens
+/// organically. From 0.5 at `shallow_pool_usd` to 1.5 at `deep_pool_usd`,
+/// log-linear in between, 1 without the pool's reserves or with bounds that
+/// aren't a positive, increasing range.
+fn pool_depth_scale(victim: &SwapTransaction, config: &HeuristicsConfig) -> f32 {
+    if !(config.shallow_pool_usd > 0.0 && config.deep_pool_usd > config.shallow_pool_usd) {
+        return 1.0;
+    }
 
-    if evidence.higher_front_gas_price {
-        confidence += 0.2;
+    let pool = match &config.pools {
+        Some(pools) => match pools.get(&victim.pool_address) {
+            Some(pool) => pool,
+            None => return 1.0,
+        },
+        None => return 1.0,
+    };
+    if victim.amount_in <= 0.0 {
+        return 1.0;
+    }
+    let input_reserve = match victim.token_out == pool.token_a_address {
+        true => pool.token_b_reserve,
+        false => pool.token_a_reserve,
+    };
+    let depth_usd = 2.0 * input_reserve * victim.usd_value_in / victim.amount_in;
+
+    let (shallow, deep) = (config.shallow_pool_usd.ln(), config.deep_pool_usd.ln());
+    let position = ((depth_usd.ln() - shallow) / (deep - shallow)).clamp(0.0, 1.0);
+    return (0.5 + position) as f32;
+}
+
+/// Distribution of the ordering fees of a block, see `ordering_fees`.
+struct BlockFees {
+    use_priority_fees: bool,
+    mean: f64,
+    std_dev: f64,
+}
+
+impl BlockFees {
+    fn new(block: &[SwapTransaction]) -> Self {
+        let use_priority_fees = block.iter().all(|tx| tx.effective_priority_fee().is_some());
+        let mut fees = Self {
+            use_priority_fees,
+            mean: 0.0,
+            std_dev: 0.0,
+        };
+        if block.is_empty() {
+            return fees;
+        }
+        let count = block.len() as f64;
+        fees.mean = block.iter().map(|tx| fees.fee(tx)).sum::<f64>() / count;
+        let variance = block
+            .iter()
+            .map(|tx| (fees.fee(tx) - fees.mean).powi(2))
+            .sum::<f64>()
+            / count;
+        fees.std_dev = variance.sqrt();
+        return fees;
+    }
+
+    fn fee(&self, tx: &SwapTransaction) -> f64 {
+        return match (self.use_priority_fees, tx.effective_priority_fee()) {
+            (true, Some(fee)) => fee as f64,
+            _ => tx.gas_price as f64,
+        };
     }
 
-    if evidence.lower_back_gas_price {
-        confidence += 0.1;
+    /// 0 when every swap of the block paid the same.
+    fn zscore(&self, tx: &SwapTransaction) -> f32 {
+        if self.std_dev == 0.0 {
+            return 0.0;
+        }
+        return ((self.fee(tx) - self.mean) / self.std_dev) as f32;
     }
+}
+
+/// USD `tx` paid the builder directly, 0 when unknown.
+pub(crate) fn builder_payment_usd(tx: &SwapTransaction, config: &HeuristicsConfig) -> f64 {
+    return match &config.builder_payments {
+        Some(payments) => payments.get(&tx.tx_hash).copied().unwrap_or(0.0),
+        None => 0.0,
+    };
+}
 
-    if evidence.front_is_contract {
-        confidence += 0.1;
+/// A profit, after gas and builder payments, that was only there before
+/// the payments.
+pub(crate) fn profitable_only_before_bribe(profit_usd: f64, builder_payments_usd: f64) -> bool {
+    return profit_usd <= 0.0 && profit_usd + builder_payments_usd > 0.0;
+}
+
+/// Effective priority fees of the three legs when all of them are known,
+/// otherwise their gas prices.
+pub(crate) fn ordering_fees(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+) -> [u64; 3] {
+    return match (
+        front.effective_priority_fee(),
+        victim.effective_priority_fee(),
+        back.effective_priority_fee(),
+    ) {
+        (Some(front_fee), Some(victim_fee), Some(back_fee)) => [front_fee, victim_fee, back_fee],
+        _ => [front.gas_price, victim.gas_price, back.gas_price],
+    };
+}
+
+/// Infer that the sandwich was submitted as a private bundle: the front-run
+/// opens the block (position 0 or 1) tipping next to nothing, since
+/// bundle ordering is bought from the builder rather than by priority fee,
+/// the back-run directly follows the victim, and no leg was seen in the
+/// public mempool (when sightings are known).
+///
+/// TODO: Builders also place bundles mid-block, we could check the
+/// builder's payment transaction at the end of the block instead.
+fn is_likely_private_bundle(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+    config: &HeuristicsConfig,
+) -> bool {
+    if front.tx_position_in_block > 1 {
+        return false;
     }
 
-    if evidence.back_is_contract {
-        confidence += 0.1;
+    if back.tx_position_in_block != victim.tx_position_in_block + 1 {
+        return false;
     }
 
-    if evidence.is_profitable {
-        confidence += 0.25;
+    match front.effective_priority_fee() {
+        Some(fee) if fee <= config.max_bundle_priority_fee => {}
+        _ => return false,
     }
 
-    if evidence.is_proportional {
-        confidence += 0.15;
+    if let Some(sightings) = &config.mempool_sightings {
+        if sightings.contains(&front.tx_hash) || sightings.contains(&back.tx_hash) {
+            return false;
+        }
     }
 
-    if evidence.price_impact_rate > 0.0 {
-        confidence += match evidence.price_impact_rate {
-            p if p < 0.25 => p,
-            _ => 0.25,
-        };
+    return true;
+}
+
+/// Whether the front-run was funded by a flashloan, either known from its
+/// events or inferred from spending more than the sender's prior balance.
+/// False without either kind of data.
+fn uses_flashloan(front: &SwapTransaction, config: &HeuristicsConfig) -> bool {
+    if let Some(flashloan_txs) = &config.flashloan_txs {
+        if flashloan_txs.contains(&front.tx_hash) {
+            return true;
+        }
     }
 
-    if confidence > 1.0 {
-        1.0
-    } else {
-        confidence
+    if let Some(balances) = &config.prior_balances {
+        let key = (
+            front.from_address.to_lowercase(),
+            front.token_in.to_lowercase(),
+        );
+        if let Some(balance) = balances.get(&key) {
+            return front.amount_in > *balance;
+        }
+    }
+
+    return false;
+}
+
+/// How plausible the front-run's size is for a sandwich, from -1 to 1,
+/// given the pool's reserves (`HeuristicsConfig::pools`), 0 without them.
+/// Dust and front-runs spending most of the pool are -1, a front-run near
+/// the optimal size scores up to 1, linearly less up to 4x off in either
+/// direction.
+fn swap_size_factor(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    config: &HeuristicsConfig,
+) -> f32 {
+    let pool = match &config.pools {
+        Some(pools) => match pools.get(&front.pool_address) {
+            Some(pool) => pool,
+            None => return 0.0,
+        },
+        None => return 0.0,
+    };
+    if front.usd_value_in < config.min_attack_usd {
+        return -1.0;
+    }
+    let input_reserve = match front.token_out == pool.token_a_address {
+        true => pool.token_b_reserve,
+        false => pool.token_a_reserve,
+    };
+    if front.amount_in > config.max_pool_share * input_reserve {
+        return -1.0;
     }
+
+    // Cross-pool sandwiches move the victim's price only indirectly
+    if victim.pool_address != front.pool_address {
+        return 0.0;
+    }
+    // The attacker's profit grows with the front-run up to the victim's limit
+    let optimal =
+        largest_tolerated_front_run(pool, front, victim, config.victim_slippage_tolerance);
+    if optimal <= 0.0 {
+        return 0.0;
+    }
+    let distance = (front.amount_in / optimal).ln().abs();
+    return (1.0 - distance / 4f64.ln()).max(0.0) as f32;
 }
 
 /// Check if sandwich trades are proportionally sized to the victim trade.
 /// Professional MEV bots typically size their trades as 10-30% of victim trade.
-///
-/// TODO: We could improve this by calculating real price impact of various
-/// sizes and expecting the attacker to try and maximize profit.
+/// See `swap_size_factor` for sizing against the pool's reserves.
 ///
 /// TODO: We could improve this by comparing against a collective of
 /// victims.
@@ -178,15 +671,18 @@ fn is_proportional_sandwich(
     front: &SwapTransaction,
     victim: &SwapTransaction,
     back: &SwapTransaction,
+    config: &HeuristicsConfig,
 ) -> bool {
     let front_ratio = front.usd_value_in / victim.usd_value_in;
     let back_ratio = back.usd_value_in / victim.usd_value_in;
 
-    // Front-run should be 5-50% of victim trade
-    let front_proportional = front_ratio >= 0.05 && front_ratio <= 0.5;
+    // Front-run should be 5-50% of victim trade by default
+    let front_proportional =
+        (config.min_front_ratio..=config.max_front_ratio).contains(&front_ratio);
 
-    // Back-run should be similar size to front-run (within 2x range)
-    let back_proportional = back_ratio >= front_ratio * 0.5 && back_ratio <= front_ratio * 2.0;
+    // Back-run should be similar size to front-run (within 2x range by default)
+    let back_proportional = back_ratio >= front_ratio / config.max_back_to_front_ratio
+        && back_ratio <= front_ratio * config.max_back_to_front_ratio;
 
     front_proportional && back_proportional
 }
@@ -200,10 +696,14 @@ fn is_proportional_sandwich(
 ///
 /// TODO: Attributing the price impact to the would be front-runner could be a mistake
 /// if other wallets also buy the same token in between the front-runner and victim.
-fn calculate_victim_price_impact(front: &SwapTransaction, victim: &SwapTransaction) -> f32 {
+pub(crate) fn calculate_victim_price_impact(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    tokens: &TokenEquivalence,
+) -> f32 {
     // Only calculate if they're trading in the same direction (same tokens)
-    if !are_tokens_equivalent(&front.token_in, &victim.token_in)
-        || !are_tokens_equivalent(&front.token_out, &victim.token_out)
+    if !tokens.are_equivalent(&front.token_in, &victim.token_in)
+        || !tokens.are_equivalent(&front.token_out, &victim.token_out)
     {
         return 0.0;
     }
@@ -221,26 +721,14 @@ fn calculate_victim_price_impact(front: &SwapTransaction, victim: &SwapTransacti
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
-
-    fn load_sample_transactions() -> Vec<SwapTransaction> {
-        let csv_content =
-            fs::read_to_string("data/sandwiches.csv").expect("Failed to read sample CSV file");
-
-        let mut reader = csv::Reader::from_reader(csv_content.as_bytes());
-        let mut transactions = Vec::new();
-
-        for result in reader.deserialize() {
-            let transaction: SwapTransaction = result.expect("Failed to parse CSV row");
-            transactions.push(transaction);
-        }
-
-        transactions
-    }
+    use crate::ingest::csv::sample_transactions;
+    use crate::sandwich::same_block_sim::Pool;
+    use crate::test_support::{sample_attack, sample_pool};
+    use std::collections::HashMap;
 
     #[test]
     fn test_find_same_block_sandwiches_with_sample_data() {
-        let transactions = load_sample_transactions();
+        let transactions = sample_transactions();
         let attacks = find_same_block_sandwiches(&transactions);
 
         // Should find exactly 6 sandwich attacks from the sample data
@@ -418,25 +906,313 @@ mod tests {
         }
 
         println!("Found {} sandwich attacks:", attacks.len());
-        for (i, attack) in attacks.iter().enumerate() {
-            println!(
-                "Attack {}: Block {} - Confidence {:.3}",
-                i + 1,
-                attack.front_run_tx.block_number,
+        for attack in &attacks {
+            println!("{}", attack.summary());
+        }
+    }
+
+    #[test]
+    fn test_detect_stream_matches_batch_detection() {
+        let transactions = sample_transactions().into_iter();
+
+        let mut streamed: Vec<String> = detect_stream(transactions)
+            .map(|attack| attack.attack_id())
+            .collect();
+        let mut batch: Vec<String> = find_same_block_sandwiches(&sample_transactions())
+            .iter()
+            .map(|attack| attack.attack_id())
+            .collect();
+
+        streamed.sort();
+        batch.sort();
+        assert_eq!(streamed, batch);
+    }
+
+    #[test]
+    fn test_same_block_number_on_different_chains_does_not_collide() {
+        let mainnet = sample_transactions();
+        let mainnet_attacks = find_same_block_sandwiches(&mainnet).len();
+
+        // The same swaps replayed on another chain, with distinct hashes
+        let mut mixed = mainnet.clone();
+        mixed.extend(mainnet.iter().map(|tx| SwapTransaction {
+            chain_id: 137,
+            tx_hash: format!("{}-polygon", tx.tx_hash),
+            ..tx.clone()
+        }));
+
+        let attacks = find_same_block_sandwiches(&mixed);
+        assert_eq!(attacks.len(), mainnet_attacks * 2);
+        assert_eq!(
+            attacks.iter().filter(|a| a.chain_id == 137).count(),
+            mainnet_attacks
+        );
+        for attack in &attacks {
+            assert_eq!(attack.front_run_tx.chain_id, attack.chain_id);
+            assert_eq!(attack.back_run_tx.chain_id, attack.chain_id);
+        }
+    }
+
+    #[test]
+    fn test_display_and_summary() {
+        let attacks = find_same_block_sandwiches(&sample_transactions());
+        let attack = attacks
+            .iter()
+            .find(|attack| attack.victim_tx.tx_hash == "0xvictim002")
+            .unwrap();
+
+        assert_eq!(
+            attack.to_string(),
+            format!(
+                "block 12361 | attacker 0xbot123 | victim 0xinnocent | pool 0xpool4 | profit $96.00 | confidence {:.3}",
                 attack.confidence_score
+            )
+        );
+        let summary = attack.summary();
+        assert!(summary.starts_with(&attack.to_string()));
+        assert!(summary.contains("\n  victim: 0xvictim002 (ETH -> NEWTOKEN)\n"));
+        assert!(summary.contains("flags:  profitable=true"));
+    }
+
+    #[test]
+    fn test_gas_flags_use_priority_fees_when_known() {
+        let attack = sample_attack(12360);
+
+        // Fee caps as gas prices: the front-run bids the highest cap but
+        // tips less than the victim
+        let with_fees =
+            |tx: &SwapTransaction, max_fee: u64, max_priority_fee: u64| SwapTransaction {
+                gas_price: max_fee,
+                max_fee_per_gas: Some(max_fee),
+                max_priority_fee_per_gas: Some(max_priority_fee),
+                base_fee_per_gas: Some(100),
+                ..tx.clone()
+            };
+        let front = with_fees(&attack.front_run_tx, 500, 2);
+        let victim = with_fees(&attack.victim_tx, 200, 5);
+        let back = with_fees(&attack.back_run_tx, 400, 1);
+        assert_eq!(victim.effective_priority_fee(), Some(5));
+
+        let flags = extract_sandwich_evidence(&front, &victim, &back, &Config::default());
+        assert!(!flags.higher_front_gas_price);
+        assert!(flags.lower_back_gas_price);
+
+        // Legacy records keep comparing gas prices
+        let legacy = |tx: &SwapTransaction| SwapTransaction {
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
+            ..tx.clone()
+        };
+        let flags = extract_sandwich_evidence(
+            &legacy(&front),
+            &legacy(&victim),
+            &legacy(&back),
+            &Config::default(),
+        );
+        assert!(flags.higher_front_gas_price);
+        assert!(!flags.lower_back_gas_price);
+    }
+
+    #[test]
+    fn test_private_bundle_inference() {
+        let attack = sample_attack(12360);
+        let at = |tx: &SwapTransaction, position: u32, max_priority_fee: u64| SwapTransaction {
+            tx_position_in_block: position,
+            max_fee_per_gas: Some(tx.gas_price),
+            max_priority_fee_per_gas: Some(max_priority_fee),
+            base_fee_per_gas: Some(0),
+            ..tx.clone()
+        };
+        let front = at(&attack.front_run_tx, 0, 0);
+        let victim = at(&attack.victim_tx, 1, 3);
+        let back = at(&attack.back_run_tx, 2, 0);
+        let mut config = Config::default();
+        assert!(extract_sandwich_evidence(&front, &victim, &back, &config).likely_private_bundle);
+
+        // Tipping to get ordered, later in the block, or without fee data
+        let tipping = at(&front, 0, 5);
+        assert!(
+            !extract_sandwich_evidence(&tipping, &victim, &back, &config).likely_private_bundle
+        );
+        let late_back = at(&back, 3, 0);
+        assert!(
+            !extract_sandwich_evidence(&front, &victim, &late_back, &config).likely_private_bundle
+        );
+        let legacy = SwapTransaction {
+            max_priority_fee_per_gas: None,
+            ..front.clone()
+        };
+        assert!(!extract_sandwich_evidence(&legacy, &victim, &back, &config).likely_private_bundle);
+
+        // Seen in the public mempool
+        config.heuristics.mempool_sightings = Some([front.tx_hash.clone()].into_iter().collect());
+        assert!(!extract_sandwich_evidence(&front, &victim, &back, &config).likely_private_bundle);
+    }
+
+    #[test]
+    fn test_flashloan_from_events_or_balances() {
+        let attack = sample_attack(12360);
+        let (front, victim, back) = (&attack.front_run_tx, &attack.victim_tx, &attack.back_run_tx);
+        let mut config = Config::default();
+        assert!(!extract_sandwich_evidence(front, victim, back, &config).uses_flashloan);
+
+        config.heuristics.flashloan_txs = Some([front.tx_hash.clone()].into_iter().collect());
+        let flags = extract_sandwich_evidence(front, victim, back, &config);
+        assert!(flags.uses_flashloan);
+        assert!(
+            additive_confidence(&flags, &config.heuristics.weights)
+                > additive_confidence(&attack.confidence_flags, &config.heuristics.weights)
+        );
+
+        config.heuristics.flashloan_txs = None;
+        let key = (
+            front.from_address.to_lowercase(),
+            front.token_in.to_lowercase(),
+        );
+        let balances = |balance: f64| Some([(key.clone(), balance)].into_iter().collect());
+        config.heuristics.prior_balances = balances(front.amount_in / 2.0);
+        assert!(extract_sandwich_evidence(front, victim, back, &config).uses_flashloan);
+        config.heuristics.prior_balances = balances(front.amount_in * 2.0);
+        assert!(!extract_sandwich_evidence(front, victim, back, &config).uses_flashloan);
+    }
+
+    #[test]
+    fn test_swap_size_factor_prefers_optimal_front_runs() {
+        let attack = sample_attack(12360);
+        let (front, victim) = (&attack.front_run_tx, &attack.victim_tx);
+        let pool = sample_pool();
+        let sized = |amount_in: f64, usd_value_in: f64| SwapTransaction {
+            amount_in,
+            usd_value_in,
+            ..front.clone()
+        };
+        // Without reserves not even dust is judged
+        let mut config = HeuristicsConfig::default();
+        assert_eq!(swap_size_factor(front, victim, &config), 0.0);
+        assert_eq!(swap_size_factor(&sized(1.0, 1.0), victim, &config), 0.0);
+
+        config.pools = Some(HashMap::from([("0xpool1".to_string(), pool.clone())]));
+        let optimal =
+            largest_tolerated_front_run(&pool, front, victim, config.victim_slippage_tolerance);
+        let at_optimum = swap_size_factor(&sized(optimal, optimal), victim, &config);
+        assert!(at_optimum > 0.99);
+        let oversized = swap_size_factor(&sized(optimal * 2.0, optimal * 2.0), victim, &config);
+        assert!(oversized > 0.0 && oversized < at_optimum);
+        assert_eq!(
+            swap_size_factor(&sized(optimal * 8.0, optimal * 8.0), victim, &config),
+            0.0
+        );
+
+        assert_eq!(swap_size_factor(&sized(1.0, 1.0), victim, &config), -1.0);
+        assert_eq!(
+            swap_size_factor(&sized(600000.0, 600000.0), victim, &config),
+            -1.0
+        );
+    }
+
+    #[test]
+    fn test_builder_payments_flag_and_reduce_profit() {
+        let attack = sample_attack(12360);
+        let (front, victim, back) = (&attack.front_run_tx, &attack.victim_tx, &attack.back_run_tx);
+        let mut config = Config::default();
+        assert!(!attack.confidence_flags.paid_builder);
+
+        config.heuristics.builder_payments = Some(HashMap::from([(back.tx_hash.clone(), 12.5)]));
+        let flags = extract_sandwich_evidence(front, victim, back, &config);
+        assert!(flags.paid_builder);
+        assert!(
+            (flags.total_profit_usd - (attack.confidence_flags.total_profit_usd - 12.5)).abs()
+                < 1e-9
+        );
+        // Already losing before the payment
+        assert!(!flags.profitable_only_before_bribe);
+
+        // Block 12361 makes about $96 after gas, a $100 bid leaves nothing
+        let attack = sample_attack(12361);
+        let (front, victim, back) = (&attack.front_run_tx, &attack.victim_tx, &attack.back_run_tx);
+        config.heuristics.builder_payments = Some(HashMap::from([(front.tx_hash.clone(), 100.0)]));
+        let flags = extract_sandwich_evidence(front, victim, back, &config);
+        assert!(!flags.is_profitable);
+        assert!(flags.profitable_only_before_bribe);
+    }
+
+    #[test]
+    fn test_front_gas_zscore_against_block() {
+        let transactions = sample_transactions();
+        // Off by default, the scores don't move without a weight
+        let mut config = Config::default();
+        config.heuristics.weights.front_gas_zscore = 0.1;
+        let attack = find_same_block_sandwiches_with_config(&transactions, &config)
+            .attacks
+            .into_iter()
+            .find(|attack| attack.victim_tx.block_number == 12360)
+            .expect("Should find attack in block 12360");
+        assert!(attack.confidence_flags.higher_front_gas_price);
+        assert!(attack.confidence_flags.front_gas_zscore > 0.0);
+
+        // Everyone else in the block outbid the front-run
+        let mut busy_block = transactions.clone();
+        busy_block.extend((10..13).map(|position| SwapTransaction {
+            tx_hash: format!("0xbusy{}", position),
+            tx_position_in_block: position,
+            from_address: format!("0xtrader{}", position),
+            gas_price: 400,
+            ..attack.victim_tx.clone()
+        }));
+        let busy = find_same_block_sandwiches_with_config(&busy_block, &config)
+            .attacks
+            .into_iter()
+            .find(|other| other.attack_id() == attack.attack_id())
+            .unwrap();
+        assert!(busy.confidence_flags.higher_front_gas_price);
+        assert!(busy.confidence_flags.front_gas_zscore < 0.0);
+        assert!(busy.confidence_score < attack.confidence_score);
+        let unweighted = find_same_block_sandwiches(&busy_block)
+            .into_iter()
+            .find(|other| other.attack_id() == attack.attack_id())
+            .unwrap();
+        let baseline = find_same_block_sandwiches(&transactions)
+            .into_iter()
+            .find(|other| other.attack_id() == attack.attack_id())
+            .unwrap();
+        assert_eq!(unweighted.confidence_score, baseline.confidence_score);
+    }
+
+    #[test]
+    fn test_price_impact_weighed_by_pool_depth() {
+        let attack = sample_attack(12360);
+        assert!(attack.confidence_flags.price_impact_rate > 0.0);
+        let (front, victim, back) = (&attack.front_run_tx, &attack.victim_tx, &attack.back_run_tx);
+        assert_eq!(attack.confidence_flags.price_impact_depth_scale, 1.0);
+
+        let mut config = Config::default();
+        config.heuristics.weights.base = 0.0;
+        let scored = |usdc_reserve: f64, config: &mut Config| {
+            let pool = Pool::new(
+                usdc_reserve,
+                usdc_reserve * 50000.0,
+                "USDC".to_string(),
+                "SHIB".to_string(),
             );
-            println!(
-                "  Profit: ${:.2} USD",
-                attack.confidence_flags.total_profit_usd
-            );
-            println!(
-                "  Flags: profitable={}, contracts={}/{}, gas_priority={}/{}",
-                attack.confidence_flags.is_profitable,
-                attack.confidence_flags.front_is_contract,
-                attack.confidence_flags.back_is_contract,
-                attack.confidence_flags.higher_front_gas_price,
-                attack.confidence_flags.lower_back_gas_price
-            );
+            config.heuristics.pools = Some(HashMap::from([("0xpool1".to_string(), pool)]));
+            let flags = extract_sandwich_evidence(front, victim, back, config);
+            let score = additive_confidence(&flags, &config.heuristics.weights);
+            return (flags.price_impact_depth_scale, score);
+        };
+        let (micro_scale, micro_score) = scored(10_000.0, &mut config);
+        let (mid_scale, _) = scored(1_000_000.0, &mut config);
+        let (deep_scale, deep_score) = scored(100_000_000.0, &mut config);
+        assert_eq!(micro_scale, 0.5);
+        assert!(mid_scale > 0.5 && mid_scale < 1.5);
+        assert_eq!(deep_scale, 1.5);
+        assert!(deep_score > micro_score);
+
+        // Degenerate bounds leave the impact unweighed instead of NaN
+        for (shallow, deep) in [(1_000_000.0, 1_000_000.0), (0.0, 1_000_000.0)] {
+            config.heuristics.shallow_pool_usd = shallow;
+            config.heuristics.deep_pool_usd = deep;
+            assert_eq!(scored(1_000_000.0, &mut config).0, 1.0);
         }
     }
 }
@@ -1,18 +1,51 @@
-use crate::sandwich::transactions::SwapTransaction;
-use crate::sandwich::utils::is_sandwich_pattern;
+use crate::config::Config;
+use crate::sandwich::cancel::CancellationToken;
+use crate::sandwich::classification::{classify_sandwich, Classification};
+use crate::sandwich::dedup::dedup_overlapping;
+use crate::sandwich::error::{DetectionOutcome, DetectorError};
+use crate::sandwich::extraction::extraction_efficiency;
+use crate::sandwich::pool_model::{Arithmetic, PoolModel};
+use crate::sandwich::progress::{Progress, ProgressTracker};
+use crate::sandwich::same_block_heuristics::{builder_payment_usd, profitable_only_before_bribe};
+use crate::sandwich::severity::{assign_simulated_severity, Severity};
+use crate::sandwich::tokens::{TokenDecimals, TokenTaxes};
+use crate::sandwich::transactions::{
+    raw_amount, stream_transactions_by_block, BlockId, SwapTransaction,
+};
+use crate::sandwich::utils::{format_legs, is_sandwich_pattern_with, sandwich_attack_id};
+use crate::telemetry;
+use ethnum::{AsU256, U256};
 use std::collections::HashMap;
+use std::fmt;
 
 /// Represents the state of an AMM liquidity pool at a specific point
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Pool {
     pub token_a_reserve: f64,
     pub token_b_reserve: f64,
     pub token_a_address: String,
     pub token_b_address: String,
+    /// LP fee taken from every swap's input, in basis points. Default `30`,
+    /// Uniswap V2's 0.3%.
+    #[serde(default = "default_fee_bps")]
+    pub fee_bps: u32,
+    /// Replaced by `SimulationConfig::arithmetic` when detecting.
+    #[serde(default)]
+    pub arithmetic: Arithmetic,
+    /// The reserves in raw units, exact past the 2^53 of f64, for
+    /// `Arithmetic::FixedPoint`. Ignored once the f64 reserve is changed.
+    #[serde(default, with = "raw_amount")]
+    pub raw_token_a_reserve: Option<U256>,
+    #[serde(default, with = "raw_amount")]
+    pub raw_token_b_reserve: Option<U256>,
+}
+
+fn default_fee_bps() -> u32 {
+    return 30;
 }
 
 /// Result of simulating a single swap transaction
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SwapSimulationResult {
     pub tokens_received: f64,
     pub price_per_token: f64,
@@ -20,13 +53,174 @@ pub struct SwapSimulationResult {
     pub new_pool_state: Pool,
 }
 
+/// One swap replayed by `SimulationDiagnostic`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ReplayStep {
+    pub tx_hash: String,
+    pub tx_position_in_block: u32,
+    pub pool_address: String,
+    pub simulated_amount_out: f64,
+    pub actual_amount_out: f64,
+    /// Signed, positive when the simulation gives more than happened.
+    pub drift_pct: f64,
+    /// Sum of the absolute drifts up to this step.
+    pub cumulative_drift_pct: f64,
+}
+
+/// How a failed reality check came about, carried by
+/// `DetectorError::SimulationDiverged` to debug pool data.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SimulationDiagnostic {
+    /// Every swap on the simulated pools up to the victim's, in block order.
+    pub steps: Vec<ReplayStep>,
+    /// The first swap off by more than the tolerance. When it comes before
+    /// the victim, the pool state was already wrong there, e.g. reserves of
+    /// another block or a swap missing from the input.
+    pub first_divergent_tx: Option<String>,
+}
+
+/// Which swaps the victim's counterfactual takes out of the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CounterfactualRemoval {
+    FrontRun,
+    /// The front-run and the back-run.
+    Legs,
+    /// Every swap of the block sent by the front- or back-run's sender,
+    /// including the legs of sandwiches on other victims.
+    #[default]
+    Bundle,
+}
+
+impl CounterfactualRemoval {
+    /// Whether `tx` is taken out for the sandwich of `front` and `back`.
+    pub fn removes(
+        &self,
+        tx: &SwapTransaction,
+        front: &SwapTransaction,
+        back: &SwapTransaction,
+    ) -> bool {
+        let is_leg = |leg: &SwapTransaction| tx.tx_position_in_block == leg.tx_position_in_block;
+        return match self {
+            CounterfactualRemoval::FrontRun => is_leg(front),
+            CounterfactualRemoval::Legs => is_leg(front) || is_leg(back),
+            CounterfactualRemoval::Bundle => {
+                is_leg(front)
+                    || is_leg(back)
+                    || tx.from_address == front.from_address
+                    || tx.from_address == back.from_address
+            }
+        };
+    }
+}
+
+/// What a victim would have received had some of the block's swaps not
+/// been there, next to what it actually received.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Counterfactual {
+    /// Output of the victim's route, in its last `token_out`.
+    pub actual_amount_out: f64,
+    pub counterfactual_amount_out: f64,
+    /// `token_out` received per `token_in` paid, actual and counterfactual.
+    pub actual_price: f64,
+    pub counterfactual_price: f64,
+    /// How far the actual output is from the counterfactual one, the
+    /// `victim_loss_percentage` of a sandwich.
+    pub loss_percentage: f64,
+}
+
+impl Counterfactual {
+    /// What the swaps taken out cost the victim, in its `token_out`.
+    pub fn amount_lost(&self) -> f64 {
+        return self.counterfactual_amount_out - self.actual_amount_out;
+    }
+}
+
 /// Represents a confirmed sandwich attack found through simulation
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct SandwichAttackBySimulation {
+    pub chain_id: u64,
     pub front_run_tx: SwapTransaction,
     pub victim_tx: SwapTransaction,
     pub back_run_tx: SwapTransaction,
     pub victim_loss_percentage: f64,
+    /// Lowest and highest `victim_loss_percentage` within the simulation's
+    /// error: every pool's fee off by `SimulationConfig::fee_uncertainty_bps`
+    /// either way, and the counterfactual off by as much as the replayed
+    /// victim output drifted from the actual one.
+    #[serde(default)]
+    pub victim_loss_percentage_low: f64,
+    #[serde(default)]
+    pub victim_loss_percentage_high: f64,
+    #[serde(default)]
+    pub classification: Classification,
+    /// Simulated back-run output less the front-run input, in the front-run's
+    /// input token. Tokens bought and not sold back aren't counted.
+    #[serde(default)]
+    pub attacker_profit_token: f64,
+    /// `attacker_profit_token` at the front-run's USD price, less both legs'
+    /// gas and any `HeuristicsConfig::builder_payments`, like the heuristic
+    /// `total_profit_usd`.
+    #[serde(default)]
+    pub attacker_profit_usd: f64,
+    /// What the front and back legs paid the builder directly, in USD.
+    #[serde(default)]
+    pub builder_payments_usd: f64,
+    /// `attacker_profit_usd` is only positive before the builder payments.
+    #[serde(default)]
+    pub profitable_only_before_bribe: bool,
+    /// Share of the optimal sandwich's profit the front-run's size got, see
+    /// `extraction_efficiency`.
+    #[serde(default)]
+    pub extraction_efficiency: f64,
+    /// The replayed victim output was off the actual one by more than
+    /// `SimulationConfig::reality_tolerance_pct`, so the numbers rest on a
+    /// pool state that may be wrong. Only with `report_unverified`.
+    #[serde(default)]
+    pub simulation_unverified: bool,
+    /// From the simulated victim loss, recurrence counted within the block.
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+impl SandwichAttackBySimulation {
+    pub fn attack_id(&self) -> String {
+        sandwich_attack_id(&self.front_run_tx, &self.victim_tx, &self.back_run_tx)
+    }
+
+    /// USD the victim lost, `victim_loss_percentage` of its input.
+    pub fn victim_loss_usd(&self) -> f64 {
+        return self.victim_tx.usd_value_in * self.victim_loss_percentage / 100.0;
+    }
+
+    /// The `Display` line followed by the three legs.
+    pub fn summary(&self) -> String {
+        return format!(
+            "{}\n{}",
+            self,
+            format_legs(&self.front_run_tx, &self.victim_tx, &self.back_run_tx).trim_end()
+        );
+    }
+}
+
+/// One compact line: block, attacker, victim, pool and the simulated victim loss.
+impl fmt::Display for SandwichAttackBySimulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block {} | attacker {} | victim {} | pool {} | victim loss {:.3}%{}",
+            self.victim_tx.block_number,
+            self.front_run_tx.from_address,
+            self.victim_tx.from_address,
+            self.victim_tx.pool_address,
+            self.victim_loss_percentage,
+            if self.simulation_unverified {
+                " (unverified)"
+            } else {
+                ""
+            }
+        )
+    }
 }
 
 impl Pool {
@@ -41,9 +235,55 @@ impl Pool {
             token_b_reserve,
             token_a_address,
             token_b_address,
+            fee_bps: default_fee_bps(),
+            arithmetic: Arithmetic::default(),
+            raw_token_a_reserve: None,
+            raw_token_b_reserve: None,
         }
     }
 
+    /// A pool from on-chain reserves, kept exact for `Arithmetic::FixedPoint`.
+    pub fn from_raw_reserves(
+        token_a_reserve: U256,
+        token_b_reserve: U256,
+        token_a_address: String,
+        token_b_address: String,
+    ) -> Self {
+        return Self {
+            raw_token_a_reserve: Some(token_a_reserve),
+            raw_token_b_reserve: Some(token_b_reserve),
+            ..Self::new(
+                token_a_reserve.as_f64(),
+                token_b_reserve.as_f64(),
+                token_a_address,
+                token_b_address,
+            )
+        };
+    }
+
+    /// E.g. `5` for a 0.05% pool, `100` for a 1% one.
+    pub fn with_fee_bps(mut self, fee_bps: u32) -> Self {
+        self.fee_bps = fee_bps;
+        return self;
+    }
+
+    pub fn with_arithmetic(mut self, arithmetic: Arithmetic) -> Self {
+        self.arithmetic = arithmetic;
+        return self;
+    }
+
+    /// This pool with raw reserves converted to whole tokens, to simulate
+    /// swaps normalized with `TokenDecimals::normalize_amounts`.
+    pub fn normalized(&self, decimals: &TokenDecimals) -> Self {
+        return Self {
+            token_a_reserve: decimals.to_units(&self.token_a_address, self.token_a_reserve),
+            token_b_reserve: decimals.to_units(&self.token_b_address, self.token_b_reserve),
+            raw_token_a_reserve: None,
+            raw_token_b_reserve: None,
+            ..self.clone()
+        };
+    }
+
     pub fn get_token_a_price(&self) -> f64 {
         self.token_b_reserve / self.token_a_reserve
     }
@@ -61,7 +301,7 @@ impl Pool {
     }
 
     pub fn simulate_swap(&self, swap: &SwapTransaction) -> SwapSimulationResult {
-        let is_buying_token_a = &swap.token_out == &self.token_a_address;
+        let is_buying_token_a = swap.token_out == self.token_a_address;
 
         let initial_price = if is_buying_token_a {
             self.get_token_a_price()
@@ -74,23 +314,61 @@ impl Pool {
         } else {
             (self.token_b_reserve, self.token_a_reserve)
         };
+        let (raw_output_reserve, raw_input_reserve) = if is_buying_token_a {
+            (self.raw_token_a_reserve, self.raw_token_b_reserve)
+        } else {
+            (self.raw_token_b_reserve, self.raw_token_a_reserve)
+        };
 
-        let tokens_received =
-            self.constant_product_formula(input_reserve, output_reserve, swap.amount_in);
+        let (amount_in, tokens_received, new_input_reserve, new_output_reserve, raw_reserves) =
+            match self.arithmetic {
+                Arithmetic::Float => {
+                    // The fee stays in the pool, only the rest of the input trades
+                    let amount_in_after_fee =
+                        swap.amount_in * (1.0 - f64::from(self.fee_bps) / 10_000.0);
+                    let tokens_received = self.constant_product_formula(
+                        input_reserve,
+                        output_reserve,
+                        amount_in_after_fee,
+                    );
+                    (
+                        swap.amount_in,
+                        tokens_received,
+                        input_reserve + swap.amount_in,
+                        output_reserve - tokens_received,
+                        None,
+                    )
+                }
+                Arithmetic::FixedPoint => {
+                    let amount_in = raw_or_rounded(swap.raw_amount_in, swap.amount_in);
+                    let input_reserve = raw_or_rounded(raw_input_reserve, input_reserve);
+                    let output_reserve = raw_or_rounded(raw_output_reserve, output_reserve);
+                    let tokens_received =
+                        get_amount_out(amount_in, input_reserve, output_reserve, self.fee_bps);
+                    let (new_input_reserve, new_output_reserve) =
+                        (input_reserve + amount_in, output_reserve - tokens_received);
+                    (
+                        amount_in.as_f64(),
+                        tokens_received.as_f64(),
+                        new_input_reserve.as_f64(),
+                        new_output_reserve.as_f64(),
+                        Some((new_input_reserve, new_output_reserve)),
+                    )
+                }
+            };
 
-        let execution_price = swap.amount_in / tokens_received;
+        let execution_price = amount_in / tokens_received;
         let slippage = self.calculate_slippage(initial_price, execution_price);
 
         let (new_token_a_reserve, new_token_b_reserve) = if is_buying_token_a {
-            (
-                self.token_a_reserve - tokens_received,
-                self.token_b_reserve + swap.amount_in,
-            )
+            (new_output_reserve, new_input_reserve)
         } else {
-            (
-                self.token_a_reserve + swap.amount_in,
-                self.token_b_reserve - tokens_received,
-            )
+            (new_input_reserve, new_output_reserve)
+        };
+        let (raw_token_a_reserve, raw_token_b_reserve) = match raw_reserves {
+            Some((input, output)) if is_buying_token_a => (Some(output), Some(input)),
+            Some((input, output)) => (Some(input), Some(output)),
+            None => (None, None),
         };
 
         return SwapSimulationResult {
@@ -102,188 +380,726 @@ impl Pool {
                 token_b_reserve: new_token_b_reserve,
                 token_a_address: self.token_a_address.clone(),
                 token_b_address: self.token_b_address.clone(),
+                fee_bps: self.fee_bps,
+                arithmetic: self.arithmetic,
+                raw_token_a_reserve,
+                raw_token_b_reserve,
             },
         };
     }
 }
 
+/// `raw` while `value` is still its f64 approximation, else `value` rounded
+/// down, e.g. for a resized swap or a reserve set by hand.
+fn raw_or_rounded(raw: Option<U256>, value: f64) -> U256 {
+    match raw {
+        // A few ulps, the f64 may come from a less careful conversion
+        Some(raw) if (raw.as_f64() - value).abs() <= value.abs() * 4.0 * f64::EPSILON => raw,
+        _ => value.as_u256(),
+    }
+}
+
+/// Uniswap V2's `getAmountOut` with the fee in basis points. Rounds down,
+/// so the pool keeps the dust.
+fn get_amount_out(amount_in: U256, reserve_in: U256, reserve_out: U256, fee_bps: u32) -> U256 {
+    let amount_in_with_fee = amount_in * U256::from(10_000 - fee_bps.min(10_000));
+    let denominator = reserve_in * U256::from(10_000u32) + amount_in_with_fee;
+    if denominator == U256::ZERO {
+        return U256::ZERO;
+    }
+    return amount_in_with_fee * reserve_out / denominator;
+}
+
 /// Find sandwich attacks across all blocks using simulation
-pub fn find_sandwich_attacks_by_simulation(
-    pool_map: &HashMap<String, Pool>,
+///
+/// Pools are looked up by address only, so `pool_map` should hold the pools
+/// of a single chain and `transactions` be filtered to that chain. They can
+/// be any `PoolModel`, e.g. `ConcentratedLiquidityPool` for Uniswap V3.
+pub fn find_sandwich_attacks_by_simulation<P: PoolModel + Clone>(
+    pool_map: &HashMap<String, P>,
     transactions: &[SwapTransaction],
 ) -> Vec<SandwichAttackBySimulation> {
-    // Group transactions by block number
-    let mut blocks: std::collections::HashMap<u64, Vec<SwapTransaction>> =
-        std::collections::HashMap::new();
+    return find_sandwich_attacks_by_simulation_with_config(
+        pool_map,
+        transactions,
+        &Config::default(),
+    )
+    .attacks;
+}
+
+/// `find_sandwich_attacks_by_simulation` with custom detection parameters,
+/// also returning the candidates that could not be simulated.
+pub fn find_sandwich_attacks_by_simulation_with_config<P: PoolModel + Clone>(
+    pool_map: &HashMap<String, P>,
+    transactions: &[SwapTransaction],
+    config: &Config,
+) -> DetectionOutcome<SandwichAttackBySimulation> {
+    return find_sandwich_attacks_by_simulation_until(
+        pool_map,
+        transactions,
+        config,
+        &CancellationToken::new(),
+    );
+}
+
+/// `find_sandwich_attacks_by_simulation_with_config` stopping once `cancel`
+/// fires, checked between blocks (oldest first) and between candidates.
+pub fn find_sandwich_attacks_by_simulation_until<P: PoolModel + Clone>(
+    pool_map: &HashMap<String, P>,
+    transactions: &[SwapTransaction],
+    config: &Config,
+    cancel: &CancellationToken,
+) -> DetectionOutcome<SandwichAttackBySimulation> {
+    return find_sandwich_attacks_by_simulation_with_progress(
+        pool_map,
+        transactions,
+        config,
+        cancel,
+        |_| {},
+    );
+}
+
+/// `find_sandwich_attacks_by_simulation_until` calling `progress` after each block.
+pub fn find_sandwich_attacks_by_simulation_with_progress<P, F>(
+    pool_map: &HashMap<String, P>,
+    transactions: &[SwapTransaction],
+    config: &Config,
+    cancel: &CancellationToken,
+    mut progress: F,
+) -> DetectionOutcome<SandwichAttackBySimulation>
+where
+    P: PoolModel + Clone,
+    F: FnMut(Progress),
+{
+    // Group transactions by chain and block number
+    let mut blocks: std::collections::BTreeMap<BlockId, Vec<SwapTransaction>> =
+        std::collections::BTreeMap::new();
     for tx in transactions {
-        blocks
-            .entry(tx.block_number)
-            .or_insert_with(Vec::new)
-            .push(tx.clone());
+        blocks.entry(tx.block_id()).or_default().push(tx.clone());
     }
 
-    let mut all_attacks = Vec::new();
+    let mut outcome = DetectionOutcome::default();
 
     // Process each block separately
-    for (_block_number, block_txs) in blocks {
-        let block_attacks = find_sandwiches_in_block_by_simulation(&pool_map, &block_txs);
-        all_attacks.extend(block_attacks);
+    let mut tracker = ProgressTracker::new(blocks.len());
+    for (block_id, block_txs) in blocks {
+        if cancel.is_cancelled() {
+            outcome.truncated = true;
+            break;
+        }
+        outcome.extend(find_sandwiches_in_block_by_simulation(
+            pool_map, &block_txs, config, cancel,
+        ));
+        progress(tracker.advance(block_id, outcome.attacks.len()));
     }
 
-    all_attacks
+    outcome
+}
+
+/// Streaming counterpart of `find_sandwich_attacks_by_simulation`, holding
+/// only one block in memory at a time. The input must be ordered by block.
+pub fn detect_stream_by_simulation<'a, P, I>(
+    pool_map: &'a HashMap<String, P>,
+    transactions: I,
+) -> impl Iterator<Item = SandwichAttackBySimulation> + 'a
+where
+    P: PoolModel + Clone,
+    I: IntoIterator<Item = SwapTransaction>,
+    I::IntoIter: 'a,
+{
+    let config = Config::default();
+    let cancel = CancellationToken::new();
+    stream_transactions_by_block(transactions).flat_map(move |(_block_id, block_txs)| {
+        find_sandwiches_in_block_by_simulation(pool_map, &block_txs, &config, &cancel).attacks
+    })
 }
 
 /// Find sandwich attacks within a single block using simulation
-fn find_sandwiches_in_block_by_simulation(
-    pool_map: &HashMap<String, Pool>,
+pub(crate) fn find_sandwiches_in_block_by_simulation<P: PoolModel + Clone>(
+    pool_map: &HashMap<String, P>,
     transactions: &[SwapTransaction],
-) -> Vec<SandwichAttackBySimulation> {
-    let mut detected_attacks = Vec::new();
-
-    for i in 0..transactions.len() {
-        for j in i + 1..transactions.len() {
-            for k in j + 1..transactions.len() {
-                let front = &transactions[i];
-                let victim = &transactions[j];
-                let back = &transactions[k];
-
-                if is_sandwich_pattern(front, victim, back) {
-                    if let Some(pool) = pool_map.get(&front.pool_address) {
-                        match simulate_sandwich_attack(pool, front, victim, back, &transactions) {
-                            Ok(attack) => detected_attacks.push(attack),
-                            Err(error) => println!("Sandwich simulation error: {}", error),
-                        }
-                    }
+    config: &Config,
+    cancel: &CancellationToken,
+) -> DetectionOutcome<SandwichAttackBySimulation> {
+    return telemetry::traced(
+        telemetry::SIMULATE_BLOCK,
+        &[("transactions", transactions.len() as i64)],
+        || {
+            if let Err(err) = config.validate() {
+                let mut outcome = DetectionOutcome::default();
+                if let Some(tx) = transactions.first() {
+                    outcome
+                        .skipped
+                        .push((tx.block_id(), DetectorError::InvalidConfig(err)));
+                }
+                return outcome;
+            }
+            let transactions = config.whole_token_amounts(transactions);
+            let mut outcome = simulate_block(pool_map, &transactions, config, cancel);
+            assign_simulated_severity(&mut outcome.attacks, &config.severity);
+            return outcome;
+        },
+    );
+}
+
+fn simulate_block<P: PoolModel + Clone>(
+    pool_map: &HashMap<String, P>,
+    transactions: &[SwapTransaction],
+    config: &Config,
+    cancel: &CancellationToken,
+) -> DetectionOutcome<SandwichAttackBySimulation> {
+    let mut outcome = DetectionOutcome::default();
+    let mut replays = ReplayCache::new();
+
+    'scan: for i in 0..transactions.len() {
+        for k in i + 2..transactions.len() {
+            let front = &transactions[i];
+            let back = &transactions[k];
+
+            // All victims of this front/back pair, to label multi-victim sandwiches
+            let mut pair_attacks = Vec::new();
+            for victim in &transactions[i + 1..k] {
+                if !is_sandwich_pattern_with(&config.tokens, front, victim, back) {
+                    continue;
+                }
+                if cancel.is_cancelled() {
+                    outcome.truncated = true;
+                    break 'scan;
+                }
+                let simulation = simulate_sandwich_attack(
+                    pool_map,
+                    front,
+                    victim,
+                    back,
+                    transactions,
+                    config,
+                    &mut replays,
+                );
+                match simulation {
+                    Ok(attack) => pair_attacks.push(attack),
+                    Err(error) => outcome.skipped.push((victim.block_id(), error)),
                 }
             }
+
+            let victims = pair_attacks.len();
+            for mut attack in pair_attacks {
+                attack.classification = classify_sandwich(front, &attack.victim_tx, back, victims);
+                outcome.attacks.push(attack);
+            }
         }
     }
 
-    detected_attacks
+    if !config.keep_raw_candidates {
+        outcome.attacks = dedup_overlapping(outcome.attacks);
+    }
+
+    outcome
 }
 
-/// Simulates a specific sandwich attack to measure victim impact
-fn simulate_sandwich_attack(
-    initial_pool: &Pool,
-    front: &SwapTransaction,
-    victim: &SwapTransaction,
-    back: &SwapTransaction,
-    all_transactions: &[SwapTransaction],
-) -> Result<SandwichAttackBySimulation, String> {
-    let pool_transactions: Vec<&SwapTransaction> = all_transactions
+fn with_arithmetic<P: PoolModel + Clone>(pool: &P, arithmetic: Arithmetic) -> P {
+    let mut pool = pool.clone();
+    pool.set_arithmetic(arithmetic);
+    return pool;
+}
+
+/// The swaps of `tx`'s transaction in execution order, one per pool an
+/// aggregator routed it through. Each hop spends what the previous one
+/// bought, so hops are chained by token.
+fn route_of<'a>(
+    tx: &'a SwapTransaction,
+    all_transactions: &'a [SwapTransaction],
+) -> Vec<&'a SwapTransaction> {
+    let mut hops: Vec<&SwapTransaction> = all_transactions
         .iter()
-        .filter(|tx| tx.pool_address == victim.pool_address)
+        .filter(|hop| hop.tx_hash == tx.tx_hash)
         .collect();
-    if pool_transactions.is_empty() {
-        return Err("No transaction's found in the victim pool.".to_string());
+    if hops.len() < 2 {
+        return vec![tx];
     }
 
-    if !check_simulation_is_like_reality(initial_pool, &pool_transactions, victim) {
-        return Err("Initial simulation is not like reality.".to_string());
+    let first = hops
+        .iter()
+        .position(|hop| !hops.iter().any(|other| other.token_out == hop.token_in))
+        .unwrap_or(0);
+    let mut route = vec![hops.remove(first)];
+    while let Some(next) = hops
+        .iter()
+        .position(|hop| hop.token_in == route[route.len() - 1].token_out)
+    {
+        route.push(hops.remove(next));
     }
+    // Hops that don't chain keep their recorded order
+    route.extend(hops);
+    return route;
+}
 
-    let difference_pct = simulate_without_attacker(initial_pool, &pool_transactions, front, victim);
+/// Swap the route's first recorded input through its hops and return the
+/// final output. Hops on pools without a state scale their recorded output
+/// to the input they get, hops on known pools pay `taxes` on what they
+/// sell and buy. Fails if a pool can't execute its hop.
+fn swap_route<P: PoolModel + Clone>(
+    pools: &mut HashMap<String, P>,
+    route: &[&SwapTransaction],
+    taxes: &TokenTaxes,
+) -> Result<f64, DetectorError> {
+    return swap_hops(pools, route, taxes, |_, _| {});
+}
+
+/// `swap_route` calling `on_hop` with the simulated output of every hop
+/// swapped on a known pool.
+fn swap_hops<P, F>(
+    pools: &mut HashMap<String, P>,
+    route: &[&SwapTransaction],
+    taxes: &TokenTaxes,
+    mut on_hop: F,
+) -> Result<f64, DetectorError>
+where
+    P: PoolModel + Clone,
+    F: FnMut(&SwapTransaction, f64),
+{
+    let mut amount = route[0].amount_in;
+    for hop in route {
+        amount = match pools.get_mut(&hop.pool_address) {
+            Some(pool) => {
+                let amount_out = pool.try_swap(&SwapTransaction {
+                    amount_in: taxes.after_sell(&hop.token_in, amount),
+                    ..(*hop).clone()
+                })?;
+                let amount_out = taxes.after_buy(&hop.token_out, amount_out);
+                on_hop(hop, amount_out);
+                amount_out
+            }
+            None if hop.amount_in > 0.0 => hop.amount_out * amount / hop.amount_in,
+            None => hop.amount_out,
+        };
+    }
+    return Ok(amount);
+}
+
+/// Pool states of a candidate before the block, and the block's routes
+/// touching them in order.
+struct RouteReplay<'a, P> {
+    initial_pools: HashMap<String, P>,
+    routes: Vec<Vec<&'a SwapTransaction>>,
+    taxes: &'a TokenTaxes,
+    /// The pools after each prefix of `routes`, the first entry being
+    /// `initial_pools`, so each prefix of the block is simulated once.
+    states: Vec<HashMap<String, P>>,
+}
+
+/// Replays of a block by the pools they start from, the front-run's and
+/// those of the victim's route. Candidates of a block sharing them share
+/// the replay.
+type ReplayCache<'a, P> = HashMap<Vec<String>, RouteReplay<'a, P>>;
+
+impl<'a, P: PoolModel + Clone> RouteReplay<'a, P> {
+    fn new(
+        initial_pools: HashMap<String, P>,
+        routes: Vec<Vec<&'a SwapTransaction>>,
+        taxes: &'a TokenTaxes,
+    ) -> Result<Self, DetectorError> {
+        let mut states = vec![initial_pools.clone()];
+        for route in &routes {
+            let mut pools = states[states.len() - 1].clone();
+            swap_route(&mut pools, route, taxes)?;
+            states.push(pools);
+        }
+        return Ok(Self {
+            initial_pools,
+            routes,
+            taxes,
+            states,
+        });
+    }
+
+    /// The states of `pools` after the routes `include` keeps among those
+    /// before `position`. Starts from the longest cached prefix with every
+    /// route kept.
+    fn pools_before<F>(
+        &self,
+        position: u32,
+        include: F,
+    ) -> Result<HashMap<String, P>, DetectorError>
+    where
+        F: Fn(&SwapTransaction) -> bool,
+    {
+        let before = |route: &Vec<&SwapTransaction>| route[0].tx_position_in_block < position;
+        let cached = self
+            .routes
+            .iter()
+            .take_while(|route| before(route) && include(route[0]))
+            .count();
+        let mut pools = self.states[cached].clone();
+        for route in &self.routes[cached..] {
+            if before(route) && include(route[0]) {
+                swap_route(&mut pools, route, self.taxes)?;
+            }
+        }
+        return Ok(pools);
+    }
+}
+
+/// Simulates a specific sandwich attack to measure victim impact
+///
+/// The front-run's pool stands for the victim's, the other pools of a
+/// routed victim are taken from `pool_map` so its loss is measured on the
+/// whole route.
+fn simulate_sandwich_attack<'a, P: PoolModel + Clone>(
+    pool_map: &HashMap<String, P>,
+    front: &SwapTransaction,
+    victim: &'a SwapTransaction,
+    back: &SwapTransaction,
+    all_transactions: &'a [SwapTransaction],
+    config: &'a Config,
+    replays: &mut ReplayCache<'a, P>,
+) -> Result<SandwichAttackBySimulation, DetectorError> {
+    let victim_route = victim_route(pool_map, front, victim, all_transactions)?;
+    let mut key = vec![front.pool_address.clone()];
+    key.extend(victim_route.iter().map(|hop| hop.pool_address.clone()));
+    if !replays.contains_key(&key) {
+        let replay = new_route_replay(pool_map, front, &victim_route, all_transactions, config)?;
+        replays.insert(key.clone(), replay);
+    }
+    let replay = &replays[&key];
+
+    let (simulation_unverified, drift_pct) = match check_simulation_is_like_reality(
+        replay,
+        &victim_route,
+        config.simulation.reality_tolerance_pct,
+    ) {
+        Ok(drift_pct) => (false, drift_pct),
+        Err(DetectorError::SimulationDiverged { difference_pct, .. })
+            if config.simulation.report_unverified =>
+        {
+            (true, difference_pct)
+        }
+        Err(error) => return Err(error),
+    };
+
+    let removal = config.simulation.counterfactual_removal;
+    let include = |tx: &SwapTransaction| !removal.removes(tx, front, back);
+    let difference_pct = counterfactual(replay, &victim_route, include)?.loss_percentage;
+    let (victim_loss_percentage_low, victim_loss_percentage_high) = victim_loss_bounds(
+        replay,
+        &victim_route,
+        include,
+        drift_pct,
+        config.simulation.fee_uncertainty_bps,
+    )?;
+
+    let attacker_profit_token =
+        simulate_attacker_round_trip(replay, front, back, all_transactions)?;
+    let token_price_usd = if front.amount_in > 0.0 {
+        front.usd_value_in / front.amount_in
+    } else {
+        0.0
+    };
+    let builder_payments_usd = builder_payment_usd(front, &config.heuristics)
+        + builder_payment_usd(back, &config.heuristics);
+    let attacker_profit_usd = attacker_profit_token * token_price_usd
+        - front.gas_cost_usd
+        - back.gas_cost_usd
+        - builder_payments_usd;
+
+    let before_front =
+        &replay.pools_before(front.tx_position_in_block, |_| true)?[&victim.pool_address];
+    let extraction_efficiency = extraction_efficiency(
+        before_front,
+        front,
+        victim,
+        config.heuristics.victim_slippage_tolerance,
+    );
 
     Ok(SandwichAttackBySimulation {
+        chain_id: victim.chain_id,
         front_run_tx: front.clone(),
         victim_tx: victim.clone(),
         back_run_tx: back.clone(),
         victim_loss_percentage: difference_pct,
+        victim_loss_percentage_low,
+        victim_loss_percentage_high,
+        classification: Classification::default(),
+        attacker_profit_token,
+        attacker_profit_usd,
+        builder_payments_usd,
+        profitable_only_before_bribe: profitable_only_before_bribe(
+            attacker_profit_usd,
+            builder_payments_usd,
+        ),
+        extraction_efficiency,
+        simulation_unverified,
+        severity: Severity::default(),
     })
 }
 
-/// Try simulate what actually happened during the real block
-/// to see if we'd get the same amount_out for the would-be victim.
-/// This acts as a sanity check to ensure the simulation is accurate.
-fn check_simulation_is_like_reality(
-    initial_pool: &Pool,
-    pool_transactions: &[&SwapTransaction],
-    victim: &SwapTransaction,
-) -> bool {
-    let before_victim_transactions: Vec<&&SwapTransaction> = pool_transactions
+/// The victim's route and the states of the pools it goes through before
+/// the block, with the block's routes touching them. The front-run's pool
+/// stands in for the victim's.
+fn route_replay<'a, P: PoolModel + Clone>(
+    pool_map: &HashMap<String, P>,
+    front: &SwapTransaction,
+    victim: &'a SwapTransaction,
+    all_transactions: &'a [SwapTransaction],
+    config: &'a Config,
+) -> Result<(RouteReplay<'a, P>, Vec<&'a SwapTransaction>), DetectorError> {
+    let victim_route = victim_route(pool_map, front, victim, all_transactions)?;
+    let replay = new_route_replay(pool_map, front, &victim_route, all_transactions, config)?;
+    return Ok((replay, victim_route));
+}
+
+/// The victim's route, once its pools are known to be simulable.
+fn victim_route<'a, P: PoolModel + Clone>(
+    pool_map: &HashMap<String, P>,
+    front: &SwapTransaction,
+    victim: &'a SwapTransaction,
+    all_transactions: &'a [SwapTransaction],
+) -> Result<Vec<&'a SwapTransaction>, DetectorError> {
+    if !pool_map.contains_key(&front.pool_address) {
+        return Err(DetectorError::UnknownPool(front.pool_address.clone()));
+    }
+    if !all_transactions
         .iter()
-        .filter(|tx| tx.tx_position_in_block < victim.tx_position_in_block)
-        .collect();
+        .any(|tx| tx.pool_address == victim.pool_address)
+    {
+        return Err(DetectorError::NoPoolTransactions(
+            victim.pool_address.clone(),
+        ));
+    }
+    return Ok(route_of(victim, all_transactions));
+}
+
+fn new_route_replay<'a, P: PoolModel + Clone>(
+    pool_map: &HashMap<String, P>,
+    front: &SwapTransaction,
+    victim_route: &[&SwapTransaction],
+    all_transactions: &'a [SwapTransaction],
+    config: &'a Config,
+) -> Result<RouteReplay<'a, P>, DetectorError> {
+    let arithmetic = config.simulation.arithmetic;
+    let mut initial_pools = HashMap::new();
+    for hop in victim_route {
+        if let Some(pool) = pool_map.get(&hop.pool_address) {
+            initial_pools.insert(hop.pool_address.clone(), with_arithmetic(pool, arithmetic));
+        }
+    }
+    // The front-run is on the victim's pool, whichever hop that is
+    initial_pools
+        .entry(front.pool_address.clone())
+        .or_insert_with(|| with_arithmetic(&pool_map[&front.pool_address], arithmetic));
+
+    let mut routes: Vec<Vec<&SwapTransaction>> = Vec::new();
+    for tx in all_transactions {
+        let seen = routes.iter().any(|route| route[0].tx_hash == tx.tx_hash);
+        if !seen {
+            routes.push(route_of(tx, all_transactions));
+        }
+    }
+    routes.retain(|route| {
+        route
+            .iter()
+            .any(|hop| initial_pools.contains_key(&hop.pool_address))
+    });
+    routes.sort_by_key(|route| route[0].tx_position_in_block);
+    return RouteReplay::new(initial_pools, routes, &config.simulation.token_taxes);
+}
 
-    let mut current_pool = initial_pool.clone();
-    for tx in before_victim_transactions {
-        let simulation = current_pool.simulate_swap(tx);
-        current_pool = simulation.new_pool_state;
+/// The range of the victim's loss with every pool's fee `fee_uncertainty_bps`
+/// lower and higher, and the counterfactual output `drift_pct` lower and
+/// higher. No loss is within range when the counterfactual could be the
+/// actual output.
+fn victim_loss_bounds<P, F>(
+    replay: &RouteReplay<P>,
+    victim_route: &[&SwapTransaction],
+    include: F,
+    drift_pct: f64,
+    fee_uncertainty_bps: u32,
+) -> Result<(f64, f64), DetectorError>
+where
+    P: PoolModel + Clone,
+    F: Fn(&SwapTransaction) -> bool + Copy,
+{
+    let mut outputs =
+        vec![counterfactual(replay, victim_route, include)?.counterfactual_amount_out];
+    for higher in [false, true] {
+        let mut initial_pools = replay.initial_pools.clone();
+        for pool in initial_pools.values_mut() {
+            let fee_bps = match higher {
+                true => pool.fee_bps() + fee_uncertainty_bps,
+                false => pool.fee_bps().saturating_sub(fee_uncertainty_bps),
+            };
+            pool.set_fee_bps(fee_bps);
+        }
+        let fee_replay = RouteReplay::new(initial_pools, replay.routes.clone(), replay.taxes)?;
+        outputs.push(counterfactual(&fee_replay, victim_route, include)?.counterfactual_amount_out);
     }
 
-    let victim_simulation = current_pool.simulate_swap(victim);
+    let low_output =
+        outputs.iter().copied().fold(f64::INFINITY, f64::min) * (1.0 - drift_pct / 100.0);
+    let high_output =
+        outputs.iter().copied().fold(f64::NEG_INFINITY, f64::max) * (1.0 + drift_pct / 100.0);
+    let actual_amount_out = victim_route[victim_route.len() - 1].amount_out;
+    let loss = |output: f64| ((actual_amount_out - output) / actual_amount_out * 100.0).abs();
+    let (low_loss, high_loss) = (loss(low_output), loss(high_output));
+    if low_output <= actual_amount_out && actual_amount_out <= high_output {
+        return Ok((0.0, low_loss.max(high_loss)));
+    }
+    return Ok((low_loss.min(high_loss), low_loss.max(high_loss)));
+}
 
-    let actual_amount_out = victim.amount_out;
-    let simulated_amount_out = victim_simulation.tokens_received;
+/// Try simulate what actually happened during the real block
+/// to see if we'd get the same amount_out for the would-be victim.
+/// This acts as a sanity check to ensure the simulation is accurate.
+fn check_simulation_is_like_reality<P: PoolModel + Clone>(
+    replay: &RouteReplay<P>,
+    victim_route: &[&SwapTransaction],
+    tolerance_pct: f64,
+) -> Result<f64, DetectorError> {
+    let victim = victim_route[0];
+    let mut current_pools = replay.pools_before(victim.tx_position_in_block, |_| true)?;
+
+    let actual_amount_out = victim_route[victim_route.len() - 1].amount_out;
+    let simulated_amount_out = swap_route(&mut current_pools, victim_route, replay.taxes)?;
     let difference_percentage =
         ((actual_amount_out - simulated_amount_out) / actual_amount_out * 100.0).abs();
 
-    return difference_percentage < 1.0;
+    if difference_percentage < tolerance_pct {
+        return Ok(difference_percentage);
+    }
+    return Err(DetectorError::SimulationDiverged {
+        tx_hash: victim.tx_hash.clone(),
+        difference_pct: difference_percentage,
+        diagnostic: Box::new(diagnose_divergence(replay, victim_route, tolerance_pct)?),
+    });
 }
 
-/// Try and simulate what actually happens during the block
-/// if we'd remove the would-be front and back transactions.
-/// This will allow us to later test if there was any price impact
-/// to the victim's transaction.
-fn simulate_without_attacker(
-    initial_pool: &Pool,
-    pool_transactions: &[&SwapTransaction],
-    front: &SwapTransaction,
-    victim: &SwapTransaction,
-) -> f64 {
-    let no_attacker_before_victim_txns: Vec<&&SwapTransaction> = pool_transactions
+/// Replay the swaps before the victim and the victim itself again,
+/// recording how far each simulated output is from the actual one.
+fn diagnose_divergence<P: PoolModel + Clone>(
+    replay: &RouteReplay<P>,
+    victim_route: &[&SwapTransaction],
+    tolerance_pct: f64,
+) -> Result<SimulationDiagnostic, DetectorError> {
+    let victim = victim_route[0];
+    let mut pools = replay.initial_pools.clone();
+    let mut steps = Vec::new();
+    let mut cumulative_drift_pct = 0.0;
+    let mut record = |hop: &SwapTransaction, simulated_amount_out: f64| {
+        let drift_pct = if hop.amount_out != 0.0 {
+            (simulated_amount_out - hop.amount_out) / hop.amount_out * 100.0
+        } else {
+            0.0
+        };
+        cumulative_drift_pct += drift_pct.abs();
+        steps.push(ReplayStep {
+            tx_hash: hop.tx_hash.clone(),
+            tx_position_in_block: hop.tx_position_in_block,
+            pool_address: hop.pool_address.clone(),
+            simulated_amount_out,
+            actual_amount_out: hop.amount_out,
+            drift_pct,
+            cumulative_drift_pct,
+        });
+    };
+    for route in &replay.routes {
+        if route[0].tx_position_in_block < victim.tx_position_in_block {
+            swap_hops(&mut pools, route, replay.taxes, &mut record)?;
+        }
+    }
+    swap_hops(&mut pools, victim_route, replay.taxes, &mut record)?;
+
+    let first_divergent_tx = steps
         .iter()
-        .filter(|tx| tx.tx_position_in_block < victim.tx_position_in_block)
-        .filter(|tx| tx.tx_position_in_block != front.tx_position_in_block)
-        .collect();
+        .find(|step| step.drift_pct.abs() >= tolerance_pct)
+        .map(|step| step.tx_hash.clone());
+    return Ok(SimulationDiagnostic {
+        steps,
+        first_divergent_tx,
+    });
+}
 
-    let mut current_pool = initial_pool.clone();
-    for tx in no_attacker_before_victim_txns {
-        let simulation = current_pool.simulate_swap(tx);
-        current_pool = simulation.new_pool_state;
-    }
+/// Replay the block up to the back-run and return what the attacker's
+/// round trip made in the front-run's input token: the simulated output of
+/// the back-run less the input of the front-run.
+fn simulate_attacker_round_trip<P: PoolModel + Clone>(
+    replay: &RouteReplay<P>,
+    front: &SwapTransaction,
+    back: &SwapTransaction,
+    all_transactions: &[SwapTransaction],
+) -> Result<f64, DetectorError> {
+    let mut current_pools = replay.pools_before(back.tx_position_in_block, |_| true)?;
+    let back_route = route_of(back, all_transactions);
+    let front_route = route_of(front, all_transactions);
+    let back_out = swap_route(&mut current_pools, &back_route, replay.taxes)?;
+    return Ok(back_out - front_route[0].amount_in);
+}
 
-    let victim_simulation = current_pool.simulate_swap(victim);
+/// What the victim would have received without the attacker's swaps that
+/// `SimulationConfig::counterfactual_removal` takes out, e.g. to show a
+/// wallet user "you would have received X without the sandwich".
+/// `pool_map` holds the pools before the block, as for
+/// `find_sandwich_attacks_by_simulation`; `all_transactions` are the
+/// block's swaps.
+pub fn simulate_without_attacker<P: PoolModel + Clone>(
+    pool_map: &HashMap<String, P>,
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+    all_transactions: &[SwapTransaction],
+    config: &Config,
+) -> Result<Counterfactual, DetectorError> {
+    let (replay, victim_route) = route_replay(pool_map, front, victim, all_transactions, config)?;
+    let removal = config.simulation.counterfactual_removal;
+    return counterfactual(&replay, &victim_route, |tx| {
+        !removal.removes(tx, front, back)
+    });
+}
 
-    let actual_amount_out = victim.amount_out;
-    let simulated_amount_out = victim_simulation.tokens_received;
-    return ((actual_amount_out - simulated_amount_out) / actual_amount_out * 100.0).abs();
+/// Replay the victim's route on the pools as they would be with only the
+/// routes `include` keeps before it.
+fn counterfactual<P, F>(
+    replay: &RouteReplay<P>,
+    victim_route: &[&SwapTransaction],
+    include: F,
+) -> Result<Counterfactual, DetectorError>
+where
+    P: PoolModel + Clone,
+    F: Fn(&SwapTransaction) -> bool,
+{
+    let victim = victim_route[0];
+    let mut current_pools = replay.pools_before(victim.tx_position_in_block, include)?;
+
+    let actual_amount_out = victim_route[victim_route.len() - 1].amount_out;
+    let simulated_amount_out = swap_route(&mut current_pools, victim_route, replay.taxes)?;
+    let price = |amount_out: f64| match victim.amount_in > 0.0 {
+        true => amount_out / victim.amount_in,
+        false => 0.0,
+    };
+    return Ok(Counterfactual {
+        actual_amount_out,
+        counterfactual_amount_out: simulated_amount_out,
+        actual_price: price(actual_amount_out),
+        counterfactual_price: price(simulated_amount_out),
+        loss_percentage: ((actual_amount_out - simulated_amount_out) / actual_amount_out * 100.0)
+            .abs(),
+    });
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ingest::csv::sample_transactions;
+    use crate::sandwich::same_block_heuristics::{
+        find_same_block_sandwiches, find_same_block_sandwiches_with_config,
+    };
+    use crate::sandwich::tokens::TokenDecimals;
     use crate::sandwich::transactions::SwapTransaction;
+    use crate::test_support::{sample_block, sample_pool, sample_pool_map};
     use std::collections::HashMap;
-    use std::fs;
-
-    fn load_sample_transactions() -> Vec<SwapTransaction> {
-        let csv_content =
-            fs::read_to_string("data/sandwiches.csv").expect("Failed to read sample CSV file");
-
-        let mut reader = csv::Reader::from_reader(csv_content.as_bytes());
-        let mut transactions = Vec::new();
-
-        for result in reader.deserialize() {
-            let transaction: SwapTransaction = result.expect("Failed to parse CSV row");
-            transactions.push(transaction);
-        }
-
-        transactions
-    }
 
     #[test]
     fn test_detect_sandwich_attacks_with_sample_data() {
         let mut pool_map = HashMap::new();
-        pool_map.insert(
-            "0xpool1".to_string(),
-            Pool::new(
-                1000000.0,
-                50000000000.0,
-                "USDC".to_string(),
-                "SHIB".to_string(),
-            ),
-        );
+        pool_map.insert("0xpool1".to_string(), sample_pool());
         pool_map.insert(
             "0xpool_uniswap".to_string(),
             Pool::new(800.0, 800000.0, "ETH".to_string(), "NEWTOKEN".to_string()),
@@ -302,7 +1118,7 @@ mod tests {
             ),
         );
 
-        let transactions = load_sample_transactions();
+        let transactions = sample_transactions();
         let all_attacks = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
 
         // Should find exactly the same attacks as heuristics method
@@ -449,29 +1265,621 @@ mod tests {
             "Simulation detected {} sandwich attacks:",
             all_attacks.len()
         );
-        for (i, attack) in all_attacks.iter().enumerate() {
-            println!(
-                "Attack {}: Block {} - Victim Loss: {:.3}%",
-                i + 1,
-                attack.front_run_tx.block_number,
-                attack.victim_loss_percentage
-            );
-            println!(
-                "  Front: {} ({} -> {})",
-                attack.front_run_tx.tx_hash,
-                attack.front_run_tx.token_in,
-                attack.front_run_tx.token_out
-            );
-            println!(
-                "  Victim: {} ({} -> {})",
-                attack.victim_tx.tx_hash, attack.victim_tx.token_in, attack.victim_tx.token_out
-            );
-            println!(
-                "  Back: {} ({} -> {})",
-                attack.back_run_tx.tx_hash,
-                attack.back_run_tx.token_in,
-                attack.back_run_tx.token_out
-            );
+        for attack in &all_attacks {
+            println!("{}", attack.summary());
         }
     }
+
+    #[test]
+    fn test_swap_fee_is_taken_from_the_input() {
+        let swap = sample_transactions()
+            .into_iter()
+            .find(|tx| tx.pool_address == "0xpool1")
+            .unwrap();
+        let pool = sample_pool();
+        let (x, y) = if swap.token_out == pool.token_a_address {
+            (pool.token_b_reserve, pool.token_a_reserve)
+        } else {
+            (pool.token_a_reserve, pool.token_b_reserve)
+        };
+
+        let free = pool.clone().with_fee_bps(0).simulate_swap(&swap);
+        assert_eq!(
+            free.tokens_received,
+            pool.constant_product_formula(x, y, swap.amount_in)
+        );
+        let standard = pool.simulate_swap(&swap);
+        let one_percent = pool.clone().with_fee_bps(100).simulate_swap(&swap);
+        assert!(one_percent.tokens_received < standard.tokens_received);
+        assert!(standard.tokens_received < free.tokens_received);
+        // The whole input, fee included, ends up in the pool
+        let state = &standard.new_pool_state;
+        assert_eq!(
+            state.token_a_reserve + state.token_b_reserve,
+            pool.token_a_reserve + pool.token_b_reserve + swap.amount_in - standard.tokens_received
+        );
+        assert_eq!(state.fee_bps, 30);
+    }
+
+    #[test]
+    fn test_attacker_profit_from_simulated_round_trip() {
+        let transactions = sample_block();
+        let pool = sample_pool();
+        let pool_map = HashMap::from([("0xpool1".to_string(), pool.clone())]);
+        let mut config = Config::default();
+        config.heuristics.builder_payments =
+            Some(HashMap::from([("0xsandwich2".to_string(), 5.0)]));
+        let attacks =
+            find_sandwich_attacks_by_simulation_with_config(&pool_map, &transactions, &config)
+                .attacks;
+        assert_eq!(attacks.len(), 1);
+        let attack = &attacks[0];
+
+        let mut replayed = pool;
+        replayed.swap(&attack.front_run_tx);
+        replayed.swap(&attack.victim_tx);
+        let back_out = replayed.swap(&attack.back_run_tx);
+        assert_eq!(attack.attacker_profit_token, back_out - 1000.0);
+        // USDC at $1, 48 + 72 of gas and the 5 paid to the builder
+        assert!((attack.attacker_profit_usd - (attack.attacker_profit_token - 125.0)).abs() < 1e-9);
+        assert!(attack.attacker_profit_usd < 0.0);
+    }
+
+    #[test]
+    fn test_routed_victim_loss_is_measured_over_the_whole_route() {
+        let usdc_weth = Pool::new(3_000_000.0, 1000.0, "USDC".into(), "WETH".into());
+        let weth_shib = Pool::new(100.0, 5_000_000_000.0, "WETH".into(), "SHIB".into());
+        let swap = |hash: &str, position: u32, from: &str, pool: &str, route: (&str, &str)| {
+            SwapTransaction {
+                tx_hash: hash.to_string(),
+                tx_position_in_block: position,
+                from_address: from.to_string(),
+                pool_address: pool.to_string(),
+                token_in: route.0.to_string(),
+                token_out: route.1.to_string(),
+                ..sample_transactions()[0].clone()
+            }
+        };
+        let mut front = swap("0xfront", 1, "0xbot", "0xusdc_weth", ("USDC", "WETH"));
+        // An aggregator routes the victim's USDC to SHIB through WETH,
+        // hops listed out of order
+        let mut second_hop = swap("0xvictim", 2, "0xuser", "0xweth_shib", ("WETH", "SHIB"));
+        let mut first_hop = swap("0xvictim", 2, "0xuser", "0xusdc_weth", ("USDC", "WETH"));
+        let mut back = swap("0xback", 3, "0xbot", "0xusdc_weth", ("WETH", "USDC"));
+
+        let (mut a, mut b) = (usdc_weth.clone(), weth_shib.clone());
+        front.amount_in = 60_000.0;
+        front.amount_out = a.swap(&front);
+        first_hop.amount_in = 300_000.0;
+        first_hop.amount_out = a.swap(&first_hop);
+        second_hop.amount_in = first_hop.amount_out;
+        second_hop.amount_out = b.swap(&second_hop);
+        back.amount_in = front.amount_out;
+        back.amount_out = a.swap(&back);
+        let transactions = vec![front, second_hop.clone(), first_hop.clone(), back];
+
+        // Without the front-run, the larger WETH amount goes through the
+        // second pool too
+        let weth = usdc_weth.clone().swap(&first_hop);
+        let shib = weth_shib.clone().swap(&SwapTransaction {
+            amount_in: weth,
+            ..second_hop.clone()
+        });
+        let route_loss = (shib - second_hop.amount_out) / second_hop.amount_out * 100.0;
+        let hop_loss = (weth - first_hop.amount_out) / first_hop.amount_out * 100.0;
+
+        let pool_map = HashMap::from([
+            ("0xusdc_weth".to_string(), usdc_weth.clone()),
+            ("0xweth_shib".to_string(), weth_shib),
+        ]);
+        let attacks = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
+        assert_eq!(attacks.len(), 1);
+        assert_eq!(attacks[0].victim_tx, first_hop);
+        assert!((attacks[0].victim_loss_percentage - route_loss).abs() < 1e-9);
+        assert!(route_loss < hop_loss);
+
+        // Without the second pool its recorded output is scaled instead
+        let pool_map = HashMap::from([("0xusdc_weth".to_string(), usdc_weth)]);
+        let attacks = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
+        assert_eq!(attacks.len(), 1);
+        assert!((attacks[0].victim_loss_percentage - hop_loss).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_routed_victim_sandwiched_on_its_second_hop() {
+        let usdc_weth = Pool::new(3_000_000.0, 1000.0, "USDC".into(), "WETH".into());
+        let weth_shib = Pool::new(100.0, 5_000_000_000.0, "WETH".into(), "SHIB".into());
+        let swap = |hash: &str, position: u32, from: &str, pool: &str, route: (&str, &str)| {
+            SwapTransaction {
+                tx_hash: hash.to_string(),
+                tx_position_in_block: position,
+                from_address: from.to_string(),
+                pool_address: pool.to_string(),
+                token_in: route.0.to_string(),
+                token_out: route.1.to_string(),
+                ..sample_transactions()[0].clone()
+            }
+        };
+        let mut front = swap("0xfront", 1, "0xbot", "0xweth_shib", ("WETH", "SHIB"));
+        let mut first_hop = swap("0xvictim", 2, "0xuser", "0xusdc_weth", ("USDC", "WETH"));
+        let mut second_hop = swap("0xvictim", 2, "0xuser", "0xweth_shib", ("WETH", "SHIB"));
+        let mut back = swap("0xback", 3, "0xbot", "0xweth_shib", ("SHIB", "WETH"));
+
+        let (mut a, mut b) = (usdc_weth.clone(), weth_shib.clone());
+        front.amount_in = 5.0;
+        front.amount_out = b.swap(&front);
+        first_hop.amount_in = 30_000.0;
+        first_hop.amount_out = a.swap(&first_hop);
+        second_hop.amount_in = first_hop.amount_out;
+        second_hop.amount_out = b.swap(&second_hop);
+        back.amount_in = front.amount_out;
+        back.amount_out = b.swap(&back);
+        let transactions = vec![front, first_hop, second_hop.clone(), back];
+
+        let shib = weth_shib.clone().swap(&second_hop);
+        let route_loss = (shib - second_hop.amount_out) / second_hop.amount_out * 100.0;
+
+        let pool_map = HashMap::from([
+            ("0xusdc_weth".to_string(), usdc_weth),
+            ("0xweth_shib".to_string(), weth_shib),
+        ]);
+        let attacks = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
+        assert_eq!(attacks.len(), 1);
+        assert_eq!(attacks[0].victim_tx, second_hop);
+        assert!(route_loss > 0.0);
+        assert!((attacks[0].victim_loss_percentage - route_loss).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_unverified_simulations_are_reported_when_asked() {
+        let transactions: Vec<_> = sample_transactions()
+            .into_iter()
+            .filter(|tx| tx.block_number == 12366)
+            .collect();
+        let pool_map = HashMap::from([(
+            "0xpool_uniswap".to_string(),
+            Pool::new(800.0, 800000.0, "ETH".into(), "NEWTOKEN".into()),
+        )]);
+
+        let mut config = Config::default();
+        let outcome =
+            find_sandwich_attacks_by_simulation_with_config(&pool_map, &transactions, &config);
+        assert!(outcome.attacks.is_empty());
+        assert!(matches!(
+            outcome.skipped[0].1,
+            DetectorError::SimulationDiverged { .. }
+        ));
+
+        config.simulation.report_unverified = true;
+        let outcome =
+            find_sandwich_attacks_by_simulation_with_config(&pool_map, &transactions, &config);
+        assert!(outcome.skipped.is_empty());
+        assert_eq!(outcome.attacks.len(), 1);
+        assert!(outcome.attacks[0].simulation_unverified);
+        assert!(outcome.attacks[0].to_string().ends_with("(unverified)"));
+
+        // A looser tolerance accepts the same state as verified
+        config.simulation.reality_tolerance_pct = 1000.0;
+        let outcome =
+            find_sandwich_attacks_by_simulation_with_config(&pool_map, &transactions, &config);
+        assert!(!outcome.attacks[0].simulation_unverified);
+    }
+
+    #[test]
+    fn test_divergence_diagnostic_points_at_the_first_drifting_swap() {
+        let transactions = sample_block();
+        // Reserves of some other block, the front-run already comes out wrong
+        let pool_map = HashMap::from([(
+            "0xpool1".to_string(),
+            Pool::new(2000000.0, 50000000000.0, "USDC".into(), "SHIB".into()),
+        )]);
+        let outcome = find_sandwich_attacks_by_simulation_with_config(
+            &pool_map,
+            &transactions,
+            &Config::default(),
+        );
+        let DetectorError::SimulationDiverged {
+            tx_hash,
+            difference_pct,
+            diagnostic,
+        } = &outcome.skipped[0].1
+        else {
+            panic!("Expected a diverged simulation");
+        };
+        assert_eq!(tx_hash, "0xvictim001");
+        assert_eq!(
+            diagnostic.first_divergent_tx.as_deref(),
+            Some("0xsandwich1")
+        );
+        let hashes: Vec<_> = diagnostic.steps.iter().map(|step| &step.tx_hash).collect();
+        assert_eq!(hashes, ["0xsandwich1", "0xvictim001"]);
+        let victim_step = &diagnostic.steps[1];
+        assert!((victim_step.drift_pct.abs() - difference_pct).abs() < 1e-9);
+        assert!(victim_step.cumulative_drift_pct > victim_step.drift_pct.abs());
+        assert_eq!(victim_step.actual_amount_out, 248260656.0);
+    }
+
+    #[test]
+    fn test_fixed_point_swaps_match_get_amount_out() {
+        // 1000 WETH against 2M USDC, in raw units
+        let (weth_reserve, usdc_reserve) = (10u128.pow(21), 2 * 10u128.pow(12));
+        let swap = SwapTransaction {
+            token_in: "WETH".to_string(),
+            token_out: "USDC".to_string(),
+            amount_in: 10f64.powi(18),
+            ..sample_transactions()[0].clone()
+        };
+        let pool = Pool::new(
+            weth_reserve as f64,
+            usdc_reserve as f64,
+            "WETH".into(),
+            "USDC".into(),
+        )
+        .with_arithmetic(Arithmetic::FixedPoint);
+
+        let amount_in_with_fee = 10u128.pow(18) * 9970;
+        let expected =
+            amount_in_with_fee * usdc_reserve / (weth_reserve * 10_000 + amount_in_with_fee);
+        let simulation = pool.simulate_swap(&swap);
+        assert_eq!(simulation.tokens_received, expected as f64);
+        assert_eq!(
+            simulation.new_pool_state.token_b_reserve,
+            (usdc_reserve - expected) as f64
+        );
+        assert_eq!(simulation.new_pool_state.arithmetic, Arithmetic::FixedPoint);
+        let float = pool.with_arithmetic(Arithmetic::Float).simulate_swap(&swap);
+        assert!(float.tokens_received.fract() != 0.0);
+
+        // Selected for every pool through the config
+        let config = Config::from_toml_str("[simulation]\narithmetic = \"fixed_point\"\n").unwrap();
+        assert_eq!(config.simulation.arithmetic, Arithmetic::FixedPoint);
+        let transactions = sample_transactions();
+        let pool_map = sample_pool_map();
+        let fixed_point =
+            find_sandwich_attacks_by_simulation_with_config(&pool_map, &transactions, &config);
+        assert!(!fixed_point.attacks.is_empty());
+        assert_eq!(
+            fixed_point.attacks.len(),
+            find_sandwich_attacks_by_simulation(&pool_map, &transactions).len()
+        );
+    }
+
+    #[test]
+    fn test_counterfactual_victim_output_without_the_sandwich() {
+        let transactions = sample_block();
+        // The sample swaps were priced without a fee
+        let pool = sample_pool().with_fee_bps(0);
+        let pool_map = HashMap::from([("0xpool1".to_string(), pool.clone())]);
+        let attacks = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
+        let attack = &attacks[0];
+        let (front, victim, back) = (&attack.front_run_tx, &attack.victim_tx, &attack.back_run_tx);
+
+        let config = Config::default();
+        let counterfactual =
+            simulate_without_attacker(&pool_map, front, victim, back, &transactions, &config)
+                .unwrap();
+        assert_eq!(counterfactual.actual_amount_out, victim.amount_out);
+        assert_eq!(
+            counterfactual.counterfactual_amount_out,
+            pool.clone().swap(victim)
+        );
+        assert!(counterfactual.amount_lost() > 0.0);
+        assert!(counterfactual.counterfactual_price > counterfactual.actual_price);
+        assert_eq!(
+            counterfactual.loss_percentage,
+            attack.victim_loss_percentage
+        );
+        // The back-run comes after the victim and changes nothing for it
+        let mut front_only = config.clone();
+        front_only.simulation.counterfactual_removal = CounterfactualRemoval::FrontRun;
+        assert_eq!(
+            simulate_without_attacker(&pool_map, front, victim, back, &transactions, &front_only),
+            Ok(counterfactual)
+        );
+
+        let empty: HashMap<String, Pool> = HashMap::new();
+        assert!(matches!(
+            simulate_without_attacker(&empty, front, victim, back, &transactions, &config),
+            Err(DetectorError::UnknownPool(_))
+        ));
+    }
+
+    #[test]
+    fn test_counterfactual_removes_the_whole_attacker_bundle_by_default() {
+        let mut transactions = sample_block();
+        // The attacker also bought first, ahead of another victim
+        let mut earlier = transactions[0].clone();
+        earlier.tx_hash = "0xsandwich0".to_string();
+        earlier.tx_position_in_block = 0;
+        earlier.amount_in = 2000.0;
+        transactions.insert(0, earlier);
+        let (front, victim, back) = (&transactions[1], &transactions[2], &transactions[3]);
+        let pool = sample_pool().with_fee_bps(0);
+        let pool_map = HashMap::from([("0xpool1".to_string(), pool.clone())]);
+
+        let counterfactual = |removal: CounterfactualRemoval| {
+            let mut config = Config::default();
+            config.simulation.counterfactual_removal = removal;
+            return simulate_without_attacker(
+                &pool_map,
+                front,
+                victim,
+                back,
+                &transactions,
+                &config,
+            )
+            .unwrap()
+            .counterfactual_amount_out;
+        };
+        let bundle = counterfactual(CounterfactualRemoval::default());
+        assert_eq!(bundle, pool.clone().swap(victim));
+        assert_eq!(
+            counterfactual(CounterfactualRemoval::Legs),
+            counterfactual(CounterfactualRemoval::FrontRun)
+        );
+        assert!(counterfactual(CounterfactualRemoval::Legs) < bundle);
+
+        let config =
+            Config::from_toml_str("[simulation]\ncounterfactual_removal = \"legs\"\n").unwrap();
+        assert_eq!(
+            config.simulation.counterfactual_removal,
+            CounterfactualRemoval::Legs
+        );
+    }
+
+    #[test]
+    fn test_token_taxes_let_taxed_pools_pass_the_reality_check() {
+        // SHIB as a token taking 5% of every buy
+        let transactions: Vec<_> = sample_transactions()
+            .into_iter()
+            .filter(|tx| tx.block_number == 12360)
+            .map(|mut tx| {
+                if tx.token_out == "SHIB" {
+                    tx.amount_out *= 0.95;
+                } else {
+                    tx.amount_in *= 0.95;
+                }
+                tx
+            })
+            .collect();
+        let pool = sample_pool().with_fee_bps(0);
+        let pool_map = HashMap::from([("0xpool1".to_string(), pool)]);
+
+        let untaxed = find_sandwich_attacks_by_simulation_with_config(
+            &pool_map,
+            &transactions,
+            &Config::default(),
+        );
+        assert!(untaxed.attacks.is_empty());
+        assert!(matches!(
+            untaxed.skipped[0].1,
+            DetectorError::SimulationDiverged { .. }
+        ));
+
+        let config =
+            Config::from_toml_str("[simulation.token_taxes]\nSHIB = { buy_tax_pct = 5.0 }\n")
+                .unwrap();
+        assert_eq!(config.simulation.token_taxes.get("SHIB").sell_tax_pct, 0.0);
+        let taxed =
+            find_sandwich_attacks_by_simulation_with_config(&pool_map, &transactions, &config);
+        assert_eq!(taxed.attacks.len(), 1);
+        assert!(taxed.attacks[0].victim_loss_percentage > 0.0);
+    }
+
+    #[test]
+    fn test_attacks_only_profitable_before_the_bribe_are_flagged() {
+        // Without gas the round trip makes about $9.95
+        let transactions: Vec<_> = sample_transactions()
+            .into_iter()
+            .filter(|tx| tx.block_number == 12360)
+            .map(|tx| SwapTransaction {
+                gas_cost_usd: 0.0,
+                ..tx
+            })
+            .collect();
+        let pool = sample_pool().with_fee_bps(0);
+        let pool_map = HashMap::from([("0xpool1".to_string(), pool)]);
+        let with_bribe = |bribe: f64| {
+            let mut config = Config::default();
+            config.heuristics.builder_payments =
+                Some(HashMap::from([("0xsandwich2".to_string(), bribe)]));
+            return find_sandwich_attacks_by_simulation_with_config(
+                &pool_map,
+                &transactions,
+                &config,
+            )
+            .attacks
+            .remove(0);
+        };
+
+        let small = with_bribe(5.0);
+        assert_eq!(small.builder_payments_usd, 5.0);
+        assert!(small.attacker_profit_usd > 0.0);
+        assert!(!small.profitable_only_before_bribe);
+
+        let large = with_bribe(15.0);
+        assert!(large.attacker_profit_usd < 0.0);
+        assert!(large.profitable_only_before_bribe);
+    }
+
+    #[test]
+    fn test_cached_prefix_states_match_a_full_replay() {
+        let mut transactions = sample_block();
+        // Retail swaps around the sandwich
+        for position in 4..40 {
+            let mut tx = transactions[1].clone();
+            tx.tx_hash = format!("0xretail{}", position);
+            tx.from_address = format!("0xretail{}", position);
+            tx.tx_position_in_block = position;
+            tx.amount_in = 10.0 * position as f64;
+            transactions.push(tx);
+        }
+        let pool_map = sample_pool_map();
+        let config = Config::default();
+        let (replay, _) = route_replay(
+            &pool_map,
+            &transactions[0],
+            &transactions[1],
+            &transactions,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(replay.states.len(), transactions.len() + 1);
+
+        let full_replay = |position: u32, skipped: u32| {
+            let mut pools = replay.initial_pools.clone();
+            for route in &replay.routes {
+                let at = route[0].tx_position_in_block;
+                if at < position && at != skipped {
+                    swap_route(&mut pools, route, replay.taxes).unwrap();
+                }
+            }
+            return pools;
+        };
+        for position in [0, 2, 3, 20, 40] {
+            for skipped in [1, 10, u32::MAX] {
+                let cached = replay
+                    .pools_before(position, |tx| tx.tx_position_in_block != skipped)
+                    .unwrap();
+                assert_eq!(cached, full_replay(position, skipped));
+            }
+        }
+        assert_eq!(
+            find_sandwich_attacks_by_simulation(&pool_map, &transactions).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_victim_loss_bounds_cover_fee_and_drift_error() {
+        let transactions = sample_block();
+        let pool_map = sample_pool_map();
+        let with_fee_uncertainty = |fee_uncertainty_bps: u32| {
+            let mut config = Config::default();
+            config.simulation.fee_uncertainty_bps = fee_uncertainty_bps;
+            return find_sandwich_attacks_by_simulation_with_config(
+                &pool_map,
+                &transactions,
+                &config,
+            )
+            .attacks
+            .remove(0);
+        };
+
+        let attack = with_fee_uncertainty(5);
+        assert!(attack.victim_loss_percentage_low <= attack.victim_loss_percentage);
+        assert!(attack.victim_loss_percentage <= attack.victim_loss_percentage_high);
+        assert!(attack.victim_loss_percentage_low < attack.victim_loss_percentage_high);
+
+        // Less certain fees give a wider range
+        let wider = with_fee_uncertainty(30);
+        assert_eq!(wider.victim_loss_percentage, attack.victim_loss_percentage);
+        assert!(wider.victim_loss_percentage_low <= attack.victim_loss_percentage_low);
+        assert!(wider.victim_loss_percentage_high > attack.victim_loss_percentage_high);
+    }
+
+    #[test]
+    fn test_configured_decimals_normalize_raw_swaps() {
+        let transactions = sample_block();
+        let decimals = TokenDecimals::default().with_token("SHIB", 18);
+        let raw: Vec<_> = transactions
+            .iter()
+            .map(|tx| SwapTransaction {
+                amount_in: tx.amount_in * 10f64.powi(decimals.get(&tx.token_in).unwrap().into()),
+                amount_out: tx.amount_out * 10f64.powi(decimals.get(&tx.token_out).unwrap().into()),
+                ..tx.clone()
+            })
+            .collect();
+        let pool_map = sample_pool_map();
+        let config = Config {
+            decimals: Some(decimals),
+            ..Config::default()
+        };
+
+        let expected = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
+        let attacks =
+            find_sandwich_attacks_by_simulation_with_config(&pool_map, &raw, &config).attacks;
+        assert!(!expected.is_empty());
+        assert_eq!(attacks.len(), expected.len());
+        for (attack, expected) in attacks.iter().zip(&expected) {
+            assert!((attack.victim_tx.amount_in - expected.victim_tx.amount_in).abs() < 1e-6);
+            assert!((attack.victim_loss_percentage - expected.victim_loss_percentage).abs() < 1e-6);
+        }
+        // Raw amounts taken as whole tokens don't match the pool at all
+        assert!(find_sandwich_attacks_by_simulation(&pool_map, &raw).is_empty());
+
+        let scored = find_same_block_sandwiches_with_config(&raw, &config).attacks;
+        let expected = find_same_block_sandwiches(&transactions);
+        assert_eq!(scored.len(), expected.len());
+        for (attack, expected) in scored.iter().zip(&expected) {
+            assert!((attack.confidence_score - expected.confidence_score).abs() < 1e-6);
+        }
+
+        // Fixed-point math on whole tokens would truncate them to integers
+        let mut fixed_point = config.clone();
+        fixed_point.simulation.arithmetic = Arithmetic::FixedPoint;
+        let outcome =
+            find_sandwich_attacks_by_simulation_with_config(&pool_map, &raw, &fixed_point);
+        assert!(outcome.attacks.is_empty());
+        assert!(matches!(
+            outcome.skipped[0].1,
+            DetectorError::InvalidConfig(_)
+        ));
+        assert!(Config::from_toml_str(
+            "[simulation]\narithmetic = \"fixed_point\"\n\n[decimals]\nSHIB = 18\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_fixed_point_keeps_raw_amounts_past_f64_precision() {
+        let (weth_reserve, dai_reserve) = (
+            U256::from(10u128.pow(21)),
+            U256::from(2 * 10u128.pow(24) + 7),
+        );
+        let raw_amount_in = U256::from(10u128.pow(18) + 1);
+        assert_eq!(raw_amount_in.as_f64(), 10f64.powi(18));
+        let swap = SwapTransaction {
+            token_in: "WETH".to_string(),
+            token_out: "DAI".to_string(),
+            amount_in: raw_amount_in.as_f64(),
+            raw_amount_in: Some(raw_amount_in),
+            ..sample_transactions()[0].clone()
+        };
+        let pool = Pool::from_raw_reserves(weth_reserve, dai_reserve, "WETH".into(), "DAI".into())
+            .with_arithmetic(Arithmetic::FixedPoint);
+
+        // The new reserves stay exact for the next swap
+        let first = pool.simulate_swap(&swap);
+        let first_out = get_amount_out(raw_amount_in, weth_reserve, dai_reserve, 30);
+        assert_eq!(first.tokens_received, first_out.as_f64());
+        let (weth_after, dai_after) = (weth_reserve + raw_amount_in, dai_reserve - first_out);
+        assert_eq!(first.new_pool_state.raw_token_a_reserve, Some(weth_after));
+        assert_eq!(first.new_pool_state.raw_token_b_reserve, Some(dai_after));
+
+        let second = first.new_pool_state.simulate_swap(&swap);
+        let on_chain = get_amount_out(raw_amount_in, weth_after, dai_after, 30);
+        assert_eq!(second.tokens_received, on_chain.as_f64());
+        let from_f64 = get_amount_out(
+            raw_amount_in.as_f64().as_u256(),
+            weth_after.as_f64().as_u256(),
+            dai_after.as_f64().as_u256(),
+            30,
+        );
+        assert_ne!(from_f64, on_chain);
+
+        // A resized swap no longer matches its raw amount and is rounded
+        let resized = SwapTransaction {
+            amount_in: 2e18,
+            ..swap.clone()
+        };
+        let simulation = pool.simulate_swap(&resized);
+        let expected = get_amount_out(
+            U256::from(2 * 10u128.pow(18)),
+            weth_reserve,
+            dai_reserve,
+            30,
+        );
+        assert_eq!(simulation.tokens_received, expected.as_f64());
+    }
 }
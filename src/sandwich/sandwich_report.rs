@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use super::classification::Classification;
+use super::same_block_heuristics::{ConfidenceFlags, SandwichAttackByHeuristics};
+use super::same_block_sim::SandwichAttackBySimulation;
+use super::transactions::SwapTransaction;
+
+/// One sandwich with the evidence of every detector that found it, keyed by
+/// `attack_id`. Fields of a detector that didn't find it are `None`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SandwichReport {
+    pub attack_id: String,
+    pub chain_id: u64,
+    pub front_run_tx: SwapTransaction,
+    pub victim_tx: SwapTransaction,
+    pub back_run_tx: SwapTransaction,
+    pub classification: Classification,
+    pub confidence_score: Option<f32>,
+    pub confidence_flags: Option<ConfidenceFlags>,
+    pub victim_loss_percentage: Option<f64>,
+    /// Attacker profit of the simulated round trip, less gas.
+    #[serde(default)]
+    pub simulated_profit_usd: Option<f64>,
+}
+
+impl SandwichReport {
+    pub fn found_by_heuristics(&self) -> bool {
+        return self.confidence_score.is_some();
+    }
+
+    pub fn found_by_simulation(&self) -> bool {
+        return self.victim_loss_percentage.is_some();
+    }
+
+    /// Attacker profit as estimated by the heuristics.
+    pub fn profit_usd(&self) -> Option<f64> {
+        return self
+            .confidence_flags
+            .as_ref()
+            .map(|flags| flags.total_profit_usd);
+    }
+
+    /// USD lost by the victim according to the simulation.
+    pub fn victim_loss_usd(&self) -> Option<f64> {
+        return self
+            .victim_loss_percentage
+            .map(|percentage| self.victim_tx.usd_value_in * percentage / 100.0);
+    }
+
+    /// Take the evidence `other` has and this report lacks. Both must
+    /// describe the same attack.
+    pub fn merge(&mut self, other: SandwichReport) {
+        debug_assert_eq!(self.attack_id, other.attack_id);
+        if self.confidence_score.is_none() {
+            self.confidence_score = other.confidence_score;
+            self.confidence_flags = other.confidence_flags;
+        }
+        if self.victim_loss_percentage.is_none() {
+            self.victim_loss_percentage = other.victim_loss_percentage;
+            self.simulated_profit_usd = other.simulated_profit_usd;
+        }
+    }
+}
+
+impl From<SandwichAttackByHeuristics> for SandwichReport {
+    fn from(attack: SandwichAttackByHeuristics) -> Self {
+        return Self {
+            attack_id: attack.attack_id(),
+            chain_id: attack.chain_id,
+            front_run_tx: attack.front_run_tx,
+            victim_tx: attack.victim_tx,
+            back_run_tx: attack.back_run_tx,
+            classification: attack.classification,
+            confidence_score: Some(attack.confidence_score),
+            confidence_flags: Some(attack.confidence_flags),
+            victim_loss_percentage: None,
+            simulated_profit_usd: None,
+        };
+    }
+}
+
+impl From<SandwichAttackBySimulation> for SandwichReport {
+    fn from(attack: SandwichAttackBySimulation) -> Self {
+        return Self {
+            attack_id: attack.attack_id(),
+            chain_id: attack.chain_id,
+            front_run_tx: attack.front_run_tx,
+            victim_tx: attack.victim_tx,
+            back_run_tx: attack.back_run_tx,
+            classification: attack.classification,
+            confidence_score: None,
+            confidence_flags: None,
+            victim_loss_percentage: Some(attack.victim_loss_percentage),
+            simulated_profit_usd: Some(attack.attacker_profit_usd),
+        };
+    }
+}
+
+/// Merge the results of both detectors into one report per attack, ordered
+/// by `attack_id`.
+pub fn merge_reports(
+    heuristics: Vec<SandwichAttackByHeuristics>,
+    simulation: Vec<SandwichAttackBySimulation>,
+) -> Vec<SandwichReport> {
+    let mut reports: BTreeMap<String, SandwichReport> = BTreeMap::new();
+    let all = heuristics
+        .into_iter()
+        .map(SandwichReport::from)
+        .chain(simulation.into_iter().map(SandwichReport::from));
+    for report in all {
+        match reports.get_mut(&report.attack_id) {
+            Some(existing) => existing.merge(report),
+            None => {
+                reports.insert(report.attack_id.clone(), report);
+            }
+        }
+    }
+    return reports.into_values().collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+
+    #[test]
+    fn test_merge_reports_combines_evidence() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let pool_map = crate::test_support::sample_pool_map();
+        let heuristics = find_same_block_sandwiches(&transactions);
+        let simulation = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
+
+        let reports = merge_reports(heuristics.clone(), simulation.clone());
+        let mut ids: Vec<String> = heuristics
+            .iter()
+            .map(|attack| attack.attack_id())
+            .chain(simulation.iter().map(|attack| attack.attack_id()))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(
+            reports
+                .iter()
+                .map(|report| report.attack_id.clone())
+                .collect::<Vec<_>>(),
+            ids
+        );
+
+        let both = reports
+            .iter()
+            .find(|report| report.found_by_heuristics() && report.found_by_simulation())
+            .expect("No attack found by both detectors");
+        assert!(both.profit_usd().is_some());
+        assert!(both.victim_loss_usd().unwrap() > 0.0);
+        let simulated = simulation
+            .iter()
+            .find(|attack| attack.attack_id() == both.attack_id)
+            .unwrap();
+        assert_eq!(
+            both.simulated_profit_usd,
+            Some(simulated.attacker_profit_usd)
+        );
+        assert!(reports
+            .iter()
+            .any(|report| report.found_by_heuristics() && !report.found_by_simulation()));
+    }
+}
@@ -0,0 +1,703 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::same_block_heuristics::{ordering_fees, ConfidenceFlags, SandwichAttackByHeuristics};
+use super::transactions::SwapTransaction;
+use crate::config::ConfidenceWeights;
+
+/// Gas z-score at which the front-run's fee counts as fully anomalous.
+pub const GAS_ZSCORE_SATURATION: f32 = 2.0;
+
+/// The front-run's gas z-score scaled to 0 to 1.
+fn gas_zscore_factor(flags: &ConfidenceFlags) -> f32 {
+    return (flags.front_gas_zscore / GAS_ZSCORE_SATURATION).clamp(0.0, 1.0);
+}
+
+/// Turns the evidence of a heuristic candidate into a confidence score
+/// between 0 and 1. Candidate generation and simulation don't depend on it,
+/// so a scorer only has to rank the candidates it is given.
+pub trait ConfidenceScorer: Send + Sync + fmt::Debug {
+    fn score(
+        &self,
+        front: &SwapTransaction,
+        victim: &SwapTransaction,
+        back: &SwapTransaction,
+        flags: &ConfidenceFlags,
+    ) -> f32;
+}
+
+/// The built-in scorer: the weights of the flags that hold, capped at 1.0.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AdditiveScorer {
+    pub weights: ConfidenceWeights,
+}
+
+impl AdditiveScorer {
+    pub fn new(weights: ConfidenceWeights) -> Self {
+        return Self { weights };
+    }
+}
+
+impl ConfidenceScorer for AdditiveScorer {
+    fn score(
+        &self,
+        _front: &SwapTransaction,
+        _victim: &SwapTransaction,
+        _back: &SwapTransaction,
+        flags: &ConfidenceFlags,
+    ) -> f32 {
+        return additive_confidence(flags, &self.weights);
+    }
+}
+
+/// How much more likely a flag is to hold (`present`) or not (`absent`) on
+/// an attack than on a benign candidate.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LikelihoodRatio {
+    pub present: f32,
+    pub absent: f32,
+}
+
+impl LikelihoodRatio {
+    pub fn new(present: f32, absent: f32) -> Self {
+        return Self { present, absent };
+    }
+
+    /// Log ratio of a graded flag, `absent` at 0 and `present` at 1,
+    /// interpolated in log space.
+    fn log_ratio_at(&self, strength: f32) -> f64 {
+        let strength = f64::from(strength.clamp(0.0, 1.0));
+        return strength * f64::from(self.present).ln()
+            + (1.0 - strength) * f64::from(self.absent).ln();
+    }
+}
+
+/// Evidence strengths of the `BayesianScorer`, one likelihood ratio per flag.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LikelihoodRatios {
+    /// Share of candidates that are attacks before any evidence. Default `0.3`.
+    pub prior: f32,
+    /// Default `3.0` / `0.5`.
+    pub higher_front_gas_price: LikelihoodRatio,
+    /// Default `1.5` / `0.8`.
+    pub lower_back_gas_price: LikelihoodRatio,
+    /// Default `2.0` / `0.7`.
+    pub front_is_contract: LikelihoodRatio,
+    /// Default `2.0` / `0.7`.
+    pub back_is_contract: LikelihoodRatio,
+    /// Default `4.0` / `0.4`.
+    pub is_profitable: LikelihoodRatio,
+    /// Default `2.0` / `0.8`.
+    pub is_proportional: LikelihoodRatio,
+    /// Applies to any victim price impact. Default `2.0` / `0.9`.
+    pub price_impact: LikelihoodRatio,
+    /// Most bots aren't labeled, so a miss says little. Default `10.0` / `1.0`.
+    pub is_known_bot: LikelihoodRatio,
+    /// Default `3.0` / `1.0`.
+    pub likely_private_bundle: LikelihoodRatio,
+    /// Default `8.0` / `1.0`.
+    pub uses_flashloan: LikelihoodRatio,
+    /// Interpolated in log space by the repeat offender factor, a one-off
+    /// attacker gets `absent`. Default `5.0` / `1.0`.
+    pub attacker_repeat_offender: LikelihoodRatio,
+    /// Default `0.1` / `1.0`.
+    pub same_entity: LikelihoodRatio,
+    /// `present` at a swap size factor of 1, `absent` at -1, interpolated
+    /// in log space. Default `3.0` / `0.3`.
+    pub swap_size: LikelihoodRatio,
+    /// Interpolated like `attacker_repeat_offender`. Default `4.0` / `1.0`.
+    pub bot_activity: LikelihoodRatio,
+    /// Default `10.0` / `1.0`.
+    pub paid_builder: LikelihoodRatio,
+    /// Interpolated by the gas z-score factor like `attacker_repeat_offender`.
+    /// Default `1.0` / `1.0`, opt-in like its additive weight.
+    pub front_gas_zscore: LikelihoodRatio,
+}
+
+impl Default for LikelihoodRatios {
+    fn default() -> Self {
+        Self {
+            prior: 0.3,
+            higher_front_gas_price: LikelihoodRatio::new(3.0, 0.5),
+            lower_back_gas_price: LikelihoodRatio::new(1.5, 0.8),
+            front_is_contract: LikelihoodRatio::new(2.0, 0.7),
+            back_is_contract: LikelihoodRatio::new(2.0, 0.7),
+            is_profitable: LikelihoodRatio::new(4.0, 0.4),
+            is_proportional: LikelihoodRatio::new(2.0, 0.8),
+            price_impact: LikelihoodRatio::new(2.0, 0.9),
+            is_known_bot: LikelihoodRatio::new(10.0, 1.0),
+            likely_private_bundle: LikelihoodRatio::new(3.0, 1.0),
+            uses_flashloan: LikelihoodRatio::new(8.0, 1.0),
+            attacker_repeat_offender: LikelihoodRatio::new(5.0, 1.0),
+            same_entity: LikelihoodRatio::new(0.1, 1.0),
+            swap_size: LikelihoodRatio::new(3.0, 0.3),
+            bot_activity: LikelihoodRatio::new(4.0, 1.0),
+            paid_builder: LikelihoodRatio::new(10.0, 1.0),
+            front_gas_zscore: LikelihoodRatio::new(1.0, 1.0),
+        }
+    }
+}
+
+/// Combines the flags with Bayes' rule: the prior odds are multiplied by
+/// the likelihood ratio of every flag, assuming the flags are independent.
+/// Unlike the additive scorer it never saturates, so strong evidence keeps
+/// separating candidates, and a missing flag can lower the score.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BayesianScorer {
+    pub ratios: LikelihoodRatios,
+}
+
+impl BayesianScorer {
+    pub fn new(ratios: LikelihoodRatios) -> Self {
+        return Self { ratios };
+    }
+
+    /// Posterior probability of an attack given `flags`.
+    pub fn posterior(&self, flags: &ConfidenceFlags) -> f32 {
+        let ratios = &self.ratios;
+        let evidence = [
+            (flags.higher_front_gas_price, ratios.higher_front_gas_price),
+            (flags.lower_back_gas_price, ratios.lower_back_gas_price),
+            (flags.front_is_contract, ratios.front_is_contract),
+            (flags.back_is_contract, ratios.back_is_contract),
+            (flags.is_profitable, ratios.is_profitable),
+            (flags.is_proportional, ratios.is_proportional),
+            (flags.price_impact_rate > 0.0, ratios.price_impact),
+            (flags.is_known_bot, ratios.is_known_bot),
+            (flags.likely_private_bundle, ratios.likely_private_bundle),
+            (flags.uses_flashloan, ratios.uses_flashloan),
+            (flags.same_entity, ratios.same_entity),
+            (flags.paid_builder, ratios.paid_builder),
+        ];
+
+        // Summed in log space so many strong flags don't overflow
+        let prior = f64::from(ratios.prior.clamp(f32::EPSILON, 1.0 - f32::EPSILON));
+        let mut log_odds = (prior / (1.0 - prior)).ln();
+        for (holds, ratio) in evidence {
+            let ratio = if holds { ratio.present } else { ratio.absent };
+            log_odds += f64::from(ratio).ln();
+        }
+        log_odds += ratios
+            .attacker_repeat_offender
+            .log_ratio_at(flags.attacker_repeat_offender);
+        log_odds += ratios.bot_activity.log_ratio_at(flags.bot_activity_factor);
+        log_odds += ratios
+            .front_gas_zscore
+            .log_ratio_at(gas_zscore_factor(flags));
+        let swap_size = f64::from(flags.swap_size_factor.clamp(-1.0, 1.0));
+        log_odds += match swap_size >= 0.0 {
+            true => swap_size * f64::from(ratios.swap_size.present).ln(),
+            false => -swap_size * f64::from(ratios.swap_size.absent).ln(),
+        };
+        return (1.0 / (1.0 + (-log_odds).exp())) as f32;
+    }
+}
+
+impl ConfidenceScorer for BayesianScorer {
+    fn score(
+        &self,
+        _front: &SwapTransaction,
+        _victim: &SwapTransaction,
+        _back: &SwapTransaction,
+        flags: &ConfidenceFlags,
+    ) -> f32 {
+        return self.posterior(flags);
+    }
+}
+
+/// A scorer set on `HeuristicsConfig::scorer`, compared by identity.
+#[derive(Clone)]
+pub struct Scorer(Arc<dyn ConfidenceScorer>);
+
+impl Scorer {
+    pub fn new<S: ConfidenceScorer + 'static>(scorer: S) -> Self {
+        return Self(Arc::new(scorer));
+    }
+}
+
+impl std::ops::Deref for Scorer {
+    type Target = dyn ConfidenceScorer;
+
+    fn deref(&self) -> &Self::Target {
+        return self.0.as_ref();
+    }
+}
+
+impl fmt::Debug for Scorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl PartialEq for Scorer {
+    fn eq(&self, other: &Self) -> bool {
+        return Arc::ptr_eq(&self.0, &other.0);
+    }
+}
+
+/// Recompute the confidence of stored attacks from their flags under new
+/// `weights`, without detecting them again. `min_confidence` isn't applied,
+/// filter afterwards (e.g. with `AttackSet::min_confidence`).
+pub fn rescore(attacks: &mut [SandwichAttackByHeuristics], weights: &ConfidenceWeights) {
+    for attack in attacks {
+        attack.confidence_score = additive_confidence(&attack.confidence_flags, weights);
+    }
+}
+
+/// `rescore` with any scorer, e.g. a `BayesianScorer` or a trained model.
+pub fn rescore_with<S: ConfidenceScorer + ?Sized>(
+    attacks: &mut [SandwichAttackByHeuristics],
+    scorer: &S,
+) {
+    for attack in attacks {
+        attack.confidence_score = scorer.score(
+            &attack.front_run_tx,
+            &attack.victim_tx,
+            &attack.back_run_tx,
+            &attack.confidence_flags,
+        );
+    }
+}
+
+/// One term of the additive confidence, e.g. "front-runner paid 3.2x
+/// victim's gas: +0.20".
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ConfidenceFactor {
+    /// The `ConfidenceFlags` field (or `base`, or the rule name).
+    pub name: String,
+    pub weight: f32,
+    pub description: String,
+}
+
+impl fmt::Display for ConfidenceFactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:+.2}", self.description, self.weight)
+    }
+}
+
+/// The terms `additive_confidence` sums for a candidate, largest first.
+/// They can add up past the 0 to 1 range the score is clamped to. Rules add
+/// no weight.
+pub(crate) fn explain_confidence(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+    evidence: &ConfidenceFlags,
+    weights: &ConfidenceWeights,
+) -> Vec<ConfidenceFactor> {
+    let [front_fee, victim_fee, back_fee] = ordering_fees(front, victim, back);
+    let mut factors = Vec::new();
+    let mut add = |name: &str, weight: f32, description: String| {
+        factors.push(ConfidenceFactor {
+            name: name.to_string(),
+            weight,
+            description,
+        });
+    };
+
+    add(
+        "base",
+        weights.base,
+        "matches the sandwich pattern".to_string(),
+    );
+
+    if evidence.higher_front_gas_price {
+        let description = match victim_fee {
+            0 => "front-runner outbid victim's zero gas".to_string(),
+            _ => format!(
+                "front-runner paid {:.1}x victim's gas",
+                front_fee as f64 / victim_fee as f64
+            ),
+        };
+        add(
+            "higher_front_gas_price",
+            weights.higher_front_gas_price,
+            description,
+        );
+    }
+
+    if weights.front_gas_zscore != 0.0 && gas_zscore_factor(evidence) > 0.0 {
+        add(
+            "front_gas_zscore",
+            gas_zscore_factor(evidence) * weights.front_gas_zscore,
+            format!(
+                "front-run's gas {:.1} standard deviations above the block's",
+                evidence.front_gas_zscore
+            ),
+        );
+    }
+
+    if evidence.lower_back_gas_price {
+        add(
+            "lower_back_gas_price",
+            weights.lower_back_gas_price,
+            format!(
+                "back-runner paid {:.1}x victim's gas",
+                back_fee as f64 / victim_fee as f64
+            ),
+        );
+    }
+
+    if evidence.front_is_contract {
+        add(
+            "front_is_contract",
+            weights.front_is_contract,
+            "front-run sent by a contract".to_string(),
+        );
+    }
+
+    if evidence.back_is_contract {
+        add(
+            "back_is_contract",
+            weights.back_is_contract,
+            "back-run sent by a contract".to_string(),
+        );
+    }
+
+    if evidence.is_profitable {
+        add(
+            "is_profitable",
+            weights.is_profitable,
+            format!(
+                "attacker made ${:.2} after costs",
+                evidence.total_profit_usd
+            ),
+        );
+    }
+
+    if evidence.is_proportional {
+        add(
+            "is_proportional",
+            weights.is_proportional,
+            format!(
+                "front-run sized {:.0}% of victim's trade",
+                front.usd_value_in / victim.usd_value_in * 100.0
+            ),
+        );
+    }
+
+    if evidence.price_impact_rate > 0.0 {
+        add(
+            "price_impact_rate",
+            evidence.price_impact_rate.min(weights.max_price_impact)
+                * evidence.price_impact_depth_scale,
+            format!(
+                "victim got a {:.1}% worse rate than the front-runner{}",
+                evidence.price_impact_rate * 100.0,
+                match evidence.price_impact_depth_scale == 1.0 {
+                    true => String::new(),
+                    false => format!(
+                        ", weighed x{:.2} for pool depth",
+                        evidence.price_impact_depth_scale
+                    ),
+                }
+            ),
+        );
+    }
+
+    if evidence.is_known_bot {
+        add(
+            "is_known_bot",
+            weights.is_known_bot,
+            "attacker is a known MEV actor".to_string(),
+        );
+    }
+
+    if evidence.likely_private_bundle {
+        add(
+            "likely_private_bundle",
+            weights.likely_private_bundle,
+            "likely submitted as a private bundle".to_string(),
+        );
+    }
+
+    if evidence.uses_flashloan {
+        add(
+            "uses_flashloan",
+            weights.uses_flashloan,
+            "front-run funded by a flashloan".to_string(),
+        );
+    }
+
+    if evidence.attacker_repeat_offender > 0.0 {
+        add(
+            "attacker_repeat_offender",
+            evidence.attacker_repeat_offender * weights.attacker_repeat_offender,
+            format!(
+                "attacker is a repeat offender (factor {:.2})",
+                evidence.attacker_repeat_offender
+            ),
+        );
+    }
+
+    if evidence.paid_builder {
+        add(
+            "paid_builder",
+            weights.paid_builder,
+            "attacker paid the block builder directly".to_string(),
+        );
+    }
+
+    if evidence.same_entity {
+        add(
+            "same_entity",
+            weights.same_entity,
+            "attacker and victim look like the same entity".to_string(),
+        );
+    }
+
+    if evidence.swap_size_factor != 0.0 {
+        let description = match evidence.swap_size_factor > 0.0 {
+            true => "front-run sized near the optimal sandwich",
+            false => "front-run implausibly small or large for the pool",
+        };
+        add(
+            "swap_size_factor",
+            evidence.swap_size_factor * weights.swap_size,
+            description.to_string(),
+        );
+    }
+
+    if evidence.bot_activity_factor > 0.0 {
+        add(
+            "bot_activity_factor",
+            evidence.bot_activity_factor * weights.bot_activity,
+            format!(
+                "attacker transacts like a bot (factor {:.2})",
+                evidence.bot_activity_factor
+            ),
+        );
+    }
+
+    for rule in &evidence.custom_flags {
+        add(rule, 0.0, format!("matched rule {}", rule));
+    }
+
+    factors.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+    return factors;
+}
+
+/// Score the sandwich evidence given the confidence flags.
+///
+/// TODO: The weights are picked by hand, fitting them on labeled data
+/// (like `PlattScaling` does for the final score) would be better.
+pub(crate) fn additive_confidence(evidence: &ConfidenceFlags, weights: &ConfidenceWeights) -> f32 {
+    let mut confidence = weights.base;
+
+    if evidence.higher_front_gas_price {
+        confidence += weights.higher_front_gas_price;
+    }
+
+    if evidence.lower_back_gas_price {
+        confidence += weights.lower_back_gas_price;
+    }
+
+    if evidence.front_is_contract {
+        confidence += weights.front_is_contract;
+    }
+
+    if evidence.back_is_contract {
+        confidence += weights.back_is_contract;
+    }
+
+    if evidence.is_profitable {
+        confidence += weights.is_profitable;
+    }
+
+    if evidence.is_proportional {
+        confidence += weights.is_proportional;
+    }
+
+    if evidence.price_impact_rate > 0.0 {
+        confidence += evidence.price_impact_rate.min(weights.max_price_impact)
+            * evidence.price_impact_depth_scale;
+    }
+
+    if evidence.is_known_bot {
+        confidence += weights.is_known_bot;
+    }
+
+    if evidence.likely_private_bundle {
+        confidence += weights.likely_private_bundle;
+    }
+
+    if evidence.uses_flashloan {
+        confidence += weights.uses_flashloan;
+    }
+
+    confidence += evidence.attacker_repeat_offender * weights.attacker_repeat_offender;
+
+    if evidence.same_entity {
+        confidence += weights.same_entity;
+    }
+
+    if evidence.paid_builder {
+        confidence += weights.paid_builder;
+    }
+
+    confidence += evidence.swap_size_factor * weights.swap_size;
+
+    confidence += evidence.bot_activity_factor * weights.bot_activity;
+
+    confidence += gas_zscore_factor(evidence) * weights.front_gas_zscore;
+
+    confidence.clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::builder::SandwichDetector;
+
+    /// Only trusts profitable candidates.
+    #[derive(Debug)]
+    struct ProfitOnly;
+
+    impl ConfidenceScorer for ProfitOnly {
+        fn score(
+            &self,
+            _front: &SwapTransaction,
+            _victim: &SwapTransaction,
+            _back: &SwapTransaction,
+            flags: &ConfidenceFlags,
+        ) -> f32 {
+            return if flags.is_profitable { 1.0 } else { 0.0 };
+        }
+    }
+
+    #[test]
+    fn test_custom_scorer_replaces_additive_weights() {
+        let transactions = crate::ingest::csv::sample_transactions();
+
+        let additive = SandwichDetector::builder()
+            .scorer(AdditiveScorer::default())
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+        let default = SandwichDetector::builder()
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+        assert_eq!(additive.len(), default.len());
+
+        let custom = SandwichDetector::builder()
+            .scorer(ProfitOnly)
+            .min_confidence(0.5)
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+        assert!(!custom.is_empty());
+        assert!(custom
+            .iter()
+            .all(|attack| attack.confidence_score == 1.0 && attack.confidence_flags.is_profitable));
+    }
+
+    #[test]
+    fn test_explanation_adds_up_to_additive_score() {
+        let attacks = crate::sandwich::same_block_heuristics::find_same_block_sandwiches(
+            &crate::ingest::csv::sample_transactions(),
+        );
+        for attack in &attacks {
+            let factors = attack.explain();
+            assert!(factors
+                .windows(2)
+                .all(|pair| pair[0].weight >= pair[1].weight));
+            let total: f32 = factors.iter().map(|factor| factor.weight).sum();
+            assert!((total.clamp(0.0, 1.0) - attack.confidence_score).abs() < 1e-6);
+        }
+
+        let attack = attacks
+            .iter()
+            .find(|attack| attack.confidence_flags.higher_front_gas_price)
+            .unwrap();
+        let gas = attack
+            .explain()
+            .into_iter()
+            .find(|factor| factor.name == "higher_front_gas_price")
+            .unwrap();
+        let ratio = attack.front_run_tx.gas_price as f64 / attack.victim_tx.gas_price as f64;
+        assert_eq!(
+            gas.to_string(),
+            format!("front-runner paid {:.1}x victim's gas: +0.20", ratio)
+        );
+    }
+
+    #[test]
+    fn test_rescore_matches_detecting_again() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let mut attacks = SandwichDetector::builder()
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+        let weights = ConfidenceWeights {
+            is_profitable: 0.5,
+            front_is_contract: 0.0,
+            ..ConfidenceWeights::default()
+        };
+        let detected_again = SandwichDetector::builder()
+            .weights(weights.clone())
+            .build()
+            .find_sandwiches(&transactions)
+            .attacks;
+
+        rescore(&mut attacks, &weights);
+        assert_eq!(attacks, detected_again);
+
+        rescore_with(&mut attacks, &ProfitOnly);
+        assert!(attacks.iter().all(|attack| {
+            attack.confidence_score == 1.0 || !attack.confidence_flags.is_profitable
+        }));
+    }
+
+    #[test]
+    fn test_bayesian_scorer_separates_saturated_candidates() {
+        let strong = ConfidenceFlags {
+            higher_front_gas_price: true,
+            lower_back_gas_price: true,
+            front_is_contract: true,
+            back_is_contract: true,
+            is_profitable: true,
+            is_proportional: true,
+            price_impact_rate: 0.05,
+            price_impact_depth_scale: 1.0,
+            total_profit_usd: 100.0,
+            is_known_bot: false,
+            likely_private_bundle: false,
+            uses_flashloan: false,
+            attacker_repeat_offender: 0.0,
+            same_entity: false,
+            swap_size_factor: 0.0,
+            bot_activity_factor: 0.0,
+            paid_builder: false,
+            profitable_only_before_bribe: false,
+            front_gas_zscore: 0.0,
+            custom_flags: Vec::new(),
+        };
+        let weaker = ConfidenceFlags {
+            lower_back_gas_price: false,
+            ..strong.clone()
+        };
+        let weights = ConfidenceWeights::default();
+        assert_eq!(additive_confidence(&strong, &weights), 1.0);
+        assert_eq!(additive_confidence(&weaker, &weights), 1.0);
+
+        let scorer = BayesianScorer::default();
+        assert!(scorer.posterior(&strong) > scorer.posterior(&weaker));
+        assert!(scorer.posterior(&strong) < 1.0);
+
+        // Without any evidence the posterior falls below the prior
+        let none = ConfidenceFlags {
+            higher_front_gas_price: false,
+            lower_back_gas_price: false,
+            front_is_contract: false,
+            back_is_contract: false,
+            is_profitable: false,
+            is_proportional: false,
+            price_impact_rate: 0.0,
+            ..strong
+        };
+        assert!(scorer.posterior(&none) < scorer.ratios.prior);
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::reputation::attacker_reputations;
+use super::same_block_heuristics::SandwichAttackByHeuristics;
+use super::same_block_sim::SandwichAttackBySimulation;
+use crate::config::SeverityConfig;
+
+/// Coarse bucket for alerting and triage, from the victim's loss, the
+/// confidence and how often the attacker strikes.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    #[default]
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    /// Bucket of the loss, capped at `Medium` below `SeverityConfig::confident`
+    /// and raised one level for recurring attackers.
+    pub fn assess(
+        loss_usd: f64,
+        confidence: f32,
+        attacker_attacks: u64,
+        config: &SeverityConfig,
+    ) -> Self {
+        let mut severity = if loss_usd >= config.critical_loss_usd {
+            Severity::Critical
+        } else if loss_usd >= config.high_loss_usd {
+            Severity::High
+        } else if loss_usd >= config.medium_loss_usd {
+            Severity::Medium
+        } else {
+            Severity::Low
+        };
+        if confidence < config.confident {
+            severity = severity.min(Severity::Medium);
+        }
+        if attacker_attacks >= config.recurring_attacks {
+            severity = severity.raised();
+        }
+        return severity;
+    }
+
+    fn raised(self) -> Self {
+        return match self {
+            Severity::Low => Severity::Medium,
+            Severity::Medium => Severity::High,
+            Severity::High | Severity::Critical => Severity::Critical,
+        };
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+/// Set the severity of every attack from the heuristics' estimate of its
+/// victim's loss (see `SandwichAttackByHeuristics::estimated_victim_loss_usd`),
+/// counting recurrence over `attacks`.
+pub fn assign_severity(attacks: &mut [SandwichAttackByHeuristics], config: &SeverityConfig) {
+    let reputations = attacker_reputations(attacks);
+    for attack in attacks {
+        let appearances = reputations[&attack.front_run_tx.from_address.to_lowercase()].appearances;
+        attack.severity = Severity::assess(
+            attack.estimated_victim_loss_usd(),
+            attack.confidence_score,
+            appearances,
+            config,
+        );
+    }
+}
+
+/// `assign_severity` for simulated attacks, from the simulated victim loss.
+/// A simulation counts as confident unless it couldn't be verified against
+/// the actual victim output.
+pub fn assign_simulated_severity(
+    attacks: &mut [SandwichAttackBySimulation],
+    config: &SeverityConfig,
+) {
+    let mut appearances: HashMap<String, u64> = HashMap::new();
+    for attack in attacks.iter() {
+        *appearances
+            .entry(attack.front_run_tx.from_address.to_lowercase())
+            .or_default() += 1;
+    }
+    for attack in attacks {
+        let confidence = if attack.simulation_unverified {
+            0.0
+        } else {
+            1.0
+        };
+        attack.severity = Severity::assess(
+            attack.victim_loss_usd(),
+            confidence,
+            appearances[&attack.front_run_tx.from_address.to_lowercase()],
+            config,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches_with_config;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+
+    #[test]
+    fn test_severity_from_loss_confidence_and_recurrence() {
+        let config = SeverityConfig::default();
+        assert_eq!(Severity::assess(50.0, 0.9, 1, &config), Severity::Low);
+        assert_eq!(Severity::assess(500.0, 0.9, 1, &config), Severity::Medium);
+        assert_eq!(Severity::assess(5_000.0, 0.9, 1, &config), Severity::High);
+        assert_eq!(
+            Severity::assess(50_000.0, 0.9, 1, &config),
+            Severity::Critical
+        );
+        assert_eq!(
+            Severity::assess(50_000.0, 0.3, 1, &config),
+            Severity::Medium
+        );
+        assert_eq!(
+            Severity::assess(5_000.0, 0.9, 5, &config),
+            Severity::Critical
+        );
+        assert_eq!(Severity::assess(50.0, 0.3, 5, &config), Severity::Medium);
+        assert!(Severity::Critical > Severity::High);
+
+        let transactions = crate::ingest::csv::sample_transactions();
+        let mut config = Config::default();
+        config.severity.medium_loss_usd = f64::MIN;
+        let attacks = find_same_block_sandwiches_with_config(&transactions, &config).attacks;
+        assert!(!attacks.is_empty());
+        for attack in &attacks {
+            assert!(attack.severity >= Severity::Medium);
+        }
+
+        config.severity = SeverityConfig {
+            medium_loss_usd: f64::MAX,
+            high_loss_usd: f64::MAX,
+            critical_loss_usd: f64::MAX,
+            recurring_attacks: 1,
+            ..SeverityConfig::default()
+        };
+        let attacks = find_same_block_sandwiches_with_config(&transactions, &config).attacks;
+        assert!(attacks
+            .iter()
+            .all(|attack| attack.severity == Severity::Medium));
+    }
+
+    #[test]
+    fn test_severity_follows_the_victim_loss() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let mut config = Config::default();
+        let attack =
+            find_same_block_sandwiches_with_config(&transactions, &config).attacks[0].clone();
+        let loss = attack.estimated_victim_loss_usd();
+        assert!(loss > 0.0);
+        config.severity.medium_loss_usd = loss;
+        config.severity.high_loss_usd = f64::MAX;
+
+        // A huge profit doesn't make the victim's loss any larger
+        let mut attacks = vec![attack.clone()];
+        attacks[0].confidence_flags.total_profit_usd = 1e12;
+        assign_severity(&mut attacks, &config.severity);
+        assert_eq!(attacks[0].severity, Severity::Medium);
+        attacks[0].confidence_flags.price_impact_rate /= 2.0;
+        assign_severity(&mut attacks, &config.severity);
+        assert_eq!(attacks[0].severity, Severity::Low);
+
+        // Simulated attacks are rated too, unverified ones capped at medium
+        let pool_map = crate::test_support::sample_pool_map();
+        let mut simulated = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
+        assert!(!simulated.is_empty());
+        config.severity = SeverityConfig {
+            critical_loss_usd: f64::MIN,
+            ..SeverityConfig::default()
+        };
+        assign_simulated_severity(&mut simulated, &config.severity);
+        assert!(simulated
+            .iter()
+            .all(|attack| attack.severity == Severity::Critical));
+        simulated[0].simulation_unverified = true;
+        assign_simulated_severity(&mut simulated, &config.severity);
+        assert_eq!(simulated[0].severity, Severity::Medium);
+    }
+}
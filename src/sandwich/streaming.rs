@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::detector::{Block, Detection, DetectionContext, Pipeline};
+use super::error::DetectorError;
+use super::transactions::BlockId;
+
+/// Blocks an attacker's history is kept for after its last attack, about a
+/// month of mainnet blocks.
+pub const DEFAULT_HISTORY_BLOCKS: u64 = 216_000;
+
+/// What a `StreamingDetector` knows about an attacker from finalized blocks.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AttackerHistory {
+    pub attacks: u64,
+    pub first_block: u64,
+    pub last_block: u64,
+}
+
+/// Runs a `Pipeline` over blocks as they arrive, e.g. from a node subscription.
+///
+/// Detections are held back until their block is `reorg_depth` blocks behind
+/// the chain head, so a block replaced by a reorg (pushed again with the same
+/// number) only ever emits the detections of its final version. Each block
+/// is detected with the earlier blocks of the window and the finalized
+/// attacks per attacker as its `DetectionContext`. An attacker is forgotten
+/// once its last attack is `history_blocks` behind the finalized blocks.
+pub struct StreamingDetector {
+    pipeline: Pipeline,
+    reorg_depth: u64,
+    history_blocks: u64,
+    heads: HashMap<u64, u64>,
+    pending: BTreeMap<BlockId, (Block, Vec<Detection>)>,
+    /// Per chain, keyed by lowercased address.
+    attackers: HashMap<u64, HashMap<String, AttackerHistory>>,
+}
+
+impl StreamingDetector {
+    pub fn new(pipeline: Pipeline, reorg_depth: u64) -> Self {
+        return Self {
+            pipeline,
+            reorg_depth,
+            history_blocks: DEFAULT_HISTORY_BLOCKS,
+            heads: HashMap::new(),
+            pending: BTreeMap::new(),
+            attackers: HashMap::new(),
+        };
+    }
+
+    pub fn with_history_blocks(mut self, history_blocks: u64) -> Self {
+        self.history_blocks = history_blocks;
+        return self;
+    }
+
+    /// Detect attacks in `block`, returning those of every block that is now
+    /// final. Pushing a block number again drops it and the later pending
+    /// blocks of its chain, they were on the orphaned branch.
+    pub fn push_block(&mut self, block: Block) -> Result<Vec<Detection>, DetectorError> {
+        let chain_id = block.id.chain_id;
+        let number = block.id.block_number;
+        if let Some(head) = self.heads.get(&chain_id) {
+            if number + self.reorg_depth <= *head {
+                return Err(DetectorError::ReorgBeyondWindow(block.id));
+            }
+            if number <= *head {
+                self.pending
+                    .retain(|id, _| id.chain_id != chain_id || id.block_number < number);
+            }
+        }
+
+        self.attackers.entry(chain_id).or_default();
+        let context = DetectionContext {
+            recent_blocks: self
+                .pending
+                .values()
+                .map(|(block, _)| block)
+                .filter(|pending| pending.id.chain_id == chain_id)
+                .collect(),
+            attackers: &self.attackers[&chain_id],
+        };
+        let detections = self.pipeline.detect_block_with_context(&block, &context);
+        self.pending.insert(block.id, (block, detections));
+        self.heads.insert(chain_id, number);
+
+        let finalized: Vec<BlockId> = self
+            .pending
+            .keys()
+            .filter(|id| id.chain_id == chain_id && id.block_number + self.reorg_depth <= number)
+            .copied()
+            .collect();
+        return Ok(self.finalize(finalized));
+    }
+
+    /// Finalize every pending block, e.g. at the end of a backfill.
+    pub fn flush(&mut self) -> Vec<Detection> {
+        let pending: Vec<BlockId> = self.pending.keys().copied().collect();
+        return self.finalize(pending);
+    }
+
+    /// Blocks still within the reorg window, oldest first per chain.
+    pub fn window(&self) -> impl Iterator<Item = &Block> {
+        self.pending.values().map(|(block, _)| block)
+    }
+
+    pub fn attacker_history(&self, chain_id: u64, address: &str) -> Option<&AttackerHistory> {
+        return self.attackers.get(&chain_id)?.get(&address.to_lowercase());
+    }
+
+    fn finalize(&mut self, block_ids: Vec<BlockId>) -> Vec<Detection> {
+        let mut finalized = Vec::new();
+        for block_id in block_ids {
+            let detections = match self.pending.remove(&block_id) {
+                Some((_, detections)) => detections,
+                None => continue,
+            };
+            let attackers = self.attackers.entry(block_id.chain_id).or_default();
+            for detection in &detections {
+                let attacker = detection.front_run_tx().from_address.to_lowercase();
+                attackers
+                    .entry(attacker)
+                    .and_modify(|history| {
+                        history.attacks += 1;
+                        history.last_block = block_id.block_number;
+                    })
+                    .or_insert(AttackerHistory {
+                        attacks: 1,
+                        first_block: block_id.block_number,
+                        last_block: block_id.block_number,
+                    });
+            }
+            attackers.retain(|_, history| {
+                history.last_block + self.history_blocks > block_id.block_number
+            });
+            finalized.extend(detections);
+        }
+        return finalized;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sandwich::detector::HeuristicsDetector;
+    use crate::sandwich::transactions::{stream_transactions_by_block, SwapTransaction};
+
+    #[test]
+    fn test_detections_wait_for_reorg_window() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let pipeline = || Pipeline::new().with_detector(HeuristicsDetector::default());
+        let expected = pipeline().run(&transactions);
+
+        let mut detector = StreamingDetector::new(pipeline(), 2);
+        let mut blocks: Vec<Block> = stream_transactions_by_block(transactions)
+            .map(Block::from)
+            .collect();
+        blocks.sort_by_key(|block| block.id);
+        let attacked = blocks
+            .iter()
+            .position(|block| !pipeline().detect_block(block).is_empty())
+            .unwrap();
+
+        let mut emitted = Vec::new();
+        for block in &blocks[..=attacked] {
+            emitted.extend(detector.push_block(block.clone()).unwrap());
+        }
+        // Not final yet, and replaced by an empty block in a reorg
+        assert!(emitted.is_empty());
+        let orphaned = blocks[attacked].clone();
+        let replacement = Block::new(orphaned.id, Vec::new());
+        detector.push_block(replacement).unwrap();
+        assert_eq!(detector.window().last().unwrap().id, orphaned.id);
+
+        for block in &blocks[attacked + 1..] {
+            emitted.extend(detector.push_block(block.clone()).unwrap());
+        }
+        emitted.extend(detector.flush());
+        let orphaned_detections = pipeline().detect_block(&orphaned).len();
+        assert_eq!(emitted.len(), expected.len() - orphaned_detections);
+
+        let attacker = &emitted[0].front_run_tx().from_address;
+        let history = detector.attacker_history(emitted[0].chain_id(), attacker);
+        assert!(history.unwrap().attacks >= 1);
+        assert_eq!(
+            detector.push_block(blocks[0].clone()),
+            Err(DetectorError::ReorgBeyondWindow(blocks[0].id))
+        );
+    }
+
+    #[test]
+    fn test_detection_counts_finalized_attacker_history() {
+        let attack = crate::test_support::sample_block();
+        let block_at = |block_number: u64| {
+            let transactions: Vec<SwapTransaction> = attack
+                .iter()
+                .map(|tx| SwapTransaction {
+                    tx_hash: format!("{}-{}", tx.tx_hash, block_number),
+                    block_number,
+                    ..tx.clone()
+                })
+                .collect();
+            Block::new(transactions[0].block_id(), transactions)
+        };
+        let mut config = Config::default();
+        config.heuristics.attacker_reputation = true;
+        let pipeline = Pipeline::new().with_detector(HeuristicsDetector::new(config));
+        let mut detector = StreamingDetector::new(pipeline, 0).with_history_blocks(2000);
+        let repeat_factor = |detections: Vec<Detection>| match &detections[..] {
+            [Detection::Heuristics(attack)] => attack.confidence_flags.attacker_repeat_offender,
+            _ => panic!("expected one heuristics detection"),
+        };
+
+        assert_eq!(
+            repeat_factor(detector.push_block(block_at(12360)).unwrap()),
+            0.0
+        );
+        assert_eq!(
+            repeat_factor(detector.push_block(block_at(13360)).unwrap()),
+            0.1
+        );
+        assert_eq!(
+            repeat_factor(detector.push_block(block_at(14360)).unwrap()),
+            0.2
+        );
+
+        // Forgotten once its last attack is out of the history
+        let attacker = attack[0].from_address.clone();
+        assert_eq!(
+            detector
+                .attacker_history(attack[0].chain_id, &attacker)
+                .unwrap()
+                .attacks,
+            3
+        );
+        let quiet = BlockId {
+            chain_id: attack[0].chain_id,
+            block_number: 16360,
+        };
+        detector.push_block(Block::new(quiet, Vec::new())).unwrap();
+        assert_eq!(
+            detector.attacker_history(attack[0].chain_id, &attacker),
+            None
+        );
+        assert_eq!(
+            repeat_factor(detector.push_block(block_at(16400)).unwrap()),
+            0.0
+        );
+    }
+}
@@ -1,30 +1,255 @@
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
 use super::transactions::SwapTransaction;
 
+/// Token equivalence groups for cross-token sandwich detection
+///
+/// TODO: Certainly there could be more equivalent tokens out there.
+pub const DEFAULT_EQUIVALENCE_GROUPS: &[(&str, &[&str])] = &[
+    // Stablecoins - all ~$1 USD
+    ("STABLECOINS", &["USDC", "USDT", "DAI", "FRAX", "BUSD"]),
+    // ETH variants
+    ("ETH_GROUP", &["ETH", "WETH", "stETH"]),
+    // Bitcoin variants
+    ("BTC_GROUP", &["WBTC", "renBTC", "sBTC"]),
+];
+
+pub(crate) static DEFAULT_EQUIVALENCE: LazyLock<TokenEquivalence> =
+    LazyLock::new(TokenEquivalence::default);
+
+/// Groups of economically equivalent tokens (e.g., USDC/USDT, ETH/WETH),
+/// keyed by group name. Tokens outside every group are only equivalent to themselves.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct TokenEquivalence {
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for TokenEquivalence {
+    /// The `DEFAULT_EQUIVALENCE_GROUPS`.
+    fn default() -> Self {
+        let groups = DEFAULT_EQUIVALENCE_GROUPS
+            .iter()
+            .map(|(name, tokens)| {
+                let tokens = tokens.iter().map(|token| token.to_string()).collect();
+                (name.to_string(), tokens)
+            })
+            .collect();
+        return Self { groups };
+    }
+}
+
+impl TokenEquivalence {
+    /// Check if two tokens are economically equivalent
+    pub fn are_equivalent(&self, token_a: &str, token_b: &str) -> bool {
+        self.group(token_a) == self.group(token_b)
+    }
+
+    /// Checks if the tokens in the swap transactions are reversed,
+    /// for example buying first and selling second.
+    pub fn are_reversed(&self, a: &SwapTransaction, b: &SwapTransaction) -> bool {
+        return self.are_equivalent(&a.token_in, &b.token_out)
+            && self.are_equivalent(&a.token_out, &b.token_in);
+    }
+
+    /// Everything else is its own group
+    fn group<'a>(&'a self, token: &'a str) -> &'a str {
+        return self
+            .groups
+            .iter()
+            .find(|(_, tokens)| tokens.iter().any(|member| member == token))
+            .map(|(name, _)| name.as_str())
+            .unwrap_or(token);
+    }
+}
+
+/// Decimals of well-known tokens, by symbol and by Ethereum mainnet address.
+pub const DEFAULT_TOKEN_DECIMALS: &[(&str, u8)] = &[
+    ("USDC", 6),
+    ("USDT", 6),
+    ("DAI", 18),
+    ("FRAX", 18),
+    ("BUSD", 18),
+    ("ETH", 18),
+    ("WETH", 18),
+    ("stETH", 18),
+    ("WBTC", 8),
+    ("renBTC", 8),
+    ("sBTC", 18),
+    ("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", 6),
+    ("0xdac17f958d2ee523a2206206994597c13d831ec7", 6),
+    ("0x6b175474e89094c44da98b954eedeac495271d0f", 18),
+    ("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", 18),
+    ("0x2260fac5e5542a773aa44fbcfedf7c193bc2c599", 8),
+];
+
+/// Decimals of each token, keyed by symbol or address as the swaps name
+/// them, to turn raw on-chain amounts into whole tokens.
+///
+/// Sources disagree on units: CSV files usually hold whole tokens while
+/// decoded logs, BigQuery rows, fetched reserves and `Sync` events are raw.
+/// Normalizing all of them lets a 6-decimal USDC leg and an 18-decimal one
+/// be compared and simulated against the same pool.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct TokenDecimals {
+    pub decimals: BTreeMap<String, u8>,
+}
+
+impl Default for TokenDecimals {
+    /// The `DEFAULT_TOKEN_DECIMALS`.
+    fn default() -> Self {
+        let decimals = DEFAULT_TOKEN_DECIMALS
+            .iter()
+            .map(|(token, decimals)| (token.to_string(), *decimals))
+            .collect();
+        return Self { decimals };
+    }
+}
+
+impl TokenDecimals {
+    pub fn with_token(mut self, token: &str, decimals: u8) -> Self {
+        self.decimals.insert(token.to_string(), decimals);
+        return self;
+    }
+
+    /// Addresses match in any case.
+    pub fn get(&self, token: &str) -> Option<u8> {
+        return self
+            .decimals
+            .get(token)
+            .or_else(|| self.decimals.get(&token.to_lowercase()))
+            .copied();
+    }
+
+    /// `raw` in whole tokens, unchanged for tokens without known decimals.
+    pub fn to_units(&self, token: &str, raw: f64) -> f64 {
+        return match self.get(token) {
+            Some(decimals) => raw / 10f64.powi(i32::from(decimals)),
+            None => raw,
+        };
+    }
+
+    /// Convert `amount_in`/`amount_out` of swaps read in raw units to whole
+    /// tokens. Don't apply it twice, or to swaps already in whole tokens.
+    pub fn normalize_amounts(&self, transactions: &mut [SwapTransaction]) {
+        for tx in transactions {
+            tx.amount_in = self.to_units(&tx.token_in, tx.amount_in);
+            tx.amount_out = self.to_units(&tx.token_out, tx.amount_out);
+        }
+    }
+}
+
+/// Transfer tax of a fee-on-transfer token, in percent of the amount moved.
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TokenTax {
+    /// Taken from what a pool pays out when the token is bought.
+    pub buy_tax_pct: f64,
+    /// Taken from what reaches the pool when the token is sold.
+    pub sell_tax_pct: f64,
+}
+
+/// Transfer taxes of long-tail tokens, keyed by symbol or address as the
+/// swaps name them. Without them the simulated outputs of a taxed token's
+/// pools never match the recorded ones and the reality check skips them.
+/// Recorded `amount_out`s are expected after tax, as the swapper got them.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct TokenTaxes {
+    pub taxes: BTreeMap<String, TokenTax>,
+}
+
+impl TokenTaxes {
+    pub fn with_token(mut self, token: &str, buy_tax_pct: f64, sell_tax_pct: f64) -> Self {
+        let tax = TokenTax {
+            buy_tax_pct,
+            sell_tax_pct,
+        };
+        self.taxes.insert(token.to_string(), tax);
+        return self;
+    }
+
+    /// Addresses match in any case. Untaxed tokens give the zero tax.
+    pub fn get(&self, token: &str) -> TokenTax {
+        return self
+            .taxes
+            .get(token)
+            .or_else(|| self.taxes.get(&token.to_lowercase()))
+            .copied()
+            .unwrap_or_default();
+    }
+
+    /// What reaches the pool of `amount` sold of `token`.
+    pub fn after_sell(&self, token: &str, amount: f64) -> f64 {
+        return amount * (1.0 - self.get(token).sell_tax_pct / 100.0);
+    }
+
+    /// What the buyer gets of `amount` of `token` paid out by the pool.
+    pub fn after_buy(&self, token: &str, amount: f64) -> f64 {
+        return amount * (1.0 - self.get(token).buy_tax_pct / 100.0);
+    }
+}
+
 /// Checks if the tokens in the swap transactions are reversed,
 /// for example buying first and selling second.
 /// It supports economically equivalent tokens (e.g., USDC/USDT, ETH/WETH).
 pub fn are_tokens_reversed(a: &SwapTransaction, b: &SwapTransaction) -> bool {
-    return are_tokens_equivalent(&a.token_in, &b.token_out)
-        && are_tokens_equivalent(&a.token_out, &b.token_in);
+    return DEFAULT_EQUIVALENCE.are_reversed(a, b);
 }
 
-/// Check if two tokens are economically equivalent
+/// Check if two tokens are economically equivalent, using the default groups.
 pub fn are_tokens_equivalent(token_a: &str, token_b: &str) -> bool {
-    get_token_equivalence_group(token_a) == get_token_equivalence_group(token_b)
+    DEFAULT_EQUIVALENCE.are_equivalent(token_a, token_b)
 }
 
-/// Token equivalence groups for cross-token sandwich detection
-///
-/// TODO: Certainly there could be more equivalent tokens out there.
-fn get_token_equivalence_group(token: &str) -> &str {
-    match token {
-        // Stablecoins - all ~$1 USD
-        "USDC" | "USDT" | "DAI" | "FRAX" | "BUSD" => "STABLECOINS",
-        // ETH variants
-        "ETH" | "WETH" | "stETH" => "ETH_GROUP",
-        // Bitcoin variants
-        "WBTC" | "renBTC" | "sBTC" => "BTC_GROUP",
-        // Everything else is its own group
-        _ => token,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_sim::{find_sandwich_attacks_by_simulation, Pool};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_raw_amounts_normalize_to_whole_tokens() {
+        let transactions = crate::test_support::sample_block();
+        let decimals = TokenDecimals::default().with_token("SHIB", 18);
+        assert_eq!(decimals.get("USDC"), Some(6));
+        assert_eq!(
+            decimals.get("0xA0b86991c6218b36c1d19d4a2e9eB0cE3606eB48"),
+            Some(6)
+        );
+        assert_eq!(decimals.get("PEPE"), None);
+        assert_eq!(decimals.to_units("PEPE", 5.0), 5.0);
+
+        // The same block as read from logs, in raw units
+        let raw =
+            |token: &str, amount: f64| amount * 10f64.powi(decimals.get(token).unwrap() as i32);
+        let mut normalized: Vec<_> = transactions
+            .iter()
+            .map(|tx| SwapTransaction {
+                amount_in: raw(&tx.token_in, tx.amount_in),
+                amount_out: raw(&tx.token_out, tx.amount_out),
+                ..tx.clone()
+            })
+            .collect();
+        decimals.normalize_amounts(&mut normalized);
+        for (tx, normalized) in transactions.iter().zip(&normalized) {
+            assert!((tx.amount_in - normalized.amount_in).abs() <= tx.amount_in * 1e-12);
+            assert!((tx.amount_out - normalized.amount_out).abs() <= tx.amount_out * 1e-12);
+        }
+
+        let pool = crate::test_support::sample_pool();
+        let raw_pool = Pool::new(1e12, 5e28, "USDC".into(), "SHIB".into());
+        let pool_map = HashMap::from([("0xpool1".to_string(), pool)]);
+        let normalized_pool_map =
+            HashMap::from([("0xpool1".to_string(), raw_pool.normalized(&decimals))]);
+        let expected = find_sandwich_attacks_by_simulation(&pool_map, &transactions);
+        let attacks = find_sandwich_attacks_by_simulation(&normalized_pool_map, &normalized);
+        assert!(!attacks.is_empty());
+        assert_eq!(attacks.len(), expected.len());
+        for (attack, expected) in attacks.iter().zip(&expected) {
+            assert_eq!(attack.attack_id(), expected.attack_id());
+        }
     }
 }
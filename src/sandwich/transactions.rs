@@ -1,8 +1,25 @@
+use ethnum::U256;
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+use crate::telemetry;
+
+/// EIP-155 chain ID of Ethereum mainnet, assumed for records without a `chain_id`.
+pub const ETHEREUM_CHAIN_ID: u64 = 1;
+
+fn default_chain_id() -> u64 {
+    ETHEREUM_CHAIN_ID
+}
+
+/// A single DEX swap, the one input type shared by every detector, adapter and exporter.
+///
+/// Adapters (CSV, databases, RPC traces, Solana, gRPC, ...) all decode into
+/// this type, so swaps loaded for the heuristics can be passed as-is to the
+/// simulation and back.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct SwapTransaction {
     pub tx_hash: String,
+    #[serde(default = "default_chain_id")]
+    pub chain_id: u64,
     pub block_number: u64,
     pub timestamp: u64,
     pub tx_position_in_block: u32,
@@ -11,6 +28,7 @@ pub struct SwapTransaction {
     pub token_out: String,
     pub amount_in: f64,
     pub amount_out: f64,
+    /// Price paid per gas in wei, the unit of the EIP-1559 fields below.
     pub gas_price: u64,
     pub pool_address: String,
     pub token_launch_block: u64,
@@ -18,19 +36,95 @@ pub struct SwapTransaction {
     pub usd_value_in: f64,
     pub usd_value_out: f64,
     pub gas_cost_usd: f64,
+    /// EIP-1559 fee cap, unset for legacy transactions and sources without it.
+    #[serde(default)]
+    pub max_fee_per_gas: Option<u64>,
+    /// EIP-1559 tip cap.
+    #[serde(default)]
+    pub max_priority_fee_per_gas: Option<u64>,
+    /// Base fee of the transaction's block.
+    #[serde(default)]
+    pub base_fee_per_gas: Option<u64>,
+    /// `amount_in` in raw units, exact past the 2^53 of f64. Read by
+    /// `Arithmetic::FixedPoint`, set by the sources that decode raw amounts.
+    #[serde(default, with = "raw_amount")]
+    pub raw_amount_in: Option<U256>,
+}
+
+/// `Option<U256>` as a decimal string, empty for `None`.
+pub(crate) mod raw_amount {
+    use ethnum::U256;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        amount: &Option<U256>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        return match amount {
+            Some(amount) => serializer.serialize_some(&amount.to_string()),
+            None => serializer.serialize_none(),
+        };
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<U256>, D::Error> {
+        return match Option::<String>::deserialize(deserializer)? {
+            Some(text) if !text.is_empty() => {
+                parse(&text).map(Some).map_err(serde::de::Error::custom)
+            }
+            _ => Ok(None),
+        };
+    }
+
+    pub fn parse(text: &str) -> Result<U256, String> {
+        return U256::from_str_radix(text.trim(), 10)
+            .map_err(|err| format!("invalid raw amount {:?}: {}", text, err));
+    }
 }
 
-/// Groups transactions by their block number, sorting them by position within the block.
+/// Identifies a block across chains, block numbers alone collide
+/// (block 12360 exists on every chain).
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub struct BlockId {
+    pub chain_id: u64,
+    pub block_number: u64,
+}
+
+impl SwapTransaction {
+    pub fn block_id(&self) -> BlockId {
+        BlockId {
+            chain_id: self.chain_id,
+            block_number: self.block_number,
+        }
+    }
+
+    /// Tip per gas the block producer receives, which is what orders
+    /// transactions after London; `gas_price` also contains the base fee.
+    /// Unknown without the block's base fee.
+    pub fn effective_priority_fee(&self) -> Option<u64> {
+        let base_fee = self.base_fee_per_gas?;
+        let fee = match (self.max_fee_per_gas, self.max_priority_fee_per_gas) {
+            (Some(max_fee), Some(max_priority_fee)) => {
+                max_priority_fee.min(max_fee.saturating_sub(base_fee))
+            }
+            // Legacy transactions tip everything above the base fee
+            _ => self.gas_price.saturating_sub(base_fee),
+        };
+        return Some(fee);
+    }
+}
+
+/// Groups transactions by their chain and block number, sorting them by position within the block.
 pub fn group_transactions_by_block(
     transactions: &[SwapTransaction],
-) -> HashMap<u64, Vec<SwapTransaction>> {
-    let mut grouped = HashMap::new();
+) -> HashMap<BlockId, Vec<SwapTransaction>> {
+    let mut grouped: HashMap<BlockId, Vec<SwapTransaction>> = HashMap::new();
 
     for tx in transactions {
-        grouped
-            .entry(tx.block_number)
-            .or_insert_with(Vec::new)
-            .push(tx.clone());
+        grouped.entry(tx.block_id()).or_default().push(tx.clone());
     }
 
     for txs in grouped.values_mut() {
@@ -39,3 +133,48 @@ pub fn group_transactions_by_block(
 
     return grouped;
 }
+
+/// Groups a stream of transactions into blocks on the fly,
+/// yielding `(block_id, transactions)` sorted by position within the block.
+///
+/// The input must be ordered by block (as exports and node APIs produce it),
+/// only the block currently being assembled is held in memory.
+/// A block that shows up again after it was emitted starts a new group,
+/// so interleaved chains must be split (or sorted by chain) beforehand.
+pub struct BlockStream<I: Iterator<Item = SwapTransaction>> {
+    transactions: I,
+    pending: Option<SwapTransaction>,
+}
+
+impl<I: Iterator<Item = SwapTransaction>> Iterator for BlockStream<I> {
+    type Item = (BlockId, Vec<SwapTransaction>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        return telemetry::traced(telemetry::INGEST_BLOCK, &[], || {
+            let first = self.pending.take().or_else(|| self.transactions.next())?;
+            let block_id = first.block_id();
+            let mut block = vec![first];
+
+            for tx in self.transactions.by_ref() {
+                if tx.block_id() != block_id {
+                    self.pending = Some(tx);
+                    break;
+                }
+                block.push(tx);
+            }
+
+            block.sort_by_key(|tx| tx.tx_position_in_block);
+            return Some((block_id, block));
+        });
+    }
+}
+
+pub fn stream_transactions_by_block<I>(transactions: I) -> BlockStream<I::IntoIter>
+where
+    I: IntoIterator<Item = SwapTransaction>,
+{
+    BlockStream {
+        transactions: transactions.into_iter(),
+        pending: None,
+    }
+}
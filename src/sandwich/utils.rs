@@ -1,4 +1,4 @@
-use crate::sandwich::tokens::are_tokens_equivalent;
+use crate::sandwich::tokens::{TokenEquivalence, DEFAULT_EQUIVALENCE};
 use crate::sandwich::transactions::SwapTransaction;
 
 /// A rudimentary sandwich pattern detection function.
@@ -15,6 +15,16 @@ pub fn is_sandwich_pattern(
     front: &SwapTransaction,
     victim: &SwapTransaction,
     back: &SwapTransaction,
+) -> bool {
+    return is_sandwich_pattern_with(&DEFAULT_EQUIVALENCE, front, victim, back);
+}
+
+/// `is_sandwich_pattern` with custom token equivalence groups.
+pub fn is_sandwich_pattern_with(
+    tokens: &TokenEquivalence,
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
 ) -> bool {
     // Front-run and victim should be same pool
     if front.pool_address != victim.pool_address {
@@ -32,23 +42,57 @@ pub fn is_sandwich_pattern(
     }
 
     // Attacker should have gotten equivalent token back
-    if !are_tokens_equivalent(&front.token_in, &back.token_out) {
+    if !tokens.are_equivalent(&front.token_in, &back.token_out) {
         return false;
     }
 
     // Front and victim should be same token direction (attacker buys before victim)
-    if !are_tokens_equivalent(&front.token_in, &victim.token_in)
-        || !are_tokens_equivalent(&front.token_out, &victim.token_out)
+    if !tokens.are_equivalent(&front.token_in, &victim.token_in)
+        || !tokens.are_equivalent(&front.token_out, &victim.token_out)
     {
         return false;
     }
 
     // Victim and back should be different token direction (attacker sells back to victim)
-    if are_tokens_equivalent(&victim.token_in, &back.token_in)
-        && are_tokens_equivalent(&victim.token_out, &back.token_out)
+    if tokens.are_equivalent(&victim.token_in, &back.token_in)
+        && tokens.are_equivalent(&victim.token_out, &back.token_out)
     {
         return false;
     }
 
     return true;
 }
+
+/// The three legs of a sandwich, one per line, for multi-line summaries.
+pub(crate) fn format_legs(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+) -> String {
+    let leg = |name: &str, tx: &SwapTransaction| {
+        format!(
+            "  {:<7} {} ({} -> {})\n",
+            format!("{}:", name),
+            tx.tx_hash,
+            tx.token_in,
+            tx.token_out
+        )
+    };
+    return format!(
+        "{}{}{}",
+        leg("front", front),
+        leg("victim", victim),
+        leg("back", back)
+    );
+}
+
+/// A stable identifier for a sandwich, derived from the hashes of its three legs.
+/// The same (front, victim, back) triple always maps to the same ID,
+/// no matter which detector found it.
+pub fn sandwich_attack_id(
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+) -> String {
+    return format!("{}-{}-{}", front.tx_hash, victim.tx_hash, back.tx_hash);
+}
@@ -0,0 +1,636 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, InputObject, Object, Schema,
+    SimpleObject,
+};
+
+use crate::storage::sqlite::{AttackQuery, SqliteStore, StoredAttack};
+
+pub type AttackSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Largest request body `serve` reads, larger ones are answered with 413.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Largest request line plus headers `serve` reads, larger ones are answered with 431.
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+
+/// Connections `serve` handles at once, more are answered with 503.
+const MAX_CONNECTIONS: usize = 64;
+
+/// Deepest selection a query may nest, e.g. `attacks { pool { attacks { attackId } } }`.
+const MAX_QUERY_DEPTH: usize = 4;
+
+/// Largest complexity a query may have. Every field costs 1, list fields
+/// times the `limit` of items they return and relations resolved with a
+/// store query `STORE_QUERY_COMPLEXITY` more.
+const MAX_QUERY_COMPLEXITY: usize = 5_000;
+
+const STORE_QUERY_COMPLEXITY: usize = 10;
+
+/// Items a list field returns without a `limit`, and the most it returns with one.
+const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 1_000;
+
+/// How long a client may take to send its request or read the response.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Build the schema over the attacks persisted in a SQLite store.
+///
+/// Queries run against the store as they come in, so attacks saved by a
+/// running detector show up without rebuilding the schema. Queries nesting
+/// deeper than `MAX_QUERY_DEPTH` or costing more than `MAX_QUERY_COMPLEXITY`
+/// are rejected before running.
+pub fn build_schema(store: Arc<Mutex<SqliteStore>>) -> AttackSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(store)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+}
+
+/// Restricts which stored attacks a query looks at, unset fields match everything.
+#[derive(Debug, Clone, Default, InputObject)]
+pub struct AttackFilter {
+    pub chain_id: Option<u64>,
+    pub pool_address: Option<String>,
+    pub attacker: Option<String>,
+    pub victim: Option<String>,
+    pub block_start: Option<u64>,
+    pub block_end: Option<u64>,
+    /// Unix timestamp (inclusive) of the earliest victim transaction.
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub min_loss_usd: Option<f64>,
+    pub min_confidence: Option<f32>,
+}
+
+impl From<AttackFilter> for AttackQuery {
+    fn from(filter: AttackFilter) -> Self {
+        Self {
+            attack_id: None,
+            chain_id: filter.chain_id,
+            pool_address: filter.pool_address,
+            attacker: filter.attacker,
+            victim: filter.victim,
+            block_start: filter.block_start,
+            block_end: filter.block_end,
+            since: filter.since,
+            until: filter.until,
+            min_loss_usd: filter.min_loss_usd,
+            min_confidence: filter.min_confidence,
+            limit: None,
+            offset: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct Attack {
+    pub attack_id: String,
+    pub chain_id: u64,
+    pub block_number: u64,
+    pub timestamp: u64,
+    pub pool_address: String,
+    pub attacker: String,
+    pub victim: String,
+    pub front_run_tx: String,
+    pub victim_tx: String,
+    pub back_run_tx: String,
+    /// Set when the heuristics found the attack.
+    pub confidence_score: Option<f32>,
+    pub total_profit_usd: Option<f64>,
+    /// Set when simulation confirmed the attack.
+    pub victim_loss_percentage: Option<f64>,
+    /// Simulated victim loss, unknown when the attack was not simulated.
+    pub loss_usd: Option<f64>,
+}
+
+impl From<StoredAttack> for Attack {
+    fn from(attack: StoredAttack) -> Self {
+        Self {
+            attack_id: attack.attack_id,
+            chain_id: attack.chain_id,
+            block_number: attack.block_number,
+            timestamp: attack.timestamp,
+            pool_address: attack.pool_address,
+            attacker: attack.attacker,
+            victim: attack.victim,
+            front_run_tx: attack.front_run_tx,
+            victim_tx: attack.victim_tx,
+            back_run_tx: attack.back_run_tx,
+            confidence_score: attack.confidence_score,
+            total_profit_usd: attack.total_profit_usd,
+            victim_loss_percentage: attack.victim_loss_percentage,
+            loss_usd: attack.loss_usd,
+        }
+    }
+}
+
+#[ComplexObject]
+impl Attack {
+    /// Every stored attack by the same attacker.
+    #[graphql(complexity = "STORE_QUERY_COMPLEXITY + child_complexity")]
+    async fn attacker_summary(&self, ctx: &Context<'_>) -> async_graphql::Result<AddressSummary> {
+        let query = AttackQuery {
+            attacker: Some(self.attacker.clone()),
+            ..AttackQuery::default()
+        };
+        return Ok(AddressSummary::new(
+            self.attacker.clone(),
+            query_attacks(ctx, &query)?,
+        ));
+    }
+
+    /// Every stored attack on the same victim.
+    #[graphql(complexity = "STORE_QUERY_COMPLEXITY + child_complexity")]
+    async fn victim_summary(&self, ctx: &Context<'_>) -> async_graphql::Result<AddressSummary> {
+        let query = AttackQuery {
+            victim: Some(self.victim.clone()),
+            ..AttackQuery::default()
+        };
+        return Ok(AddressSummary::new(
+            self.victim.clone(),
+            query_attacks(ctx, &query)?,
+        ));
+    }
+
+    #[graphql(complexity = "STORE_QUERY_COMPLEXITY + child_complexity")]
+    async fn pool(&self, ctx: &Context<'_>) -> async_graphql::Result<PoolSummary> {
+        let query = AttackQuery {
+            chain_id: Some(self.chain_id),
+            pool_address: Some(self.pool_address.clone()),
+            ..AttackQuery::default()
+        };
+        return Ok(PoolSummary::new(
+            self.chain_id,
+            self.pool_address.clone(),
+            query_attacks(ctx, &query)?,
+        ));
+    }
+
+    #[graphql(complexity = "STORE_QUERY_COMPLEXITY + child_complexity")]
+    async fn block(&self, ctx: &Context<'_>) -> async_graphql::Result<BlockSummary> {
+        let query = AttackQuery {
+            chain_id: Some(self.chain_id),
+            block_start: Some(self.block_number),
+            block_end: Some(self.block_number),
+            ..AttackQuery::default()
+        };
+        return Ok(BlockSummary::new(
+            self.chain_id,
+            self.block_number,
+            query_attacks(ctx, &query)?,
+        ));
+    }
+}
+
+/// An attacker or victim with the attacks it was involved in.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct AddressSummary {
+    pub address: String,
+    pub attack_count: u64,
+    pub total_loss_usd: f64,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub attacks: Vec<Attack>,
+}
+
+impl AddressSummary {
+    fn new(address: String, attacks: Vec<Attack>) -> Self {
+        Self {
+            address,
+            attack_count: attacks.len() as u64,
+            total_loss_usd: total_loss_usd(&attacks),
+            first_block: attacks.iter().map(|a| a.block_number).min().unwrap_or(0),
+            last_block: attacks.iter().map(|a| a.block_number).max().unwrap_or(0),
+            attacks,
+        }
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct PoolSummary {
+    pub chain_id: u64,
+    pub pool_address: String,
+    pub attack_count: u64,
+    pub total_loss_usd: f64,
+    pub attacks: Vec<Attack>,
+}
+
+impl PoolSummary {
+    fn new(chain_id: u64, pool_address: String, attacks: Vec<Attack>) -> Self {
+        Self {
+            chain_id,
+            pool_address,
+            attack_count: attacks.len() as u64,
+            total_loss_usd: total_loss_usd(&attacks),
+            attacks,
+        }
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct BlockSummary {
+    pub chain_id: u64,
+    pub block_number: u64,
+    pub attack_count: u64,
+    pub total_loss_usd: f64,
+    pub attacks: Vec<Attack>,
+}
+
+impl BlockSummary {
+    fn new(chain_id: u64, block_number: u64, attacks: Vec<Attack>) -> Self {
+        Self {
+            chain_id,
+            block_number,
+            attack_count: attacks.len() as u64,
+            total_loss_usd: total_loss_usd(&attacks),
+            attacks,
+        }
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Attacks matching the filter, ordered by chain and block.
+    #[graphql(complexity = "STORE_QUERY_COMPLEXITY + limit as usize * child_complexity")]
+    async fn attacks(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<AttackFilter>,
+        #[graphql(default_with = "DEFAULT_PAGE_SIZE")] limit: u32,
+        #[graphql(default)] offset: u32,
+    ) -> async_graphql::Result<Vec<Attack>> {
+        let query = AttackQuery {
+            limit: Some(limit.min(MAX_PAGE_SIZE) as usize),
+            offset: offset as usize,
+            ..AttackQuery::from(filter.unwrap_or_default())
+        };
+        return query_attacks(ctx, &query);
+    }
+
+    async fn attack(
+        &self,
+        ctx: &Context<'_>,
+        attack_id: String,
+    ) -> async_graphql::Result<Option<Attack>> {
+        let query = AttackQuery {
+            attack_id: Some(attack_id),
+            ..AttackQuery::default()
+        };
+        return Ok(query_attacks(ctx, &query)?.into_iter().next());
+    }
+
+    /// Attackers of the matching attacks, by total loss caused.
+    #[graphql(complexity = "STORE_QUERY_COMPLEXITY + limit as usize * child_complexity")]
+    async fn attackers(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<AttackFilter>,
+        #[graphql(default_with = "DEFAULT_PAGE_SIZE")] limit: u32,
+    ) -> async_graphql::Result<Vec<AddressSummary>> {
+        let attacks = query_attacks(ctx, &AttackQuery::from(filter.unwrap_or_default()))?;
+        let summaries = group_by(attacks, |a| a.attacker.clone())
+            .into_iter()
+            .map(|(address, attacks)| AddressSummary::new(address, attacks));
+        return Ok(rank(summaries, |s| s.total_loss_usd, limit));
+    }
+
+    /// Victims of the matching attacks, by total loss suffered.
+    #[graphql(complexity = "STORE_QUERY_COMPLEXITY + limit as usize * child_complexity")]
+    async fn victims(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<AttackFilter>,
+        #[graphql(default_with = "DEFAULT_PAGE_SIZE")] limit: u32,
+    ) -> async_graphql::Result<Vec<AddressSummary>> {
+        let attacks = query_attacks(ctx, &AttackQuery::from(filter.unwrap_or_default()))?;
+        let summaries = group_by(attacks, |a| a.victim.clone())
+            .into_iter()
+            .map(|(address, attacks)| AddressSummary::new(address, attacks));
+        return Ok(rank(summaries, |s| s.total_loss_usd, limit));
+    }
+
+    /// Pools of the matching attacks, by total loss.
+    #[graphql(complexity = "STORE_QUERY_COMPLEXITY + limit as usize * child_complexity")]
+    async fn pools(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<AttackFilter>,
+        #[graphql(default_with = "DEFAULT_PAGE_SIZE")] limit: u32,
+    ) -> async_graphql::Result<Vec<PoolSummary>> {
+        let attacks = query_attacks(ctx, &AttackQuery::from(filter.unwrap_or_default()))?;
+        let summaries = group_by(attacks, |a| (a.chain_id, a.pool_address.clone()))
+            .into_iter()
+            .map(|((chain_id, pool), attacks)| PoolSummary::new(chain_id, pool, attacks));
+        return Ok(rank(summaries, |s| s.total_loss_usd, limit));
+    }
+
+    /// Blocks with matching attacks, in chain and block order.
+    #[graphql(complexity = "STORE_QUERY_COMPLEXITY + limit as usize * child_complexity")]
+    async fn blocks(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<AttackFilter>,
+        #[graphql(default_with = "DEFAULT_PAGE_SIZE")] limit: u32,
+    ) -> async_graphql::Result<Vec<BlockSummary>> {
+        let attacks = query_attacks(ctx, &AttackQuery::from(filter.unwrap_or_default()))?;
+        let blocks = group_by(attacks, |a| (a.chain_id, a.block_number))
+            .into_iter()
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .map(|((chain_id, block_number), attacks)| {
+                BlockSummary::new(chain_id, block_number, attacks)
+            })
+            .collect();
+        return Ok(blocks);
+    }
+}
+
+fn query_attacks(ctx: &Context<'_>, query: &AttackQuery) -> async_graphql::Result<Vec<Attack>> {
+    let store = ctx.data::<Arc<Mutex<SqliteStore>>>()?;
+    let store = store
+        .lock()
+        .map_err(|_| async_graphql::Error::new("attack store is unavailable"))?;
+    let attacks = store
+        .query_attacks(query)
+        .map_err(async_graphql::Error::new)?;
+    return Ok(attacks.into_iter().map(Attack::from).collect());
+}
+
+fn group_by<K: Ord, F: Fn(&Attack) -> K>(attacks: Vec<Attack>, key: F) -> BTreeMap<K, Vec<Attack>> {
+    let mut groups: BTreeMap<K, Vec<Attack>> = BTreeMap::new();
+    for attack in attacks {
+        groups.entry(key(&attack)).or_default().push(attack);
+    }
+    return groups;
+}
+
+fn rank<T, I, F>(items: I, score: F, limit: u32) -> Vec<T>
+where
+    I: Iterator<Item = T>,
+    F: Fn(&T) -> f64,
+{
+    let mut items: Vec<T> = items.collect();
+    items.sort_by(|a, b| score(b).total_cmp(&score(a)));
+    items.truncate(limit.min(MAX_PAGE_SIZE) as usize);
+    return items;
+}
+
+fn total_loss_usd(attacks: &[Attack]) -> f64 {
+    attacks.iter().filter_map(|a| a.loss_usd).sum()
+}
+
+/// Serve `POST /graphql` (JSON `{"query": …, "variables": …}`) on `addr`
+/// until the process is stopped, each connection on its own thread, at most
+/// `MAX_CONNECTIONS` at once.
+pub fn serve<A: ToSocketAddrs>(schema: AttackSchema, addr: A) -> Result<(), String> {
+    let listener =
+        TcpListener::bind(addr).map_err(|err| format!("failed to bind GraphQL server: {}", err))?;
+    let runtime = Arc::new(
+        tokio::runtime::Builder::new_multi_thread()
+            .build()
+            .map_err(|err| format!("failed to start runtime: {}", err))?,
+    );
+
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    for mut stream in listener.incoming().flatten() {
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+            let _ = stream.write_all(
+                http_response(
+                    "503 Service Unavailable",
+                    "text/plain",
+                    "too many connections",
+                )
+                .as_bytes(),
+            );
+            continue;
+        }
+        let schema = schema.clone();
+        let runtime = Arc::clone(&runtime);
+        let connections = Arc::clone(&connections);
+        // A failed request only concerns its client, which sees it fail
+        std::thread::spawn(move || {
+            let _ = handle_connection(stream, &schema, &runtime);
+            connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    return Ok(());
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    schema: &AttackSchema,
+    runtime: &tokio::runtime::Runtime,
+) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(IO_TIMEOUT))
+        .map_err(|err| err.to_string())?;
+    stream
+        .set_write_timeout(Some(IO_TIMEOUT))
+        .map_err(|err| err.to_string())?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut head_budget = MAX_HEAD_BYTES;
+    let mut request_line = String::new();
+    let mut content_length = 0;
+    let mut head_fits = read_head_line(&mut reader, &mut request_line, &mut head_budget)?;
+    while head_fits {
+        let mut header = String::new();
+        head_fits = read_head_line(&mut reader, &mut header, &mut head_budget)?;
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| "invalid content length")?;
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        _ if !head_fits => http_response(
+            "431 Request Header Fields Too Large",
+            "text/plain",
+            &format!("request line and headers exceed {} bytes", MAX_HEAD_BYTES),
+        ),
+        (Some("POST"), Some("/graphql")) if content_length > MAX_BODY_BYTES => http_response(
+            "413 Payload Too Large",
+            "text/plain",
+            &format!("request body exceeds {} bytes", MAX_BODY_BYTES),
+        ),
+        (Some("POST"), Some("/graphql")) => {
+            let mut body = vec![0; content_length];
+            reader
+                .read_exact(&mut body)
+                .map_err(|err| err.to_string())?;
+            match serde_json::from_slice::<async_graphql::Request>(&body) {
+                Ok(request) => {
+                    let response = runtime.block_on(schema.execute(request));
+                    let body = serde_json::to_string(&response).map_err(|err| err.to_string())?;
+                    http_response("200 OK", "application/json", &body)
+                }
+                Err(err) => http_response("400 Bad Request", "text/plain", &err.to_string()),
+            }
+        }
+        _ => http_response("404 Not Found", "text/plain", ""),
+    };
+
+    stream
+        .write_all(response.as_bytes())
+        .map_err(|err| err.to_string())
+}
+
+/// Read a line of the request head into `line`, taking at most `budget`
+/// bytes and spending what it reads. False once the budget runs out before
+/// the line's end.
+fn read_head_line<R: BufRead>(
+    reader: &mut R,
+    line: &mut String,
+    budget: &mut usize,
+) -> Result<bool, String> {
+    let read = reader
+        .take(*budget as u64)
+        .read_line(line)
+        .map_err(|err| err.to_string())?;
+    *budget -= read;
+    return Ok(line.ends_with('\n') || *budget > 0);
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_heuristics::find_same_block_sandwiches;
+
+    #[tokio::test]
+    async fn test_query_attacks_by_pool_with_relations() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let attacks = find_same_block_sandwiches(&transactions);
+        let mut store = SqliteStore::open_in_memory().expect("Failed to open store");
+        store
+            .save_attacks(&attacks)
+            .expect("Failed to save attacks");
+
+        let pool = attacks[0].victim_tx.pool_address.clone();
+        let expected = attacks
+            .iter()
+            .filter(|a| a.victim_tx.pool_address == pool)
+            .count();
+
+        let schema = build_schema(Arc::new(Mutex::new(store)));
+        let query = format!(
+            r#"{{
+                attacks(filter: {{ poolAddress: "{0}" }}) {{
+                    attackId
+                    pool {{ attackCount }}
+                    attackerSummary {{ address attackCount }}
+                }}
+                valued: attacks(filter: {{ poolAddress: "{0}", minLossUsd: 0 }}) {{
+                    attackId
+                }}
+                attackers(limit: 1) {{ address }}
+            }}"#,
+            pool
+        );
+        let response = schema.execute(query).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let data = response.data.into_json().unwrap();
+        let found = data["attacks"].as_array().unwrap();
+        assert_eq!(found.len(), expected);
+        assert_eq!(found[0]["pool"]["attackCount"], expected);
+        assert_eq!(data["attackers"].as_array().unwrap().len(), 1);
+        assert!(
+            data["valued"].as_array().unwrap().is_empty(),
+            "Heuristic attacks have no known loss"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deeply_nested_query_is_rejected() {
+        let store = SqliteStore::open_in_memory().expect("Failed to open store");
+        let schema = build_schema(Arc::new(Mutex::new(store)));
+
+        let response = schema
+            .execute(
+                "{ attacks { pool { attacks { attackerSummary { attacks { attackId } } } } } }",
+            )
+            .await;
+        assert!(!response.errors.is_empty());
+        assert!(response.errors[0].message.contains("too deep"));
+    }
+
+    #[test]
+    fn test_oversized_body_is_rejected_unread() {
+        let store = SqliteStore::open_in_memory().expect("Failed to open store");
+        let schema = build_schema(Arc::new(Mutex::new(store)));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        write!(
+            client,
+            "POST /graphql HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            usize::MAX
+        )
+        .unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &schema, &runtime).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+    }
+
+    #[test]
+    fn test_oversized_head_is_rejected() {
+        let store = SqliteStore::open_in_memory().expect("Failed to open store");
+        let schema = build_schema(Arc::new(Mutex::new(store)));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        // Exactly the budget, so no bytes are left unread to reset the connection
+        let request_line = "POST /graphql HTTP/1.1\r\n";
+        let header = "X-Padding: ";
+        write!(
+            client,
+            "{}{}{}",
+            request_line,
+            header,
+            "a".repeat(MAX_HEAD_BYTES - request_line.len() - header.len())
+        )
+        .unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &schema, &runtime).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 431"), "{}", response);
+    }
+}
@@ -0,0 +1,417 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::codegen::{http, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService};
+use tonic::{Status, Streaming};
+use tonic_prost::ProstCodec;
+
+use crate::sandwich::same_block_heuristics::{
+    detect_stream, find_same_block_sandwiches, SandwichAttackByHeuristics,
+};
+use crate::sandwich::transactions::{BlockId, SwapTransaction, ETHEREUM_CHAIN_ID};
+use crate::stream::buffer::BlockBuffer;
+
+const DETECT_SANDWICHES_PATH: &str = "/toxicflow.v1.SandwichDetector/DetectSandwiches";
+const DETECT_BATCH_PATH: &str = "/toxicflow.v1.SandwichDetector/DetectBatch";
+
+/// Attacks waiting to be sent to a streaming client before detection pauses.
+const ATTACK_CHANNEL_CAPACITY: usize = 256;
+
+/// Messages of `proto/toxicflow.proto`.
+///
+/// They're written out by hand (instead of generated by `tonic-build` in a
+/// build script) so building the crate doesn't require `protoc`; keep the
+/// tags in sync with the proto file.
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Swap {
+        #[prost(string, tag = "1")]
+        pub tx_hash: String,
+        #[prost(uint64, tag = "2")]
+        pub chain_id: u64,
+        #[prost(uint64, tag = "3")]
+        pub block_number: u64,
+        #[prost(uint64, tag = "4")]
+        pub timestamp: u64,
+        #[prost(uint32, tag = "5")]
+        pub tx_position_in_block: u32,
+        #[prost(string, tag = "6")]
+        pub from_address: String,
+        #[prost(string, tag = "7")]
+        pub token_in: String,
+        #[prost(string, tag = "8")]
+        pub token_out: String,
+        #[prost(double, tag = "9")]
+        pub amount_in: f64,
+        #[prost(double, tag = "10")]
+        pub amount_out: f64,
+        #[prost(uint64, tag = "11")]
+        pub gas_price: u64,
+        #[prost(string, tag = "12")]
+        pub pool_address: String,
+        #[prost(uint64, tag = "13")]
+        pub token_launch_block: u64,
+        #[prost(bool, tag = "14")]
+        pub is_contract_caller: bool,
+        #[prost(double, tag = "15")]
+        pub usd_value_in: f64,
+        #[prost(double, tag = "16")]
+        pub usd_value_out: f64,
+        #[prost(double, tag = "17")]
+        pub gas_cost_usd: f64,
+        #[prost(uint64, optional, tag = "18")]
+        pub max_fee_per_gas: Option<u64>,
+        #[prost(uint64, optional, tag = "19")]
+        pub max_priority_fee_per_gas: Option<u64>,
+        #[prost(uint64, optional, tag = "20")]
+        pub base_fee_per_gas: Option<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ConfidenceFlags {
+        #[prost(bool, tag = "1")]
+        pub higher_front_gas_price: bool,
+        #[prost(bool, tag = "2")]
+        pub lower_back_gas_price: bool,
+        #[prost(bool, tag = "3")]
+        pub front_is_contract: bool,
+        #[prost(bool, tag = "4")]
+        pub back_is_contract: bool,
+        #[prost(bool, tag = "5")]
+        pub is_profitable: bool,
+        #[prost(bool, tag = "6")]
+        pub is_proportional: bool,
+        #[prost(float, tag = "7")]
+        pub price_impact_rate: f32,
+        #[prost(double, tag = "8")]
+        pub total_profit_usd: f64,
+        #[prost(bool, tag = "9")]
+        pub is_known_bot: bool,
+        #[prost(bool, tag = "10")]
+        pub likely_private_bundle: bool,
+        #[prost(bool, tag = "11")]
+        pub uses_flashloan: bool,
+        #[prost(float, tag = "12")]
+        pub attacker_repeat_offender: f32,
+        #[prost(bool, tag = "13")]
+        pub same_entity: bool,
+        #[prost(float, tag = "14")]
+        pub swap_size_factor: f32,
+        #[prost(float, tag = "15")]
+        pub bot_activity_factor: f32,
+        #[prost(bool, tag = "16")]
+        pub paid_builder: bool,
+        #[prost(bool, tag = "17")]
+        pub profitable_only_before_bribe: bool,
+        #[prost(float, tag = "18")]
+        pub front_gas_zscore: f32,
+        #[prost(float, tag = "19")]
+        pub price_impact_depth_scale: f32,
+        #[prost(string, repeated, tag = "20")]
+        pub custom_flags: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SandwichAttack {
+        #[prost(string, tag = "1")]
+        pub attack_id: String,
+        #[prost(uint64, tag = "2")]
+        pub chain_id: u64,
+        #[prost(message, optional, tag = "3")]
+        pub front_run_tx: Option<Swap>,
+        #[prost(message, optional, tag = "4")]
+        pub victim_tx: Option<Swap>,
+        #[prost(message, optional, tag = "5")]
+        pub back_run_tx: Option<Swap>,
+        #[prost(float, tag = "6")]
+        pub confidence_score: f32,
+        #[prost(message, optional, tag = "7")]
+        pub confidence_flags: Option<ConfidenceFlags>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DetectBatchRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub swaps: Vec<Swap>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DetectBatchResponse {
+        #[prost(message, repeated, tag = "1")]
+        pub attacks: Vec<SandwichAttack>,
+    }
+}
+
+impl From<proto::Swap> for SwapTransaction {
+    fn from(swap: proto::Swap) -> Self {
+        Self {
+            tx_hash: swap.tx_hash,
+            chain_id: match swap.chain_id {
+                0 => ETHEREUM_CHAIN_ID,
+                chain_id => chain_id,
+            },
+            block_number: swap.block_number,
+            timestamp: swap.timestamp,
+            tx_position_in_block: swap.tx_position_in_block,
+            from_address: swap.from_address,
+            token_in: swap.token_in,
+            token_out: swap.token_out,
+            amount_in: swap.amount_in,
+            amount_out: swap.amount_out,
+            gas_price: swap.gas_price,
+            pool_address: swap.pool_address,
+            token_launch_block: swap.token_launch_block,
+            is_contract_caller: swap.is_contract_caller,
+            usd_value_in: swap.usd_value_in,
+            usd_value_out: swap.usd_value_out,
+            gas_cost_usd: swap.gas_cost_usd,
+            max_fee_per_gas: swap.max_fee_per_gas,
+            max_priority_fee_per_gas: swap.max_priority_fee_per_gas,
+            base_fee_per_gas: swap.base_fee_per_gas,
+            raw_amount_in: None,
+        }
+    }
+}
+
+impl From<&SwapTransaction> for proto::Swap {
+    fn from(tx: &SwapTransaction) -> Self {
+        Self {
+            tx_hash: tx.tx_hash.clone(),
+            chain_id: tx.chain_id,
+            block_number: tx.block_number,
+            timestamp: tx.timestamp,
+            tx_position_in_block: tx.tx_position_in_block,
+            from_address: tx.from_address.clone(),
+            token_in: tx.token_in.clone(),
+            token_out: tx.token_out.clone(),
+            amount_in: tx.amount_in,
+            amount_out: tx.amount_out,
+            gas_price: tx.gas_price,
+            pool_address: tx.pool_address.clone(),
+            token_launch_block: tx.token_launch_block,
+            is_contract_caller: tx.is_contract_caller,
+            usd_value_in: tx.usd_value_in,
+            usd_value_out: tx.usd_value_out,
+            gas_cost_usd: tx.gas_cost_usd,
+            max_fee_per_gas: tx.max_fee_per_gas,
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+            base_fee_per_gas: tx.base_fee_per_gas,
+        }
+    }
+}
+
+impl From<&SandwichAttackByHeuristics> for proto::SandwichAttack {
+    fn from(attack: &SandwichAttackByHeuristics) -> Self {
+        let flags = &attack.confidence_flags;
+        Self {
+            attack_id: attack.attack_id(),
+            chain_id: attack.chain_id,
+            front_run_tx: Some(proto::Swap::from(&attack.front_run_tx)),
+            victim_tx: Some(proto::Swap::from(&attack.victim_tx)),
+            back_run_tx: Some(proto::Swap::from(&attack.back_run_tx)),
+            confidence_score: attack.confidence_score,
+            confidence_flags: Some(proto::ConfidenceFlags {
+                higher_front_gas_price: flags.higher_front_gas_price,
+                lower_back_gas_price: flags.lower_back_gas_price,
+                front_is_contract: flags.front_is_contract,
+                back_is_contract: flags.back_is_contract,
+                is_profitable: flags.is_profitable,
+                is_proportional: flags.is_proportional,
+                price_impact_rate: flags.price_impact_rate,
+                total_profit_usd: flags.total_profit_usd,
+                is_known_bot: flags.is_known_bot,
+                likely_private_bundle: flags.likely_private_bundle,
+                uses_flashloan: flags.uses_flashloan,
+                attacker_repeat_offender: flags.attacker_repeat_offender,
+                same_entity: flags.same_entity,
+                swap_size_factor: flags.swap_size_factor,
+                bot_activity_factor: flags.bot_activity_factor,
+                paid_builder: flags.paid_builder,
+                profitable_only_before_bribe: flags.profitable_only_before_bribe,
+                front_gas_zscore: flags.front_gas_zscore,
+                price_impact_depth_scale: flags.price_impact_depth_scale,
+                custom_flags: flags.custom_flags.clone(),
+            }),
+        }
+    }
+}
+
+/// The `toxicflow.v1.SandwichDetector` service, running the heuristic detector.
+///
+/// Add it to an existing `tonic::transport::Server`, or run it alone with `serve`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SandwichDetectorServer;
+
+impl NamedService for SandwichDetectorServer {
+    const NAME: &'static str = "toxicflow.v1.SandwichDetector";
+}
+
+impl<B> Service<http::Request<B>> for SandwichDetectorServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        match request.uri().path() {
+            DETECT_SANDWICHES_PATH => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.streaming(DetectSandwiches, request).await)
+            }),
+            DETECT_BATCH_PATH => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(DetectBatch, request).await)
+            }),
+            path => {
+                let status = Status::unimplemented(format!("unknown method {}", path));
+                Box::pin(async move { Ok(status.into_http()) })
+            }
+        }
+    }
+}
+
+struct DetectSandwiches;
+
+impl Service<tonic::Request<Streaming<proto::Swap>>> for DetectSandwiches {
+    type Response = tonic::Response<ReceiverStream<Result<proto::SandwichAttack, Status>>>;
+    type Error = Status;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: tonic::Request<Streaming<proto::Swap>>) -> Self::Future {
+        let attacks = detect_swap_stream(request.into_inner());
+        Box::pin(async move { Ok(tonic::Response::new(attacks)) })
+    }
+}
+
+struct DetectBatch;
+
+impl Service<tonic::Request<proto::DetectBatchRequest>> for DetectBatch {
+    type Response = tonic::Response<proto::DetectBatchResponse>;
+    type Error = Status;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: tonic::Request<proto::DetectBatchRequest>) -> Self::Future {
+        let response = detect_batch(request.into_inner());
+        Box::pin(async move { Ok(tonic::Response::new(response)) })
+    }
+}
+
+pub fn detect_batch(request: proto::DetectBatchRequest) -> proto::DetectBatchResponse {
+    let swaps: Vec<SwapTransaction> = request.swaps.into_iter().map(Into::into).collect();
+    let attacks = find_same_block_sandwiches(&swaps)
+        .iter()
+        .map(proto::SandwichAttack::from)
+        .collect();
+    return proto::DetectBatchResponse { attacks };
+}
+
+/// Run detection over a stream of swaps on a background task, sending back
+/// the attacks of each block once a later block starts.
+///
+/// Detection stops when the client hangs up, and an error in the input
+/// stream is passed on to the client.
+pub fn detect_swap_stream<S>(swaps: S) -> ReceiverStream<Result<proto::SandwichAttack, Status>>
+where
+    S: Stream<Item = Result<proto::Swap, Status>> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(ATTACK_CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        let mut swaps = Box::pin(swaps);
+        let mut buffer = BlockBuffer::new(0);
+
+        while let Some(swap) = swaps.next().await {
+            let completed = match swap {
+                Ok(swap) => buffer.push(swap.into()),
+                Err(status) => {
+                    let _ = sender.send(Err(status)).await;
+                    return;
+                }
+            };
+            if !send_attacks(&sender, completed).await {
+                return;
+            }
+        }
+        send_attacks(&sender, buffer.flush()).await;
+    });
+
+    return ReceiverStream::new(receiver);
+}
+
+/// Returns false once the client is gone.
+async fn send_attacks(
+    sender: &mpsc::Sender<Result<proto::SandwichAttack, Status>>,
+    blocks: Vec<(BlockId, Vec<SwapTransaction>)>,
+) -> bool {
+    for (_block, swaps) in blocks {
+        for attack in detect_stream(swaps) {
+            let attack = proto::SandwichAttack::from(&attack);
+            if sender.send(Ok(attack)).await.is_err() {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+/// Serve the detector on `addr` until the process is stopped.
+pub fn serve(addr: SocketAddr) -> Result<(), String> {
+    let runtime = tokio::runtime::Runtime::new()
+        .map_err(|err| format!("failed to start runtime: {}", err))?;
+    runtime
+        .block_on(
+            tonic::transport::Server::builder()
+                .add_service(SandwichDetectorServer)
+                .serve(addr),
+        )
+        .map_err(|err| format!("gRPC server failed: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stream_and_batch_detection_agree() {
+        let swaps: Vec<proto::Swap> = crate::ingest::csv::sample_transactions()
+            .iter()
+            .map(proto::Swap::from)
+            .collect();
+
+        let batch = detect_batch(proto::DetectBatchRequest {
+            swaps: swaps.clone(),
+        });
+        assert!(!batch.attacks.is_empty());
+
+        let streamed: Vec<proto::SandwichAttack> =
+            detect_swap_stream(tokio_stream::iter(swaps.into_iter().map(Ok)))
+                .map(|attack| attack.expect("Stream failed"))
+                .collect()
+                .await;
+
+        let mut batch_ids: Vec<String> = batch.attacks.into_iter().map(|a| a.attack_id).collect();
+        let mut streamed_ids: Vec<String> = streamed.into_iter().map(|a| a.attack_id).collect();
+        batch_ids.sort();
+        streamed_ids.sort();
+        assert_eq!(streamed_ids, batch_ids);
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::sandwich::transactions::{BlockId, SwapTransaction};
+use crate::storage::write_atomically;
+
+/// Blocks between checkpoint saves in `Pipeline::run_resumable`.
+pub const CHECKPOINT_INTERVAL_BLOCKS: usize = 100;
+
+/// How far a scan got: the last processed block of each chain. Blocks are
+/// scanned oldest first, so everything up to it is done.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct Checkpoint {
+    /// Chain id to block number.
+    pub last_blocks: BTreeMap<u64, u64>,
+}
+
+impl Checkpoint {
+    pub fn record(&mut self, block: BlockId) {
+        let last = self.last_blocks.entry(block.chain_id).or_insert(0);
+        *last = (*last).max(block.block_number);
+    }
+
+    pub fn is_processed(&self, block: BlockId) -> bool {
+        return self
+            .last_blocks
+            .get(&block.chain_id)
+            .is_some_and(|last| block.block_number <= *last);
+    }
+
+    /// The transactions of blocks the scan hasn't reached yet.
+    pub fn pending(&self, transactions: &[SwapTransaction]) -> Vec<SwapTransaction> {
+        return transactions
+            .iter()
+            .filter(|tx| !self.is_processed(tx.block_id()))
+            .cloned()
+            .collect();
+    }
+}
+
+/// Where a scan persists its `Checkpoint`.
+pub trait CheckpointStore {
+    /// The saved checkpoint, empty if the scan never ran.
+    fn load_checkpoint(&mut self) -> Result<Checkpoint, String>;
+    fn save_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), String>;
+}
+
+/// A checkpoint kept as a JSON file.
+#[derive(Debug, Clone)]
+pub struct FileCheckpoint {
+    path: PathBuf,
+}
+
+impl FileCheckpoint {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        return Self { path: path.into() };
+    }
+}
+
+impl CheckpointStore for FileCheckpoint {
+    fn load_checkpoint(&mut self) -> Result<Checkpoint, String> {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Checkpoint::default())
+            }
+            Err(err) => return Err(format!("failed to read checkpoint: {}", err)),
+        };
+        return serde_json::from_str(&text).map_err(|err| format!("invalid checkpoint: {}", err));
+    }
+
+    /// Replaces the file atomically, see `write_atomically`.
+    fn save_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), String> {
+        let text = serde_json::to_string(checkpoint)
+            .map_err(|err| format!("failed to serialize checkpoint: {}", err))?;
+        return write_atomically(&self.path, &text)
+            .map_err(|err| format!("failed to write checkpoint: {}", err));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::cancel::CancellationToken;
+    use crate::sandwich::detector::{Detection, HeuristicsDetector, Pipeline};
+
+    #[test]
+    fn test_interrupted_scan_resumes_from_checkpoint() {
+        let transactions = crate::ingest::csv::sample_transactions();
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let pipeline = Pipeline::new().with_detector(HeuristicsDetector::default());
+        let complete = pipeline.run(&transactions);
+
+        // Stop after the first few blocks, as if the process had been killed
+        let cancel = CancellationToken::new();
+        let mut detections: Vec<Detection> = Vec::new();
+        let mut blocks = 0;
+        let finished = pipeline
+            .run_resumable(
+                &transactions,
+                &mut FileCheckpoint::new(&path),
+                &cancel,
+                |_block, block_detections| {
+                    detections.extend(block_detections);
+                    blocks += 1;
+                    if blocks == 3 {
+                        cancel.cancel();
+                    }
+                    return Ok(());
+                },
+            )
+            .unwrap();
+        assert!(!finished);
+        let checkpoint = FileCheckpoint::new(&path).load_checkpoint().unwrap();
+        assert_eq!(checkpoint.last_blocks.len(), 1);
+        assert!(checkpoint.pending(&transactions).len() < transactions.len());
+
+        let finished = pipeline
+            .run_resumable(
+                &transactions,
+                &mut FileCheckpoint::new(&path),
+                &CancellationToken::new(),
+                |_block, block_detections| {
+                    detections.extend(block_detections);
+                    return Ok(());
+                },
+            )
+            .unwrap();
+        assert!(finished);
+        assert_eq!(detections, complete);
+
+        let checkpoint = FileCheckpoint::new(&path).load_checkpoint().unwrap();
+        assert!(checkpoint.pending(&transactions).is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
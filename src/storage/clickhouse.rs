@@ -0,0 +1,268 @@
+use super::{FEE_COLUMNS, SWAP_COLUMNS};
+use crate::sandwich::transactions::SwapTransaction;
+
+/// Server-side filter applied when reading swaps from ClickHouse.
+/// Empty `pools`/`tokens` lists mean "no filter".
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapFilter {
+    pub chain_id: Option<u64>,
+    pub block_start: u64,
+    pub block_end: u64,
+    pub pools: Vec<String>,
+    pub tokens: Vec<String>,
+}
+
+impl SwapFilter {
+    /// Filter on an inclusive block range.
+    pub fn new(block_start: u64, block_end: u64) -> Self {
+        Self {
+            chain_id: None,
+            block_start,
+            block_end,
+            pools: Vec::new(),
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Only keep swaps of one chain, block ranges of a mixed-chain table are ambiguous otherwise.
+    pub fn chain(mut self, chain_id: u64) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    pub fn pool(mut self, pool_address: &str) -> Self {
+        self.pools.push(pool_address.to_string());
+        self
+    }
+
+    /// Only keep swaps where the token is either bought or sold.
+    pub fn token(mut self, token: &str) -> Self {
+        self.tokens.push(token.to_string());
+        self
+    }
+}
+
+/// Reads swaps from a ClickHouse table over the HTTP interface.
+///
+/// Rows are requested as `CSVWithNames` and decoded while the response
+/// body is still being received, so large block ranges are never fully
+/// buffered in memory.
+#[derive(Debug, Clone)]
+pub struct ClickHouseReader {
+    pub url: String,
+    pub table: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+impl ClickHouseReader {
+    /// `url` is the HTTP endpoint (e.g. `http://localhost:8123`), `table` may be
+    /// database qualified (e.g. `ethereum.dex_swaps`) and must expose the `SWAP_COLUMNS`.
+    /// Whichever of the (nullable) `FEE_COLUMNS` it has are read too.
+    pub fn new(url: &str, table: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            table: table.to_string(),
+            user: None,
+            password: None,
+        }
+    }
+
+    pub fn with_credentials(mut self, user: &str, password: &str) -> Self {
+        self.user = Some(user.to_string());
+        self.password = Some(password.to_string());
+        self
+    }
+
+    /// Names of the table's columns, as listed by `DESCRIBE TABLE`.
+    pub fn table_columns(&self) -> Result<Vec<String>, String> {
+        let query = format!(
+            "DESCRIBE TABLE {} FORMAT TabSeparated",
+            quote_identifier(&self.table)
+        );
+        let description = self
+            .request()
+            .send(query)
+            .map_err(|err| format!("clickhouse query failed: {}", err))?
+            .into_body()
+            .read_to_string()
+            .map_err(|err| format!("failed to read clickhouse response: {}", err))?;
+
+        return Ok(description
+            .lines()
+            .filter_map(|line| line.split('\t').next())
+            .map(|name| name.to_string())
+            .collect());
+    }
+
+    /// Build the query and its bound parameters, selecting the `FEE_COLUMNS`
+    /// found in `table_columns`. Filter values are never interpolated into
+    /// the SQL, they are sent as ClickHouse query parameters.
+    pub fn build_query(
+        &self,
+        filter: &SwapFilter,
+        table_columns: &[String],
+    ) -> (String, Vec<(String, String)>) {
+        let mut conditions =
+            vec!["block_number BETWEEN {block_start:UInt64} AND {block_end:UInt64}".to_string()];
+        let mut params = vec![
+            (
+                "param_block_start".to_string(),
+                filter.block_start.to_string(),
+            ),
+            ("param_block_end".to_string(), filter.block_end.to_string()),
+        ];
+
+        if let Some(chain_id) = filter.chain_id {
+            conditions.push("chain_id = {chain_id:UInt64}".to_string());
+            params.push(("param_chain_id".to_string(), chain_id.to_string()));
+        }
+
+        if !filter.pools.is_empty() {
+            conditions.push("pool_address IN {pools:Array(String)}".to_string());
+            params.push(("param_pools".to_string(), array_param(&filter.pools)));
+        }
+
+        if !filter.tokens.is_empty() {
+            conditions.push(
+                "(token_in IN {tokens:Array(String)} OR token_out IN {tokens:Array(String)})"
+                    .to_string(),
+            );
+            params.push(("param_tokens".to_string(), array_param(&filter.tokens)));
+        }
+
+        let query = format!(
+            "SELECT {} FROM {} WHERE {} ORDER BY chain_id, block_number, tx_position_in_block FORMAT CSVWithNames",
+            SWAP_COLUMNS
+                .iter()
+                .chain(
+                    FEE_COLUMNS
+                        .iter()
+                        .filter(|column| table_columns.iter().any(|name| name == *column))
+                )
+                .copied()
+                .collect::<Vec<_>>()
+                .join(", "),
+            quote_identifier(&self.table),
+            conditions.join(" AND ")
+        );
+
+        return (query, params);
+    }
+
+    /// Stream the swaps matching the filter, ordered by block and position.
+    pub fn stream_swaps(
+        &self,
+        filter: &SwapFilter,
+    ) -> Result<impl Iterator<Item = Result<SwapTransaction, String>>, String> {
+        let (query, params) = self.build_query(filter, &self.table_columns()?);
+
+        // NULL fees come back as empty fields instead of `\N`, which deserialize to `None`
+        let response = self
+            .request()
+            .query_pairs(params)
+            .query("format_csv_null_representation", "")
+            .send(query)
+            .map_err(|err| format!("clickhouse query failed: {}", err))?;
+        let reader = csv::Reader::from_reader(response.into_body().into_reader());
+
+        let swaps = reader
+            .into_deserialize()
+            .map(|row| row.map_err(|err| format!("invalid clickhouse row: {}", err)));
+
+        return Ok(swaps);
+    }
+
+    fn request(&self) -> ureq::RequestBuilder<ureq::typestate::WithBody> {
+        let mut request = ureq::post(&self.url);
+        if let (Some(user), Some(password)) = (&self.user, &self.password) {
+            request = request
+                .header("X-ClickHouse-User", user)
+                .header("X-ClickHouse-Key", password);
+        }
+        return request;
+    }
+}
+
+/// Encode a list of strings as a ClickHouse `Array(String)` parameter value.
+fn array_param(values: &[String]) -> String {
+    let quoted: Vec<String> = values
+        .iter()
+        .map(|value| format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")))
+        .collect();
+    return format!("[{}]", quoted.join(","));
+}
+
+/// Quote a (possibly database qualified) table name with backticks.
+fn quote_identifier(name: &str) -> String {
+    name.split('.')
+        .map(|part| format!("`{}`", part.replace('`', "\\`")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_query_binds_filters_as_parameters() {
+        let reader = ClickHouseReader::new("http://localhost:8123/", "ethereum.dex_swaps");
+        let filter = SwapFilter::new(12360, 12366)
+            .chain(1)
+            .pool("0xpool1")
+            .token("USDC")
+            .token("it's");
+
+        let columns: Vec<String> = SWAP_COLUMNS
+            .iter()
+            .chain(FEE_COLUMNS)
+            .map(|column| column.to_string())
+            .collect();
+
+        let (query, params) = reader.build_query(&filter, &columns);
+
+        assert!(query.starts_with("SELECT tx_hash, chain_id, block_number,"));
+        assert!(query.contains(
+            "gas_cost_usd, max_fee_per_gas, max_priority_fee_per_gas, base_fee_per_gas FROM"
+        ));
+        assert!(query.contains("FROM `ethereum`.`dex_swaps`"));
+        assert!(query.contains("pool_address IN {pools:Array(String)}"));
+        assert!(query.ends_with("FORMAT CSVWithNames"));
+        assert!(
+            !query.contains("0xpool1"),
+            "Values must not be interpolated"
+        );
+
+        assert_eq!(reader.url, "http://localhost:8123");
+        assert_eq!(
+            params,
+            vec![
+                ("param_block_start".to_string(), "12360".to_string()),
+                ("param_block_end".to_string(), "12366".to_string()),
+                ("param_chain_id".to_string(), "1".to_string()),
+                ("param_pools".to_string(), "['0xpool1']".to_string()),
+                ("param_tokens".to_string(), "['USDC','it\\'s']".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_query_without_optional_filters() {
+        let reader = ClickHouseReader::new("http://localhost:8123", "swaps");
+        let columns: Vec<String> = SWAP_COLUMNS
+            .iter()
+            .chain(&["base_fee_per_gas"])
+            .map(|column| column.to_string())
+            .collect();
+        let (query, params) = reader.build_query(&SwapFilter::new(1, 2), &columns);
+
+        assert!(
+            query.contains("gas_cost_usd, base_fee_per_gas FROM"),
+            "Only the fee columns the table has are selected"
+        );
+        assert!(!query.contains("pool_address IN"));
+        assert!(!query.contains("token_in IN"));
+        assert_eq!(params.len(), 2);
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::{Path, PathBuf};
+
+pub mod checkpoint;
+#[cfg(feature = "clickhouse")]
+pub mod clickhouse;
+pub mod pool_snapshot;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// Columns a swap table (or query) must provide, matching the `SwapTransaction` fields.
+pub const SWAP_COLUMNS: &[&str] = &[
+    "tx_hash",
+    "chain_id",
+    "block_number",
+    "timestamp",
+    "tx_position_in_block",
+    "from_address",
+    "token_in",
+    "token_out",
+    "amount_in",
+    "amount_out",
+    "gas_price",
+    "pool_address",
+    "token_launch_block",
+    "is_contract_caller",
+    "usd_value_in",
+    "usd_value_out",
+    "gas_cost_usd",
+];
+
+/// Optional EIP-1559 columns, sources without them leave the fee fields unset.
+pub const FEE_COLUMNS: &[&str] = &[
+    "max_fee_per_gas",
+    "max_priority_fee_per_gas",
+    "base_fee_per_gas",
+];
+
+/// Write `contents` to a temporary file next to `path` (its full name plus
+/// `.tmp`) and rename it over `path`, so a crash mid-write keeps the
+/// previous file.
+pub(crate) fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    std::fs::write(&temporary, contents)?;
+    return std::fs::rename(&temporary, path);
+}
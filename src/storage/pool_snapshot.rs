@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::sandwich::same_block_sim::Pool;
+use crate::sandwich::transactions::BlockId;
+use crate::storage::write_atomically;
+
+/// Layout version of snapshot files, files of another version are refused.
+pub const POOL_SNAPSHOT_VERSION: u32 = 1;
+
+/// A simulation `pool_map` saved as a JSON file, so reserves reconstructed
+/// or fetched once can be reused across runs and machines.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PoolSnapshot {
+    pub version: u32,
+    /// The block the pools are the starting state of, as in
+    /// `ReserveReconstructor::pool_map`.
+    pub block: BlockId,
+    /// Tokens, reserves and fee of each pool, by address.
+    pub pools: BTreeMap<String, Pool>,
+}
+
+impl PoolSnapshot {
+    pub fn new(block: BlockId, pool_map: &HashMap<String, Pool>) -> Self {
+        return Self {
+            version: POOL_SNAPSHOT_VERSION,
+            block,
+            pools: pool_map
+                .iter()
+                .map(|(address, pool)| (address.clone(), pool.clone()))
+                .collect(),
+        };
+    }
+
+    pub fn pool_map(&self) -> HashMap<String, Pool> {
+        return self
+            .pools
+            .iter()
+            .map(|(address, pool)| (address.clone(), pool.clone()))
+            .collect();
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read pool snapshot {}: {}", path.display(), err))?;
+        let snapshot: PoolSnapshot = serde_json::from_str(&text)
+            .map_err(|err| format!("invalid pool snapshot {}: {}", path.display(), err))?;
+        if snapshot.version != POOL_SNAPSHOT_VERSION {
+            return Err(format!(
+                "pool snapshot {} has version {}, expected {}",
+                path.display(),
+                snapshot.version,
+                POOL_SNAPSHOT_VERSION
+            ));
+        }
+        return Ok(snapshot);
+    }
+
+    /// Replaces the file atomically, see `write_atomically`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|err| format!("failed to serialize pool snapshot: {}", err))?;
+        return write_atomically(path.as_ref(), &text)
+            .map_err(|err| format!("failed to write pool snapshot: {}", err));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandwich::same_block_sim::find_sandwich_attacks_by_simulation;
+
+    #[test]
+    fn test_pool_map_round_trips_through_a_snapshot_file() {
+        let transactions = crate::test_support::sample_block();
+        let pool_map = HashMap::from([
+            ("0xpool1".to_string(), crate::test_support::sample_pool()),
+            (
+                "0xpool2".to_string(),
+                Pool::new(1000.0, 3_200_000.0, "ETH".into(), "USDC".into()).with_fee_bps(5),
+            ),
+        ]);
+        let path = std::env::temp_dir().join(format!("pools-{}.json", std::process::id()));
+        let snapshot = PoolSnapshot::new(transactions[0].block_id(), &pool_map);
+        // The temporary file is named after the whole file name, another
+        // file of the same stem is left alone
+        let neighbour = path.with_extension("tmp");
+        std::fs::write(&neighbour, "not a snapshot").unwrap();
+        snapshot.save(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&neighbour).unwrap(),
+            "not a snapshot"
+        );
+        std::fs::remove_file(&neighbour).unwrap();
+
+        let loaded = PoolSnapshot::load(&path).unwrap();
+        assert_eq!(loaded, snapshot);
+        assert_eq!(loaded.block.block_number, 12360);
+        assert_eq!(loaded.pool_map(), pool_map);
+        assert_eq!(
+            find_sandwich_attacks_by_simulation(&loaded.pool_map(), &transactions),
+            find_sandwich_attacks_by_simulation(&pool_map, &transactions)
+        );
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, text.replace("\"version\": 1", "\"version\": 99")).unwrap();
+        assert!(PoolSnapshot::load(&path)
+            .unwrap_err()
+            .contains("version 99"));
+        std::fs::remove_file(&path).unwrap();
+        assert!(PoolSnapshot::load(&path).is_err());
+    }
+}
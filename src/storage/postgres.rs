@@ -0,0 +1,579 @@
+use ethnum::U256;
+use postgres::{Client, NoTls, Row};
+
+use super::{FEE_COLUMNS, SWAP_COLUMNS};
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+use crate::sandwich::same_block_sim::SandwichAttackBySimulation;
+use crate::sandwich::transactions::{raw_amount, SwapTransaction};
+
+/// Normalized schema for swaps and detected attacks.
+///
+/// Attacks only reference their legs by hash and pool, the swaps themselves
+/// live in `swap_transactions`, one row per hop of a multi-hop transaction.
+/// Heuristic and simulation evidence are kept in separate tables so either
+/// detector can write without the other.
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS swap_transactions (
+    tx_hash TEXT NOT NULL,
+    chain_id BIGINT NOT NULL DEFAULT 1,
+    block_number BIGINT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    tx_position_in_block BIGINT NOT NULL,
+    from_address TEXT NOT NULL,
+    token_in TEXT NOT NULL,
+    token_out TEXT NOT NULL,
+    amount_in DOUBLE PRECISION NOT NULL,
+    amount_out DOUBLE PRECISION NOT NULL,
+    gas_price BIGINT NOT NULL,
+    pool_address TEXT NOT NULL,
+    token_launch_block BIGINT NOT NULL,
+    is_contract_caller BOOLEAN NOT NULL,
+    usd_value_in DOUBLE PRECISION NOT NULL,
+    usd_value_out DOUBLE PRECISION NOT NULL,
+    gas_cost_usd DOUBLE PRECISION NOT NULL,
+    max_fee_per_gas BIGINT,
+    max_priority_fee_per_gas BIGINT,
+    base_fee_per_gas BIGINT,
+    raw_amount_in TEXT,
+    PRIMARY KEY (tx_hash, pool_address)
+);
+
+CREATE TABLE IF NOT EXISTS sandwich_attacks (
+    attack_id TEXT PRIMARY KEY,
+    chain_id BIGINT NOT NULL DEFAULT 1,
+    block_number BIGINT NOT NULL,
+    front_run_tx_hash TEXT NOT NULL,
+    victim_tx_hash TEXT NOT NULL,
+    back_run_tx_hash TEXT NOT NULL,
+    attacker_address TEXT NOT NULL,
+    victim_address TEXT NOT NULL,
+    pool_address TEXT NOT NULL,
+    front_run_pool_address TEXT NOT NULL,
+    back_run_pool_address TEXT NOT NULL,
+    FOREIGN KEY (front_run_tx_hash, front_run_pool_address)
+        REFERENCES swap_transactions (tx_hash, pool_address),
+    FOREIGN KEY (victim_tx_hash, pool_address)
+        REFERENCES swap_transactions (tx_hash, pool_address),
+    FOREIGN KEY (back_run_tx_hash, back_run_pool_address)
+        REFERENCES swap_transactions (tx_hash, pool_address)
+);
+
+CREATE INDEX IF NOT EXISTS sandwich_attacks_block_number_idx
+    ON sandwich_attacks (chain_id, block_number);
+
+CREATE TABLE IF NOT EXISTS sandwich_heuristic_results (
+    attack_id TEXT PRIMARY KEY REFERENCES sandwich_attacks (attack_id),
+    confidence_score REAL NOT NULL,
+    higher_front_gas_price BOOLEAN NOT NULL,
+    lower_back_gas_price BOOLEAN NOT NULL,
+    front_is_contract BOOLEAN NOT NULL,
+    back_is_contract BOOLEAN NOT NULL,
+    is_profitable BOOLEAN NOT NULL,
+    is_proportional BOOLEAN NOT NULL,
+    price_impact_rate REAL NOT NULL,
+    total_profit_usd DOUBLE PRECISION NOT NULL,
+    price_impact_depth_scale REAL NOT NULL DEFAULT 1,
+    is_known_bot BOOLEAN NOT NULL DEFAULT FALSE,
+    likely_private_bundle BOOLEAN NOT NULL DEFAULT FALSE,
+    uses_flashloan BOOLEAN NOT NULL DEFAULT FALSE,
+    attacker_repeat_offender REAL NOT NULL DEFAULT 0,
+    same_entity BOOLEAN NOT NULL DEFAULT FALSE,
+    swap_size_factor REAL NOT NULL DEFAULT 0,
+    bot_activity_factor REAL NOT NULL DEFAULT 0,
+    paid_builder BOOLEAN NOT NULL DEFAULT FALSE,
+    profitable_only_before_bribe BOOLEAN NOT NULL DEFAULT FALSE,
+    front_gas_zscore REAL NOT NULL DEFAULT 0,
+    custom_flags TEXT[] NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE IF NOT EXISTS sandwich_simulation_results (
+    attack_id TEXT PRIMARY KEY REFERENCES sandwich_attacks (attack_id),
+    victim_loss_percentage DOUBLE PRECISION NOT NULL
+);
+";
+
+/// Where to read swaps from.
+#[derive(Debug, Clone)]
+pub enum SwapSource {
+    /// Read every row of a table exposing the `SWAP_COLUMNS`, plus whichever
+    /// of the `FEE_COLUMNS` and `raw_amount_in` it has.
+    Table(String),
+    /// Run an arbitrary query, its result set must expose the `SWAP_COLUMNS`
+    /// and may expose the `FEE_COLUMNS` and `raw_amount_in`.
+    Query(String),
+}
+
+/// Quote a (possibly schema qualified) table name so it can't inject SQL.
+fn quote_identifier(name: &str) -> String {
+    name.split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+pub struct PostgresStore {
+    client: Client,
+}
+
+impl PostgresStore {
+    /// Connect using a libpq style connection string,
+    /// e.g. `host=localhost user=postgres dbname=toxicflow`.
+    ///
+    /// TODO: Support TLS connections for managed databases.
+    pub fn connect(connection_string: &str) -> Result<Self, String> {
+        let client = Client::connect(connection_string, NoTls)
+            .map_err(|err| format!("failed to connect to postgres: {}", err))?;
+        Ok(Self { client })
+    }
+
+    /// Create the tables of the normalized schema if they don't exist yet.
+    pub fn migrate(&mut self) -> Result<(), String> {
+        self.client
+            .batch_execute(SCHEMA)
+            .map_err(|err| format!("failed to create schema: {}", err))
+    }
+
+    pub fn load_swaps(&mut self, source: &SwapSource) -> Result<Vec<SwapTransaction>, String> {
+        let sql = match source {
+            SwapSource::Table(table) => self.table_query(table)?,
+            SwapSource::Query(query) => query.clone(),
+        };
+        let rows = self
+            .client
+            .query(sql.as_str(), &[])
+            .map_err(|err| format!("failed to query swaps: {}", err))?;
+
+        rows.iter().map(swap_from_row).collect()
+    }
+
+    /// Select the `SWAP_COLUMNS` of a table and the optional columns it has,
+    /// so tables created without the fee fields still load.
+    fn table_query(&mut self, table: &str) -> Result<String, String> {
+        let table = quote_identifier(table);
+        let existing: Vec<String> = self
+            .client
+            .query(
+                "SELECT attname::TEXT FROM pg_attribute
+                WHERE attrelid = to_regclass($1) AND attnum > 0 AND NOT attisdropped",
+                &[&table],
+            )
+            .map_err(|err| format!("failed to read columns of {}: {}", table, err))?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        let optional = FEE_COLUMNS
+            .iter()
+            .chain(&["raw_amount_in"])
+            .filter(|column| existing.iter().any(|name| name == *column));
+        return Ok(format!(
+            "SELECT {} FROM {} ORDER BY chain_id, block_number, tx_position_in_block",
+            SWAP_COLUMNS
+                .iter()
+                .chain(optional)
+                .copied()
+                .collect::<Vec<_>>()
+                .join(", "),
+            table
+        ));
+    }
+
+    pub fn save_swaps(&mut self, swaps: &[SwapTransaction]) -> Result<(), String> {
+        let mut transaction = self
+            .client
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+
+        for swap in swaps {
+            upsert_swap(&mut transaction, swap)?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit swaps: {}", err))
+    }
+
+    /// Write attacks found by the heuristics, together with their legs and
+    /// confidence flags. Re-saving an attack overwrites its previous evidence.
+    pub fn save_attacks(&mut self, attacks: &[SandwichAttackByHeuristics]) -> Result<(), String> {
+        let mut transaction = self
+            .client
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+
+        for attack in attacks {
+            let attack_id = attack.attack_id();
+            upsert_attack(
+                &mut transaction,
+                &attack_id,
+                &attack.front_run_tx,
+                &attack.victim_tx,
+                &attack.back_run_tx,
+            )?;
+
+            let flags = &attack.confidence_flags;
+            transaction
+                .execute(
+                    "INSERT INTO sandwich_heuristic_results (
+                        attack_id, confidence_score, higher_front_gas_price, lower_back_gas_price,
+                        front_is_contract, back_is_contract, is_profitable, is_proportional,
+                        price_impact_rate, total_profit_usd, price_impact_depth_scale,
+                        is_known_bot, likely_private_bundle, uses_flashloan,
+                        attacker_repeat_offender, same_entity, swap_size_factor,
+                        bot_activity_factor, paid_builder, profitable_only_before_bribe,
+                        front_gas_zscore, custom_flags
+                    ) VALUES (
+                        $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16,
+                        $17, $18, $19, $20, $21, $22
+                    )
+                    ON CONFLICT (attack_id) DO UPDATE SET
+                        confidence_score = EXCLUDED.confidence_score,
+                        higher_front_gas_price = EXCLUDED.higher_front_gas_price,
+                        lower_back_gas_price = EXCLUDED.lower_back_gas_price,
+                        front_is_contract = EXCLUDED.front_is_contract,
+                        back_is_contract = EXCLUDED.back_is_contract,
+                        is_profitable = EXCLUDED.is_profitable,
+                        is_proportional = EXCLUDED.is_proportional,
+                        price_impact_rate = EXCLUDED.price_impact_rate,
+                        total_profit_usd = EXCLUDED.total_profit_usd,
+                        price_impact_depth_scale = EXCLUDED.price_impact_depth_scale,
+                        is_known_bot = EXCLUDED.is_known_bot,
+                        likely_private_bundle = EXCLUDED.likely_private_bundle,
+                        uses_flashloan = EXCLUDED.uses_flashloan,
+                        attacker_repeat_offender = EXCLUDED.attacker_repeat_offender,
+                        same_entity = EXCLUDED.same_entity,
+                        swap_size_factor = EXCLUDED.swap_size_factor,
+                        bot_activity_factor = EXCLUDED.bot_activity_factor,
+                        paid_builder = EXCLUDED.paid_builder,
+                        profitable_only_before_bribe = EXCLUDED.profitable_only_before_bribe,
+                        front_gas_zscore = EXCLUDED.front_gas_zscore,
+                        custom_flags = EXCLUDED.custom_flags",
+                    &[
+                        &attack_id,
+                        &attack.confidence_score,
+                        &flags.higher_front_gas_price,
+                        &flags.lower_back_gas_price,
+                        &flags.front_is_contract,
+                        &flags.back_is_contract,
+                        &flags.is_profitable,
+                        &flags.is_proportional,
+                        &flags.price_impact_rate,
+                        &flags.total_profit_usd,
+                        &flags.price_impact_depth_scale,
+                        &flags.is_known_bot,
+                        &flags.likely_private_bundle,
+                        &flags.uses_flashloan,
+                        &flags.attacker_repeat_offender,
+                        &flags.same_entity,
+                        &flags.swap_size_factor,
+                        &flags.bot_activity_factor,
+                        &flags.paid_builder,
+                        &flags.profitable_only_before_bribe,
+                        &flags.front_gas_zscore,
+                        &flags.custom_flags,
+                    ],
+                )
+                .map_err(|err| format!("failed to save attack {}: {}", attack_id, err))?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit attacks: {}", err))
+    }
+
+    /// Write attacks confirmed by simulation, together with their legs and victim loss.
+    pub fn save_simulated_attacks(
+        &mut self,
+        attacks: &[SandwichAttackBySimulation],
+    ) -> Result<(), String> {
+        let mut transaction = self
+            .client
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+
+        for attack in attacks {
+            let attack_id = attack.attack_id();
+            upsert_attack(
+                &mut transaction,
+                &attack_id,
+                &attack.front_run_tx,
+                &attack.victim_tx,
+                &attack.back_run_tx,
+            )?;
+
+            transaction
+                .execute(
+                    "INSERT INTO sandwich_simulation_results (attack_id, victim_loss_percentage)
+                    VALUES ($1, $2)
+                    ON CONFLICT (attack_id) DO UPDATE SET
+                        victim_loss_percentage = EXCLUDED.victim_loss_percentage",
+                    &[&attack_id, &attack.victim_loss_percentage],
+                )
+                .map_err(|err| format!("failed to save attack {}: {}", attack_id, err))?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit attacks: {}", err))
+    }
+}
+
+fn upsert_swap(
+    transaction: &mut postgres::Transaction,
+    swap: &SwapTransaction,
+) -> Result<(), String> {
+    transaction
+        .execute(
+            "INSERT INTO swap_transactions (
+                tx_hash, chain_id, block_number, timestamp, tx_position_in_block, from_address,
+                token_in, token_out, amount_in, amount_out, gas_price, pool_address,
+                token_launch_block, is_contract_caller, usd_value_in, usd_value_out, gas_cost_usd,
+                max_fee_per_gas, max_priority_fee_per_gas, base_fee_per_gas, raw_amount_in
+            ) VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17,
+                $18, $19, $20, $21
+            )
+            ON CONFLICT (tx_hash, pool_address) DO NOTHING",
+            &[
+                &swap.tx_hash,
+                &to_i64(swap.chain_id)?,
+                &to_i64(swap.block_number)?,
+                &to_i64(swap.timestamp)?,
+                &i64::from(swap.tx_position_in_block),
+                &swap.from_address,
+                &swap.token_in,
+                &swap.token_out,
+                &swap.amount_in,
+                &swap.amount_out,
+                &to_i64(swap.gas_price)?,
+                &swap.pool_address,
+                &to_i64(swap.token_launch_block)?,
+                &swap.is_contract_caller,
+                &swap.usd_value_in,
+                &swap.usd_value_out,
+                &swap.gas_cost_usd,
+                &swap.max_fee_per_gas.map(to_i64).transpose()?,
+                &swap.max_priority_fee_per_gas.map(to_i64).transpose()?,
+                &swap.base_fee_per_gas.map(to_i64).transpose()?,
+                &swap.raw_amount_in.map(|amount| amount.to_string()),
+            ],
+        )
+        .map_err(|err| format!("failed to save swap {}: {}", swap.tx_hash, err))?;
+
+    return Ok(());
+}
+
+fn upsert_attack(
+    transaction: &mut postgres::Transaction,
+    attack_id: &str,
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+) -> Result<(), String> {
+    for swap in [front, victim, back] {
+        upsert_swap(transaction, swap)?;
+    }
+
+    transaction
+        .execute(
+            "INSERT INTO sandwich_attacks (
+                attack_id, chain_id, block_number, front_run_tx_hash, victim_tx_hash,
+                back_run_tx_hash, attacker_address, victim_address, pool_address,
+                front_run_pool_address, back_run_pool_address
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (attack_id) DO NOTHING",
+            &[
+                &attack_id,
+                &to_i64(victim.chain_id)?,
+                &to_i64(victim.block_number)?,
+                &front.tx_hash,
+                &victim.tx_hash,
+                &back.tx_hash,
+                &front.from_address,
+                &victim.from_address,
+                &victim.pool_address,
+                &front.pool_address,
+                &back.pool_address,
+            ],
+        )
+        .map_err(|err| format!("failed to save attack {}: {}", attack_id, err))?;
+
+    return Ok(());
+}
+
+fn to_i64(value: u64) -> Result<i64, String> {
+    i64::try_from(value).map_err(|_| format!("{} does not fit in a BIGINT column", value))
+}
+
+/// Read an unsigned integer column, accepting both `INTEGER` and `BIGINT` columns.
+fn get_u64(row: &Row, column: &str) -> Result<u64, String> {
+    let value = match row.try_get::<_, i64>(column) {
+        Ok(value) => value,
+        Err(_) => row
+            .try_get::<_, i32>(column)
+            .map(i64::from)
+            .map_err(|err| format!("invalid column {}: {}", column, err))?,
+    };
+
+    u64::try_from(value).map_err(|_| format!("column {} is negative: {}", column, value))
+}
+
+/// An optional fee column, unset when the result set doesn't have it.
+fn get_fee(row: &Row, column: &str) -> Result<Option<u64>, String> {
+    if !row.columns().iter().any(|field| field.name() == column) {
+        return Ok(None);
+    }
+    let value: Option<i64> = get(row, column)?;
+    return value
+        .map(|value| {
+            u64::try_from(value).map_err(|_| format!("column {} is negative: {}", column, value))
+        })
+        .transpose();
+}
+
+/// The exact input amount as a decimal string, unset when the result set doesn't have it.
+fn get_raw_amount(row: &Row) -> Result<Option<U256>, String> {
+    if !row
+        .columns()
+        .iter()
+        .any(|field| field.name() == "raw_amount_in")
+    {
+        return Ok(None);
+    }
+    let value: Option<String> = get(row, "raw_amount_in")?;
+    return value
+        .map(|value| {
+            raw_amount::parse(&value)
+                .map_err(|err| format!("invalid column raw_amount_in: {}", err))
+        })
+        .transpose();
+}
+
+fn get<'a, T: postgres::types::FromSql<'a>>(row: &'a Row, column: &str) -> Result<T, String> {
+    row.try_get(column)
+        .map_err(|err| format!("invalid column {}: {}", column, err))
+}
+
+fn swap_from_row(row: &Row) -> Result<SwapTransaction, String> {
+    let tx_position_in_block = get_u64(row, "tx_position_in_block")?;
+
+    Ok(SwapTransaction {
+        tx_hash: get(row, "tx_hash")?,
+        chain_id: get_u64(row, "chain_id")?,
+        block_number: get_u64(row, "block_number")?,
+        timestamp: get_u64(row, "timestamp")?,
+        tx_position_in_block: u32::try_from(tx_position_in_block).map_err(|_| {
+            format!(
+                "tx_position_in_block out of range: {}",
+                tx_position_in_block
+            )
+        })?,
+        from_address: get(row, "from_address")?,
+        token_in: get(row, "token_in")?,
+        token_out: get(row, "token_out")?,
+        amount_in: get(row, "amount_in")?,
+        amount_out: get(row, "amount_out")?,
+        gas_price: get_u64(row, "gas_price")?,
+        pool_address: get(row, "pool_address")?,
+        token_launch_block: get_u64(row, "token_launch_block")?,
+        is_contract_caller: get(row, "is_contract_caller")?,
+        usd_value_in: get(row, "usd_value_in")?,
+        usd_value_out: get(row, "usd_value_out")?,
+        gas_cost_usd: get(row, "gas_cost_usd")?,
+        max_fee_per_gas: get_fee(row, "max_fee_per_gas")?,
+        max_priority_fee_per_gas: get_fee(row, "max_priority_fee_per_gas")?,
+        base_fee_per_gas: get_fee(row, "base_fee_per_gas")?,
+        raw_amount_in: get_raw_amount(row)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::csv::sample_transactions;
+    use crate::sandwich::find_same_block_sandwiches;
+
+    /// Needs a disposable database, e.g.
+    /// `TOXICFLOW_TEST_POSTGRES="host=localhost user=postgres dbname=toxicflow_test"`,
+    /// skipped without it.
+    #[test]
+    fn test_multi_hop_swaps_and_flags_round_trip() {
+        let connection_string = match std::env::var("TOXICFLOW_TEST_POSTGRES") {
+            Ok(connection_string) => connection_string,
+            Err(_) => return,
+        };
+        let mut store = PostgresStore::connect(&connection_string).expect("Failed to connect");
+        store
+            .client
+            .batch_execute(
+                "DROP TABLE IF EXISTS sandwich_heuristic_results, sandwich_simulation_results,
+                    sandwich_attacks, swap_transactions",
+            )
+            .unwrap();
+        store.migrate().expect("Failed to migrate");
+        store.migrate().expect("Migrating twice must be a no-op");
+
+        let mut transactions = sample_transactions();
+        let victim = transactions
+            .iter()
+            .find(|tx| tx.block_number == 12360 && tx.tx_position_in_block == 2)
+            .expect("Sample block 12360 has a victim")
+            .clone();
+        let mut second_hop = victim.clone();
+        second_hop.pool_address = "0xpool2".to_string();
+        second_hop.max_fee_per_gas = Some(60_000_000_000);
+        second_hop.max_priority_fee_per_gas = Some(2_000_000_000);
+        second_hop.base_fee_per_gas = Some(30_000_000_000);
+        second_hop.raw_amount_in = Some(U256::from(u64::MAX) * U256::from(1_000_000_007u64));
+        transactions.push(second_hop.clone());
+        store.save_swaps(&transactions).unwrap();
+
+        let mut attacks = find_same_block_sandwiches(&transactions);
+        attacks.retain(|attack| attack.victim_tx.tx_hash == victim.tx_hash);
+        attacks[0].confidence_flags.paid_builder = true;
+        attacks[0].confidence_flags.custom_flags = vec!["large_victim".to_string()];
+        store.save_attacks(&attacks).unwrap();
+
+        let loaded = store
+            .load_swaps(&SwapSource::Table("swap_transactions".to_string()))
+            .unwrap();
+        assert_eq!(loaded.len(), transactions.len());
+        assert!(loaded.contains(&second_hop));
+        let loaded_hop = loaded
+            .iter()
+            .find(|swap| swap.tx_hash == victim.tx_hash && swap.pool_address == "0xpool2")
+            .unwrap();
+        assert_eq!(loaded_hop.max_fee_per_gas, Some(60_000_000_000));
+        assert_eq!(loaded_hop.max_priority_fee_per_gas, Some(2_000_000_000));
+        assert_eq!(loaded_hop.base_fee_per_gas, Some(30_000_000_000));
+        assert_eq!(loaded_hop.raw_amount_in, second_hop.raw_amount_in);
+
+        // A user table without the optional columns still loads
+        store
+            .client
+            .batch_execute(&format!(
+                "DROP TABLE IF EXISTS plain_swaps;
+                CREATE TABLE plain_swaps AS SELECT {} FROM swap_transactions",
+                SWAP_COLUMNS.join(", ")
+            ))
+            .unwrap();
+        let plain = store
+            .load_swaps(&SwapSource::Table("plain_swaps".to_string()))
+            .unwrap();
+        assert_eq!(plain.len(), transactions.len());
+        assert!(plain.iter().all(|swap| swap.max_fee_per_gas.is_none()));
+
+        let row = store
+            .client
+            .query_one(
+                "SELECT paid_builder, custom_flags FROM sandwich_heuristic_results",
+                &[],
+            )
+            .unwrap();
+        assert!(row.get::<_, bool>("paid_builder"));
+        assert_eq!(
+            row.get::<_, Vec<String>>("custom_flags"),
+            vec!["large_victim".to_string()]
+        );
+    }
+}
@@ -0,0 +1,1140 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, Row};
+
+use super::checkpoint::{Checkpoint, CheckpointStore};
+use super::{FEE_COLUMNS, SWAP_COLUMNS};
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+use crate::sandwich::same_block_sim::{Pool, SandwichAttackBySimulation};
+use crate::sandwich::transactions::{raw_amount, BlockId, SwapTransaction};
+
+/// Same normalized layout as the Postgres schema, plus pool snapshots and
+/// the set of blocks that were already analyzed.
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS swap_transactions (
+    tx_hash TEXT NOT NULL,
+    chain_id INTEGER NOT NULL DEFAULT 1,
+    block_number INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    tx_position_in_block INTEGER NOT NULL,
+    from_address TEXT NOT NULL,
+    token_in TEXT NOT NULL,
+    token_out TEXT NOT NULL,
+    amount_in REAL NOT NULL,
+    amount_out REAL NOT NULL,
+    gas_price INTEGER NOT NULL,
+    pool_address TEXT NOT NULL,
+    token_launch_block INTEGER NOT NULL,
+    is_contract_caller INTEGER NOT NULL,
+    usd_value_in REAL NOT NULL,
+    usd_value_out REAL NOT NULL,
+    gas_cost_usd REAL NOT NULL,
+    max_fee_per_gas INTEGER,
+    max_priority_fee_per_gas INTEGER,
+    base_fee_per_gas INTEGER,
+    raw_amount_in TEXT,
+    PRIMARY KEY (tx_hash, pool_address)
+);
+
+CREATE INDEX IF NOT EXISTS swap_transactions_block_number_idx
+    ON swap_transactions (chain_id, block_number);
+
+CREATE TABLE IF NOT EXISTS sandwich_attacks (
+    attack_id TEXT PRIMARY KEY,
+    chain_id INTEGER NOT NULL DEFAULT 1,
+    block_number INTEGER NOT NULL,
+    front_run_tx_hash TEXT NOT NULL,
+    victim_tx_hash TEXT NOT NULL,
+    back_run_tx_hash TEXT NOT NULL,
+    attacker_address TEXT NOT NULL,
+    victim_address TEXT NOT NULL,
+    pool_address TEXT NOT NULL,
+    front_run_pool_address TEXT NOT NULL,
+    back_run_pool_address TEXT NOT NULL,
+    FOREIGN KEY (front_run_tx_hash, front_run_pool_address)
+        REFERENCES swap_transactions (tx_hash, pool_address),
+    FOREIGN KEY (victim_tx_hash, pool_address)
+        REFERENCES swap_transactions (tx_hash, pool_address),
+    FOREIGN KEY (back_run_tx_hash, back_run_pool_address)
+        REFERENCES swap_transactions (tx_hash, pool_address)
+);
+
+CREATE TABLE IF NOT EXISTS sandwich_heuristic_results (
+    attack_id TEXT PRIMARY KEY REFERENCES sandwich_attacks (attack_id),
+    confidence_score REAL NOT NULL,
+    higher_front_gas_price INTEGER NOT NULL,
+    lower_back_gas_price INTEGER NOT NULL,
+    front_is_contract INTEGER NOT NULL,
+    back_is_contract INTEGER NOT NULL,
+    is_profitable INTEGER NOT NULL,
+    is_proportional INTEGER NOT NULL,
+    price_impact_rate REAL NOT NULL,
+    total_profit_usd REAL NOT NULL,
+    price_impact_depth_scale REAL NOT NULL DEFAULT 1,
+    is_known_bot INTEGER NOT NULL DEFAULT 0,
+    likely_private_bundle INTEGER NOT NULL DEFAULT 0,
+    uses_flashloan INTEGER NOT NULL DEFAULT 0,
+    attacker_repeat_offender REAL NOT NULL DEFAULT 0,
+    same_entity INTEGER NOT NULL DEFAULT 0,
+    swap_size_factor REAL NOT NULL DEFAULT 0,
+    bot_activity_factor REAL NOT NULL DEFAULT 0,
+    paid_builder INTEGER NOT NULL DEFAULT 0,
+    profitable_only_before_bribe INTEGER NOT NULL DEFAULT 0,
+    front_gas_zscore REAL NOT NULL DEFAULT 0,
+    -- JSON array of rule names
+    custom_flags TEXT NOT NULL DEFAULT '[]'
+);
+
+CREATE TABLE IF NOT EXISTS sandwich_simulation_results (
+    attack_id TEXT PRIMARY KEY REFERENCES sandwich_attacks (attack_id),
+    victim_loss_percentage REAL NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS pool_snapshots (
+    chain_id INTEGER NOT NULL,
+    pool_address TEXT NOT NULL,
+    block_number INTEGER NOT NULL,
+    token_a_address TEXT NOT NULL,
+    token_b_address TEXT NOT NULL,
+    token_a_reserve REAL NOT NULL,
+    token_b_reserve REAL NOT NULL,
+    fee_bps INTEGER NOT NULL DEFAULT 30,
+    PRIMARY KEY (chain_id, pool_address, block_number)
+);
+
+CREATE TABLE IF NOT EXISTS processed_blocks (
+    chain_id INTEGER NOT NULL,
+    block_number INTEGER NOT NULL,
+    PRIMARY KEY (chain_id, block_number)
+);
+
+CREATE TABLE IF NOT EXISTS scan_checkpoints (
+    chain_id INTEGER PRIMARY KEY,
+    last_block INTEGER NOT NULL
+);
+";
+
+/// USD lost by the victim as simulated, NULL for attacks only the heuristics
+/// found: their profit estimate says nothing about the victim's loss.
+const LOSS_USD_SQL: &str = "v.usd_value_in * s.victim_loss_percentage / 100.0";
+
+/// Filters for `SqliteStore::query_attacks`, unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AttackQuery {
+    pub attack_id: Option<String>,
+    pub chain_id: Option<u64>,
+    pub pool_address: Option<String>,
+    pub attacker: Option<String>,
+    pub victim: Option<String>,
+    /// Inclusive block range.
+    pub block_start: Option<u64>,
+    pub block_end: Option<u64>,
+    /// Inclusive range of the victim transaction's timestamp.
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    /// Attacks without a simulated loss never match.
+    pub min_loss_usd: Option<f64>,
+    /// Only attacks scored by the heuristics can match.
+    pub min_confidence: Option<f32>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+/// An attack as stored, with the evidence of whichever detectors found it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredAttack {
+    pub attack_id: String,
+    pub chain_id: u64,
+    pub block_number: u64,
+    pub timestamp: u64,
+    pub pool_address: String,
+    pub attacker: String,
+    pub victim: String,
+    pub front_run_tx: String,
+    pub victim_tx: String,
+    pub back_run_tx: String,
+    pub confidence_score: Option<f32>,
+    pub total_profit_usd: Option<f64>,
+    pub victim_loss_percentage: Option<f64>,
+    /// See `LOSS_USD_SQL`.
+    pub loss_usd: Option<f64>,
+}
+
+/// A page of `SqliteStore::get_attacks`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttackPage {
+    pub attacks: Vec<StoredAttack>,
+    /// Where the next page starts, `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// Position in the `ORDER BY` of attack queries, serialized as
+/// `chain_id:block_number:attack_id`.
+struct AttackCursor {
+    chain_id: u64,
+    block_number: u64,
+    attack_id: String,
+}
+
+impl AttackCursor {
+    fn parse(cursor: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid cursor: {}", cursor);
+        let mut parts = cursor.splitn(3, ':');
+        let mut number = || -> Result<u64, String> {
+            return parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .ok_or_else(invalid);
+        };
+        let chain_id = number()?;
+        let block_number = number()?;
+        let attack_id = match parts.next() {
+            Some(attack_id) => attack_id.to_string(),
+            None => return Err(invalid()),
+        };
+        return Ok(Self {
+            chain_id,
+            block_number,
+            attack_id,
+        });
+    }
+}
+
+impl From<&StoredAttack> for AttackCursor {
+    fn from(attack: &StoredAttack) -> Self {
+        return Self {
+            chain_id: attack.chain_id,
+            block_number: attack.block_number,
+            attack_id: attack.attack_id.clone(),
+        };
+    }
+}
+
+impl std::fmt::Display for AttackCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.chain_id, self.block_number, self.attack_id
+        )
+    }
+}
+
+pub struct SqliteStore {
+    connection: Connection,
+}
+
+impl SqliteStore {
+    /// Open (or create) a store at the given path and make sure the schema exists.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let connection = Connection::open(path)
+            .map_err(|err| format!("failed to open sqlite database: {}", err))?;
+        return Self::from_connection(connection);
+    }
+
+    pub fn open_in_memory() -> Result<Self, String> {
+        let connection = Connection::open_in_memory()
+            .map_err(|err| format!("failed to open sqlite database: {}", err))?;
+        return Self::from_connection(connection);
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self, String> {
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|err| format!("failed to create schema: {}", err))?;
+        Ok(Self { connection })
+    }
+
+    pub fn save_swaps(&mut self, swaps: &[SwapTransaction]) -> Result<(), String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+
+        for swap in swaps {
+            upsert_swap(&transaction, swap)?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit swaps: {}", err))
+    }
+
+    /// Load every stored swap ordered by block and position.
+    pub fn load_swaps(&self) -> Result<Vec<SwapTransaction>, String> {
+        return self.query_swaps("1 = 1", params![]);
+    }
+
+    /// Load the swaps of an (inclusive) block range of a chain ordered by block and position.
+    pub fn load_swaps_in_blocks(
+        &self,
+        chain_id: u64,
+        block_start: u64,
+        block_end: u64,
+    ) -> Result<Vec<SwapTransaction>, String> {
+        return self.query_swaps(
+            "chain_id = ?1 AND block_number BETWEEN ?2 AND ?3",
+            params![chain_id as i64, block_start as i64, block_end as i64],
+        );
+    }
+
+    fn query_swaps(
+        &self,
+        condition: &str,
+        query_params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<SwapTransaction>, String> {
+        let sql = format!(
+            "SELECT {} FROM swap_transactions WHERE {} ORDER BY chain_id, block_number, tx_position_in_block",
+            SWAP_COLUMNS
+                .iter()
+                .chain(FEE_COLUMNS)
+                .chain(&["raw_amount_in"])
+                .copied()
+                .collect::<Vec<_>>()
+                .join(", "),
+            condition
+        );
+        let mut statement = self
+            .connection
+            .prepare(&sql)
+            .map_err(|err| format!("failed to query swaps: {}", err))?;
+
+        let swaps = statement
+            .query_map(query_params, swap_from_row)
+            .map_err(|err| format!("failed to query swaps: {}", err))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("invalid swap row: {}", err))?;
+
+        return Ok(swaps);
+    }
+
+    /// Write attacks found by the heuristics. Saving is idempotent: storing the
+    /// same attack again (same attack ID) replaces its evidence instead of duplicating it.
+    pub fn save_attacks(&mut self, attacks: &[SandwichAttackByHeuristics]) -> Result<(), String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+
+        for attack in attacks {
+            let attack_id = attack.attack_id();
+            upsert_attack(
+                &transaction,
+                &attack_id,
+                &attack.front_run_tx,
+                &attack.victim_tx,
+                &attack.back_run_tx,
+            )?;
+
+            let flags = &attack.confidence_flags;
+            let custom_flags = serde_json::to_string(&flags.custom_flags)
+                .map_err(|err| format!("failed to encode custom flags: {}", err))?;
+            transaction
+                .execute(
+                    "INSERT INTO sandwich_heuristic_results (
+                        attack_id, confidence_score, higher_front_gas_price, lower_back_gas_price,
+                        front_is_contract, back_is_contract, is_profitable, is_proportional,
+                        price_impact_rate, total_profit_usd, price_impact_depth_scale,
+                        is_known_bot, likely_private_bundle, uses_flashloan,
+                        attacker_repeat_offender, same_entity, swap_size_factor,
+                        bot_activity_factor, paid_builder, profitable_only_before_bribe,
+                        front_gas_zscore, custom_flags
+                    ) VALUES (
+                        ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                        ?17, ?18, ?19, ?20, ?21, ?22
+                    )
+                    ON CONFLICT (attack_id) DO UPDATE SET
+                        confidence_score = excluded.confidence_score,
+                        higher_front_gas_price = excluded.higher_front_gas_price,
+                        lower_back_gas_price = excluded.lower_back_gas_price,
+                        front_is_contract = excluded.front_is_contract,
+                        back_is_contract = excluded.back_is_contract,
+                        is_profitable = excluded.is_profitable,
+                        is_proportional = excluded.is_proportional,
+                        price_impact_rate = excluded.price_impact_rate,
+                        total_profit_usd = excluded.total_profit_usd,
+                        price_impact_depth_scale = excluded.price_impact_depth_scale,
+                        is_known_bot = excluded.is_known_bot,
+                        likely_private_bundle = excluded.likely_private_bundle,
+                        uses_flashloan = excluded.uses_flashloan,
+                        attacker_repeat_offender = excluded.attacker_repeat_offender,
+                        same_entity = excluded.same_entity,
+                        swap_size_factor = excluded.swap_size_factor,
+                        bot_activity_factor = excluded.bot_activity_factor,
+                        paid_builder = excluded.paid_builder,
+                        profitable_only_before_bribe = excluded.profitable_only_before_bribe,
+                        front_gas_zscore = excluded.front_gas_zscore,
+                        custom_flags = excluded.custom_flags",
+                    params![
+                        attack_id,
+                        attack.confidence_score,
+                        flags.higher_front_gas_price,
+                        flags.lower_back_gas_price,
+                        flags.front_is_contract,
+                        flags.back_is_contract,
+                        flags.is_profitable,
+                        flags.is_proportional,
+                        flags.price_impact_rate,
+                        flags.total_profit_usd,
+                        flags.price_impact_depth_scale,
+                        flags.is_known_bot,
+                        flags.likely_private_bundle,
+                        flags.uses_flashloan,
+                        flags.attacker_repeat_offender,
+                        flags.same_entity,
+                        flags.swap_size_factor,
+                        flags.bot_activity_factor,
+                        flags.paid_builder,
+                        flags.profitable_only_before_bribe,
+                        flags.front_gas_zscore,
+                        custom_flags,
+                    ],
+                )
+                .map_err(|err| format!("failed to save attack {}: {}", attack_id, err))?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit attacks: {}", err))
+    }
+
+    /// Write attacks confirmed by simulation, idempotent like `save_attacks`.
+    pub fn save_simulated_attacks(
+        &mut self,
+        attacks: &[SandwichAttackBySimulation],
+    ) -> Result<(), String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+
+        for attack in attacks {
+            let attack_id = attack.attack_id();
+            upsert_attack(
+                &transaction,
+                &attack_id,
+                &attack.front_run_tx,
+                &attack.victim_tx,
+                &attack.back_run_tx,
+            )?;
+
+            transaction
+                .execute(
+                    "INSERT INTO sandwich_simulation_results (attack_id, victim_loss_percentage)
+                    VALUES (?1, ?2)
+                    ON CONFLICT (attack_id) DO UPDATE SET
+                        victim_loss_percentage = excluded.victim_loss_percentage",
+                    params![attack_id, attack.victim_loss_percentage],
+                )
+                .map_err(|err| format!("failed to save attack {}: {}", attack_id, err))?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit attacks: {}", err))
+    }
+
+    /// IDs of all stored attacks, whichever detector found them.
+    pub fn attack_ids(&self) -> Result<Vec<String>, String> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT attack_id FROM sandwich_attacks ORDER BY chain_id, block_number, attack_id",
+            )
+            .map_err(|err| format!("failed to query attacks: {}", err))?;
+
+        let ids = statement
+            .query_map([], |row| row.get(0))
+            .map_err(|err| format!("failed to query attacks: {}", err))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|err| format!("invalid attack row: {}", err))?;
+
+        return Ok(ids);
+    }
+
+    /// Stored attacks matching `query`, with the evidence of whichever detectors found them.
+    pub fn query_attacks(&self, query: &AttackQuery) -> Result<Vec<StoredAttack>, String> {
+        return self.select_attacks(query, None);
+    }
+
+    /// One page of at most `limit` attacks matching `filter`, starting after
+    /// `cursor` (the `next_cursor` of the previous page, `None` for the first).
+    ///
+    /// Unlike `offset`, a cursor stays cheap deep into large result sets and
+    /// doesn't skip or repeat attacks stored between two requests.
+    /// `filter.limit` and `filter.offset` are ignored, a zero `limit` is
+    /// rejected since its page could never move the cursor.
+    pub fn get_attacks(
+        &self,
+        filter: &AttackQuery,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<AttackPage, String> {
+        if limit == 0 {
+            return Err("page limit must be at least 1".to_string());
+        }
+        let after = cursor.map(AttackCursor::parse).transpose()?;
+        let query = AttackQuery {
+            limit: Some(limit.saturating_add(1)),
+            offset: 0,
+            ..filter.clone()
+        };
+        let mut attacks = self.select_attacks(&query, after.as_ref())?;
+
+        let mut next_cursor = None;
+        if attacks.len() > limit {
+            attacks.truncate(limit);
+            next_cursor = attacks
+                .last()
+                .map(|last| AttackCursor::from(last).to_string());
+        }
+        return Ok(AttackPage {
+            attacks,
+            next_cursor,
+        });
+    }
+
+    fn select_attacks(
+        &self,
+        query: &AttackQuery,
+        after: Option<&AttackCursor>,
+    ) -> Result<Vec<StoredAttack>, String> {
+        let mut conditions = vec!["1 = 1".to_string()];
+        let mut values: Vec<Value> = Vec::new();
+        let mut condition = |sql: String, value: Value| {
+            conditions.push(sql);
+            values.push(value);
+        };
+
+        if let Some(attack_id) = &query.attack_id {
+            condition("a.attack_id = ?".into(), Value::Text(attack_id.clone()));
+        }
+        if let Some(chain_id) = query.chain_id {
+            condition("a.chain_id = ?".into(), Value::Integer(chain_id as i64));
+        }
+        if let Some(pool) = &query.pool_address {
+            condition("a.pool_address = ?".into(), Value::Text(pool.clone()));
+        }
+        if let Some(attacker) = &query.attacker {
+            condition(
+                "a.attacker_address = ?".into(),
+                Value::Text(attacker.clone()),
+            );
+        }
+        if let Some(victim) = &query.victim {
+            condition("a.victim_address = ?".into(), Value::Text(victim.clone()));
+        }
+        if let Some(block_start) = query.block_start {
+            condition(
+                "a.block_number >= ?".into(),
+                Value::Integer(block_start as i64),
+            );
+        }
+        if let Some(block_end) = query.block_end {
+            condition(
+                "a.block_number <= ?".into(),
+                Value::Integer(block_end as i64),
+            );
+        }
+        if let Some(since) = query.since {
+            condition("v.timestamp >= ?".into(), Value::Integer(since as i64));
+        }
+        if let Some(until) = query.until {
+            condition("v.timestamp <= ?".into(), Value::Integer(until as i64));
+        }
+        if let Some(min_loss_usd) = query.min_loss_usd {
+            condition(format!("{} >= ?", LOSS_USD_SQL), Value::Real(min_loss_usd));
+        }
+        if let Some(min_confidence) = query.min_confidence {
+            condition(
+                "h.confidence_score >= ?".into(),
+                Value::Real(f64::from(min_confidence)),
+            );
+        }
+
+        if let Some(after) = after {
+            conditions.push("(a.chain_id, a.block_number, a.attack_id) > (?, ?, ?)".to_string());
+            values.push(Value::Integer(after.chain_id as i64));
+            values.push(Value::Integer(after.block_number as i64));
+            values.push(Value::Text(after.attack_id.clone()));
+        }
+
+        // SQLite reads a negative limit as no limit
+        values.push(Value::Integer(query.limit.map_or(-1, |limit| limit as i64)));
+        values.push(Value::Integer(query.offset as i64));
+
+        let sql = format!(
+            "SELECT a.attack_id, a.chain_id, a.block_number, v.timestamp, a.pool_address,
+                a.attacker_address, a.victim_address, a.front_run_tx_hash, a.victim_tx_hash,
+                a.back_run_tx_hash, h.confidence_score, h.total_profit_usd,
+                s.victim_loss_percentage, {} AS loss_usd
+            FROM sandwich_attacks a
+            JOIN swap_transactions v
+                ON v.tx_hash = a.victim_tx_hash AND v.pool_address = a.pool_address
+            LEFT JOIN sandwich_heuristic_results h ON h.attack_id = a.attack_id
+            LEFT JOIN sandwich_simulation_results s ON s.attack_id = a.attack_id
+            WHERE {}
+            ORDER BY a.chain_id, a.block_number, a.attack_id
+            LIMIT ? OFFSET ?",
+            LOSS_USD_SQL,
+            conditions.join(" AND ")
+        );
+        let mut statement = self
+            .connection
+            .prepare(&sql)
+            .map_err(|err| format!("failed to query attacks: {}", err))?;
+
+        let attacks = statement
+            .query_map(params_from_iter(values), stored_attack_from_row)
+            .map_err(|err| format!("failed to query attacks: {}", err))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("invalid attack row: {}", err))?;
+
+        return Ok(attacks);
+    }
+
+    /// Store the state of a pool at the start of the given block.
+    pub fn save_pool_snapshot(
+        &mut self,
+        pool_address: &str,
+        block: BlockId,
+        pool: &Pool,
+    ) -> Result<(), String> {
+        self.connection
+            .execute(
+                "INSERT INTO pool_snapshots (
+                    chain_id, pool_address, block_number, token_a_address, token_b_address,
+                    token_a_reserve, token_b_reserve, fee_bps
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                ON CONFLICT (chain_id, pool_address, block_number) DO UPDATE SET
+                    token_a_address = excluded.token_a_address,
+                    token_b_address = excluded.token_b_address,
+                    token_a_reserve = excluded.token_a_reserve,
+                    token_b_reserve = excluded.token_b_reserve,
+                    fee_bps = excluded.fee_bps",
+                params![
+                    block.chain_id as i64,
+                    pool_address,
+                    block.block_number as i64,
+                    pool.token_a_address,
+                    pool.token_b_address,
+                    pool.token_a_reserve,
+                    pool.token_b_reserve,
+                    pool.fee_bps,
+                ],
+            )
+            .map_err(|err| format!("failed to save pool snapshot {}: {}", pool_address, err))?;
+
+        return Ok(());
+    }
+
+    /// Build a simulation `pool_map` from the most recent snapshot of every
+    /// pool of the block's chain taken at or before the given block.
+    pub fn load_pool_map(&self, block: BlockId) -> Result<HashMap<String, Pool>, String> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT pool_address, token_a_reserve, token_b_reserve, token_a_address, token_b_address,
+                    fee_bps
+                FROM pool_snapshots AS snapshot
+                WHERE chain_id = ?1 AND block_number = (
+                    SELECT MAX(block_number) FROM pool_snapshots
+                    WHERE chain_id = snapshot.chain_id
+                        AND pool_address = snapshot.pool_address
+                        AND block_number <= ?2
+                )",
+            )
+            .map_err(|err| format!("failed to query pool snapshots: {}", err))?;
+
+        let pools = statement
+            .query_map(
+                params![block.chain_id as i64, block.block_number as i64],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        Pool::new(row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)
+                            .with_fee_bps(row.get(5)?),
+                    ))
+                },
+            )
+            .map_err(|err| format!("failed to query pool snapshots: {}", err))?
+            .collect::<Result<HashMap<_, _>, _>>()
+            .map_err(|err| format!("invalid pool snapshot row: {}", err))?;
+
+        return Ok(pools);
+    }
+
+    pub fn mark_blocks_processed(&mut self, blocks: &[BlockId]) -> Result<(), String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+
+        for block in blocks {
+            transaction
+                .execute(
+                    "INSERT OR IGNORE INTO processed_blocks (chain_id, block_number) VALUES (?1, ?2)",
+                    params![block.chain_id as i64, block.block_number as i64],
+                )
+                .map_err(|err| format!("failed to mark block {:?}: {}", block, err))?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit processed blocks: {}", err))
+    }
+
+    pub fn is_block_processed(&self, block: BlockId) -> Result<bool, String> {
+        self.connection
+            .query_row(
+                "SELECT EXISTS (
+                    SELECT 1 FROM processed_blocks WHERE chain_id = ?1 AND block_number = ?2
+                )",
+                params![block.chain_id as i64, block.block_number as i64],
+                |row| row.get(0),
+            )
+            .map_err(|err| format!("failed to query processed blocks: {}", err))
+    }
+
+    /// Drop the transactions of blocks that were already analyzed, so an
+    /// incremental run only looks at blocks it hasn't seen before.
+    pub fn filter_unprocessed(
+        &self,
+        transactions: &[SwapTransaction],
+    ) -> Result<Vec<SwapTransaction>, String> {
+        let mut processed: HashMap<BlockId, bool> = HashMap::new();
+        let mut unprocessed = Vec::new();
+
+        for tx in transactions {
+            let is_processed = match processed.get(&tx.block_id()) {
+                Some(is_processed) => *is_processed,
+                None => {
+                    let is_processed = self.is_block_processed(tx.block_id())?;
+                    processed.insert(tx.block_id(), is_processed);
+                    is_processed
+                }
+            };
+
+            if !is_processed {
+                unprocessed.push(tx.clone());
+            }
+        }
+
+        return Ok(unprocessed);
+    }
+}
+
+/// The checkpoint is kept apart from `processed_blocks`: incremental runs can
+/// leave gaps below their latest block, while a scan saves its checkpoint only
+/// once every block up to it is done.
+impl CheckpointStore for SqliteStore {
+    fn load_checkpoint(&mut self) -> Result<Checkpoint, String> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT chain_id, last_block FROM scan_checkpoints")
+            .map_err(|err| format!("failed to query checkpoint: {}", err))?;
+        let last_blocks = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64))
+            })
+            .map_err(|err| format!("failed to query checkpoint: {}", err))?
+            .collect::<Result<_, _>>()
+            .map_err(|err| format!("invalid checkpoint row: {}", err))?;
+        return Ok(Checkpoint { last_blocks });
+    }
+
+    fn save_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<(), String> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|err| format!("failed to start transaction: {}", err))?;
+
+        for (chain_id, last_block) in &checkpoint.last_blocks {
+            transaction
+                .execute(
+                    "INSERT INTO scan_checkpoints (chain_id, last_block) VALUES (?1, ?2)
+                    ON CONFLICT (chain_id) DO UPDATE SET last_block = excluded.last_block",
+                    params![*chain_id as i64, *last_block as i64],
+                )
+                .map_err(|err| format!("failed to save checkpoint: {}", err))?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|err| format!("failed to commit checkpoint: {}", err))
+    }
+}
+
+fn upsert_swap(connection: &Connection, swap: &SwapTransaction) -> Result<(), String> {
+    connection
+        .execute(
+            "INSERT INTO swap_transactions (
+                tx_hash, chain_id, block_number, timestamp, tx_position_in_block, from_address,
+                token_in, token_out, amount_in, amount_out, gas_price, pool_address,
+                token_launch_block, is_contract_caller, usd_value_in, usd_value_out, gas_cost_usd,
+                max_fee_per_gas, max_priority_fee_per_gas, base_fee_per_gas, raw_amount_in
+            ) VALUES (
+                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                ?18, ?19, ?20, ?21
+            )
+            ON CONFLICT (tx_hash, pool_address) DO NOTHING",
+            params![
+                swap.tx_hash,
+                swap.chain_id as i64,
+                swap.block_number as i64,
+                swap.timestamp as i64,
+                swap.tx_position_in_block,
+                swap.from_address,
+                swap.token_in,
+                swap.token_out,
+                swap.amount_in,
+                swap.amount_out,
+                swap.gas_price as i64,
+                swap.pool_address,
+                swap.token_launch_block as i64,
+                swap.is_contract_caller,
+                swap.usd_value_in,
+                swap.usd_value_out,
+                swap.gas_cost_usd,
+                swap.max_fee_per_gas.map(|fee| fee as i64),
+                swap.max_priority_fee_per_gas.map(|fee| fee as i64),
+                swap.base_fee_per_gas.map(|fee| fee as i64),
+                swap.raw_amount_in.map(|amount| amount.to_string()),
+            ],
+        )
+        .map_err(|err| format!("failed to save swap {}: {}", swap.tx_hash, err))?;
+
+    return Ok(());
+}
+
+fn upsert_attack(
+    connection: &Connection,
+    attack_id: &str,
+    front: &SwapTransaction,
+    victim: &SwapTransaction,
+    back: &SwapTransaction,
+) -> Result<(), String> {
+    for swap in [front, victim, back] {
+        upsert_swap(connection, swap)?;
+    }
+
+    connection
+        .execute(
+            "INSERT INTO sandwich_attacks (
+                attack_id, chain_id, block_number, front_run_tx_hash, victim_tx_hash,
+                back_run_tx_hash, attacker_address, victim_address, pool_address,
+                front_run_pool_address, back_run_pool_address
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+            ON CONFLICT (attack_id) DO NOTHING",
+            params![
+                attack_id,
+                victim.chain_id as i64,
+                victim.block_number as i64,
+                front.tx_hash,
+                victim.tx_hash,
+                back.tx_hash,
+                front.from_address,
+                victim.from_address,
+                victim.pool_address,
+                front.pool_address,
+                back.pool_address,
+            ],
+        )
+        .map_err(|err| format!("failed to save attack {}: {}", attack_id, err))?;
+
+    return Ok(());
+}
+
+fn stored_attack_from_row(row: &Row) -> rusqlite::Result<StoredAttack> {
+    Ok(StoredAttack {
+        attack_id: row.get("attack_id")?,
+        chain_id: row.get::<_, i64>("chain_id")? as u64,
+        block_number: row.get::<_, i64>("block_number")? as u64,
+        timestamp: row.get::<_, i64>("timestamp")? as u64,
+        pool_address: row.get("pool_address")?,
+        attacker: row.get("attacker_address")?,
+        victim: row.get("victim_address")?,
+        front_run_tx: row.get("front_run_tx_hash")?,
+        victim_tx: row.get("victim_tx_hash")?,
+        back_run_tx: row.get("back_run_tx_hash")?,
+        confidence_score: row
+            .get::<_, Option<f64>>("confidence_score")?
+            .map(|s| s as f32),
+        total_profit_usd: row.get("total_profit_usd")?,
+        victim_loss_percentage: row.get("victim_loss_percentage")?,
+        loss_usd: row.get("loss_usd")?,
+    })
+}
+
+fn swap_from_row(row: &Row) -> rusqlite::Result<SwapTransaction> {
+    Ok(SwapTransaction {
+        tx_hash: row.get("tx_hash")?,
+        chain_id: row.get::<_, i64>("chain_id")? as u64,
+        block_number: row.get::<_, i64>("block_number")? as u64,
+        timestamp: row.get::<_, i64>("timestamp")? as u64,
+        tx_position_in_block: row.get("tx_position_in_block")?,
+        from_address: row.get("from_address")?,
+        token_in: row.get("token_in")?,
+        token_out: row.get("token_out")?,
+        amount_in: row.get("amount_in")?,
+        amount_out: row.get("amount_out")?,
+        gas_price: row.get::<_, i64>("gas_price")? as u64,
+        pool_address: row.get("pool_address")?,
+        token_launch_block: row.get::<_, i64>("token_launch_block")? as u64,
+        is_contract_caller: row.get("is_contract_caller")?,
+        usd_value_in: row.get("usd_value_in")?,
+        usd_value_out: row.get("usd_value_out")?,
+        gas_cost_usd: row.get("gas_cost_usd")?,
+        max_fee_per_gas: row
+            .get::<_, Option<i64>>("max_fee_per_gas")?
+            .map(|fee| fee as u64),
+        max_priority_fee_per_gas: row
+            .get::<_, Option<i64>>("max_priority_fee_per_gas")?
+            .map(|fee| fee as u64),
+        base_fee_per_gas: row
+            .get::<_, Option<i64>>("base_fee_per_gas")?
+            .map(|fee| fee as u64),
+        raw_amount_in: row
+            .get::<_, Option<String>>("raw_amount_in")?
+            .map(|amount| {
+                raw_amount::parse(&amount).map_err(|err| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        0,
+                        rusqlite::types::Type::Text,
+                        err.into(),
+                    )
+                })
+            })
+            .transpose()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::csv::sample_transactions;
+    use crate::sandwich::cancel::CancellationToken;
+    use crate::sandwich::detector::{HeuristicsDetector, Pipeline};
+    use crate::sandwich::find_same_block_sandwiches;
+    use crate::sandwich::transactions::ETHEREUM_CHAIN_ID;
+    use ethnum::U256;
+
+    #[test]
+    fn test_incremental_runs_with_sqlite_store() {
+        let mut store = SqliteStore::open_in_memory().expect("Failed to open store");
+        let transactions = sample_transactions();
+        store
+            .save_swaps(&transactions)
+            .expect("Failed to save swaps");
+
+        let loaded = store.load_swaps().expect("Failed to load swaps");
+        assert_eq!(loaded.len(), transactions.len());
+        assert_eq!(
+            store
+                .load_swaps_in_blocks(ETHEREUM_CHAIN_ID, 12360, 12361)
+                .unwrap()
+                .len(),
+            6,
+            "Blocks 12360 and 12361 have 3 swaps each"
+        );
+
+        // First run analyzes everything and records the blocks it saw
+        let attacks = find_same_block_sandwiches(&loaded);
+        store
+            .save_attacks(&attacks)
+            .expect("Failed to save attacks");
+        let mut blocks: Vec<BlockId> = loaded.iter().map(|tx| tx.block_id()).collect();
+        blocks.dedup();
+        store.mark_blocks_processed(&blocks).unwrap();
+
+        // Saving the same attacks again must not create duplicates
+        store
+            .save_attacks(&attacks)
+            .expect("Failed to re-save attacks");
+        assert_eq!(store.attack_ids().unwrap().len(), attacks.len());
+
+        // A second run over the same data has nothing left to analyze
+        assert!(store.filter_unprocessed(&transactions).unwrap().is_empty());
+        let block = |chain_id, block_number| BlockId {
+            chain_id,
+            block_number,
+        };
+        assert!(store.is_block_processed(block(1, 12360)).unwrap());
+        assert!(!store.is_block_processed(block(1, 99999)).unwrap());
+        assert!(
+            !store.is_block_processed(block(137, 12360)).unwrap(),
+            "Same block number on another chain"
+        );
+        // Only a scan's own checkpoint tells where it can resume from
+        let checkpoint = store.load_checkpoint().unwrap();
+        assert_eq!(checkpoint.pending(&transactions).len(), transactions.len());
+    }
+
+    #[test]
+    fn test_scan_resumes_below_blocks_processed_out_of_order() {
+        let mut store = SqliteStore::open_in_memory().expect("Failed to open store");
+        let transactions = sample_transactions();
+        let block = |block_number| BlockId {
+            chain_id: ETHEREUM_CHAIN_ID,
+            block_number,
+        };
+        // 12366 finished before 12361, which never did
+        store
+            .mark_blocks_processed(&[block(12360), block(12366)])
+            .unwrap();
+        let checkpoint = store.load_checkpoint().unwrap();
+        assert!(checkpoint
+            .pending(&transactions)
+            .iter()
+            .any(|tx| tx.block_number == 12361));
+
+        let pipeline = Pipeline::new().with_detector(HeuristicsDetector::default());
+        let mut scanned = Vec::new();
+        let finished = pipeline
+            .run_resumable(
+                &transactions,
+                &mut store,
+                &CancellationToken::new(),
+                |block, _detections| {
+                    scanned.push(block.block_number);
+                    return Ok(());
+                },
+            )
+            .unwrap();
+        assert!(finished);
+        assert!(scanned.contains(&12361));
+        let checkpoint = store.load_checkpoint().unwrap();
+        assert!(checkpoint.pending(&transactions).is_empty());
+    }
+
+    #[test]
+    fn test_multi_hop_swaps_keep_every_hop() {
+        let mut store = SqliteStore::open_in_memory().expect("Failed to open store");
+        let mut transactions = sample_transactions();
+        // The victim routes through a second pool in the same transaction
+        let victim = transactions
+            .iter()
+            .find(|tx| tx.block_number == 12360 && tx.tx_position_in_block == 2)
+            .expect("Sample block 12360 has a victim")
+            .clone();
+        let mut second_hop = victim.clone();
+        second_hop.pool_address = "0xpool2".to_string();
+        second_hop.token_in = victim.token_out.clone();
+        second_hop.token_out = "WETH".to_string();
+        // Beyond f64's 53 bits of precision, must come back exactly
+        second_hop.raw_amount_in = Some(U256::from(u64::MAX) * U256::from(1_000_000_007u64));
+        transactions.push(second_hop.clone());
+
+        store.save_swaps(&transactions).unwrap();
+        let hops: Vec<SwapTransaction> = store
+            .load_swaps()
+            .unwrap()
+            .into_iter()
+            .filter(|tx| tx.tx_hash == victim.tx_hash)
+            .collect();
+        assert_eq!(hops.len(), 2);
+        assert!(hops.contains(&second_hop));
+        assert!(hops.iter().any(|hop| hop.raw_amount_in.is_none()));
+
+        let mut attacks = find_same_block_sandwiches(&transactions);
+        attacks.retain(|attack| attack.victim_tx.tx_hash == victim.tx_hash);
+        attacks[0].confidence_flags.paid_builder = true;
+        attacks[0].confidence_flags.custom_flags = vec!["large_victim".to_string()];
+        store.save_attacks(&attacks).unwrap();
+        let stored = store.query_attacks(&AttackQuery::default()).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].pool_address, victim.pool_address);
+
+        let (paid_builder, custom_flags): (bool, String) = store
+            .connection
+            .query_row(
+                "SELECT paid_builder, custom_flags FROM sandwich_heuristic_results",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert!(paid_builder);
+        assert_eq!(custom_flags, r#"["large_victim"]"#);
+    }
+
+    #[test]
+    fn test_pool_snapshots_resolve_latest_state() {
+        let mut store = SqliteStore::open_in_memory().expect("Failed to open store");
+        let pool = |reserve: f64| Pool::new(reserve, 50000000000.0, "USDC".into(), "SHIB".into());
+
+        let block = |chain_id, block_number| BlockId {
+            chain_id,
+            block_number,
+        };
+
+        store
+            .save_pool_snapshot("0xpool1", block(1, 12350), &pool(1000.0))
+            .unwrap();
+        store
+            .save_pool_snapshot("0xpool1", block(1, 12360), &pool(2000.0).with_fee_bps(5))
+            .unwrap();
+        store
+            .save_pool_snapshot("0xpool1", block(1, 12370), &pool(3000.0))
+            .unwrap();
+        store
+            .save_pool_snapshot("0xpool1", block(137, 12362), &pool(9000.0))
+            .unwrap();
+
+        let pool_map = store.load_pool_map(block(1, 12365)).unwrap();
+        assert_eq!(pool_map["0xpool1"].token_a_reserve, 2000.0);
+        assert_eq!(pool_map["0xpool1"].fee_bps, 5);
+        assert!(store.load_pool_map(block(1, 12000)).unwrap().is_empty());
+        assert_eq!(
+            store.load_pool_map(block(137, 12365)).unwrap()["0xpool1"].token_a_reserve,
+            9000.0
+        );
+    }
+
+    #[test]
+    fn test_cursor_pages_cover_all_attacks() {
+        let mut store = SqliteStore::open_in_memory().expect("Failed to open store");
+        let transactions = sample_transactions();
+        store.save_swaps(&transactions).unwrap();
+        store
+            .save_attacks(&find_same_block_sandwiches(&transactions))
+            .unwrap();
+        let all = store.query_attacks(&AttackQuery::default()).unwrap();
+        assert!(all.len() > 2);
+
+        let mut paged = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = store
+                .get_attacks(&AttackQuery::default(), cursor.as_deref(), 2)
+                .unwrap();
+            assert!(page.attacks.len() <= 2);
+            paged.extend(page.attacks);
+            cursor = match page.next_cursor {
+                Some(cursor) => Some(cursor),
+                None => break,
+            };
+        }
+        assert_eq!(paged, all);
+        assert!(all.iter().all(|attack| attack.loss_usd.is_none()));
+        let valued = AttackQuery {
+            min_loss_usd: Some(0.0),
+            ..AttackQuery::default()
+        };
+        assert!(
+            store.query_attacks(&valued).unwrap().is_empty(),
+            "Unsimulated attacks have no known loss"
+        );
+
+        assert!(store
+            .get_attacks(&AttackQuery::default(), Some("not a cursor"), 2)
+            .is_err());
+        assert!(store.get_attacks(&AttackQuery::default(), None, 0).is_err());
+        let unbounded = store
+            .get_attacks(&AttackQuery::default(), None, usize::MAX)
+            .unwrap();
+        assert_eq!(unbounded.attacks, all);
+        assert_eq!(unbounded.next_cursor, None);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::sandwich::transactions::{BlockId, SwapTransaction};
+
+/// Buffers swaps arriving from an unordered stream and releases whole blocks.
+///
+/// A block is released once a swap `lag_blocks` blocks ahead of it has been
+/// seen on the same chain, which gives late or out of order messages time to
+/// arrive. Swaps for a block that was already released are dropped and
+/// counted in `late_swaps`.
+#[derive(Debug)]
+pub struct BlockBuffer {
+    lag_blocks: u64,
+    blocks: BTreeMap<BlockId, Vec<SwapTransaction>>,
+    /// Highest block seen and last block released, per chain.
+    highest_block: HashMap<u64, u64>,
+    released_up_to: HashMap<u64, u64>,
+    pub late_swaps: u64,
+}
+
+impl BlockBuffer {
+    pub fn new(lag_blocks: u64) -> Self {
+        Self {
+            lag_blocks,
+            blocks: BTreeMap::new(),
+            highest_block: HashMap::new(),
+            released_up_to: HashMap::new(),
+            late_swaps: 0,
+        }
+    }
+
+    /// Add a swap and return the blocks it completed, oldest first.
+    /// Each block's swaps are sorted by their position within the block.
+    pub fn push(&mut self, swap: SwapTransaction) -> Vec<(BlockId, Vec<SwapTransaction>)> {
+        let block = swap.block_id();
+        if self
+            .released_up_to
+            .get(&block.chain_id)
+            .is_some_and(|released| block.block_number <= *released)
+        {
+            self.late_swaps += 1;
+            return Vec::new();
+        }
+
+        self.blocks.entry(block).or_default().push(swap);
+        let highest = self
+            .highest_block
+            .get(&block.chain_id)
+            .map_or(block.block_number, |h| (*h).max(block.block_number));
+        self.highest_block.insert(block.chain_id, highest);
+
+        match highest.checked_sub(self.lag_blocks) {
+            Some(watermark) => self.release_before(block.chain_id, watermark),
+            None => Vec::new(),
+        }
+    }
+
+    /// Release every buffered block, e.g. when the stream is shutting down.
+    pub fn flush(&mut self) -> Vec<(BlockId, Vec<SwapTransaction>)> {
+        let highest_blocks: Vec<(u64, u64)> = self
+            .highest_block
+            .iter()
+            .map(|(chain_id, highest)| (*chain_id, *highest))
+            .collect();
+
+        let mut released = Vec::new();
+        for (chain_id, highest) in highest_blocks {
+            released.extend(self.release_before(chain_id, highest + 1));
+        }
+        released.sort_by_key(|(block, _swaps)| *block);
+
+        return released;
+    }
+
+    pub fn buffered_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Release the blocks of a chain below `block_number`.
+    fn release_before(
+        &mut self,
+        chain_id: u64,
+        block_number: u64,
+    ) -> Vec<(BlockId, Vec<SwapTransaction>)> {
+        let ready: Vec<BlockId> = self
+            .blocks
+            .range(
+                BlockId {
+                    chain_id,
+                    block_number: 0,
+                }..BlockId {
+                    chain_id,
+                    block_number,
+                },
+            )
+            .map(|(block, _swaps)| *block)
+            .collect();
+
+        let mut released = Vec::new();
+        for block in ready {
+            let Some(mut swaps) = self.blocks.remove(&block) else {
+                continue;
+            };
+            swaps.sort_by_key(|swap| swap.tx_position_in_block);
+            self.released_up_to.insert(chain_id, block.block_number);
+            released.push((block, swaps));
+        }
+
+        return released;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::csv::sample_transactions;
+
+    #[test]
+    fn test_releases_blocks_after_lag() {
+        let swaps = sample_transactions();
+        let first_block = swaps[0].block_number;
+
+        let mut buffer = BlockBuffer::new(1);
+        let mut released = Vec::new();
+        // Feed in reverse to simulate out of order delivery within a block
+        for swap in swaps.iter().rev().filter(|s| s.block_number == first_block) {
+            released.extend(buffer.push(swap.clone()));
+        }
+        assert!(released.is_empty(), "Block must wait for the lag");
+
+        let mut next = swaps[0].clone();
+        next.block_number = first_block + 1;
+        released.extend(buffer.push(next.clone()));
+        assert!(released.is_empty(), "One block of lag is still pending");
+
+        next.block_number = first_block + 2;
+        released.extend(buffer.push(next.clone()));
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].0.block_number, first_block);
+        let positions: Vec<u32> = released[0]
+            .1
+            .iter()
+            .map(|s| s.tx_position_in_block)
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] <= w[1]));
+
+        next.block_number = first_block;
+        assert!(buffer.push(next.clone()).is_empty());
+        assert_eq!(buffer.late_swaps, 1);
+
+        // Another chain keeps its own watermark
+        next.chain_id = 137;
+        next.block_number = first_block - 100;
+        assert!(buffer.push(next).is_empty());
+        assert_eq!(buffer.late_swaps, 1);
+
+        let flushed = buffer.flush();
+        assert_eq!(flushed.len(), 3);
+        assert_eq!(buffer.buffered_blocks(), 0);
+    }
+}
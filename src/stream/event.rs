@@ -0,0 +1,38 @@
+use serde::Serialize;
+
+use crate::sandwich::same_block_heuristics::SandwichAttackByHeuristics;
+
+/// A detected attack as pushed to live consumers (the Kafka output topic,
+/// keyed by `attack_id`, and WebSocket clients).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttackEvent {
+    pub attack_id: String,
+    pub chain_id: u64,
+    pub block_number: u64,
+    pub pool_address: String,
+    pub attacker: String,
+    pub victim: String,
+    pub front_run_tx: String,
+    pub victim_tx: String,
+    pub back_run_tx: String,
+    pub confidence_score: f32,
+    pub total_profit_usd: f64,
+}
+
+impl From<&SandwichAttackByHeuristics> for AttackEvent {
+    fn from(attack: &SandwichAttackByHeuristics) -> Self {
+        Self {
+            attack_id: attack.attack_id(),
+            chain_id: attack.chain_id,
+            block_number: attack.victim_tx.block_number,
+            pool_address: attack.victim_tx.pool_address.clone(),
+            attacker: attack.front_run_tx.from_address.clone(),
+            victim: attack.victim_tx.from_address.clone(),
+            front_run_tx: attack.front_run_tx.tx_hash.clone(),
+            victim_tx: attack.victim_tx.tx_hash.clone(),
+            back_run_tx: attack.back_run_tx.tx_hash.clone(),
+            confidence_score: attack.confidence_score,
+            total_profit_usd: attack.confidence_flags.total_profit_usd,
+        }
+    }
+}
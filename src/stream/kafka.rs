@@ -0,0 +1,300 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
+use rdkafka::{Message, Offset, TopicPartitionList};
+
+use super::buffer::BlockBuffer;
+pub use super::event::AttackEvent;
+use crate::metrics::DetectorMetrics;
+use crate::sandwich::same_block_heuristics::{detect_stream, SandwichAttackByHeuristics};
+use crate::sandwich::same_block_sim::{find_sandwich_attacks_by_simulation, Pool};
+use crate::sandwich::transactions::{BlockId, SwapTransaction};
+
+const POLL_TIMEOUT: Duration = Duration::from_millis(500);
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+type AttackListener = Box<dyn FnMut(&AttackEvent)>;
+/// Called with the `(partition, offset)` of a skipped message and why.
+type SkipListener = Box<dyn FnMut(i32, i64, &str)>;
+
+/// How swap records are encoded on the input topic.
+#[derive(Debug, Clone)]
+pub enum SwapEncoding {
+    /// One JSON object per message, with the `SwapTransaction` field names.
+    Json,
+    /// Avro datum written with the given schema. Messages framed for the
+    /// Confluent Schema Registry (magic byte + schema id) are accepted too.
+    #[cfg(feature = "avro")]
+    Avro(apache_avro::Schema),
+}
+
+#[derive(Debug, Clone)]
+pub struct KafkaConfig {
+    pub brokers: String,
+    pub group_id: String,
+    pub input_topic: String,
+    pub output_topic: String,
+    pub encoding: SwapEncoding,
+    /// Blocks to wait for late swaps before running detection on a block.
+    pub lag_blocks: u64,
+}
+
+impl KafkaConfig {
+    pub fn new(brokers: &str, group_id: &str, input_topic: &str, output_topic: &str) -> Self {
+        Self {
+            brokers: brokers.to_string(),
+            group_id: group_id.to_string(),
+            input_topic: input_topic.to_string(),
+            output_topic: output_topic.to_string(),
+            encoding: SwapEncoding::Json,
+            lag_blocks: 2,
+        }
+    }
+}
+
+pub fn decode_swap(payload: &[u8], encoding: &SwapEncoding) -> Result<SwapTransaction, String> {
+    match encoding {
+        SwapEncoding::Json => {
+            serde_json::from_slice(payload).map_err(|err| format!("invalid swap message: {}", err))
+        }
+        #[cfg(feature = "avro")]
+        SwapEncoding::Avro(schema) => {
+            let mut datum = match payload {
+                [0, _, _, _, _, rest @ ..] => rest,
+                _ => payload,
+            };
+            let value = apache_avro::from_avro_datum(schema, &mut datum, None)
+                .map_err(|err| format!("invalid avro swap message: {}", err))?;
+            apache_avro::from_value(&value).map_err(|err| format!("invalid avro swap: {}", err))
+        }
+    }
+}
+
+/// Consumes swaps from a topic, buffers them per block and publishes the
+/// sandwiches found in each completed block to the output topic.
+///
+/// Offsets are committed manually, and never past a swap whose block is
+/// still buffered, so a restart replays any block that wasn't processed.
+pub struct KafkaDetector {
+    config: KafkaConfig,
+    consumer: BaseConsumer,
+    producer: BaseProducer,
+    buffer: BlockBuffer,
+    /// Offsets of the buffered swaps, per block, as `(partition, offset)`.
+    pending_offsets: BTreeMap<BlockId, Vec<(i32, i64)>>,
+    /// Next offset to read, per partition.
+    next_offsets: HashMap<i32, i64>,
+    metrics: Option<Arc<DetectorMetrics>>,
+    /// Pools to simulate blocks against for the victim loss metric.
+    pools: Option<HashMap<String, Pool>>,
+    listeners: Vec<AttackListener>,
+    skip_listeners: Vec<SkipListener>,
+    pub invalid_messages: u64,
+}
+
+impl KafkaDetector {
+    pub fn new(config: KafkaConfig) -> Result<Self, String> {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &config.brokers)
+            .set("group.id", &config.group_id)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .map_err(|err| format!("failed to create kafka consumer: {}", err))?;
+        consumer
+            .subscribe(&[config.input_topic.as_str()])
+            .map_err(|err| format!("failed to subscribe to {}: {}", config.input_topic, err))?;
+
+        let producer: BaseProducer = ClientConfig::new()
+            .set("bootstrap.servers", &config.brokers)
+            .create()
+            .map_err(|err| format!("failed to create kafka producer: {}", err))?;
+
+        return Ok(Self {
+            buffer: BlockBuffer::new(config.lag_blocks),
+            config,
+            consumer,
+            producer,
+            pending_offsets: BTreeMap::new(),
+            next_offsets: HashMap::new(),
+            metrics: None,
+            pools: None,
+            listeners: Vec::new(),
+            skip_listeners: Vec::new(),
+            invalid_messages: 0,
+        });
+    }
+
+    /// Record processed blocks, attacks and detection latency, e.g. for `metrics::serve`.
+    pub fn with_metrics(mut self, metrics: Arc<DetectorMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Also simulate every block against `pools`, keyed by pool address, so
+    /// the metrics record the victims' simulated losses.
+    pub fn with_pools(mut self, pools: HashMap<String, Pool>) -> Self {
+        self.pools = Some(pools);
+        self
+    }
+
+    /// Call `listener` with every attack as it's published, e.g. to push it
+    /// to WebSocket clients or alerting.
+    pub fn on_attack<F: FnMut(&AttackEvent) + 'static>(mut self, listener: F) -> Self {
+        self.listeners.push(Box::new(listener));
+        self
+    }
+
+    /// Call `listener` with every message that can't be decoded as a swap,
+    /// e.g. to log it. Such messages are skipped and counted in
+    /// `invalid_messages`.
+    pub fn on_skipped_message<F: FnMut(i32, i64, &str) + 'static>(mut self, listener: F) -> Self {
+        self.skip_listeners.push(Box::new(listener));
+        self
+    }
+
+    /// Process messages until `keep_running` returns false, then flush the
+    /// buffered blocks. Returns the number of attacks published.
+    pub fn run<F>(&mut self, mut keep_running: F) -> Result<usize, String>
+    where
+        F: FnMut() -> bool,
+    {
+        let mut published = 0;
+
+        while keep_running() {
+            let Some(message) = self.consumer.poll(POLL_TIMEOUT) else {
+                continue;
+            };
+            let message = message.map_err(|err| format!("kafka consume failed: {}", err))?;
+            let (partition, offset) = (message.partition(), message.offset());
+            self.next_offsets.insert(partition, offset + 1);
+
+            let swap =
+                match decode_swap(message.payload().unwrap_or_default(), &self.config.encoding) {
+                    Ok(swap) => swap,
+                    Err(err) => {
+                        for listener in &mut self.skip_listeners {
+                            listener(partition, offset, &err);
+                        }
+                        self.invalid_messages += 1;
+                        continue;
+                    }
+                };
+
+            let block = swap.block_id();
+            let late_swaps = self.buffer.late_swaps;
+            let completed = self.buffer.push(swap);
+            if self.buffer.late_swaps == late_swaps {
+                self.pending_offsets
+                    .entry(block)
+                    .or_default()
+                    .push((partition, offset));
+            }
+
+            if !completed.is_empty() {
+                published += self.publish_blocks(completed)?;
+            }
+        }
+
+        let remaining = self.buffer.flush();
+        published += self.publish_blocks(remaining)?;
+
+        return Ok(published);
+    }
+
+    fn publish_blocks(
+        &mut self,
+        blocks: Vec<(BlockId, Vec<SwapTransaction>)>,
+    ) -> Result<usize, String> {
+        let mut published = 0;
+
+        for (block, swaps) in blocks {
+            if let (Some(metrics), Some(pools)) = (&self.metrics, &self.pools) {
+                metrics.observe_victim_losses(&find_sandwich_attacks_by_simulation(pools, &swaps));
+            }
+
+            let started = Instant::now();
+            let attacks: Vec<SandwichAttackByHeuristics> = detect_stream(swaps).collect();
+            if let Some(metrics) = &self.metrics {
+                let profit = attacks
+                    .iter()
+                    .map(|attack| attack.confidence_flags.total_profit_usd)
+                    .sum();
+                metrics.observe_block(block, started.elapsed(), attacks.len(), profit);
+            }
+
+            for attack in attacks {
+                let event = AttackEvent::from(&attack);
+                let payload = serde_json::to_string(&event)
+                    .map_err(|err| format!("failed to encode attack: {}", err))?;
+                self.producer
+                    .send(
+                        BaseRecord::to(&self.config.output_topic)
+                            .key(&event.attack_id)
+                            .payload(&payload),
+                    )
+                    .map_err(|(err, _record)| format!("failed to publish attack: {}", err))?;
+                for listener in &mut self.listeners {
+                    listener(&event);
+                }
+                published += 1;
+            }
+            self.pending_offsets.remove(&block);
+        }
+
+        self.producer
+            .flush(FLUSH_TIMEOUT)
+            .map_err(|err| format!("failed to flush attacks: {}", err))?;
+        self.commit_offsets()?;
+
+        return Ok(published);
+    }
+
+    /// Commit, per partition, up to the oldest swap still waiting in the buffer.
+    fn commit_offsets(&self) -> Result<(), String> {
+        let mut safe_offsets = self.next_offsets.clone();
+        for (partition, offset) in self.pending_offsets.values().flatten() {
+            let safe = safe_offsets.entry(*partition).or_insert(*offset);
+            *safe = (*safe).min(*offset);
+        }
+
+        let mut partitions = TopicPartitionList::new();
+        for (partition, offset) in safe_offsets {
+            partitions
+                .add_partition_offset(&self.config.input_topic, partition, Offset::Offset(offset))
+                .map_err(|err| format!("invalid offset: {}", err))?;
+        }
+        if partitions.count() == 0 {
+            return Ok(());
+        }
+
+        self.consumer
+            .commit(&partitions, CommitMode::Sync)
+            .map_err(|err| format!("failed to commit offsets: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_json_swap() {
+        let payload = br#"{"tx_hash":"0xa","block_number":12360,"timestamp":1620000000,
+            "tx_position_in_block":3,"from_address":"0xbot","token_in":"ETH","token_out":"USDC",
+            "amount_in":1.5,"amount_out":4500.0,"gas_price":100,"pool_address":"0xpool1",
+            "token_launch_block":0,"is_contract_caller":true,"usd_value_in":4500.0,
+            "usd_value_out":4500.0,"gas_cost_usd":12.0}"#;
+
+        let swap = decode_swap(payload, &SwapEncoding::Json).expect("Failed to decode swap");
+        assert_eq!(swap.tx_hash, "0xa");
+        assert_eq!(swap.block_number, 12360);
+        assert!(swap.is_contract_caller);
+
+        assert!(decode_swap(b"not json", &SwapEncoding::Json).is_err());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod buffer;
+pub mod event;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "websocket")]
+pub mod websocket;
@@ -0,0 +1,166 @@
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tungstenite::{Message, WebSocket};
+
+use super::event::AttackEvent;
+
+/// How long a client may take to accept a message before it's disconnected,
+/// so one stalled client can't hold up the detector.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a client may take to send its upgrade request before it's dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Messages queued for a client before it counts as stalled and is dropped.
+const CLIENT_QUEUE_SIZE: usize = 64;
+
+/// Pushes detected attacks to every connected WebSocket client, one JSON
+/// `AttackEvent` per text message.
+///
+/// Hook it into the live detector with
+/// `detector.on_attack(move |event| { let _ = broadcaster.publish(event); })`.
+/// Clients only receive attacks published after they connected. Each client
+/// is handshaken with and written to from its own thread, so neither a
+/// stalled handshake nor `publish` waits on another client's socket.
+#[derive(Debug, Default)]
+pub struct AttackBroadcaster {
+    /// Message queue of each client's writer thread, by client ID.
+    clients: Mutex<Vec<(u64, SyncSender<String>)>>,
+    next_client_id: AtomicU64,
+}
+
+impl AttackBroadcaster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept WebSocket connections on `addr` from a background thread.
+    /// Returns the bound address (useful with port 0).
+    pub fn listen<A: ToSocketAddrs>(self: &Arc<Self>, addr: A) -> Result<SocketAddr, String> {
+        let listener = TcpListener::bind(addr)
+            .map_err(|err| format!("failed to bind WebSocket server: {}", err))?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(|err| format!("failed to bind WebSocket server: {}", err))?;
+
+        let broadcaster = Arc::clone(self);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let broadcaster = Arc::clone(&broadcaster);
+                // A client whose handshake failed never gets events, nothing
+                // else to do about it
+                std::thread::spawn(move || broadcaster.accept(stream));
+            }
+        });
+
+        return Ok(local_addr);
+    }
+
+    /// Handshake with the client, then send it the published attacks until
+    /// it goes away.
+    fn accept(&self, stream: TcpStream) -> Result<(), String> {
+        stream
+            .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+            .map_err(|err| err.to_string())?;
+        let socket = tungstenite::accept(stream).map_err(|err| err.to_string())?;
+        socket
+            .get_ref()
+            .set_write_timeout(Some(WRITE_TIMEOUT))
+            .map_err(|err| err.to_string())?;
+
+        let (sender, receiver) = mpsc::sync_channel(CLIENT_QUEUE_SIZE);
+        let id = self.next_client_id.fetch_add(1, Ordering::Relaxed);
+        self.clients
+            .lock()
+            .map_err(|_| "client list poisoned")?
+            .push((id, sender));
+
+        write_messages(socket, receiver);
+        return Ok(());
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().map_or(0, |clients| clients.len())
+    }
+
+    /// Queue an attack for every client, dropping the ones that went away or
+    /// fell `CLIENT_QUEUE_SIZE` messages behind. Returns how many clients it
+    /// was queued for, or why the attack couldn't be encoded.
+    pub fn publish(&self, event: &AttackEvent) -> Result<usize, String> {
+        let payload = serde_json::to_string(event)
+            .map_err(|err| format!("failed to encode attack {}: {}", event.attack_id, err))?;
+        let Ok(clients) = self.clients.lock().map(|clients| clients.clone()) else {
+            return Ok(0);
+        };
+
+        let gone: Vec<u64> = clients
+            .iter()
+            .filter(|(_, sender)| sender.try_send(payload.clone()).is_err())
+            .map(|(id, _)| *id)
+            .collect();
+        if gone.is_empty() {
+            return Ok(clients.len());
+        }
+        let Ok(mut clients) = self.clients.lock() else {
+            return Ok(0);
+        };
+        clients.retain(|(id, _)| !gone.contains(id));
+        return Ok(clients.len());
+    }
+}
+
+/// Send queued messages to `socket` until it fails or the broadcaster drops
+/// the client, then close it.
+fn write_messages(mut socket: WebSocket<TcpStream>, receiver: mpsc::Receiver<String>) {
+    for payload in receiver {
+        if socket.send(Message::text(payload)).is_err() {
+            return;
+        }
+    }
+    let _ = socket.close(None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_to_connected_client() {
+        let broadcaster = Arc::new(AttackBroadcaster::new());
+        let addr = broadcaster
+            .listen("127.0.0.1:0")
+            .expect("Failed to start server");
+
+        // Never sends its upgrade request, mustn't keep others from connecting
+        let _stalled = TcpStream::connect(addr).expect("Failed to connect");
+        let (mut client, _response) =
+            tungstenite::connect(format!("ws://{}", addr)).expect("Failed to connect");
+        while broadcaster.client_count() == 0 {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let event = AttackEvent {
+            attack_id: "0xa-0xb-0xc".to_string(),
+            chain_id: 1,
+            block_number: 12360,
+            pool_address: "0xpool1".to_string(),
+            attacker: "0xbot".to_string(),
+            victim: "0xvictim".to_string(),
+            front_run_tx: "0xa".to_string(),
+            victim_tx: "0xb".to_string(),
+            back_run_tx: "0xc".to_string(),
+            confidence_score: 0.9,
+            total_profit_usd: 42.0,
+        };
+        assert_eq!(broadcaster.publish(&event), Ok(1));
+
+        let message = client.read().expect("Failed to read message");
+        let received: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        assert_eq!(received["attack_id"], "0xa-0xb-0xc");
+        assert_eq!(received["block_number"], 12360);
+    }
+}
@@ -0,0 +1,78 @@
+use std::convert::Infallible;
+use std::fmt::Display;
+
+#[cfg(feature = "otel")]
+use opentelemetry::trace::{Span, Status, Tracer};
+#[cfg(feature = "otel")]
+use opentelemetry::{global, KeyValue};
+
+/// Name of the tracer the spans are recorded with.
+pub const TRACER_NAME: &str = "toxicflow-detector";
+
+/// Span names, one per pipeline stage.
+pub const INGEST_BLOCK: &str = "ingest_block";
+pub const DETECT_BLOCK: &str = "detect_block";
+pub const HEURISTICS_BLOCK: &str = "heuristics_block";
+pub const SIMULATE_BLOCK: &str = "simulate_block";
+pub const WRITE_REPORT: &str = "write_report";
+
+/// Run `f` in a span named `name` with `attributes`. Without the `otel`
+/// feature this only calls `f`.
+pub(crate) fn traced<T, F>(name: &'static str, attributes: &[(&'static str, i64)], f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    return match traced_result(name, attributes, || Ok::<T, Infallible>(f())) {
+        Ok(value) => value,
+        Err(never) => match never {},
+    };
+}
+
+/// `traced`, marking the span as failed when `f` returns an error.
+#[cfg(feature = "otel")]
+pub(crate) fn traced_result<T, E, F>(
+    name: &'static str,
+    attributes: &[(&'static str, i64)],
+    f: F,
+) -> Result<T, E>
+where
+    E: Display,
+    F: FnOnce() -> Result<T, E>,
+{
+    let mut span = global::tracer(TRACER_NAME).start(name);
+    for (key, value) in attributes {
+        span.set_attribute(KeyValue::new(*key, *value));
+    }
+    let result = f();
+    if let Err(err) = &result {
+        span.set_status(Status::error(err.to_string()));
+    }
+    span.end();
+    return result;
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn traced_result<T, E, F>(
+    _name: &'static str,
+    _attributes: &[(&'static str, i64)],
+    f: F,
+) -> Result<T, E>
+where
+    E: Display,
+    F: FnOnce() -> Result<T, E>,
+{
+    return f();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traced_returns_result_of_stage() {
+        assert_eq!(traced(DETECT_BLOCK, &[("block_number", 12360)], || 3), 3);
+        let err: Result<u64, String> =
+            traced_result(WRITE_REPORT, &[], || Err("disk full".to_string()));
+        assert_eq!(err, Err("disk full".to_string()));
+    }
+}
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+
+use crate::ingest::csv::sample_transactions;
+use crate::sandwich::same_block_heuristics::{
+    find_same_block_sandwiches, SandwichAttackByHeuristics,
+};
+use crate::sandwich::same_block_sim::Pool;
+use crate::sandwich::transactions::SwapTransaction;
+
+/// The swaps of block 12360 of the sample export, a sandwich on `0xpool1`.
+pub(crate) fn sample_block() -> Vec<SwapTransaction> {
+    return sample_transactions()
+        .into_iter()
+        .filter(|tx| tx.block_number == 12360)
+        .collect();
+}
+
+/// `0xpool1` (USDC/SHIB) at the start of `sample_block`.
+pub(crate) fn sample_pool() -> Pool {
+    return Pool::new(1000000.0, 50000000000.0, "USDC".into(), "SHIB".into());
+}
+
+/// The pool map to simulate `sample_block` with, `sample_pool` alone.
+pub(crate) fn sample_pool_map() -> HashMap<String, Pool> {
+    return HashMap::from([("0xpool1".to_string(), sample_pool())]);
+}
+
+/// The attack the heuristics find in `block_number` of the sample export.
+pub(crate) fn sample_attack(block_number: u64) -> SandwichAttackByHeuristics {
+    return find_same_block_sandwiches(&sample_transactions())
+        .into_iter()
+        .find(|attack| attack.victim_tx.block_number == block_number)
+        .expect("Should find an attack in the block");
+}